@@ -0,0 +1,51 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use cardano::address::ExtendedAddr;
+
+use utils::term::{style::Style, Term};
+
+use super::{AddressBook, Result, LABEL_PREFIX};
+
+/// give the label to the address
+pub fn add(term: &mut Term, root_dir: PathBuf, label: String, address: ExtendedAddr) -> Result<()> {
+    let mut book = AddressBook::load(&root_dir)?;
+    if let Some(existing) = book.label_of(&address) {
+        term.warn(&format!(
+            "the address is already labelled `{}{}'\n",
+            LABEL_PREFIX, existing
+        ))?;
+    }
+    book.add(label.clone(), address)?;
+    book.save()?;
+
+    term.success(&format!("address labelled `{}{}'\n", LABEL_PREFIX, label))?;
+    Ok(())
+}
+
+/// list the labels and their addresses
+pub fn list(term: &mut Term, root_dir: PathBuf) -> Result<()> {
+    let book = AddressBook::load(&root_dir)?;
+    for (label, address) in book.iter() {
+        writeln!(
+            term,
+            "{}{} {}",
+            LABEL_PREFIX,
+            style!(label.as_str()).cyan(),
+            style!(address)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn remove(term: &mut Term, root_dir: PathBuf, label: String) -> Result<()> {
+    let mut book = AddressBook::load(&root_dir)?;
+    let address = book.remove(&label)?;
+    book.save()?;
+
+    term.success(&format!(
+        "label `{}{}' of {} removed\n",
+        LABEL_PREFIX, label, address
+    ))?;
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! address book
+//!
+//! labels given to external addresses (exchanges, other people's
+//! wallets...), kept in the root directory. Wherever an address is
+//! expected on the command line, `@LABEL` stands for the address with this
+//! label; the listings show the label alongside the address.
+
+#[cfg(feature = "cli")]
+pub mod commands;
+
+use std::{collections::BTreeMap, error, fmt, fs, io, path::PathBuf, str::FromStr};
+
+use cardano::address::ExtendedAddr;
+use serde_yaml;
+use storage_units::utils::tmpfile::TmpFile;
+
+use utils::era;
+
+const ADDRESS_BOOK_FILE: &'static str = "address-book.yml";
+
+/// the prefix telling a label from an address on the command line
+pub const LABEL_PREFIX: char = '@';
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    ReadFailed(PathBuf, serde_yaml::Error),
+    WriteFailed(PathBuf, serde_yaml::Error),
+    InvalidLabel(String),
+    InvalidAddress(String),
+    InvalidEntry(String, String),
+    LabelNotFound(String),
+    LabelAlreadyExists(String, ExtendedAddr),
+    UnsupportedAddress(era::Error),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::ReadFailed(path, _) => write!(
+                f,
+                "Failed to read the address book `{}`",
+                path.to_string_lossy()
+            ),
+            Error::WriteFailed(path, _) => write!(
+                f,
+                "Failed to write the address book `{}`",
+                path.to_string_lossy()
+            ),
+            Error::InvalidLabel(label) => write!(
+                f,
+                "Invalid label `{}', only letters, digits, `-', `_' and `.' are allowed",
+                label
+            ),
+            Error::InvalidAddress(address) => write!(f, "Invalid address `{}'", address),
+            Error::InvalidEntry(label, address) => write!(
+                f,
+                "The address book entry `{}' has an invalid address `{}'",
+                label, address
+            ),
+            Error::LabelNotFound(label) => {
+                write!(f, "No address labelled `{}' in the address book", label)
+            }
+            Error::LabelAlreadyExists(label, address) => write!(
+                f,
+                "The label `{}' is already given to {}, remove it first",
+                label, address
+            ),
+            Error::UnsupportedAddress(_) => write!(f, "Unsupported address"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::ReadFailed(_, ref err) => Some(err),
+            Error::WriteFailed(_, ref err) => Some(err),
+            Error::InvalidLabel(_) => None,
+            Error::InvalidAddress(_) => None,
+            Error::InvalidEntry(_, _) => None,
+            Error::LabelNotFound(_) => None,
+            Error::LabelAlreadyExists(_, _) => None,
+            Error::UnsupportedAddress(ref err) => Some(err),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// the address given on the command line: `@LABEL` for the address with
+/// this label, or an address. The address book is only read for labels.
+pub fn resolve(root_dir: &PathBuf, argument: &str) -> Result<ExtendedAddr> {
+    if argument.starts_with(LABEL_PREFIX) {
+        let label = &argument[LABEL_PREFIX.len_utf8()..];
+        AddressBook::load(root_dir)?
+            .get(label)
+            .cloned()
+            .ok_or_else(|| Error::LabelNotFound(label.to_owned()))
+    } else {
+        era::parse_address(argument).map_err(|err| match err {
+            era::Error::InvalidAddress(_) => Error::InvalidAddress(argument.to_owned()),
+            err => Error::UnsupportedAddress(err),
+        })
+    }
+}
+
+/// check the label is not empty and made of letters, digits, `-`, `_`
+/// and `.` only
+pub fn validate_label(label: &str) -> Result<()> {
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidLabel(label.to_owned()))
+    }
+}
+
+pub struct AddressBook {
+    path: PathBuf,
+    entries: BTreeMap<String, ExtendedAddr>,
+}
+impl AddressBook {
+    pub fn load(root_dir: &PathBuf) -> Result<Self> {
+        let path = root_dir.join(ADDRESS_BOOK_FILE);
+        // the addresses are kept in their base58 form, readable and
+        // editable by hand
+        let raw: BTreeMap<String, String> = match fs::File::open(&path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+            Ok(file) => {
+                serde_yaml::from_reader(file).map_err(|e| Error::ReadFailed(path.clone(), e))?
+            }
+        };
+
+        let mut entries = BTreeMap::new();
+        for (label, address) in raw {
+            match ExtendedAddr::from_str(&address) {
+                Ok(parsed) => {
+                    entries.insert(label, parsed);
+                }
+                Err(_) => return Err(Error::InvalidEntry(label, address)),
+            }
+        }
+        Ok(AddressBook { path, entries })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let raw: BTreeMap<&String, String> = self
+            .entries
+            .iter()
+            .map(|(label, address)| (label, address.to_string()))
+            .collect();
+
+        let dir = self
+            .path
+            .parent()
+            .expect("the address book is in the root directory");
+        fs::DirBuilder::new().recursive(true).create(dir)?;
+        let mut tmpfile = TmpFile::create(dir.to_path_buf())?;
+        serde_yaml::to_writer(&mut tmpfile, &raw)
+            .map_err(|e| Error::WriteFailed(self.path.clone(), e))?;
+        tmpfile.render_permanent(&self.path)?;
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ExtendedAddr)> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, label: &str) -> Option<&ExtendedAddr> {
+        self.entries.get(label)
+    }
+
+    /// the label of the address, if it has one
+    pub fn label_of(&self, address: &ExtendedAddr) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, entry)| *entry == address)
+            .map(|(label, _)| label.as_str())
+    }
+
+    pub fn add(&mut self, label: String, address: ExtendedAddr) -> Result<()> {
+        validate_label(&label)?;
+        if let Some(existing) = self.entries.get(&label) {
+            return Err(Error::LabelAlreadyExists(label, existing.clone()));
+        }
+        self.entries.insert(label, address);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, label: &str) -> Result<ExtendedAddr> {
+        self.entries
+            .remove(label)
+            .ok_or_else(|| Error::LabelNotFound(label.to_owned()))
+    }
+
+    /// the address followed by its label, if it has one
+    pub fn describe(&self, address: &ExtendedAddr) -> String {
+        match self.label_of(address) {
+            Some(label) => format!("{} ({}{})", address, LABEL_PREFIX, label),
+            None => address.to_string(),
+        }
+    }
+}
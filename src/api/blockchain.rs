@@ -0,0 +1,46 @@
+//! the local blockchains
+//!
+//! A blockchain is created with `Blockchain::new` (the configuration of
+//! the known networks being `Config::mainnet()`, `Config::testnet()`...)
+//! and saved with `Blockchain::save`.
+
+use std::path::Path;
+
+pub use blockchain::{Blockchain, BlockchainName, Config};
+pub use exe_common::network::api::BlockRef;
+
+use blockchain::pull;
+use utils::{clock, interaction::Silent};
+
+use super::Result;
+
+/// fetch the new blocks from the peers of the blockchain, `jobs` epochs at
+/// a time for the stable epochs, and forward the local tip.
+///
+/// The local clock is not checked (see `utils::clock`): it is up to the
+/// application.
+///
+/// Returns the new local tip.
+pub fn pull(root_dir: &Path, name: BlockchainName, jobs: usize) -> Result<BlockRef> {
+    let clock_check = clock::Config {
+        policy: clock::Policy::Off,
+        ..clock::Config::default()
+    };
+    pull::pull(
+        &mut Silent::new(),
+        root_dir.to_path_buf(),
+        name.clone(),
+        jobs,
+        None,
+        &clock_check,
+    )?;
+
+    let blockchain = Blockchain::load(root_dir, name)?;
+    Ok(blockchain.load_tip().0)
+}
+
+/// the local tip of the blockchain
+pub fn tip(root_dir: &Path, name: BlockchainName) -> Result<BlockRef> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    Ok(blockchain.load_tip().0)
+}
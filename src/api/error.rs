@@ -0,0 +1,64 @@
+use std::{error, fmt};
+
+use cardano::tx::TxoPointer;
+
+use blockchain;
+use transaction;
+use wallet::{self, WalletName};
+
+#[derive(Debug)]
+pub enum Error {
+    Blockchain(blockchain::Error),
+    Wallet(wallet::Error),
+    Transaction(transaction::error::Error),
+    WalletAlreadyExists(WalletName),
+    MissingPassword(WalletName),
+    InputNotOwned(TxoPointer),
+}
+impl From<blockchain::Error> for Error {
+    fn from(e: blockchain::Error) -> Self {
+        Error::Blockchain(e)
+    }
+}
+impl From<wallet::Error> for Error {
+    fn from(e: wallet::Error) -> Self {
+        Error::Wallet(e)
+    }
+}
+impl From<transaction::error::Error> for Error {
+    fn from(e: transaction::error::Error) -> Self {
+        Error::Transaction(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Blockchain(_) => write!(f, "Error with the blockchain"),
+            Error::Wallet(_) => write!(f, "Error with the wallet"),
+            Error::Transaction(_) => write!(f, "Error with the staging transaction"),
+            Error::WalletAlreadyExists(name) => write!(f, "Wallet `{}' already exists", name),
+            Error::MissingPassword(name) => {
+                write!(f, "No spending password given for the wallet `{}'", name)
+            }
+            Error::InputNotOwned(txin) => write!(
+                f,
+                "No wallet owns the input {}.{} of the transaction",
+                txin.id, txin.index
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::Blockchain(ref err) => Some(err),
+            Error::Wallet(ref err) => Some(err),
+            Error::Transaction(ref err) => Some(err),
+            Error::WalletAlreadyExists(_) => None,
+            Error::MissingPassword(_) => None,
+            Error::InputNotOwned(_) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
@@ -0,0 +1,44 @@
+//! programmatic API
+//!
+//! the operations of the command line without the command line: create and
+//! sync the wallets, pull the local blockchains, build and sign the staging
+//! transactions of a root directory. The passwords are given by the caller
+//! and the results are returned, nothing is prompted or printed.
+//!
+//! ```no_run
+//! extern crate cardano_cli;
+//!
+//! use cardano_cli::api::{self, wallet::WalletName};
+//! use std::path::Path;
+//!
+//! # fn main() -> api::Result<()> {
+//! let root_dir = Path::new("/var/lib/cardano-cli");
+//! let name = WalletName::new("shop".to_owned()).unwrap();
+//!
+//! api::blockchain::pull(root_dir, "mainnet".parse().unwrap(), 4)?;
+//! api::wallet::sync(root_dir, name.clone(), b"spending password")?;
+//! println!("{}", api::wallet::balance(root_dir, name)?);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The functions are the stable part of the library: they keep their
+//! signature across the releases of the same major version.
+
+pub mod blockchain;
+mod error;
+pub mod transaction;
+pub mod wallet;
+
+pub use self::error::{Error, Result};
+
+/// set the originator recorded in the audit log of the staging
+/// transactions (`transactions.audit`) for the following operations, the
+/// user running the process by default
+pub use utils::logging::set_originator;
+
+/// set how long the following operations wait for the lock of a staging
+/// transaction's file another process holds, not at all by default (see
+/// `transaction::StagingTransaction::read_from_file_waiting` to wait for a
+/// single one)
+pub use transaction::core::staging_transaction::set_lock_wait;
@@ -0,0 +1,156 @@
+//! the staging transactions
+//!
+//! A staging transaction is built with the operations of
+//! `StagingTransaction` (`add_input`, `add_output`, `add_change`...), each
+//! one recorded in its file, then finalized (its fee pinned with the fee
+//! algorithm, see `fee_algorithm`) and signed with the keys of the wallets
+//! owning its inputs.
+
+use std::{collections::BTreeMap, path::Path};
+
+use cardano::{
+    fee::LinearFee,
+    tx::{TxAux, TxId, TxInWitness},
+};
+
+pub use transaction::core::{
+    Change, Input, Operation, Output, StagingId, StagingTransaction, Transaction,
+};
+
+use blockchain::{Blockchain, BlockchainName};
+use transaction::{self, error::Error as TransactionError};
+use utils::logging;
+use wallet::{
+    state::lookup::{self, randomindex::RandomIndexLookup, sequentialindex::SequentialBip44Lookup},
+    utils::load_utxo_index,
+    HDWalletModel, Wallet, WalletName, Wallets,
+};
+
+use super::{Error, Result};
+
+/// create a new staging transaction for the blockchain
+pub fn new(root_dir: &Path, blockchain: BlockchainName) -> Result<StagingTransaction> {
+    let blockchain = Blockchain::load(root_dir, blockchain)?;
+    let staging = StagingTransaction::new(root_dir.to_path_buf(), blockchain.config.protocol_magic)
+        .map_err(TransactionError::CannotCreateNewTransaction)?;
+    Ok(staging)
+}
+
+pub fn load(root_dir: &Path, id: StagingId) -> Result<StagingTransaction> {
+    let staging = StagingTransaction::read_from_file(root_dir.to_path_buf(), id)
+        .map_err(TransactionError::CannotLoadStagingTransaction)?;
+    Ok(staging)
+}
+
+/// the fee algorithm of the staging transaction, the one of the local
+/// blockchain of its protocol magic (the mainnet's without such a
+/// blockchain)
+pub fn fee_algorithm(root_dir: &Path, staging: &StagingTransaction) -> LinearFee {
+    transaction::staging_fee_algorithm(root_dir, staging, None)
+}
+
+/// the key of the address of the wallet, to sign the transaction
+fn sign_input(
+    wallet: &Wallet,
+    password: &[u8],
+    staging: &StagingTransaction,
+    address: &lookup::Address,
+    txid: &TxId,
+) -> Result<Option<TxInWitness>> {
+    let protocol_magic = staging.protocol_magic;
+    let witness = match (&wallet.config.hdwallet_model, address) {
+        (HDWalletModel::BIP44, lookup::Address::Bip44(addressing)) => {
+            let keys = SequentialBip44Lookup::new(
+                wallet.get_wallet_bip44(password)?,
+                protocol_magic.into(),
+            );
+            Some(TxInWitness::new(
+                protocol_magic,
+                &*keys.get_private_key(addressing),
+                txid,
+            ))
+        }
+        (HDWalletModel::RandomIndex2Levels, lookup::Address::RIndex(addressing)) => {
+            let keys = RandomIndexLookup::from_wallet(
+                wallet.get_wallet_rindex(password)?,
+                protocol_magic.into(),
+            );
+            Some(TxInWitness::new(
+                protocol_magic,
+                &keys.get_private_key(addressing),
+                txid,
+            ))
+        }
+        _ => None,
+    };
+    Ok(witness)
+}
+
+/// sign the inputs of the staging transaction with the keys of the wallets
+/// owning them, given their spending passwords
+pub fn sign(
+    root_dir: &Path,
+    staging: &mut StagingTransaction,
+    fee_algorithm: &LinearFee,
+    passwords: &BTreeMap<WalletName, Vec<u8>>,
+) -> Result<()> {
+    let tx = staging
+        .transaction()
+        .mk_txbuilder(fee_algorithm)
+        .map_err(TransactionError::CannotSignTransactionNotFinalized)?
+        .0
+        .make_tx()
+        .map_err(TransactionError::CannotSignTransactionInvalidTxAux)?;
+    let txid = tx.id();
+
+    let mut wallets = Vec::new();
+    for (name, wallet) in Wallets::load(root_dir)? {
+        // the wallets not attached have no state
+        if let Ok(index) = load_utxo_index(&wallet, root_dir) {
+            wallets.push((name, wallet, index));
+        }
+    }
+
+    let mut signatures = Vec::new();
+    for input in staging.transaction().inputs() {
+        let txin = input.extract_txin();
+        let owner = wallets
+            .iter()
+            .filter_map(|(name, wallet, index)| index.get(&txin).map(|utxo| (name, wallet, utxo)))
+            .next();
+        let (name, wallet, utxo) = owner.ok_or_else(|| Error::InputNotOwned(txin.clone()))?;
+        let password = passwords
+            .get(name)
+            .ok_or_else(|| Error::MissingPassword(name.clone()))?;
+        let signature = sign_input(wallet, password, staging, &utxo.credited_addressing, &txid)?;
+        signatures.push(signature.ok_or_else(|| Error::InputNotOwned(txin.clone()))?);
+        info!(
+            "input {}.{} of staging transaction {} signed with wallet {} by {}",
+            txin.id,
+            txin.index,
+            staging.id(),
+            name,
+            logging::originator()
+        );
+    }
+
+    for signature in signatures {
+        staging
+            .add_signature(signature)
+            .map_err(TransactionError::CannotSignTransactionCannotAddSignature)?;
+    }
+    Ok(())
+}
+
+/// the signed transaction of the finalized staging transaction, to be sent
+/// to the network
+pub fn txaux(staging: &StagingTransaction, fee_algorithm: &LinearFee) -> Result<TxAux> {
+    let (finalized, _) = staging
+        .transaction()
+        .mk_finalized(fee_algorithm)
+        .map_err(TransactionError::CannotSendTransactionNotFinalized)?;
+    let txaux = finalized
+        .make_txaux()
+        .map_err(TransactionError::CannotSendTransactionInvalidTxAux)?;
+    Ok(txaux)
+}
@@ -0,0 +1,149 @@
+//! the wallets
+//!
+//! The wallets are created from the entropy of their mnemonic words (see
+//! `bip39::Entropy::from_mnemonics`), attached to a local blockchain and
+//! synced with it. Their state (balance, unspent outputs) is the one of the
+//! last sync.
+
+use std::path::Path;
+
+use cardano::{
+    bip::bip39,
+    block::BlockDate,
+    coin::Coin,
+    hdwallet::{self, DerivationScheme},
+    wallet::keygen,
+};
+
+pub use utils::password_encrypted::Password;
+pub use wallet::{
+    state::{lookup::Address, utxo::UTxOs},
+    Config, HDWalletModel, Wallet, WalletName, Wallets,
+};
+
+use blockchain::{Blockchain, BlockchainName};
+use utils::interaction::Silent;
+use wallet::{
+    self,
+    state::lookup::{
+        accum::Accum, randomindex::RandomIndexLookup, sequentialindex::SequentialBip44Lookup,
+    },
+    utils::{
+        create_wallet_state_from_logs, load_attached_blockchain, load_utxo_index, reindex_wallet,
+        rollback_wallet_if_forked, update_wallet_state_with_utxos,
+    },
+};
+
+use super::{Error, Result};
+
+/// create the wallet of the mnemonic words' entropy and passphrase (the
+/// "25th word", empty if none), its private key encrypted with the
+/// spending password
+pub fn create(
+    root_dir: &Path,
+    name: WalletName,
+    hdwallet_model: HDWalletModel,
+    derivation_scheme: DerivationScheme,
+    entropy: &bip39::Entropy,
+    mnemonic_passphrase: &[u8],
+    password: &Password,
+) -> Result<Wallet> {
+    if Wallet::load(root_dir, name.clone()).is_ok() {
+        return Err(Error::WalletAlreadyExists(name));
+    }
+
+    let config = Config {
+        attached_blockchain: None,
+        derivation_scheme: derivation_scheme,
+        hdwallet_model: hdwallet_model,
+        alarms: Vec::new(),
+        gap_limit: None,
+    };
+
+    let mut seed = [0; hdwallet::XPRV_SIZE];
+    keygen::generate_seed(entropy, mnemonic_passphrase, &mut seed);
+    let xprv = hdwallet::XPrv::normalize_bytes(seed);
+
+    let wallet = Wallet::from_root_key(root_dir, name, config, &xprv, password);
+    wallet.save()?;
+    Ok(wallet)
+}
+
+/// attach the wallet to the local blockchain, to sync it
+pub fn attach(root_dir: &Path, name: WalletName, blockchain: BlockchainName) -> Result<Wallet> {
+    let mut wallet = Wallet::load(root_dir, name)?;
+    if let Some(ref attached) = wallet.config.attached_blockchain {
+        return Err(wallet::Error::AttachAlreadyAttached(attached.clone()).into());
+    }
+    Blockchain::load(root_dir, blockchain.clone())?;
+
+    wallet.config.attached_blockchain = Some(blockchain.as_ref().to_owned());
+    wallet.save()?;
+    Ok(wallet)
+}
+
+/// sync the wallet with the blocks of its attached blockchain, the
+/// spending password recovering the keys of its addresses.
+///
+/// Returns the block date the wallet was synced to before.
+pub fn sync(root_dir: &Path, name: WalletName, password: &Password) -> Result<BlockDate> {
+    let wallet = Wallet::load(root_dir, name)?;
+    let blockchain = load_attached_blockchain(root_dir, &wallet.config)?;
+    let network_magic = blockchain.config.protocol_magic.into();
+
+    let mut silent = Silent::new();
+    rollback_wallet_if_forked(&mut silent, root_dir, &wallet, &blockchain)?;
+
+    let synced_from = match wallet.config.hdwallet_model {
+        HDWalletModel::BIP44 => {
+            let keys = wallet.get_wallet_bip44(password)?;
+            let mut lookup_struct = SequentialBip44Lookup::with_gap_limit(
+                keys,
+                network_magic,
+                wallet.config.gap_limit(),
+            );
+            lookup_struct
+                .prepare_next_account()
+                .map_err(wallet::Error::from)?;
+
+            let mut state = create_wallet_state_from_logs(&wallet, root_dir, lookup_struct)?;
+            let synced_from = state.ptr().latest_block_date();
+            update_wallet_state_with_utxos(&mut silent, &wallet, &blockchain, &mut state)?;
+            synced_from
+        }
+        HDWalletModel::RandomIndex2Levels => {
+            let keys = wallet.get_wallet_rindex(password)?;
+            let lookup_struct = RandomIndexLookup::from_wallet(keys, network_magic);
+
+            let mut state = create_wallet_state_from_logs(&wallet, root_dir, lookup_struct)?;
+            let synced_from = state.ptr().latest_block_date();
+            update_wallet_state_with_utxos(&mut silent, &wallet, &blockchain, &mut state)?;
+            synced_from
+        }
+    };
+    Ok(synced_from)
+}
+
+/// the balance of the wallet
+pub fn balance(root_dir: &Path, name: WalletName) -> Result<Coin> {
+    let wallet = Wallet::load(root_dir, name)?;
+    let state = create_wallet_state_from_logs(&wallet, root_dir, Accum::default())?;
+    Ok(state.total().map_err(wallet::Error::from)?)
+}
+
+/// the unspent outputs of the wallet, with the address they were sent to
+pub fn utxos(root_dir: &Path, name: WalletName) -> Result<UTxOs<Address>> {
+    let wallet = Wallet::load(root_dir, name)?;
+    let index = load_utxo_index(&wallet, root_dir)?;
+    Ok(index
+        .utxos()
+        .map(|utxo| (utxo.extract_txin(), utxo.clone()))
+        .collect())
+}
+
+/// rebuild the state snapshot and the UTxO index of the wallet from its log
+pub fn reindex(root_dir: &Path, name: WalletName) -> Result<()> {
+    let wallet = Wallet::load(root_dir, name)?;
+    reindex_wallet(&wallet, root_dir)?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! file of raw blocks, to carry a range of the blockchain to a machine
+//! without network connection.
+//!
+//! The file starts with a magic, the protocol magic and the genesis hash of
+//! the blockchain the blocks were exported from, then the blocks follow in
+//! chain order, each one prefixed with its length. The file is read one
+//! block at a time, it is never loaded in memory as a whole.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use cardano::{
+    block::{self, HeaderHash, RawBlock},
+    config::ProtocolMagic,
+};
+use cardano_storage as storage;
+use storage_units::utils::serialize;
+
+use super::{Blockchain, Error, Result};
+
+const MAGIC: &'static [u8] = b"CARDANO_BLOCKS_V1";
+
+/// write the given blocks to the file, checking each block links to the
+/// previous one.
+///
+/// Returns the number of blocks written.
+pub fn export<I, P>(blockchain: &Blockchain, path: P, blocks: I) -> Result<usize>
+where
+    I: IntoIterator<Item = storage::Result<(RawBlock, block::Block)>>,
+    P: AsRef<Path>,
+{
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_header(
+        &mut file,
+        &blockchain.config.protocol_magic,
+        &blockchain.config.genesis,
+    )?;
+
+    let mut prev_hash: Option<HeaderHash> = None;
+    let mut count = 0;
+    for res in blocks {
+        let (raw, block) = res?;
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        if let Some(prev_hash) = prev_hash {
+            if header.get_previous_header() != prev_hash {
+                return Err(Error::BlocksNotLinked(hash, prev_hash));
+            }
+        }
+
+        write_block(&mut file, &raw)?;
+        prev_hash = Some(hash);
+        count += 1;
+    }
+    file.flush()?;
+    Ok(count)
+}
+
+fn write_header<W: Write>(
+    file: &mut W,
+    protocol_magic: &ProtocolMagic,
+    genesis: &HeaderHash,
+) -> io::Result<()> {
+    file.write_all(MAGIC)?;
+    serialize::io::write_u32(file, **protocol_magic)?;
+    file.write_all(genesis.as_ref())
+}
+
+fn write_block<W: Write>(file: &mut W, raw: &RawBlock) -> io::Result<()> {
+    serialize::io::write_u32(file, raw.as_ref().len() as u32)?;
+    file.write_all(raw.as_ref())
+}
+
+/// the blocks of a block file, read one at a time
+pub struct Reader<R> {
+    path: PathBuf,
+    file: R,
+}
+impl Reader<io::BufReader<fs::File>> {
+    /// open the block file, checking it was exported from the same
+    /// blockchain.
+    pub fn open<P: AsRef<Path>>(blockchain: &Blockchain, path: P) -> Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path.as_ref())?);
+        Reader::new(
+            file,
+            path.as_ref(),
+            &blockchain.config.protocol_magic,
+            &blockchain.config.genesis,
+        )
+    }
+}
+impl<R: Read> Reader<R> {
+    fn new(
+        mut file: R,
+        path: &Path,
+        protocol_magic: &ProtocolMagic,
+        genesis: &HeaderHash,
+    ) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::ImportBlocksInvalidFile(path.to_path_buf(), reason.to_owned());
+
+        let mut magic = vec![0; MAGIC.len()];
+        file.read_exact(&mut magic)
+            .map_err(|_| invalid("file too short"))?;
+        if magic != MAGIC {
+            return Err(invalid("not a block file"));
+        }
+        let file_protocol_magic = ProtocolMagic::from(
+            serialize::io::read_u32(&mut file).map_err(|_| invalid("file too short"))?,
+        );
+        let mut file_genesis = [0; 32];
+        file.read_exact(&mut file_genesis)
+            .map_err(|_| invalid("file too short"))?;
+        let file_genesis = HeaderHash::from(file_genesis);
+        if &file_protocol_magic != protocol_magic || &file_genesis != genesis {
+            return Err(Error::ImportBlocksWrongBlockchain(file_genesis));
+        }
+
+        Ok(Reader {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// the next block of the file, `None` once all the blocks were read
+    pub fn next_block(&mut self) -> Result<Option<RawBlock>> {
+        let len = match serialize::io::read_u32(&mut self.file) {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+            Ok(len) => len,
+        };
+        let mut bytes = vec![0; len as usize];
+        self.file.read_exact(&mut bytes).map_err(|_| {
+            Error::ImportBlocksInvalidFile(self.path.clone(), "truncated block".to_owned())
+        })?;
+        Ok(Some(RawBlock::from_dat(bytes)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn protocol_magic() -> ProtocolMagic {
+        ProtocolMagic::from(1097911063)
+    }
+
+    fn genesis() -> HeaderHash {
+        HeaderHash::from([0x9b; 32])
+    }
+
+    fn file(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut file = Vec::new();
+        write_header(&mut file, &protocol_magic(), &genesis()).unwrap();
+        for block in blocks {
+            write_block(&mut file, &RawBlock::from_dat(block.to_vec())).unwrap();
+        }
+        file
+    }
+
+    fn reader(file: &[u8]) -> Result<Reader<&[u8]>> {
+        Reader::new(file, Path::new("blocks"), &protocol_magic(), &genesis())
+    }
+
+    #[test]
+    fn blocks_are_read_back_in_order() {
+        let file = file(&[b"first block", b"", b"third block"]);
+        let mut reader = reader(&file).unwrap();
+        for expected in &[&b"first block"[..], b"", b"third block"] {
+            let block = reader.next_block().unwrap().unwrap();
+            assert_eq!(block.as_ref(), *expected);
+        }
+        assert!(reader.next_block().unwrap().is_none());
+    }
+
+    #[test]
+    fn other_blockchain_is_rejected() {
+        let mut file = Vec::new();
+        write_header(&mut file, &protocol_magic(), &HeaderHash::from([0x42; 32])).unwrap();
+        match reader(&file) {
+            Err(Error::ImportBlocksWrongBlockchain(genesis)) => {
+                assert!(genesis == HeaderHash::from([0x42; 32]))
+            }
+            _ => panic!("the file of another blockchain is accepted"),
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(match reader(b"CARDANO") {
+            Err(Error::ImportBlocksInvalidFile(..)) => true,
+            _ => false,
+        });
+        assert!(match reader(b"NOT_A_BLOCK_FILE_AT_ALL_BUT_LONG_ENOUGH") {
+            Err(Error::ImportBlocksInvalidFile(..)) => true,
+            _ => false,
+        });
+
+        let file = file(&[b"truncated block"]);
+        let mut reader = reader(&file[..file.len() - 1]).unwrap();
+        assert!(match reader.next_block() {
+            Err(Error::ImportBlocksInvalidFile(..)) => true,
+            _ => false,
+        });
+    }
+}
@@ -2,20 +2,29 @@ use std::io::Write;
 use std::path::PathBuf;
 
 use cardano_storage as storage;
-use exe_common::config::net::Config;
+use exe_common::{config::net::Config, network::api::BlockRef};
 
+use schema;
 use utils::{
+    era,
     term::{style::Style, Term},
     time,
 };
+use wallet::{self, state::lookup, utils::create_wallet_state_from_logs, Wallets};
 
-use super::{peer, Blockchain, BlockchainName, Error, Result};
+use super::{
+    blockfile, explorer, fetcher, genesisdata, health, integrity, parameters, peer, proxy, seeds,
+    Blockchain, BlockchainName, Error, Result,
+};
 use cardano::{
     self,
-    block::{BlockDate, HeaderHash},
+    block::{BlockDate, EpochId, HeaderHash},
+    tx::TxId,
     util::hex,
 };
 
+pub use super::pull::{forward, pull};
+
 /// function to create and initialize a given new blockchain
 ///
 /// It will mainly create the subdirectories needed for the storage
@@ -26,8 +35,50 @@ use cardano::{
 /// the genesis hash of the blockchain (given in the same configuration
 /// structure `Config`).
 ///
-pub fn new(term: &mut Term, root_dir: PathBuf, name: BlockchainName, config: Config) -> Result<()> {
-    let blockchain = Blockchain::new(root_dir, name, config)?;
+/// Given the genesis data file of a private network and the hash of its
+/// first block, the blockchain is created from them instead: only the peers
+/// of the configuration are kept. The protocol parameters file, if any,
+/// gives the current fee policy of the blockchain.
+///
+pub fn new(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    config: Config,
+    genesis: Option<(PathBuf, HeaderHash)>,
+    protocol_parameters: Option<PathBuf>,
+) -> Result<()> {
+    let protocol_parameters = match protocol_parameters {
+        None => None,
+        Some(path) => Some(parameters::read_protocol_parameters(&path)?),
+    };
+    let protocol_parameters = protocol_parameters.as_ref().map(|bytes| &bytes[..]);
+
+    let blockchain = match genesis {
+        None => {
+            let genesis_data = genesisdata::data::get_genesis_data(&config.genesis_prev)
+                .map_err(Error::VerifyChainGenesisHashNotFound)?;
+            Blockchain::new_with_genesis(
+                root_dir,
+                name,
+                config,
+                genesis_data.as_bytes(),
+                protocol_parameters,
+            )?
+        }
+        Some((genesis_file, genesis_block)) => {
+            let (mut genesis_config, genesis_data) =
+                parameters::genesis_config(&genesis_file, genesis_block)?;
+            genesis_config.peers = config.peers;
+            Blockchain::new_with_genesis(
+                root_dir,
+                name,
+                genesis_config,
+                &genesis_data,
+                protocol_parameters,
+            )?
+        }
+    };
     blockchain.save();
 
     term.success(&format!(
@@ -98,11 +149,7 @@ wallets won't be able to interact with this blockchain.",
         ::console::style(&blockchain.name).bold().red(),
     )?;
 
-    let confirmation = ::dialoguer::Confirmation::new()
-        .with_text("Are you sure?")
-        .default(false)
-        .interact()?;
-    if confirmation {
+    if term.confirm("Are you sure?")? {
         unsafe { blockchain.destroy() }?;
         term.success("blockchain successfully destroyed\n")?;
     }
@@ -147,6 +194,9 @@ pub fn remote_rm(
     remote_alias: String,
 ) -> Result<()> {
     let mut blockchain = Blockchain::load(root_dir, name)?;
+    if !blockchain.peers().any(|np| np.name() == remote_alias) {
+        return Err(Error::PeerNotFound(remote_alias));
+    }
     blockchain.remove_peer(remote_alias.clone());
     blockchain.save();
 
@@ -164,15 +214,19 @@ pub fn remote_fetch(
     name: BlockchainName,
     peers: Vec<String>,
 ) -> Result<()> {
-    let blockchain = Blockchain::load(root_dir, name)?;
+    let mut blockchain = Blockchain::load(root_dir, name)?;
+    seeds::add_seed_peers(&mut blockchain)?;
 
-    for np in blockchain.peers() {
-        if peers.is_empty() || peers.contains(&np.name().to_owned()) {
-            term.info(&format!("fetching blocks from peer: {}\n", np.name()))?;
+    for peer_name in health::ordered_peers(&blockchain, false) {
+        if peers.is_empty() || peers.contains(&peer_name) {
+            term.info(&format!("fetching blocks from peer: {}\n", peer_name))?;
 
-            let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
+            let peer = peer::Peer::prepare(&blockchain, peer_name);
 
-            peer.connect(term).unwrap().sync(term);
+            // the peer is reported unreachable by `connect'
+            if let Ok(connected) = peer.connect(term) {
+                connected.sync(term)?;
+            }
         }
     }
 
@@ -217,7 +271,13 @@ pub fn remote_ls(
             writeln!(term, " * local tip date:  {}", style!(tip.date))?;
 
             if detailed >= RemoteDetail::Remote {
-                let mut connected_peer = peer.connect(term).unwrap();
+                let mut connected_peer = match peer.connect(term) {
+                    Ok(connected_peer) => connected_peer,
+                    Err(()) => {
+                        writeln!(term, " * remote:          {}", style!("unreachable").red())?;
+                        continue;
+                    }
+                };
                 let remote_tip = connected_peer.query_tip();
                 let block_diff = remote_tip.date - tip.date;
 
@@ -235,6 +295,219 @@ pub fn remote_ls(
     Ok(())
 }
 
+/// list the peers of the blockchain in the order they are tried, with
+/// their recorded health.
+pub fn peer_list(term: &mut Term, root_dir: PathBuf, name: BlockchainName) -> Result<()> {
+    let mut blockchain = Blockchain::load(root_dir, name)?;
+    seeds::add_seed_peers(&mut blockchain)?;
+    let health = health::Health::load(&blockchain);
+
+    for (idx, peer_name) in health::ordered_peers(&blockchain, false)
+        .into_iter()
+        .enumerate()
+    {
+        let peer = peer::Peer::prepare(&blockchain, peer_name);
+        writeln!(
+            term,
+            "{}. {} ({})",
+            style!(idx + 1),
+            style!(&peer.name).cyan(),
+            style!(&peer.config)
+        )?;
+
+        let peer_health = match health.get(&peer.name) {
+            None => {
+                writeln!(
+                    term,
+                    "   * status:      {}",
+                    style!("never checked").yellow()
+                )?;
+                continue;
+            }
+            Some(peer_health) => peer_health,
+        };
+        if peer_health.is_available() {
+            writeln!(term, "   * status:      {}", style!("available").green())?;
+        } else {
+            writeln!(
+                term,
+                "   * status:      {} ({} consecutive failures)",
+                style!("unreachable").red(),
+                style!(peer_health.consecutive_failures)
+            )?;
+        }
+        if let Some(latency) = peer_health.latency {
+            writeln!(term, "   * latency:     {}ms", style!(latency))?;
+        }
+        writeln!(
+            term,
+            "   * connections: {} succeeded, {} failed",
+            style!(peer_health.successes).green(),
+            style!(peer_health.failures).red()
+        )?;
+        if let Some(last_check) = peer_health.last_check {
+            let last_check = time::Time::from(last_check);
+            writeln!(
+                term,
+                "   * last check:  {} ({} ago)",
+                style!(last_check),
+                style!(time::Duration::since(last_check))
+            )?;
+        }
+        if let Some(ref error) = peer_health.last_error {
+            writeln!(term, "   * last error:  {}", style!(error).red())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// connect to the given peers (all the peers of the blockchain if none
+/// are given), query their tip and record their health.
+pub fn peer_test(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    peers: Vec<String>,
+) -> Result<()> {
+    let mut blockchain = Blockchain::load(root_dir, name)?;
+    seeds::add_seed_peers(&mut blockchain)?;
+    if let Some(unknown) = peers
+        .iter()
+        .find(|p| !blockchain.peers().any(|np| np.name() == p.as_str()))
+    {
+        return Err(Error::PeerNotFound(unknown.clone()));
+    }
+
+    for np in blockchain.peers() {
+        if !peers.is_empty() && !peers.contains(&np.name().to_owned()) {
+            continue;
+        }
+        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
+        let start = ::std::time::SystemTime::now();
+        match peer.connect(term) {
+            Err(()) => term.error(&format!("[FAIL] {}\n", np.name()))?,
+            Ok(mut connected) => {
+                let tip = connected.query_tip();
+                let elapsed = start.elapsed().unwrap_or_default();
+                term.success(&format!(
+                    "[ OK ] {} ({}ms), tip {} ({})\n",
+                    np.name(),
+                    elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+                    tip.hash,
+                    tip.date
+                ))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// add a DNS seed to the blockchain, it is resolved at the next connection
+pub fn seed_add(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    endpoint: String,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let mut seeds = seeds::Seeds::load(&blockchain)?;
+    seeds.add(endpoint.clone())?;
+    seeds.save(&blockchain)?;
+
+    term.success(&format!(
+        "DNS seed `{}' added to blockchain `{}'\n",
+        endpoint, blockchain.name
+    ))?;
+
+    Ok(())
+}
+
+pub fn seed_remove(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    endpoint: String,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let mut seeds = seeds::Seeds::load(&blockchain)?;
+    seeds.remove(&endpoint)?;
+    seeds.save(&blockchain)?;
+
+    term.success(&format!(
+        "DNS seed `{}' removed from blockchain `{}'\n",
+        endpoint, blockchain.name
+    ))?;
+
+    Ok(())
+}
+
+/// list the DNS seeds of the blockchain and the addresses they last
+/// resolved to
+pub fn seed_list(term: &mut Term, root_dir: PathBuf, name: BlockchainName) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let seeds = seeds::Seeds::load(&blockchain)?;
+
+    for seed in seeds.seeds.iter() {
+        writeln!(term, "{}", style!(&seed.endpoint).cyan())?;
+        match seed.resolved_at {
+            None => writeln!(term, " * resolved:  {}", style!("never").yellow())?,
+            Some(resolved_at) => {
+                let resolved_at = time::Time::from(resolved_at);
+                writeln!(
+                    term,
+                    " * resolved:  {} ({} ago){}",
+                    style!(resolved_at),
+                    style!(time::Duration::since(resolved_at)),
+                    if seed.is_stale() {
+                        ", will be resolved again at the next connection"
+                    } else {
+                        ""
+                    }
+                )?;
+            }
+        }
+        for address in seed.addresses.iter() {
+            writeln!(term, "   - {}", style!(address))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// set the SOCKS5 proxy the connections to the peers of the blockchain go
+/// through
+pub fn proxy_set(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    address: String,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    proxy::save(&blockchain, Some(&address))?;
+
+    term.success(&format!(
+        "blockchain `{}' now connects to its peers through the proxy `{}'\n",
+        blockchain.name, address
+    ))?;
+
+    Ok(())
+}
+
+/// connect directly to the peers of the blockchain again
+pub fn proxy_unset(term: &mut Term, root_dir: PathBuf, name: BlockchainName) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    proxy::save(&blockchain, None)?;
+
+    term.success(&format!(
+        "blockchain `{}' now connects directly to its peers\n",
+        blockchain.name
+    ))?;
+
+    Ok(())
+}
+
 pub fn log(
     term: &mut Term,
     root_dir: PathBuf,
@@ -250,7 +523,7 @@ pub fn log(
         blockchain.load_tip().0.hash
     };
 
-    for block in storage::iter::ReverseIter::from(&blockchain.storage, from)? {
+    for block in blockchain.reverse_iter(from)? {
         use utils::pretty::Pretty;
 
         block.pretty(term, 0)?;
@@ -259,60 +532,86 @@ pub fn log(
     Ok(())
 }
 
-pub fn forward(
+/// remove the epoch packs older than the last `keep_epochs` epochs
+///
+/// The epochs' references (the list of the hashes of their blocks) and
+/// chain states are kept, only the packs of blocks are deleted. Because the
+/// wallets sync from the blocks, the pruning is refused if an attached
+/// wallet is not synced past the epochs to prune.
+pub fn prune(
     term: &mut Term,
     root_dir: PathBuf,
     name: BlockchainName,
-    to: Option<HeaderHash>,
+    keep_epochs: EpochId,
 ) -> Result<()> {
-    let blockchain = Blockchain::load(root_dir, name)?;
+    let blockchain = Blockchain::load(root_dir.clone(), name)?;
 
-    let hash = if let Some(hash) = to {
-        blockchain.storage.block_location(hash.as_hash_bytes())?;
-        hash
-    } else {
-        let initial_tip = blockchain.load_tip().0;
-
-        let tip = blockchain
-            .peers()
-            .map(|np| {
-                peer::Peer::prepare(&blockchain, np.name().to_owned())
-                    .load_local_tip()
-                    .0
-            })
-            .fold(initial_tip, |current_tip, tip| {
-                if tip.date > current_tip.date {
-                    tip
-                } else {
-                    current_tip
-                }
-            });
+    let tip = blockchain.load_tip().0;
+    let first_kept = tip.date.get_epochid().saturating_sub(keep_epochs);
+    let pruned_until = blockchain
+        .load_pruned_until()?
+        .unwrap_or(blockchain.config.epoch_start);
+    if first_kept <= pruned_until {
+        term.info("nothing to prune\n")?;
+        return Ok(());
+    }
 
-        tip.hash
+    // check none of the attached wallets still need the epochs to prune
+    let wallets = match Wallets::load(&root_dir) {
+        Ok(wallets) => wallets,
+        Err(wallet::Error::WalletsLoadFailed(ref err))
+            if err.kind() == ::std::io::ErrorKind::NotFound =>
+        {
+            Wallets::new()
+        }
+        Err(err) => return Err(Error::PruneWalletLoadFailed(format!("{}", err))),
     };
+    for (wallet_name, wallet) in wallets {
+        match wallet.config.attached_blockchain() {
+            Ok(Some(ref attached)) if attached == &blockchain.name => {}
+            _ => continue,
+        }
+        let state =
+            create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default())
+                .map_err(|_| Error::PruneWalletLoadFailed(wallet_name.to_string()))?;
+        match state.ptr().latest_addr {
+            None => return Err(Error::PruneWalletNotSynced(wallet_name.to_string())),
+            Some(ref date) if date.get_epochid() < first_kept => {
+                return Err(Error::PruneWalletNeedsEpoch(
+                    wallet_name.to_string(),
+                    date.get_epochid(),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
 
-    term.success(&format!("forward local tip to: {}\n", hash))?;
-
-    blockchain.save_tip(&hash);
-
-    Ok(())
-}
-
-pub fn pull(term: &mut Term, root_dir: PathBuf, name: BlockchainName) -> Result<()> {
-    let blockchain = Blockchain::load(root_dir.clone(), name.clone())?;
-
-    for np in blockchain.peers() {
-        if !np.is_native() {
+    let mut pruned = 0;
+    let mut freed = 0;
+    for epoch_id in pruned_until..first_kept {
+        if !peer::internal::epoch_exists(&blockchain.storage, epoch_id) {
             continue;
         }
-        term.info(&format!("fetching blocks from peer: {}\n", np.name()))?;
-
-        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
-
-        peer.connect(term).unwrap().sync(term);
+        let packhash = storage::epoch::epoch_read_pack(&blockchain.storage.config, epoch_id)?;
+        for path in &[
+            blockchain.storage.config.get_pack_filepath(&packhash),
+            blockchain.storage.config.get_index_filepath(&packhash),
+        ] {
+            if let Ok(metadata) = ::std::fs::metadata(path) {
+                freed += metadata.len();
+                ::std::fs::remove_file(path)?;
+            }
+        }
+        debug!("epoch {} pruned ({})", epoch_id, hex::encode(&packhash[..]));
+        pruned += 1;
     }
+    blockchain.save_pruned_until(first_kept)?;
 
-    forward(term, root_dir, name, None)
+    term.success(&format!(
+        "{} epochs pruned, {} bytes freed. Blocks are stored from epoch {}\n",
+        pruned, freed, first_kept
+    ))?;
+    Ok(())
 }
 
 arg_enum! {
@@ -349,6 +648,7 @@ pub fn cat(
             None => {
                 use utils::pretty::Pretty;
 
+                era::check_block(rblk.as_ref()).map_err(Error::UnsupportedEra)?;
                 let blk = rblk.decode().map_err(Error::CatMalformedBlock)?;
                 if debug {
                     writeln!(term, "{:#?}", blk)?;
@@ -385,6 +685,12 @@ pub fn status(term: &mut Term, root_dir: PathBuf, name: BlockchainName) -> Resul
         )?;
         writeln!(term, " * local tip hash:  {}", style!(tip.hash))?;
         writeln!(term, " * local tip date:  {}", style!(tip.date))?;
+        if let Some(pruned_until) = blockchain.load_pruned_until()? {
+            writeln!(term, " * pruned until:    epoch {}", style!(pruned_until))?;
+        }
+        if let Some(proxy) = &blockchain.proxy {
+            writeln!(term, " * proxy:           {}", style!(proxy))?;
+        }
     }
 
     writeln!(term, "{}:", style!("Peers").cyan().bold())?;
@@ -427,6 +733,7 @@ pub fn verify_block(
 ) -> Result<()> {
     let blockchain = Blockchain::load(root_dir, name)?;
     let rblk = blockchain.storage.read_block(hash.as_hash_bytes())?;
+    era::check_block(rblk.as_ref()).map_err(Error::UnsupportedEra)?;
     match rblk.decode() {
         Ok(blk) => match cardano::block::verify_block(&hash, &blk) {
             Ok(()) => Ok(writeln!(term, "{}", style!("Block is valid").green())?),
@@ -441,9 +748,17 @@ pub fn verify_chain(
     root_dir: PathBuf,
     name: BlockchainName,
     stop_on_error: bool,
+    repair: bool,
 ) -> Result<()> {
     let blockchain = Blockchain::load(root_dir, name)?;
 
+    // the chain is verified from the genesis, all the blocks are needed
+    if let Some(pruned_until) = blockchain.load_pruned_until()? {
+        return Err(Error::EpochsPruned(pruned_until));
+    }
+
+    verify_epoch_packs(term, &blockchain, repair)?;
+
     let tip = blockchain.load_tip().0;
     let num_blocks = tip.date.slot_number();
 
@@ -463,7 +778,7 @@ pub fn verify_chain(
     let mut chain_state = cardano::block::ChainState::new(&genesis_data);
 
     for res in blockchain.iter_to_tip(blockchain.config.genesis.clone())? {
-        let (_raw_blk, blk) = res.unwrap();
+        let (_raw_blk, blk) = res?;
         let hash = blk.get_header().compute_hash();
         match chain_state.verify_block(&hash, &blk) {
             Ok(()) => {
@@ -528,6 +843,67 @@ pub fn verify_chain(
     }
 }
 
+/// check the integrity of the epoch packs, and download again the
+/// damaged ones if `repair` is set.
+fn verify_epoch_packs(term: &mut Term, blockchain: &Blockchain, repair: bool) -> Result<()> {
+    let progress = term.progress_spinner();
+    progress.set_message("verifying epoch packs... ");
+    let checks = integrity::check_epochs(blockchain, |check| {
+        progress.set_message(&format!("verifying epoch {}... ", check.epoch_id));
+        progress.inc(1);
+    })?;
+    progress.finish();
+
+    let ranges = integrity::damaged_ranges(&checks);
+    if ranges.is_empty() {
+        writeln!(
+            term,
+            "{} epoch packs verified",
+            style!(checks.len()).green()
+        )?;
+        return Ok(());
+    }
+
+    for check in checks.iter() {
+        if let Err(ref err) = check.result {
+            writeln!(
+                term,
+                "Epoch {} is damaged: {}",
+                style!(check.epoch_id).red(),
+                err
+            )?;
+        }
+    }
+    for range in ranges.iter() {
+        term.warn(&format!(
+            "corrupted epochs: {} to {}\n",
+            range.start,
+            range.end - 1
+        ))?;
+    }
+
+    let num_damaged = checks.iter().filter(|check| check.result.is_err()).count();
+    if !repair {
+        return Err(Error::BlockchainHasDamagedEpochs(num_damaged));
+    }
+
+    for (idx, check) in checks.iter().enumerate() {
+        if check.result.is_ok() {
+            continue;
+        }
+        let prev_hash = if idx > 0 {
+            checks[idx - 1].result.as_ref().ok()
+        } else {
+            None
+        };
+        term.info(&format!("downloading epoch {} again\n", check.epoch_id))?;
+        fetcher::refetch_epoch(blockchain, check.epoch_id, prev_hash)
+            .map_err(|err| Error::RepairEpochFailed(check.epoch_id, err))?;
+    }
+    term.success(&format!("{} damaged epochs repaired\n", num_damaged))?;
+    Ok(())
+}
+
 pub struct QueryParams {
     pub start: Option<BlockDate>,
     pub end: Option<BlockDate>,
@@ -542,6 +918,11 @@ pub fn query(
     let blockchain = Blockchain::load(root_dir, name)?;
     // FIXME: make blockchain.load_tip() return errors gracefully
     let tip = blockchain.load_tip().0.hash;
+    let start = params
+        .start
+        .clone()
+        .unwrap_or(BlockDate::Boundary(blockchain.config.epoch_start));
+    blockchain.check_not_pruned(&start)?;
     let from = match params.start {
         Some(date) => {
             let resolved = storage::resolve_date_to_blockhash(&blockchain.storage, &tip, &date)?;
@@ -573,3 +954,354 @@ pub fn query(
     }
     Ok(())
 }
+
+/// print the decoded content of the block, with the transactions' inputs,
+/// outputs and fees.
+pub fn block(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    query: explorer::BlockQuery,
+    json: bool,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let (hash, block) = explorer::find_block(&blockchain, &query)?;
+    let details = explorer::block_details(term, &blockchain, hash, &block)?;
+
+    if json {
+        explorer::display_json(term, &schema::BLOCKCHAIN_BLOCK, &details)
+    } else {
+        explorer::display_block(term, &details)
+    }
+}
+
+/// look for the transaction in the local blockchain and print its inputs,
+/// outputs and fee.
+pub fn tx(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    txid: TxId,
+    json: bool,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let (block, txaux) = explorer::find_transaction(term, &blockchain, &txid)?;
+    let spent = explorer::find_spent_outputs(
+        term,
+        &blockchain,
+        block.get_header().compute_hash(),
+        txaux.tx.inputs.iter(),
+    )?;
+    let details = explorer::tx_details(&block, &txaux, &spent);
+
+    if json {
+        explorer::display_json(term, &schema::BLOCKCHAIN_TX, &details)
+    } else {
+        explorer::display_transaction(term, &details)
+    }
+}
+
+/// write the blocks of the given range to a file, to import them with
+/// `import_blocks` on a machine without network connection.
+pub fn export_blocks(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    from: BlockDate,
+    to: Option<BlockDate>,
+    output: PathBuf,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+    let tip = blockchain.load_tip().0;
+    let to = to.unwrap_or(tip.date);
+    if from > to {
+        return Err(Error::ExportBlocksInvalidRange(from, to));
+    }
+    blockchain.check_not_pruned(&from)?;
+
+    let resolve = |date: &BlockDate| -> Result<HeaderHash> {
+        match storage::resolve_date_to_blockhash(&blockchain.storage, &tip.hash, date)? {
+            Some(hash) => Ok(HeaderHash::from(hash)),
+            None => Err(Error::QueryBlockDateNotResolved(date.clone())),
+        }
+    };
+    let from_hash = resolve(&from)?;
+    let to_hash = resolve(&to)?;
+
+    let count = blockfile::export(&blockchain, &output, blockchain.iter(from_hash, to_hash)?)?;
+    term.success(&format!(
+        "{} blocks, from {} to {}, exported to `{}'\n",
+        count,
+        from,
+        to,
+        output.to_string_lossy()
+    ))?;
+    Ok(())
+}
+
+/// add the blocks of a file written by `export_blocks` to the local
+/// blockchain, and forward the local tip to the last of them.
+///
+/// The blocks need to be valid and to follow a block of the local
+/// blockchain.
+pub fn import_blocks(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    input: PathBuf,
+) -> Result<()> {
+    let blockchain = Blockchain::load(root_dir, name)?;
+
+    // first pass: check the blocks are valid and linked together, keeping
+    // only what is needed to check where they go
+    let mut reader = blockfile::Reader::open(&blockchain, &input)?;
+    let (tip, is_genesis) = blockchain.load_tip();
+    let mut parent = None;
+    let mut last: Option<(HeaderHash, BlockDate)> = None;
+    let mut has_tip = false;
+    let mut count = 0;
+    while let Some(raw) = reader.next_block()? {
+        let block = raw.decode().map_err(|e| {
+            Error::ImportBlocksInvalidFile(input.clone(), format!("malformed block: {}", e))
+        })?;
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        match last {
+            None => parent = Some(header.get_previous_header()),
+            Some((ref prev_hash, _)) => {
+                if &header.get_previous_header() != prev_hash {
+                    return Err(Error::BlocksNotLinked(hash, prev_hash.clone()));
+                }
+            }
+        }
+        cardano::block::verify_block(&hash, &block)
+            .map_err(|e| Error::ImportBlocksInvalidBlock(hash.clone(), format!("{:?}", e)))?;
+        has_tip = has_tip || hash == tip.hash;
+        last = Some((hash, header.get_blockdate()));
+        count += 1;
+    }
+
+    let (parent, (last_hash, last_date)) = match (parent, last) {
+        (Some(parent), Some(last)) => (parent, last),
+        _ => {
+            term.info("no blocks to import\n")?;
+            return Ok(());
+        }
+    };
+
+    // the blocks need to follow a block of the local blockchain
+    if parent != blockchain.config.genesis_prev {
+        let parent_date = match blockchain.storage.read_block(parent.as_hash_bytes()) {
+            Err(storage::Error::BlockNotFound(_)) => {
+                return Err(Error::ImportBlocksNotLinked(parent))
+            }
+            Err(err) => return Err(err.into()),
+            Ok(raw) => raw
+                .decode()
+                .map_err(|_| Error::GetInvalidBlock(parent.clone()))?
+                .get_header()
+                .get_blockdate(),
+        };
+        if !blockchain.is_on_chain(&parent, &parent_date)? {
+            return Err(Error::ImportBlocksNotLinked(parent));
+        }
+    }
+
+    if blockchain.is_on_chain(&last_hash, &last_date)? {
+        term.info("the local blockchain already has these blocks\n")?;
+        return Ok(());
+    }
+    if parent != tip.hash && !has_tip {
+        return Err(Error::ImportBlocksForkFromTip(tip.hash));
+    }
+
+    // second pass: store the blocks following the local tip, packing the
+    // epochs as they become complete and stable
+    let first_unstable_epoch =
+        peer::first_unstable_epoch(&last_date, blockchain.config.epoch_stability_depth);
+    let progress = term.progress_bar(count as u64);
+    progress.set_message("importing blocks -> ");
+    let mut reader = blockfile::Reader::open(&blockchain, &input)?;
+    let mut after_tip =
+        parent == tip.hash || (is_genesis && parent == blockchain.config.genesis_prev);
+    let mut prev_hash = parent;
+    let mut imported = 0;
+    while let Some(raw) = reader.next_block()? {
+        progress.inc(1);
+        let header = raw
+            .decode()
+            .map_err(|e| {
+                Error::ImportBlocksInvalidFile(input.clone(), format!("malformed block: {}", e))
+            })?
+            .get_header();
+        let hash = header.compute_hash();
+        if !after_tip {
+            // the local blockchain already has the blocks up to its tip
+            after_tip = hash == tip.hash;
+            prev_hash = hash;
+            continue;
+        }
+
+        let date = header.get_blockdate();
+        let epoch_id = date.get_epochid();
+        if date.is_boundary()
+            && epoch_id > blockchain.config.epoch_start
+            && epoch_id - 1 < first_unstable_epoch
+        {
+            peer::internal::maybe_create_epoch(&blockchain.storage, epoch_id - 1, &prev_hash);
+        }
+
+        let blob_hash = storage::types::header_to_blockhash(&hash);
+        storage::blob::write(&blockchain.storage, &blob_hash, raw.as_ref())?;
+        imported += 1;
+        if imported % SYNC_FROM_LOCAL_CHECKPOINT_INTERVAL == 0 {
+            blockchain.save_tip(&hash);
+        }
+        prev_hash = hash;
+    }
+    progress.finish();
+    blockchain.save_tip(&last_hash);
+
+    term.success(&format!(
+        "{} blocks imported, local tip forwarded to {} ({})\n",
+        imported,
+        style!(&last_hash),
+        style!(last_date)
+    ))?;
+    Ok(())
+}
+
+/// number of blocks copied from another local blockchain between two
+/// checkpoints of the local tip
+const SYNC_FROM_LOCAL_CHECKPOINT_INTERVAL: usize = 2000;
+
+/// copy the blocks the local blockchain does not have yet from the same
+/// blockchain in another root directory (e.g. on a USB drive), then fetch
+/// the remaining blocks from the peers, unless `offline`.
+///
+/// The copied blocks are verified and checked to follow the local tip, the
+/// other blockchain is only read.
+pub fn sync_from_local(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    from_root_dir: PathBuf,
+    from_name: Option<BlockchainName>,
+    offline: bool,
+    proxy: Option<String>,
+) -> Result<()> {
+    let from_name = from_name.unwrap_or_else(|| name.clone());
+    let from_dir = super::config::directory(&from_root_dir, &from_name);
+    if !from_dir.is_dir() {
+        return Err(Error::SyncFromLocalNotFound(from_dir));
+    }
+
+    let blockchain = Blockchain::load(root_dir.clone(), name.clone())?;
+    let source = Blockchain::load(from_root_dir, from_name)?;
+    if source.config.genesis != blockchain.config.genesis
+        || source.config.protocol_magic != blockchain.config.protocol_magic
+    {
+        return Err(Error::SyncFromLocalWrongBlockchain(from_dir));
+    }
+
+    let (tip, is_genesis) = blockchain.load_tip();
+    let source_tip = source.load_tip().0;
+    source.check_not_pruned(&tip.date)?;
+    if source_tip.date <= tip.date {
+        term.info(&format!(
+            "{} is not ahead of the local blockchain\n",
+            from_dir.to_string_lossy()
+        ))?;
+    } else {
+        if !is_genesis && !source.is_on_chain(&tip.hash, &tip.date)? {
+            return Err(Error::SyncFromLocalForked(tip.hash));
+        }
+        let (count, new_tip) =
+            copy_blocks(term, &blockchain, &source, &tip, is_genesis, &source_tip)?;
+
+        // the peers' sync starts from the best of their tags, make them
+        // start after the copied blocks
+        for np in blockchain.peers() {
+            let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
+            if peer.load_local_tip().0.date < source_tip.date {
+                peer.save_peer_local_tip(&new_tip);
+            }
+        }
+
+        term.success(&format!(
+            "{} blocks copied from {}, local tip forwarded to {} ({})\n",
+            count,
+            from_dir.to_string_lossy(),
+            style!(&new_tip),
+            style!(source_tip.date)
+        ))?;
+    }
+
+    if offline {
+        return Ok(());
+    }
+    pull(term, root_dir, name, 1, proxy)
+}
+
+/// copy the blocks of `source` following the local tip up to `source_tip`,
+/// packing the stable epochs as they complete.
+///
+/// Returns the number of blocks copied and the new local tip.
+fn copy_blocks(
+    term: &mut Term,
+    blockchain: &Blockchain,
+    source: &Blockchain,
+    tip: &BlockRef,
+    is_genesis: bool,
+    source_tip: &BlockRef,
+) -> Result<(usize, HeaderHash)> {
+    let first_unstable_epoch =
+        peer::first_unstable_epoch(&source_tip.date, blockchain.config.epoch_stability_depth);
+    let progress = term.progress_bar((source_tip.date - tip.date) as u64);
+    progress.set_message("copying blocks -> ");
+
+    // from the genesis, the genesis block itself is copied too
+    let mut prev_hash = if is_genesis {
+        blockchain.config.genesis_prev.clone()
+    } else {
+        tip.hash.clone()
+    };
+    let mut count = 0;
+    for res in source.iter(tip.hash.clone(), source_tip.hash.clone())? {
+        let (raw, block) = res?;
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        if !is_genesis && hash == tip.hash {
+            continue;
+        }
+        if header.get_previous_header() != prev_hash {
+            return Err(Error::BlocksNotLinked(hash, prev_hash));
+        }
+        cardano::block::verify_block(&hash, &block)
+            .map_err(|e| Error::SyncFromLocalInvalidBlock(hash.clone(), format!("{:?}", e)))?;
+
+        // the previous epoch is complete, pack it if it is stable
+        let date = header.get_blockdate();
+        let epoch_id = date.get_epochid();
+        if date.is_boundary()
+            && epoch_id > blockchain.config.epoch_start
+            && epoch_id - 1 < first_unstable_epoch
+        {
+            peer::internal::maybe_create_epoch(&blockchain.storage, epoch_id - 1, &prev_hash);
+        }
+
+        let blob_hash = storage::types::header_to_blockhash(&hash);
+        storage::blob::write(&blockchain.storage, &blob_hash, raw.as_ref())?;
+
+        count += 1;
+        progress.inc(1);
+        if count % SYNC_FROM_LOCAL_CHECKPOINT_INTERVAL == 0 {
+            blockchain.save_tip(&hash);
+        }
+        prev_hash = hash;
+    }
+    progress.finish();
+
+    blockchain.save_tip(&prev_hash);
+    Ok((count, prev_hash))
+}
@@ -1,7 +1,10 @@
-use cardano::block::{self, BlockDate, HeaderHash};
+use cardano::block::{self, BlockDate, EpochId, HeaderHash};
+use cardano::coin;
+use cardano::tx::TxId;
 use cardano_storage;
 use cbor_event;
 use std::{error, fmt, io, path::PathBuf};
+use utils::{clock, era};
 
 #[derive(Debug)]
 pub enum Error {
@@ -12,6 +15,9 @@ pub enum Error {
 
     LoadConfigFileNotFound(PathBuf),
 
+    GenesisInvalidFile(PathBuf, String),
+    ProtocolParametersInvalidFile(PathBuf, String),
+
     ListNoBlockchains,
     ListPermissionsDenied,
     ListBlockchainInvalidName(::storage_units::utils::directory_name::DirectoryNameError),
@@ -22,6 +28,7 @@ pub enum Error {
     GetInvalidBlock(HeaderHash),
 
     CatMalformedBlock(cbor_event::Error),
+    UnsupportedEra(era::Error),
 
     VerifyInvalidBlock(block::Error),
     VerifyMalformedBlock(cbor_event::Error),
@@ -29,8 +36,40 @@ pub enum Error {
     VerifyChainGenesisHashNotFound(HeaderHash),
     VerifyChainInvalidGenesisPrevHash(HeaderHash, HeaderHash), // (Expected, got)
     BlockchainIsNotValid(usize),
+    BlockchainHasDamagedEpochs(usize),
+    RepairEpochFailed(EpochId, String),
 
     QueryBlockDateNotResolved(BlockDate),
+    QueryTransactionNotFound(TxId),
+    QueryInvalidAmount(u64, coin::Error),
+
+    BlocksNotLinked(HeaderHash, HeaderHash), // (block, expected previous block)
+    ExportBlocksInvalidRange(BlockDate, BlockDate),
+    ImportBlocksInvalidFile(PathBuf, String),
+    ImportBlocksWrongBlockchain(HeaderHash),
+    ImportBlocksInvalidBlock(HeaderHash, String),
+    ImportBlocksNotLinked(HeaderHash),
+    ImportBlocksForkFromTip(HeaderHash),
+    SyncFromLocalNotFound(PathBuf),
+    SyncFromLocalWrongBlockchain(PathBuf),
+    SyncFromLocalForked(HeaderHash),
+    SyncFromLocalInvalidBlock(HeaderHash, String),
+
+    PeerNotFound(String),
+    PeersUnreachable,
+    PeerRequestFailed(String, String), // (peer, error)
+    ClockSkewed(clock::Error),
+    SeedInvalid(String),
+    SeedAlreadyExists(String),
+    SeedNotFound(String),
+    SeedsInvalidFile(PathBuf, String),
+    ProxyInvalid(String),
+
+    PruneInvalidMarker(PathBuf),
+    PruneWalletNotSynced(String),
+    PruneWalletNeedsEpoch(String, EpochId),
+    PruneWalletLoadFailed(String),
+    EpochsPruned(EpochId),
 }
 
 impl From<io::Error> for Error {
@@ -55,6 +94,8 @@ impl fmt::Display for Error {
 
             Error::NewCannotInitializeBlockchainDirectory(_) => write!(f, "Cannot Initialise the blockchain directory"),
             Error::LoadConfigFileNotFound(p)                 => write!(f, "Cannot load blockchain configuration from `{}`; is the blockchain initialized?", p.to_string_lossy()),
+            Error::GenesisInvalidFile(p, reason)             => write!(f, "Invalid genesis data file `{}`: {}", p.to_string_lossy(), reason),
+            Error::ProtocolParametersInvalidFile(p, reason)  => write!(f, "Invalid protocol parameters file `{}`: {}", p.to_string_lossy(), reason),
             Error::ListNoBlockchains                         => write!(f, "No local blockchains yet"),
             Error::ListPermissionsDenied                     => write!(f, "No local blockchains (permission denied to the cardano-cli directory, check the `root-dir` option of the CLI)"),
             Error::ListBlockchainInvalidName(_)              => write!(f, "Blockchain with invalid name"),
@@ -62,14 +103,48 @@ impl fmt::Display for Error {
             Error::GetBlockDoesNotExist(hh)                  => write!(f, "Block `{}` does not exist", hh),
             Error::GetInvalidBlock(hh)                       => write!(f, "Block `{}` cannot be read from the local storage", hh),
             Error::CatMalformedBlock(_)                      => write!(f, "Unsupported or corrupted block"),
+            Error::UnsupportedEra(_)                         => write!(f, "Unsupported era"),
             Error::VerifyInvalidBlock(_)                     => write!(f, "Block is not valid"),
             Error::VerifyMalformedBlock(_)                   => write!(f, "Unsupported or corrupted block"),
             Error::VerifyChainGenesisHashNotFound(hh)        => write!(f, "Genesis data for given blockchain not found ({})", hh),
             Error::VerifyChainInvalidGenesisPrevHash(eh, hh) => write!(f, "Genesis data invalid: expected previous hash {} different from the one provided {}", eh, hh),
             Error::BlockchainIsNotValid(num_invalid_blocks)  => write!(f, "Blockchain has {} invalid blocks", num_invalid_blocks),
+            Error::BlockchainHasDamagedEpochs(num)           => write!(f, "Blockchain has {} damaged epoch packs, use `--repair' to download them again", num),
+            Error::RepairEpochFailed(epoch, err)             => write!(f, "Cannot repair epoch {}: {}", epoch, err),
             Error::QueryBlockDateNotResolved(date) => {
                 write!(f, "Cannot resolve block date {}", date)
             }
+            Error::QueryTransactionNotFound(txid) => {
+                write!(f, "Transaction {} not found in the local blockchain", txid)
+            }
+            Error::QueryInvalidAmount(amount, _) => {
+                write!(f, "Invalid amount of {} lovelace", amount)
+            }
+            Error::BlocksNotLinked(hh, prev) => write!(f, "Block {} does not follow block {}", hh, prev),
+            Error::ExportBlocksInvalidRange(from, to) => write!(f, "Invalid range of blocks, {} is after {}", from, to),
+            Error::ImportBlocksInvalidFile(p, reason) => write!(f, "Invalid block file `{}`: {}", p.to_string_lossy(), reason),
+            Error::ImportBlocksWrongBlockchain(genesis) => write!(f, "The blocks were exported from another blockchain (genesis {})", genesis),
+            Error::ImportBlocksInvalidBlock(hh, reason) => write!(f, "Block {} is not valid: {}", hh, reason),
+            Error::ImportBlocksNotLinked(hh) => write!(f, "The blocks follow block {}, which is not part of the local blockchain", hh),
+            Error::ImportBlocksForkFromTip(tip) => write!(f, "The blocks do not extend the local tip {}", tip),
+            Error::SyncFromLocalNotFound(p) => write!(f, "No blockchain in `{}`", p.to_string_lossy()),
+            Error::SyncFromLocalWrongBlockchain(p) => write!(f, "The blockchain in `{}` is another blockchain (different genesis)", p.to_string_lossy()),
+            Error::SyncFromLocalForked(tip) => write!(f, "The local tip {} is not part of the other blockchain", tip),
+            Error::SyncFromLocalInvalidBlock(hh, reason) => write!(f, "Block {} of the other blockchain is not valid: {}", hh, reason),
+            Error::PeerNotFound(peer) => write!(f, "No peer `{}' in the blockchain", peer),
+            Error::PeersUnreachable => write!(f, "None of the peers of the blockchain could be reached, check them with `blockchain peer test'"),
+            Error::PeerRequestFailed(peer, err) => write!(f, "The request to the peer `{}' failed: {}", peer, err),
+            Error::ClockSkewed(_) => write!(f, "The local clock is skewed, the peers would reject the blocks"),
+            Error::SeedInvalid(seed) => write!(f, "Invalid DNS seed `{}', expected a domain name and a port (e.g. `relays.cardano-mainnet.iohk.io:3000')", seed),
+            Error::SeedAlreadyExists(seed) => write!(f, "DNS seed `{}' is already a seed of the blockchain", seed),
+            Error::SeedNotFound(seed) => write!(f, "No DNS seed `{}' in the blockchain", seed),
+            Error::SeedsInvalidFile(p, reason) => write!(f, "Invalid DNS seeds file `{}`: {}", p.to_string_lossy(), reason),
+            Error::ProxyInvalid(proxy) => write!(f, "Invalid proxy `{}', expected the host and the port of a SOCKS5 proxy (e.g. `127.0.0.1:9050')", proxy),
+            Error::PruneInvalidMarker(p) => write!(f, "Invalid pruned epochs marker `{}`", p.to_string_lossy()),
+            Error::PruneWalletNotSynced(w) => write!(f, "Wallet `{}' is attached to the blockchain but not synced yet, it still needs all the epochs", w),
+            Error::PruneWalletNeedsEpoch(w, epoch) => write!(f, "Wallet `{}' is synced up to epoch {}, sync the wallet before pruning", w, epoch),
+            Error::PruneWalletLoadFailed(w) => write!(f, "Cannot load the state of wallet `{}'", w),
+            Error::EpochsPruned(epoch) => write!(f, "The blocks before epoch {} have been pruned from the local blockchain", epoch),
         }
     }
 }
@@ -81,8 +156,11 @@ impl error::Error for Error {
             Error::NewCannotInitializeBlockchainDirectory(ref err) => Some(err),
             Error::ListBlockchainInvalidName(ref err) => Some(err),
             Error::CatMalformedBlock(ref err) => Some(err),
+            Error::UnsupportedEra(ref err) => Some(err),
             Error::VerifyInvalidBlock(ref err) => Some(err),
             Error::VerifyMalformedBlock(ref err) => Some(err),
+            Error::ClockSkewed(ref err) => Some(err),
+            Error::QueryInvalidAmount(_, ref err) => Some(err),
             _ => None,
         }
     }
@@ -0,0 +1,366 @@
+//! look up blocks and transactions in the local storage
+//!
+//! The local storage has no index of the transactions: finding a
+//! transaction, or the outputs spent by the inputs of a transaction, means
+//! walking the blockchain backward from the local tip.
+
+use std::{collections::BTreeMap, fmt, io::Write, str::FromStr};
+
+use cardano::{
+    block::{Block, BlockDate, ChainState, HeaderHash},
+    coin::Coin,
+    tx::{TxAux, TxId, TxOut, TxoPointer},
+};
+use cardano_storage as storage;
+use serde_json;
+
+use schema::Schema;
+use utils::term::{style::Style, Term};
+
+use super::{Blockchain, Error, Result};
+
+/// a block given either by its hash or by its date
+pub enum BlockQuery {
+    Hash(HeaderHash),
+    Date(BlockDate),
+}
+impl FromStr for BlockQuery {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        if let Ok(hash) = HeaderHash::from_str(s) {
+            return Ok(BlockQuery::Hash(hash));
+        }
+        match BlockDate::from_str(s) {
+            Ok(date) => Ok(BlockQuery::Date(date)),
+            Err(_) => Err(format!("`{}' is neither a block hash nor a block date", s)),
+        }
+    }
+}
+impl fmt::Display for BlockQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockQuery::Hash(hash) => write!(f, "{}", hash),
+            BlockQuery::Date(date) => write!(f, "{}", date),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct BlockDetails {
+    pub hash: String,
+    pub date: String,
+    pub previous: String,
+    pub transactions: Vec<TxDetails>,
+}
+
+#[derive(Serialize)]
+pub struct TxDetails {
+    pub txid: String,
+    pub block: String,
+    pub date: String,
+    pub inputs: Vec<InputDetails>,
+    pub outputs: Vec<OutputDetails>,
+    /// the total of the inputs minus the total of the outputs, only known
+    /// if all the spent outputs were found.
+    pub fee: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct InputDetails {
+    pub txid: String,
+    pub index: u32,
+    /// the spent output, if found in the local blockchain or in its
+    /// genesis data
+    pub address: Option<String>,
+    pub amount: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct OutputDetails {
+    pub address: String,
+    pub amount: u64,
+}
+
+pub fn find_block(blockchain: &Blockchain, query: &BlockQuery) -> Result<(HeaderHash, Block)> {
+    let hash = match query {
+        BlockQuery::Hash(hash) => hash.clone(),
+        BlockQuery::Date(date) => {
+            let tip = blockchain.load_tip().0.hash;
+            match storage::resolve_date_to_blockhash(&blockchain.storage, &tip, date)? {
+                Some(hash) => HeaderHash::from(hash),
+                None => return Err(Error::QueryBlockDateNotResolved(date.clone())),
+            }
+        }
+    };
+
+    let raw = match blockchain.storage.read_block(hash.as_hash_bytes()) {
+        Err(storage::Error::BlockNotFound(_)) => return Err(Error::GetBlockDoesNotExist(hash)),
+        Err(err) => return Err(err.into()),
+        Ok(raw) => raw,
+    };
+    let block = raw
+        .decode()
+        .map_err(|_| Error::GetInvalidBlock(hash.clone()))?;
+    Ok((hash, block))
+}
+
+/// look for the transaction from the local tip down to the genesis,
+/// returns the block it was found in.
+pub fn find_transaction(
+    term: &mut Term,
+    blockchain: &Blockchain,
+    txid: &TxId,
+) -> Result<(Block, TxAux)> {
+    let progress = term.progress_spinner();
+    progress.set_message("looking for the transaction ");
+
+    let tip = blockchain.load_tip().0.hash;
+    let mut found = None;
+    let mut blocks = blockchain.reverse_iter(tip)?;
+    for block in &mut blocks {
+        progress.inc(1);
+        let txaux = block
+            .get_transactions()
+            .and_then(|txs| txs.iter().find(|txaux| &txaux.tx.id() == txid).cloned());
+        if let Some(txaux) = txaux {
+            found = Some((block, txaux));
+            break;
+        }
+    }
+    progress.finish_and_clear();
+
+    match (found, blocks.pruned_until()) {
+        (Some(found), _) => Ok(found),
+        (None, Some(pruned_until)) => Err(Error::EpochsPruned(pruned_until)),
+        (None, None) => Err(Error::QueryTransactionNotFound(txid.clone())),
+    }
+}
+
+/// describe the transactions of the given block, the outputs spent by
+/// all of them are looked up in one walk of the blockchain
+pub fn block_details(
+    term: &mut Term,
+    blockchain: &Blockchain,
+    hash: HeaderHash,
+    block: &Block,
+) -> Result<BlockDetails> {
+    let header = block.get_header();
+    let mut transactions = Vec::new();
+    if let Some(txs) = block.get_transactions() {
+        let spent = find_spent_outputs(
+            term,
+            blockchain,
+            hash.clone(),
+            txs.iter().flat_map(|txaux| txaux.tx.inputs.iter()),
+        )?;
+        for txaux in txs.iter() {
+            transactions.push(tx_details(block, txaux, &spent));
+        }
+    }
+
+    Ok(BlockDetails {
+        hash: format!("{}", hash),
+        date: format!("{}", header.get_blockdate()),
+        previous: format!("{}", header.get_previous_header()),
+        transactions,
+    })
+}
+
+/// describe the given transaction of the given block, with the outputs its
+/// inputs spend (see `find_spent_outputs`)
+pub fn tx_details(block: &Block, txaux: &TxAux, spent: &BTreeMap<TxoPointer, TxOut>) -> TxDetails {
+    let header = block.get_header();
+    let inputs: Vec<InputDetails> = txaux
+        .tx
+        .inputs
+        .iter()
+        .map(|input| {
+            let output = spent.get(input);
+            InputDetails {
+                txid: format!("{}", input.id),
+                index: input.index,
+                address: output.map(|output| format!("{}", output.address)),
+                amount: output.map(|output| u64::from(output.value)),
+            }
+        })
+        .collect();
+    let outputs: Vec<OutputDetails> = txaux
+        .tx
+        .outputs
+        .iter()
+        .map(|output| OutputDetails {
+            address: format!("{}", output.address),
+            amount: u64::from(output.value),
+        })
+        .collect();
+
+    let input_total = inputs.iter().try_fold(0u64, |total, input| {
+        input.amount.and_then(|amount| total.checked_add(amount))
+    });
+    let output_total = outputs
+        .iter()
+        .try_fold(0u64, |total, output| total.checked_add(output.amount));
+    let fee = match (input_total, output_total) {
+        (Some(input_total), Some(output_total)) => input_total.checked_sub(output_total),
+        _ => None,
+    };
+
+    TxDetails {
+        txid: format!("{}", txaux.tx.id()),
+        block: format!("{}", header.compute_hash()),
+        date: format!("{}", header.get_blockdate()),
+        inputs,
+        outputs,
+        fee,
+    }
+}
+
+/// look for the outputs spent by the given inputs, from the given block
+/// down to the genesis, then in the genesis data: the initial outputs and
+/// the AVVM redeem outputs are not in any block.
+///
+/// The blockchain is walked once for all the inputs. The outputs not found
+/// (e.g. in pruned epochs) are not in the returned map.
+pub fn find_spent_outputs<'a, I>(
+    term: &mut Term,
+    blockchain: &Blockchain,
+    from: HeaderHash,
+    inputs: I,
+) -> Result<BTreeMap<TxoPointer, TxOut>>
+where
+    I: IntoIterator<Item = &'a TxoPointer>,
+{
+    let mut missing: BTreeMap<TxId, Vec<u32>> = BTreeMap::new();
+    for input in inputs {
+        missing
+            .entry(input.id.clone())
+            .or_insert_with(Vec::new)
+            .push(input.index);
+    }
+    let mut spent = BTreeMap::new();
+
+    if !missing.is_empty() && from != blockchain.config.genesis_prev {
+        let progress = term.progress_spinner();
+        progress.set_message("looking for the spent outputs ");
+        for block in blockchain.reverse_iter(from)? {
+            progress.inc(1);
+            if let Some(txs) = block.get_transactions() {
+                for txaux in txs.iter() {
+                    let id = txaux.tx.id();
+                    let indexes = match missing.remove(&id) {
+                        None => continue,
+                        Some(indexes) => indexes,
+                    };
+                    for index in indexes {
+                        if let Some(output) = txaux.tx.outputs.get(index as usize) {
+                            spent.insert(TxoPointer::new(id.clone(), index), output.clone());
+                        }
+                    }
+                }
+            }
+            if missing.is_empty() {
+                break;
+            }
+        }
+        progress.finish_and_clear();
+    }
+
+    if !missing.is_empty() {
+        let genesis = ChainState::new(&blockchain.load_genesis_data()?);
+        for (id, indexes) in missing {
+            for index in indexes {
+                let pointer = TxoPointer::new(id.clone(), index);
+                if let Some(output) = genesis.utxos.get(&pointer) {
+                    spent.insert(pointer, output.clone());
+                }
+            }
+        }
+    }
+
+    Ok(spent)
+}
+
+pub fn display_block(term: &mut Term, details: &BlockDetails) -> Result<()> {
+    writeln!(
+        term,
+        "{} {}",
+        style!("Block").cyan().bold(),
+        style!(&details.hash)
+    )?;
+    writeln!(term, " * date:          {}", style!(&details.date))?;
+    writeln!(term, " * previous:      {}", style!(&details.previous))?;
+    writeln!(
+        term,
+        " * transactions:  {}",
+        style!(details.transactions.len())
+    )?;
+    for tx in details.transactions.iter() {
+        writeln!(term)?;
+        display_transaction(term, tx)?;
+    }
+    Ok(())
+}
+
+pub fn display_transaction(term: &mut Term, details: &TxDetails) -> Result<()> {
+    writeln!(
+        term,
+        "{} {}",
+        style!("Transaction").cyan().bold(),
+        style!(&details.txid)
+    )?;
+    writeln!(
+        term,
+        " * block:    {} ({})",
+        style!(&details.block),
+        style!(&details.date)
+    )?;
+    writeln!(term, " * inputs:")?;
+    for input in details.inputs.iter() {
+        match (&input.address, input.amount) {
+            (Some(address), Some(amount)) => writeln!(
+                term,
+                "   - {}.{} {} {}",
+                style!(&input.txid),
+                style!(input.index).yellow(),
+                style!(address),
+                style!(coin(amount)?).green()
+            )?,
+            _ => writeln!(
+                term,
+                "   - {}.{} {}",
+                style!(&input.txid),
+                style!(input.index).yellow(),
+                style!("spent output not found locally").red()
+            )?,
+        }
+    }
+    writeln!(term, " * outputs:")?;
+    for output in details.outputs.iter() {
+        writeln!(
+            term,
+            "   - {} {}",
+            style!(&output.address),
+            style!(coin(output.amount)?).green()
+        )?;
+    }
+    match details.fee {
+        Some(fee) => writeln!(term, " * fee:      {}", style!(coin(fee)?).green())?,
+        None => writeln!(term, " * fee:      {}", style!("unknown").red())?,
+    }
+    Ok(())
+}
+
+pub fn display_json<T: ::serde::Serialize>(
+    term: &mut Term,
+    schema: &Schema,
+    details: &T,
+) -> Result<()> {
+    schema.debug_check(details);
+    serde_json::to_writer_pretty(&mut *term, details).map_err(::std::io::Error::from)?;
+    writeln!(term)?;
+    Ok(())
+}
+
+fn coin(amount: u64) -> Result<Coin> {
+    Coin::new(amount).map_err(|err| Error::QueryInvalidAmount(amount, err))
+}
@@ -0,0 +1,420 @@
+//! parallel download of the stable epochs
+//!
+//! The HTTP peers of a blockchain (hermes, cardano-http-bridge...) serve the
+//! stable epochs already packed. This module downloads several of these packs
+//! concurrently, from all the HTTP peers of the blockchain, verifies them and
+//! writes them in order in the local storage.
+//!
+//! The unstable epochs are not served packed, they are still fetched by the
+//! native peers' sync.
+
+use super::{health, peer, proxy, Blockchain};
+use cardano::block::{self, EpochId, HeaderHash, RawBlock};
+use cardano_storage as storage;
+use exe_common::network::api::BlockRef;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use storage_units::packfile;
+use utils::{era, interaction::Interaction};
+
+/// an epoch, downloaded and verified: the hash and the raw block of every
+/// block of the epoch, in order.
+struct FetchedEpoch {
+    blocks: Vec<(HeaderHash, HeaderHash, RawBlock)>,
+}
+
+struct Endpoint {
+    /// the name of the peer in the blockchain configuration
+    name: String,
+    url: String,
+    /// the SOCKS5 proxy of the blockchain
+    proxy: Option<String>,
+}
+
+/// download the stable epochs not in the local storage yet, with up to
+/// `jobs` concurrent downloads.
+///
+/// Returns the number of epochs written in the local storage.
+pub fn fetch_stable_epochs(
+    term: &mut Interaction,
+    blockchain: &Blockchain,
+    jobs: usize,
+) -> Result<usize, String> {
+    let endpoints = http_endpoints(blockchain)?;
+
+    // start from the best tip we know of, it needs to be the last block of a
+    // packed epoch (or the genesis) as we are downloading full epochs.
+    let best_tip = blockchain.load_remote_tips().into_iter().fold(
+        blockchain.load_tip(),
+        |best_tip, current_tip| {
+            if best_tip.0.date < current_tip.0.date {
+                current_tip
+            } else {
+                best_tip
+            }
+        },
+    );
+    let (first_epoch, prev_hash) = if best_tip.1 {
+        (
+            blockchain.config.epoch_start,
+            blockchain.config.genesis_prev.clone(),
+        )
+    } else if peer::internal::epoch_exists(&blockchain.storage, best_tip.0.date.get_epochid()) {
+        (best_tip.0.date.get_epochid() + 1, best_tip.0.hash.clone())
+    } else {
+        // some blocks of the epoch have already been fetched, the native
+        // sync will complete the epoch.
+        return Ok(0);
+    };
+
+    let remote_tip = query_remote_tip(term, blockchain)?;
+    let end_epoch =
+        peer::first_unstable_epoch(&remote_tip.date, blockchain.config.epoch_stability_depth);
+    if first_epoch >= end_epoch {
+        return Ok(0);
+    }
+
+    term.info(&format!(
+        "downloading epochs {} to {} with {} jobs\n",
+        first_epoch,
+        end_epoch - 1,
+        jobs
+    ))
+    .unwrap();
+
+    let queue: Arc<Mutex<VecDeque<EpochId>>> =
+        Arc::new(Mutex::new((first_epoch..end_epoch).collect()));
+    let next_to_write = Arc::new(AtomicUsize::new(first_epoch as usize));
+    let stop = Arc::new(AtomicBool::new(false));
+    let endpoints = Arc::new(endpoints);
+    let (sender, receiver) = mpsc::channel();
+
+    let mut workers = Vec::with_capacity(jobs);
+    for _ in 0..jobs {
+        let queue = queue.clone();
+        let next_to_write = next_to_write.clone();
+        let stop = stop.clone();
+        let endpoints = endpoints.clone();
+        let sender = sender.clone();
+        workers.push(thread::spawn(move || {
+            worker(queue, next_to_write, stop, endpoints, sender, jobs)
+        }));
+    }
+    drop(sender);
+
+    let progress = term.progress((end_epoch - first_epoch) as u64);
+    progress.set_message("downloading epochs -> ");
+
+    let mut pending = BTreeMap::new();
+    let mut prev_hash = prev_hash;
+    let mut written = 0;
+    let mut result = Ok(());
+    for (epoch_id, fetched) in receiver {
+        pending.insert(epoch_id, fetched);
+
+        // write the epochs in order, as soon as they are available
+        let mut next = next_to_write.load(Ordering::SeqCst) as EpochId;
+        while let Some(fetched) = pending.remove(&next) {
+            let epoch = match fetched {
+                Ok(epoch) => epoch,
+                Err(err) => {
+                    result = Err(format!("cannot download epoch {}: {}", next, err));
+                    break;
+                }
+            };
+            if let Err(err) = check_chain(&prev_hash, &epoch) {
+                result = Err(format!("epoch {} is invalid: {}", next, err));
+                break;
+            }
+            prev_hash = write_epoch(&blockchain.storage, next, &epoch);
+            peer::Peer::prepare(blockchain, endpoints[0].name.clone())
+                .save_peer_local_tip(&prev_hash);
+
+            written += 1;
+            progress.inc(1);
+            next += 1;
+            next_to_write.store(next as usize, Ordering::SeqCst);
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+    progress.finish();
+
+    stop.store(true, Ordering::SeqCst);
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    result.map(|()| written)
+}
+
+/// download again the given epoch, replacing the pack in the local storage.
+///
+/// `prev_hash` is the hash of the last block of the previous epoch, if
+/// known. Returns the hash of the last block of the epoch.
+pub fn refetch_epoch(
+    blockchain: &Blockchain,
+    epoch_id: EpochId,
+    prev_hash: Option<&HeaderHash>,
+) -> Result<HeaderHash, String> {
+    let endpoints = http_endpoints(blockchain)?;
+
+    let mut result = Err("no peers".to_owned());
+    for endpoint in endpoints.iter() {
+        result = fetch_epoch(endpoint, epoch_id).and_then(|epoch| match prev_hash {
+            Some(prev_hash) => check_chain(prev_hash, &epoch).map(|()| epoch),
+            None => Ok(epoch),
+        });
+        match result {
+            Ok(_) => break,
+            Err(ref err) => warn!(
+                "cannot download epoch {} from {}: {}",
+                epoch_id, endpoint.name, err
+            ),
+        }
+    }
+    let epoch = result?;
+
+    let damaged_pack = storage::epoch::epoch_read_pack(&blockchain.storage.config, epoch_id).ok();
+    let last_hash = write_epoch(&blockchain.storage, epoch_id, &epoch);
+
+    // the new pack has a different hash than the damaged one, remove the
+    // damaged pack from the storage.
+    if let Some(packhash) = damaged_pack {
+        let new_pack = storage::epoch::epoch_read_pack(&blockchain.storage.config, epoch_id)
+            .map_err(|e| e.to_string())?;
+        if new_pack != packhash {
+            let _ = fs::remove_file(blockchain.storage.config.get_pack_filepath(&packhash));
+            let _ = fs::remove_file(blockchain.storage.config.get_index_filepath(&packhash));
+        }
+    }
+
+    Ok(last_hash)
+}
+
+fn http_endpoints(blockchain: &Blockchain) -> Result<Vec<Endpoint>, String> {
+    let endpoints: Vec<Endpoint> = blockchain
+        .peers()
+        .filter_map(|np| match np.peer() {
+            super::Peer::Http(url) => Some(Endpoint {
+                name: np.name().to_owned(),
+                url: format!("{}/{}", url.trim_end_matches('/'), blockchain.name),
+                proxy: blockchain.proxy.clone(),
+            }),
+            _ => None,
+        })
+        .collect();
+    if endpoints.is_empty() {
+        return Err("no HTTP peers to download the epochs from".to_owned());
+    }
+    Ok(endpoints)
+}
+
+fn query_remote_tip(term: &mut Interaction, blockchain: &Blockchain) -> Result<BlockRef, String> {
+    for peer_name in health::ordered_peers(blockchain, true) {
+        let peer = peer::Peer::prepare(blockchain, peer_name);
+        if let Ok(mut connected) = peer.connect(term) {
+            return Ok(connected.query_tip());
+        }
+    }
+    Err("cannot query the tip from any of the native peers".to_owned())
+}
+
+fn worker(
+    queue: Arc<Mutex<VecDeque<EpochId>>>,
+    next_to_write: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    endpoints: Arc<Vec<Endpoint>>,
+    sender: mpsc::Sender<(EpochId, Result<FetchedEpoch, String>)>,
+    jobs: usize,
+) {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // don't get too far ahead of the writer, the epochs are kept in
+        // memory until they are written.
+        let epoch_id = {
+            let mut queue = queue.lock().unwrap();
+            let ready = match queue.front() {
+                None => return,
+                Some(epoch_id) => {
+                    (*epoch_id as usize) < next_to_write.load(Ordering::SeqCst) + 2 * jobs
+                }
+            };
+            if ready {
+                queue.pop_front()
+            } else {
+                None
+            }
+        };
+        let epoch_id = match epoch_id {
+            None => {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Some(epoch_id) => epoch_id,
+        };
+
+        // spread the epochs across the peers, falling back to the other
+        // peers if the download fails.
+        let mut result = Err("no peers".to_owned());
+        for attempt in 0..endpoints.len() {
+            let endpoint = &endpoints[(epoch_id as usize + attempt) % endpoints.len()];
+            let start = SystemTime::now();
+            result = fetch_epoch(endpoint, epoch_id);
+            match result {
+                Ok(_) => {
+                    debug!(
+                        "epoch {} downloaded from {} in {:?}",
+                        epoch_id,
+                        endpoint.name,
+                        start.elapsed().unwrap_or_default()
+                    );
+                    break;
+                }
+                Err(ref err) => {
+                    warn!(
+                        "cannot download epoch {} from {}: {}",
+                        epoch_id, endpoint.name, err
+                    );
+                }
+            }
+        }
+
+        if sender.send((epoch_id, result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// download the packed epoch and verify each of its blocks
+fn fetch_epoch(endpoint: &Endpoint, epoch_id: EpochId) -> Result<FetchedEpoch, String> {
+    let url = format!("{}/epoch/{}", endpoint.url, epoch_id);
+    let bytes =
+        http_get(&url, endpoint.proxy.as_ref().map(String::as_str)).map_err(|e| e.to_string())?;
+
+    let mut reader = packfile::Reader::init(io::Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let mut blocks = Vec::new();
+    while let Some(data) = reader.next_block().map_err(|e| e.to_string())? {
+        let block_raw = RawBlock::from_dat(data);
+        era::check_block(block_raw.as_ref()).map_err(|e| e.to_string())?;
+        let block = block_raw.decode().map_err(|e| e.to_string())?;
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        let date = header.get_blockdate();
+
+        if date.get_epochid() != epoch_id {
+            return Err(format!("block {} ({}) is not in the epoch", hash, date));
+        }
+        if blocks.is_empty() && !date.is_boundary() {
+            return Err("epoch does not start with a boundary block".to_owned());
+        }
+        block::verify_block(&hash, &block).map_err(|e| format!("block {}: {:?}", hash, e))?;
+
+        blocks.push((hash, header.get_previous_header(), block_raw));
+    }
+    if blocks.is_empty() {
+        return Err("empty epoch".to_owned());
+    }
+
+    Ok(FetchedEpoch { blocks })
+}
+
+/// check the blocks of the epoch are chained together, and chained to
+/// the last block we have.
+fn check_chain(prev_hash: &HeaderHash, epoch: &FetchedEpoch) -> Result<(), String> {
+    let mut prev_hash = prev_hash;
+    for (hash, previous, _) in epoch.blocks.iter() {
+        if previous != prev_hash {
+            return Err(format!(
+                "block {} does not follow block {}",
+                hash, prev_hash
+            ));
+        }
+        prev_hash = hash;
+    }
+    Ok(())
+}
+
+/// write the epoch pack in the local storage, returns the hash of the last
+/// block of the epoch
+fn write_epoch(storage: &storage::Storage, epoch_id: EpochId, epoch: &FetchedEpoch) -> HeaderHash {
+    let epoch_time_start = SystemTime::now();
+    let mut writer = storage::pack::packwriter_init(&storage.config).unwrap();
+    for (hash, _, block_raw) in epoch.blocks.iter() {
+        writer
+            .append(
+                &storage::types::header_to_blockhash(hash),
+                block_raw.as_ref(),
+            )
+            .unwrap();
+    }
+    peer::internal::finish_epoch(storage, epoch_id, writer, &epoch_time_start, &[]);
+
+    epoch.blocks.last().unwrap().0.clone()
+}
+
+/// minimal HTTP/1.0 client, the HTTP peers are plain HTTP endpoints
+fn http_get(url: &str, proxy: Option<&str>) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+    if !url.starts_with("http://") {
+        return Err(invalid(format!("unsupported url `{}'", url)));
+    }
+    let url = &url["http://".len()..];
+    let (host, path) = match url.find('/') {
+        Some(idx) => (&url[..idx], &url[idx..]),
+        None => (url, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let mut stream = match proxy {
+        None => TcpStream::connect(address)?,
+        Some(proxy) => proxy::connect(proxy, &address, health::PROBE_TIMEOUT)?,
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(120)))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: cardano-cli\r\n\r\n",
+        path, host
+    )?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("GET {}: {}", path, status.trim()),
+        ));
+    }
+    // skip the headers
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+    Ok(body)
+}
@@ -0,0 +1,176 @@
+//! health of the peers of a blockchain
+//!
+//! Each connection attempt to a peer is recorded (latency of the
+//! connection, consecutive failures) in the blockchain directory. The peers
+//! are then tried in order: the available ones first, the fastest first,
+//! so a relay which is down does not delay every `pull` or `send`.
+//!
+//! Before the handshake, the peer is probed with a TCP connection bounded by
+//! `PROBE_TIMEOUT`: a dead peer fails fast instead of hanging until the
+//! system's connection timeout.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, SystemTime},
+};
+
+use serde_yaml;
+
+use super::{Blockchain, Peer};
+
+/// file, in the blockchain directory, keeping the health of the peers
+const HEALTH_FILE: &'static str = "peers-health.yml";
+
+/// how long to wait for the TCP connection to a peer
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerHealth {
+    /// duration of the last successful connection, in milliseconds
+    pub latency: Option<u64>,
+    /// number of failed connections since the last successful one
+    pub consecutive_failures: u32,
+    pub successes: u64,
+    pub failures: u64,
+    pub last_check: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+impl PeerHealth {
+    pub fn is_available(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+}
+
+/// the health of all the peers of a blockchain, by peer name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Health(BTreeMap<String, PeerHealth>);
+impl Health {
+    /// load the health of the peers, a missing or unreadable file is
+    /// an empty history
+    pub fn load(blockchain: &Blockchain) -> Self {
+        let path = blockchain.dir.join(HEALTH_FILE);
+        match fs::File::open(&path) {
+            Err(_) => Health::default(),
+            Ok(file) => serde_yaml::from_reader(file).unwrap_or_else(|err| {
+                warn!(
+                    "ignoring the peers' health, cannot read {:?}: {}",
+                    path, err
+                );
+                Health::default()
+            }),
+        }
+    }
+
+    pub fn save(&self, blockchain: &Blockchain) {
+        let path = blockchain.dir.join(HEALTH_FILE);
+        let result = fs::File::create(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_yaml::to_writer(file, self).map_err(|e| e.to_string()));
+        if let Err(err) = result {
+            warn!("cannot save the peers' health to {:?}: {}", path, err);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PeerHealth> {
+        self.0.get(name)
+    }
+
+    /// forget about a peer removed from the blockchain
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn record_success(&mut self, name: &str, latency: Duration) {
+        let health = self
+            .0
+            .entry(name.to_owned())
+            .or_insert_with(PeerHealth::default);
+        health.latency = Some(latency.as_secs() * 1000 + u64::from(latency.subsec_millis()));
+        health.consecutive_failures = 0;
+        health.successes += 1;
+        health.last_check = Some(SystemTime::now());
+        health.last_error = None;
+    }
+
+    pub fn record_failure(&mut self, name: &str, error: String) {
+        let health = self
+            .0
+            .entry(name.to_owned())
+            .or_insert_with(PeerHealth::default);
+        health.consecutive_failures += 1;
+        health.failures += 1;
+        health.last_check = Some(SystemTime::now());
+        health.last_error = Some(error);
+    }
+}
+
+/// record the outcome of a connection to the given peer
+pub fn record(blockchain: &Blockchain, name: &str, result: Result<Duration, String>) {
+    let mut health = Health::load(blockchain);
+    match result {
+        Ok(latency) => health.record_success(name, latency),
+        Err(error) => health.record_failure(name, error),
+    }
+    health.save(blockchain);
+}
+
+/// the names of the peers of the blockchain in the order they should be
+/// tried: the available peers first, by increasing latency, then the peers
+/// never checked, then the failing ones. Peers with the same health keep
+/// the order of the blockchain configuration.
+pub fn ordered_peers(blockchain: &Blockchain, native_only: bool) -> Vec<String> {
+    let health = Health::load(blockchain);
+    let mut peers: Vec<(u8, u64, String)> = blockchain
+        .peers()
+        .filter(|np| !native_only || np.is_native())
+        .map(|np| {
+            let name = np.name().to_owned();
+            match health.get(&name) {
+                Some(h) if !h.is_available() => (2, u64::from(h.consecutive_failures), name),
+                Some(h) => match h.latency {
+                    Some(latency) => (0, latency, name),
+                    None => (1, 0, name),
+                },
+                None => (1, 0, name),
+            }
+        })
+        .collect();
+    peers.sort_by_key(|&(class, rank, _)| (class, rank));
+    peers.into_iter().map(|(_, _, name)| name).collect()
+}
+
+/// open a TCP connection to the peer, bounded by `PROBE_TIMEOUT`
+///
+/// Returns how long the connection took.
+pub fn probe(peer: &Peer) -> Result<Duration, String> {
+    let address = match peer {
+        Peer::Native(address) => address.clone(),
+        Peer::Http(url) => {
+            let host = url
+                .trim_start_matches("http://")
+                .split('/')
+                .next()
+                .unwrap_or("");
+            if host.contains(':') {
+                host.to_owned()
+            } else {
+                format!("{}:80", host)
+            }
+        }
+    };
+
+    let start = SystemTime::now();
+    let addresses = address
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve `{}': {}", address, e))?;
+    let mut error = format!("`{}' does not resolve to any address", address);
+    for socket_addr in addresses {
+        match TcpStream::connect_timeout(&socket_addr, PROBE_TIMEOUT) {
+            Ok(_) => return Ok(start.elapsed().unwrap_or_default()),
+            Err(err) => error = format!("cannot connect to {}: {}", socket_addr, err),
+        }
+    }
+    Err(error)
+}
@@ -0,0 +1,210 @@
+//! integrity check of the epoch packs stored locally
+//!
+//! Every block of a pack is decoded and verified, and the blocks need to be
+//! chained together, starting with the epoch boundary block. A damaged pack
+//! can be downloaded again from the HTTP peers of the blockchain (see
+//! `fetcher::refetch_epoch`).
+
+use super::{peer, Blockchain};
+use cardano::block::{self, EpochId, HeaderHash, RawBlock};
+use cardano_storage as storage;
+use std::ops::Range;
+
+/// the result of the check of one epoch pack
+pub struct EpochCheck {
+    pub epoch_id: EpochId,
+    /// the hash of the last block of the epoch, or the reason why the
+    /// pack is damaged.
+    pub result: Result<HeaderHash, String>,
+}
+
+/// check the epoch packs stored locally, from the first epoch not pruned
+/// to the last packed epoch.
+///
+/// The given function is called after each epoch has been checked.
+pub fn check_epochs<F>(blockchain: &Blockchain, mut on_epoch: F) -> super::Result<Vec<EpochCheck>>
+where
+    F: FnMut(&EpochCheck),
+{
+    let first_epoch = blockchain
+        .load_pruned_until()?
+        .unwrap_or(blockchain.config.epoch_start);
+
+    // the previous hash of the first epoch is only known when starting from
+    // the genesis, the pruned epochs' blocks are not available anymore.
+    let mut prev_hash = if first_epoch == blockchain.config.epoch_start {
+        Some(blockchain.config.genesis_prev.clone())
+    } else {
+        None
+    };
+
+    let mut checks = Vec::new();
+    let mut epoch_id = first_epoch;
+    while peer::internal::epoch_exists(&blockchain.storage, epoch_id) {
+        let check = EpochCheck {
+            epoch_id,
+            result: check_epoch(&blockchain.storage, epoch_id, prev_hash.as_ref()),
+        };
+        on_epoch(&check);
+
+        // we don't know where a damaged epoch ends, don't check the link
+        // of the next epoch with it.
+        prev_hash = check.result.as_ref().ok().cloned();
+        checks.push(check);
+        epoch_id += 1;
+    }
+    Ok(checks)
+}
+
+/// check the pack of the given epoch, returns the hash of its last block
+pub fn check_epoch(
+    storage: &storage::Storage,
+    epoch_id: EpochId,
+    prev_hash: Option<&HeaderHash>,
+) -> Result<HeaderHash, String> {
+    let mut reader = storage::epoch::epoch_open_pack_reader(&storage.config, epoch_id)
+        .map_err(|e| e.to_string())?;
+
+    let mut prev_hash = prev_hash.cloned();
+    let mut first = true;
+    while let Some(data) = reader.next_block().map_err(|e| e.to_string())? {
+        let block = RawBlock::from_dat(data)
+            .decode()
+            .map_err(|e| format!("malformed block: {}", e))?;
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        let date = header.get_blockdate();
+
+        if date.get_epochid() != epoch_id {
+            return Err(format!("block {} ({}) is not in the epoch", hash, date));
+        }
+        if first && !date.is_boundary() {
+            return Err("epoch does not start with a boundary block".to_owned());
+        }
+        if let Some(ref prev_hash) = prev_hash {
+            if &header.get_previous_header() != prev_hash {
+                return Err(format!(
+                    "block {} ({}) does not follow block {}",
+                    hash, date, prev_hash
+                ));
+            }
+        }
+        block::verify_block(&hash, &block)
+            .map_err(|e| format!("block {} ({}): {:?}", hash, date, e))?;
+
+        prev_hash = Some(hash);
+        first = false;
+    }
+
+    prev_hash
+        .filter(|_| !first)
+        .ok_or_else(|| "empty epoch".to_owned())
+}
+
+/// group the damaged epochs in ranges of consecutive epochs
+pub fn damaged_ranges(checks: &[EpochCheck]) -> Vec<Range<EpochId>> {
+    let mut ranges: Vec<Range<EpochId>> = Vec::new();
+    for check in checks.iter().filter(|check| check.result.is_err()) {
+        let follows_last = ranges
+            .last()
+            .map(|range| range.end == check.epoch_id)
+            .unwrap_or(false);
+        if follows_last {
+            ranges.last_mut().unwrap().end += 1;
+        } else {
+            ranges.push(check.epoch_id..check.epoch_id + 1);
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, process, time::SystemTime};
+
+    fn temp_storage(name: &str) -> storage::Storage {
+        let dir = ::std::env::temp_dir().join(format!(
+            "cardano-cli-integrity-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        storage::Storage::init(&storage::config::StorageConfig::new(&dir)).unwrap()
+    }
+
+    fn write_pack(storage: &storage::Storage, epoch_id: EpochId, block: &[u8]) {
+        let mut writer = storage::pack::packwriter_init(&storage.config).unwrap();
+        let hash = storage::types::header_to_blockhash(&HeaderHash::new(block));
+        writer.append(&hash, block).unwrap();
+        peer::internal::finish_epoch(storage, epoch_id, writer, &SystemTime::now(), &[]);
+    }
+
+    fn healthy(epoch_id: EpochId) -> EpochCheck {
+        EpochCheck {
+            epoch_id,
+            result: Ok(HeaderHash::new(&[epoch_id as u8])),
+        }
+    }
+
+    #[test]
+    fn corrupted_pack_is_reported() {
+        let storage = temp_storage("corrupted");
+        write_pack(&storage, 3, b"not a cbor encoded block");
+        write_pack(&storage, 4, b"not a cbor encoded block either");
+
+        // truncate the pack of epoch 4 in the middle of its block
+        let packhash = storage::epoch::epoch_read_pack(&storage.config, 4).unwrap();
+        let pack = storage.config.get_pack_filepath(&packhash);
+        let len = fs::metadata(&pack).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&pack)
+            .unwrap()
+            .set_len(len - 8)
+            .unwrap();
+
+        let checks = vec![
+            healthy(1),
+            healthy(2),
+            EpochCheck {
+                epoch_id: 3,
+                result: check_epoch(&storage, 3, None),
+            },
+            EpochCheck {
+                epoch_id: 4,
+                result: check_epoch(&storage, 4, None),
+            },
+            healthy(5),
+        ];
+        assert!(checks[2]
+            .result
+            .as_ref()
+            .unwrap_err()
+            .starts_with("malformed block"));
+        assert!(checks[3].result.is_err());
+        assert_eq!(damaged_ranges(&checks), vec![3..5]);
+
+        fs::remove_dir_all(&storage.config.root_path).unwrap();
+    }
+
+    #[test]
+    fn ranges_of_consecutive_epochs() {
+        let damaged = |epoch_id| EpochCheck {
+            epoch_id,
+            result: Err("damaged".to_owned()),
+        };
+        assert!(damaged_ranges(&[healthy(0), healthy(1)]).is_empty());
+        assert_eq!(
+            damaged_ranges(&[
+                damaged(0),
+                healthy(1),
+                damaged(2),
+                damaged(3),
+                healthy(4),
+                damaged(5)
+            ]),
+            vec![0..1, 2..4, 5..6]
+        );
+    }
+}
@@ -1,7 +1,18 @@
+pub mod blockfile;
+#[cfg(feature = "cli")]
 pub mod commands;
 pub mod config;
 pub mod error;
+#[cfg(feature = "cli")]
+pub mod explorer;
+pub mod fetcher;
+pub mod health;
+pub mod integrity;
+pub mod parameters;
 pub mod peer;
+pub mod proxy;
+pub mod pull;
+pub mod seeds;
 
 pub use self::error::{Error, Result};
 
@@ -11,9 +22,12 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
     str::FromStr,
+    time::SystemTime,
 };
 
-use cardano::block;
+use cardano::block::{self, EpochId};
+use cardano::config::ProtocolMagic;
+use cardano::fee::LinearFee;
 use cardano_storage::{self as storage, config::StorageConfig, tag, Storage};
 use exe_common::network::api::BlockRef;
 pub use exe_common::{
@@ -24,6 +38,10 @@ use storage_units::utils::directory_name::{DirectoryName, DirectoryNameError};
 
 pub const LOCAL_BLOCKCHAIN_TIP_TAG: &'static str = "tip";
 
+/// file, in the blockchain directory, keeping the first epoch whose blocks
+/// are still stored locally (the previous ones have been pruned).
+const PRUNED_UNTIL_FILE: &'static str = "pruned";
+
 pub type BlockchainNameError = DirectoryNameError;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -64,10 +82,27 @@ pub struct Blockchain {
     pub storage_config: StorageConfig,
     pub storage: Storage,
     pub config: Config,
+    /// the SOCKS5 proxy to connect to the peers through, if any
+    pub proxy: Option<String>,
 }
 impl Blockchain {
-    /// create the new blockhain with the given setting
+    /// create the new blockhain with the given setting, the genesis data
+    /// is the one of the known network of the setting
     pub fn new(root_dir: PathBuf, name: BlockchainName, config: Config) -> Result<Self> {
+        let genesis_data = genesisdata::data::get_genesis_data(&config.genesis_prev)
+            .map_err(Error::VerifyChainGenesisHashNotFound)?;
+        Self::new_with_genesis(root_dir, name, config, genesis_data.as_bytes(), None)
+    }
+
+    /// create the new blockchain with the given setting, its genesis data
+    /// and, optionally, its current protocol parameters (see `parameters`)
+    pub fn new_with_genesis(
+        root_dir: PathBuf,
+        name: BlockchainName,
+        config: Config,
+        genesis_data: &[u8],
+        protocol_parameters: Option<&[u8]>,
+    ) -> Result<Self> {
         let dir = config::directory(root_dir, &name);
         let storage_config = StorageConfig::new(&dir);
 
@@ -92,23 +127,34 @@ impl Blockchain {
             storage_config,
             storage,
             config,
+            proxy: None,
         };
 
         debug!("saving initial Tip");
         blockchain.save_tip(&blockchain.config.genesis);
         debug!("initializing genesis data");
-        blockchain.init_genesis_data()?;
+        blockchain.write_genesis_data(genesis_data)?;
+        if let Some(protocol_parameters) = protocol_parameters {
+            debug!("writing the protocol parameters");
+            ::std::fs::write(
+                blockchain.dir.join(parameters::PROTOCOL_PARAMETERS_FILE),
+                protocol_parameters,
+            )?;
+        }
         debug!("done...");
 
         Ok(blockchain)
     }
 
     fn init_genesis_data(&self) -> Result<()> {
-        use std::{fs::OpenOptions, io::Write};
         let genesis_data = genesisdata::data::get_genesis_data(&self.config.genesis_prev)
             .map_err(Error::VerifyChainGenesisHashNotFound)?;
+        self.write_genesis_data(genesis_data.as_bytes())
+    }
 
-        let path = self.dir.join("genesis.json");
+    fn write_genesis_data(&self, genesis_data: &[u8]) -> Result<()> {
+        use std::{fs::OpenOptions, io::Write};
+        let path = self.genesis_file();
 
         debug!("writing genesis file: {:?}", path);
         let mut fs = OpenOptions::new()
@@ -117,14 +163,29 @@ impl Blockchain {
             .create(true)
             .open(path)?;
 
-        fs.write_all(genesis_data.as_bytes())?;
+        fs.write_all(genesis_data)?;
         Ok(())
     }
 
+    fn genesis_file(&self) -> PathBuf {
+        self.dir.join("genesis.json")
+    }
+
+    /// the fee policy of the blockchain: the one of its protocol parameters
+    /// if it has some, the one of its genesis data otherwise
+    pub fn fee_policy(&self) -> Result<LinearFee> {
+        parameters::load_fee_policy(&self.dir, &self.genesis_file())
+    }
+
+    /// the maximum size of the transactions of the blockchain, if its
+    /// protocol parameters or its genesis data give one
+    pub fn max_tx_size(&self) -> Result<Option<u64>> {
+        parameters::load_max_tx_size(&self.dir, &self.genesis_file())
+    }
+
     pub fn load_genesis_data(&self) -> Result<cardano::config::GenesisData> {
         use std::fs::OpenOptions;
-        let path = self.dir.join("genesis.json");
-        let fs = OpenOptions::new().read(true).open(path)?;
+        let fs = OpenOptions::new().read(true).open(self.genesis_file())?;
 
         let genesis_data = genesisdata::parse::parse(fs);
 
@@ -155,6 +216,7 @@ impl Blockchain {
                 return Err(Error::LoadConfigFileNotFound(file));
             }
         };
+        let proxy = proxy::load(&dir)?;
 
         let blockchain = Blockchain {
             name,
@@ -162,17 +224,36 @@ impl Blockchain {
             storage_config,
             storage,
             config,
+            proxy,
         };
 
         // compatibility with previously generated blockchain
-        let genesis_file = blockchain.dir.join("genesis.json");
-        if !genesis_file.exists() {
+        if !blockchain.genesis_file().exists() {
             blockchain.init_genesis_data()?;
         }
 
         Ok(blockchain)
     }
 
+    /// the first local blockchain (in the order of the names) of the given
+    /// protocol magic, if any
+    pub fn find_by_protocol_magic<P>(root_dir: P, protocol_magic: ProtocolMagic) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut names: Vec<BlockchainName> =
+            ::std::fs::read_dir(config::blockchains_directory(root_dir.as_ref()))
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| BlockchainName::from_os_str(entry.file_name()).ok())
+                .collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| Self::load(root_dir.as_ref(), name).ok())
+            .find(|blockchain| blockchain.config.protocol_magic == protocol_magic)
+    }
+
     /// save the blockchain settings
     pub fn save(&self) {
         self.config.to_file(self.storage_config.get_config_file());
@@ -212,6 +293,10 @@ impl Blockchain {
             .collect();
         let tag = self.mk_remote_tag(&remote_alias);
         tag::remove_tag(&self.storage, &tag);
+
+        let mut health = health::Health::load(self);
+        health.remove(&remote_alias);
+        health.save(self);
     }
 
     pub fn peers<'a>(&'a self) -> impl Iterator<Item = &'a net::NamedPeer> {
@@ -245,10 +330,80 @@ impl Blockchain {
             }
         }
     }
+    /// check the given block is part of the local blockchain, i.e. it is
+    /// the local tip or one of its ancestors.
+    pub fn is_on_chain(&self, hash: &block::HeaderHash, date: &block::BlockDate) -> Result<bool> {
+        let tip = self.load_tip().0;
+        if date > &tip.date {
+            return Ok(false);
+        }
+        let resolved = storage::resolve_date_to_blockhash(&self.storage, &tip.hash, date)?;
+        Ok(resolved
+            .map(|h| block::HeaderHash::from(h) == *hash)
+            .unwrap_or(false))
+    }
+
+    /// the number of slots from the genesis to the given block date
+    pub fn absolute_slot(&self, date: &block::BlockDate) -> u64 {
+        absolute_slot(self.config.epoch_stability_depth, date)
+    }
+
+    /// the time at which the slot of the given block date started, with the
+    /// epoch parameters of the genesis data
+    pub fn slot_start_time(
+        &self,
+        genesis: &cardano::config::GenesisData,
+        date: &block::BlockDate,
+    ) -> SystemTime {
+        let slot = absolute_slot(genesis.epoch_stability_depth, date);
+        genesis.start_time + genesis.slot_duration * (slot as u32)
+    }
+
     pub fn save_tip(&self, hh: &block::HeaderHash) {
         tag::write_hash(&self.storage, &LOCAL_BLOCKCHAIN_TIP_TAG, hh);
     }
 
+    /// the first epoch whose blocks are still stored locally, if the
+    /// older epochs have been pruned
+    pub fn load_pruned_until(&self) -> Result<Option<EpochId>> {
+        use std::fs;
+        match fs::read_to_string(self.dir.join(PRUNED_UNTIL_FILE)) {
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+            Ok(content) => content
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::PruneInvalidMarker(self.dir.join(PRUNED_UNTIL_FILE))),
+        }
+    }
+
+    pub fn save_pruned_until(&self, epoch: EpochId) -> Result<()> {
+        ::std::fs::write(self.dir.join(PRUNED_UNTIL_FILE), format!("{}\n", epoch))?;
+        Ok(())
+    }
+
+    /// fail with `Error::EpochsPruned` if the blocks of the given date's
+    /// epoch have been pruned
+    pub fn check_not_pruned(&self, date: &block::BlockDate) -> Result<()> {
+        match self.load_pruned_until()? {
+            Some(pruned_until) if date.get_epochid() < pruned_until => {
+                Err(Error::EpochsPruned(pruned_until))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// iterate the blocks from the given block down to the genesis, or down
+    /// to the first block still stored locally if the blockchain was pruned
+    pub fn reverse_iter<'a>(&'a self, from: block::HeaderHash) -> Result<ReverseIter<'a>> {
+        Ok(ReverseIter {
+            inner: storage::iter::ReverseIter::from(&self.storage, from)?,
+            pruned_until: self.load_pruned_until()?,
+            done: false,
+        })
+    }
+
     pub fn iter<'a>(
         &'a self,
         from: block::HeaderHash,
@@ -266,3 +421,49 @@ impl Blockchain {
         self.iter(from, to)
     }
 }
+
+/// see `Blockchain::reverse_iter`
+pub struct ReverseIter<'a> {
+    inner: storage::iter::ReverseIter<'a>,
+    pruned_until: Option<EpochId>,
+    done: bool,
+}
+impl<'a> ReverseIter<'a> {
+    /// the first epoch still stored locally, if the iteration stopped there
+    /// before reaching the genesis
+    pub fn pruned_until(&self) -> Option<EpochId> {
+        if self.done {
+            self.pruned_until
+        } else {
+            None
+        }
+    }
+}
+impl<'a> Iterator for ReverseIter<'a> {
+    type Item = block::Block;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let block = self.inner.next()?;
+        if let Some(pruned_until) = self.pruned_until {
+            // the block preceding the retained epoch boundary is gone
+            let date = block.get_header().get_blockdate();
+            if date.is_boundary() && date.get_epochid() <= pruned_until {
+                self.done = true;
+            }
+        }
+        Some(block)
+    }
+}
+
+/// the number of slots from the genesis to the given block date, the Byron
+/// epochs having 10 times the security parameter (`k`) slots
+fn absolute_slot(epoch_stability_depth: usize, date: &block::BlockDate) -> u64 {
+    let slots_per_epoch = epoch_stability_depth as u64 * 10;
+    match date {
+        block::BlockDate::Boundary(epoch) => epoch * slots_per_epoch,
+        block::BlockDate::Normal(d) => d.epoch * slots_per_epoch + d.slotid as u64,
+    }
+}
@@ -0,0 +1,194 @@
+//! genesis data and protocol parameters of the private networks
+//!
+//! The private Byron networks are not among the templates of `blockchain
+//! new`: their blockchain is created from the genesis data file of the
+//! network instead. The protocol magic, the security parameter `k` and the
+//! fee policy are read from it, and the hash of its canonical form is the
+//! genesis hash. The hash of the first block (the boundary block of epoch
+//! 0) depends on the slot leaders of the first epoch, it is given along.
+//!
+//! The fee policy can be updated after the genesis: the protocol parameters
+//! file (the `blockVersionData` of the genesis data, or any JSON object
+//! with its `txFeePolicy`) gives the current one.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use cardano::{
+    block::HeaderHash,
+    config::ProtocolMagic,
+    fee::{LinearFee, Milli},
+};
+use serde_json::{self, Value};
+
+use super::{genesisdata, Config, Error, Peers, Result};
+
+/// file, in the blockchain directory, keeping the protocol parameters
+pub const PROTOCOL_PARAMETERS_FILE: &'static str = "protocol-parameters.json";
+
+/// the coefficients of the fee policy are in billionths of lovelace
+const FEE_POLICY_UNIT_PER_MILLI: u64 = 1_000_000;
+
+fn read_json(path: &Path, invalid: fn(&Path, String) -> Error) -> Result<(Vec<u8>, Value)> {
+    let bytes = fs::read(path)?;
+    let value = serde_json::from_slice(&bytes).map_err(|err| invalid(path, err.to_string()))?;
+    Ok((bytes, value))
+}
+
+fn invalid_genesis(path: &Path, reason: String) -> Error {
+    Error::GenesisInvalidFile(path.to_path_buf(), reason)
+}
+
+fn invalid_parameters(path: &Path, reason: String) -> Error {
+    Error::ProtocolParametersInvalidFile(path.to_path_buf(), reason)
+}
+
+/// an unsigned integer, written as a number or a string
+fn integer(value: &Value, pointer: &str) -> ::std::result::Result<u64, String> {
+    let integer = match value.pointer(pointer) {
+        Some(Value::Number(number)) => number.as_u64(),
+        Some(Value::String(string)) => string.parse().ok(),
+        _ => None,
+    };
+    integer.ok_or_else(|| format!("missing or invalid `{}'", pointer))
+}
+
+/// the fee policy of the `txFeePolicy` of the JSON object. The fee policy
+/// counts in thousandths of lovelace, a coefficient with a finer part is
+/// rejected rather than rounded
+fn fee_policy(value: &Value) -> ::std::result::Result<LinearFee, String> {
+    let milli = |pointer| {
+        let coefficient = integer(value, pointer)?;
+        if coefficient % FEE_POLICY_UNIT_PER_MILLI != 0 {
+            return Err(format!(
+                "`{}' is not a whole number of thousandths of lovelace",
+                pointer
+            ));
+        }
+        let milli = coefficient / FEE_POLICY_UNIT_PER_MILLI;
+        Ok(Milli::new(milli / 1000, milli % 1000))
+    };
+    Ok(LinearFee::new(
+        milli("/txFeePolicy/summand")?,
+        milli("/txFeePolicy/multiplier")?,
+    ))
+}
+
+/// the configuration of the blockchain of the genesis data file, without
+/// peers, and the content of the file
+pub fn genesis_config(path: &Path, genesis_block: HeaderHash) -> Result<(Config, Vec<u8>)> {
+    let (bytes, value) = read_json(path, invalid_genesis)?;
+    let protocol_magic =
+        integer(&value, "/protocolConsts/protocolMagic").map_err(|e| invalid_genesis(path, e))?;
+    let k = integer(&value, "/protocolConsts/k").map_err(|e| invalid_genesis(path, e))?;
+    fee_policy(&value["blockVersionData"]).map_err(|e| invalid_genesis(path, e))?;
+    integer(&value, "/startTime").map_err(|e| invalid_genesis(path, e))?;
+
+    let genesis_data = genesisdata::parse::parse(&bytes[..]);
+
+    let mut config = Config::mainnet();
+    config.genesis = genesis_block;
+    config.genesis_prev = genesis_data.genesis_prev;
+    config.epoch_start = 0;
+    config.protocol_magic = ProtocolMagic::from(protocol_magic as u32);
+    config.epoch_stability_depth = k as usize;
+    config.peers = Peers::new();
+    Ok((config, bytes))
+}
+
+/// check the protocol parameters file and return its content
+pub fn read_protocol_parameters(path: &Path) -> Result<Vec<u8>> {
+    let (bytes, value) = read_json(path, invalid_parameters)?;
+    fee_policy(&value).map_err(|e| invalid_parameters(path, e))?;
+    Ok(bytes)
+}
+
+/// the current protocol parameters of the blockchain in the given
+/// directory: its protocol parameters file if any, the `blockVersionData`
+/// of its genesis data otherwise. Along with the function reporting them
+/// invalid.
+fn current_parameters(
+    dir: &Path,
+    genesis_file: &Path,
+) -> Result<(PathBuf, Value, fn(&Path, String) -> Error)> {
+    let parameters_file = dir.join(PROTOCOL_PARAMETERS_FILE);
+    if parameters_file.exists() {
+        let (_, value) = read_json(&parameters_file, invalid_parameters)?;
+        Ok((parameters_file, value, invalid_parameters))
+    } else {
+        let (_, value) = read_json(genesis_file, invalid_genesis)?;
+        let parameters = value["blockVersionData"].clone();
+        Ok((genesis_file.to_path_buf(), parameters, invalid_genesis))
+    }
+}
+
+/// the fee policy of the blockchain in the given directory: the one of its
+/// protocol parameters if any, of its genesis data otherwise
+pub fn load_fee_policy(dir: &Path, genesis_file: &Path) -> Result<LinearFee> {
+    let (path, value, invalid) = current_parameters(dir, genesis_file)?;
+    fee_policy(&value).map_err(|e| invalid(&path, e))
+}
+
+/// the maximum size of the transactions (`maxTxSize`) of the blockchain in
+/// the given directory, if its current protocol parameters give one
+pub fn load_max_tx_size(dir: &Path, genesis_file: &Path) -> Result<Option<u64>> {
+    let (path, value, invalid) = current_parameters(dir, genesis_file)?;
+    if value.get("maxTxSize").is_none() {
+        return Ok(None);
+    }
+    integer(&value, "/maxTxSize")
+        .map(Some)
+        .map_err(|e| invalid(&path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fee_policy_of(summand: &str, multiplier: &str) -> ::std::result::Result<LinearFee, String> {
+        let json = format!(
+            r#"{{"txFeePolicy": {{"summand": {}, "multiplier": {}}}}}"#,
+            summand, multiplier
+        );
+        fee_policy(&serde_json::from_str(&json).unwrap())
+    }
+
+    #[test]
+    fn mainnet_fee_policy() {
+        let fee = fee_policy_of(r#""155381000000000""#, r#""43946000000""#).unwrap();
+        assert_eq!(fee, LinearFee::default());
+    }
+
+    #[test]
+    fn coefficients_as_numbers() {
+        let fee = fee_policy_of("155381000000000", "43946000000").unwrap();
+        assert_eq!(fee, LinearFee::default());
+    }
+
+    #[test]
+    fn thousandths_of_lovelace() {
+        let fee = fee_policy_of("1000000", "2500000000").unwrap();
+        assert_eq!(fee, LinearFee::new(Milli::new(0, 1), Milli::new(2, 500)));
+    }
+
+    #[test]
+    fn finer_coefficients_are_rejected() {
+        assert!(fee_policy_of("155381000000001", "43946000000").is_err());
+        assert!(fee_policy_of("155381000000000", "999999").is_err());
+    }
+
+    #[test]
+    fn invalid_coefficients_are_rejected() {
+        assert!(fee_policy_of("-1", "43946000000").is_err());
+        assert!(fee_policy_of(r#""155381 lovelace""#, "43946000000").is_err());
+        assert!(fee_policy_of("null", "43946000000").is_err());
+    }
+
+    #[test]
+    fn missing_fee_policy_is_rejected() {
+        let value: Value = serde_json::from_str(r#"{"maxTxSize": "4096"}"#).unwrap();
+        assert!(fee_policy(&value).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use super::{error::Error, health, proxy};
 use cardano::{
     block::{BlockDate, EpochId, HeaderHash},
     tx::TxAux,
@@ -5,11 +6,34 @@ use cardano::{
 use cardano_storage::{self as storage, tag};
 use exe_common;
 use exe_common::network::{api::Api, api::BlockRef};
+use std::cmp;
 use std::mem;
 use std::ops::Deref;
 use std::time::SystemTime;
 use storage_units::packfile;
-use utils::term::Term;
+use utils::interaction::Interaction;
+
+/// number of blocks of a stable epoch fetched between two checkpoints of
+/// the peer's tag. An interrupted sync resumes from the last checkpoint
+/// instead of restarting the epoch from its boundary block.
+const SYNC_CHECKPOINT_INTERVAL: usize = 2000;
+
+/// Determine the first epoch that is not stable yet, given the tip of the
+/// blockchain. Note: This assumes that k is smaller than the number of
+/// blocks in an epoch.
+pub fn first_unstable_epoch(tip: &BlockDate, epoch_stability_depth: usize) -> EpochId {
+    tip.get_epochid()
+        - match tip {
+            BlockDate::Boundary(_) => 1,
+            BlockDate::Normal(d) => {
+                if d.slotid as usize <= epoch_stability_depth {
+                    1
+                } else {
+                    0
+                }
+            }
+        }
+}
 
 pub struct ConnectedPeer<'a> {
     peer: Peer<'a>,
@@ -33,16 +57,26 @@ impl<'a> ConnectedPeer<'a> {
     }
 
     pub fn send_txaux(mut self, txaux: TxAux) -> bool {
+        let txid = txaux.tx.id();
         match self.connection.send_transaction(txaux) {
             Err(err) => {
-                warn!("cannot send transaction to {}: {}", self.peer.name, err);
+                warn!(
+                    "cannot send transaction {} to {}: {}",
+                    txid, self.peer.name, err
+                );
                 false
             }
-            Ok(sent) => sent,
+            Ok(sent) => {
+                info!(
+                    "transaction {} sent to {}, accepted: {}",
+                    txid, self.peer.name, sent
+                );
+                sent
+            }
         }
     }
 
-    pub fn sync(mut self, term: &mut Term) -> Peer<'a> {
+    pub fn sync(mut self, term: &mut Interaction) -> Result<Peer<'a>, Error> {
         // recover and print the TIP of the network
         let tip = self.query_tip();
 
@@ -64,6 +98,22 @@ impl<'a> ConnectedPeer<'a> {
         let mut connection = self.connection;
         let peer = self.peer;
 
+        // check the remote did not switch to another branch than the one we
+        // have locally. If it did, roll back to the last common block and
+        // fetch the remote's branch from there.
+        if let Some(intersection) = peer.find_fork_intersection(&best_tip.0, &tip, |hash| {
+            peer.remote_has(&mut connection, hash, &tip)
+        })? {
+            term.warn(&format!(
+                "local blockchain diverged from remote {}, rolling back to {} ({})\n",
+                peer.name, intersection.date, intersection.hash
+            ))
+            .unwrap();
+            peer.rollback_to(&intersection);
+            let is_boundary = intersection.hash == peer.blockchain.config.genesis;
+            best_tip = (intersection, is_boundary);
+        }
+
         if best_tip.0.date < tip.date {
             // do nothing, best_tip is behind the remote tip.
         } else if best_tip.0.date > tip.date {
@@ -77,11 +127,11 @@ impl<'a> ConnectedPeer<'a> {
                     .unwrap();
                     best_tip = our_tip;
                 }
-                Err(err) => panic!(err),
+                Err(err) => return Err(err.into()),
                 Ok(_) => {
                     term.info("remote already as further as it takes").unwrap();
                     peer.save_peer_local_tip(&tip.hash);
-                    return peer;
+                    return Ok(peer);
                 }
             }
         } else {
@@ -89,7 +139,7 @@ impl<'a> ConnectedPeer<'a> {
             if best_tip.0.hash == tip.hash {
                 // this is the same block hash. save the local tip
                 peer.save_peer_local_tip(&tip.hash);
-                return peer;
+                return Ok(peer);
             } else {
                 // it seems the best_tip is for the same date, but has a different hash
                 // it could be there is a fork between the remotes.
@@ -101,32 +151,21 @@ impl<'a> ConnectedPeer<'a> {
             }
         }
 
-        // TODO: we need to handle the case where our_tip is not an
-        // ancestor of tip. In that case we should start from the last
-        // stable epoch before our_tip.
-
         info!(
             "Fetching from        : {} ({})",
             best_tip.0.hash, best_tip.0.date
         );
 
-        // Determine whether the previous epoch is stable yet. Note: This
-        // assumes that k is smaller than the number of blocks in an
-        // epoch.
-        let first_unstable_epoch = tip.date.get_epochid()
-            - match tip.date {
-                BlockDate::Boundary(_) => 1,
-                BlockDate::Normal(d) => {
-                    if d.slotid as usize <= peer.blockchain.config.epoch_stability_depth {
-                        1
-                    } else {
-                        0
-                    }
-                }
-            };
+        let first_unstable_epoch =
+            first_unstable_epoch(&tip.date, peer.blockchain.config.epoch_stability_depth);
         info!("First unstable epoch : {}", first_unstable_epoch);
 
-        let mut cur_epoch_state: Option<(EpochId, packfile::Writer, SystemTime)> = None;
+        let mut cur_epoch_state: Option<(
+            EpochId,
+            packfile::Writer,
+            SystemTime,
+            Vec<storage::types::BlockHash>,
+        )> = None;
 
         let mut last_block: Option<HeaderHash> = None;
 
@@ -137,18 +176,23 @@ impl<'a> ConnectedPeer<'a> {
             && !internal::epoch_exists(&peer.blockchain.storage, best_tip.0.date.get_epochid())
         {
             let epoch_id = best_tip.0.date.get_epochid();
+            term.info(&format!(
+                "resuming epoch {} from checkpoint {} ({})\n",
+                epoch_id, best_tip.0.hash, best_tip.0.date
+            ))
+            .unwrap();
             let mut writer =
                 storage::pack::packwriter_init(&peer.blockchain.storage.config).unwrap();
             let epoch_time_start = SystemTime::now();
 
-            let prev_block = internal::append_blocks_to_epoch_reverse(
+            let (prev_block, blobs) = internal::append_blocks_to_epoch_reverse(
                 &peer.blockchain.storage,
                 epoch_id,
                 &mut writer,
                 &best_tip.0.hash,
             );
 
-            cur_epoch_state = Some((epoch_id, writer, epoch_time_start));
+            cur_epoch_state = Some((epoch_id, writer, epoch_time_start, blobs));
             last_block = Some(best_tip.0.hash.clone());
 
             // If tip.slotid < w, the previous epoch won't have been
@@ -188,7 +232,9 @@ impl<'a> ConnectedPeer<'a> {
 
         // initialisation of the progress bar:
         let count = tip.date - best_tip.0.date;
-        let pbr = term.progress_bar(count as u64);
+        let pbr = term.progress(count as u64);
+        let sync_time_start = SystemTime::now();
+        let mut fetched: usize = 0;
         connection
             .get_blocks(
                 &best_tip.0,
@@ -196,19 +242,25 @@ impl<'a> ConnectedPeer<'a> {
                 &tip,
                 &mut |block_hash, block, block_raw| {
                     let date = block.get_header().get_blockdate();
+                    fetched += 1;
                     pbr.inc(1);
-                    pbr.set_message(&format!("downloading epoch {} -> ", date.get_epochid()));
+                    pbr.set_message(&format!(
+                        "downloading epoch {} ({} blocks/s) -> ",
+                        date.get_epochid(),
+                        throughput(fetched, &sync_time_start)
+                    ));
 
                     // Flush the previous epoch (if any).
                     if date.is_boundary() {
                         let mut writer_state = None;
                         mem::swap(&mut writer_state, &mut cur_epoch_state);
-                        if let Some((epoch_id, writer, epoch_time_start)) = writer_state {
+                        if let Some((epoch_id, writer, epoch_time_start, blobs)) = writer_state {
                             internal::finish_epoch(
                                 &peer.blockchain.storage,
                                 epoch_id,
                                 writer,
                                 &epoch_time_start,
+                                &blobs,
                             );
 
                             // Checkpoint the tip so we don't have to refetch
@@ -229,6 +281,18 @@ impl<'a> ConnectedPeer<'a> {
                         )
                         .unwrap();
                     } else {
+                        // Keep a loose copy of the block too, so the epoch
+                        // can be packed from the last checkpoint if the sync
+                        // is interrupted before the end of the epoch. The
+                        // copy is removed once the epoch pack is written.
+                        let blob_hash = storage::types::header_to_blockhash(&block_hash);
+                        storage::blob::write(
+                            &peer.blockchain.storage,
+                            &blob_hash,
+                            block_raw.as_ref(),
+                        )
+                        .unwrap();
+
                         // If this is the epoch boundary block, start writing a new epoch pack.
                         if date.is_boundary() {
                             cur_epoch_state = Some((
@@ -236,17 +300,18 @@ impl<'a> ConnectedPeer<'a> {
                                 storage::pack::packwriter_init(&peer.blockchain.storage.config)
                                     .unwrap(),
                                 SystemTime::now(),
+                                Vec::new(),
                             ));
                         }
 
                         // And append the block to the epoch pack.
-                        let (_, writer, _) = &mut cur_epoch_state.as_mut().unwrap();
-                        writer
-                            .append(
-                                &storage::types::header_to_blockhash(&block_hash),
-                                block_raw.as_ref(),
-                            )
-                            .unwrap();
+                        let (_, writer, _, blobs) = &mut cur_epoch_state.as_mut().unwrap();
+                        writer.append(&blob_hash, block_raw.as_ref()).unwrap();
+                        blobs.push(blob_hash);
+
+                        if fetched % SYNC_CHECKPOINT_INTERVAL == 0 {
+                            peer.save_peer_local_tip(&block_hash);
+                        }
                     }
 
                     last_block = Some(block_hash.clone());
@@ -260,7 +325,18 @@ impl<'a> ConnectedPeer<'a> {
             peer.save_peer_local_tip(&block_hash);
         }
 
-        peer
+        Ok(peer)
+    }
+}
+
+/// number of blocks fetched per second since the given time
+fn throughput(fetched: usize, since: &SystemTime) -> u64 {
+    let elapsed = since.elapsed().unwrap_or_default();
+    let millis = elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64;
+    if millis == 0 {
+        0
+    } else {
+        fetched as u64 * 1000 / millis
     }
 }
 
@@ -295,25 +371,76 @@ impl<'a> Peer<'a> {
     }
 
     /// initialise the connection by performing initial handshake (if necessary).
-    pub fn connect(self, term: &mut Term) -> Result<ConnectedPeer<'a>, ()> {
+    ///
+    /// The peer is probed first, so an unreachable peer fails within
+    /// `health::PROBE_TIMEOUT`. The outcome is recorded in the peers' health.
+    ///
+    /// If the blockchain has a proxy, the native peers are reached through
+    /// it and the HTTP peers are skipped.
+    pub fn connect(self, term: &mut Interaction) -> Result<ConnectedPeer<'a>, ()> {
+        let start = SystemTime::now();
+        let config = match (&self.blockchain.proxy, &self.config) {
+            (None, config) => health::probe(config).map(|_| config.clone()),
+            (Some(proxy), exe_common::config::net::Peer::Native(address)) => {
+                proxy::relay(proxy, address, health::PROBE_TIMEOUT)
+                    .map(|local| exe_common::config::net::Peer::Native(local.to_string()))
+                    .map_err(|err| format!("cannot connect through the proxy {}: {}", proxy, err))
+            }
+            (Some(proxy), exe_common::config::net::Peer::Http(_)) => {
+                term.warn(&format!(
+                    "Skipping HTTP peer {} ({}), it cannot be reached through the proxy {}\n",
+                    self.name, self.config, proxy
+                ))
+                .unwrap();
+                return Err(());
+            }
+        };
+        let config = match config {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(
+                    "peer {} ({}) is unreachable: {}",
+                    self.name, self.config, err
+                );
+                term.warn(&format!(
+                    "Peer {} ({}) is unreachable\n\t{}\n",
+                    self.name, self.config, err
+                ))
+                .unwrap();
+                health::record(self.blockchain, &self.name, Err(err));
+                return Err(());
+            }
+        };
+
         let peer_handshake = exe_common::network::Peer::new(
             self.blockchain.name.as_ref().to_owned(),
             self.name.to_owned(),
-            self.config.clone(),
+            config,
             self.blockchain.config.protocol_magic,
         );
 
         let connection = match peer_handshake {
             Err(err) => {
+                warn!(
+                    "handshake with peer {} ({}) failed: {:?}",
+                    self.name, self.config, err
+                );
                 term.warn(&format!(
                     "Unable to initiate handshake with peer {} ({})\n\t{:?}\n",
                     self.name, self.config, err
                 ))
                 .unwrap();
+                health::record(self.blockchain, &self.name, Err(format!("{:?}", err)));
                 return Err(());
             }
             Ok(peer) => peer,
         };
+        let elapsed = start.elapsed().unwrap_or_default();
+        info!(
+            "connected to peer {} ({}) in {:?}",
+            self.name, self.config, elapsed
+        );
+        health::record(self.blockchain, &self.name, Ok(elapsed));
 
         Ok(ConnectedPeer {
             peer: self,
@@ -321,6 +448,128 @@ impl<'a> Peer<'a> {
         })
     }
 
+    /// find the latest block of the local branch ending at `local` the
+    /// remote has too, to detect the remote switched to another branch.
+    ///
+    /// The local branch is probed at exponentially increasing depths (the
+    /// tip, then 1, 2, 4... blocks back) and the latest common block is then
+    /// narrowed down by bisection: a handful of requests instead of one per
+    /// block of the stability depth.
+    ///
+    /// `remote_has` tells if the remote has the given block.
+    ///
+    /// Returns `None` if the remote has `local` (no fork), or if the common
+    /// block cannot be found within the stability depth.
+    fn find_fork_intersection<F>(
+        &self,
+        local: &BlockRef,
+        remote: &BlockRef,
+        mut remote_has: F,
+    ) -> Result<Option<BlockRef>, Error>
+    where
+        F: FnMut(&HeaderHash) -> Result<bool, Error>,
+    {
+        let max_depth = self.blockchain.config.epoch_stability_depth;
+
+        // we are too far behind the remote tip for a rollback to be possible
+        if remote.date > local.date && remote.date - local.date > max_depth {
+            return Ok(None);
+        }
+        if remote.hash == local.hash || remote_has(&local.hash)? {
+            return Ok(None);
+        }
+
+        let mut branch = self.local_branch(local, max_depth)?;
+        let (mut missing, mut depth) = (0, 1);
+        let mut found = None;
+        while missing + 1 < branch.len() {
+            let index = cmp::min(depth, branch.len() - 1);
+            if remote_has(&branch[index].hash)? {
+                found = Some(index);
+                break;
+            }
+            missing = index;
+            depth *= 2;
+        }
+        let mut found = match found {
+            Some(found) => found,
+            None => {
+                warn!(
+                    "no common block with {} within the last {} blocks",
+                    self.name, max_depth
+                );
+                return Ok(None);
+            }
+        };
+
+        // the blocks the remote has are the oldest ones of the branch
+        while found - missing > 1 {
+            let middle = (missing + found) / 2;
+            if remote_has(&branch[middle].hash)? {
+                found = middle;
+            } else {
+                missing = middle;
+            }
+        }
+        Ok(Some(branch.swap_remove(found)))
+    }
+
+    /// the blocks of the local branch ending at `tip`, the most recent
+    /// first, back to at most `depth` blocks before it
+    fn local_branch(&self, tip: &BlockRef, depth: usize) -> Result<Vec<BlockRef>, Error> {
+        let mut branch = vec![tip.clone()];
+        loop {
+            let (hash, parent) = {
+                let last = branch.last().expect("the branch has its tip");
+                (last.hash.clone(), last.parent.clone())
+            };
+            if branch.len() > depth || hash == self.blockchain.config.genesis {
+                return Ok(branch);
+            }
+            let block_raw = self.blockchain.storage.read_block(parent.as_hash_bytes())?;
+            let header = block_raw
+                .decode()
+                .map_err(|_| Error::GetInvalidBlock(parent.clone()))?
+                .get_header();
+            branch.push(BlockRef {
+                hash: parent,
+                parent: header.get_previous_header(),
+                date: header.get_blockdate(),
+            });
+        }
+    }
+
+    /// tell if the remote has the block. The request failing for the
+    /// remote's own tip too is a network error, not a missing block.
+    fn remote_has(
+        &self,
+        connection: &mut exe_common::network::Peer,
+        hash: &HeaderHash,
+        remote: &BlockRef,
+    ) -> Result<bool, Error> {
+        let err = match connection.get_block(hash) {
+            Ok(_) => return Ok(true),
+            Err(err) => err,
+        };
+        debug!("block {} not served by {}: {}", hash, self.name, err);
+        match connection.get_block(&remote.hash) {
+            Ok(_) => Ok(false),
+            Err(err) => Err(Error::PeerRequestFailed(
+                self.name.clone(),
+                format!("{}", err),
+            )),
+        }
+    }
+
+    /// roll the peer tag, and the local tip if it is past the given block,
+    /// back to the given block.
+    fn rollback_to(&self, to: &BlockRef) {
+        self.save_peer_local_tip(&to.hash);
+        if self.blockchain.load_tip().0.date > to.date {
+            self.blockchain.save_tip(&to.hash);
+        }
+    }
+
     /// load the peer current block
     pub fn load_peer_local_tip(&self) -> HeaderHash {
         match tag::read_hash(&self.blockchain.storage, &self.tag) {
@@ -330,7 +579,7 @@ impl<'a> Peer<'a> {
     }
 
     /// save the given peer header hash
-    fn save_peer_local_tip(&self, tip: &HeaderHash) {
+    pub(super) fn save_peer_local_tip(&self, tip: &HeaderHash) {
         tag::write_hash(&self.blockchain.storage, &self.tag, tip)
     }
 
@@ -370,7 +619,7 @@ impl<'a> Peer<'a> {
     }
 }
 
-mod internal {
+pub(super) mod internal {
     use cardano::block::{EpochId, HeaderHash};
     use cardano::util::hex;
     use cardano_storage as storage;
@@ -397,11 +646,10 @@ mod internal {
         let mut writer = storage::pack::packwriter_init(&storage.config).unwrap();
         let epoch_time_start = SystemTime::now();
 
-        append_blocks_to_epoch_reverse(&storage, epoch_id, &mut writer, last_block);
-
-        finish_epoch(storage, epoch_id, writer, &epoch_time_start);
+        let (_, blobs) =
+            append_blocks_to_epoch_reverse(&storage, epoch_id, &mut writer, last_block);
 
-        // TODO: delete the blocks from disk?
+        finish_epoch(storage, epoch_id, writer, &epoch_time_start, &blobs);
     }
 
     // Check whether an epoch pack exists on disk.
@@ -416,7 +664,7 @@ mod internal {
         epoch_id: EpochId,
         writer: &mut packfile::Writer,
         last_block: &HeaderHash,
-    ) -> HeaderHash {
+    ) -> (HeaderHash, Vec<storage::types::BlockHash>) {
         let mut cur_hash = last_block.clone();
         let mut blocks = vec![];
         loop {
@@ -431,11 +679,13 @@ mod internal {
             }
         }
 
+        let mut packed = Vec::with_capacity(blocks.len());
         while let Some((hash, block_raw)) = blocks.pop() {
             writer.append(&hash, block_raw.as_ref()).unwrap();
+            packed.push(hash);
         }
 
-        cur_hash
+        (cur_hash, packed)
     }
 
     pub fn finish_epoch(
@@ -443,6 +693,7 @@ mod internal {
         epoch_id: EpochId,
         writer: packfile::Writer,
         epoch_time_start: &SystemTime,
+        blobs: &[storage::types::BlockHash],
     ) {
         let (packhash, index) = storage::pack::packwriter_finalize(&storage.config, writer);
         let (_, tmpfile) = storage::pack::create_index(&storage, &index);
@@ -455,6 +706,11 @@ mod internal {
 
         storage::epoch::epoch_create(&storage, &packhash, epoch_id, None);
 
+        // the blocks are in the pack now, drop their loose copies
+        for hash in blobs {
+            storage::blob::remove(&storage, hash);
+        }
+
         info!(
             "=> pack {} written for epoch {} in {}",
             hex::encode(&packhash[..]),
@@ -463,3 +719,160 @@ mod internal {
         );
     }
 }
+
+#[cfg(all(test, feature = "cli"))]
+mod test {
+    use super::*;
+    use blockchain::{Blockchain, BlockchainName, Config, Peers};
+    use selftest::boundary_block;
+    use std::{cell::Cell, fs, path::PathBuf, process, str::FromStr};
+
+    const PEER: &str = "remote";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-peer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// a blockchain of the testnet settings holding a chain of `len`
+    /// boundary blocks, the first one being the genesis, and the chain
+    fn mock_blockchain(root_dir: PathBuf, len: usize) -> (Blockchain, Vec<BlockRef>) {
+        let testnet = Config::testnet();
+        let mut chain: Vec<BlockRef> = Vec::new();
+        for epoch in testnet.epoch_start..testnet.epoch_start + len as EpochId {
+            let parent = chain
+                .last()
+                .map(|block| block.hash.clone())
+                .unwrap_or_else(|| testnet.genesis_prev.clone());
+            let raw = boundary_block(&testnet.protocol_magic, &parent, epoch).unwrap();
+            chain.push(BlockRef {
+                hash: raw.decode().unwrap().get_header().compute_hash(),
+                parent,
+                date: BlockDate::Boundary(epoch),
+            });
+        }
+        let config = Config {
+            genesis: chain[0].hash.clone(),
+            peers: Peers::new(),
+            ..testnet
+        };
+        let name = BlockchainName::from_str("peer-test").unwrap();
+        let blockchain = Blockchain::new(root_dir, name, config).unwrap();
+        for block in chain.iter() {
+            let raw = boundary_block(
+                &blockchain.config.protocol_magic,
+                &block.parent,
+                block.date.get_epochid(),
+            )
+            .unwrap();
+            let blob_hash = storage::types::header_to_blockhash(&block.hash);
+            storage::blob::write(&blockchain.storage, &blob_hash, raw.as_ref()).unwrap();
+        }
+        (blockchain, chain)
+    }
+
+    fn peer(blockchain: &Blockchain) -> Peer {
+        Peer {
+            blockchain,
+            config: exe_common::config::net::Peer::Native("127.0.0.1:3000".to_owned()),
+            name: PEER.to_owned(),
+            tag: blockchain.mk_remote_tag(PEER),
+        }
+    }
+
+    /// a tip of the remote on another branch than the local one
+    fn remote_tip(local: &BlockRef) -> BlockRef {
+        BlockRef {
+            hash: HeaderHash::new(b"remote tip"),
+            parent: HeaderHash::new(b"remote parent"),
+            date: local.date.clone(),
+        }
+    }
+
+    /// the common block found for a remote having the given blocks of the
+    /// chain, and the number of blocks requested to the remote
+    fn intersection(peer: &Peer, chain: &[BlockRef], common: usize) -> (Option<HeaderHash>, usize) {
+        let remote_blocks: Vec<_> = chain[..common].iter().map(|b| b.hash.clone()).collect();
+        let local = chain.last().unwrap();
+        let requests = Cell::new(0);
+        let found = peer
+            .find_fork_intersection(local, &remote_tip(local), |hash| {
+                requests.set(requests.get() + 1);
+                Ok(remote_blocks.contains(hash))
+            })
+            .unwrap();
+        (found.map(|block| block.hash), requests.get())
+    }
+
+    #[test]
+    fn no_fork() {
+        let root_dir = temp_dir("no-fork");
+        let (blockchain, chain) = mock_blockchain(root_dir.clone(), 8);
+        let peer = peer(&blockchain);
+
+        assert_eq!(intersection(&peer, &chain, chain.len()), (None, 1));
+
+        ::std::mem::drop(peer);
+        ::std::mem::drop(blockchain);
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn fork_at_genesis() {
+        let root_dir = temp_dir("fork-at-genesis");
+        let (blockchain, chain) = mock_blockchain(root_dir.clone(), 8);
+        let peer = peer(&blockchain);
+
+        let (found, _) = intersection(&peer, &chain, 1);
+        assert_eq!(found, Some(chain[0].hash.clone()));
+
+        ::std::mem::drop(peer);
+        ::std::mem::drop(blockchain);
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn deep_fork() {
+        let root_dir = temp_dir("deep-fork");
+        let (blockchain, chain) = mock_blockchain(root_dir.clone(), 40);
+        let peer = peer(&blockchain);
+
+        // the remote has the 11 oldest blocks of the 40: the common block
+        // is found in a handful of requests, not one per block
+        let (found, requests) = intersection(&peer, &chain, 11);
+        assert_eq!(found, Some(chain[10].hash.clone()));
+        assert!(requests <= 12, "{} requests", requests);
+
+        // the remote has every block but the tip
+        let (found, _) = intersection(&peer, &chain, chain.len() - 1);
+        assert_eq!(found, Some(chain[chain.len() - 2].hash.clone()));
+
+        ::std::mem::drop(peer);
+        ::std::mem::drop(blockchain);
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn rollback_restores_the_tip() {
+        let root_dir = temp_dir("rollback");
+        let (blockchain, chain) = mock_blockchain(root_dir.clone(), 4);
+        let peer = peer(&blockchain);
+        blockchain.save_tip(&chain[3].hash);
+        peer.save_peer_local_tip(&chain[3].hash);
+
+        peer.rollback_to(&chain[1]);
+        assert_eq!(blockchain.load_tip().0.hash, chain[1].hash);
+        assert_eq!(peer.load_peer_local_tip(), chain[1].hash);
+
+        // the local tip behind the block is left as it is
+        peer.rollback_to(&chain[2]);
+        assert_eq!(blockchain.load_tip().0.hash, chain[1].hash);
+        assert_eq!(peer.load_peer_local_tip(), chain[2].hash);
+
+        ::std::mem::drop(peer);
+        ::std::mem::drop(blockchain);
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+//! SOCKS5 proxy of a blockchain
+//!
+//! When a proxy is set (e.g. Tor's `127.0.0.1:9050`), every TCP connection
+//! to the peers goes through it: the domain names are handed to the proxy
+//! unresolved, so no DNS request leaves the machine either.
+//!
+//! The native protocol's connections are opened by the network layer,
+//! which only knows direct connections: they are pointed at a local relay
+//! forwarding the bytes to the proxy's connection to the peer.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use super::{Blockchain, Error, Result};
+
+/// file, in the blockchain directory, keeping the address of the proxy
+const PROXY_FILE: &'static str = "proxy";
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTHENTICATION: u8 = 0;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN_NAME: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+/// split `HOST:PORT`, the host of an IPv6 address may be in brackets
+fn split_address(address: &str) -> Option<(&str, u16)> {
+    let index = address.rfind(':')?;
+    let port = address[index + 1..].parse().ok()?;
+    let host = address[..index]
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if host.is_empty() || host.len() > 255 {
+        return None;
+    }
+    Some((host, port))
+}
+
+/// check the address of a proxy is a `HOST:PORT`
+pub fn validate(address: &str) -> Result<()> {
+    match split_address(address) {
+        Some(_) => Ok(()),
+        None => Err(Error::ProxyInvalid(address.to_owned())),
+    }
+}
+
+/// the proxy set for the blockchain in the given directory, if any
+pub fn load(dir: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(dir.join(PROXY_FILE)) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+        Ok(content) => {
+            let address = content.trim().to_owned();
+            validate(&address)?;
+            Ok(Some(address))
+        }
+    }
+}
+
+/// set (or unset) the proxy of the blockchain
+pub fn save(blockchain: &Blockchain, address: Option<&str>) -> Result<()> {
+    let path = blockchain.dir.join(PROXY_FILE);
+    match address {
+        Some(address) => {
+            validate(address)?;
+            fs::write(path, format!("{}\n", address))?;
+        }
+        None => match fs::remove_file(path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            result => result?,
+        },
+    }
+    Ok(())
+}
+
+fn socks_error(reply: u8) -> io::Error {
+    let reason = match reply {
+        1 => "general failure",
+        2 => "connection not allowed by the proxy's rules",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    };
+    io::Error::new(io::ErrorKind::Other, format!("SOCKS5 proxy: {}", reason))
+}
+
+/// open a TCP connection to `target` (`HOST:PORT`) through the SOCKS5 proxy,
+/// the proxy resolves the domain names.
+pub fn connect(proxy: &str, target: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let (host, port) = split_address(target).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid address `{}'", target),
+        )
+    })?;
+
+    let mut error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("proxy `{}' does not resolve to any address", proxy),
+    );
+    let mut stream = None;
+    for socket_addr in proxy.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_addr, timeout) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(err) => error = err,
+        }
+    }
+    let mut stream = match stream {
+        None => return Err(error),
+        Some(stream) => stream,
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    // greeting, no authentication
+    stream.write_all(&[SOCKS_VERSION, 1, SOCKS_NO_AUTHENTICATION])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [SOCKS_VERSION, SOCKS_NO_AUTHENTICATION] {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "SOCKS5 proxy: authentication required",
+        ));
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        request.push(SOCKS_IPV4);
+        request.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        request.push(SOCKS_IPV6);
+        request.extend_from_slice(&ip.octets());
+    } else {
+        request.push(SOCKS_DOMAIN_NAME);
+        request.push(host.len() as u8);
+        request.extend_from_slice(host.as_bytes());
+    }
+    request.push((port >> 8) as u8);
+    request.push(port as u8);
+    stream.write_all(&request)?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(socks_error(reply[1]));
+    }
+    // skip the address the proxy bound to
+    let bound = match reply[3] {
+        SOCKS_IPV4 => 4,
+        SOCKS_IPV6 => 16,
+        SOCKS_DOMAIN_NAME => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(socks_error(8)),
+    };
+    let mut bound = vec![0; bound + 2];
+    stream.read_exact(&mut bound)?;
+
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(stream)
+}
+
+/// connect to `target` through the proxy and listen on a local port
+/// forwarding the first connection to it.
+///
+/// Returns the local address to connect to instead of `target`.
+pub fn relay(proxy: &str, target: &str, timeout: Duration) -> io::Result<SocketAddr> {
+    let upstream = connect(proxy, target, timeout)?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let local = listener.local_addr()?;
+
+    thread::spawn(move || match listener.accept() {
+        Ok((downstream, _)) => forward(downstream, upstream),
+        Err(err) => warn!("proxy relay: cannot accept the connection: {}", err),
+    });
+    Ok(local)
+}
+
+/// copy the bytes both ways until one of the sides closes the connection
+fn forward(downstream: TcpStream, upstream: TcpStream) {
+    let copy = |mut from: TcpStream, mut to: TcpStream| {
+        if let Err(err) = io::copy(&mut from, &mut to) {
+            debug!("proxy relay: {}", err);
+        }
+        let _ = to.shutdown(Shutdown::Write);
+    };
+    let (down, up) = match (downstream.try_clone(), upstream.try_clone()) {
+        (Ok(down), Ok(up)) => (down, up),
+        (Err(err), _) | (_, Err(err)) => {
+            warn!("proxy relay: {}", err);
+            return;
+        }
+    };
+    let uplink = thread::spawn(move || copy(down, up));
+    copy(upstream, downstream);
+    let _ = uplink.join();
+}
@@ -0,0 +1,109 @@
+//! pull of the blocks from the peers of a blockchain
+//!
+//! The stable epochs are downloaded in parallel from the HTTP peers (see
+//! `fetcher`), the remaining blocks from the best native peer, then the
+//! local tip is forwarded. `blockchain pull`, `watch` and the library
+//! (`api::blockchain::pull`) share it: it reports through `Interaction`,
+//! not the terminal.
+
+use std::path::PathBuf;
+
+use cardano::block::HeaderHash;
+
+use utils::{clock, interaction::Interaction};
+
+use super::{fetcher, health, peer, proxy, seeds, Blockchain, BlockchainName, Error, Result};
+
+pub fn forward(
+    term: &mut Interaction,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    to: Option<HeaderHash>,
+) -> Result<()> {
+    let mut blockchain = Blockchain::load(root_dir, name)?;
+
+    let hash = if let Some(hash) = to {
+        blockchain.storage.block_location(hash.as_hash_bytes())?;
+        hash
+    } else {
+        // the tips fetched from the peers of the DNS seeds count too
+        seeds::add_seed_peers(&mut blockchain)?;
+        let initial_tip = blockchain.load_tip().0;
+
+        let tip = blockchain
+            .peers()
+            .map(|np| {
+                peer::Peer::prepare(&blockchain, np.name().to_owned())
+                    .load_local_tip()
+                    .0
+            })
+            .fold(initial_tip, |current_tip, tip| {
+                if tip.date > current_tip.date {
+                    tip
+                } else {
+                    current_tip
+                }
+            });
+
+        tip.hash
+    };
+
+    term.success(&format!("forward local tip to: {}\n", hash))?;
+
+    blockchain.save_tip(&hash);
+
+    Ok(())
+}
+
+/// fetch the new blocks from the peers and forward the local tip.
+///
+/// `proxy` overrides the proxy set for the blockchain.
+pub fn pull(
+    term: &mut Interaction,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    jobs: usize,
+    proxy: Option<String>,
+    clock_check: &clock::Config,
+) -> Result<()> {
+    let mut blockchain = Blockchain::load(root_dir.clone(), name.clone())?;
+    if let Some(address) = proxy {
+        proxy::validate(&address)?;
+        blockchain.proxy = Some(address);
+    }
+    clock::check(
+        term,
+        clock_check,
+        blockchain.proxy.as_ref().map(|proxy| proxy.as_str()),
+    )
+    .map_err(Error::ClockSkewed)?;
+    seeds::add_seed_peers(&mut blockchain)?;
+
+    // download the stable epochs concurrently first, the native peers will
+    // fetch the remaining blocks.
+    if jobs > 1 {
+        match fetcher::fetch_stable_epochs(term, &blockchain, jobs) {
+            Ok(epochs) => info!("{} epochs downloaded in parallel", epochs),
+            Err(err) => term.warn(&format!("parallel download stopped: {}\n", err))?,
+        }
+    }
+
+    // sync from the best native peer, failing over to the next one if it
+    // cannot be reached.
+    let native_peers = health::ordered_peers(&blockchain, true);
+    let mut synced = false;
+    for peer_name in native_peers.iter().cloned() {
+        let peer = peer::Peer::prepare(&blockchain, peer_name);
+        if let Ok(connected) = peer.connect(term) {
+            term.info(&format!("fetching blocks from peer: {}\n", connected.name))?;
+            connected.sync(term)?;
+            synced = true;
+            break;
+        }
+    }
+    if !synced && !native_peers.is_empty() {
+        return Err(Error::PeersUnreachable);
+    }
+
+    forward(term, root_dir, name, None)
+}
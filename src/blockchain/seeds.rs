@@ -0,0 +1,195 @@
+//! DNS seeds of a blockchain
+//!
+//! A DNS seed is a domain name (e.g. the IOHK relays' name) resolving to
+//! a rotating set of relays. Instead of pinning the peers to static IP
+//! addresses which go stale, the seeds are resolved at connection time and
+//! each of the addresses becomes a native peer of the blockchain for the
+//! duration of the command. The addresses are cached in the blockchain
+//! directory and resolved again every `REFRESH_INTERVAL`.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    net::ToSocketAddrs,
+    time::{Duration, SystemTime},
+};
+
+use serde_yaml;
+
+use super::{Blockchain, Error, Peer, Result};
+
+/// file, in the blockchain directory, keeping the seeds and their addresses
+const SEEDS_FILE: &'static str = "dns-seeds.yml";
+
+/// how long the resolved addresses of a seed are used before resolving
+/// the seed again
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// maximum number of peers taken from one seed
+pub const MAX_PEERS_PER_SEED: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Seed {
+    /// the domain name and the port of the relays, e.g.
+    /// `relays.cardano-mainnet.iohk.io:3000`
+    pub endpoint: String,
+    /// the addresses the seed resolved to
+    pub addresses: Vec<String>,
+    pub resolved_at: Option<SystemTime>,
+}
+impl Seed {
+    fn new(endpoint: String) -> Self {
+        Seed {
+            endpoint,
+            addresses: Vec::new(),
+            resolved_at: None,
+        }
+    }
+
+    fn host(&self) -> &str {
+        self.endpoint
+            .rsplitn(2, ':')
+            .last()
+            .unwrap_or(&self.endpoint)
+    }
+
+    /// the name of the peer of the seed at the given address. The name
+    /// is made of the address, not of its rank in the resolution, so the
+    /// recorded health of the peer follows the relay when the seed
+    /// reorders its addresses.
+    fn peer_name(&self, address: &str) -> String {
+        format!("{}-{}", self.host(), address)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        match self.resolved_at {
+            None => true,
+            Some(resolved_at) => match resolved_at.elapsed() {
+                Ok(elapsed) => elapsed >= REFRESH_INTERVAL,
+                Err(_) => true,
+            },
+        }
+    }
+
+    /// resolve the seed, keeping the previous addresses if the resolution
+    /// fails
+    fn refresh(&mut self) {
+        match self.endpoint.to_socket_addrs() {
+            Ok(addresses) => {
+                // keep the order of the resolver, which rotates the relays
+                let mut seen = BTreeSet::new();
+                let addresses: Vec<String> = addresses
+                    .map(|a| a.to_string())
+                    .filter(|a| seen.insert(a.clone()))
+                    .take(MAX_PEERS_PER_SEED)
+                    .collect();
+                debug!("DNS seed {} resolved to {:?}", self.endpoint, addresses);
+                self.addresses = addresses;
+                self.resolved_at = Some(SystemTime::now());
+            }
+            Err(err) => warn!(
+                "cannot resolve DNS seed {}, using the previous addresses: {}",
+                self.endpoint, err
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Seeds {
+    pub seeds: Vec<Seed>,
+}
+impl Seeds {
+    pub fn load(blockchain: &Blockchain) -> Result<Self> {
+        let path = blockchain.dir.join(SEEDS_FILE);
+        match fs::File::open(&path) {
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => Ok(Seeds::default()),
+            Err(err) => Err(err.into()),
+            Ok(file) => serde_yaml::from_reader(file)
+                .map_err(|e| Error::SeedsInvalidFile(path, e.to_string())),
+        }
+    }
+
+    pub fn save(&self, blockchain: &Blockchain) -> Result<()> {
+        let path = blockchain.dir.join(SEEDS_FILE);
+        let file = fs::File::create(&path)?;
+        serde_yaml::to_writer(file, self).map_err(|e| Error::SeedsInvalidFile(path, e.to_string()))
+    }
+
+    pub fn add(&mut self, endpoint: String) -> Result<()> {
+        match endpoint.rfind(':') {
+            Some(index) if index > 0 && endpoint[index + 1..].parse::<u16>().is_ok() => {}
+            _ => return Err(Error::SeedInvalid(endpoint)),
+        }
+        if self.seeds.iter().any(|seed| seed.endpoint == endpoint) {
+            return Err(Error::SeedAlreadyExists(endpoint));
+        }
+        self.seeds.push(Seed::new(endpoint));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, endpoint: &str) -> Result<()> {
+        let len = self.seeds.len();
+        self.seeds.retain(|seed| seed.endpoint != endpoint);
+        if self.seeds.len() == len {
+            return Err(Error::SeedNotFound(endpoint.to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// resolve the stale seeds of the blockchain and add their addresses to the
+/// blockchain's peers (for the duration of the command only, the blockchain
+/// configuration is not saved).
+///
+/// With a proxy, the seeds are not resolved locally: each seed becomes one
+/// peer whose domain name is resolved by the proxy.
+///
+/// Returns the number of peers added.
+pub fn add_seed_peers(blockchain: &mut Blockchain) -> Result<usize> {
+    let mut seeds = Seeds::load(blockchain)?;
+    if seeds.seeds.is_empty() {
+        return Ok(0);
+    }
+
+    if blockchain.proxy.is_some() {
+        let mut added = 0;
+        for seed in seeds.seeds.iter() {
+            let name = seed.endpoint.clone();
+            if blockchain.peers().any(|np| np.name() == name) {
+                continue;
+            }
+            blockchain
+                .config
+                .peers
+                .push(name, Peer::new(seed.endpoint.clone()));
+            added += 1;
+        }
+        return Ok(added);
+    }
+
+    let mut refreshed = false;
+    for seed in seeds.seeds.iter_mut().filter(|seed| seed.is_stale()) {
+        seed.refresh();
+        refreshed = true;
+    }
+    if refreshed {
+        seeds.save(blockchain)?;
+    }
+
+    let mut added = 0;
+    for seed in seeds.seeds.iter() {
+        for address in seed.addresses.iter() {
+            let name = seed.peer_name(address);
+            if blockchain.peers().any(|np| np.name() == name) {
+                continue;
+            }
+            blockchain
+                .config
+                .peers
+                .push(name, Peer::new(address.clone()));
+            added += 1;
+        }
+    }
+    Ok(added)
+}
@@ -0,0 +1,718 @@
+//! CBOR inspector
+//!
+//! prints any CBOR blob in diagnostic notation (RFC 7049, section 6), the
+//! embedded CBOR (tag 24) decoded in place as `<<...>>`. The Cardano
+//! structures (signed transactions, transactions, blocks, addresses and
+//! witnesses) are recognized, their fields annotated with diagnostic
+//! comments (`/ ... /`): the transaction ids, the amounts in ADA, the
+//! addresses in base58 with their type and attributes...
+
+use std::{
+    error, fmt, fs,
+    io::{self, Cursor, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use base64;
+use cardano::{
+    address::ExtendedAddr,
+    block::Block,
+    tx::{Tx, TxAux, TxInWitness, TxOut, TxoPointer},
+    util::{hex, try_from_slice::TryFromSlice},
+};
+use cbor_event::{
+    self,
+    de::{Deserialize, Deserializer},
+    se::Serializer,
+    ObjectKey, Special, Value,
+};
+
+use utils::{era, term::Term};
+
+const LOVELACE_PER_ADA: u64 = 1_000_000;
+
+const INDENT: &'static str = "  ";
+
+/// the tag of the embedded CBOR data items
+const TAG_ENCODED_CBOR: u64 = 24;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    InvalidInput,
+    InvalidCbor(cbor_event::Error),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::InvalidInput => write!(
+                f,
+                "Expected hexadecimal or base64 encoded data, or a binary file"
+            ),
+            Error::InvalidCbor(_) => write!(f, "The data is not a valid CBOR data item"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::InvalidInput => None,
+            Error::InvalidCbor(ref err) => Some(err),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// the Cardano structures the inspector recognizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// a signed transaction: the transaction and its witnesses
+    TxAux,
+    Tx,
+    Block,
+    Address,
+    Witness,
+    /// any CBOR, without annotations
+    Raw,
+}
+impl Kind {
+    pub const ALL: &'static [&'static str] = &["txaux", "tx", "block", "address", "witness", "raw"];
+
+    /// the kind of the data, `Raw` if it is none of the Cardano structures
+    fn detect(bytes: &[u8]) -> Self {
+        if decode::<Block>(bytes).is_some() {
+            Kind::Block
+        } else if decode::<TxAux>(bytes).is_some() {
+            Kind::TxAux
+        } else if decode::<Tx>(bytes).is_some() {
+            Kind::Tx
+        } else if ExtendedAddr::try_from_slice(bytes).is_ok() {
+            Kind::Address
+        } else if decode::<TxInWitness>(bytes).is_some() {
+            Kind::Witness
+        } else {
+            Kind::Raw
+        }
+    }
+
+    fn schema(self) -> Schema {
+        match self {
+            Kind::TxAux => Schema::TxAux,
+            Kind::Tx => Schema::Tx,
+            Kind::Block => Schema::Block,
+            Kind::Address => Schema::Address,
+            Kind::Witness => Schema::Witness,
+            Kind::Raw => Schema::Any,
+        }
+    }
+}
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::TxAux => write!(f, "signed transaction (TxAux)"),
+            Kind::Tx => write!(f, "transaction"),
+            Kind::Block => write!(f, "block"),
+            Kind::Address => write!(f, "address"),
+            Kind::Witness => write!(f, "witness"),
+            Kind::Raw => write!(f, "CBOR"),
+        }
+    }
+}
+impl FromStr for Kind {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "txaux" => Ok(Kind::TxAux),
+            "tx" => Ok(Kind::Tx),
+            "block" => Ok(Kind::Block),
+            "address" => Ok(Kind::Address),
+            "witness" => Ok(Kind::Witness),
+            "raw" => Ok(Kind::Raw),
+            _ => Err(format!("unknown kind `{}'", s)),
+        }
+    }
+}
+
+/// what a data item is expected to be, to annotate it and its children
+#[derive(Debug, Clone, Copy)]
+enum Schema {
+    Any,
+    Label(&'static str),
+    TxAux,
+    Tx,
+    TxIns,
+    TxIn,
+    TxOuts,
+    TxOut,
+    Coin,
+    Address,
+    AddressPayload,
+    AddressAttributes,
+    AddressType,
+    Witnesses,
+    Witness,
+    WitnessPayload,
+    Block,
+    BoundaryBlock,
+    BoundaryHeader,
+    BoundaryConsensus,
+    MainBlock,
+    MainHeader,
+    MainConsensus,
+    SlotId,
+    MainBody,
+    TxPayload,
+}
+
+fn decode<T: Deserialize>(bytes: &[u8]) -> Option<T> {
+    Deserializer::from(Cursor::new(bytes))
+        .deserialize_complete()
+        .ok()
+}
+
+/// the value decoded as a Cardano structure
+fn decode_value<T: Deserialize>(value: &Value) -> Option<T> {
+    let mut serializer = Serializer::new_vec();
+    serializer.serialize(value).ok()?;
+    decode(&serializer.finalize())
+}
+
+fn format_ada(lovelace: u64) -> String {
+    format!(
+        "{}.{:06} ADA",
+        lovelace / LOVELACE_PER_ADA,
+        lovelace % LOVELACE_PER_ADA
+    )
+}
+
+fn array(value: &Value) -> &[Value] {
+    match value {
+        Value::Array(items) | Value::IArray(items) => items,
+        _ => &[],
+    }
+}
+
+fn unsigned(value: Option<&Value>) -> Option<u64> {
+    match value {
+        Some(Value::U64(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn annotation(value: &Value, schema: Schema) -> Option<String> {
+    match schema {
+        Schema::Any => None,
+        Schema::Label(label) => Some(label.to_owned()),
+        Schema::TxAux => decode_value::<TxAux>(value)
+            .map(|txaux| format!("signed transaction {}", txaux.tx.id())),
+        Schema::Tx => decode_value::<Tx>(value).map(|tx| format!("transaction {}", tx.id())),
+        Schema::TxIns => Some(format!("{} input(s)", array(value).len())),
+        Schema::TxIn => {
+            decode_value::<TxoPointer>(value).map(|ptr| format!("input {}.{}", ptr.id, ptr.index))
+        }
+        Schema::TxOuts => Some(format!("{} output(s)", array(value).len())),
+        Schema::TxOut => decode_value::<TxOut>(value)
+            .map(|output| format!("output of {}", format_ada(u64::from(output.value)))),
+        Schema::Coin => unsigned(Some(value)).map(format_ada),
+        Schema::Address => decode_value::<ExtendedAddr>(value).map(|address| {
+            let derivation = if address.attributes.derivation_path.is_some() {
+                "with derivation path"
+            } else {
+                "no derivation path"
+            };
+            format!(
+                "address {}, {}, network magic {:?}, {}",
+                address, address.addr_type, address.attributes.network_magic, derivation
+            )
+        }),
+        Schema::AddressPayload => Some("address payload".to_owned()),
+        Schema::AddressAttributes => Some("address attributes".to_owned()),
+        Schema::AddressType => match unsigned(Some(value)) {
+            Some(0) => Some("public key address".to_owned()),
+            Some(1) => Some("script address".to_owned()),
+            Some(2) => Some("redeem address".to_owned()),
+            _ => Some("unknown address type".to_owned()),
+        },
+        Schema::Witnesses => Some(format!("{} witness(es)", array(value).len())),
+        Schema::Witness => decode_value::<TxInWitness>(value).map(|witness| {
+            let kind = match witness {
+                TxInWitness::PkWitness(..) => "public key witness",
+                TxInWitness::ScriptWitness(..) => "script witness",
+                TxInWitness::RedeemWitness(..) => "redeem witness",
+            };
+            kind.to_owned()
+        }),
+        Schema::WitnessPayload => None,
+        Schema::Block => {
+            let block = decode_value::<Block>(value)?;
+            let kind = match unsigned(array(value).get(0)) {
+                Some(0) => "boundary block",
+                _ => "main block",
+            };
+            Some(format!("{} {}", kind, block.header().compute_hash()))
+        }
+        Schema::BoundaryBlock | Schema::MainBlock => None,
+        Schema::BoundaryHeader | Schema::MainHeader => Some("header".to_owned()),
+        Schema::BoundaryConsensus => {
+            unsigned(array(value).get(0)).map(|epoch| format!("consensus data, epoch {}", epoch))
+        }
+        Schema::MainConsensus => Some("consensus data".to_owned()),
+        Schema::SlotId => {
+            let slot = array(value);
+            match (unsigned(slot.get(0)), unsigned(slot.get(1))) {
+                (Some(epoch), Some(slot)) => Some(format!("epoch {}, slot {}", epoch, slot)),
+                _ => None,
+            }
+        }
+        Schema::MainBody => Some("body".to_owned()),
+        Schema::TxPayload => Some(format!("{} transaction(s)", array(value).len())),
+    }
+}
+
+/// the schema of the item `index` of the array of the given schema
+fn item_schema(schema: Schema, items: &[Value], index: usize) -> Schema {
+    match (schema, index) {
+        (Schema::TxAux, 0) => Schema::Tx,
+        (Schema::TxAux, 1) => Schema::Witnesses,
+        (Schema::Tx, 0) => Schema::TxIns,
+        (Schema::Tx, 1) => Schema::TxOuts,
+        (Schema::Tx, 2) => Schema::Label("transaction attributes"),
+        (Schema::TxIns, _) => Schema::TxIn,
+        (Schema::TxIn, 0) => Schema::Label("input type"),
+        (Schema::TxOuts, _) => Schema::TxOut,
+        (Schema::TxOut, 0) => Schema::Address,
+        (Schema::TxOut, 1) => Schema::Coin,
+        (Schema::Address, 0) => Schema::AddressPayload,
+        (Schema::Address, 1) => Schema::Label("CRC32 checksum"),
+        (Schema::AddressPayload, 0) => Schema::Label("address root (hash of the spending data)"),
+        (Schema::AddressPayload, 1) => Schema::AddressAttributes,
+        (Schema::AddressPayload, 2) => Schema::AddressType,
+        (Schema::Witnesses, _) => Schema::Witness,
+        (Schema::Witness, 0) => Schema::Label("witness type"),
+        (Schema::Witness, 1) => match unsigned(items.get(0)) {
+            Some(0) | Some(2) => Schema::WitnessPayload,
+            _ => Schema::Any,
+        },
+        (Schema::WitnessPayload, 0) => Schema::Label("public key"),
+        (Schema::WitnessPayload, 1) => Schema::Label("signature"),
+        (Schema::Block, 0) => Schema::Label("block type"),
+        (Schema::Block, 1) => match unsigned(items.get(0)) {
+            Some(0) => Schema::BoundaryBlock,
+            _ => Schema::MainBlock,
+        },
+        (Schema::BoundaryBlock, 0) => Schema::BoundaryHeader,
+        (Schema::BoundaryBlock, 1) => Schema::Label("slot leaders of the epoch"),
+        (Schema::BoundaryBlock, 2) => Schema::Label("extra data"),
+        (Schema::BoundaryHeader, 0) | (Schema::MainHeader, 0) => Schema::Label("protocol magic"),
+        (Schema::BoundaryHeader, 1) | (Schema::MainHeader, 1) => Schema::Label("previous block"),
+        (Schema::BoundaryHeader, 2) | (Schema::MainHeader, 2) => Schema::Label("body proof"),
+        (Schema::BoundaryHeader, 3) => Schema::BoundaryConsensus,
+        (Schema::MainHeader, 3) => Schema::MainConsensus,
+        (Schema::BoundaryHeader, 4) | (Schema::MainHeader, 4) => Schema::Label("extra data"),
+        (Schema::BoundaryConsensus, 1) => Schema::Label("chain difficulty"),
+        (Schema::MainConsensus, 0) => Schema::SlotId,
+        (Schema::MainConsensus, 1) => Schema::Label("slot leader"),
+        (Schema::MainConsensus, 2) => Schema::Label("chain difficulty"),
+        (Schema::MainConsensus, 3) => Schema::Label("block signature"),
+        (Schema::MainBlock, 0) => Schema::MainHeader,
+        (Schema::MainBlock, 1) => Schema::MainBody,
+        (Schema::MainBlock, 2) => Schema::Label("extra data"),
+        (Schema::MainBody, 0) => Schema::TxPayload,
+        (Schema::MainBody, 1) => Schema::Label("shared seed computation"),
+        (Schema::MainBody, 2) => Schema::Label("delegation"),
+        (Schema::MainBody, 3) => Schema::Label("update proposal and votes"),
+        (Schema::TxPayload, _) => Schema::TxAux,
+        _ => Schema::Any,
+    }
+}
+
+/// the schema of the value of the key of the map of the given schema
+fn entry_schema(schema: Schema, key: &ObjectKey) -> Schema {
+    match (schema, key) {
+        (Schema::AddressAttributes, ObjectKey::Integer(0)) => Schema::Label("stake distribution"),
+        (Schema::AddressAttributes, ObjectKey::Integer(1)) => {
+            Schema::Label("derivation path, encrypted with the wallet's key")
+        }
+        (Schema::AddressAttributes, ObjectKey::Integer(2)) => Schema::Label("network magic"),
+        _ => Schema::Any,
+    }
+}
+
+fn write_key(out: &mut String, key: &ObjectKey) {
+    match key {
+        ObjectKey::Integer(n) => out.push_str(&n.to_string()),
+        ObjectKey::Bytes(bytes) => out.push_str(&format!("h'{}'", hex::encode(bytes))),
+        ObjectKey::Text(text) => out.push_str(&format!("{:?}", text)),
+    }
+}
+
+fn write_comment(out: &mut String, value: &Value, schema: Schema) {
+    if let Some(annotation) = annotation(value, schema) {
+        out.push_str(&format!(" / {} /", annotation));
+    }
+}
+
+fn write_indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+/// write the value in diagnostic notation, from the current position of
+/// the output (already indented) without the final new line
+fn write_value(out: &mut String, value: &Value, schema: Schema, depth: usize) {
+    match value {
+        Value::U64(n) => {
+            out.push_str(&n.to_string());
+            write_comment(out, value, schema);
+        }
+        Value::I64(n) => {
+            out.push_str(&n.to_string());
+            write_comment(out, value, schema);
+        }
+        Value::Bytes(bytes) => {
+            out.push_str(&format!("h'{}'", hex::encode(bytes)));
+            write_comment(out, value, schema);
+        }
+        Value::Text(text) => {
+            out.push_str(&format!("{:?}", text));
+            write_comment(out, value, schema);
+        }
+        Value::Array(items) | Value::IArray(items) => {
+            let indefinite = match value {
+                Value::IArray(_) => "_ ",
+                _ => "",
+            };
+            if items.is_empty() {
+                out.push_str(&format!("[{}]", indefinite.trim()));
+                write_comment(out, value, schema);
+                return;
+            }
+            out.push_str(&format!("[{}", indefinite));
+            write_comment(out, value, schema);
+            out.push('\n');
+            for (index, item) in items.iter().enumerate() {
+                write_indent(out, depth + 1);
+                write_value(out, item, item_schema(schema, items, index), depth + 1);
+                if index + 1 < items.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            write_indent(out, depth);
+            out.push(']');
+        }
+        Value::Object(entries) | Value::IObject(entries) => {
+            let indefinite = match value {
+                Value::IObject(_) => "_ ",
+                _ => "",
+            };
+            if entries.is_empty() {
+                out.push_str(&format!("{{{}}}", indefinite.trim()));
+                write_comment(out, value, schema);
+                return;
+            }
+            out.push_str(&format!("{{{}", indefinite));
+            write_comment(out, value, schema);
+            out.push('\n');
+            for (index, (key, entry)) in entries.iter().enumerate() {
+                write_indent(out, depth + 1);
+                write_key(out, key);
+                out.push_str(": ");
+                write_value(out, entry, entry_schema(schema, key), depth + 1);
+                if index + 1 < entries.len() {
+                    out.push(',');
+                }
+                out.push('\n');
+            }
+            write_indent(out, depth);
+            out.push('}');
+        }
+        Value::Tag(TAG_ENCODED_CBOR, inner) => {
+            let embedded = match inner.as_ref() {
+                Value::Bytes(bytes) => decode::<Value>(bytes),
+                _ => None,
+            };
+            match embedded {
+                Some(embedded) => {
+                    // the annotations are the ones of the embedded item,
+                    // the tag being how Cardano wraps it
+                    out.push_str("24(<<");
+                    write_value(out, &embedded, schema, depth);
+                    out.push_str(">>)");
+                }
+                None => {
+                    out.push_str("24(");
+                    write_value(out, inner, schema, depth);
+                    out.push(')');
+                }
+            }
+        }
+        Value::Tag(tag, inner) => {
+            out.push_str(&format!("{}(", tag));
+            write_value(out, inner, schema, depth);
+            out.push(')');
+        }
+        Value::Special(special) => {
+            match special {
+                Special::Bool(true) => out.push_str("true"),
+                Special::Bool(false) => out.push_str("false"),
+                Special::Null => out.push_str("null"),
+                Special::Undefined => out.push_str("undefined"),
+                Special::Float(float) => out.push_str(&format!("{:?}", float)),
+                Special::Unassigned(n) => out.push_str(&format!("simple({})", n)),
+                Special::Break => out.push_str("break"),
+            }
+            write_comment(out, value, schema);
+        }
+    }
+}
+
+/// the bytes of the input: the content of the file if it is one, the
+/// hexadecimal (or base64) encoded data otherwise. Without input, stdin.
+fn read_input(input: Option<&str>) -> Result<Vec<u8>> {
+    let bytes = match input {
+        Some(input) if Path::new(input).is_file() => fs::read(input)?,
+        Some(input) => input.as_bytes().to_vec(),
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            bytes
+        }
+    };
+
+    let text = match ::std::str::from_utf8(&bytes) {
+        Ok(text) => text.split_whitespace().collect::<String>(),
+        // a binary file
+        Err(_) => return Ok(bytes),
+    };
+    if let Ok(decoded) = hex::decode(&text) {
+        return Ok(decoded);
+    }
+    if let Ok(decoded) = base64::decode(&text) {
+        return Ok(decoded);
+    }
+    if input
+        .map(|input| Path::new(input).is_file())
+        .unwrap_or(false)
+    {
+        // a binary file that happens to be valid UTF-8
+        return Ok(bytes);
+    }
+    Err(Error::InvalidInput)
+}
+
+/// print the CBOR of the input in diagnostic notation, annotated as the
+/// given kind of structure (or the detected one)
+pub fn decode_cbor(term: &mut Term, input: Option<&str>, kind: Option<Kind>) -> Result<()> {
+    let bytes = read_input(input)?;
+    let value: Value = Deserializer::from(Cursor::new(&bytes[..]))
+        .deserialize_complete()
+        .map_err(Error::InvalidCbor)?;
+
+    let kind = kind.unwrap_or_else(|| Kind::detect(&bytes));
+    let mut out = String::new();
+    write_value(&mut out, &value, kind.schema(), 0);
+
+    writeln!(term, "/ {}, {} bytes /", kind, bytes.len())?;
+    if kind == Kind::Raw {
+        if let Some(note) = later_era_note(&bytes) {
+            writeln!(term, "/ {} /", note)?;
+        }
+    }
+    writeln!(term, "{}", out)?;
+    Ok(())
+}
+
+/// what the data not recognized as a Byron structure may be
+fn later_era_note(bytes: &[u8]) -> Option<String> {
+    match era::block_era(bytes) {
+        Some(era) if !era.is_supported() => {
+            return Some(format!(
+                "looks like a block of the {} era, not annotated: only the Byron era is supported",
+                era
+            ))
+        }
+        _ => {}
+    }
+    match era::transaction_era(bytes) {
+        Some(era) if !era.is_supported() => Some(
+            "looks like a transaction of the Shelley era (or a later one), not annotated: only the Byron era is supported"
+                .to_owned(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{coin::Coin, config::ProtocolMagic, hash::Blake2b256, tx::TxWitness};
+    use cbor_event::Len;
+    use utils::testing::{address, xprv};
+
+    fn encode<T: ::cbor_event::se::Serialize>(value: &T) -> Vec<u8> {
+        let mut se = Serializer::new_vec();
+        se.serialize(value).unwrap();
+        se.finalize()
+    }
+
+    fn annotate(bytes: &[u8], kind: Kind) -> String {
+        let value: Value = Deserializer::from(Cursor::new(bytes))
+            .deserialize_complete()
+            .unwrap();
+        let mut out = String::new();
+        write_value(&mut out, &value, kind.schema(), 0);
+        out
+    }
+
+    /// a transaction spending an output to an address of the mainnet
+    fn tx() -> Tx {
+        Tx::new_with(
+            vec![TxoPointer::new(Blake2b256::new(&[1]), 3)],
+            vec![TxOut::new(address(1), Coin::new(1_500_000).unwrap())],
+        )
+    }
+
+    fn txaux() -> TxAux {
+        let tx = tx();
+        let witness = TxInWitness::new(ProtocolMagic::from(764824073), &xprv(1), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    /// the boundary block of the epoch 2 of the mainnet, without slot leaders
+    fn boundary_block() -> Vec<u8> {
+        let mut body = Serializer::new_vec();
+        body.write_array(Len::Len(0)).unwrap();
+        let body = body.finalize();
+
+        let mut se = Serializer::new_vec();
+        se.write_array(Len::Len(2))
+            .unwrap()
+            .write_unsigned_integer(0)
+            .unwrap()
+            .write_array(Len::Len(3))
+            .unwrap();
+        se.write_array(Len::Len(5))
+            .unwrap()
+            .write_unsigned_integer(764824073)
+            .unwrap()
+            .write_bytes(&[0; 32])
+            .unwrap()
+            .write_bytes(&Blake2b256::new(&body).as_hash_bytes()[..])
+            .unwrap()
+            .write_array(Len::Len(2))
+            .unwrap()
+            .write_unsigned_integer(2)
+            .unwrap()
+            .write_array(Len::Len(1))
+            .unwrap()
+            .write_unsigned_integer(42)
+            .unwrap()
+            .write_array(Len::Len(1))
+            .unwrap()
+            .write_map(Len::Len(0))
+            .unwrap();
+        se.write_raw_bytes(&body)
+            .unwrap()
+            .write_array(Len::Len(1))
+            .unwrap()
+            .write_map(Len::Len(0))
+            .unwrap();
+        se.finalize()
+    }
+
+    #[test]
+    fn diagnostic_notation() {
+        // [1, -2, "a", h'ff', 24(h'8102'), {0: true}, [_ ], null]
+        let bytes = hex::decode("880121616141ffd818428102a100f59ffff6").unwrap();
+        assert_eq!(
+            annotate(&bytes, Kind::Raw),
+            "[\n  1,\n  -2,\n  \"a\",\n  h'ff',\n  24(<<[\n    2\n  ]>>),\n  {\n    0: true\n  },\n  [_],\n  null\n]"
+        );
+    }
+
+    #[test]
+    fn detect_block() {
+        let block = boundary_block();
+        assert_eq!(Kind::detect(&block), Kind::Block);
+    }
+
+    #[test]
+    fn annotate_block() {
+        let bytes = boundary_block();
+        let block = decode::<Block>(&bytes).unwrap();
+        let out = annotate(&bytes, Kind::Block);
+
+        assert!(out.starts_with(&format!(
+            "[ / boundary block {} /\n",
+            block.header().compute_hash()
+        )));
+        assert!(out.contains("0 / block type /"));
+        assert!(out.contains("764824073 / protocol magic /"));
+        assert!(out.contains(&format!("h'{}' / previous block /", hex::encode(&[0; 32]))));
+        assert!(out.contains("[ / consensus data, epoch 2 /"));
+        assert!(out.contains("42 / chain difficulty /"));
+        assert!(out.contains("[] / slot leaders of the epoch /"));
+    }
+
+    #[test]
+    fn detect_transactions() {
+        assert_eq!(Kind::detect(&encode(&tx())), Kind::Tx);
+        assert_eq!(Kind::detect(&encode(&txaux())), Kind::TxAux);
+    }
+
+    #[test]
+    fn annotate_tx() {
+        let tx = tx();
+        let out = annotate(&encode(&tx), Kind::Tx);
+
+        assert!(out.starts_with(&format!("[ / transaction {} /\n", tx.id())));
+        assert!(out.contains("[ / 1 input(s) /"));
+        assert!(out.contains(&format!("[ / input {}.3 /", Blake2b256::new(&[1]))));
+        assert!(out.contains("[ / 1 output(s) /"));
+        assert!(out.contains("[ / output of 1.500000 ADA /"));
+        assert!(out.contains(&format!("[ / address {}, ", tx.outputs[0].address)));
+        assert!(out.contains("24(<<[ / address payload /"));
+        assert!(out.contains("0 / public key address /"));
+        assert!(out.contains("/ CRC32 checksum /"));
+        assert!(out.contains("1500000 / 1.500000 ADA /"));
+        assert!(out.contains("{} / transaction attributes /"));
+    }
+
+    #[test]
+    fn annotate_txaux() {
+        let txaux = txaux();
+        let out = annotate(&encode(&txaux), Kind::TxAux);
+
+        assert!(out.starts_with(&format!("[ / signed transaction {} /\n", txaux.tx.id())));
+        assert!(out.contains(&format!("[ / transaction {} /", txaux.tx.id())));
+        assert!(out.contains("[ / 1 witness(es) /"));
+        assert!(out.contains("[ / public key witness /"));
+        assert!(out.contains("0 / witness type /"));
+        assert!(out.contains("/ public key /"));
+        assert!(out.contains("/ signature /"));
+    }
+
+    #[test]
+    fn detect_raw() {
+        assert_eq!(Kind::detect(&[0x01]), Kind::Raw);
+        assert_eq!(Kind::detect(&[0x82, 0x01, 0x02]), Kind::Raw);
+    }
+
+    #[test]
+    fn ada() {
+        assert_eq!(format_ada(0), "0.000000 ADA");
+        assert_eq!(format_ada(1_500_000), "1.500000 ADA");
+        assert_eq!(format_ada(45_000_000_000_000_000), "45000000000.000000 ADA");
+    }
+}
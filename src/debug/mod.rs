@@ -1,3 +1,6 @@
+pub mod decode;
+pub mod reorg;
+
 use cardano::hdwallet;
 use cardano::{
     address::{ExtendedAddr, StakeDistribution},
@@ -5,11 +8,65 @@ use cardano::{
     util::{base58, hex, try_from_slice::TryFromSlice},
 };
 use exe_common::genesisdata;
-use rand;
 use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::{error, fmt};
+use utils::entropy::{self, Purpose};
+use utils::era;
 use utils::term::{emoji, style::Style, Term};
+use wallet::{self, utils as wallet_utils, HDWalletModel, Wallet, WalletName};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    InvalidBase64(base64::DecodeError),
+    UnsupportedTransaction(era::Error),
+    InvalidTransaction(cbor_event::Error),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::InvalidBase64(_) => write!(f, "Expected base64 encoded data"),
+            Error::UnsupportedTransaction(_) => write!(f, "Unsupported transaction"),
+            Error::InvalidTransaction(_) => write!(f, "Invalid signed transaction"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::InvalidBase64(ref err) => Some(err),
+            Error::UnsupportedTransaction(ref err) => Some(err),
+            Error::InvalidTransaction(ref err) => Some(err),
+        }
+    }
+}
+
+pub fn command_address(
+    mut term: Term,
+    root_dir: PathBuf,
+    address: String,
+    wallet: Option<WalletName>,
+) {
+    // the Shelley era addresses are checked only, no other command takes them
+    if let Ok(era::Address::Shelley(shelley)) = era::validate_address(&address) {
+        term.success("Shelley era address\n").unwrap();
+        term.info(&format!("  - address type:       {}\n", shelley.kind))
+            .unwrap();
+        term.info(&format!("  - network id:         {}\n", shelley.network))
+            .unwrap();
+        term.warn("only the Byron era addresses are supported by the other commands\n")
+            .unwrap();
+        return;
+    }
 
-pub fn command_address(mut term: Term, address: String) {
     let bytes = match base58::decode(&address) {
         Err(err) => {
             term.error(&format!("Invalid Address, should be encoded in base58\n"))
@@ -54,6 +111,68 @@ pub fn command_address(mut term: Term, address: String) {
             .info(&format!("  - stake distribution: {}\n", id))
             .unwrap(),
     }
+
+    if let Some(name) = wallet {
+        address_derivation_path(&mut term, root_dir, name, &address)
+            .unwrap_or_else(|e| term.fail_with(e));
+    }
+}
+
+/// print the derivation path of the address in the wallet: the one of its
+/// payload if the wallet's key decrypts it (random index wallets), the one
+/// found in the wallet's address space otherwise (BIP44 wallets)
+fn address_derivation_path(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    address: &ExtendedAddr,
+) -> wallet::Result<()> {
+    let wallet = Wallet::load(root_dir, name)?;
+    let network_magic = address.attributes.network_magic;
+
+    match wallet.config.hdwallet_model {
+        HDWalletModel::RandomIndex2Levels => {
+            if address.attributes.derivation_path.is_none() {
+                term.warn(&format!(
+                    "  - wallet {}: no payload, not an address of a random index wallet\n",
+                    wallet.name
+                ))?;
+                return Ok(());
+            }
+            let lookup_struct = wallet_utils::load_randomindex_lookup_structure(term, network_magic, &wallet)?;
+            match lookup_struct.decrypt_addressing(address)? {
+                None => term.warn(&format!(
+                    "  - wallet {}: the payload does not decrypt with the wallet's key, not one of its addresses\n",
+                    wallet.name
+                ))?,
+                Some(addressing) => {
+                    term.info(&format!("  - derivation path:    {}\n", addressing))?;
+                    let derived = lookup_struct.get_address(&addressing);
+                    if &derived == address {
+                        term.success(&format!("  - wallet {}: one of its addresses\n", wallet.name))?;
+                    } else {
+                        // the payload was made with the wallet's key but not
+                        // the spending data: the funds cannot be spent
+                        term.error(&format!(
+                            "  - wallet {}: derives {} with this path, the funds of this address cannot be spent with the wallet's keys\n",
+                            wallet.name, derived
+                        ))?;
+                    }
+                }
+            }
+        }
+        HDWalletModel::BIP44 => match wallet_utils::find_wallet_address(term, network_magic, &wallet, address)? {
+            Some((addressing, _)) => {
+                term.info(&format!("  - derivation path:    {}\n", addressing.derivation_path()))?;
+                term.success(&format!("  - wallet {}: one of its addresses\n", wallet.name))?;
+            }
+            None => term.warn(&format!(
+                "  - wallet {}: not one of its addresses (up to the gap limit after its used addresses)\n",
+                wallet.name
+            ))?,
+        },
+    }
+    Ok(())
 }
 
 /// Read a JSON file from stdin and write its canonicalized form to stdout.
@@ -74,16 +193,16 @@ pub fn hash() {
     println!("{}", hash::Blake2b256::new(&data));
 }
 
-pub fn decode_signed_tx() {
+/// Decode the base64 encoded signed transaction on stdin.
+pub fn decode_signed_tx() -> Result<(), Error> {
     let mut data = String::new();
-    io::stdin()
-        .read_to_string(&mut data)
-        .expect("Cannot read stdin.");
+    io::stdin().read_to_string(&mut data)?;
 
-    let bytes = base64::decode(&data).unwrap();
+    let bytes = base64::decode(data.trim()).map_err(Error::InvalidBase64)?;
+    era::check_transaction(&bytes).map_err(Error::UnsupportedTransaction)?;
     let txaux: cardano::tx::TxAux = cbor_event::de::Deserializer::from(std::io::Cursor::new(bytes))
         .deserialize_complete()
-        .unwrap();
+        .map_err(Error::InvalidTransaction)?;
 
     println!("inputs({})", txaux.tx.inputs.len());
     for ((i, input), witness) in txaux.tx.inputs.iter().enumerate().zip(txaux.witness.iter()) {
@@ -111,13 +230,12 @@ pub fn decode_signed_tx() {
             style!(&output.value)
         );
     }
+    Ok(())
 }
 
 pub fn generate_xprv(output_prv: &str) {
     let mut buf = [0u8; hdwallet::XPRV_SIZE];
-    for x in buf.iter_mut() {
-        *x = rand::random()
-    }
+    entropy::default_source().fill_bytes(Purpose::PrivateKey, &mut buf);
 
     let xprv = hdwallet::XPrv::normalize_bytes(buf);
     let s = hex::encode(xprv.as_ref());
@@ -0,0 +1,323 @@
+//! simulation of a blockchain reorganisation, to check the wallets unwind
+//! and reapply what they learnt from the abandoned blocks.
+//!
+//! Valid blocks cannot be forged without the slot leaders' keys, so the
+//! competing branch is an empty one starting `depth` blocks below the local
+//! tip: the local tip is moved back to the fork point, the wallets are rolled
+//! back as `wallet sync` does when the blockchain forked, then the tip is
+//! restored and the wallets synced again through the normal sync path.
+//!
+//! The simulation runs on a copy of the blockchain and of the attached
+//! wallets, under a temporary root directory: the real tip and wallet logs
+//! are never modified. The blocks, packs and indexes never change once
+//! written, they are hard linked in the copy when the temporary directory is
+//! on the same filesystem.
+
+use std::{
+    error, fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use cardano::{block::BlockDate, coin::Coin, tx::TxoPointer};
+use cardano_storage as storage;
+
+use blockchain::{self, Blockchain, BlockchainName};
+use utils::{
+    entropy::{self, Purpose},
+    term::{style::Style, Term},
+};
+use wallet::{
+    self,
+    state::{
+        log, lookup,
+        ptr::StatePtr,
+        state::{FromLogsError, State},
+    },
+    utils::{create_wallet_state_from_logs, lock_wallet_log, rollback_wallet_if_forked},
+    Wallet, Wallets,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Blockchain(blockchain::Error),
+    Wallet(wallet::Error),
+    Storage(storage::Error),
+    ChainTooShort(usize),
+    WalletsDiverged(usize),
+    CannotCopy(PathBuf, io::Error),
+    CannotRemoveTempRootDir(PathBuf, io::Error),
+}
+impl From<blockchain::Error> for Error {
+    fn from(e: blockchain::Error) -> Self {
+        Error::Blockchain(e)
+    }
+}
+impl From<wallet::Error> for Error {
+    fn from(e: wallet::Error) -> Self {
+        Error::Wallet(e)
+    }
+}
+impl From<log::Error> for Error {
+    fn from(e: log::Error) -> Self {
+        Error::Wallet(e.into())
+    }
+}
+impl From<storage::Error> for Error {
+    fn from(e: storage::Error) -> Self {
+        Error::Storage(e)
+    }
+}
+impl From<::std::io::Error> for Error {
+    fn from(e: ::std::io::Error) -> Self {
+        Error::Wallet(e.into())
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Blockchain(_) => write!(f, "Cannot load the blockchain"),
+            Error::Wallet(_) => write!(f, "Error with the wallets"),
+            Error::Storage(_) => write!(f, "Cannot read the blocks from the local storage"),
+            Error::ChainTooShort(depth) => {
+                write!(f, "The local blockchain has less than {} blocks", depth)
+            }
+            Error::WalletsDiverged(count) => write!(
+                f,
+                "{} wallet(s) did not unwind or reapply the abandoned blocks correctly",
+                count
+            ),
+            Error::CannotCopy(path, _) => write!(
+                f,
+                "Cannot copy `{}' to the temporary root directory",
+                path.to_string_lossy()
+            ),
+            Error::CannotRemoveTempRootDir(path, _) => write!(
+                f,
+                "Cannot remove the temporary root directory `{}'",
+                path.to_string_lossy()
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::Blockchain(ref err) => Some(err),
+            Error::Wallet(ref err) => Some(err),
+            Error::Storage(ref err) => Some(err),
+            Error::ChainTooShort(_) => None,
+            Error::WalletsDiverged(_) => None,
+            Error::CannotCopy(_, ref err) => Some(err),
+            Error::CannotRemoveTempRootDir(_, ref err) => Some(err),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// what the wallet knows: its balance and its unspent outputs
+#[derive(PartialEq)]
+struct Snapshot {
+    total: Coin,
+    utxos: Vec<TxoPointer>,
+}
+impl Snapshot {
+    fn new<LS: lookup::AddressLookup>(state: &State<LS>) -> Result<Self> {
+        Ok(Snapshot {
+            total: state.total().map_err(wallet::Error::from)?,
+            utxos: state.utxos.keys().cloned().collect(),
+        })
+    }
+}
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {} unspent outputs", self.total, self.utxos.len())
+    }
+}
+
+/// the directories of the storage whose files are never modified once
+/// written, they can be shared with the copy
+const IMMUTABLE_STORAGE_DIRS: &'static [&'static str] = &["blob", "pack", "index"];
+
+pub fn simulate_reorg(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    depth: usize,
+) -> Result<()> {
+    let temp_root_dir = ::std::env::temp_dir().join(format!(
+        "cardano-cli-reorg-{:08x}",
+        entropy::default_source().next_u32(Purpose::Other)
+    ));
+    let result = copy_to_temp_root(&root_dir, &temp_root_dir, &name)
+        .and_then(|()| simulate(term, &temp_root_dir, name, depth));
+    let removed = fs::remove_dir_all(&temp_root_dir);
+    result?;
+    removed.map_err(|e| Error::CannotRemoveTempRootDir(temp_root_dir, e))
+}
+
+/// copy the blockchain and the wallets attached to it under the temporary
+/// root directory
+fn copy_to_temp_root(root_dir: &Path, temp_root_dir: &Path, name: &BlockchainName) -> Result<()> {
+    copy_dir(
+        &blockchain::config::directory(root_dir, name),
+        &blockchain::config::directory(temp_root_dir, name),
+        false,
+    )?;
+    for (_, wallet) in Wallets::load(root_dir)? {
+        match wallet.config.attached_blockchain() {
+            Ok(Some(ref attached)) if attached == name => {}
+            _ => continue,
+        }
+        let dirname = wallet.name.as_dirname();
+        copy_dir(
+            &wallet::config::directory(root_dir, &dirname),
+            &wallet::config::directory(temp_root_dir, &dirname),
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// copy the directory recursively, the files of the immutable storage
+/// directories (or all of them if `link_files`) are hard linked if possible
+fn copy_dir(from: &Path, to: &Path, link_files: bool) -> Result<()> {
+    let copy_error = |e| Error::CannotCopy(from.to_path_buf(), e);
+    fs::create_dir_all(to).map_err(copy_error)?;
+    for entry in fs::read_dir(from).map_err(copy_error)? {
+        let entry = entry.map_err(copy_error)?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if entry.file_type().map_err(copy_error)?.is_dir() {
+            let immutable = IMMUTABLE_STORAGE_DIRS
+                .iter()
+                .any(|dir| entry.file_name() == *dir);
+            copy_dir(&source, &target, link_files || immutable)?;
+        } else if !(link_files && fs::hard_link(&source, &target).is_ok()) {
+            fs::copy(&source, &target).map_err(|e| Error::CannotCopy(source.clone(), e))?;
+        }
+    }
+    Ok(())
+}
+
+fn simulate(term: &mut Term, root_dir: &Path, name: BlockchainName, depth: usize) -> Result<()> {
+    let root_dir = root_dir.to_path_buf();
+    let blockchain = Blockchain::load(&root_dir, name)?;
+    let tip = blockchain.load_tip().0;
+    let fork_point = storage::iter::ReverseIter::from(&blockchain.storage, tip.hash.clone())?
+        .nth(depth)
+        .ok_or(Error::ChainTooShort(depth + 1))?;
+    let fork_hash = fork_point.get_header().compute_hash();
+    let fork_date = fork_point.get_header().get_blockdate();
+
+    let wallets: Vec<Wallet> = Wallets::load(&root_dir)?
+        .into_iter()
+        .map(|(_, wallet)| wallet)
+        .filter(|wallet| match wallet.config.attached_blockchain() {
+            Ok(Some(ref attached)) => attached == &blockchain.name,
+            _ => false,
+        })
+        .collect();
+
+    term.info(&format!(
+        "simulating a reorganisation from {} ({}), abandoning {} blocks, on {} wallet(s)\n",
+        style!(fork_hash),
+        style!(fork_date),
+        style!(depth),
+        style!(wallets.len())
+    ))?;
+
+    let mut before = Vec::new();
+    let mut expected = Vec::new();
+    for wallet in wallets.iter() {
+        let state =
+            create_wallet_state_from_logs(wallet, &root_dir, lookup::accum::Accum::default())?;
+        before.push(Snapshot::new(&state)?);
+        expected.push(expected_after_rollback(wallet, &blockchain, &fork_date)?);
+    }
+
+    // 1. switch to the competing (empty) branch and unwind the wallets
+    blockchain.save_tip(&fork_hash);
+    let unwound = unwind_wallets(term, &root_dir, &blockchain, &wallets);
+
+    // 2. switch back to the original branch and reapply the blocks, the
+    //    tip is restored even if the wallets failed to unwind.
+    blockchain.save_tip(&tip.hash);
+    let unwound = unwound?;
+    let mut reapplied = Vec::new();
+    for wallet in wallets.iter() {
+        wallet::commands::sync(term, root_dir.clone(), wallet.name.clone())?;
+        let state =
+            create_wallet_state_from_logs(wallet, &root_dir, lookup::accum::Accum::default())?;
+        reapplied.push(Snapshot::new(&state)?);
+    }
+
+    let mut diverged = 0;
+    for (i, wallet) in wallets.iter().enumerate() {
+        let ok = unwound[i] == expected[i] && reapplied[i] == before[i];
+        if ok {
+            term.success(&format!("[PASS] {}\n", wallet.name))?;
+        } else {
+            diverged += 1;
+            term.error(&format!("[FAIL] {}\n", wallet.name))?;
+        }
+        writeln!(term, "   * before:    {}", before[i])?;
+        writeln!(
+            term,
+            "   * unwound:   {} (expected {})",
+            unwound[i], expected[i]
+        )?;
+        writeln!(term, "   * reapplied: {}", reapplied[i])?;
+    }
+
+    if diverged > 0 {
+        Err(Error::WalletsDiverged(diverged))
+    } else {
+        Ok(())
+    }
+}
+
+fn unwind_wallets(
+    term: &mut Term,
+    root_dir: &Path,
+    blockchain: &Blockchain,
+    wallets: &[Wallet],
+) -> Result<Vec<Snapshot>> {
+    let mut unwound = Vec::new();
+    for wallet in wallets.iter() {
+        rollback_wallet_if_forked(term, root_dir, wallet, blockchain)?;
+        let state =
+            create_wallet_state_from_logs(wallet, root_dir, lookup::accum::Accum::default())?;
+        unwound.push(Snapshot::new(&state)?);
+    }
+    Ok(unwound)
+}
+
+/// the wallet's state made of the log entries up to the fork point
+fn expected_after_rollback(
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+    fork_date: &BlockDate,
+) -> Result<Snapshot> {
+    let reader = log::LogReader::open(lock_wallet_log(wallet)?)?;
+    let entries: Vec<log::Log<lookup::Address>> = reader
+        .into_iter()
+        .collect::<log::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|entry| match entry.ptr().latest_addr {
+            None => true,
+            Some(ref date) => date <= fork_date,
+        })
+        .collect();
+
+    match State::from_logs(lookup::accum::Accum::default(), entries.into_iter().map(Ok)) {
+        Ok(state) => Snapshot::new(&state),
+        Err(FromLogsError::NoEntries(lookup_struct)) => Snapshot::new(&State::new(
+            StatePtr::new_before_genesis(blockchain.config.genesis.clone()),
+            lookup_struct,
+        )),
+        Err(FromLogsError::LogReadFailed(err)) => Err(err.into()),
+        Err(FromLogsError::AddressLookupFailed(err)) => Err(wallet::Error::from(err).into()),
+    }
+}
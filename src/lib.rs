@@ -0,0 +1,70 @@
+//! cardano-cli as a library
+//!
+//! the wallets, the local blockchains and the staging transactions managed
+//! by the `cardano-cli` command, for the services embedding them instead of
+//! spawning the command and scraping its output. The library is the `api`
+//! module and the types it re-exports: its functions neither prompt nor
+//! write to the terminal, nor exit the process.
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate lazy_static;
+extern crate cardano;
+extern crate cardano_storage;
+extern crate cbor_event;
+extern crate cryptoxide;
+extern crate exe_common;
+extern crate storage_units;
+
+#[cfg(feature = "cli")]
+extern crate atty;
+#[cfg(feature = "cli")]
+extern crate console;
+#[cfg(feature = "cli")]
+extern crate dialoguer;
+#[cfg(feature = "cli")]
+extern crate indicatif;
+
+#[macro_use]
+extern crate serde_derive;
+extern crate base64;
+extern crate humantime;
+extern crate qrcode;
+extern crate rand;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
+
+// the implementation of the command (see main.rs), not the interface of
+// the library: the `cli` feature adds the modules of its terminal
+#[doc(hidden)]
+#[macro_use]
+pub mod utils;
+#[doc(hidden)]
+pub mod address_book;
+#[doc(hidden)]
+pub mod blockchain;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod debug;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod profile;
+#[doc(hidden)]
+pub mod schema;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod selftest;
+#[doc(hidden)]
+pub mod transaction;
+#[doc(hidden)]
+pub mod wallet;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod watch;
+
+pub mod api;
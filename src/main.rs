@@ -3,42 +3,21 @@ use std::path::PathBuf;
 extern crate dirs;
 #[macro_use]
 extern crate log;
-extern crate env_logger;
 
-#[macro_use]
-extern crate lazy_static;
 extern crate cardano;
-extern crate cardano_storage;
-extern crate cbor_event;
-extern crate cryptoxide;
-extern crate exe_common;
-extern crate storage_units;
-
-extern crate console;
-extern crate dialoguer;
-extern crate indicatif;
-
-#[macro_use]
-extern crate serde_derive;
-extern crate base64;
-extern crate humantime;
-extern crate rand;
-extern crate serde;
-extern crate serde_json;
-extern crate serde_yaml;
+extern crate cardano_cli;
 
-#[macro_use]
-mod utils;
-mod blockchain;
-mod debug;
-mod transaction;
-mod wallet;
+// the implementation of the commands, in the library
+use cardano_cli::{
+    address_book, blockchain, debug, plan, profile, schema, selftest, transaction, utils, wallet,
+    watch,
+};
 
-use utils::term;
+use utils::{logging, term};
 
 #[macro_use]
 extern crate clap;
-use clap::{App, Arg, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 
 fn main() {
     let default_root_dir = get_default_root_dir();
@@ -64,25 +43,52 @@ commit: {}
         .after_help(crate_description!())
         .arg(global_verbose_definition())
         .arg(global_quiet_definition())
+        .args(&global_log_definitions())
         .arg(global_color_definition())
+        .arg(global_progress_definition())
+        .arg(global_passphrase_file_definition())
         .arg(global_rootdir_definition(&default_root_dir))
+        .arg(global_profile_definition())
+        .arg(global_lock_wait_definition())
         .subcommand(blockchain_commands_definition())
         .subcommand(wallet_commands_definition())
         .subcommand(transaction_commands_definition())
         .subcommand(debug_commands_definition())
+        .subcommand(run_commands_definition())
+        .subcommand(selftest_commands_definition())
+        .subcommand(schema_commands_definition())
+        .subcommand(watch_commands_definition())
+        .subcommand(address_book_commands_definition())
+        .subcommand(profile_commands_definition())
         .get_matches();
 
     let mut term = term::Term::new(configure_terminal(&matches));
 
-    let root_dir = global_rootdir_match(&default_root_dir, &matches);
+    let profiles_file = get_profiles_file(&default_root_dir);
+    let profile = global_profile_match(&mut term, &profiles_file, &matches);
+    let root_dir = global_rootdir_match(&default_root_dir, &profile, &matches);
 
     debug!("cardano-cli's root directory: `{:?}`", root_dir);
+    global_lock_wait_match(&matches);
 
     match matches.subcommand() {
-        (BLOCKCHAIN_COMMAND, Some(matches)) => subcommand_blockchain(term, root_dir, matches),
-        (WALLET_COMMAND, Some(matches)) => subcommand_wallet(term, root_dir, matches),
-        (TRANSACTION_COMMAND, Some(matches)) => subcommand_transaction(term, root_dir, matches),
+        (BLOCKCHAIN_COMMAND, Some(matches)) => {
+            subcommand_blockchain(term, root_dir, &profile, matches)
+        }
+        (WALLET_COMMAND, Some(matches)) => subcommand_wallet(term, root_dir, &profile, matches),
+        (TRANSACTION_COMMAND, Some(matches)) => {
+            subcommand_transaction(term, root_dir, &profile, matches)
+        }
         (DEBUG_COMMAND, Some(matches)) => subcommand_debug(term, root_dir, matches),
+        (RUN_COMMAND, Some(matches)) => subcommand_run(term, root_dir, &profile, matches),
+        (SELFTEST_COMMAND, Some(matches)) => subcommand_selftest(term, matches),
+        (SCHEMA_COMMAND, Some(matches)) => subcommand_schema(term, matches),
+        (WATCH_COMMAND, Some(matches)) => subcommand_watch(term, root_dir, &profile, matches),
+        (ADDRESS_BOOK_COMMAND, Some(matches)) => subcommand_address_book(term, root_dir, matches),
+        (PROFILE_COMMAND, Some(sub_matches)) => {
+            let active = matches.value_of("PROFILE").map(|name| name.to_owned());
+            subcommand_profile(term, profiles_file, active, sub_matches)
+        }
         _ => {
             term.error(matches.usage()).unwrap();
             ::std::process::exit(1)
@@ -96,6 +102,7 @@ commit: {}
 
 const APPLICATION_DIRECTORY_NAME: &'static str = "cardano-cli";
 const APPLICATION_ENVIRONMENT_ROOT_DIR: &'static str = "CARDANO_CLI_ROOT_DIR";
+const APPLICATION_ENVIRONMENT_PROFILE: &'static str = "CARDANO_CLI_PROFILE";
 
 fn get_default_root_dir() -> PathBuf {
     match dirs::data_local_dir() {
@@ -110,7 +117,20 @@ fn global_rootdir_definition<'a, 'b>(default: &'a PathBuf) -> Arg<'a, 'b> {
         .default_value(default.to_str().unwrap())
         .env(APPLICATION_ENVIRONMENT_ROOT_DIR)
 }
-fn global_rootdir_match<'a>(default: &'a PathBuf, matches: &ArgMatches<'a>) -> PathBuf {
+fn global_rootdir_match<'a>(
+    default: &'a PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) -> PathBuf {
+    // the root directory of the profile takes the place of the default
+    // value, not of the one given on the command line or the environment
+    let given = matches.occurrences_of("ROOT_DIR") > 0
+        || ::std::env::var_os(APPLICATION_ENVIRONMENT_ROOT_DIR).is_some();
+    if !given {
+        if let Some(ref dir) = profile.root_dir {
+            return dir.clone();
+        }
+    }
     match matches.value_of("ROOT_DIR") {
         Some(dir) => PathBuf::from(dir),
 
@@ -121,6 +141,73 @@ fn global_rootdir_match<'a>(default: &'a PathBuf, matches: &ArgMatches<'a>) -> P
     }
 }
 
+/// the profiles file of the user's configuration directory, of the default
+/// root directory when there is no such directory
+fn get_profiles_file(default_root_dir: &PathBuf) -> PathBuf {
+    match dirs::config_dir() {
+        None => default_root_dir.join(profile::PROFILES_FILE),
+        Some(dir) => dir
+            .join(APPLICATION_DIRECTORY_NAME)
+            .join(profile::PROFILES_FILE),
+    }
+}
+fn global_profile_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("PROFILE")
+        .long("profile")
+        .takes_value(true)
+        .value_name("NAME")
+        .env(APPLICATION_ENVIRONMENT_PROFILE)
+        .help("the profile whose settings (root directory, protocol magic, blockchain, peers, fee policy) are used in place of the default ones")
+}
+/// the profile given to `--profile`, or the empty profile
+fn global_profile_match<'a>(
+    term: &mut term::Term,
+    profiles_file: &PathBuf,
+    matches: &ArgMatches<'a>,
+) -> profile::Profile {
+    match matches.value_of("PROFILE") {
+        None => profile::Profile::default(),
+        Some(name) => profile::Profiles::load(profiles_file.clone())
+            .and_then(|profiles| profiles.get(name).map(|profile| profile.clone()))
+            .unwrap_or_else(|e| term.fail_with(e)),
+    }
+}
+
+/// the address given to the argument: an address, or `@LABEL` for the
+/// address with this label in the address book
+fn address_argument_match<'a>(
+    term: &mut term::Term,
+    root_dir: &PathBuf,
+    matches: &ArgMatches<'a>,
+    name: &str,
+) -> Option<cardano::address::ExtendedAddr> {
+    let value = matches.value_of(name)?;
+    Some(address_book::resolve(root_dir, value).unwrap_or_else(|e| term.fail_with(e)))
+}
+
+fn qr_argument_definitions<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("QR")
+            .long("qr")
+            .help("render it as a QR code in the terminal, in chunks if it is too large for one"),
+        Arg::with_name("QR_PNG")
+            .long("qr-png")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with("QR")
+            .help("render it as a QR code in this PNG file, numbered (`FILE-1.png', `FILE-2.png'...) if it is too large for one"),
+    ]
+}
+fn qr_argument_match<'a>(matches: &ArgMatches<'a>) -> Option<utils::qr::Output> {
+    if let Some(path) = matches.value_of("QR_PNG") {
+        Some(utils::qr::Output::Png(PathBuf::from(path)))
+    } else if matches.is_present("QR") {
+        Some(utils::qr::Output::Terminal)
+    } else {
+        None
+    }
+}
+
 fn global_quiet_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("QUIET")
         .long("quiet")
@@ -131,6 +218,69 @@ fn global_quiet_option<'a>(matches: &ArgMatches<'a>) -> bool {
     matches.is_present("QUIET")
 }
 
+fn global_log_definitions<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("LOG_LEVEL")
+            .long("log-level")
+            .takes_value(true)
+            .value_name("LEVEL")
+            .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+            .global(true)
+            .help("the level of the log records to write, instead of the one of `--verbose'"),
+        Arg::with_name("LOG_FILE")
+            .long("log-file")
+            .takes_value(true)
+            .value_name("FILE")
+            .global(true)
+            .help("write the log records to this file instead of the standard error, rotated every 10MiB (5 files kept)"),
+        Arg::with_name("LOG_FORMAT")
+            .long("log-format")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .default_value("text")
+            .possible_values(&["text", "json"])
+            .global(true)
+            .help("write the log records as text or as one JSON object per line"),
+    ]
+}
+fn global_log_option<'a>(matches: &ArgMatches<'a>) -> logging::Config {
+    let level = match matches.value_of("LOG_LEVEL") {
+        // clap checked the possible values
+        Some(level) => level.parse().unwrap(),
+        None => match global_verbose_option(matches) {
+            0 => log::LevelFilter::Warn,
+            1 => log::LevelFilter::Info,
+            2 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        },
+    };
+    let format = match matches.value_of("LOG_FORMAT") {
+        Some("json") => logging::Format::Json,
+        _ => logging::Format::Text,
+    };
+    logging::Config {
+        level: level,
+        format: format,
+        file: matches.value_of("LOG_FILE").map(PathBuf::from),
+    }
+}
+
+fn global_lock_wait_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("LOCK_WAIT")
+        .long("lock-wait")
+        .takes_value(true)
+        .value_name("SECONDS")
+        .default_value("0")
+        .global(true)
+        .help("how long to wait for a staging transaction another process is using, instead of failing right away")
+}
+fn global_lock_wait_match<'a>(matches: &ArgMatches<'a>) {
+    let seconds = value_t!(matches, "LOCK_WAIT", u64).unwrap_or_else(|e| e.exit());
+    transaction::core::staging_transaction::set_lock_wait(::std::time::Duration::from_secs(
+        seconds,
+    ));
+}
+
 fn global_color_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("COLOR")
         .long("color")
@@ -153,6 +303,25 @@ fn global_color_option<'a>(matches: &ArgMatches<'a>) -> term::ColorChoice {
         }
     }
 }
+fn global_progress_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("PROGRESS")
+        .long("progress")
+        .takes_value(true)
+        .value_name("FORMAT")
+        .default_value("terminal")
+        .possible_values(&["terminal", "json", "none"])
+        .global(true)
+        .help("how to report the progress of the sync, the rescan... progress bars, one JSON event per line on the standard error, or no progress bars")
+}
+fn global_progress_option<'a>(matches: &ArgMatches<'a>) -> term::ProgressFormat {
+    match matches.value_of("PROGRESS") {
+        None => term::ProgressFormat::Terminal,
+        Some("terminal") => term::ProgressFormat::Terminal,
+        Some("json") => term::ProgressFormat::Json,
+        Some("none") => term::ProgressFormat::Hidden,
+        Some(&_) => unreachable!(),
+    }
+}
 fn global_verbose_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("VERBOSITY")
         .long("verbose")
@@ -165,26 +334,44 @@ fn global_verbose_option<'a>(matches: &ArgMatches<'a>) -> u64 {
     matches.occurrences_of("VERBOSITY")
 }
 
+fn global_passphrase_file_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("PASSPHRASE_FILE")
+        .long("passphrase-file")
+        .takes_value(true)
+        .value_name("FILE")
+        .global(true)
+        .help("read the spending and encryption passphrases from the first line of the file instead of prompting them")
+}
+fn global_passphrase_option<'a>(matches: &ArgMatches<'a>) -> Option<term::PassphraseSource> {
+    match matches.value_of("PASSPHRASE_FILE") {
+        Some(file) => Some(term::PassphraseSource::File(PathBuf::from(file))),
+        None if ::std::env::var_os(term::PASSPHRASE_ENV).is_some() => {
+            Some(term::PassphraseSource::Environment(term::PASSPHRASE_ENV))
+        }
+        None => None,
+    }
+}
+
 fn configure_terminal<'a>(matches: &ArgMatches<'a>) -> term::Config {
     let quiet = global_quiet_option(matches);
     let color = global_color_option(matches);
-    let verbosity = global_verbose_option(matches);
-
-    if !quiet {
-        let log_level = match verbosity {
-            0 => log::LevelFilter::Warn,
-            1 => log::LevelFilter::Info,
-            2 => log::LevelFilter::Debug,
-            _ => log::LevelFilter::Trace,
-        };
-        env_logger::Builder::from_default_env()
-            .filter_level(log_level)
-            .init();
+    let passphrase = global_passphrase_option(matches);
+    let progress = global_progress_option(matches);
+    let log = global_log_option(matches);
+
+    // the log file is not the command line output, `--quiet' keeps it
+    if !quiet || log.file.is_some() {
+        if let Err(err) = logging::init(log) {
+            eprintln!("cannot initialise the logs: {}", err);
+            ::std::process::exit(1);
+        }
     }
 
     term::Config {
         color: color,
         quiet: quiet,
+        passphrase: passphrase,
+        progress: progress,
     }
 }
 
@@ -208,6 +395,30 @@ fn blockchain_argument_name_match<'a>(
         Err(err) => term.fail_with(err),
     }
 }
+/// the blockchain name, optional when the profile has a blockchain: only
+/// for the commands where it is the last positional argument
+fn blockchain_argument_default_name_definition<'a, 'b>() -> Arg<'a, 'b> {
+    blockchain_argument_name_definition()
+        .required(false)
+        .help("the blockchain name, the blockchain of the profile if not given")
+}
+fn blockchain_argument_name_or_default_match<'a>(
+    term: &mut term::Term,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) -> blockchain::BlockchainName {
+    if matches.is_present("BLOCKCHAIN_NAME") {
+        return blockchain_argument_name_match(term, matches);
+    }
+    match profile.blockchain() {
+        Some(name) => name,
+        None => {
+            term.error("no blockchain given and no blockchain in the profile\n")
+                .unwrap();
+            ::std::process::exit(1)
+        }
+    }
+}
 fn blockchain_argument_remote_alias_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("BLOCKCHAIN_REMOTE_ALIAS")
         .help("Alias given to a remote node.")
@@ -230,6 +441,41 @@ fn blockchain_argument_remote_endpoint_match<'a>(matches: &ArgMatches<'a>) -> St
         None => unreachable!(),
     }
 }
+fn blockchain_argument_proxy_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("BLOCKCHAIN_PROXY")
+        .long("proxy")
+        .takes_value(true)
+        .value_name("HOST:PORT")
+        .required(false)
+        .help("connect to the peers through this SOCKS5 proxy (e.g. Tor's `127.0.0.1:9050'), instead of the proxy set with `blockchain proxy set'")
+}
+fn blockchain_argument_proxy_match<'a>(matches: &ArgMatches<'a>) -> Option<String> {
+    matches
+        .value_of("BLOCKCHAIN_PROXY")
+        .map(|proxy| proxy.to_owned())
+}
+fn clock_check_argument_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CLOCK_CHECK")
+        .long("clock-check")
+        .takes_value(true)
+        .value_name("POLICY")
+        .possible_values(&["off", "warn", "abort"])
+        .default_value("off")
+        .help("check the local clock against the NTP server of the profile first (a query to a third party, waiting up to 3 seconds for it), and warn or abort if it is skewed")
+}
+fn clock_check_argument_match<'a>(
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) -> utils::clock::Config {
+    let policy =
+        value_t!(matches, "CLOCK_CHECK", utils::clock::Policy).unwrap_or_else(|e| e.exit());
+    let default = utils::clock::Config::default();
+    utils::clock::Config {
+        policy,
+        server: profile.ntp_server.clone().unwrap_or(default.server),
+        max_skew: profile.max_clock_skew.unwrap_or(default.max_skew),
+    }
+}
 fn blockchain_argument_template_definition<'a, 'b>() -> Arg<'a, 'b> {
     const AVAILABLE_TEMPLATES: &'static [&'static str] = &["mainnet", "staging", "testnet"];
 
@@ -254,6 +500,39 @@ fn blockchain_argument_template_match<'a>(matches: &ArgMatches<'a>) -> blockchai
         }
     }
 }
+fn blockchain_argument_genesis_definitions<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("BLOCKCHAIN_GENESIS")
+            .long("genesis")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .requires("BLOCKCHAIN_GENESIS_BLOCK")
+            .help("the genesis data file of a private network, the blockchain is created from it instead of the template"),
+        Arg::with_name("BLOCKCHAIN_GENESIS_BLOCK")
+            .long("genesis-block")
+            .takes_value(true)
+            .value_name("HASH")
+            .required(false)
+            .requires("BLOCKCHAIN_GENESIS")
+            .help("the hash of the first block (the epoch 0 boundary block) of the private network"),
+        Arg::with_name("BLOCKCHAIN_PROTOCOL_PARAMETERS")
+            .long("protocol-parameters")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .help("the current protocol parameters of the network (JSON, with its `txFeePolicy'), when the fee policy changed since the genesis"),
+    ]
+}
+fn blockchain_argument_genesis_match<'a>(
+    term: &mut term::Term,
+    matches: &ArgMatches<'a>,
+) -> Option<(PathBuf, cardano::block::HeaderHash)> {
+    let genesis_file = value_t!(matches, "BLOCKCHAIN_GENESIS", PathBuf).ok()?;
+    let genesis_block =
+        blockchain_argument_headhash_match(term, matches, "BLOCKCHAIN_GENESIS_BLOCK");
+    Some((genesis_file, genesis_block))
+}
 fn blockchain_argument_headhash_match<'a>(
     term: &mut term::Term,
     matches: &ArgMatches<'a>,
@@ -308,7 +587,12 @@ fn blockchain_argument_query_params_match<'a>(
     blockchain::commands::QueryParams { start, end }
 }
 
-fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_blockchain<'a>(
+    mut term: term::Term,
+    root_dir: PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) {
     match matches.subcommand() {
         ("list", Some(matches)) => {
             let detailed = matches.is_present("LIST_DETAILS");
@@ -318,10 +602,33 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
         }
         ("new", Some(matches)) => {
             let name = blockchain_argument_name_match(&mut term, &matches);
-            let net_config = blockchain_argument_template_match(&matches);
+            let mut net_config = blockchain_argument_template_match(&matches);
+            let genesis = blockchain_argument_genesis_match(&mut term, &matches);
+            let protocol_parameters =
+                value_t!(matches, "BLOCKCHAIN_PROTOCOL_PARAMETERS", PathBuf).ok();
+            // the template is ignored for the blockchains of a genesis file
+            if genesis.is_some() {
+                net_config.peers = blockchain::Peers::new();
+            }
+            // the peers of the profile take the place of the template's
+            if !profile.peers.is_empty() {
+                net_config.peers = blockchain::Peers::new();
+                for (alias, endpoint) in profile.peers.iter() {
+                    net_config
+                        .peers
+                        .push(alias.clone(), blockchain::Peer::new(endpoint.clone()));
+                }
+            }
 
-            blockchain::commands::new(&mut term, root_dir, name, net_config)
-                .unwrap_or_else(|e| term.fail_with(e));
+            blockchain::commands::new(
+                &mut term,
+                root_dir,
+                name,
+                net_config,
+                genesis,
+                protocol_parameters,
+            )
+            .unwrap_or_else(|e| term.fail_with(e));
         }
         ("remote-add", Some(matches)) => {
             let name = blockchain_argument_name_match(&mut term, &matches);
@@ -361,6 +668,89 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
             blockchain::commands::remote_ls(&mut term, root_dir, name, detailed)
                 .unwrap_or_else(|e| term.fail_with(e));
         }
+        ("peer", Some(matches)) => {
+            let res = match matches.subcommand() {
+                ("add", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let alias = blockchain_argument_remote_alias_match(&matches);
+                    let endpoint = blockchain_argument_remote_endpoint_match(&matches);
+
+                    blockchain::commands::remote_add(&mut term, root_dir, name, alias, endpoint)
+                }
+                ("remove", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let alias = blockchain_argument_remote_alias_match(&matches);
+
+                    blockchain::commands::remote_rm(&mut term, root_dir, name, alias)
+                }
+                ("list", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+
+                    blockchain::commands::peer_list(&mut term, root_dir, name)
+                }
+                ("test", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let peers = values_t!(matches, "BLOCKCHAIN_REMOTE_ALIAS", String)
+                        .unwrap_or_else(|_| Vec::new());
+
+                    blockchain::commands::peer_test(&mut term, root_dir, name, peers)
+                }
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            };
+            res.unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("seed", Some(matches)) => {
+            let res = match matches.subcommand() {
+                ("add", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let endpoint =
+                        value_t!(matches, "SEED_ENDPOINT", String).unwrap_or_else(|e| e.exit());
+
+                    blockchain::commands::seed_add(&mut term, root_dir, name, endpoint)
+                }
+                ("remove", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let endpoint =
+                        value_t!(matches, "SEED_ENDPOINT", String).unwrap_or_else(|e| e.exit());
+
+                    blockchain::commands::seed_remove(&mut term, root_dir, name, endpoint)
+                }
+                ("list", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+
+                    blockchain::commands::seed_list(&mut term, root_dir, name)
+                }
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            };
+            res.unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("proxy", Some(matches)) => {
+            let res = match matches.subcommand() {
+                ("set", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+                    let address =
+                        value_t!(matches, "PROXY_ADDRESS", String).unwrap_or_else(|e| e.exit());
+
+                    blockchain::commands::proxy_set(&mut term, root_dir, name, address)
+                }
+                ("unset", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&mut term, &matches);
+
+                    blockchain::commands::proxy_unset(&mut term, root_dir, name)
+                }
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            };
+            res.unwrap_or_else(|e| term.fail_with(e));
+        }
         ("forward", Some(matches)) => {
             let name = blockchain_argument_name_match(&mut term, &matches);
             let opt_hash =
@@ -370,9 +760,19 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
                 .unwrap_or_else(|e| term.fail_with(e));
         }
         ("pull", Some(matches)) => {
+            let name = blockchain_argument_name_or_default_match(&mut term, profile, &matches);
+            let jobs = value_t!(matches, "JOBS", usize).unwrap_or_else(|e| e.exit());
+            let proxy = blockchain_argument_proxy_match(&matches);
+            let clock_check = clock_check_argument_match(profile, &matches);
+
+            blockchain::commands::pull(&mut term, root_dir, name, jobs, proxy, &clock_check)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("prune", Some(matches)) => {
             let name = blockchain_argument_name_match(&mut term, &matches);
+            let keep_epochs = value_t!(matches, "KEEP_EPOCHS", u64).unwrap_or_else(|e| e.exit());
 
-            blockchain::commands::pull(&mut term, root_dir, name)
+            blockchain::commands::prune(&mut term, root_dir, name, keep_epochs)
                 .unwrap_or_else(|e| term.fail_with(e));
         }
         ("cat", Some(matches)) => {
@@ -400,8 +800,15 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
             )
             .unwrap_or_else(|e| term.fail_with(e));
         }
+        ("set-fee", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+            let fee = value_t!(matches, "TRANSACTION_FEE", cardano::coin::Coin)
+                .unwrap_or_else(|e| e.exit());
+
+            transaction::commands::set_fee(&mut term, root_dir, id, fee)
+        }
         ("status", Some(matches)) => {
-            let name = blockchain_argument_name_match(&mut term, &matches);
+            let name = blockchain_argument_name_or_default_match(&mut term, profile, &matches);
 
             blockchain::commands::status(&mut term, root_dir, name)
                 .unwrap_or_else(|e| term.fail_with(e));
@@ -427,10 +834,11 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
                 .unwrap_or_else(|e| term.fail_with(e));
         }
         ("verify", Some(matches)) => {
-            let name = blockchain_argument_name_match(&mut term, &matches);
+            let name = blockchain_argument_name_or_default_match(&mut term, profile, &matches);
             let stop_on_error = matches.is_present("STOP_FIRST_ERROR");
+            let repair = matches.is_present("VERIFY_REPAIR");
 
-            blockchain::commands::verify_chain(&mut term, root_dir, name, stop_on_error)
+            blockchain::commands::verify_chain(&mut term, root_dir, name, stop_on_error, repair)
                 .unwrap_or_else(|e| term.fail_with(e));
         }
         ("query", Some(matches)) => {
@@ -440,6 +848,66 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
             blockchain::commands::query(&mut term, root_dir, name, params)
                 .unwrap_or_else(|e| term.fail_with(e));
         }
+        ("block", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let query = value_t!(matches, "BLOCK_QUERY", blockchain::explorer::BlockQuery)
+                .unwrap_or_else(|e| e.exit());
+            let json = matches.is_present("OUTPUT_JSON");
+
+            blockchain::commands::block(&mut term, root_dir, name, query, json)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("tx", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let txid = value_t!(matches, "TRANSACTION_TXID", cardano::tx::TxId)
+                .unwrap_or_else(|e| e.exit());
+            let json = matches.is_present("OUTPUT_JSON");
+
+            blockchain::commands::tx(&mut term, root_dir, name, txid, json)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("export-blocks", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let from = blockchain_argument_blockdate_match(&mut term, matches, "EXPORT_FROM_DATE");
+            let to = blockchain_argument_opt_blockdate_match(&mut term, matches, "EXPORT_TO_DATE");
+            let output = value_t!(matches, "EXPORT_FILE", PathBuf).unwrap_or_else(|e| e.exit());
+
+            blockchain::commands::export_blocks(&mut term, root_dir, name, from, to, output)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("import-blocks", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let input = value_t!(matches, "IMPORT_FILE", PathBuf).unwrap_or_else(|e| e.exit());
+
+            blockchain::commands::import_blocks(&mut term, root_dir, name, input)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("sync", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let from_root_dir =
+                value_t!(matches, "SYNC_FROM_LOCAL", PathBuf).unwrap_or_else(|e| e.exit());
+            let from_name = if matches.is_present("SYNC_FROM_BLOCKCHAIN") {
+                Some(
+                    value_t!(matches, "SYNC_FROM_BLOCKCHAIN", blockchain::BlockchainName)
+                        .unwrap_or_else(|e| term.fail_with(e)),
+                )
+            } else {
+                None
+            };
+            let offline = matches.is_present("SYNC_OFFLINE");
+            let proxy = blockchain_argument_proxy_match(&matches);
+
+            blockchain::commands::sync_from_local(
+                &mut term,
+                root_dir,
+                name,
+                from_root_dir,
+                from_name,
+                offline,
+                proxy,
+            )
+            .unwrap_or_else(|e| term.fail_with(e));
+        }
         _ => {
             term.error(matches.usage()).unwrap();
             ::std::process::exit(1)
@@ -462,6 +930,7 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("new")
             .about("create a new local blockchain")
             .arg(blockchain_argument_template_definition())
+            .args(&blockchain_argument_genesis_definitions())
             .arg(blockchain_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("remote-add")
@@ -505,6 +974,73 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .help("print all local known information regarding the remotes as well as the details from the remote (needs a network connection)")
             )
         )
+        .subcommand(SubCommand::with_name("peer")
+            .about("manage the peers of the blockchain, they are tried the best ones first and skipped when unreachable")
+            .subcommand(SubCommand::with_name("add")
+                .about("add a peer to the blockchain (same as `remote-add')")
+                .arg(blockchain_argument_name_definition())
+                .arg(blockchain_argument_remote_alias_definition())
+                .arg(blockchain_argument_remote_endpoint_definition())
+            )
+            .subcommand(SubCommand::with_name("remove")
+                .about("remove a peer from the blockchain (same as `remote-rm')")
+                .arg(blockchain_argument_name_definition())
+                .arg(blockchain_argument_remote_alias_definition())
+            )
+            .subcommand(SubCommand::with_name("list")
+                .about("list the peers of the blockchain in the order they are tried, with their availability and latency")
+                .arg(blockchain_argument_name_definition())
+            )
+            .subcommand(SubCommand::with_name("test")
+                .about("connect to the peers (optionally specified by the aliases), query their tip and record their availability and latency")
+                .arg(blockchain_argument_name_definition())
+                .arg(blockchain_argument_remote_alias_definition()
+                    .multiple(true)
+                    .required(false)
+                )
+            )
+        )
+        .subcommand(SubCommand::with_name("seed")
+            .about("manage the DNS seeds of the blockchain, resolved at connection time to a set of native peers")
+            .subcommand(SubCommand::with_name("add")
+                .about("add a DNS seed to the blockchain")
+                .arg(blockchain_argument_name_definition())
+                .arg(Arg::with_name("SEED_ENDPOINT")
+                    .value_name("HOST:PORT")
+                    .required(true)
+                    .help("the domain name and the port of the relays, e.g. `relays.cardano-mainnet.iohk.io:3000'")
+                )
+            )
+            .subcommand(SubCommand::with_name("remove")
+                .about("remove a DNS seed from the blockchain")
+                .arg(blockchain_argument_name_definition())
+                .arg(Arg::with_name("SEED_ENDPOINT")
+                    .value_name("HOST:PORT")
+                    .required(true)
+                    .help("the DNS seed, as displayed by `seed list'")
+                )
+            )
+            .subcommand(SubCommand::with_name("list")
+                .about("list the DNS seeds of the blockchain and the addresses they last resolved to")
+                .arg(blockchain_argument_name_definition())
+            )
+        )
+        .subcommand(SubCommand::with_name("proxy")
+            .about("manage the SOCKS5 proxy the connections to the peers of the blockchain go through")
+            .subcommand(SubCommand::with_name("set")
+                .about("connect to the peers of the blockchain through a SOCKS5 proxy")
+                .arg(blockchain_argument_name_definition())
+                .arg(Arg::with_name("PROXY_ADDRESS")
+                    .value_name("HOST:PORT")
+                    .required(true)
+                    .help("the host and the port of the SOCKS5 proxy, e.g. Tor's `127.0.0.1:9050'")
+                )
+            )
+            .subcommand(SubCommand::with_name("unset")
+                .about("connect directly to the peers of the blockchain")
+                .arg(blockchain_argument_name_definition())
+            )
+        )
         .subcommand(SubCommand::with_name("forward")
             .about("Forward the local tip to what seems to be the consensus within the remote blocks. This function must be used combined with `remote-fetch'.")
             .arg(blockchain_argument_name_definition())
@@ -516,12 +1052,33 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(SubCommand::with_name("pull")
             .about("handy command to `remote-fetch' and `forward' the local blockchain.")
-            .arg(blockchain_argument_name_definition())
+            .arg(blockchain_argument_default_name_definition())
+            .arg(Arg::with_name("JOBS")
+                .long("jobs")
+                .short("j")
+                .takes_value(true)
+                .value_name("N")
+                .default_value("1")
+                .help("number of stable epochs to download concurrently from the HTTP peers")
+            )
+            .arg(blockchain_argument_proxy_definition())
+            .arg(clock_check_argument_definition())
         )
         .subcommand(SubCommand::with_name("gc")
             .about("run garbage collection of lose blocks. This function might be a bit slow to run but it will free some disk space.")
             .arg(blockchain_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("prune")
+            .about("remove the old epochs' blocks from the local storage, keeping only the most recent epochs. The attached wallets need to be synced first.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("KEEP_EPOCHS")
+                .long("keep-epochs")
+                .takes_value(true)
+                .value_name("N")
+                .required(true)
+                .help("number of epochs, before the local tip, to keep")
+            )
+        )
         .subcommand(SubCommand::with_name("cat")
             .about("print the content of a block.")
             .arg(blockchain_argument_name_definition())
@@ -547,7 +1104,7 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(SubCommand::with_name("status")
             .about("print some details about the given blockchain")
-            .arg(blockchain_argument_name_definition())
+            .arg(blockchain_argument_default_name_definition())
         )
         .subcommand(SubCommand::with_name("destroy")
             .about("destroy the given blockchain, deleting all the blocks downloaded from the disk.")
@@ -572,13 +1129,17 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
             )
         )
         .subcommand(SubCommand::with_name("verify")
-            .about("verify all blocks in the chain")
-            .arg(blockchain_argument_name_definition())
+            .about("verify the integrity of the epoch packs and all blocks in the chain")
+            .arg(blockchain_argument_default_name_definition())
             .arg(Arg::with_name("STOP_FIRST_ERROR")
                 .required(false)
                 .short("werror")
                 .help("stop at the first error it found")
             )
+            .arg(Arg::with_name("VERIFY_REPAIR")
+                .long("repair")
+                .help("download again, from the HTTP peers, the epoch packs found damaged")
+            )
         )
         .subcommand(SubCommand::with_name("query")
             .about("query blocks on the chain")
@@ -596,6 +1157,84 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .help("Block date to end at (defaults to the local tip).")
             )
         )
+        .subcommand(SubCommand::with_name("block")
+            .about("print the transactions of a block, with their inputs, outputs and fees")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("BLOCK_QUERY")
+                .value_name("HASH|BLOCKDATE")
+                .required(true)
+                .help("the hash or the date (e.g. `12.345') of the block")
+            )
+            .arg(Arg::with_name("OUTPUT_JSON")
+                .long("json")
+                .help("print the block in JSON")
+            )
+        )
+        .subcommand(SubCommand::with_name("tx")
+            .about("look for a transaction in the local blockchain and print its inputs, outputs and fee")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("TRANSACTION_TXID")
+                .value_name("TXID")
+                .required(true)
+                .help("the identifier of the transaction, in hexadecimal")
+            )
+            .arg(Arg::with_name("OUTPUT_JSON")
+                .long("json")
+                .help("print the transaction in JSON")
+            )
+        )
+        .subcommand(SubCommand::with_name("export-blocks")
+            .about("write a range of blocks of the local blockchain to a file, to import them on a machine without network connection")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("EXPORT_FROM_DATE")
+                .long("from")
+                .value_name("BLOCKDATE")
+                .required(true)
+                .help("Block date of the first block to export.")
+            )
+            .arg(Arg::with_name("EXPORT_TO_DATE")
+                .long("to")
+                .value_name("BLOCKDATE")
+                .required(false)
+                .help("Block date of the last block to export (defaults to the local tip).")
+            )
+            .arg(Arg::with_name("EXPORT_FILE")
+                .long("out")
+                .value_name("FILE")
+                .required(true)
+                .help("the file to write the blocks to")
+            )
+        )
+        .subcommand(SubCommand::with_name("import-blocks")
+            .about("add the blocks of a file written by `export-blocks' to the local blockchain and forward the local tip to them")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("IMPORT_FILE")
+                .value_name("FILE")
+                .required(true)
+                .help("the file to read the blocks from")
+            )
+        )
+        .subcommand(SubCommand::with_name("sync")
+            .about("copy the missing blocks from the same blockchain in another cardano-cli root directory (e.g. on a USB drive), then fetch the remaining blocks from the peers")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("SYNC_FROM_LOCAL")
+                .long("from-local")
+                .value_name("ROOT_DIR")
+                .required(true)
+                .help("the root directory of the other cardano-cli, it is only read")
+            )
+            .arg(Arg::with_name("SYNC_FROM_BLOCKCHAIN")
+                .long("from-blockchain")
+                .value_name("NAME")
+                .required(false)
+                .help("the name of the blockchain in the other root directory (defaults to the same name)")
+            )
+            .arg(Arg::with_name("SYNC_OFFLINE")
+                .long("offline")
+                .help("only copy the blocks, do not fetch the remaining blocks from the peers")
+            )
+            .arg(blockchain_argument_proxy_definition())
+        )
 }
 
 /* ------------------------------------------------------------------------- *
@@ -614,6 +1253,30 @@ fn wallet_argument_name_match<'a>(matches: &ArgMatches<'a>) -> wallet::WalletNam
         None => unreachable!(),
     }
 }
+fn wallet_argument_named_match<'a>(matches: &ArgMatches<'a>, name: &str) -> wallet::WalletName {
+    match matches.value_of(name) {
+        Some(r) => wallet::WalletName::new(r.to_owned())
+            .expect("Wallet name is invalid. cannot contains . and /"),
+        None => unreachable!(),
+    }
+}
+/// the protocol magic given to `--protocol-magic`, or the one of the profile
+fn protocol_magic_argument_match<'a>(
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) -> Option<u32> {
+    if matches.is_present("PROTOCOL_MAGIC") {
+        Some(value_t!(matches, "PROTOCOL_MAGIC", u32).unwrap_or_else(|e| e.exit()))
+    } else {
+        profile.protocol_magic
+    }
+}
+fn wallet_argument_txin_match<'a>(matches: &ArgMatches<'a>) -> cardano::tx::TxoPointer {
+    match transaction_argument_txin_match(matches) {
+        Some((id, index)) => cardano::tx::TxoPointer { id, index },
+        None => unreachable!(),
+    }
+}
 fn wallet_argument_wallet_scheme<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("WALLET_SCHEME")
         .help("the scheme to organize accounts and addresses in a Wallet.")
@@ -739,7 +1402,12 @@ fn wallet_argument_daedalus_seed_match<'a>(matches: &ArgMatches<'a>) -> bool {
 
 const WALLET_COMMAND: &'static str = "wallet";
 
-fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_wallet<'a>(
+    mut term: term::Term,
+    root_dir: PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) {
     let res = match matches.subcommand() {
         ("create", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -765,9 +1433,15 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
             let mut mnemonic_length = wallet_argument_mnemonic_size_match(&matches);
             let mnemonic_lang = wallet_argument_mnemonic_language_match(&matches);
             let daedalus_seed = wallet_argument_daedalus_seed_match(&matches);
+            let paper_wallet = matches.is_present("RECOVER_PAPER_WALLET");
             let interactive = matches.is_present("RECOVER_INTERACTIVE");
 
-            if daedalus_seed {
+            if paper_wallet {
+                // the paper wallet certificates are only for Daedalus' wallets
+                wallet_scheme = wallet::HDWalletModel::RandomIndex2Levels;
+                derivation_scheme = cardano::hdwallet::DerivationScheme::V1;
+                mnemonic_length = cardano::bip::bip39::Type::Type12Words;
+            } else if daedalus_seed {
                 if wallet_scheme != wallet::HDWalletModel::RandomIndex2Levels {
                     term.warn("Daedalus wallet are usually using `--wallet-scheme=random_index_2levels'\n").unwrap();
                 }
@@ -790,6 +1464,7 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
                 mnemonic_length,
                 interactive,
                 daedalus_seed,
+                paper_wallet,
                 mnemonic_lang,
             )
         }
@@ -797,11 +1472,7 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
             let name = wallet_argument_name_match(&matches);
             let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
             let index = value_t!(matches, "ADDRESS_INDEX", u32).unwrap_or_else(|e| e.exit());
-            let protocol_magic = if matches.is_present("PROTOCOL_MAGIC") {
-                Some(value_t!(matches, "PROTOCOL_MAGIC", u32).unwrap_or_else(|e| e.exit()))
-            } else {
-                None
-            };
+            let protocol_magic = protocol_magic_argument_match(profile, &matches);
             let is_internal = matches.is_present("INTERNAL_ADDRESS");
 
             wallet::commands::address(
@@ -812,23 +1483,152 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
                 account,
                 is_internal,
                 index,
+                qr_argument_match(&matches),
             )
         }
-        ("attach", Some(matches)) => {
+        ("payment-request", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
-            let blockchain = blockchain_argument_name_match(&mut term, &matches);
-
-            wallet::commands::attach(&mut term, root_dir, name, blockchain)
+            let protocol_magic = protocol_magic_argument_match(profile, &matches);
+            let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let index = value_t!(matches, "ADDRESS_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let amount = if matches.is_present("PAYMENT_AMOUNT") {
+                Some(
+                    value_t!(matches, "PAYMENT_AMOUNT", cardano::coin::Coin)
+                        .unwrap_or_else(|e| e.exit()),
+                )
+            } else {
+                None
+            };
+            let label = matches
+                .value_of("PAYMENT_LABEL")
+                .map(|label| label.to_owned());
+            let message = matches
+                .value_of("PAYMENT_MESSAGE")
+                .map(|message| message.to_owned());
+
+            wallet::commands::payment_request(
+                &mut term,
+                root_dir,
+                name,
+                protocol_magic,
+                account,
+                index,
+                amount,
+                label,
+                message,
+                qr_argument_match(&matches),
+            )
         }
-        ("detach", Some(matches)) => {
+        ("sign-message", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let protocol_magic = protocol_magic_argument_match(profile, &matches);
+            let address =
+                address_argument_match(&mut term, &root_dir, &matches, "MESSAGE_ADDRESS").unwrap();
+            let message = value_t_or_exit!(matches.value_of("MESSAGE"), String);
 
-            wallet::commands::detach(&mut term, root_dir, name)
-        }
-        ("sync", Some(matches)) => {
+            wallet::commands::sign_message(
+                &mut term,
+                root_dir,
+                name,
+                protocol_magic,
+                address,
+                message,
+            )
+        }
+        ("owns-address", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let protocol_magic = protocol_magic_argument_match(profile, &matches);
+            let address =
+                address_argument_match(&mut term, &root_dir, &matches, "OWNED_ADDRESS").unwrap();
+            let report = if matches.is_present("OWNS_JSON") {
+                wallet::commands::OwnershipReport::Json
+            } else if matches.is_present("OWNS_EXIT_CODE") {
+                wallet::commands::OwnershipReport::ExitCode
+            } else {
+                wallet::commands::OwnershipReport::Text
+            };
 
-            wallet::commands::sync(&mut term, root_dir, name)
+            match wallet::commands::owns_address(
+                &mut term,
+                root_dir,
+                name,
+                protocol_magic,
+                address,
+                report,
+            ) {
+                Ok(true) => Ok(()),
+                Ok(false) => ::std::process::exit(wallet::commands::NOT_OWNED_EXIT_CODE),
+                Err(err) => Err(err),
+            }
+        }
+        ("verify-message", Some(matches)) => {
+            let address =
+                address_argument_match(&mut term, &root_dir, &matches, "MESSAGE_ADDRESS").unwrap();
+            let message = value_t_or_exit!(matches.value_of("MESSAGE"), String);
+            let signature = value_t_or_exit!(matches.value_of("MESSAGE_SIGNATURE"), String);
+
+            wallet::commands::verify_message(&mut term, address, message, signature)
+        }
+        ("attach", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let blockchain =
+                blockchain_argument_name_or_default_match(&mut term, profile, &matches);
+
+            wallet::commands::attach(&mut term, root_dir, name, blockchain)
+        }
+        ("detach", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+
+            wallet::commands::detach(&mut term, root_dir, name)
+        }
+        ("sync", Some(matches)) => {
+            // no names for all the attached wallets
+            let names = if matches.is_present("SYNC_ALL") {
+                Vec::new()
+            } else {
+                values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit())
+            };
+
+            wallet::commands::sync(&mut term, root_dir, names)
+        }
+        ("history", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let mut filter = wallet::history::Filter::default();
+            filter.address =
+                address_argument_match(&mut term, &root_dir, &matches, "HISTORY_ADDRESS");
+            if matches.is_present("HISTORY_ACCOUNT") {
+                filter.account = Some(value_t_or_exit!(matches.value_of("HISTORY_ACCOUNT"), u32));
+            }
+            let csv = matches.value_of("HISTORY_CSV").map(PathBuf::from);
+
+            wallet::commands::history(&mut term, root_dir, name, filter, csv)
+        }
+        ("reindex", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+
+            wallet::commands::reindex(&mut term, root_dir, name)
+        }
+        ("rescan", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let from_slot = if matches.is_present("RESCAN_FROM_SLOT") {
+                Some(value_t_or_exit!(matches.value_of("RESCAN_FROM_SLOT"), u64))
+            } else {
+                None
+            };
+
+            wallet::commands::rescan(&mut term, root_dir, name, from_slot)
+        }
+        ("migrate", Some(matches)) => {
+            let old = wallet_argument_named_match(&matches, "MIGRATE_OLD_WALLET");
+            let new = wallet_argument_named_match(&matches, "MIGRATE_NEW_WALLET");
+            let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let index = value_t!(matches, "ADDRESS_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let fee_policy = profile.fee_algorithm();
+            let dry_run = matches.is_present("MIGRATE_DRY_RUN");
+
+            wallet::commands::migrate(
+                &mut term, root_dir, old, new, account, index, fee_policy, dry_run,
+            )
         }
         ("status", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -842,19 +1642,133 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
         }
         ("utxos", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let address = address_argument_match(&mut term, &root_dir, &matches, "UTXOS_ADDRESS");
 
-            wallet::commands::utxos(&mut term, root_dir, name)
+            wallet::commands::utxos(&mut term, root_dir, name, address)
         }
+        ("utxo", Some(matches)) => match matches.subcommand() {
+            ("lease", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let txin = wallet_argument_txin_match(&matches);
+                let ttl = value_t_or_exit!(matches.value_of("LEASE_TTL"), u64);
+                let ttl = ::std::time::Duration::from_secs(ttl);
+                let owner = value_t_or_exit!(matches.value_of("LEASE_OWNER"), String);
+
+                wallet::commands::utxo_lease(&mut term, root_dir, name, txin, ttl, owner)
+            }
+            ("release", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let txin = wallet_argument_txin_match(&matches);
+                let owner = value_t_or_exit!(matches.value_of("LEASE_OWNER"), String);
+
+                wallet::commands::utxo_release(&mut term, root_dir, name, txin, owner)
+            }
+            ("leases", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+
+                wallet::commands::utxo_leases(&mut term, root_dir, name)
+            }
+            _ => {
+                term.error(matches.usage()).unwrap();
+                ::std::process::exit(1)
+            }
+        },
         ("statement", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
             wallet::commands::log(&mut term, root_dir, name, true)
         }
+        ("backup", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let output = value_t_or_exit!(matches.value_of("BACKUP_OUTPUT"), PathBuf);
+
+            wallet::commands::backup(&mut term, root_dir, name, output)
+        }
+        ("restore-backup", Some(matches)) => {
+            let input = value_t_or_exit!(matches.value_of("BACKUP_INPUT"), PathBuf);
+            let name = matches
+                .value_of("WALLET_NAME")
+                .map(|_| wallet_argument_name_match(&matches));
+
+            wallet::commands::restore_backup(&mut term, root_dir, input, name)
+        }
+        ("change-passphrase", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+
+            wallet::commands::change_password(&mut term, root_dir, name)
+        }
         ("destroy", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
             wallet::commands::destroy(&mut term, root_dir, name)
         }
+        ("alarm", Some(matches)) => match matches.subcommand() {
+            ("add", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let alarm = if matches.is_present("ALARM_BALANCE_BELOW") {
+                    wallet::alarm::Alarm::BalanceBelow(value_t_or_exit!(
+                        matches.value_of("ALARM_BALANCE_BELOW"),
+                        u64
+                    ))
+                } else if matches.is_present("ALARM_NO_INCOMING_FOR") {
+                    wallet::alarm::Alarm::NoIncomingFunds(value_t_or_exit!(
+                        matches.value_of("ALARM_NO_INCOMING_FOR"),
+                        u64
+                    ))
+                } else {
+                    wallet::alarm::Alarm::OutgoingTransaction
+                };
+
+                wallet::commands::alarm_add(&mut term, root_dir, name, alarm)
+            }
+            ("list", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+
+                wallet::commands::alarm_list(&mut term, root_dir, name)
+            }
+            ("remove", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let index = value_t_or_exit!(matches.value_of("ALARM_INDEX"), usize);
+
+                wallet::commands::alarm_remove(&mut term, root_dir, name, index)
+            }
+            _ => {
+                term.error(matches.usage()).unwrap();
+                ::std::process::exit(1)
+            }
+        },
+        ("config", Some(matches)) => match matches.subcommand() {
+            ("get", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let setting = if matches.is_present("CONFIG_SETTING") {
+                    Some(value_t_or_exit!(
+                        matches.value_of("CONFIG_SETTING"),
+                        wallet::Setting
+                    ))
+                } else {
+                    None
+                };
+
+                wallet::commands::config_get(&mut term, root_dir, name, setting)
+            }
+            ("set", Some(matches)) => {
+                let name = wallet_argument_name_match(&matches);
+                let setting = value_t_or_exit!(matches.value_of("CONFIG_SETTING"), wallet::Setting);
+                let value = value_t_or_exit!(matches.value_of("CONFIG_VALUE"), String);
+                let rescan = !matches.is_present("CONFIG_NO_RESCAN");
+
+                wallet::commands::config_set(&mut term, root_dir, name, setting, value, rescan)
+            }
+            _ => {
+                term.error(matches.usage()).unwrap();
+                ::std::process::exit(1)
+            }
+        },
+        ("portfolio", Some(matches)) => {
+            let json = matches.is_present("PORTFOLIO_JSON");
+
+            wallet::commands::portfolio(&mut term, root_dir, json)
+        }
         ("list", Some(matches)) => {
             let detailed = matches.is_present("WALLET_LIST_DETAILED");
 
@@ -878,6 +1792,13 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .help("display some metadata information of the wallet")
             )
         )
+        .subcommand(SubCommand::with_name("portfolio")
+            .about("print the balances of all the wallets, with the funds being spent and their last activity")
+            .arg(Arg::with_name("PORTFOLIO_JSON")
+                .help("print the portfolio in JSON")
+                .long("json")
+            )
+        )
         .subcommand(SubCommand::with_name("create")
             .about("create a new wallet")
             .arg(wallet_argument_mnemonic_size())
@@ -899,6 +1820,36 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .long("interactive")
                 .short("i")
             )
+            .arg(Arg::with_name("RECOVER_PAPER_WALLET")
+                .help("recover a Daedalus wallet from the 27 words of its paper wallet certificate")
+                .long("paper-wallet")
+            )
+        )
+        .subcommand(SubCommand::with_name("backup")
+            .about("write the wallet (keys, configuration and state) to a single encrypted file")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("BACKUP_OUTPUT")
+                .help("the backup file to write")
+                .long("out")
+                .value_name("FILE")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("restore-backup")
+            .about("recreate a wallet from its backup, without rescanning the blockchain")
+            .arg(Arg::with_name("BACKUP_INPUT")
+                .help("the backup file, as written by `wallet backup'")
+                .value_name("FILE")
+                .required(true)
+            )
+            .arg(wallet_argument_name_definition()
+                .required(false)
+                .help("the name of the restored wallet, the name of the backed up wallet by default")
+            )
+        )
+        .subcommand(SubCommand::with_name("change-passphrase")
+            .about("change the spending password of the wallet, the mnemonics are not needed")
+            .arg(wallet_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("destroy")
             .about("delete all data associated to the given wallet.")
@@ -911,18 +1862,118 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(Arg::with_name("ACCOUNT_INDEX").required(true))
             .arg(Arg::with_name("ADDRESS_INDEX").required(true))
             .arg(Arg::with_name("INTERNAL_ADDRESS").long("internal"))
+            .args(&qr_argument_definitions())
+        )
+        .subcommand(SubCommand::with_name("payment-request")
+            .about("print a payment URI of one of the wallet's addresses, to be given to the payer (see `transaction add-output')")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("PROTOCOL_MAGIC")
+                .long("protocol-magic")
+                .takes_value(true)
+                .value_name("MAGIC")
+                .help("Optional parameter, only required if the wallet is not attached.")
+            )
+            .arg(Arg::with_name("ACCOUNT_INDEX")
+                .long("account")
+                .takes_value(true)
+                .value_name("INDEX")
+                .default_value("0")
+                .help("the account of the address")
+            )
+            .arg(Arg::with_name("ADDRESS_INDEX")
+                .long("index")
+                .takes_value(true)
+                .value_name("INDEX")
+                .default_value("0")
+                .help("the index of the address in the account, give a new one for every payment to tell them apart")
+            )
+            .arg(Arg::with_name("PAYMENT_AMOUNT")
+                .long("amount")
+                .takes_value(true)
+                .value_name("LOVELACE")
+                .help("the amount requested, in lovelace")
+            )
+            .arg(Arg::with_name("PAYMENT_LABEL")
+                .long("label")
+                .takes_value(true)
+                .value_name("LABEL")
+                .help("the name of the payee")
+            )
+            .arg(Arg::with_name("PAYMENT_MESSAGE")
+                .long("message")
+                .takes_value(true)
+                .value_name("MESSAGE")
+                .help("a message for the payer, e.g. the order number")
+            )
+            .args(&qr_argument_definitions())
+        )
+        .subcommand(SubCommand::with_name("sign-message")
+            .about("sign a message with the key of one of the wallet's addresses, to prove the control of the address")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("PROTOCOL_MAGIC")
+                .long("protocol-magic")
+                .takes_value(true)
+                .value_name("MAGIC")
+                .help("Optional parameter, only required if the wallet is not attached.")
+            )
+            .arg(Arg::with_name("MESSAGE_ADDRESS")
+                .help("the address of the wallet to sign the message for (or @LABEL of the address book)")
+                .required(true)
+            )
+            .arg(Arg::with_name("MESSAGE")
+                .help("the message to sign")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("owns-address")
+            .about("tell whether the address is one of the wallet's, and its derivation path")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("PROTOCOL_MAGIC")
+                .long("protocol-magic")
+                .takes_value(true)
+                .value_name("MAGIC")
+                .help("Optional parameter, only required if the wallet is not attached.")
+            )
+            .arg(Arg::with_name("OWNED_ADDRESS")
+                .help("the address to look for in the wallet (or @LABEL of the address book)")
+                .required(true)
+            )
+            .arg(Arg::with_name("OWNS_JSON")
+                .help("print the result in JSON")
+                .long("json")
+            )
+            .arg(Arg::with_name("OWNS_EXIT_CODE")
+                .help("print nothing, only exit with 0 if the address is the wallet's, 2 if it is not")
+                .long("exit-code")
+                .conflicts_with("OWNS_JSON")
+            )
+        )
+        .subcommand(SubCommand::with_name("verify-message")
+            .about("check a message was signed with the key of the address, as with `wallet sign-message'")
+            .arg(Arg::with_name("MESSAGE_ADDRESS")
+                .help("the address the message was signed for (or @LABEL of the address book)")
+                .required(true)
+            )
+            .arg(Arg::with_name("MESSAGE")
+                .help("the signed message")
+                .required(true)
+            )
+            .arg(Arg::with_name("MESSAGE_SIGNATURE")
+                .help("the signature, as printed by `wallet sign-message'")
+                .required(true)
+            )
         )
         .subcommand(SubCommand::with_name("attach")
             .about("Attach the existing wallet to the existing local blockchain. Detach first to attach to an other blockchain.")
             .arg(wallet_argument_name_definition())
-            .arg(blockchain_argument_name_definition())
+            .arg(blockchain_argument_default_name_definition())
         )
         .subcommand(SubCommand::with_name("detach")
             .about("detach the wallet from its associated blockchain")
             .arg(wallet_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("sync")
-            .about("synchronize the wallet with the attached blockchain")
+            .about("synchronize the wallets with their attached blockchain, the wallets of the same blockchain together")
             .arg(Arg::with_name("DRY_RUN")
                 .help("perform the sync without storing the updated states.")
                 .long("dry-run")
@@ -933,8 +1984,83 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .value_name("HASH")
                 .takes_value(true)
             )
+            .arg(Arg::with_name("SYNC_ALL")
+                .help("synchronize all the wallets attached to a blockchain")
+                .long("all")
+                .conflicts_with("WALLET_NAME")
+            )
+            .arg(wallet_argument_name_definition()
+                .multiple(true)
+                .required_unless("SYNC_ALL")
+            )
+        )
+        .subcommand(SubCommand::with_name("history")
+            .about("list the outputs credited to and spent by the wallet, with the transactions' counterparties and the running balance")
+            .arg(Arg::with_name("HISTORY_ADDRESS")
+                .help("only list the outputs of this address (or `@LABEL' of the address book)")
+                .long("address")
+                .value_name("ADDRESS")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("HISTORY_ACCOUNT")
+                .help("only list the outputs of this account (BIP44 wallets)")
+                .long("account")
+                .value_name("ACCOUNT")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("HISTORY_CSV")
+                .help("write the history to this CSV file instead")
+                .long("csv")
+                .value_name("FILE")
+                .takes_value(true)
+            )
+            .arg(wallet_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name("reindex")
+            .about("rebuild the state snapshot and the UTxO index of the wallet from its log")
+            .arg(wallet_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name("rescan")
+            .about("rebuild the wallet's state from the blocks stored locally, resuming the interrupted rescan if any")
+            .arg(Arg::with_name("RESCAN_FROM_SLOT")
+                .help("only rescan the blocks from this slot (counted from the genesis) onwards, e.g. after a rollback")
+                .long("from-slot")
+                .value_name("SLOT")
+                .takes_value(true)
+            )
             .arg(wallet_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("migrate")
+            .about("stage the transactions sweeping all the funds of a random index (Daedalus) wallet to a BIP44 (Icarus) wallet")
+            .arg(Arg::with_name("MIGRATE_OLD_WALLET")
+                .value_name("OLD_WALLET")
+                .required(true)
+                .help("the random index wallet to sweep, attached and synced")
+            )
+            .arg(Arg::with_name("MIGRATE_NEW_WALLET")
+                .value_name("NEW_WALLET")
+                .required(true)
+                .help("the BIP44 wallet receiving the funds")
+            )
+            .arg(Arg::with_name("ACCOUNT_INDEX")
+                .long("account")
+                .takes_value(true)
+                .value_name("INDEX")
+                .default_value("0")
+                .help("the account of the new wallet's address receiving the funds")
+            )
+            .arg(Arg::with_name("ADDRESS_INDEX")
+                .long("index")
+                .takes_value(true)
+                .value_name("INDEX")
+                .default_value("0")
+                .help("the index of the new wallet's address receiving the funds")
+            )
+            .arg(Arg::with_name("MIGRATE_DRY_RUN")
+                .long("dry-run")
+                .help("only report the sweep transactions and their fees, without staging them")
+            )
+        )
         .subcommand(SubCommand::with_name("status")
             .about("print some status information from the given wallet (funds, transactions...)")
             .arg(wallet_argument_name_definition())
@@ -949,8 +2075,118 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(SubCommand::with_name("utxos")
             .about("print the wallet's available funds")
+            .arg(Arg::with_name("UTXOS_ADDRESS")
+                .help("only print the funds sent to this address (or @LABEL of the address book)")
+                .long("address")
+                .value_name("ADDRESS")
+                .takes_value(true)
+            )
             .arg(wallet_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("utxo")
+            .about("lease the wallet's unspent outputs, to coordinate several instances of the CLI using the same wallet")
+            .subcommand(SubCommand::with_name("lease")
+                .about("reserve an unspent output for the given owner, `transaction input-select' does not select it for the other owners until the lease expires")
+                .arg(wallet_argument_name_definition())
+                .arg(transaction_argument_txid_definition().required(true))
+                .arg(transaction_argument_index_definition().required(true))
+                .arg(Arg::with_name("LEASE_TTL")
+                    .long("ttl")
+                    .value_name("SECS")
+                    .required(true)
+                    .help("duration of the lease in seconds")
+                )
+                .arg(Arg::with_name("LEASE_OWNER")
+                    .long("owner")
+                    .value_name("ID")
+                    .required(true)
+                    .help("identifier of the owner of the lease, only the owner can renew or release it")
+                )
+            )
+            .subcommand(SubCommand::with_name("release")
+                .about("release the lease of an unspent output before it expires")
+                .arg(wallet_argument_name_definition())
+                .arg(transaction_argument_txid_definition().required(true))
+                .arg(transaction_argument_index_definition().required(true))
+                .arg(Arg::with_name("LEASE_OWNER")
+                    .long("owner")
+                    .value_name("ID")
+                    .required(true)
+                    .help("identifier of the owner of the lease")
+                )
+            )
+            .subcommand(SubCommand::with_name("leases")
+                .about("list the active leases of the wallet")
+                .arg(wallet_argument_name_definition())
+            )
+        )
+        .subcommand(SubCommand::with_name("alarm")
+            .about("manage the alarms evaluated when the wallet is synchronized")
+            .subcommand(SubCommand::with_name("add")
+                .about("add an alarm to the wallet")
+                .arg(wallet_argument_name_definition())
+                .arg(Arg::with_name("ALARM_BALANCE_BELOW")
+                    .help("alert if the balance of the wallet drops below the given value (in lovelace)")
+                    .long("balance-below")
+                    .value_name("LOVELACE")
+                    .takes_value(true)
+                )
+                .arg(Arg::with_name("ALARM_NO_INCOMING_FOR")
+                    .help("alert if the wallet did not receive any funds for the given number of days")
+                    .long("no-incoming-for")
+                    .value_name("DAYS")
+                    .takes_value(true)
+                )
+                .arg(Arg::with_name("ALARM_ON_OUTGOING")
+                    .help("alert on any outgoing transaction")
+                    .long("on-outgoing")
+                )
+                .group(ArgGroup::with_name("ALARM")
+                    .args(&["ALARM_BALANCE_BELOW", "ALARM_NO_INCOMING_FOR", "ALARM_ON_OUTGOING"])
+                    .required(true)
+                )
+            )
+            .subcommand(SubCommand::with_name("list")
+                .about("list the alarms of the wallet")
+                .arg(wallet_argument_name_definition())
+            )
+            .subcommand(SubCommand::with_name("remove")
+                .about("remove an alarm from the wallet")
+                .arg(wallet_argument_name_definition())
+                .arg(Arg::with_name("ALARM_INDEX")
+                    .help("index of the alarm to remove, as displayed by `alarm list'")
+                    .required(true)
+                )
+            )
+        )
+        .subcommand(SubCommand::with_name("config")
+            .about("read or change the settings of the wallet")
+            .subcommand(SubCommand::with_name("get")
+                .about("print the value of a setting, or of all the settings")
+                .arg(wallet_argument_name_definition())
+                .arg(Arg::with_name("CONFIG_SETTING")
+                    .help("the setting to print")
+                    .possible_values(&wallet::Setting::variants())
+                )
+            )
+            .subcommand(SubCommand::with_name("set")
+                .about("change a setting of the wallet")
+                .arg(wallet_argument_name_definition())
+                .arg(Arg::with_name("CONFIG_SETTING")
+                    .help("the setting to change")
+                    .possible_values(&wallet::Setting::variants())
+                    .required(true)
+                )
+                .arg(Arg::with_name("CONFIG_VALUE")
+                    .help("the new value of the setting")
+                    .required(true)
+                )
+                .arg(Arg::with_name("CONFIG_NO_RESCAN")
+                    .help("do not rescan the wallet after raising the gap limit")
+                    .long("no-rescan")
+                )
+            )
+        )
 }
 
 /* ------------------------------------------------------------------------- *
@@ -973,10 +2209,17 @@ pub enum TransactionCmd {
     AddChange,
     AddInput,
     AddOutput,
+    Check,
+    Diff,
     RmInput,
     RmOutput,
     RmChange,
+    Seal,
+    SetFee,
+    SigningInfo,
     Status,
+    Pending,
+    Resubmit,
 }
 impl TransactionCmd {
     pub fn as_string(self) -> &'static str {
@@ -993,10 +2236,17 @@ impl TransactionCmd {
             TransactionCmd::AddChange => "add-change",
             TransactionCmd::AddInput => "add-input",
             TransactionCmd::AddOutput => "add-output",
+            TransactionCmd::Check => "check",
+            TransactionCmd::Diff => "diff",
             TransactionCmd::RmInput => "rm-input",
             TransactionCmd::RmOutput => "rm-output",
             TransactionCmd::RmChange => "rm-change",
+            TransactionCmd::Seal => "seal",
+            TransactionCmd::SetFee => "set-fee",
+            TransactionCmd::SigningInfo => "signing-info",
             TransactionCmd::Status => "status",
+            TransactionCmd::Pending => "pending",
+            TransactionCmd::Resubmit => "resubmit",
         }
     }
 }
@@ -1046,25 +2296,67 @@ fn transaction_argument_input_match<'a>(
     Some((txid, index, coin))
 }
 fn transaction_argument_address_definition<'a, 'b>() -> Arg<'a, 'b> {
-    Arg::with_name("TRANSACTION_ADDRESS").help("Address to send funds too")
+    Arg::with_name("TRANSACTION_ADDRESS")
+        .help("Address to send funds too (or `@LABEL' of the address book)")
 }
 fn transaction_argument_output_match<'a>(
+    term: &mut term::Term,
+    root_dir: &PathBuf,
     matches: &ArgMatches<'a>,
 ) -> Option<(cardano::address::ExtendedAddr, cardano::coin::Coin)> {
-    if !matches.is_present("TRANSACTION_ADDRESS") {
-        return None;
+    use utils::payment_uri::{is_payment_uri, PaymentUri};
+
+    let argument = matches.value_of("TRANSACTION_ADDRESS")?;
+    if !is_payment_uri(argument) {
+        let address = address_argument_match(term, root_dir, matches, "TRANSACTION_ADDRESS")?;
+        let coin = value_t!(matches, "TRANSACTION_AMOUNT", cardano::coin::Coin)
+            .unwrap_or_else(|e| e.exit());
+        return Some((address, coin));
     }
 
-    let address = value_t!(
-        matches,
-        "TRANSACTION_ADDRESS",
-        cardano::address::ExtendedAddr
-    )
-    .unwrap_or_else(|e| e.exit());
-    let coin =
-        value_t!(matches, "TRANSACTION_AMOUNT", cardano::coin::Coin).unwrap_or_else(|e| e.exit());
+    let uri = argument
+        .parse::<PaymentUri>()
+        .unwrap_or_else(|e| term.fail_with(e));
+    let coin = match (uri.amount, matches.is_present("TRANSACTION_AMOUNT")) {
+        (Some(_), true) => {
+            term.error("the amount is given by the payment URI, do not give it again\n")
+                .unwrap();
+            ::std::process::exit(1)
+        }
+        (Some(amount), false) => amount,
+        (None, _) => value_t!(matches, "TRANSACTION_AMOUNT", cardano::coin::Coin)
+            .unwrap_or_else(|e| e.exit()),
+    };
+    if let Some(ref label) = uri.label {
+        term.info(&format!("paying {}\n", label)).unwrap();
+    }
+    if let Some(ref message) = uri.message {
+        term.info(&format!("{}\n", message)).unwrap();
+    }
 
-    Some((address, coin))
+    Some((uri.address, coin))
+}
+fn transaction_argument_dust_threshold_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("DUST_THRESHOLD")
+        .long("dust-threshold")
+        .value_name("LOVELACE")
+        .takes_value(true)
+        .required(false)
+        .help("the outputs below this value are dust, in place of the dust threshold of the profile (1000000 by default)")
+}
+fn transaction_argument_dust_threshold_match<'a>(
+    term: &mut term::Term,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) -> cardano::coin::Coin {
+    let threshold = if matches.is_present("DUST_THRESHOLD") {
+        value_t!(matches, "DUST_THRESHOLD", u64).unwrap_or_else(|e| e.exit())
+    } else {
+        profile
+            .dust_threshold
+            .unwrap_or(transaction::check::DEFAULT_DUST_THRESHOLD)
+    };
+    cardano::coin::Coin::new(threshold).unwrap_or_else(|e| term.fail_with(e))
 }
 fn transaction_argument_selection_algorithm_match<'a>(
     matches: &ArgMatches<'a>,
@@ -1082,10 +2374,16 @@ fn transaction_argument_selection_algorithm_match<'a>(
     }
 }
 
-fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_transaction<'a>(
+    mut term: term::Term,
+    root_dir: PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) {
     let res = match matches.subcommand() {
         ("new", Some(matches)) => {
-            let blockchain = blockchain_argument_name_match(&mut term, &matches);
+            let blockchain =
+                blockchain_argument_name_or_default_match(&mut term, profile, &matches);
             transaction::commands::new(&mut term, root_dir, blockchain)
         }
         ("list", _) => transaction::commands::list(&mut term, root_dir),
@@ -1096,7 +2394,8 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         ("export", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
             let file = matches.value_of("EXPORT_FILE");
-            transaction::commands::export(&mut term, root_dir, id, file)
+            let qr_output = qr_argument_match(&matches);
+            transaction::commands::export(&mut term, root_dir, id, file, qr_output)
         }
         ("import", Some(matches)) => {
             let file = matches.value_of("IMPORT_FILE");
@@ -1104,19 +2403,53 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         }
         ("send", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            let blockchain = blockchain_argument_name_match(&mut term, &matches);
+            let blockchain =
+                blockchain_argument_name_or_default_match(&mut term, profile, &matches);
+            let proxy = blockchain_argument_proxy_match(&matches);
+            let fee_policy = profile.fee_algorithm();
+
+            let dry_run = matches.is_present("SEND_DRY_RUN");
+            let clock_check = clock_check_argument_match(profile, &matches);
 
-            transaction::commands::send(&mut term, root_dir, id, blockchain)
+            transaction::commands::send(
+                &mut term,
+                root_dir,
+                id,
+                blockchain,
+                proxy,
+                fee_policy,
+                dry_run,
+                &clock_check,
+            )
         }
         ("finalize", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
 
-            transaction::commands::finalize(&mut term, root_dir, id)
+            let fee_policy = profile.fee_algorithm();
+
+            transaction::commands::finalize(&mut term, root_dir, id, fee_policy)
         }
         ("sign", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
 
-            transaction::commands::sign(&mut term, root_dir, id)
+            let fee_policy = profile.fee_algorithm();
+
+            match matches.value_of("SIGNER") {
+                None => transaction::commands::sign(&mut term, root_dir, id, fee_policy),
+                Some(name) => {
+                    let signer = match profile.signers.get(name) {
+                        Some(signer) => signer,
+                        None => {
+                            term.error(&format!("no signer `{}' in the profile\n", name))
+                                .unwrap();
+                            ::std::process::exit(1)
+                        }
+                    };
+                    transaction::commands::sign_with_signer(
+                        &mut term, root_dir, id, name, signer, fee_policy,
+                    )
+                }
+            }
         }
         ("add-input", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
@@ -1126,14 +2459,55 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         }
         ("add-output", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            let output = transaction_argument_output_match(&matches);
+            let output = transaction_argument_output_match(&mut term, &root_dir, &matches);
+            let dust_threshold =
+                transaction_argument_dust_threshold_match(&mut term, profile, &matches);
+            let reject_dust = matches.is_present("REJECT_DUST");
 
-            transaction::commands::add_output(&mut term, root_dir, id, output)
+            transaction::commands::add_output(
+                &mut term,
+                root_dir,
+                id,
+                output,
+                dust_threshold,
+                reject_dust,
+            )
+        }
+        ("check", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+            let dust_threshold =
+                transaction_argument_dust_threshold_match(&mut term, profile, &matches);
+            let max_outputs = value_t!(matches, "MAX_OUTPUTS", usize).unwrap_or_else(|e| e.exit());
+            let fee_policy = profile.fee_algorithm();
+
+            transaction::commands::check(
+                &mut term,
+                root_dir,
+                id,
+                fee_policy,
+                dust_threshold,
+                max_outputs,
+            )
+        }
+        ("diff", Some(matches)) => {
+            let from = matches.value_of("DIFF_FROM").unwrap();
+            let to = matches.value_of("DIFF_TO").unwrap();
+            let json = matches.is_present("DIFF_JSON");
+
+            transaction::commands::diff(&mut term, root_dir, from, to, json)
+        }
+        ("signing-info", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+            let wallets =
+                values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit());
+            let fee_policy = profile.fee_algorithm();
+
+            transaction::commands::signing_info(&mut term, root_dir, id, wallets, fee_policy)
         }
         ("add-change", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            let address = value_t!(matches, "CHANGE_ADDRESS", cardano::address::ExtendedAddr)
-                .unwrap_or_else(|e| e.exit());
+            let address = address_argument_match(&mut term, &root_dir, &matches, "CHANGE_ADDRESS")
+                .expect("the change address is required");
 
             transaction::commands::add_change(&mut term, root_dir, id, address)
         }
@@ -1143,22 +2517,34 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
                 values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit());
             let selection_algorithm = transaction_argument_selection_algorithm_match(&matches);
 
+            let lease_owner = matches
+                .value_of("LEASE_OWNER")
+                .map(|owner| owner.to_owned());
+            let fee_policy = profile.fee_algorithm();
+
             transaction::commands::input_select(
                 &mut term,
                 root_dir,
                 id,
                 wallets,
                 selection_algorithm,
+                lease_owner,
+                fee_policy,
             )
         }
+        ("seal", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+            let wallets =
+                values_t!(matches, "SEAL_WALLET", wallet::WalletName).unwrap_or_else(|e| e.exit());
+            let change = address_argument_match(&mut term, &root_dir, &matches, "SEAL_CHANGE");
+            let fee_policy = profile.fee_algorithm();
+
+            transaction::commands::seal(&mut term, root_dir, id, wallets, change, fee_policy)
+        }
         ("rm-output", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            let address = value_t!(
-                matches,
-                "TRANSACTION_ADDRESS",
-                cardano::address::ExtendedAddr
-            )
-            .ok();
+            let address =
+                address_argument_match(&mut term, &root_dir, &matches, "TRANSACTION_ADDRESS");
 
             transaction::commands::remove_output(&mut term, root_dir, id, address)
         }
@@ -1170,14 +2556,22 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         }
         ("rm-change", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            let address = value_t!(matches, "CHANGE_ADDRESS", cardano::address::ExtendedAddr)
-                .unwrap_or_else(|e| e.exit());
+            let address = address_argument_match(&mut term, &root_dir, &matches, "CHANGE_ADDRESS")
+                .expect("the change address is required");
 
             transaction::commands::remove_change(&mut term, root_dir, id, address)
         }
         ("status", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
-            transaction::commands::status(&mut term, root_dir, id)
+            let fee_policy = profile.fee_algorithm();
+            transaction::commands::status(&mut term, root_dir, id, fee_policy)
+        }
+        ("pending", _) => transaction::commands::pending(&mut term, root_dir),
+        ("resubmit", Some(matches)) => {
+            let txid = value_t!(matches, "TRANSACTION_TXID", cardano::tx::TxId)
+                .unwrap_or_else(|e| e.exit());
+
+            transaction::commands::resubmit(&mut term, root_dir, txid)
         }
         _ => {
             term.error(matches.usage()).unwrap();
@@ -1191,8 +2585,8 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .about("Transaction operations.")
         .subcommand(SubCommand::with_name(TransactionCmd::New.as_string())
             .about("Create a new empty staging transaction")
-            .arg(blockchain_argument_name_definition()
-                .help("Transaction are linked to a blockchain to be valid")
+            .arg(blockchain_argument_default_name_definition()
+                .help("Transaction are linked to a blockchain to be valid, the blockchain of the profile if not given")
             )
         )
         .subcommand(SubCommand::with_name(TransactionCmd::List.as_string())
@@ -1208,7 +2602,9 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(Arg::with_name("EXPORT_FILE")
                 .help("optional file to export the staging transaction to (default will display the export to stdout)")
                 .required(false)
+                .conflicts_with_all(&["QR", "QR_PNG"])
             )
+            .args(&qr_argument_definitions())
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Import.as_string())
             .about("Import a human readable format transaction into a new staging transaction")
@@ -1220,13 +2616,26 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name(TransactionCmd::Sign.as_string())
             .about("Finalize a staging a transaction into a transaction ready to send to the blockchain network")
             .arg(transaction_argument_name_definition())
+            .arg(Arg::with_name("SIGNER")
+                .long("signer")
+                .value_name("NAME")
+                .takes_value(true)
+                .required(false)
+                .help("sign with the external signer (hardware wallet, HSM...) of the profile, in place of the keys of the wallets")
+            )
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Send.as_string())
             .about("Send the transaction transaction to the blockchain")
             .arg(transaction_argument_name_definition())
-            .arg(blockchain_argument_name_definition()
-                .help("The blockchain the send the transaction too (will contact the peers of this blockchain)")
+            .arg(blockchain_argument_default_name_definition()
+                .help("The blockchain the send the transaction too (will contact the peers of this blockchain), the blockchain of the profile if not given")
+            )
+            .arg(blockchain_argument_proxy_definition())
+            .arg(Arg::with_name("SEND_DRY_RUN")
+                .long("dry-run")
+                .help("check the transaction against a peer's tip and the protocol parameters of the local blockchain, without sending it")
             )
+            .arg(clock_check_argument_definition())
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Finalize.as_string())
             .about("Finalize a staging transaction")
@@ -1252,16 +2661,42 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .value_name("MAX_EXTRA_FEES")
                 .help("select the exact necessary amount to perform the transaction. The optional parameter takes the accepted loss (in Lovelace, 1µ Ada).")
             )
+            .arg(Arg::with_name("LEASE_OWNER")
+                .long("lease-owner")
+                .value_name("ID")
+                .required(false)
+                .help("also select the unspent outputs leased to this owner (see `wallet utxo lease'), the outputs leased to the other owners are never selected")
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Seal.as_string())
+            .about("Select the inputs the outputs still need, set the change and finalize a staging transaction in one step")
+            .arg(transaction_argument_name_definition())
+            .arg(Arg::with_name("SEAL_WALLET")
+                .long("wallet")
+                .value_name("WALLET_NAME")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help("wallet to select the inputs from, can be given more than once")
+            )
+            .arg(Arg::with_name("SEAL_CHANGE")
+                .long("change")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .required(false)
+                .help("the change address (or `@LABEL' of the address book), in place of the change address of the transaction")
+            )
         )
         .subcommand(SubCommand::with_name(TransactionCmd::AddChange.as_string())
             .about("Add a change address to a transaction")
             .arg(transaction_argument_name_definition())
-            .arg(Arg::with_name("CHANGE_ADDRESS").required(true).help("address to send the change to"))
+            .arg(Arg::with_name("CHANGE_ADDRESS").required(true).help("address to send the change to (or `@LABEL' of the address book)"))
         )
         .subcommand(SubCommand::with_name(TransactionCmd::RmChange.as_string())
             .about("Remove a change address from a transaction")
             .arg(transaction_argument_name_definition())
-            .arg(Arg::with_name("CHANGE_ADDRESS").required(true).help("address to remove"))
+            .arg(Arg::with_name("CHANGE_ADDRESS").required(true).help("address to remove (or `@LABEL' of the address book)"))
         )
         .subcommand(SubCommand::with_name(TransactionCmd::AddInput.as_string())
             .about("Add an input to a transaction")
@@ -1273,8 +2708,51 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name(TransactionCmd::AddOutput.as_string())
             .about("Add an output to a transaction")
             .arg(transaction_argument_name_definition())
-            .arg(transaction_argument_address_definition().requires("TRANSACTION_AMOUNT"))
+            .arg(transaction_argument_address_definition()
+                .help("Address to send funds too (or `@LABEL' of the address book), or a payment URI (`web+cardano:ADDRESS?amount=ADA')")
+            )
             .arg(transaction_argument_amount_definition())
+            .arg(transaction_argument_dust_threshold_definition())
+            .arg(Arg::with_name("REJECT_DUST")
+                .long("reject-dust")
+                .required(false)
+                .help("fail instead of warning when the output is below the dust threshold")
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Check.as_string())
+            .about("Check a transaction for dust outputs and change, too many outputs and an oversize transaction")
+            .arg(transaction_argument_name_definition())
+            .arg(transaction_argument_dust_threshold_definition())
+            .arg(Arg::with_name("MAX_OUTPUTS")
+                .long("max-outputs")
+                .value_name("COUNT")
+                .takes_value(true)
+                .required(false)
+                .default_value("50")
+                .help("the maximum number of outputs, the change included")
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Diff.as_string())
+            .about("Print the inputs, outputs, changes and signatures added or removed from a staging transaction to another")
+            .arg(Arg::with_name("DIFF_FROM")
+                .help("the transaction staging identifier, or the file it was exported to")
+                .value_name("FROM")
+                .required(true)
+            )
+            .arg(Arg::with_name("DIFF_TO")
+                .help("the transaction staging identifier, or the file it was exported to, to compare with")
+                .value_name("TO")
+                .required(true)
+            )
+            .arg(Arg::with_name("DIFF_JSON")
+                .long("json")
+                .help("print the differences in JSON")
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::SigningInfo.as_string())
+            .about("Print, in JSON, the transaction to sign and the derivation path of the address each input spends, for an external signer")
+            .arg(transaction_argument_name_definition())
+            .arg(Arg::with_name("WALLET_NAME").required(true).multiple(true).help("the wallets owning the inputs"))
         )
         .subcommand(SubCommand::with_name(TransactionCmd::RmInput.as_string())
             .about("Remove an input to a transaction")
@@ -1287,10 +2765,25 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(transaction_argument_name_definition())
             .arg(transaction_argument_address_definition())
         )
+        .subcommand(SubCommand::with_name(TransactionCmd::SetFee.as_string())
+            .about("Set the fee of a transaction, in place of the fee computed with the fee policy")
+            .arg(transaction_argument_name_definition())
+            .arg(Arg::with_name("TRANSACTION_FEE").required(true).help("The fee in lovelace"))
+        )
         .subcommand(SubCommand::with_name(TransactionCmd::Status.as_string())
             .about("Status of a staging transaction")
             .arg(transaction_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name(TransactionCmd::Pending.as_string())
+            .about("List the transactions sent but not yet seen in the local blockchains")
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Resubmit.as_string())
+            .about("Send again a transaction recorded in the pending journal")
+            .arg(Arg::with_name("TRANSACTION_TXID")
+                .help("the identifier of the sent transaction, in hexadecimal")
+                .required(true)
+            )
+        )
 }
 
 /* ------------------------------------------------------------------------- *
@@ -1299,12 +2792,16 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
 
 const DEBUG_COMMAND: &'static str = "debug";
 
-fn subcommand_debug<'a>(mut term: term::Term, _rootdir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_debug<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
     match matches.subcommand() {
         ("address", Some(matches)) => {
             let address = value_t!(matches, "ADDRESS", String).unwrap_or_else(|e| e.exit());
+            let wallet = matches.value_of("ADDRESS_WALLET").map(|name| {
+                wallet::WalletName::new(name.to_owned())
+                    .expect("Wallet name is invalid. cannot contains . and /")
+            });
 
-            debug::command_address(term, address);
+            debug::command_address(term, root_dir, address, wallet);
         }
         ("canonicalize-json", Some(_)) => {
             debug::canonicalize_json();
@@ -1313,7 +2810,21 @@ fn subcommand_debug<'a>(mut term: term::Term, _rootdir: PathBuf, matches: &ArgMa
             debug::hash();
         }
         ("decode-signed-tx", Some(_)) => {
-            debug::decode_signed_tx();
+            debug::decode_signed_tx().unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("decode", Some(matches)) => {
+            let input = matches.value_of("DECODE_INPUT");
+            let kind = if matches.is_present("DECODE_KIND") {
+                Some(
+                    value_t!(matches, "DECODE_KIND", debug::decode::Kind)
+                        .unwrap_or_else(|e| e.exit()),
+                )
+            } else {
+                None
+            };
+
+            debug::decode::decode_cbor(&mut term, input, kind)
+                .unwrap_or_else(|e| term.fail_with(e));
         }
         ("generate-xprv", Some(matches)) => {
             let xprv_out = matches.value_of("OUTPUT_FILE").expect("OUTPUT_FILE");
@@ -1324,6 +2835,13 @@ fn subcommand_debug<'a>(mut term: term::Term, _rootdir: PathBuf, matches: &ArgMa
             let xpub_out = matches.value_of("OUTPUT_FILE").expect("OUTPUT_FILE");
             debug::xprv_to_xpub(xprv_in, xpub_out);
         }
+        ("simulate-reorg", Some(matches)) => {
+            let name = blockchain_argument_name_match(&mut term, &matches);
+            let depth = value_t!(matches, "REORG_DEPTH", usize).unwrap_or_else(|e| e.exit());
+
+            debug::reorg::simulate_reorg(&mut term, root_dir, name, depth)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
         ("chain-state-dump", Some(_matches)) => {
             ()
             //let storage_path = matches.value_of("STORAGE-PATH").expect("INPUT_FILE");
@@ -1345,6 +2863,12 @@ fn debug_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .value_name("ADDRESS")
                 .required(true)
             )
+            .arg(Arg::with_name("ADDRESS_WALLET")
+                .long("wallet")
+                .takes_value(true)
+                .value_name("WALLET_NAME")
+                .help("also print the derivation path of the address in this wallet (its spending password is asked for), and whether the wallet can spend its funds")
+            )
         )
         .subcommand(SubCommand::with_name("log-dump")
             .about("pretty print the content of the wallet log file")
@@ -1366,6 +2890,21 @@ fn debug_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("decode-signed-tx")
             .about("decode a signed transaction (TxAux)")
         )
+        .subcommand(SubCommand::with_name("decode")
+            .about("print any CBOR data in diagnostic notation, the fields of the Cardano structures (signed transaction, transaction, block, address, witness) annotated")
+            .arg(Arg::with_name("DECODE_INPUT")
+                .help("the hexadecimal (or base64) encoded data, or the file of the data (default will read stdin)")
+                .value_name("HEX|FILE")
+                .required(false)
+            )
+            .arg(Arg::with_name("DECODE_KIND")
+                .long("as")
+                .takes_value(true)
+                .value_name("KIND")
+                .possible_values(debug::decode::Kind::ALL)
+                .help("the structure of the data (default will detect it)")
+            )
+        )
         .subcommand(SubCommand::with_name("generate-xprv")
             .about("generate a random valid XPrv")
             .arg(Arg::with_name("OUTPUT_FILE")
@@ -1387,4 +2926,308 @@ fn debug_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
             )
         )
+        .subcommand(SubCommand::with_name("simulate-reorg")
+            .about("abandon the last blocks of the local blockchain and check the attached wallets unwind and reapply them correctly")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("REORG_DEPTH")
+                .long("depth")
+                .takes_value(true)
+                .value_name("N")
+                .required(true)
+                .help("number of blocks, below the local tip, to abandon")
+            )
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Run Sub Command                                            *
+ * ------------------------------------------------------------------------- */
+
+const RUN_COMMAND: &'static str = "run";
+
+fn subcommand_run<'a>(
+    mut term: term::Term,
+    root_dir: PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) {
+    let path = PathBuf::from(matches.value_of("PLAN_FILE").unwrap());
+    let dust_threshold = profile
+        .dust_threshold
+        .unwrap_or(transaction::check::DEFAULT_DUST_THRESHOLD);
+    let options = plan::Options {
+        dry_run: matches.is_present("PLAN_DRY_RUN"),
+        dust_threshold: cardano::coin::Coin::new(dust_threshold)
+            .unwrap_or_else(|e| term.fail_with(e)),
+        fee_policy: profile.fee_algorithm(),
+    };
+
+    plan::run(&mut term, root_dir, path, profile.blockchain(), options)
+        .unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn run_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(RUN_COMMAND)
+        .about("build a staging transaction from the steps of a plan file (add outputs, select inputs, finalize, sign, export...) in one process, removing it if a step fails")
+        .arg(Arg::with_name("PLAN_FILE")
+            .value_name("PLAN")
+            .required(true)
+            .help("the YAML plan file")
+        )
+        .arg(Arg::with_name("PLAN_DRY_RUN")
+            .long("dry-run")
+            .help("run the steps up to the signature on a staging transaction kept in memory, writing nothing")
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Selftest Sub Command                                       *
+ * ------------------------------------------------------------------------- */
+
+const SELFTEST_COMMAND: &'static str = "selftest";
+
+fn subcommand_selftest<'a>(mut term: term::Term, matches: &ArgMatches<'a>) {
+    let keep = matches.is_present("SELFTEST_KEEP");
+
+    selftest::run(&mut term, keep).unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn selftest_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SELFTEST_COMMAND)
+        .about("run the main workflows (blockchain and wallet creation, wallet recovery, staging, signing...) against a throwaway root directory and report the result of each of them")
+        .arg(Arg::with_name("SELFTEST_KEEP")
+            .long("keep")
+            .help("do not remove the throwaway root directory once the selftest is done")
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Schema Sub Command                                         *
+ * ------------------------------------------------------------------------- */
+
+const SCHEMA_COMMAND: &'static str = "schema";
+
+fn subcommand_schema<'a>(mut term: term::Term, matches: &ArgMatches<'a>) {
+    let command = matches.value_of("SCHEMA_OUTPUT_COMMAND");
+
+    schema::print(&mut term, command).unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn schema_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SCHEMA_COMMAND)
+        .about("print the JSON schema of the machine readable output of a command, or list the commands with such an output")
+        .arg(Arg::with_name("SCHEMA_OUTPUT_COMMAND")
+            .value_name("COMMAND")
+            .required(false)
+            .possible_values(&schema::commands())
+            .help("the command, e.g. `blockchain block'")
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Watch Sub Command                                          *
+ * ------------------------------------------------------------------------- */
+
+const WATCH_COMMAND: &'static str = "watch";
+
+fn subcommand_watch<'a>(
+    mut term: term::Term,
+    root_dir: PathBuf,
+    profile: &profile::Profile,
+    matches: &ArgMatches<'a>,
+) {
+    let name = blockchain_argument_name_or_default_match(&mut term, profile, &matches);
+    let interval = value_t!(matches, "WATCH_INTERVAL", u64).unwrap_or_else(|e| e.exit());
+    let socket = matches.value_of("WATCH_SOCKET").map(PathBuf::from);
+    let metrics = matches.value_of("WATCH_METRICS").map(String::from);
+    let proxy = blockchain_argument_proxy_match(&matches);
+    let clock_check = clock_check_argument_match(profile, &matches);
+    let digests = if matches.is_present("WATCH_DIGEST") {
+        Some(watch::Digests {
+            period: value_t!(matches, "WATCH_DIGEST", wallet::digest::DigestPeriod)
+                .unwrap_or_else(|e| e.exit()),
+            dir: matches.value_of("WATCH_DIGEST_DIR").map(PathBuf::from),
+        })
+    } else {
+        None
+    };
+
+    watch::run(
+        &mut term,
+        root_dir,
+        name,
+        ::std::time::Duration::from_secs(interval),
+        socket,
+        metrics,
+        proxy,
+        &clock_check,
+        digests,
+        matches.value_of("WATCH_ALARM_HOOK").map(PathBuf::from),
+    )
+    .unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn watch_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(WATCH_COMMAND)
+        .about("pull the blockchain at regular intervals and print a JSON event, one per line, for every new block and every output credited to or spent by the attached wallets")
+        .arg(blockchain_argument_default_name_definition())
+        .arg(Arg::with_name("WATCH_INTERVAL")
+            .long("interval")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .default_value("20")
+            .help("time to wait between two pulls of the blockchain")
+        )
+        .arg(Arg::with_name("WATCH_SOCKET")
+            .long("socket")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("write the events to the clients of this Unix socket instead of the standard output")
+        )
+        .arg(Arg::with_name("WATCH_METRICS")
+            .long("metrics")
+            .takes_value(true)
+            .value_name("HOST:PORT")
+            .help("serve the Prometheus metrics (tip, peers, wallets' balance, transactions) on http://HOST:PORT/metrics")
+        )
+        .arg(Arg::with_name("WATCH_DIGEST")
+            .long("digest")
+            .takes_value(true)
+            .value_name("PERIOD")
+            .possible_values(&wallet::digest::DigestPeriod::variants())
+            .case_insensitive(true)
+            .help("emit a digest of the activity of every attached wallet at the end of every period")
+        )
+        .arg(Arg::with_name("WATCH_DIGEST_DIR")
+            .long("digest-dir")
+            .takes_value(true)
+            .value_name("DIR")
+            .requires("WATCH_DIGEST")
+            .help("also write the digests to this directory, one YAML file per wallet and period")
+        )
+        .arg(Arg::with_name("WATCH_ALARM_HOOK")
+            .long("alarm-hook")
+            .takes_value(true)
+            .value_name("COMMAND")
+            .help("run this command for every alarm of the wallets triggered, the alarm event on its standard input")
+        )
+        .arg(blockchain_argument_proxy_definition())
+        .arg(clock_check_argument_definition())
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Address Book Sub Commands                                  *
+ * ------------------------------------------------------------------------- */
+
+const ADDRESS_BOOK_COMMAND: &'static str = "address-book";
+
+fn address_book_argument_label_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("ADDRESS_BOOK_LABEL")
+        .value_name("LABEL")
+        .required(true)
+        .help("the label, used as `@LABEL' in place of the address")
+}
+fn address_book_argument_label_match<'a>(matches: &ArgMatches<'a>) -> String {
+    let label = matches.value_of("ADDRESS_BOOK_LABEL").unwrap();
+    label
+        .trim_start_matches(address_book::LABEL_PREFIX)
+        .to_owned()
+}
+
+fn subcommand_address_book<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+    let res = match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let label = address_book_argument_label_match(&matches);
+            let address =
+                utils::era::parse_address(matches.value_of("ADDRESS_BOOK_ADDRESS").unwrap())
+                    .unwrap_or_else(|e| term.fail_with(e));
+
+            address_book::commands::add(&mut term, root_dir, label, address)
+        }
+        ("list", _) => address_book::commands::list(&mut term, root_dir),
+        ("remove", Some(matches)) => {
+            let label = address_book_argument_label_match(&matches);
+
+            address_book::commands::remove(&mut term, root_dir, label)
+        }
+        _ => {
+            term.error(matches.usage()).unwrap();
+            ::std::process::exit(1)
+        }
+    };
+    res.unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn address_book_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(ADDRESS_BOOK_COMMAND)
+        .about("labels of external addresses, usable as `@LABEL' wherever an address is expected")
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("give a label to an address")
+                .arg(address_book_argument_label_definition())
+                .arg(
+                    Arg::with_name("ADDRESS_BOOK_ADDRESS")
+                        .value_name("ADDRESS")
+                        .required(true)
+                        .help("the address"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("list").about("list the labels and their addresses"))
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("remove the label")
+                .arg(address_book_argument_label_definition()),
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *                Profile Sub Commands                                       *
+ * ------------------------------------------------------------------------- */
+
+const PROFILE_COMMAND: &'static str = "profile";
+
+fn subcommand_profile<'a>(
+    mut term: term::Term,
+    profiles_file: PathBuf,
+    active: Option<String>,
+    matches: &ArgMatches<'a>,
+) {
+    let res = match matches.subcommand() {
+        ("list", _) => profile::commands::list(&mut term, profiles_file, active),
+        ("show", Some(matches)) => {
+            let name = matches
+                .value_of("PROFILE_NAME")
+                .map(|name| name.to_owned())
+                .or(active);
+            match name {
+                Some(name) => profile::commands::show(&mut term, profiles_file, name),
+                None => {
+                    term.error("no profile given and no active profile\n")
+                        .unwrap();
+                    ::std::process::exit(1)
+                }
+            }
+        }
+        _ => {
+            term.error(matches.usage()).unwrap();
+            ::std::process::exit(1)
+        }
+    };
+    res.unwrap_or_else(|e| term.fail_with(e))
+}
+
+fn profile_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(PROFILE_COMMAND)
+        .about("the profiles of the profiles file, named settings used in place of the default ones with `--profile NAME'")
+        .subcommand(SubCommand::with_name("list")
+            .about("list the profiles, the active one marked with `*'")
+        )
+        .subcommand(SubCommand::with_name("show")
+            .about("print the settings of the profile")
+            .arg(Arg::with_name("PROFILE_NAME")
+                .value_name("NAME")
+                .required(false)
+                .help("the profile, the active one if not given")
+            )
+        )
 }
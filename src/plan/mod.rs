@@ -0,0 +1,598 @@
+//! declarative staging transactions
+//!
+//! `cardano-cli run PLAN` builds a staging transaction from the steps of a
+//! YAML plan, in one process holding the staging transaction (and its lock)
+//! from its creation to the last step, instead of one `transaction` command
+//! per step:
+//!
+//! ```yaml
+//! blockchain: mainnet           # the blockchain of the profile by default
+//! steps:
+//!   - add-output: { address: "@alice", amount: 12500000 }
+//!   - add-change: { address: Ae2tdPwUPEZ... }
+//!   - input-select: { wallets: [ MyWallet ] }
+//!   - check
+//!   - finalize
+//!   - sign
+//!   - export: ./transaction.yml
+//! ```
+//!
+//! The whole plan is read and its values checked before the staging
+//! transaction is created, an `export` to an existing file being refused:
+//! the plan only ever writes new files. The inputs `input-select` adds are
+//! leased to the staging transaction (see `wallet::lease`). If a step
+//! fails, the staging transaction and the files exported by the previous
+//! steps are removed and the leases released.
+//!
+//! In dry run, the steps run up to the signature on a staging transaction
+//! kept in memory: `sign` and `export` are skipped, and neither a staging
+//! file, nor the audit log, nor the leases are written.
+
+use std::{error, fmt, fs, io, path::PathBuf, str::FromStr};
+
+use cardano::{
+    address::ExtendedAddr,
+    coin::Coin,
+    fee::LinearFee,
+    tx::{TxId, TxoPointer},
+    wallet::scheme::SelectionPolicy,
+};
+use serde_yaml;
+
+use address_book;
+use blockchain::{self, Blockchain, BlockchainName};
+use transaction::{self, check, commands, core::StagingTransaction};
+use utils::term::{style::Style, Term};
+use wallet::{lease, utils::load_utxo_index, Wallet, WalletName};
+
+#[derive(Debug)]
+pub enum Error {
+    CannotReadPlan(PathBuf, io::Error),
+    InvalidPlan(PathBuf, serde_yaml::Error),
+    NoBlockchain,
+    InvalidBlockchain(String),
+    InvalidAddress(usize, address_book::Error),
+    InvalidValue(usize, String),
+    BlockchainError(blockchain::Error),
+    CannotCreateStagingTransaction(transaction::Error),
+    StepFailed(usize, transaction::Error),
+}
+impl From<blockchain::Error> for Error {
+    fn from(e: blockchain::Error) -> Self {
+        Error::BlockchainError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::CannotReadPlan(path, _) => write!(f, "Cannot read the plan {:?}", path),
+            Error::InvalidPlan(path, _) => write!(f, "Invalid plan {:?}", path),
+            Error::NoBlockchain => write!(
+                f,
+                "No blockchain in the plan and no blockchain in the profile"
+            ),
+            Error::InvalidBlockchain(name) => write!(f, "Invalid blockchain name `{}'", name),
+            Error::InvalidAddress(step, _) => write!(f, "Invalid address in step {}", step),
+            Error::InvalidValue(step, msg) => write!(f, "Invalid step {}: {}", step, msg),
+            Error::BlockchainError(_) => write!(f, "Blockchain error"),
+            Error::CannotCreateStagingTransaction(_) => {
+                write!(f, "Cannot create the staging transaction")
+            }
+            Error::StepFailed(step, _) => write!(
+                f,
+                "Step {} failed, the staging transaction was removed",
+                step
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::CannotReadPlan(_, ref err) => Some(err),
+            Error::InvalidPlan(_, ref err) => Some(err),
+            Error::NoBlockchain => None,
+            Error::InvalidBlockchain(_) => None,
+            Error::InvalidAddress(_, ref err) => Some(err),
+            Error::InvalidValue(_, _) => None,
+            Error::BlockchainError(ref err) => Some(err),
+            Error::CannotCreateStagingTransaction(ref err) => Some(err),
+            Error::StepFailed(_, ref err) => Some(err),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    /// the blockchain of the staging transaction
+    #[serde(default)]
+    pub blockchain: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+/// a step of the plan, as written in the plan file: the addresses may be
+/// `@LABEL`s of the address book, the amounts are in lovelace
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    AddOutput {
+        address: String,
+        amount: u64,
+    },
+    AddChange {
+        address: String,
+    },
+    AddInput {
+        txid: String,
+        index: u32,
+        /// the value of the output, looked for in the wallets if not given
+        #[serde(default)]
+        amount: Option<u64>,
+    },
+    InputSelect {
+        wallets: Vec<String>,
+        /// select the exact inputs, accepting to lose up to this fee (in
+        /// lovelace), instead of the largest inputs first
+        #[serde(default)]
+        exact_inputs: Option<u32>,
+        #[serde(default)]
+        lease_owner: Option<String>,
+    },
+    SetFee(u64),
+    Check,
+    Finalize,
+    Sign,
+    Export(PathBuf),
+}
+
+/// a step of the plan, its values checked
+enum Action {
+    AddOutput(ExtendedAddr, Coin),
+    AddChange(ExtendedAddr),
+    AddInput(TxId, u32, Option<Coin>),
+    InputSelect(Vec<WalletName>, SelectionPolicy, Option<String>),
+    SetFee(Coin),
+    Check,
+    Finalize,
+    Sign,
+    Export(PathBuf),
+}
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::AddOutput(address, amount) => write!(f, "add output {} to {}", amount, address),
+            Action::AddChange(address) => write!(f, "add change {}", address),
+            Action::AddInput(txid, index, _) => write!(f, "add input {}.{}", txid, index),
+            Action::InputSelect(wallets, _, _) => {
+                let names: Vec<_> = wallets.iter().map(|name| name.to_string()).collect();
+                write!(f, "select inputs from {}", names.join(", "))
+            }
+            Action::SetFee(fee) => write!(f, "set fee {}", fee),
+            Action::Check => write!(f, "check"),
+            Action::Finalize => write!(f, "finalize"),
+            Action::Sign => write!(f, "sign"),
+            Action::Export(path) => write!(f, "export to {:?}", path),
+        }
+    }
+}
+
+fn coin(step: usize, amount: u64) -> Result<Coin> {
+    Coin::new(amount).map_err(|_| Error::InvalidValue(step, format!("invalid amount {}", amount)))
+}
+
+impl Step {
+    fn action(&self, root_dir: &PathBuf, step: usize) -> Result<Action> {
+        let address = |address: &str| {
+            address_book::resolve(root_dir, address).map_err(|e| Error::InvalidAddress(step, e))
+        };
+        let action = match self {
+            Step::AddOutput {
+                address: to,
+                amount,
+            } => Action::AddOutput(address(to)?, coin(step, *amount)?),
+            Step::AddChange { address: to } => Action::AddChange(address(to)?),
+            Step::AddInput {
+                txid,
+                index,
+                amount,
+            } => {
+                let txid = TxId::from_str(txid).map_err(|_| {
+                    Error::InvalidValue(step, format!("invalid transaction id `{}'", txid))
+                })?;
+                let amount = match amount {
+                    None => None,
+                    Some(amount) => Some(coin(step, *amount)?),
+                };
+                Action::AddInput(txid, *index, amount)
+            }
+            Step::InputSelect {
+                wallets,
+                exact_inputs,
+                lease_owner,
+            } => {
+                let mut names = Vec::new();
+                for wallet in wallets {
+                    let name = WalletName::new(wallet.clone()).ok_or_else(|| {
+                        Error::InvalidValue(step, format!("invalid wallet name `{}'", wallet))
+                    })?;
+                    names.push(name);
+                }
+                let policy = match exact_inputs {
+                    None => SelectionPolicy::LargestFirst,
+                    Some(max_extra_fees) => SelectionPolicy::Blackjack((*max_extra_fees).into()),
+                };
+                Action::InputSelect(names, policy, lease_owner.clone())
+            }
+            Step::SetFee(fee) => Action::SetFee(coin(step, *fee)?),
+            Step::Check => Action::Check,
+            Step::Finalize => Action::Finalize,
+            Step::Sign => Action::Sign,
+            Step::Export(path) => {
+                if path.exists() {
+                    return Err(Error::InvalidValue(
+                        step,
+                        format!("the export file {:?} already exists", path),
+                    ));
+                }
+                Action::Export(path.clone())
+            }
+        };
+        Ok(action)
+    }
+}
+
+impl Plan {
+    pub fn load(path: &PathBuf) -> Result<Self> {
+        let file = fs::File::open(path).map_err(|e| Error::CannotReadPlan(path.clone(), e))?;
+        serde_yaml::from_reader(file).map_err(|e| Error::InvalidPlan(path.clone(), e))
+    }
+}
+
+/// the settings of the plan's steps given on the command line or by the
+/// profile
+pub struct Options {
+    pub dry_run: bool,
+    pub dust_threshold: Coin,
+    pub fee_policy: Option<LinearFee>,
+}
+
+/// the staging transaction being built, and the files exported and the
+/// inputs leased so far
+struct Run<'a> {
+    root_dir: &'a PathBuf,
+    options: &'a Options,
+    staging: StagingTransaction,
+    exported: Vec<PathBuf>,
+    leased: Vec<(Wallet, Vec<TxoPointer>)>,
+}
+impl<'a> Run<'a> {
+    fn step(
+        &mut self,
+        term: &mut Term,
+        action: Action,
+    ) -> ::std::result::Result<(), transaction::Error> {
+        use transaction::Error as TxError;
+
+        let options = self.options;
+        match action {
+            Action::AddOutput(address, amount) => {
+                let output =
+                    commands::checked_output(term, address, amount, options.dust_threshold, false)?;
+                self.staging
+                    .add_output(output)
+                    .map_err(TxError::CannotAddOutput)
+            }
+            Action::AddChange(address) => self
+                .staging
+                .add_change(address.into())
+                .map_err(TxError::CannotAddChange),
+            Action::AddInput(txid, index, value) => {
+                let input = commands::input_of(term, self.root_dir, txid, index, value)?;
+                self.staging
+                    .add_input(input)
+                    .map_err(TxError::CannotAddInput)
+            }
+            Action::InputSelect(wallets, policy, lease_owner) => {
+                let selected_from = self.staging.transaction().inputs().len();
+                commands::input_select_staging(
+                    self.root_dir,
+                    &mut self.staging,
+                    wallets.clone(),
+                    policy,
+                    lease_owner,
+                    options.fee_policy.clone(),
+                )?;
+                if options.dry_run {
+                    return Ok(());
+                }
+                let selected = self.staging.transaction().inputs()[selected_from..]
+                    .iter()
+                    .map(|input| input.extract_txin())
+                    .collect();
+                self.lease(wallets, selected)
+            }
+            Action::SetFee(fee) => self.staging.set_fee(fee).map_err(TxError::CannotSetFee),
+            Action::Check => commands::check_staging(
+                term,
+                self.root_dir,
+                &self.staging,
+                options.fee_policy.clone(),
+                options.dust_threshold,
+                check::DEFAULT_MAX_OUTPUTS,
+            ),
+            Action::Finalize => {
+                let fee_algorithm = transaction::staging_fee_algorithm(
+                    self.root_dir,
+                    &self.staging,
+                    options.fee_policy.clone(),
+                );
+                self.staging
+                    .finalize(&fee_algorithm)
+                    .map_err(TxError::CannotFinalize)
+            }
+            Action::Sign => commands::sign_staging(
+                term,
+                self.root_dir,
+                &mut self.staging,
+                options.fee_policy.clone(),
+            ),
+            Action::Export(path) => {
+                // never over an existing file (checked with the plan, but
+                // it may have been created since or by a previous export):
+                // the rollback removes the files the plan exported
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .map_err(TxError::CannotExportToFileCannotOpenOutFile)?;
+                self.exported.push(path);
+                serde_yaml::to_writer(&mut file, &self.staging.export())
+                    .map_err(TxError::CannotExportToFile)
+            }
+        }
+    }
+
+    /// lease the inputs the selection added to the staging transaction, as
+    /// `transaction seal` does, but the ones leased already (to the
+    /// `lease_owner` of the step)
+    fn lease(
+        &mut self,
+        wallets: Vec<WalletName>,
+        selected: Vec<TxoPointer>,
+    ) -> ::std::result::Result<(), transaction::Error> {
+        let owner = lease::staging_owner(self.staging.id());
+        let mut wallets = wallets;
+        // each leases file is locked once
+        wallets.sort();
+        wallets.dedup();
+        for name in wallets {
+            let wallet = Wallet::load(self.root_dir, name)?;
+            let index = load_utxo_index(&wallet, self.root_dir)?;
+            let mut leases = lease::Leases::open(&wallet)?;
+            let mut leased = Vec::new();
+            let owned = selected.iter().filter(|txin| index.get(txin).is_some());
+            for txin in owned {
+                if leases.get(txin).is_some() {
+                    continue;
+                }
+                if let Err(err) = leases.grant(txin.clone(), owner.clone(), lease::STAGING_TTL) {
+                    // the rollback releases the ones granted
+                    self.leased.push((wallet, leased));
+                    return Err(err.into());
+                }
+                leased.push(txin.clone());
+            }
+            self.leased.push((wallet, leased));
+        }
+        Ok(())
+    }
+
+    /// remove the staging transaction and the files exported, all created
+    /// by the plan, and release the leases it took
+    fn rollback(self) {
+        let id = *self.staging.id();
+        let owner = lease::staging_owner(&id);
+        if let Err(err) = self.staging.destroy() {
+            warn!("cannot remove the staging transaction {}: {}", id, err);
+        }
+        for path in self.exported {
+            if let Err(err) = fs::remove_file(&path) {
+                warn!("cannot remove the exported transaction {:?}: {}", path, err);
+            }
+        }
+        for (wallet, txins) in self.leased {
+            if txins.is_empty() {
+                continue;
+            }
+            let mut leases = match lease::Leases::open(&wallet) {
+                Ok(leases) => leases,
+                Err(err) => {
+                    warn!(
+                        "cannot release the leases of wallet {}: {}",
+                        wallet.name, err
+                    );
+                    continue;
+                }
+            };
+            for txin in txins {
+                if let Err(err) = leases.release(&txin, &owner) {
+                    warn!(
+                        "cannot release the lease of {}.{}: {}",
+                        txin.id, txin.index, err
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// run the plan, the staging transaction is created in the given
+/// blockchain if the plan gives none
+pub fn run(
+    term: &mut Term,
+    root_dir: PathBuf,
+    path: PathBuf,
+    default_blockchain: Option<BlockchainName>,
+    options: Options,
+) -> Result<()> {
+    let plan = Plan::load(&path)?;
+
+    let blockchain = match plan.blockchain {
+        Some(ref name) => {
+            BlockchainName::from_str(name).map_err(|_| Error::InvalidBlockchain(name.clone()))?
+        }
+        None => default_blockchain.ok_or(Error::NoBlockchain)?,
+    };
+    let blockchain = Blockchain::load(root_dir.clone(), blockchain)?;
+
+    let mut actions = Vec::new();
+    for (index, step) in plan.steps.iter().enumerate() {
+        actions.push(step.action(&root_dir, index + 1)?);
+    }
+
+    let protocol_magic = blockchain.config.protocol_magic;
+    let staging = if options.dry_run {
+        StagingTransaction::in_memory(root_dir.clone(), protocol_magic)
+    } else {
+        StagingTransaction::new(root_dir.clone(), protocol_magic).map_err(|e| {
+            Error::CannotCreateStagingTransaction(transaction::Error::CannotCreateNewTransaction(e))
+        })?
+    };
+    let id = *staging.id();
+    if options.dry_run {
+        term.info(&format!(
+            "staging transaction {} (in memory, dry run)\n",
+            style!(id)
+        ))
+        .unwrap();
+    } else {
+        term.info(&format!("staging transaction {}\n", style!(id)))
+            .unwrap();
+    }
+
+    let mut run = Run {
+        root_dir: &root_dir,
+        options: &options,
+        staging,
+        exported: Vec::new(),
+        leased: Vec::new(),
+    };
+    for (index, action) in actions.into_iter().enumerate() {
+        let step = index + 1;
+        let skipped = match action {
+            Action::Sign | Action::Export(_) => options.dry_run,
+            _ => false,
+        };
+        if skipped {
+            term.simply(&format!(" {}. {} (skipped, dry run)\n", step, action))
+                .unwrap();
+            continue;
+        }
+        term.simply(&format!(" {}. {}\n", step, action)).unwrap();
+        if let Err(err) = run.step(term, action) {
+            run.rollback();
+            return Err(Error::StepFailed(step, err));
+        }
+    }
+
+    if options.dry_run {
+        let summary = {
+            let transaction = run.staging.transaction();
+            format!(
+                "plan completed in dry run: {} input(s), {} output(s), nothing was written\n",
+                transaction.inputs().len(),
+                transaction.outputs().len()
+            )
+        };
+        run.rollback();
+        term.success(&summary).unwrap();
+    } else {
+        term.success(&format!(
+            "plan completed, staging transaction {}\n",
+            style!(id)
+        ))
+        .unwrap();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::config::ProtocolMagic;
+    use std::process;
+    use utils::testing::input;
+    use wallet::Config;
+
+    #[test]
+    fn export_over_existing_file() {
+        let root_dir = ::std::env::temp_dir().join(format!("cardano-cli-plan-{}", process::id()));
+        fs::create_dir_all(&root_dir).unwrap();
+        let existing = root_dir.join("transaction.yml");
+        fs::write(&existing, "not to be lost").unwrap();
+
+        match Step::Export(existing.clone()).action(&root_dir, 7) {
+            Err(Error::InvalidValue(7, _)) => {}
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(action) => panic!("export over an existing file accepted: {}", action),
+        }
+        match Step::Export(root_dir.join("new.yml")).action(&root_dir, 7) {
+            Ok(Action::Export(ref path)) if path == &root_dir.join("new.yml") => {}
+            Ok(action) => panic!("unexpected action: {}", action),
+            Err(err) => panic!("export to a new file refused: {:?}", err),
+        }
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "not to be lost");
+
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn rollback_releases_the_leases() {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-plan-rollback-{}", process::id()));
+        let _ = fs::remove_dir_all(&root_dir);
+        let name = WalletName::new("wallet".to_owned()).unwrap();
+        let wallet = Wallet::new(root_dir.clone(), name, Config::default(), Vec::new(), None);
+        wallet.save().unwrap();
+
+        let options = Options {
+            dry_run: false,
+            dust_threshold: Coin::new(0).unwrap(),
+            fee_policy: None,
+        };
+        let staging =
+            StagingTransaction::in_memory(root_dir.clone(), ProtocolMagic::from(764824073));
+        let owner = lease::staging_owner(staging.id());
+        let leased = input(1, 1_000_000).extract_txin();
+        let other = input(2, 1_000_000).extract_txin();
+        {
+            let mut leases = lease::Leases::open(&wallet).unwrap();
+            leases
+                .grant(leased.clone(), owner, lease::STAGING_TTL)
+                .unwrap();
+            leases
+                .grant(other.clone(), "other".to_owned(), lease::STAGING_TTL)
+                .unwrap();
+        }
+
+        let run = Run {
+            root_dir: &root_dir,
+            options: &options,
+            staging,
+            exported: Vec::new(),
+            leased: vec![(wallet.clone(), vec![leased.clone()])],
+        };
+        run.rollback();
+
+        // the leases of others are kept
+        let leases = lease::Leases::open(&wallet).unwrap();
+        assert!(leases.get(&leased).is_none());
+        assert!(leases.get(&other).is_some());
+        ::std::mem::drop(leases);
+
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use utils::term::{style::Style, Term};
+
+use super::{Profiles, Result};
+
+/// list the profiles, the active one marked with `*`
+pub fn list(term: &mut Term, profiles_file: PathBuf, active: Option<String>) -> Result<()> {
+    let profiles = Profiles::load(profiles_file)?;
+    for (name, profile) in profiles.iter() {
+        let marker = if active.as_ref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        write!(term, "{} {}", marker, style!(name.as_str()).cyan())?;
+        if let Some(ref blockchain) = profile.blockchain {
+            write!(term, " (blockchain `{}')", blockchain)?;
+        }
+        writeln!(term)?;
+    }
+    if let Some(ref server) = profile.ntp_server {
+        writeln!(term, "NTP server: {}", style!(server.as_str()))?;
+    }
+    if let Some(max_clock_skew) = profile.max_clock_skew {
+        writeln!(
+            term,
+            "maximum clock skew: {} seconds",
+            style!(max_clock_skew)
+        )?;
+    }
+    Ok(())
+}
+
+/// print the settings of the profile, the unset ones are left out
+pub fn show(term: &mut Term, profiles_file: PathBuf, name: String) -> Result<()> {
+    let profiles = Profiles::load(profiles_file)?;
+    let profile = profiles.get(&name)?;
+
+    writeln!(term, "profile: {}", style!(name.as_str()).cyan())?;
+    if let Some(ref root_dir) = profile.root_dir {
+        writeln!(term, "root directory: {}", root_dir.display())?;
+    }
+    if let Some(protocol_magic) = profile.protocol_magic {
+        writeln!(term, "protocol magic: {}", style!(protocol_magic))?;
+    }
+    if let Some(ref blockchain) = profile.blockchain {
+        writeln!(term, "blockchain: {}", style!(blockchain.as_str()))?;
+    }
+    for (alias, endpoint) in profile.peers.iter() {
+        writeln!(term, "peer: {} {}", style!(alias.as_str()).cyan(), endpoint)?;
+    }
+    if let Some(ref policy) = profile.fee_policy {
+        writeln!(
+            term,
+            "fee policy: {} + {} * size (lovelace)",
+            policy.constant, policy.coefficient
+        )?;
+    }
+    if let Some(dust_threshold) = profile.dust_threshold {
+        writeln!(term, "dust threshold: {} lovelace", style!(dust_threshold))?;
+    }
+    for (name, signer) in profile.signers.iter() {
+        write!(
+            term,
+            "signer: {} {}",
+            style!(name.as_str()).cyan(),
+            signer.command.display()
+        )?;
+        for arg in signer.args.iter() {
+            write!(term, " {}", arg)?;
+        }
+        writeln!(term)?;
+    }
+    Ok(())
+}
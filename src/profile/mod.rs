@@ -0,0 +1,199 @@
+//! profiles
+//!
+//! named sets of defaults (root directory, protocol magic, blockchain,
+//! peers of the new blockchains, fee policy, dust threshold and external
+//! signers) kept in the profiles file
+//! of the user's configuration directory. `--profile NAME` applies a
+//! profile to the command, switching between mainnet and testnet without
+//! repeating the options: the options given on the command line still take
+//! precedence.
+
+#[cfg(feature = "cli")]
+pub mod commands;
+
+use std::{collections::BTreeMap, error, fmt, fs, io, path::PathBuf, str::FromStr};
+
+use blockchain::BlockchainName;
+use cardano::fee::{LinearFee, Milli};
+use serde_yaml;
+
+/// the name of the profiles file, in the configuration directory
+pub const PROFILES_FILE: &'static str = "profiles.yml";
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    ReadFailed(PathBuf, serde_yaml::Error),
+    ProfileNotFound(String, PathBuf),
+    InvalidBlockchain(String, String),
+    InvalidFeePolicy(String),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::ReadFailed(path, _) => write!(f, "Failed to read the profiles `{}'", path.to_string_lossy()),
+            Error::ProfileNotFound(name, path) => write!(
+                f,
+                "No profile `{}' in the profiles `{}'",
+                name,
+                path.to_string_lossy()
+            ),
+            Error::InvalidBlockchain(name, blockchain) => write!(
+                f,
+                "The profile `{}' has an invalid blockchain name `{}'",
+                name, blockchain
+            ),
+            Error::InvalidFeePolicy(name) => write!(
+                f,
+                "The profile `{}' has an invalid fee policy, the coefficient must be a positive number",
+                name
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::ReadFailed(_, ref err) => Some(err),
+            Error::ProfileNotFound(_, _) => None,
+            Error::InvalidBlockchain(_, _) => None,
+            Error::InvalidFeePolicy(_) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// the linear fee policy of the network: `constant + coefficient * size`,
+/// the size of the signed transaction in bytes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FeePolicy {
+    /// in lovelace
+    pub constant: u64,
+    /// in lovelace per byte, down to the thousandth
+    pub coefficient: f64,
+}
+impl FeePolicy {
+    fn is_valid(&self) -> bool {
+        self.coefficient.is_finite() && self.coefficient >= 0.0
+    }
+
+    pub fn to_linear_fee(&self) -> LinearFee {
+        let coefficient = (self.coefficient * 1000.0).round() as u64;
+        LinearFee::new(
+            Milli::integral(self.constant),
+            Milli::new(coefficient / 1000, coefficient % 1000),
+        )
+    }
+}
+
+/// an external signer (hardware wallet, HSM...), the program run by
+/// `transaction sign --signer` (see `transaction::signer`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signer {
+    pub command: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// the seconds it is given to sign (`signer::DEFAULT_TIMEOUT` if none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// in place of `--root-dir`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_dir: Option<PathBuf>,
+    /// in place of `--protocol-magic`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_magic: Option<u32>,
+    /// the blockchain of the commands given none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blockchain: Option<String>,
+    /// the peers (alias and endpoint) of the blockchains created with
+    /// `blockchain new`, in place of the peers of the template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub peers: BTreeMap<String, String>,
+    /// in place of the fee policy of the blockchain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_policy: Option<FeePolicy>,
+    /// in place of the default dust threshold of the outputs, in lovelace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dust_threshold: Option<u64>,
+    /// the external signers, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signers: BTreeMap<String, Signer>,
+    /// the NTP server (`HOST:PORT`) the clock is checked against, in place
+    /// of `pool.ntp.org:123`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ntp_server: Option<String>,
+    /// the skew of the local clock allowed, in seconds (10 by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clock_skew: Option<u64>,
+}
+impl Profile {
+    pub fn blockchain(&self) -> Option<BlockchainName> {
+        self.blockchain.as_ref().map(|name| {
+            BlockchainName::from_str(name)
+                .expect("blockchain name checked when loading the profiles")
+        })
+    }
+
+    /// the fee algorithm of the transactions, if the profile has a fee
+    /// policy (otherwise the one of the blockchain applies)
+    pub fn fee_algorithm(&self) -> Option<LinearFee> {
+        self.fee_policy.map(|policy| policy.to_linear_fee())
+    }
+}
+
+pub struct Profiles {
+    path: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+}
+impl Profiles {
+    /// load the profiles file, no profiles if it does not exist
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let profiles: BTreeMap<String, Profile> = match fs::File::open(&path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+            Ok(file) => {
+                serde_yaml::from_reader(file).map_err(|e| Error::ReadFailed(path.clone(), e))?
+            }
+        };
+
+        for (name, profile) in profiles.iter() {
+            if let Some(ref blockchain) = profile.blockchain {
+                if BlockchainName::from_str(blockchain).is_err() {
+                    return Err(Error::InvalidBlockchain(name.clone(), blockchain.clone()));
+                }
+            }
+            if let Some(ref policy) = profile.fee_policy {
+                if !policy.is_valid() {
+                    return Err(Error::InvalidFeePolicy(name.clone()));
+                }
+            }
+        }
+        Ok(Profiles { path, profiles })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Profile)> {
+        self.profiles.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| Error::ProfileNotFound(name.to_owned(), self.path.clone()))
+    }
+}
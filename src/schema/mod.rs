@@ -0,0 +1,664 @@
+//! JSON schemas of the machine readable outputs
+//!
+//! Every structure printed in JSON by a command has its schema here, so
+//! integrators can code against a stable contract. `cardano-cli schema`
+//! prints them and, in debug builds, the outputs are validated against them
+//! before being printed.
+//!
+//! The validator only knows the subset of JSON Schema (draft-07) the
+//! schemas below use: `type`, `properties`, `required`,
+//! `additionalProperties: false`, `items`, `minimum` and `$ref` to the
+//! definitions of these schemas.
+
+use std::fmt;
+#[cfg(feature = "cli")]
+use std::io::Write;
+
+use serde_json::{self, Map, Value};
+
+#[cfg(feature = "cli")]
+use utils::term::{style::Style, Term};
+
+/// the schema of the JSON output of a command
+pub struct Schema {
+    /// the command producing the output, e.g. `blockchain block`
+    pub command: &'static str,
+    /// the schema document
+    pub document: &'static str,
+}
+
+pub const BLOCKCHAIN_BLOCK: Schema = Schema {
+    command: "blockchain block",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/blockchain-block.json",
+  "title": "blockchain block --json",
+  "type": "object",
+  "required": ["hash", "date", "previous", "transactions"],
+  "additionalProperties": false,
+  "properties": {
+    "hash": { "type": "string", "description": "hash of the block" },
+    "date": { "type": "string", "description": "date of the block (`epoch.slot', or `epoch' for an epoch boundary block)" },
+    "previous": { "type": "string", "description": "hash of the previous block" },
+    "transactions": {
+      "type": "array",
+      "items": { "$ref": "#/definitions/transaction" }
+    }
+  },
+  "definitions": {
+    "transaction": {
+      "type": "object",
+      "required": ["txid", "block", "date", "inputs", "outputs", "fee"],
+      "additionalProperties": false,
+      "properties": {
+        "txid": { "type": "string" },
+        "block": { "type": "string", "description": "hash of the block the transaction is in" },
+        "date": { "type": "string", "description": "date of the block the transaction is in" },
+        "inputs": { "type": "array", "items": { "$ref": "#/definitions/input" } },
+        "outputs": { "type": "array", "items": { "$ref": "#/definitions/output" } },
+        "fee": {
+          "type": ["integer", "null"],
+          "minimum": 0,
+          "description": "fee in lovelace, null if some of the spent outputs were not found in the local blockchain"
+        }
+      }
+    },
+    "input": {
+      "type": "object",
+      "required": ["txid", "index", "address", "amount"],
+      "additionalProperties": false,
+      "properties": {
+        "txid": { "type": "string" },
+        "index": { "type": "integer", "minimum": 0 },
+        "address": { "type": ["string", "null"], "description": "address of the spent output, null if not found" },
+        "amount": { "type": ["integer", "null"], "minimum": 0, "description": "amount of the spent output in lovelace, null if not found" }
+      }
+    },
+    "output": {
+      "type": "object",
+      "required": ["address", "amount"],
+      "additionalProperties": false,
+      "properties": {
+        "address": { "type": "string" },
+        "amount": { "type": "integer", "minimum": 0, "description": "amount in lovelace" }
+      }
+    }
+  }
+}"##,
+};
+
+pub const BLOCKCHAIN_TX: Schema = Schema {
+    command: "blockchain tx",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/blockchain-tx.json",
+  "title": "blockchain tx --json",
+  "$ref": "blockchain-block.json#/definitions/transaction"
+}"##,
+};
+
+pub const WATCH_EVENT: Schema = Schema {
+    command: "watch",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/watch-event.json",
+  "title": "watch, one event per line",
+  "type": "object",
+  "required": ["event", "blockchain"],
+  "additionalProperties": false,
+  "properties": {
+    "event": { "type": "string", "description": "`block', `rollback', `resync', `credited', `debited', `digest' or `alarm'" },
+    "blockchain": { "type": "string" },
+    "hash": { "type": "string", "description": "block: hash of the new block" },
+    "previous": { "type": "string", "description": "block: hash of the previous block" },
+    "transactions": { "type": "integer", "minimum": 0, "description": "block: number of transactions in the block" },
+    "from": { "type": "string", "description": "rollback, resync: hash of the local tip before the rollback or the resync; digest: beginning of the period (RFC 3339)" },
+    "to": { "type": "string", "description": "rollback: hash of the block the local tip was rolled back to, the following block events are after it; resync: hash of the new local tip; digest: end of the period (RFC 3339)" },
+    "wallet": { "type": "string", "description": "credited, debited, digest, alarm: name of the wallet" },
+    "txid": { "type": "string", "description": "credited, debited: transaction of the output" },
+    "index": { "type": "integer", "minimum": 0, "description": "credited, debited: index of the output in its transaction" },
+    "address": { "type": "string", "description": "credited, debited: address of the output" },
+    "amount": { "type": "integer", "minimum": 0, "description": "credited, debited: amount of the output in lovelace" },
+    "date": { "type": "string", "description": "block: date of the block; resync: date of the new local tip; credited, debited: date of the block the output was credited or spent in" },
+    "period": { "type": "string", "description": "digest: `daily' or `weekly'" },
+    "received": { "type": "integer", "minimum": 0, "description": "digest: funds received during the period, in lovelace" },
+    "sent": { "type": "integer", "minimum": 0, "description": "digest: funds spent during the period, in lovelace" },
+    "received_transactions": { "type": "integer", "minimum": 0, "description": "digest: transactions crediting the wallet confirmed during the period" },
+    "sent_transactions": { "type": "integer", "minimum": 0, "description": "digest: transactions debiting the wallet confirmed during the period" },
+    "balance": { "type": "integer", "minimum": 0, "description": "digest: balance at the end of the period, in lovelace" },
+    "pending": { "type": "array", "items": { "type": "string" }, "description": "digest: transactions sent and not confirmed yet" },
+    "expired": { "type": "array", "items": { "type": "string" }, "description": "digest: transactions sent before the period and still not confirmed, to resubmit" },
+    "alarm": { "type": "string", "description": "alarm: the alarm of the wallet, as listed by `wallet alarm list'" },
+    "reason": { "type": "string", "description": "alarm: what triggered it" }
+  }
+}"##,
+};
+
+pub const TRANSACTION_SIGNING_INFO: Schema = Schema {
+    command: "transaction signing-info",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/transaction-signing-info.json",
+  "title": "transaction signing-info",
+  "type": "object",
+  "required": ["staging_id", "protocol_magic", "txid", "tx", "inputs"],
+  "additionalProperties": false,
+  "properties": {
+    "staging_id": { "type": "string", "description": "identifier of the staging transaction" },
+    "protocol_magic": { "type": "integer", "minimum": 0 },
+    "txid": { "type": "string", "description": "identifier of the transaction, what the witnesses sign" },
+    "tx": { "type": "string", "description": "CBOR of the transaction, in hexadecimal" },
+    "inputs": {
+      "type": "array",
+      "items": { "$ref": "#/definitions/input" }
+    }
+  },
+  "definitions": {
+    "input": {
+      "type": "object",
+      "required": ["txid", "index", "value", "wallet", "address", "derivation_path", "derivation_indices"],
+      "additionalProperties": false,
+      "properties": {
+        "txid": { "type": "string" },
+        "index": { "type": "integer", "minimum": 0 },
+        "value": { "type": "integer", "minimum": 0, "description": "value of the spent output in lovelace" },
+        "wallet": { "type": ["string", "null"], "description": "wallet owning the input, null if none of the given wallets does" },
+        "address": { "type": ["string", "null"], "description": "address of the spent output, null if not owned" },
+        "derivation_path": { "type": ["string", "null"], "description": "derivation path of the address's key (`m/44'/1815'/ACCOUNT'/CHANGE/INDEX' for BIP44), null if not owned" },
+        "derivation_indices": {
+          "type": ["array", "null"],
+          "items": { "type": "integer", "minimum": 0 },
+          "description": "indices of the BIP44 derivation path, the hardened ones offset by 2^31, null if not owned or not a BIP44 address"
+        }
+      }
+    }
+  }
+}"##,
+};
+
+pub const TRANSACTION_DIFF: Schema = Schema {
+    command: "transaction diff",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/transaction-diff.json",
+  "title": "transaction diff --json",
+  "type": "object",
+  "required": ["protocol_magic", "inputs", "outputs", "changes", "signatures", "fee", "finalized"],
+  "additionalProperties": false,
+  "properties": {
+    "protocol_magic": {
+      "type": ["object", "null"],
+      "required": ["from", "to"],
+      "additionalProperties": false,
+      "properties": {
+        "from": { "type": "integer", "minimum": 0 },
+        "to": { "type": "integer", "minimum": 0 }
+      },
+      "description": "the protocol magic of the transactions (their network), null if it did not change"
+    },
+    "inputs": {
+      "type": "object",
+      "required": ["removed", "added", "reordered"],
+      "additionalProperties": false,
+      "properties": {
+        "removed": { "type": "array", "items": { "$ref": "#/definitions/input" } },
+        "added": { "type": "array", "items": { "$ref": "#/definitions/input" } },
+        "reordered": {
+          "type": ["object", "null"],
+          "required": ["from", "to"],
+          "additionalProperties": false,
+          "properties": {
+            "from": { "type": "array", "items": { "$ref": "#/definitions/input" } },
+            "to": { "type": "array", "items": { "$ref": "#/definitions/input" } }
+          },
+          "description": "the elements of both transactions in the order of each, null if it did not change"
+        }
+      }
+    },
+    "outputs": {
+      "type": "object",
+      "required": ["removed", "added", "reordered"],
+      "additionalProperties": false,
+      "properties": {
+        "removed": { "type": "array", "items": { "$ref": "#/definitions/output" } },
+        "added": { "type": "array", "items": { "$ref": "#/definitions/output" } },
+        "reordered": {
+          "type": ["object", "null"],
+          "required": ["from", "to"],
+          "additionalProperties": false,
+          "properties": {
+            "from": { "type": "array", "items": { "$ref": "#/definitions/output" } },
+            "to": { "type": "array", "items": { "$ref": "#/definitions/output" } }
+          },
+          "description": "the elements of both transactions in the order of each, null if it did not change"
+        }
+      }
+    },
+    "changes": {
+      "type": "object",
+      "required": ["removed", "added", "reordered"],
+      "additionalProperties": false,
+      "properties": {
+        "removed": { "type": "array", "items": { "$ref": "#/definitions/change" } },
+        "added": { "type": "array", "items": { "$ref": "#/definitions/change" } },
+        "reordered": {
+          "type": ["object", "null"],
+          "required": ["from", "to"],
+          "additionalProperties": false,
+          "properties": {
+            "from": { "type": "array", "items": { "$ref": "#/definitions/change" } },
+            "to": { "type": "array", "items": { "$ref": "#/definitions/change" } }
+          },
+          "description": "the elements of both transactions in the order of each, null if it did not change"
+        }
+      }
+    },
+    "signatures": {
+      "type": "object",
+      "required": ["removed", "added", "reordered"],
+      "additionalProperties": false,
+      "properties": {
+        "removed": { "type": "array", "items": { "$ref": "#/definitions/signature" } },
+        "added": { "type": "array", "items": { "$ref": "#/definitions/signature" } },
+        "reordered": {
+          "type": ["object", "null"],
+          "required": ["from", "to"],
+          "additionalProperties": false,
+          "properties": {
+            "from": { "type": "array", "items": { "$ref": "#/definitions/signature" } },
+            "to": { "type": "array", "items": { "$ref": "#/definitions/signature" } }
+          },
+          "description": "the elements of both transactions in the order of each, null if it did not change"
+        }
+      }
+    },
+    "fee": {
+      "type": ["object", "null"],
+      "required": ["from", "to"],
+      "additionalProperties": false,
+      "properties": {
+        "from": { "type": ["integer", "null"], "minimum": 0, "description": "fee in lovelace, null if not set" },
+        "to": { "type": ["integer", "null"], "minimum": 0, "description": "fee in lovelace, null if not set" }
+      },
+      "description": "the fee set in the transactions, null if it did not change"
+    },
+    "finalized": {
+      "type": ["object", "null"],
+      "required": ["from", "to"],
+      "additionalProperties": false,
+      "properties": {
+        "from": { "type": "boolean" },
+        "to": { "type": "boolean" }
+      },
+      "description": "whether the transactions are finalized, null if it did not change"
+    }
+  },
+  "definitions": {
+    "input": {
+      "type": "object",
+      "required": ["transaction_id", "index_in_transaction", "expected_value"],
+      "additionalProperties": false,
+      "properties": {
+        "transaction_id": { "type": "string" },
+        "index_in_transaction": { "type": "integer", "minimum": 0 },
+        "expected_value": { "type": "integer", "minimum": 0, "description": "value of the spent output in lovelace" }
+      }
+    },
+    "output": {
+      "type": "object",
+      "required": ["address", "amount"],
+      "additionalProperties": false,
+      "properties": {
+        "address": { "type": "string" },
+        "amount": { "type": "integer", "minimum": 0, "description": "amount in lovelace" }
+      }
+    },
+    "change": {
+      "type": "object",
+      "required": ["address"],
+      "additionalProperties": false,
+      "properties": {
+        "address": { "type": "string" }
+      }
+    },
+    "signature": {
+      "type": "object",
+      "description": "the witness, keyed by its kind (`PkWitness', `ScriptWitness' or `RedeemWitness')"
+    }
+  }
+}"##,
+};
+
+pub const WALLET_OWNS_ADDRESS: Schema = Schema {
+    command: "wallet owns-address",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/wallet-owns-address.json",
+  "title": "wallet owns-address --json",
+  "type": "object",
+  "required": ["address", "owned", "path"],
+  "additionalProperties": false,
+  "properties": {
+    "address": { "type": "string" },
+    "owned": { "type": "boolean", "description": "whether the address is one of the wallet's" },
+    "path": { "type": ["string", "null"], "description": "derivation path of the address, null if not owned" }
+  }
+}"##,
+};
+
+pub const WALLET_PORTFOLIO: Schema = Schema {
+    command: "wallet portfolio",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/wallet-portfolio.json",
+  "title": "wallet portfolio --json",
+  "type": "object",
+  "required": ["wallets", "totals"],
+  "additionalProperties": false,
+  "properties": {
+    "wallets": {
+      "type": "array",
+      "items": { "$ref": "#/definitions/wallet" }
+    },
+    "totals": {
+      "type": "object",
+      "description": "total balance of the wallets of each blockchain, in lovelace, keyed by the blockchain's name"
+    }
+  },
+  "definitions": {
+    "wallet": {
+      "type": "object",
+      "required": ["wallet", "blockchain", "balance", "utxos", "staged", "pending", "synced_to", "last_activity", "last_activity_time"],
+      "additionalProperties": false,
+      "properties": {
+        "wallet": { "type": "string" },
+        "blockchain": { "type": ["string", "null"], "description": "the attached blockchain, null if none" },
+        "balance": { "type": "integer", "minimum": 0, "description": "in lovelace" },
+        "utxos": { "type": "integer", "minimum": 0, "description": "number of unspent outputs" },
+        "staged": { "type": "integer", "minimum": 0, "description": "funds the staging transactions spend, in lovelace" },
+        "pending": { "type": "integer", "minimum": 0, "description": "funds the transactions sent and not confirmed yet spend, in lovelace" },
+        "synced_to": { "type": ["string", "null"], "description": "date of the block the wallet is synced to, null if never synced" },
+        "last_activity": { "type": ["string", "null"], "description": "date of the block of the last credit or debit" },
+        "last_activity_time": { "type": ["string", "null"], "description": "time of the block of the last activity (RFC 3339)" }
+      }
+    }
+  }
+}"##,
+};
+
+pub const PROGRESS_EVENT: Schema = Schema {
+    command: "progress",
+    document: r##"{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "cardano-cli/progress-event.json",
+  "title": "--progress json, one event per line on the standard error",
+  "type": "object",
+  "required": ["event", "message"],
+  "additionalProperties": false,
+  "properties": {
+    "event": { "type": "string", "description": "`success', `info', `warning', `error', `progress' or `finished'" },
+    "message": { "type": "string", "description": "the message, or what the operation in progress is doing" },
+    "position": { "type": "integer", "minimum": 0, "description": "progress, finished: number of steps done" },
+    "total": { "type": "integer", "minimum": 0, "description": "progress, finished: number of steps of the operation" }
+  }
+}"##,
+};
+
+/// all the schemas, in the order `cardano-cli schema` lists them
+pub const SCHEMAS: &'static [Schema] = &[
+    BLOCKCHAIN_BLOCK,
+    BLOCKCHAIN_TX,
+    WATCH_EVENT,
+    TRANSACTION_SIGNING_INFO,
+    TRANSACTION_DIFF,
+    WALLET_OWNS_ADDRESS,
+    WALLET_PORTFOLIO,
+    PROGRESS_EVENT,
+];
+
+/// the commands having a JSON output, as accepted by `cardano-cli schema`
+pub fn commands() -> Vec<&'static str> {
+    SCHEMAS.iter().map(|schema| schema.command).collect()
+}
+
+pub fn find(command: &str) -> Option<&'static Schema> {
+    SCHEMAS.iter().find(|schema| schema.command == command)
+}
+
+/// a value not matching its schema, `path` is the JSON pointer to the value
+pub struct Violation {
+    pub path: String,
+    pub reason: String,
+}
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            if self.path.is_empty() {
+                "/"
+            } else {
+                &self.path[..]
+            },
+            self.reason
+        )
+    }
+}
+
+impl Schema {
+    fn parse(&self) -> Value {
+        // the documents are constants of this module, they are valid JSON
+        serde_json::from_str(self.document).expect("invalid JSON schema document")
+    }
+
+    /// check the value against the schema, returns all the violations
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        let document = self.parse();
+        let mut violations = Vec::new();
+        validate(
+            self,
+            &document,
+            &document,
+            value,
+            String::new(),
+            &mut violations,
+        );
+        violations
+    }
+
+    /// check the given output in debug builds, an output not matching its
+    /// schema is a bug of the command.
+    pub fn debug_check<T: ::serde::Serialize>(&self, output: &T) {
+        if cfg!(debug_assertions) {
+            let value = serde_json::to_value(output).expect("output not serializable to JSON");
+            let violations = self.validate(&value);
+            if !violations.is_empty() {
+                let violations: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                panic!(
+                    "the JSON output of `{}' does not match its schema:\n  {}",
+                    self.command,
+                    violations.join("\n  ")
+                );
+            }
+        }
+    }
+}
+
+/// resolve a `$ref`: either local to the root document (`#/definitions/x`)
+/// or to another of the schemas (`blockchain-block.json#/definitions/x`).
+///
+/// Returns the document of the target, against which the target's own
+/// references are resolved, and the target.
+fn resolve(root: &Value, reference: &str) -> Option<(Value, Value)> {
+    let (document, pointer) = match reference.find('#') {
+        Some(index) => (&reference[..index], &reference[index + 1..]),
+        None => (reference, ""),
+    };
+    let root = if document.is_empty() {
+        root.clone()
+    } else {
+        let id = format!("cardano-cli/{}", document);
+        SCHEMAS
+            .iter()
+            .map(|schema| schema.parse())
+            .find(|document| document.get("$id").and_then(Value::as_str) == Some(&id))?
+    };
+    let target = root.pointer(pointer).cloned()?;
+    Some((root, target))
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    let actual = type_of(value);
+    actual == expected || (expected == "number" && actual == "integer")
+}
+
+fn validate(
+    schema: &Schema,
+    root: &Value,
+    node: &Value,
+    value: &Value,
+    path: String,
+    violations: &mut Vec<Violation>,
+) {
+    let node = match node.as_object() {
+        Some(node) => node,
+        None => return,
+    };
+
+    if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some((root, target)) => {
+                validate(schema, &root, &target, value, path.clone(), violations)
+            }
+            None => violations.push(Violation {
+                path: path.clone(),
+                reason: format!(
+                    "unresolved reference `{}' in the schema of `{}'",
+                    reference, schema.command
+                ),
+            }),
+        }
+    }
+
+    if let Some(expected) = node.get("type") {
+        let ok = match expected {
+            Value::String(expected) => type_matches(expected, value),
+            Value::Array(expected) => expected
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|expected| type_matches(expected, value)),
+            _ => true,
+        };
+        if !ok {
+            violations.push(Violation {
+                path: path.clone(),
+                reason: format!("expected {}, found {}", expected, type_of(value)),
+            });
+            return;
+        }
+    }
+
+    if let (Some(minimum), Some(n)) = (node.get("minimum").and_then(Value::as_f64), value.as_f64())
+    {
+        if n < minimum {
+            violations.push(Violation {
+                path: path.clone(),
+                reason: format!("{} is less than the minimum {}", n, minimum),
+            });
+        }
+    }
+
+    if let Value::Object(object) = value {
+        validate_object(schema, root, node, object, &path, violations);
+    }
+
+    if let (Some(items), Value::Array(array)) = (node.get("items"), value) {
+        for (index, item) in array.iter().enumerate() {
+            validate(
+                schema,
+                root,
+                items,
+                item,
+                format!("{}/{}", path, index),
+                violations,
+            );
+        }
+    }
+}
+
+fn validate_object(
+    schema: &Schema,
+    root: &Value,
+    node: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<Violation>,
+) {
+    let empty = Map::new();
+    let properties = node
+        .get("properties")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+
+    if let Some(required) = node.get("required").and_then(Value::as_array) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                violations.push(Violation {
+                    path: path.to_owned(),
+                    reason: format!("missing required property `{}'", key),
+                });
+            }
+        }
+    }
+
+    let closed = node.get("additionalProperties") == Some(&Value::Bool(false));
+    for (key, value) in object.iter() {
+        match properties.get(key) {
+            Some(property) => validate(
+                schema,
+                root,
+                property,
+                value,
+                format!("{}/{}", path, key),
+                violations,
+            ),
+            None if closed => violations.push(Violation {
+                path: path.to_owned(),
+                reason: format!("unexpected property `{}'", key),
+            }),
+            None => {}
+        }
+    }
+}
+
+/// print the schema of the given command, or the list of the commands
+/// having a JSON output
+#[cfg(feature = "cli")]
+pub fn print(term: &mut Term, command: Option<&str>) -> ::std::io::Result<()> {
+    match command.and_then(find) {
+        Some(schema) => writeln!(term, "{}", schema.document),
+        None => {
+            writeln!(
+                term,
+                "{}",
+                style!("commands with a JSON output:").cyan().bold()
+            )?;
+            for schema in SCHEMAS.iter() {
+                writeln!(term, " * {}", style!(schema.command))?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,762 @@
+//! end-to-end check of the main workflows of the CLI
+//!
+//! The scenarios run one after the other against a throwaway root directory
+//! (created in the system's temporary directory). They share a `Context` so
+//! each scenario can build on what the previous ones created: the blockchain,
+//! the wallet, the fund received by the wallet, the staging transaction...
+//!
+//! No connection is made outside of the machine: the blockchain is a mock
+//! chain of epoch boundary blocks, the wallet's fund is mocked with the
+//! wallet log entries the scenarios write directly, and the peers are local:
+//! the mock chain is synced from another root directory and the transaction
+//! is broadcast to a peer that is down.
+
+use std::{error, fmt, fs, io, net::TcpListener, path::PathBuf, str::FromStr};
+
+use cardano::{
+    bip::bip39,
+    block::{BlockDate, EpochId, EpochSlotId, HeaderHash, RawBlock},
+    coin::Coin,
+    config::ProtocolMagic,
+    fee::LinearFee,
+    hash::Blake2b256,
+    hdwallet::{self, DerivationScheme, XPrv},
+    tx::{TxAux, TxInWitness},
+    wallet::{bip44, keygen},
+};
+use cardano_storage as storage;
+use cbor_event::{self, se::Serializer, Len};
+
+use blockchain::{self, Blockchain, BlockchainName};
+use transaction::{
+    self,
+    core::{Input, Output, PendingTransaction, StagingTransaction},
+};
+use utils::{
+    entropy::{self, Purpose},
+    password_encrypted,
+    term::Term,
+};
+use wallet::{
+    self,
+    state::{
+        log::{Log, LogWriter},
+        lookup::{self, sequentialindex::SequentialBip44Lookup},
+        ptr::StatePtr,
+        utxo::UTxO,
+    },
+    utils::{create_wallet_state_from_logs, rollback_wallet_log},
+    Wallet, WalletName,
+};
+
+/// name of the blockchain and of the wallet created by the selftest
+const NAME: &'static str = "selftest";
+const RECOVERY_PASSWORD: &'static [u8] = b"selftest recovery password";
+const SPENDING_PASSWORD: &'static [u8] = b"selftest spending password";
+
+/// value of the fund the mock chain credits to the wallet
+const FUND_VALUE: u64 = 1_000_000;
+/// value sent back to the wallet by the transaction of the selftest
+const SENT_VALUE: u64 = 800_000;
+
+/// number of epochs of the mock chain, each one made of its boundary block
+const MOCK_EPOCHS: EpochId = 4;
+/// name of the root directory (in the selftest root directory) and of the
+/// peer serving the mock chain
+const MOCK_PEER: &'static str = "mock-peer";
+
+#[derive(Debug)]
+pub enum Error {
+    CannotCreateRootDir(PathBuf, io::Error),
+    CannotRemoveRootDir(PathBuf, io::Error),
+    ScenariosFailed(usize),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::CannotCreateRootDir(path, _) => write!(
+                f,
+                "Cannot create the selftest root directory `{}'",
+                path.to_string_lossy()
+            ),
+            Error::CannotRemoveRootDir(path, _) => write!(
+                f,
+                "Cannot remove the selftest root directory `{}'",
+                path.to_string_lossy()
+            ),
+            Error::ScenariosFailed(count) => write!(f, "{} selftest scenario(s) failed", count),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::CannotCreateRootDir(_, ref err) => Some(err),
+            Error::CannotRemoveRootDir(_, ref err) => Some(err),
+            Error::ScenariosFailed(_) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// a scenario fails with the reason of the failure
+type Scenario = fn(&mut Context) -> ::std::result::Result<(), String>;
+
+const SCENARIOS: &'static [(&'static str, Scenario)] = &[
+    ("blockchain init", scenario_blockchain_init),
+    ("wallet creation", scenario_wallet_creation),
+    ("wallet recovery", scenario_wallet_recovery),
+    ("wallet state", scenario_wallet_state),
+    ("staging transaction", scenario_staging_transaction),
+    ("signing", scenario_signing),
+    ("pending journal", scenario_pending_journal),
+    ("rollback", scenario_rollback),
+];
+
+/// a scenario talking to the mock peer, reporting its progress on the
+/// terminal like the commands it runs
+type PeerScenario = fn(&mut Term, &mut Context) -> ::std::result::Result<(), String>;
+
+const PEER_SCENARIOS: &'static [(&'static str, PeerScenario)] = &[
+    ("chain sync", scenario_chain_sync),
+    ("network broadcast", scenario_network_broadcast),
+];
+
+/// everything the scenarios created so far
+struct Context {
+    root_dir: PathBuf,
+    blockchain: Option<Blockchain>,
+    mock_chain: Option<Vec<(HeaderHash, RawBlock)>>,
+    wallet: Option<Wallet>,
+    mnemonics: Option<String>,
+    xprv: Option<XPrv>,
+    lookup: Option<SequentialBip44Lookup>,
+    utxo: Option<UTxO<lookup::Address>>,
+    staging: Option<StagingTransaction>,
+    txaux: Option<TxAux>,
+}
+
+/// run all the scenarios against a throwaway root directory and report
+/// the result of each of them.
+///
+/// The root directory is removed at the end unless `keep` is set.
+pub fn run(term: &mut Term, keep: bool) -> Result<()> {
+    let root_dir = ::std::env::temp_dir().join(format!(
+        "cardano-cli-selftest-{:08x}",
+        entropy::default_source().next_u32(Purpose::Other)
+    ));
+    fs::create_dir_all(&root_dir).map_err(|e| Error::CannotCreateRootDir(root_dir.clone(), e))?;
+    term.info(&format!(
+        "selftest root directory: {}\n",
+        root_dir.to_string_lossy()
+    ))
+    .unwrap();
+
+    let mut context = Context {
+        root_dir: root_dir.clone(),
+        blockchain: None,
+        mock_chain: None,
+        wallet: None,
+        mnemonics: None,
+        xprv: None,
+        lookup: None,
+        utxo: None,
+        staging: None,
+        txaux: None,
+    };
+
+    let mut failures = 0;
+    for (name, scenario) in SCENARIOS.iter() {
+        let result = scenario(&mut context);
+        report(term, name, result, &mut failures);
+    }
+    for (name, scenario) in PEER_SCENARIOS.iter() {
+        let result = scenario(term, &mut context);
+        report(term, name, result, &mut failures);
+    }
+
+    term.info(&format!(
+        "{} passed, {} failed\n",
+        SCENARIOS.len() + PEER_SCENARIOS.len() - failures,
+        failures
+    ))
+    .unwrap();
+
+    // release the locks (staging transaction, storage) before removing the
+    // files they point to.
+    ::std::mem::drop(context);
+    if !keep {
+        fs::remove_dir_all(&root_dir).map_err(|e| Error::CannotRemoveRootDir(root_dir, e))?;
+    }
+
+    if failures > 0 {
+        Err(Error::ScenariosFailed(failures))
+    } else {
+        Ok(())
+    }
+}
+
+/// print the result of the scenario, counting the failures
+fn report(
+    term: &mut Term,
+    name: &str,
+    result: ::std::result::Result<(), String>,
+    failures: &mut usize,
+) {
+    match result {
+        Ok(()) => term.success(&format!("[PASS] {}\n", name)).unwrap(),
+        Err(reason) => {
+            *failures += 1;
+            term.error(&format!("[FAIL] {}: {}\n", name, reason))
+                .unwrap()
+        }
+    }
+}
+
+/// format the error with the given context, for the scenario's failure report
+fn failed<E: fmt::Display>(context: &'static str) -> impl Fn(E) -> String {
+    move |e| format!("{}: {}", context, e)
+}
+
+fn required<'a, T>(value: &'a Option<T>, what: &str) -> ::std::result::Result<&'a T, String> {
+    value
+        .as_ref()
+        .ok_or_else(|| format!("needs the {} of a previous scenario", what))
+}
+
+fn xprv_from_entropy(entropy: &bip39::Entropy) -> XPrv {
+    let mut seed = [0; hdwallet::XPRV_SIZE];
+    keygen::generate_seed(entropy, RECOVERY_PASSWORD, &mut seed);
+    XPrv::normalize_bytes(seed)
+}
+
+/// the address of the wallet the mock chain credits
+fn fund_addressing() -> ::std::result::Result<bip44::Addressing, String> {
+    Ok(bip44::Addressing {
+        account: bip44::bip44::Account::new(0).map_err(failed("invalid account"))?,
+        change: 0,
+        index: bip44::Index::new(3).map_err(failed("invalid index"))?,
+    })
+}
+
+/// encode the boundary block of the given epoch, without slot leaders
+pub(crate) fn boundary_block(
+    protocol_magic: &ProtocolMagic,
+    previous: &HeaderHash,
+    epoch: EpochId,
+) -> cbor_event::Result<RawBlock> {
+    let mut body = Serializer::new_vec();
+    body.write_array(Len::Len(0))?;
+    let body = body.finalize();
+
+    let mut se = Serializer::new_vec();
+    se.write_array(Len::Len(2))?
+        .write_unsigned_integer(0)?
+        .write_array(Len::Len(3))?;
+    // header: protocol magic, previous block, body proof, consensus
+    // (epoch and chain difficulty) and attributes
+    se.write_array(Len::Len(5))?
+        .serialize(protocol_magic)?
+        .write_bytes(&previous.as_hash_bytes()[..])?
+        .write_bytes(&Blake2b256::new(&body).as_hash_bytes()[..])?
+        .write_array(Len::Len(2))?
+        .write_unsigned_integer(epoch)?
+        .write_array(Len::Len(1))?
+        .write_unsigned_integer(0)?
+        .write_array(Len::Len(1))?
+        .write_map(Len::Len(0))?;
+    // body and attributes
+    se.write_raw_bytes(&body)?
+        .write_array(Len::Len(1))?
+        .write_map(Len::Len(0))?;
+    Ok(RawBlock::from_dat(se.finalize()))
+}
+
+/// the boundary blocks of the first epochs, following the genesis data of
+/// the given blockchain settings
+fn mock_chain(
+    config: &blockchain::Config,
+) -> ::std::result::Result<Vec<(HeaderHash, RawBlock)>, String> {
+    let mut blocks = Vec::new();
+    let mut previous = config.genesis_prev.clone();
+    for epoch in config.epoch_start..config.epoch_start + MOCK_EPOCHS {
+        let raw = boundary_block(&config.protocol_magic, &previous, epoch)
+            .map_err(failed("cannot encode the mock block"))?;
+        let hash = raw
+            .decode()
+            .map_err(failed("cannot decode the mock block"))?
+            .get_header()
+            .compute_hash();
+        previous = hash.clone();
+        blocks.push((hash, raw));
+    }
+    Ok(blocks)
+}
+
+fn scenario_blockchain_init(context: &mut Context) -> ::std::result::Result<(), String> {
+    let name = BlockchainName::from_str(NAME).map_err(|_| "invalid blockchain name".to_owned())?;
+
+    // the testnet settings, with the mock chain in place of the testnet's
+    // blocks and no peers
+    let testnet = blockchain::Config::testnet();
+    let mock_chain = mock_chain(&testnet)?;
+    let config = blockchain::Config {
+        genesis: mock_chain[0].0.clone(),
+        peers: blockchain::Peers::new(),
+        ..testnet
+    };
+    Blockchain::new(context.root_dir.clone(), name.clone(), config)
+        .map_err(failed("cannot create the blockchain"))?;
+
+    let blockchain =
+        Blockchain::load(&context.root_dir, name).map_err(failed("cannot load the blockchain"))?;
+    let (tip, is_genesis) = blockchain.load_tip();
+    if !is_genesis || tip.hash != blockchain.config.genesis {
+        return Err(format!(
+            "the tip of the new blockchain is {}, not the genesis",
+            tip.hash
+        ));
+    }
+
+    context.blockchain = Some(blockchain);
+    context.mock_chain = Some(mock_chain);
+    Ok(())
+}
+
+fn scenario_wallet_creation(context: &mut Context) -> ::std::result::Result<(), String> {
+    let entropy =
+        entropy::mnemonic_entropy(&mut *entropy::default_source(), bip39::Type::Type15Words);
+    let mnemonics = format!(
+        "{}",
+        entropy
+            .to_mnemonics()
+            .to_string(&bip39::dictionary::ENGLISH)
+    );
+    let xprv = xprv_from_entropy(&entropy);
+
+    let config = wallet::Config {
+        attached_blockchain: Some(NAME.to_owned()),
+        ..wallet::Config::default()
+    };
+    let name = WalletName::new(NAME.to_owned()).ok_or_else(|| "invalid wallet name".to_owned())?;
+    let encrypted_key = password_encrypted::encrypt(SPENDING_PASSWORD, xprv.as_ref());
+    Wallet::new(&context.root_dir, name.clone(), config, encrypted_key, None)
+        .save()
+        .map_err(failed("cannot save the wallet"))?;
+
+    let wallet = Wallet::load(&context.root_dir, name).map_err(failed("cannot load the wallet"))?;
+    if wallet
+        .get_wallet_bip44(b"not the spending password")
+        .is_ok()
+    {
+        return Err("the wallet's key can be decrypted with a wrong password".to_owned());
+    }
+    let loaded = wallet
+        .get_wallet_bip44(SPENDING_PASSWORD)
+        .map_err(failed("cannot decrypt the wallet's key"))?;
+
+    // the saved wallet and the generated key need to derive the same addresses
+    let protocol_magic = required(&context.blockchain, "blockchain")?
+        .config
+        .protocol_magic;
+    let addressing = fund_addressing()?;
+    let lookup = SequentialBip44Lookup::new(loaded, protocol_magic.into());
+    let expected = SequentialBip44Lookup::new(
+        bip44::Wallet::from_root_key(xprv.clone(), DerivationScheme::V2),
+        protocol_magic.into(),
+    );
+    if lookup.get_address(&addressing) != expected.get_address(&addressing) {
+        return Err("the saved wallet does not derive the addresses of its key".to_owned());
+    }
+
+    context.wallet = Some(wallet);
+    context.mnemonics = Some(mnemonics);
+    context.xprv = Some(xprv);
+    context.lookup = Some(lookup);
+    Ok(())
+}
+
+fn scenario_wallet_recovery(context: &mut Context) -> ::std::result::Result<(), String> {
+    let dic = bip39::dictionary::ENGLISH;
+    let string =
+        bip39::MnemonicString::new(&dic, required(&context.mnemonics, "mnemonics")?.clone())
+            .map_err(failed("invalid mnemonic phrase"))?;
+    let mnemonics =
+        bip39::Mnemonics::from_string(&dic, &string).map_err(failed("invalid mnemonic words"))?;
+    let entropy =
+        bip39::Entropy::from_mnemonics(&mnemonics).map_err(failed("invalid mnemonics checksum"))?;
+
+    let xprv = xprv_from_entropy(&entropy);
+    if xprv.as_ref() != required(&context.xprv, "wallet key")?.as_ref() {
+        return Err("the recovered key differs from the created one".to_owned());
+    }
+    Ok(())
+}
+
+fn scenario_wallet_state(context: &mut Context) -> ::std::result::Result<(), String> {
+    let lookup = context
+        .lookup
+        .take()
+        .ok_or_else(|| "needs the wallet of a previous scenario".to_owned())?;
+    let mut state = wallet::state::State::new(
+        StatePtr::new_before_genesis(
+            required(&context.blockchain, "blockchain")?
+                .config
+                .genesis
+                .clone(),
+        ),
+        lookup,
+    );
+    state
+        .lookup_struct
+        .prepare_next_account()
+        .map_err(failed("cannot prepare the wallet's first account"))?;
+
+    // mock a block of the first epoch paying to one of the wallet's
+    // addresses and to an address the wallet does not know
+    let epoch = required(&context.blockchain, "blockchain")?
+        .config
+        .epoch_start;
+    let ptr = StatePtr::new(
+        BlockDate::Normal(EpochSlotId { epoch, slotid: 1 }),
+        HeaderHash::from([0x5e; 32]),
+    );
+    let address = state.lookup_struct.get_address(&fund_addressing()?);
+    let foreign_address = {
+        let other = bip44::Wallet::from_root_key(
+            XPrv::normalize_bytes([0x42; hdwallet::XPRV_SIZE]),
+            DerivationScheme::V2,
+        );
+        let protocol_magic = required(&context.blockchain, "blockchain")?
+            .config
+            .protocol_magic;
+        SequentialBip44Lookup::new(other, protocol_magic.into()).get_address(&fund_addressing()?)
+    };
+    let value = Coin::new(FUND_VALUE).map_err(failed("invalid value"))?;
+    let utxos = vec![address, foreign_address]
+        .into_iter()
+        .enumerate()
+        .map(|(index, address)| {
+            let utxo = UTxO {
+                transaction_id: Blake2b256::new(NAME.as_bytes()),
+                index_in_transaction: index as u32,
+                credited_address: address.clone(),
+                credited_addressing: address,
+                credited_value: value,
+            };
+            (ptr.clone(), utxo)
+        })
+        .collect::<Vec<_>>();
+
+    let logs = state
+        .forward_with_utxos(utxos)
+        .map_err(failed("cannot look up the mocked outputs"))?;
+    if logs.len() != 1 {
+        return Err(format!("{} outputs recognised, expected 1", logs.len()));
+    }
+
+    let wallet = required(&context.wallet, "wallet")?;
+    {
+        let mut writer =
+            LogWriter::open(wallet.log().map_err(failed("cannot lock the wallet log"))?)
+                .map_err(failed("cannot open the wallet log"))?;
+        for log in logs.iter() {
+            writer
+                .append(log)
+                .map_err(failed("cannot write the wallet log"))?;
+        }
+    }
+
+    let reloaded =
+        create_wallet_state_from_logs(wallet, &context.root_dir, lookup::accum::Accum::default())
+            .map_err(failed("cannot load the wallet state from its log"))?;
+    let total = reloaded.total().map_err(failed("invalid total"))?;
+    if total != value {
+        return Err(format!(
+            "the wallet's balance is {}, expected {}",
+            total, value
+        ));
+    }
+
+    context.utxo = match logs.into_iter().next() {
+        Some(Log::ReceivedFund(_, utxo)) => Some(utxo),
+        _ => return Err("the recognised output is not logged as received fund".to_owned()),
+    };
+    context.lookup = Some(state.lookup_struct);
+    Ok(())
+}
+
+fn scenario_staging_transaction(context: &mut Context) -> ::std::result::Result<(), String> {
+    let protocol_magic = required(&context.blockchain, "blockchain")?
+        .config
+        .protocol_magic;
+    let utxo = required(&context.utxo, "wallet's fund")?;
+    let lookup = required(&context.lookup, "wallet")?;
+
+    let mut staging = StagingTransaction::new(context.root_dir.clone(), protocol_magic)
+        .map_err(failed("cannot create the staging transaction"))?;
+    staging
+        .add_input(Input {
+            transaction_id: utxo.transaction_id,
+            index_in_transaction: utxo.index_in_transaction,
+            expected_value: utxo.credited_value,
+        })
+        .map_err(failed("cannot add the input"))?;
+    staging
+        .add_output(Output {
+            address: lookup.get_address(&bip44::Addressing {
+                change: 1,
+                ..fund_addressing()?
+            }),
+            amount: Coin::new(SENT_VALUE).map_err(failed("invalid value"))?,
+        })
+        .map_err(failed("cannot add the output"))?;
+    staging
+        .finalize(&LinearFee::default())
+        .map_err(failed("cannot finalize"))?;
+
+    let txid = staging
+        .transaction()
+        .mk_txbuilder(&LinearFee::default())
+        .map_err(failed("invalid transaction"))?
+        .0
+        .make_tx()
+        .map_err(failed("invalid transaction"))?
+        .id();
+
+    // export, destroy and import back the staging transaction
+    let export = ::serde_yaml::to_string(&staging.export()).map_err(failed("cannot export"))?;
+    staging
+        .destroy()
+        .map_err(failed("cannot destroy the staging transaction"))?;
+    let export = ::serde_yaml::from_str(&export).map_err(failed("cannot read the export back"))?;
+    let staging = StagingTransaction::import(context.root_dir.clone(), export)
+        .map_err(failed("cannot import"))?;
+
+    let imported = staging
+        .transaction()
+        .mk_txbuilder(&LinearFee::default())
+        .map_err(failed("invalid imported transaction"))?
+        .0
+        .make_tx()
+        .map_err(failed("invalid imported transaction"))?
+        .id();
+    if imported != txid || !staging.is_finalized() {
+        return Err("the imported transaction differs from the exported one".to_owned());
+    }
+
+    context.staging = Some(staging);
+    Ok(())
+}
+
+fn scenario_signing(context: &mut Context) -> ::std::result::Result<(), String> {
+    let protocol_magic = required(&context.blockchain, "blockchain")?
+        .config
+        .protocol_magic;
+    let utxo = required(&context.utxo, "wallet's fund")?.clone();
+    let xprv = match utxo.credited_addressing {
+        lookup::Address::Bip44(ref addressing) => {
+            required(&context.lookup, "wallet")?.get_private_key(addressing)
+        }
+        _ => return Err("the fund is not credited to a BIP44 address".to_owned()),
+    };
+    let staging = context
+        .staging
+        .as_mut()
+        .ok_or_else(|| "needs the staging transaction of a previous scenario".to_owned())?;
+
+    let tx = staging
+        .transaction()
+        .mk_txbuilder(&LinearFee::default())
+        .map_err(failed("invalid transaction"))?
+        .0
+        .make_tx()
+        .map_err(failed("invalid transaction"))?;
+    let witness = TxInWitness::new(protocol_magic, &*xprv, &tx.id());
+    if !witness.verify_address(&utxo.credited_address) {
+        return Err("the witness does not match the address of the input".to_owned());
+    }
+    if !witness.verify_tx(protocol_magic, &tx) {
+        return Err("the witness does not verify the transaction".to_owned());
+    }
+
+    staging
+        .add_signature(witness)
+        .map_err(failed("cannot add the signature"))?;
+    let (finalized, _) = staging
+        .transaction()
+        .mk_finalized(&LinearFee::default())
+        .map_err(failed("cannot finalize the signed transaction"))?;
+    let txaux = finalized
+        .make_txaux()
+        .map_err(failed("cannot build the signed transaction"))?;
+
+    context.txaux = Some(txaux);
+    Ok(())
+}
+
+fn scenario_pending_journal(context: &mut Context) -> ::std::result::Result<(), String> {
+    let blockchain = required(&context.blockchain, "blockchain")?;
+    let staging = required(&context.staging, "staging transaction")?;
+    let txaux = required(&context.txaux, "signed transaction")?;
+    let txid = txaux.tx.id();
+
+    // record the transaction as `transaction send` does, without sending it
+    PendingTransaction::new(
+        *staging.id(),
+        NAME.to_owned(),
+        blockchain.load_tip().0.hash,
+        txaux,
+    )
+    .save(context.root_dir.clone())
+    .map_err(failed("cannot record the pending transaction"))?;
+
+    let pendings = PendingTransaction::list(context.root_dir.clone())
+        .map_err(failed("cannot list the pending transactions"))?;
+    if pendings.len() != 1 || pendings[0].txid != txid {
+        return Err("the pending transaction is not listed".to_owned());
+    }
+    let pending = PendingTransaction::load(context.root_dir.clone(), &txid)
+        .map_err(failed("cannot load the pending transaction"))?;
+    let recorded = pending
+        .txaux()
+        .map_err(failed("cannot decode the pending transaction"))?;
+    if recorded.tx.id() != txid || recorded.witness.len() != txaux.witness.len() {
+        return Err("the pending transaction differs from the signed one".to_owned());
+    }
+    pending
+        .remove(context.root_dir.clone())
+        .map_err(failed("cannot remove the pending transaction"))?;
+    Ok(())
+}
+
+fn scenario_rollback(context: &mut Context) -> ::std::result::Result<(), String> {
+    let blockchain = required(&context.blockchain, "blockchain")?;
+    let wallet = required(&context.wallet, "wallet")?;
+
+    // the block crediting the wallet was only mocked in the wallet log, the
+    // local blockchain never switched to it: the wallet needs rolling back.
+    let (ptr, removed) = rollback_wallet_log(wallet, blockchain)
+        .map_err(failed("cannot roll back the wallet"))?
+        .ok_or_else(|| "the wallet was not rolled back".to_owned())?;
+    if ptr.latest_addr.is_some() || removed.len() != 1 {
+        return Err(format!(
+            "rolled back to {} removing {} log entries",
+            ptr,
+            removed.len()
+        ));
+    }
+
+    let state =
+        create_wallet_state_from_logs(wallet, &context.root_dir, lookup::accum::Accum::default())
+            .map_err(failed("cannot load the wallet state from its log"))?;
+    let total = state.total().map_err(failed("invalid total"))?;
+    if total != Coin::zero() {
+        return Err(format!(
+            "the wallet's balance is {} after the rollback",
+            total
+        ));
+    }
+    Ok(())
+}
+
+fn scenario_chain_sync(
+    term: &mut Term,
+    context: &mut Context,
+) -> ::std::result::Result<(), String> {
+    let mock_chain = required(&context.mock_chain, "mock chain")?;
+    let (name, config) = {
+        let blockchain = required(&context.blockchain, "blockchain")?;
+        (blockchain.name.clone(), blockchain.config.clone())
+    };
+    let epoch_start = config.epoch_start;
+
+    // the mock peer is the same blockchain in another root directory,
+    // holding the blocks of the mock chain
+    let peer_root_dir = context.root_dir.join(MOCK_PEER);
+    let peer = Blockchain::new(peer_root_dir.clone(), name.clone(), config)
+        .map_err(failed("cannot create the mock peer"))?;
+    for (hash, raw) in mock_chain.iter() {
+        let blob_hash = storage::types::header_to_blockhash(hash);
+        storage::blob::write(&peer.storage, &blob_hash, raw.as_ref())
+            .map_err(failed("cannot write the mock chain"))?;
+    }
+    let tip = &mock_chain[mock_chain.len() - 1].0;
+    peer.save_tip(tip);
+
+    blockchain::commands::sync_from_local(
+        term,
+        context.root_dir.clone(),
+        name.clone(),
+        peer_root_dir,
+        None,
+        true,
+        None,
+    )
+    .map_err(failed("cannot sync from the mock peer"))?;
+
+    let blockchain =
+        Blockchain::load(&context.root_dir, name).map_err(failed("cannot load the blockchain"))?;
+    let local_tip = blockchain.load_tip().0;
+    if &local_tip.hash != tip {
+        return Err(format!(
+            "the local tip is {}, not the tip of the mock chain {}",
+            local_tip.hash, tip
+        ));
+    }
+    // only the last two epochs are not stable yet
+    for epoch in epoch_start..epoch_start + MOCK_EPOCHS - 2 {
+        if !blockchain::peer::internal::epoch_exists(&blockchain.storage, epoch) {
+            return Err(format!("the stable epoch {} is not packed", epoch));
+        }
+    }
+
+    context.blockchain = Some(blockchain);
+    Ok(())
+}
+
+fn scenario_network_broadcast(
+    term: &mut Term,
+    context: &mut Context,
+) -> ::std::result::Result<(), String> {
+    // the mock peer is down: nothing listens on its port anymore
+    let address = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map_err(failed("cannot find a free local port"))?;
+    {
+        let blockchain = context
+            .blockchain
+            .as_mut()
+            .ok_or_else(|| "needs the blockchain of a previous scenario".to_owned())?;
+        blockchain.add_peer(MOCK_PEER.to_owned(), address.to_string());
+        blockchain.save();
+    }
+
+    let blockchain = required(&context.blockchain, "blockchain")?;
+    let staging = required(&context.staging, "staging transaction")?;
+    let txaux = required(&context.txaux, "signed transaction")?;
+    let txid = txaux.tx.id();
+    PendingTransaction::new(
+        *staging.id(),
+        NAME.to_owned(),
+        blockchain.load_tip().0.hash,
+        txaux,
+    )
+    .save(context.root_dir.clone())
+    .map_err(failed("cannot record the pending transaction"))?;
+
+    match transaction::commands::resubmit(term, context.root_dir.clone(), txid.clone()) {
+        Err(transaction::error::Error::CannotSendTransactionNotSent) => {}
+        Err(err) => return Err(format!("cannot broadcast the transaction: {}", err)),
+        Ok(()) => return Err("the transaction was sent to a peer that is down".to_owned()),
+    }
+
+    // the transaction is not lost, it stays recorded to be resubmitted
+    let pending = PendingTransaction::load(context.root_dir.clone(), &txid)
+        .map_err(failed("the transaction is not recorded anymore"))?;
+    pending
+        .remove(context.root_dir.clone())
+        .map_err(failed("cannot remove the pending transaction"))?;
+    Ok(())
+}
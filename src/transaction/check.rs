@@ -0,0 +1,292 @@
+//! guardrails of the staging transactions
+//!
+//! An output worth less than the dust threshold costs about as much to
+//! spend as it is worth: `transaction add-output` warns about (or rejects)
+//! them and `transaction check` reports them, along with the change left
+//! below the threshold, the transactions with too many outputs and the ones
+//! larger than the nodes accept, before the transaction is finalized.
+//!
+//! Once signed, `validate` gives the reasons the nodes would reject the
+//! transaction (`transaction send --dry-run`): missing or invalid
+//! witnesses, a fee below the fee policy or a size above the maximum size
+//! of the protocol parameters. The protocol parameters are the ones of the
+//! local blockchain (its genesis data), not asked to a peer.
+
+use super::core::{self, Transaction};
+use cardano::{
+    address::ExtendedAddr,
+    coin::Coin,
+    config::ProtocolMagic,
+    fee::{self, FeeAlgorithm, LinearFee},
+    tx::{self, Tx, TxAux, TxInWitness},
+};
+
+use std::{fmt, iter};
+
+/// the default dust threshold, in lovelace
+pub const DEFAULT_DUST_THRESHOLD: u64 = 1_000_000;
+
+/// the default maximum number of outputs of a transaction
+pub const DEFAULT_MAX_OUTPUTS: usize = 50;
+
+/// the maximum size of a transaction, in bytes, when the blockchain does
+/// not give one (the `maxTxSize` of the mainnet)
+pub const DEFAULT_MAX_TX_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub dust_threshold: Coin,
+    pub max_outputs: usize,
+    /// in bytes, the signed transaction included
+    pub max_tx_size: u64,
+}
+
+#[derive(Debug)]
+pub enum Issue {
+    DustOutput(ExtendedAddr, Coin),
+    DustChange(ExtendedAddr, Coin),
+    TooManyOutputs(usize, usize), // (outputs, maximum)
+    Oversize(u64, u64),           // (size, maximum)
+}
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::DustOutput(address, value) => write!(f, "the output to {} of {} is dust", address, value),
+            Issue::DustChange(address, value) => write!(
+                f,
+                "the change to {} of {} is dust, add it to an output or to the fee (`transaction set-fee')",
+                address, value
+            ),
+            Issue::TooManyOutputs(outputs, max) => {
+                write!(f, "the transaction has {} outputs, more than {}", outputs, max)
+            }
+            Issue::Oversize(size, max) => write!(
+                f,
+                "the signed transaction will be {} bytes, larger than the {} bytes the nodes accept",
+                size, max
+            ),
+        }
+    }
+}
+
+/// is the value below the dust threshold
+pub fn is_dust(value: Coin, limits: &Limits) -> bool {
+    value < limits.dust_threshold
+}
+
+/// the issues of the transaction, the change and the size being the ones
+/// of the transaction built with the given fee algorithm
+pub fn check(
+    transaction: &Transaction,
+    fee_algorithm: &LinearFee,
+    limits: &Limits,
+) -> Result<Vec<Issue>, core::transaction::Error> {
+    let mut issues = Vec::new();
+
+    for output in transaction.outputs() {
+        if is_dust(output.amount, limits) {
+            issues.push(Issue::DustOutput(output.address.clone(), output.amount));
+        }
+    }
+
+    let (builder, changes) = transaction.mk_txbuilder(fee_algorithm)?;
+    for change in changes.iter() {
+        if is_dust(change.value, limits) {
+            issues.push(Issue::DustChange(change.address.clone(), change.value));
+        }
+    }
+
+    let outputs = transaction.outputs().len() + changes.len();
+    if outputs > limits.max_outputs {
+        issues.push(Issue::TooManyOutputs(outputs, limits.max_outputs));
+    }
+
+    let tx = builder.make_tx().unwrap_or_else(|_| Tx::new());
+    let fake_witnesses: Vec<TxInWitness> = iter::repeat(TxInWitness::fake())
+        .take(transaction.inputs().len())
+        .collect();
+    let size = tx::txaux_serialize_size(&tx, &fake_witnesses) as u64;
+    if size > limits.max_tx_size {
+        issues.push(Issue::Oversize(size, limits.max_tx_size));
+    }
+
+    Ok(issues)
+}
+
+#[derive(Debug)]
+pub enum Rejection {
+    MissingWitnesses(usize, usize), // (witnesses, inputs)
+    InvalidWitness(usize),
+    WitnessDoesNotMatchAddress(usize, ExtendedAddr),
+    FeeTooLow(u64, Coin), // (fee, fee of the fee policy)
+    Oversize(u64, u64),   // (size, maximum)
+}
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::MissingWitnesses(witnesses, inputs) => write!(
+                f,
+                "the transaction has {} witnesses for {} inputs, sign it first",
+                witnesses, inputs
+            ),
+            Rejection::InvalidWitness(index) => {
+                write!(
+                    f,
+                    "the witness of input {} does not sign the transaction",
+                    index
+                )
+            }
+            Rejection::WitnessDoesNotMatchAddress(index, address) => write!(
+                f,
+                "the witness of input {} is not from the key of its address {}",
+                index, address
+            ),
+            Rejection::FeeTooLow(fee, expected) => write!(
+                f,
+                "the fee of {} lovelace is below the {} of the fee policy",
+                fee, expected
+            ),
+            Rejection::Oversize(size, max) => write!(
+                f,
+                "the signed transaction is {} bytes, larger than the {} bytes the nodes accept",
+                size, max
+            ),
+        }
+    }
+}
+
+/// the reasons the nodes would reject the signed transaction, spending
+/// inputs worth `input_total`. `addresses` gives the address of each input,
+/// in the order of the inputs, when it is known: the witness of the input
+/// must be from its key.
+pub fn validate(
+    txaux: &TxAux,
+    protocol_magic: ProtocolMagic,
+    addresses: &[Option<ExtendedAddr>],
+    input_total: Coin,
+    fee_algorithm: &LinearFee,
+    max_tx_size: u64,
+) -> Result<Vec<Rejection>, fee::Error> {
+    let mut rejections = Vec::new();
+
+    let inputs = txaux.tx.inputs.len();
+    if txaux.witness.len() != inputs {
+        rejections.push(Rejection::MissingWitnesses(txaux.witness.len(), inputs));
+    }
+    for (index, witness) in txaux.witness.iter().enumerate() {
+        if !witness.verify_tx(protocol_magic, &txaux.tx) {
+            rejections.push(Rejection::InvalidWitness(index));
+        }
+        if let Some(Some(address)) = addresses.get(index) {
+            if !witness.verify_address(address) {
+                rejections.push(Rejection::WitnessDoesNotMatchAddress(
+                    index,
+                    address.clone(),
+                ));
+            }
+        }
+    }
+
+    let expected = fee_algorithm.calculate_for_txaux(txaux)?.to_coin();
+    // the outputs are checked against the inputs when finalizing
+    let output_total: u64 = txaux
+        .tx
+        .outputs
+        .iter()
+        .map(|output| u64::from(output.value))
+        .sum();
+    let fee = u64::from(input_total).saturating_sub(output_total);
+    if fee < u64::from(expected) {
+        rejections.push(Rejection::FeeTooLow(fee, expected));
+    }
+
+    let size = tx::txaux_serialize_size(&txaux.tx, &txaux.witness) as u64;
+    if size > max_tx_size {
+        rejections.push(Rejection::Oversize(size, max_tx_size));
+    }
+
+    Ok(rejections)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{
+        hash::Blake2b256,
+        tx::{TxOut, TxWitness, TxoPointer},
+    };
+    use utils::testing::{address, xprv};
+
+    /// a transaction spending one input, signed with the key of the seed
+    fn txaux(seed: u8) -> TxAux {
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(Blake2b256::new(&[1]), 0)],
+            vec![TxOut::new(address(3), Coin::new(1_000_000).unwrap())],
+        );
+        let witness = TxInWitness::new(ProtocolMagic::from(764824073), &xprv(seed), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    fn rejections(txaux: &TxAux, addresses: &[Option<ExtendedAddr>]) -> Vec<Rejection> {
+        validate(
+            txaux,
+            ProtocolMagic::from(764824073),
+            addresses,
+            Coin::new(2_000_000).unwrap(),
+            &LinearFee::default(),
+            DEFAULT_MAX_TX_SIZE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn valid_transaction() {
+        assert!(rejections(&txaux(1), &[Some(address(1))]).is_empty());
+        // the address of an input no wallet owns is not known
+        assert!(rejections(&txaux(2), &[None]).is_empty());
+    }
+
+    #[test]
+    fn witness_of_another_key() {
+        match rejections(&txaux(2), &[Some(address(1))]).as_slice() {
+            [Rejection::WitnessDoesNotMatchAddress(0, rejected)] => {
+                assert_eq!(rejected, &address(1))
+            }
+            other => panic!("unexpected rejections: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn witness_of_another_network() {
+        let rejected = validate(
+            &txaux(1),
+            ProtocolMagic::from(1097911063),
+            &[None],
+            Coin::new(2_000_000).unwrap(),
+            &LinearFee::default(),
+            DEFAULT_MAX_TX_SIZE,
+        )
+        .unwrap();
+        match rejected.as_slice() {
+            [Rejection::InvalidWitness(0)] => {}
+            other => panic!("unexpected rejections: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fee_too_low() {
+        let rejected = validate(
+            &txaux(1),
+            ProtocolMagic::from(764824073),
+            &[Some(address(1))],
+            Coin::new(1_000_001).unwrap(),
+            &LinearFee::default(),
+            DEFAULT_MAX_TX_SIZE,
+        )
+        .unwrap();
+        match rejected.as_slice() {
+            [Rejection::FeeTooLow(1, _)] => {}
+            other => panic!("unexpected rejections: {:?}", other),
+        }
+    }
+}
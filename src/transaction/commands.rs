@@ -1,17 +1,26 @@
-use super::super::blockchain::{Blockchain, BlockchainName};
-use super::super::wallet::{
-    self, state::lookup, utils::create_wallet_state_from_logs, Wallet, WalletName, Wallets,
-};
+use super::super::address_book::{AddressBook, LABEL_PREFIX};
+use super::super::blockchain::{health, peer::Peer, proxy, seeds, Blockchain, BlockchainName};
+use super::super::wallet::{self, lease, utils::load_utxo_index, Wallet, WalletName, Wallets};
+use super::super::{profile, schema};
+use super::check;
 use super::core::{self, StagingId, StagingTransaction};
+use super::diff;
 use super::error::Error;
+use super::signer;
+use super::signing;
+use super::staging_fee_algorithm;
 use cardano::{
     address::ExtendedAddr,
     coin::{sum_coins, Coin},
+    config::ProtocolMagic,
     fee::{FeeAlgorithm, LinearFee},
     tx::{self, Tx, TxId, TxInWitness, TxoPointer},
     wallet::scheme::SelectionPolicy,
 };
-use utils::term::{style::Style, Term};
+use utils::{
+    clock, logging, qr,
+    term::{style::Style, Term},
+};
 
 use std::{
     collections::BTreeMap,
@@ -69,29 +78,245 @@ pub fn send(
     root_dir: PathBuf,
     id_str: &str,
     blockchain: BlockchainName,
+    proxy: Option<String>,
+    fee_policy: Option<LinearFee>,
+    dry_run: bool,
+    clock_check: &clock::Config,
 ) -> Result<(), Error> {
-    let blockchain = Blockchain::load(root_dir.clone(), blockchain)?;
+    let mut blockchain = Blockchain::load(root_dir.clone(), blockchain)?;
+    if let Some(address) = proxy {
+        proxy::validate(&address)?;
+        blockchain.proxy = Some(address);
+    }
+    clock::check(
+        term,
+        clock_check,
+        blockchain.proxy.as_ref().map(|proxy| proxy.as_str()),
+    )
+    .map_err(Error::CannotSendTransactionClockSkewed)?;
     let staging = load_staging(root_dir.clone(), id_str)?;
+    warn_unpinned_fee(&staging);
+    let fee_algorithm = match fee_policy {
+        Some(fee_policy) => fee_policy,
+        None => blockchain.fee_policy()?,
+    };
 
     let (finalized, changes) = staging
         .transaction()
-        .mk_finalized()
+        .mk_finalized(&fee_algorithm)
         .map_err(Error::CannotSendTransactionNotFinalized)?;
     let txaux = finalized
         .make_txaux()
         .map_err(Error::CannotSendTransactionInvalidTxAux)?;
 
+    if dry_run {
+        return dry_run_send(
+            term,
+            &root_dir,
+            &mut blockchain,
+            &staging,
+            &txaux,
+            &fee_algorithm,
+        );
+    }
+
+    // record the signed transaction before broadcasting it, so it can be
+    // resubmitted even if none of the peers accepted it.
+    let pending = core::PendingTransaction::new(
+        staging.id(),
+        blockchain.name.to_string(),
+        blockchain.load_tip().0.hash,
+        &txaux,
+    );
+    pending
+        .save(root_dir)
+        .map_err(Error::CannotRecordPendingTransaction)?;
+
     writeln!(term, "sending transaction {}", style!(txaux.tx.id()))?;
 
+    broadcast(term, &mut blockchain, txaux)
+}
+
+/// check the signed transaction as the nodes would, against the protocol
+/// parameters of the local blockchain, after asking a peer for its tip: the
+/// transaction is neither sent nor recorded in the pending journal
+fn dry_run_send(
+    term: &mut Term,
+    root_dir: &Path,
+    blockchain: &mut Blockchain,
+    staging: &StagingTransaction,
+    txaux: &tx::TxAux,
+    fee_algorithm: &LinearFee,
+) -> Result<(), Error> {
+    seeds::add_seed_peers(blockchain)?;
+    let (local_tip, _) = blockchain.load_tip();
+    let mut peer_tip = None;
+    for peer_name in health::ordered_peers(blockchain, true) {
+        let peer = Peer::prepare(blockchain, peer_name.clone());
+        // the peer is reported unreachable by `connect'
+        if let Ok(mut connected) = peer.connect(term) {
+            peer_tip = Some((peer_name, connected.query_tip()));
+            break;
+        }
+    }
+    let (peer_name, peer_tip) = peer_tip.ok_or(Error::CannotDryRunNoPeerReachable)?;
+    writeln!(
+        term,
+        "peer {} tip: {} (local tip: {})",
+        style!(peer_name.as_str()),
+        peer_tip.date,
+        local_tip.date
+    )?;
+    if peer_tip.date > local_tip.date {
+        term.warn(
+            "the local blockchain is behind the peer, the protocol parameters and the unspent outputs of the wallets may be outdated (`blockchain pull')\n",
+        )?;
+    }
+
+    let max_tx_size = blockchain.max_tx_size().unwrap_or_else(|err| {
+        warn!(
+            "cannot read the maximum transaction size of blockchain {}: {}",
+            blockchain.name, err
+        );
+        None
+    });
+    let max_tx_size = max_tx_size.unwrap_or(check::DEFAULT_MAX_TX_SIZE);
+    let input_total = sum_coins(
+        staging
+            .transaction()
+            .inputs()
+            .iter()
+            .map(|input| input.expected_value),
+    )
+    .map_err(Error::CannotReportStatusInvalidInputTotal)?;
+
+    // the unattached wallets have no unspent outputs to look the inputs up
+    let indexes: Vec<_> = Wallets::load(root_dir)?
+        .into_iter()
+        .filter_map(|(_, wallet)| load_utxo_index(&wallet, root_dir).ok())
+        .collect();
+    // the witnesses of the inputs the wallets own must be from their keys
+    let addresses: Vec<_> = txaux
+        .tx
+        .inputs
+        .iter()
+        .map(|txin| {
+            indexes
+                .iter()
+                .filter_map(|index| index.get(txin))
+                .map(|utxo| utxo.credited_address.clone())
+                .next()
+        })
+        .collect();
+    let rejections = check::validate(
+        txaux,
+        staging.protocol_magic,
+        &addresses,
+        input_total,
+        fee_algorithm,
+        max_tx_size,
+    )
+    .map_err(Error::CannotReportStatusInvalidFee)?;
+
+    for input in staging.transaction().inputs() {
+        let txin = input.extract_txin();
+        if !indexes.iter().any(|index| index.get(&txin).is_some()) {
+            term.warn(&format!(
+                "input {}.{} is not among the unspent outputs of the wallets, it may be spent already\n",
+                txin.id, txin.index
+            ))?;
+        }
+    }
+
+    if rejections.is_empty() {
+        term.success(&format!(
+            "transaction {} would be accepted, it was not sent\n",
+            style!(txaux.tx.id())
+        ))?;
+        return Ok(());
+    }
+    for rejection in rejections.iter() {
+        term.error(&format!("{}\n", rejection))?;
+    }
+    Err(Error::TransactionDryRunRejected(rejections.len()))
+}
+
+/// list the transactions sent but not yet seen in the local blockchain
+///
+/// the transactions found in the blocks of the local blockchain are removed
+/// from the journal.
+pub fn pending(term: &mut Term, root_dir: PathBuf) -> Result<(), Error> {
+    let pendings = core::PendingTransaction::list(root_dir.clone())
+        .map_err(Error::CannotListPendingTransactions)?;
+
+    for pending in pendings {
+        let confirmed = match pending.blockchain.parse::<BlockchainName>() {
+            Err(_) => None,
+            Ok(name) => match Blockchain::load(root_dir.clone(), name) {
+                Err(_) => None,
+                Ok(blockchain) => find_transaction_since(&blockchain, &pending),
+            },
+        };
+
+        if let Some(date) = confirmed {
+            writeln!(
+                term,
+                "{} {} confirmed in block {}",
+                style!(pending.txid),
+                style!(pending.blockchain),
+                style!(date)
+            )?;
+            pending
+                .remove(root_dir.clone())
+                .map_err(Error::CannotListPendingTransactions)?;
+        } else {
+            writeln!(
+                term,
+                "{} {} pending (sent {}, staging {})",
+                style!(pending.txid),
+                style!(pending.blockchain),
+                pending.sent_at(),
+                style!(pending.staging_id)
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// send again a transaction recorded in the pending journal
+pub fn resubmit(term: &mut Term, root_dir: PathBuf, txid: TxId) -> Result<(), Error> {
+    let pending = core::PendingTransaction::load(root_dir.clone(), &txid)
+        .map_err(Error::CannotResubmitTransaction)?;
+    let txaux = pending.txaux().map_err(Error::CannotResubmitTransaction)?;
+    let name = pending
+        .blockchain
+        .parse::<BlockchainName>()
+        .map_err(|_| Error::CannotResubmitUnknownBlockchain(pending.blockchain.clone()))?;
+    let mut blockchain = Blockchain::load(root_dir, name)?;
+
+    writeln!(term, "resending transaction {}", style!(txaux.tx.id()))?;
+
+    broadcast(term, &mut blockchain, txaux)
+}
+
+/// send the given transaction to the native peers of the blockchain, the
+/// best ones first, until one of them accepts it
+fn broadcast(term: &mut Term, blockchain: &mut Blockchain, txaux: tx::TxAux) -> Result<(), Error> {
+    seeds::add_seed_peers(blockchain)?;
+
     let mut sent = false;
-    for np in blockchain.peers() {
-        if !np.is_native() {
-            continue;
+    for peer_name in health::ordered_peers(blockchain, true) {
+        if sent {
+            break;
         }
 
-        let peer = super::super::blockchain::peer::Peer::prepare(&blockchain, np.name().to_owned());
+        let peer = Peer::prepare(blockchain, peer_name);
 
-        sent = sent || peer.connect(term).unwrap().send_txaux(txaux.clone());
+        // the peer is reported unreachable by `connect'
+        if let Ok(connected) = peer.connect(term) {
+            sent = connected.send_txaux(txaux.clone());
+        }
     }
 
     if sent {
@@ -101,14 +326,80 @@ pub fn send(
     }
 }
 
-pub fn sign(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Error> {
+pub fn sign(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    sign_staging(term, &root_dir, &mut staging, fee_policy)
+}
+
+/// sign the inputs of the staging transaction with the external signer,
+/// given the addresses and derivation paths of the inputs the wallets own
+pub fn sign_with_signer(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    signer_name: &str,
+    signer: &profile::Signer,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let mut staging = load_staging(root_dir.clone(), id_str)?;
+
+    let mut indexes = Vec::new();
+    for (name, wallet) in Wallets::load(&root_dir)? {
+        match load_utxo_index(&wallet, &root_dir) {
+            Ok(index) => indexes.push((wallet, index)),
+            Err(e) => {
+                term.warn(&format!("cannot load wallet {}: {}\n", name, e))?;
+            }
+        }
+    }
+
+    warn_unpinned_fee(&staging);
+    let fee_algorithm = staging_fee_algorithm(&root_dir, &staging, fee_policy);
+    let info = signing::signing_info(&staging, &indexes, &fee_algorithm)?;
+    term.info(&format!(
+        "signing {} inputs with signer {}\n",
+        style!(info.inputs.len()),
+        style!(signer_name)
+    ))?;
+
+    let witnesses =
+        signer::sign(signer_name, signer, &info).map_err(Error::CannotSignTransactionWithSigner)?;
+    for (input, witness) in info.inputs.iter().zip(witnesses) {
+        staging
+            .add_signature(witness)
+            .map_err(Error::CannotSignTransactionCannotAddSignature)?;
+        info!(
+            "input {}.{} of staging transaction {} signed with signer {} by {}",
+            input.txid,
+            input.index,
+            staging.id(),
+            signer_name,
+            logging::originator()
+        );
+    }
+    Ok(())
+}
+
+/// sign the inputs of the staging transaction with the keys of the wallets
+/// owning them, their spending passwords being asked one after the other
+pub fn sign_staging(
+    term: &mut Term,
+    root_dir: &Path,
+    staging: &mut StagingTransaction,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
     let mut signatures = Vec::new();
 
     let mut wallets = BTreeMap::new();
-    for (name, wallet) in Wallets::load(root_dir.clone())? {
-        match create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default()) {
-            Ok(state) => {
-                wallets.insert(name, (wallet, state));
+    for (name, wallet) in Wallets::load(root_dir)? {
+        match load_utxo_index(&wallet, root_dir) {
+            Ok(index) => {
+                wallets.insert(name, (wallet, index));
             }
             Err(e) => {
                 term.warn(&format!("cannot load wallet {}: {}", name, e))
@@ -117,14 +408,15 @@ pub fn sign(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Erro
         }
     }
 
-    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    warn_unpinned_fee(staging);
+    let fee_algorithm = staging_fee_algorithm(root_dir, staging, fee_policy);
     let (finalized, changes) = staging
         .transaction()
-        .mk_finalized()
+        .mk_finalized(&fee_algorithm)
         .map_err(Error::CannotSignTransactionNotFinalized)?;
     let tx = staging
         .transaction()
-        .mk_txbuilder()
+        .mk_txbuilder(&fee_algorithm)
         .map_err(Error::CannotSignTransactionNotFinalized)?
         .0
         .make_tx()
@@ -136,8 +428,8 @@ pub fn sign(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Erro
     for input in staging.transaction().inputs() {
         let txin = input.extract_txin();
         let mut signature = None;
-        for (name, (wallet, state)) in wallets.iter() {
-            if let Some(utxo) = state.utxos.get(&txin) {
+        for (name, (wallet, index)) in wallets.iter() {
+            if let Some(utxo) = index.get(&txin) {
                 term.info(&format!(
                     "signing input {}.{} ({})\n",
                     style!(input.transaction_id),
@@ -152,7 +444,15 @@ pub fn sign(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Erro
                     protocol_magic,
                     &txid,
                     &utxo.credited_addressing,
-                ));
+                )?);
+                info!(
+                    "input {}.{} of staging transaction {} signed with wallet {} by {}",
+                    input.transaction_id,
+                    input.index_in_transaction,
+                    staging.id(),
+                    name,
+                    logging::originator()
+                );
             }
         }
 
@@ -171,8 +471,18 @@ pub fn sign(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Erro
     Ok(())
 }
 
-pub fn status(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Error> {
-    let staging = load_staging(root_dir, id_str)?;
+pub fn status(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let staging = load_staging(root_dir.clone(), id_str)?;
+    let fee_algorithm = staging_fee_algorithm(&root_dir, &staging, fee_policy);
+    // the labels are only displayed, the status does not need them
+    let address_book = AddressBook::load(&root_dir)
+        .map_err(|err| warn!("cannot load the address book: {}", err))
+        .ok();
 
     let trans = staging.transaction();
     let inputs = trans.inputs();
@@ -180,7 +490,7 @@ pub fn status(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Er
         .map_err(Error::CannotReportStatusInvalidInputTotal)?;
     let (builder, changes) = staging
         .transaction()
-        .mk_txbuilder()
+        .mk_txbuilder(&fee_algorithm)
         .map_err(Error::CannotReportStatusInvalidTxBuilder)?;
     let tx = builder.make_tx().unwrap_or_else(|_| Tx::new());
     let output_total = tx
@@ -192,11 +502,10 @@ pub fn status(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Er
         (i as i64) - (o as i64)
     };
 
-    let fee_alg = LinearFee::default();
     let fake_witnesses: Vec<TxInWitness> = iter::repeat(TxInWitness::fake())
         .take(inputs.len())
         .collect();
-    let fee = fee_alg
+    let fee = fee_algorithm
         .calculate_for_txaux_component(&tx, &fake_witnesses)
         .map_err(Error::CannotReportStatusInvalidFee)?;
 
@@ -210,7 +519,20 @@ pub fn status(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Er
         difference / 1000000,
         difference % 1000000
     )?;
-    writeln!(term, "fee: {}", fee.to_coin())?;
+    match trans.fee() {
+        None => writeln!(term, "fee: {}", fee.to_coin())?,
+        Some(set_fee) => {
+            writeln!(
+                term,
+                "fee: {} (set, the fee policy asks for {})",
+                set_fee,
+                fee.to_coin()
+            )?;
+            if set_fee < fee.to_coin() {
+                term.warn("the fee set is below the fee the fee policy asks for, the transaction may be rejected\n")?;
+            }
+        }
+    }
     writeln!(term, "tx-bytes: {}", txbytes_length)?;
 
     writeln!(term, "inputs:")?;
@@ -219,10 +541,16 @@ pub fn status(term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Er
     }
     writeln!(term, "outputs:")?;
     for output in tx.outputs.iter() {
+        let label = address_book
+            .as_ref()
+            .and_then(|book| book.label_of(&output.address))
+            .map(|label| format!(" ({}{})", LABEL_PREFIX, label))
+            .unwrap_or_default();
         writeln!(
             term,
-            "  {} {}",
+            "  {}{} {}",
             style!(&output.address),
+            label,
             style!(output.value)
         )?;
     }
@@ -239,14 +567,7 @@ pub fn add_input(
     let mut staging = load_staging(root_dir.clone(), id_str)?;
 
     let input = if let Some(input) = input {
-        match input.2 {
-            None => find_input_in_all_utxos(&root_dir, input.0, input.1)?,
-            Some(v) => core::Input {
-                transaction_id: input.0,
-                index_in_transaction: input.1,
-                expected_value: v,
-            },
-        }
+        input_of(term, &root_dir, input.0, input.1, input.2)?
     } else {
         // TODO, implement interactive mode
         unimplemented!()
@@ -255,19 +576,49 @@ pub fn add_input(
     staging.add_input(input).map_err(Error::CannotAddInput)
 }
 
+/// the input spending the output, of the given value or of the value found
+/// in the wallets (warning if the output is leased)
+pub fn input_of(
+    term: &mut Term,
+    root_dir: &Path,
+    txid: TxId,
+    index: u32,
+    value: Option<Coin>,
+) -> Result<core::Input, Error> {
+    match value {
+        None => {
+            let (input, lease) = find_input_in_all_utxos(root_dir, txid, index)?;
+            if let Some(lease) = lease {
+                term.warn(&format!(
+                    "{} for {} more seconds\n",
+                    lease,
+                    lease.remaining().as_secs()
+                ))?;
+            }
+            Ok(input)
+        }
+        Some(v) => Ok(core::Input {
+            transaction_id: txid,
+            index_in_transaction: index,
+            expected_value: v,
+        }),
+    }
+}
+
+/// add the output to the transaction, the outputs below the dust threshold
+/// are rejected if `reject_dust` is set, added with a warning otherwise
 pub fn add_output(
-    _term: &mut Term,
+    term: &mut Term,
     root_dir: PathBuf,
     id_str: &str,
     output: Option<(ExtendedAddr, Coin)>,
+    dust_threshold: Coin,
+    reject_dust: bool,
 ) -> Result<(), Error> {
     let mut staging = load_staging(root_dir, id_str)?;
 
     let output = if let Some(output) = output {
-        core::Output {
-            address: output.0,
-            amount: output.1,
-        }
+        checked_output(term, output.0, output.1, dust_threshold, reject_dust)?
     } else {
         // TODO, implement interactive mode
         unimplemented!()
@@ -276,6 +627,27 @@ pub fn add_output(
     staging.add_output(output).map_err(Error::CannotAddOutput)
 }
 
+/// the output, unless it is below the dust threshold and `reject_dust` is
+/// set (it is only warned about otherwise)
+pub fn checked_output(
+    term: &mut Term,
+    address: ExtendedAddr,
+    amount: Coin,
+    dust_threshold: Coin,
+    reject_dust: bool,
+) -> Result<core::Output, Error> {
+    if amount < dust_threshold {
+        if reject_dust {
+            return Err(Error::CannotAddOutputDust(amount, dust_threshold));
+        }
+        term.warn(&format!(
+            "the output of {} is below the dust threshold ({}), it will cost about as much to spend\n",
+            amount, dust_threshold
+        ))?;
+    }
+    Ok(core::Output { address, amount })
+}
+
 pub fn add_change(
     _term: &mut Term,
     root_dir: PathBuf,
@@ -341,44 +713,145 @@ pub fn remove_change(
         .map_err(Error::CannotRemoveChange)
 }
 
-pub fn finalize(_term: &mut Term, root_dir: PathBuf, id_str: &str) -> Result<(), Error> {
+pub fn set_fee(_term: &mut Term, root_dir: PathBuf, id_str: &str, fee: Coin) -> Result<(), Error> {
     let mut staging = load_staging(root_dir, id_str)?;
 
-    staging.finalize().map_err(Error::CannotFinalize)
+    staging.set_fee(fee).map_err(Error::CannotSetFee)
+}
+
+/// report the dust outputs and change, the excessive number of outputs and
+/// the oversize transaction. Fails if there is any issue.
+pub fn check(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    fee_policy: Option<LinearFee>,
+    dust_threshold: Coin,
+    max_outputs: usize,
+) -> Result<(), Error> {
+    let staging = load_staging(root_dir.clone(), id_str)?;
+    check_staging(
+        term,
+        &root_dir,
+        &staging,
+        fee_policy,
+        dust_threshold,
+        max_outputs,
+    )
 }
 
+/// report the issues of the staging transaction, fails if there is any
+pub fn check_staging(
+    term: &mut Term,
+    root_dir: &Path,
+    staging: &StagingTransaction,
+    fee_policy: Option<LinearFee>,
+    dust_threshold: Coin,
+    max_outputs: usize,
+) -> Result<(), Error> {
+    let fee_algorithm = staging_fee_algorithm(root_dir, staging, fee_policy);
+    let max_tx_size = match Blockchain::find_by_protocol_magic(root_dir, staging.protocol_magic) {
+        None => None,
+        Some(blockchain) => blockchain.max_tx_size().unwrap_or_else(|err| {
+            warn!(
+                "cannot read the maximum transaction size of blockchain {}: {}",
+                blockchain.name, err
+            );
+            None
+        }),
+    };
+    let max_tx_size = max_tx_size.unwrap_or(check::DEFAULT_MAX_TX_SIZE);
+    let limits = check::Limits {
+        dust_threshold,
+        max_outputs,
+        max_tx_size,
+    };
+
+    let issues = check::check(staging.transaction(), &fee_algorithm, &limits)
+        .map_err(Error::CannotCheckInvalidTxBuilder)?;
+    if issues.is_empty() {
+        term.success("no issues\n")?;
+        return Ok(());
+    }
+    for issue in issues.iter() {
+        term.warn(&format!("{}\n", issue))?;
+    }
+    Err(Error::TransactionCheckFailed(issues.len()))
+}
+
+pub fn finalize(
+    _term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    let fee_algorithm = staging_fee_algorithm(&root_dir, &staging, fee_policy);
+
+    staging
+        .finalize(&fee_algorithm)
+        .map_err(Error::CannotFinalize)
+}
+
+/// the prefix of the QR code chunks of the exported staging transactions
+pub const EXPORT_QR_PREFIX: &'static str = "cardano-cli-export";
+
 pub fn export(
     term: &mut Term,
     root_dir: PathBuf,
     id_str: &str,
     export_file: Option<&str>,
+    qr_output: Option<qr::Output>,
 ) -> Result<(), Error> {
     let staging = load_staging(root_dir, id_str)?;
 
     let export = staging.export();
 
-    if let Some(export_file) = export_file {
-        let mut file = ::std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(export_file)
-            .map_err(Error::CannotExportToFileCannotOpenOutFile)?;
-        ::serde_yaml::to_writer(&mut file, &export).map_err(Error::CannotExportToFile)
+    if let Some(output) = qr_output {
+        let text = ::serde_yaml::to_string(&export).map_err(Error::CannotExportToStdout)?;
+        let chunks = qr::split_chunks(EXPORT_QR_PREFIX, text.as_bytes());
+        let rendered = qr::render_all(&chunks, &output).map_err(Error::CannotExportToQrCode)?;
+        write!(term, "{}", rendered)?;
+        Ok(())
+    } else if let Some(export_file) = export_file {
+        export_to_file(&staging, Path::new(export_file))
     } else {
         ::serde_yaml::to_writer(term, &export).map_err(Error::CannotExportToStdout)
     }
 }
 
+/// export the staging transaction to the file, for `import`
+pub fn export_to_file(staging: &StagingTransaction, export_file: &Path) -> Result<(), Error> {
+    let mut file = ::std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(export_file)
+        .map_err(Error::CannotExportToFileCannotOpenOutFile)?;
+    ::serde_yaml::to_writer(&mut file, &staging.export()).map_err(Error::CannotExportToFile)
+}
+
+/// import the staging transaction exported with `export`, or the text of
+/// the QR code chunks of the export (one per line, in any order)
 pub fn import(term: &mut Term, root_dir: PathBuf, import_file: Option<&str>) -> Result<(), Error> {
-    let import = if let Some(import_file) = import_file {
-        let mut file = ::std::fs::OpenOptions::new()
-            .read(true)
-            .open(import_file)
-            .map_err(Error::CannotImportFromFileCannotOpenInputFile)?;
-        ::serde_yaml::from_reader(&mut file).map_err(Error::CannotImportFromFile)?
+    use std::io::Read;
+
+    let text = if let Some(import_file) = import_file {
+        ::std::fs::read_to_string(import_file)
+            .map_err(Error::CannotImportFromFileCannotOpenInputFile)?
     } else {
-        let mut stdin = ::std::io::stdin();
-        ::serde_yaml::from_reader(&mut stdin).map_err(Error::CannotImportFromStdin)?
+        let mut text = String::new();
+        ::std::io::stdin().read_to_string(&mut text)?;
+        text
+    };
+
+    let import = if qr::is_chunks(EXPORT_QR_PREFIX, &text) {
+        let bytes =
+            qr::join_chunks(EXPORT_QR_PREFIX, &text).map_err(Error::CannotImportFromQrCode)?;
+        ::serde_yaml::from_slice(&bytes).map_err(Error::CannotImportFromFile)?
+    } else if import_file.is_some() {
+        ::serde_yaml::from_str(&text).map_err(Error::CannotImportFromFile)?
+    } else {
+        ::serde_yaml::from_str(&text).map_err(Error::CannotImportFromStdin)?
     };
 
     let staging =
@@ -391,19 +864,254 @@ pub fn import(term: &mut Term, root_dir: PathBuf, import_file: Option<&str>) ->
     Ok(())
 }
 
+/// the staging transaction, or the exported one if `source` is a file
+fn load_transaction_or_export(
+    root_dir: &Path,
+    source: &str,
+) -> Result<(ProtocolMagic, core::Transaction), Error> {
+    if !Path::new(source).is_file() {
+        let staging = load_staging(root_dir.to_path_buf(), source)?;
+        return Ok((staging.protocol_magic, staging.transaction().clone()));
+    }
+    let text = ::std::fs::read_to_string(source).map_err(Error::CannotDiffCannotOpenExportFile)?;
+    let export: core::staging_transaction::Export = if qr::is_chunks(EXPORT_QR_PREFIX, &text) {
+        let bytes =
+            qr::join_chunks(EXPORT_QR_PREFIX, &text).map_err(Error::CannotDiffInvalidQrCode)?;
+        ::serde_yaml::from_slice(&bytes).map_err(Error::CannotDiffInvalidExportFile)?
+    } else {
+        ::serde_yaml::from_str(&text).map_err(Error::CannotDiffInvalidExportFile)?
+    };
+    Ok((export.protocol_magic, export.transaction().clone()))
+}
+
+/// print what changed from the first staging transaction to the second
+/// one (staging identifiers or files written by `export`)
+pub fn diff(
+    term: &mut Term,
+    root_dir: PathBuf,
+    from: &str,
+    to: &str,
+    json: bool,
+) -> Result<(), Error> {
+    let (from_protocol_magic, from) = load_transaction_or_export(&root_dir, from)?;
+    let (to_protocol_magic, to) = load_transaction_or_export(&root_dir, to)?;
+    let diff = diff::Diff::new(from_protocol_magic, &from, to_protocol_magic, &to);
+
+    if json {
+        schema::TRANSACTION_DIFF.debug_check(&diff);
+        let json = ::serde_json::to_string_pretty(&diff)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+        writeln!(term, "{}", json)?;
+        return Ok(());
+    }
+
+    if diff.is_empty() {
+        term.success("the staging transactions are identical\n")?;
+        return Ok(());
+    }
+
+    if let Some(ref protocol_magic) = diff.protocol_magic {
+        writeln!(
+            term,
+            "protocol magic: {} -> {} (not the same network)",
+            style!(protocol_magic.from).red(),
+            style!(protocol_magic.to).green()
+        )?;
+    }
+    if !diff.inputs.is_empty() {
+        writeln!(term, "inputs:")?;
+        for input in diff.inputs.removed.iter() {
+            let line = format!(
+                "- {}.{} {}",
+                input.transaction_id, input.index_in_transaction, input.expected_value
+            );
+            writeln!(term, "  {}", style!(line).red())?;
+        }
+        for input in diff.inputs.added.iter() {
+            let line = format!(
+                "+ {}.{} {}",
+                input.transaction_id, input.index_in_transaction, input.expected_value
+            );
+            writeln!(term, "  {}", style!(line).green())?;
+        }
+        print_reordered(term, &diff.inputs.reordered, |input| {
+            format!("{}.{}", input.transaction_id, input.index_in_transaction)
+        })?;
+    }
+    if !diff.outputs.is_empty() {
+        writeln!(term, "outputs:")?;
+        for output in diff.outputs.removed.iter() {
+            writeln!(
+                term,
+                "  {}",
+                style!(format!("- {} {}", output.address, output.amount)).red()
+            )?;
+        }
+        for output in diff.outputs.added.iter() {
+            writeln!(
+                term,
+                "  {}",
+                style!(format!("+ {} {}", output.address, output.amount)).green()
+            )?;
+        }
+        print_reordered(term, &diff.outputs.reordered, |output| {
+            format!("{} {}", output.address, output.amount)
+        })?;
+    }
+    if !diff.changes.is_empty() {
+        writeln!(term, "changes:")?;
+        for change in diff.changes.removed.iter() {
+            writeln!(term, "  {}", style!(format!("- {}", change.address)).red())?;
+        }
+        for change in diff.changes.added.iter() {
+            writeln!(
+                term,
+                "  {}",
+                style!(format!("+ {}", change.address)).green()
+            )?;
+        }
+        print_reordered(term, &diff.changes.reordered, |change| {
+            change.address.to_string()
+        })?;
+    }
+    if !diff.signatures.is_empty() {
+        writeln!(term, "signatures:")?;
+        for witness in diff.signatures.removed.iter() {
+            let line = format!("- {}", diff::describe_signature(witness));
+            writeln!(term, "  {}", style!(line).red())?;
+        }
+        for witness in diff.signatures.added.iter() {
+            let line = format!("+ {}", diff::describe_signature(witness));
+            writeln!(term, "  {}", style!(line).green())?;
+        }
+        print_reordered(term, &diff.signatures.reordered, diff::describe_signature)?;
+    }
+    if let Some(ref fee) = diff.fee {
+        let display = |fee: Option<Coin>| {
+            fee.map(|fee| fee.to_string())
+                .unwrap_or_else(|| "not set".to_owned())
+        };
+        writeln!(
+            term,
+            "fee: {} -> {}",
+            style!(display(fee.from)).red(),
+            style!(display(fee.to)).green()
+        )?;
+    }
+    if let Some(ref finalized) = diff.finalized {
+        writeln!(
+            term,
+            "finalized: {} -> {}",
+            style!(finalized.from.to_string()).red(),
+            style!(finalized.to.to_string()).green()
+        )?;
+    }
+    Ok(())
+}
+
+/// print the elements both staging transactions have, in the order of
+/// each, if it changed
+fn print_reordered<T, F>(
+    term: &mut Term,
+    reordered: &Option<diff::Modified<Vec<T>>>,
+    describe: F,
+) -> ::std::io::Result<()>
+where
+    F: Fn(&T) -> String,
+{
+    if let Some(ref reordered) = reordered {
+        let order = |elements: &[T]| {
+            elements
+                .iter()
+                .map(&describe)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(term, "  reordered:")?;
+        writeln!(
+            term,
+            "    {}",
+            style!(format!("- {}", order(&reordered.from))).red()
+        )?;
+        writeln!(
+            term,
+            "    {}",
+            style!(format!("+ {}", order(&reordered.to))).green()
+        )?;
+    }
+    Ok(())
+}
+
+/// print, in JSON, the transaction to sign and the address and derivation
+/// path of each of its inputs in the given wallets, for an external signer
+pub fn signing_info(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    wallets: Vec<WalletName>,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let staging = load_staging(root_dir.clone(), id_str)?;
+    let mut loaded = Vec::new();
+    for wallet in wallets {
+        loaded.push(Wallet::load(&root_dir, wallet)?);
+    }
+    let indexes = signing::load_indexes(&root_dir, loaded)?;
+
+    warn_unpinned_fee(&staging);
+    let fee_algorithm = staging_fee_algorithm(&root_dir, &staging, fee_policy);
+    let info = signing::signing_info(&staging, &indexes, &fee_algorithm)?;
+
+    schema::TRANSACTION_SIGNING_INFO.debug_check(&info);
+    let json = ::serde_json::to_string_pretty(&info)
+        .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+    writeln!(term, "{}", json)?;
+
+    // the JSON is the only output, for the signers to read it
+    for input in info.unowned_inputs() {
+        warn!(
+            "input {}.{} is not owned by the given wallets",
+            input.txid, input.index
+        );
+    }
+    Ok(())
+}
+
 pub fn input_select(
     term: &mut Term,
     root_dir: PathBuf,
     id_str: &str,
     wallets: Vec<WalletName>,
     selection_type: SelectionPolicy,
+    lease_owner: Option<String>,
+    fee_policy: Option<LinearFee>,
 ) -> Result<(), Error> {
-    use cardano::input_selection::{Blackjack, HeadFirst, LargestFirst};
-    use cardano::{fee, input_selection::InputSelectionAlgorithm, txutils};
+    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    input_select_staging(
+        &root_dir,
+        &mut staging,
+        wallets,
+        selection_type,
+        lease_owner,
+        fee_policy,
+    )
+}
 
-    let alg = fee::LinearFee::default();
+/// add the inputs the selection algorithm selects among the unspent
+/// outputs of the wallets (but the ones leased to another owner) to pay
+/// the outputs of the staging transaction
+pub fn input_select_staging(
+    root_dir: &Path,
+    staging: &mut StagingTransaction,
+    wallets: Vec<WalletName>,
+    selection_type: SelectionPolicy,
+    lease_owner: Option<String>,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    use cardano::input_selection::{Blackjack, HeadFirst, LargestFirst};
+    use cardano::{input_selection::InputSelectionAlgorithm, txutils};
 
-    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    let fee_algorithm = staging_fee_algorithm(root_dir, staging, fee_policy);
 
     if !staging.transaction().has_change() {
         return Err(Error::CannotInputSelectNoChangeOption);
@@ -418,17 +1126,17 @@ pub fn input_select(
         .iter()
         .map(|output| output.into())
         .collect::<Vec<_>>();
-    let inputs = list_input_inputs(&root_dir, wallets)?;
+    let inputs = list_input_inputs(root_dir, wallets, lease_owner.as_ref().map(|o| o.as_str()))?;
 
     let selection_result = match selection_type {
         SelectionPolicy::Blackjack(threshold) => {
-            Blackjack::new(threshold, inputs).compute(&alg, outputs, &output_policy)
+            Blackjack::new(threshold, inputs).compute(&fee_algorithm, outputs, &output_policy)
         }
         SelectionPolicy::LargestFirst => {
-            LargestFirst::from(inputs).compute(&alg, outputs, &output_policy)
+            LargestFirst::from(inputs).compute(&fee_algorithm, outputs, &output_policy)
         }
         SelectionPolicy::FirstMatchFirst => {
-            HeadFirst::from(inputs).compute(&alg, outputs, &output_policy)
+            HeadFirst::from(inputs).compute(&fee_algorithm, outputs, &output_policy)
         }
     };
     let selection_result = selection_result.map_err(Error::CannotInputSelectSelectionFailed)?;
@@ -445,6 +1153,345 @@ pub fn input_select(
     Ok(())
 }
 
+/// select the inputs the outputs still need in the wallets, set the change
+/// address and finalize the staging transaction in one step
+pub fn seal(
+    term: &mut Term,
+    root_dir: PathBuf,
+    id_str: &str,
+    wallets: Vec<WalletName>,
+    change: Option<ExtendedAddr>,
+    fee_policy: Option<LinearFee>,
+) -> Result<(), Error> {
+    let mut staging = load_staging(root_dir.clone(), id_str)?;
+    let fee_algorithm = staging_fee_algorithm(&root_dir, &staging, fee_policy);
+    let sealed = seal_staging(&root_dir, &mut staging, wallets, change, &fee_algorithm)?;
+
+    for input in sealed.added_inputs.iter() {
+        writeln!(
+            term,
+            "input {}.{} {}",
+            style!(input.transaction_id),
+            style!(input.index_in_transaction),
+            style!(input.expected_value)
+        )?;
+    }
+    writeln!(term, "fee: {}", style!(sealed.fee))?;
+    match sealed.change {
+        Some(ref change) => writeln!(
+            term,
+            "change: {} {}",
+            style!(&change.address),
+            style!(change.value)
+        )?,
+        None => writeln!(term, "change: none")?,
+    }
+    term.success(&format!(
+        "staging transaction {} finalized\n",
+        style!(staging.id())
+    ))?;
+    Ok(())
+}
+
+/// what `seal_staging` did to the staging transaction
+pub struct Sealed {
+    pub added_inputs: Vec<core::Input>,
+    /// the fee of the transaction, as pinned when finalizing (or set)
+    pub fee: Coin,
+    pub change: Option<tx::TxOut>,
+}
+
+/// add to the staging transaction the unspent outputs of the wallets its
+/// inputs miss to pay the outputs and the fee (the largest first, the
+/// ones leased to others left out), replace its change address with the
+/// given one and finalize it, pinning its fee. The transaction is balanced
+/// in memory first, the inputs added are leased to the staging transaction
+/// (see `lease::staging_owner`) and the operations appended are rolled back
+/// (and the leases released) if one of them fails.
+pub fn seal_staging(
+    root_dir: &Path,
+    staging: &mut StagingTransaction,
+    wallets: Vec<WalletName>,
+    change: Option<ExtendedAddr>,
+    fee_algorithm: &LinearFee,
+) -> Result<Sealed, Error> {
+    if staging.is_finalized() {
+        return Err(Error::CannotSealFinalizedTransaction);
+    }
+
+    let mut operations = Vec::new();
+    let current_change = staging
+        .transaction()
+        .changes()
+        .first()
+        .map(|change| change.address.clone());
+    let change_address = match (change, current_change) {
+        (None, None) => return Err(Error::CannotSealNoChangeAddress),
+        (None, Some(current)) => current,
+        (Some(address), current) => {
+            if current.as_ref() != Some(&address) {
+                if let Some(current) = current {
+                    operations.push(core::Operation::RemoveChange(current));
+                }
+                operations.push(core::Operation::AddChange(core::Change::from(
+                    address.clone(),
+                )));
+            }
+            address
+        }
+    };
+
+    // the unspent outputs not already spent by the transaction, the largest
+    // first, with the leases of their wallet: the leases files stay locked
+    // until the inputs added are leased
+    let owner = lease::staging_owner(staging.id());
+    let mut wallets = wallets;
+    // each leases file is locked once
+    wallets.sort();
+    wallets.dedup();
+    let mut wallet_leases = Vec::new();
+    let mut candidates = Vec::new();
+    for wallet in wallets {
+        let wallet = Wallet::load(root_dir, wallet)?;
+        let index = load_utxo_index(&wallet, root_dir)?;
+        let leases = lease::Leases::open(&wallet)?;
+        let position = wallet_leases.len();
+        candidates.extend(
+            index
+                .utxos()
+                .filter(|utxo| {
+                    staging
+                        .transaction()
+                        .lookup_input(utxo.extract_txin())
+                        .is_none()
+                })
+                .filter(|utxo| {
+                    !leases.is_leased_to_other(&utxo.extract_txin(), Some(owner.as_str()))
+                })
+                .map(|utxo| {
+                    let txin = utxo.extract_txin();
+                    let input = core::Input {
+                        transaction_id: txin.id,
+                        index_in_transaction: txin.index,
+                        expected_value: utxo.credited_value,
+                    };
+                    (position, input)
+                }),
+        );
+        wallet_leases.push(leases);
+    }
+    candidates.sort_by_key(|(_, input)| ::std::cmp::Reverse(u64::from(input.expected_value)));
+
+    let mut working = staging.transaction().clone();
+    for operation in operations.iter() {
+        working
+            .update_with(operation.clone())
+            .map_err(Error::CannotSealInvalidTransaction)?;
+    }
+    let added = add_balancing_inputs(&mut working, candidates, &change_address, fee_algorithm)?;
+    let added_inputs: Vec<core::Input> = added.iter().map(|(_, input)| input.clone()).collect();
+    operations.extend(added_inputs.iter().cloned().map(core::Operation::AddInput));
+    operations.push(core::Operation::Finalize);
+
+    // the transaction must finalize, its fee pinned as `finalize` pins it,
+    // before anything is appended
+    if !working.inputs().is_empty() {
+        let fee = working
+            .checked_fee(fee_algorithm)
+            .map_err(Error::CannotSealInvalidTransaction)?;
+        if working.fee().is_none() {
+            working
+                .update_with(core::Operation::SetFee(fee))
+                .map_err(Error::CannotSealInvalidTransaction)?;
+        }
+    }
+    working
+        .update_with(core::Operation::Finalize)
+        .map_err(Error::CannotSealInvalidTransaction)?;
+    let (_, changes) = working
+        .mk_finalized(fee_algorithm)
+        .map_err(Error::CannotSealInvalidTransaction)?;
+
+    for (granted, (position, input)) in added.iter().enumerate() {
+        let txin = input.extract_txin();
+        if let Err(err) = wallet_leases[*position].grant(txin, owner.clone(), lease::STAGING_TTL) {
+            release_leases(&mut wallet_leases, &added[..granted], &owner);
+            return Err(err.into());
+        }
+    }
+    let start = staging.operations().len();
+    for operation in operations {
+        if let Err(err) = append_operation(staging, operation, fee_algorithm) {
+            rollback_operations(staging, start);
+            release_leases(&mut wallet_leases, &added, &owner);
+            return Err(Error::CannotSeal(err));
+        }
+    }
+    info!(
+        "staging transaction {} sealed with {} more inputs by {}",
+        staging.id(),
+        added_inputs.len(),
+        logging::originator()
+    );
+
+    Ok(Sealed {
+        added_inputs,
+        fee: staging.transaction().fee().unwrap_or_else(Coin::zero),
+        change: changes.into_iter().next(),
+    })
+}
+
+/// add the candidates to the inputs of the transaction, in order, until
+/// they pay its outputs and its fee (see `balance`), returns the ones added
+fn add_balancing_inputs<T>(
+    transaction: &mut core::Transaction,
+    candidates: Vec<(T, core::Input)>,
+    change: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+) -> Result<Vec<(T, core::Input)>, Error> {
+    let mut candidates = candidates.into_iter();
+    let mut added = Vec::new();
+    loop {
+        let (available, needed) = balance(transaction, change, fee_algorithm)?;
+        if available >= needed {
+            return Ok(added);
+        }
+        match candidates.next() {
+            Some((key, input)) => {
+                transaction
+                    .update_with(core::Operation::AddInput(input.clone()))
+                    .map_err(Error::CannotSealInvalidTransaction)?;
+                added.push((key, input));
+            }
+            None => {
+                return Err(Error::CannotSealNotEnoughFunds(
+                    coin_of(needed)?,
+                    coin_of(available)?,
+                ));
+            }
+        }
+    }
+}
+
+/// release the leases granted to `owner` on the inputs, each one in the
+/// leases of its wallet
+fn release_leases(
+    wallet_leases: &mut [lease::Leases],
+    inputs: &[(usize, core::Input)],
+    owner: &str,
+) {
+    for (position, input) in inputs {
+        if let Err(err) = wallet_leases[*position].release(&input.extract_txin(), owner) {
+            warn!(
+                "cannot release the lease of input {}.{}: {}",
+                input.transaction_id, input.index_in_transaction, err
+            );
+        }
+    }
+}
+
+/// the value of the inputs of the transaction and what it needs to pay
+/// the outputs and the fee: the fee set, or the fee of the fee policy for
+/// the transaction with a change output
+fn balance(
+    transaction: &core::Transaction,
+    change: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+) -> Result<(u64, u64), Error> {
+    let available: u64 = transaction
+        .inputs()
+        .iter()
+        .map(|input| u64::from(input.expected_value))
+        .sum();
+    let outputs: u64 = transaction
+        .outputs()
+        .iter()
+        .map(|output| u64::from(output.amount))
+        .sum();
+    let fee = match transaction.fee() {
+        Some(fee) => u64::from(fee),
+        None => {
+            // the change is at most the value of the inputs, a larger
+            // value than the actual change overestimates its size
+            let mut txouts: Vec<tx::TxOut> = transaction
+                .outputs()
+                .iter()
+                .map(|output| output.into())
+                .collect();
+            txouts.push(tx::TxOut {
+                address: change.clone(),
+                value: coin_of(available)?,
+            });
+            let mut tx = Tx::new();
+            tx.inputs = transaction
+                .inputs()
+                .iter()
+                .map(|input| input.extract_txin())
+                .collect();
+            tx.outputs = txouts;
+            let fake_witnesses: Vec<TxInWitness> = iter::repeat(TxInWitness::fake())
+                .take(transaction.inputs().len())
+                .collect();
+            let fee = fee_algorithm
+                .calculate_for_txaux_component(&tx, &fake_witnesses)
+                .map_err(Error::CannotReportStatusInvalidFee)?;
+            u64::from(fee.to_coin())
+        }
+    };
+    Ok((available, outputs + fee))
+}
+
+fn coin_of(value: u64) -> Result<Coin, Error> {
+    Coin::new(value).map_err(Error::CannotSealInvalidTotal)
+}
+
+fn append_operation(
+    staging: &mut StagingTransaction,
+    operation: core::Operation,
+    fee_algorithm: &LinearFee,
+) -> Result<(), core::staging_transaction::StagingUpdateError> {
+    match operation {
+        core::Operation::AddInput(input) => staging.add_input(input),
+        core::Operation::AddChange(change) => staging.add_change(change),
+        core::Operation::RemoveChange(address) => staging.remove_change(address),
+        core::Operation::Finalize => staging.finalize(fee_algorithm),
+        operation => unreachable!("not an operation of seal: {:?}", operation),
+    }
+}
+
+/// undo the operations appended to the staging transaction since the
+/// `start`th one, the last one first
+fn rollback_operations(staging: &mut StagingTransaction, start: usize) {
+    let appended: Vec<core::Operation> = staging.operations()[start..].iter().cloned().collect();
+    for operation in appended.into_iter().rev() {
+        let undone = match operation {
+            core::Operation::AddInput(input) => staging.remove_input(input.extract_txin()),
+            core::Operation::AddChange(change) => staging.remove_change(change.address),
+            core::Operation::RemoveChange(address) => {
+                staging.add_change(core::Change::from(address))
+            }
+            // the fee pinned when finalizing cannot be unset, the inputs
+            // added are kept so the transaction still pays it
+            core::Operation::SetFee(_) => {
+                error!(
+                    "cannot roll back the staging transaction {}: its fee is pinned",
+                    staging.id()
+                );
+                return;
+            }
+            operation => unreachable!("not an operation of seal: {:?}", operation),
+        };
+        if let Err(err) = undone {
+            error!(
+                "cannot roll back the staging transaction {}: {}",
+                staging.id(),
+                err
+            );
+            return;
+        }
+    }
+}
+
 /// helper function to load a staging file
 fn load_staging(root_dir: PathBuf, id_str: &str) -> Result<StagingTransaction, Error> {
     let id = id_str
@@ -456,53 +1503,212 @@ fn load_staging(root_dir: PathBuf, id_str: &str) -> Result<StagingTransaction, E
 
 // ----------------------------------- helpers ---------------------------------
 
-fn find_input_in_all_utxos(root_dir: &Path, txid: TxId, index: u32) -> Result<core::Input, Error> {
+/// warn that the change of the staging transaction, finalized before the
+/// fee was pinned when finalizing, is computed with the current fee policy
+fn warn_unpinned_fee(staging: &StagingTransaction) {
+    let transaction = staging.transaction();
+    if transaction.is_finalized() && transaction.fee().is_none() && !transaction.inputs().is_empty()
+    {
+        warn!(
+            "the fee of staging transaction {} is not pinned, its change (and id) depend on the fee policy",
+            staging.id()
+        );
+    }
+}
+
+/// look for the unspent output in all the wallets, returns it with its
+/// lease if it is leased
+fn find_input_in_all_utxos(
+    root_dir: &Path,
+    txid: TxId,
+    index: u32,
+) -> Result<(core::Input, Option<lease::Lease>), Error> {
     let txin = TxoPointer {
         id: txid,
         index: index,
     };
     for (_, wallet) in Wallets::load(root_dir)? {
-        let state = match create_wallet_state_from_logs(
-            &wallet,
-            &root_dir,
-            lookup::accum::Accum::default(),
-        ) {
-            Ok(state) => state,
+        let index = match load_utxo_index(&wallet, &root_dir) {
+            Ok(index) => index,
             Err(_) => {
                 // Silently ignore bad or unattached wallets
                 continue;
             }
         };
 
-        if let Some(utxo) = state.utxos.get(&txin) {
+        if let Some(utxo) = index.get(&txin) {
+            let lease = lease::Leases::open(&wallet)?.get(&txin).cloned();
             let txin = utxo.extract_txin();
-            return Ok(core::Input {
-                transaction_id: txin.id,
-                index_in_transaction: txin.index,
-                expected_value: utxo.credited_value,
-            });
+            return Ok((
+                core::Input {
+                    transaction_id: txin.id,
+                    index_in_transaction: txin.index,
+                    expected_value: utxo.credited_value,
+                },
+                lease,
+            ));
         }
     }
 
     Err(Error::CannotFindInputsInAllLocalUtxos)
 }
 
+/// look for the pending transaction in the blocks appended to the local
+/// blockchain since the transaction was sent, returns the date of the block
+/// it was found in.
+fn find_transaction_since(
+    blockchain: &Blockchain,
+    pending: &core::PendingTransaction,
+) -> Option<::cardano::block::BlockDate> {
+    // the block we were on when sending may have been rolled back, in
+    // that case we cannot tell and we leave the transaction pending.
+    let iter = blockchain.iter_to_tip(pending.sent_at_tip.clone()).ok()?;
+    for res in iter {
+        let (_, block) = res.ok()?;
+        if let Some(txs) = block.get_transactions() {
+            if txs.iter().any(|txaux| txaux.tx.id() == pending.txid) {
+                return Some(block.get_header().get_blockdate());
+            }
+        }
+    }
+    None
+}
+
+/// list the unspent outputs of the wallets, except the ones leased to
+/// another owner than `lease_owner`
 fn list_input_inputs(
     root_dir: &Path,
     wallets: Vec<WalletName>,
+    lease_owner: Option<&str>,
 ) -> Result<Vec<::cardano::txutils::Input<ExtendedAddr>>, Error> {
     let mut inputs = Vec::new();
     for wallet in wallets {
         let wallet = Wallet::load(root_dir, wallet)?;
-        let state =
-            create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default())?;
-
-        inputs.extend(state.utxos.iter().map(|(_, utxo)| {
-            let txin = utxo.extract_txin();
-            let txout = utxo.extract_txout();
-            ::cardano::txutils::Input::new(txin, txout, utxo.credited_address.clone())
-        }))
+        let index = load_utxo_index(&wallet, &root_dir)?;
+        let leases = lease::Leases::open(&wallet)?;
+
+        inputs.extend(
+            index
+                .utxos()
+                .filter(|utxo| !leases.is_leased_to_other(&utxo.extract_txin(), lease_owner))
+                .map(|utxo| {
+                    let txin = utxo.extract_txin();
+                    let txout = utxo.extract_txout();
+                    ::cardano::txutils::Input::new(txin, txout, utxo.credited_address.clone())
+                }),
+        )
     }
 
     Ok(inputs)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utils::testing::{address, input};
+
+    /// a transaction paying the amount to an address
+    fn paying(amount: u64) -> core::Transaction {
+        let mut transaction = core::Transaction::new();
+        transaction
+            .update_with(core::Operation::AddOutput(core::Output {
+                address: address(1),
+                amount: Coin::new(amount).unwrap(),
+            }))
+            .unwrap();
+        transaction
+    }
+
+    #[test]
+    fn balance_with_the_fee_set() {
+        let mut transaction = paying(1_000_000);
+        transaction
+            .update_with(core::Operation::AddInput(input(1, 2_000_000)))
+            .unwrap();
+        transaction
+            .update_with(core::Operation::SetFee(Coin::new(200_000).unwrap()))
+            .unwrap();
+        let (available, needed) =
+            balance(&transaction, &address(2), &LinearFee::default()).unwrap();
+        assert_eq!(available, 2_000_000);
+        assert_eq!(needed, 1_200_000);
+    }
+
+    #[test]
+    fn balance_with_the_fee_policy() {
+        let mut transaction = paying(1_000_000);
+        let fee_algorithm = LinearFee::default();
+        let (available, needed) = balance(&transaction, &address(2), &fee_algorithm).unwrap();
+        assert_eq!(available, 0);
+        // the constant of the fee policy, and the size of the transaction
+        assert!(needed > 1_155_381, "{}", needed);
+
+        // each input makes the transaction larger, so the fee as well
+        transaction
+            .update_with(core::Operation::AddInput(input(1, 2_000_000)))
+            .unwrap();
+        let (available, needed_with_input) =
+            balance(&transaction, &address(2), &fee_algorithm).unwrap();
+        assert_eq!(available, 2_000_000);
+        assert!(needed_with_input > needed);
+    }
+
+    #[test]
+    fn add_the_inputs_needed() {
+        let mut transaction = paying(7_000_000);
+        let candidates = vec![
+            (0, input(1, 5_000_000)),
+            (1, input(2, 3_000_000)),
+            (2, input(3, 1_000_000)),
+        ];
+        let added = add_balancing_inputs(
+            &mut transaction,
+            candidates,
+            &address(2),
+            &LinearFee::default(),
+        )
+        .unwrap();
+        let keys: Vec<_> = added.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![0, 1]);
+        assert_eq!(transaction.inputs().len(), 2);
+        let (available, needed) =
+            balance(&transaction, &address(2), &LinearFee::default()).unwrap();
+        assert!(available >= needed);
+    }
+
+    #[test]
+    fn add_no_input_to_a_balanced_transaction() {
+        let mut transaction = paying(1_000_000);
+        transaction
+            .update_with(core::Operation::AddInput(input(1, 5_000_000)))
+            .unwrap();
+        let added = add_balancing_inputs(
+            &mut transaction,
+            vec![(0, input(2, 3_000_000))],
+            &address(2),
+            &LinearFee::default(),
+        )
+        .unwrap();
+        assert!(added.is_empty());
+        assert_eq!(transaction.inputs().len(), 1);
+    }
+
+    #[test]
+    fn not_enough_funds() {
+        let mut transaction = paying(100_000_000);
+        let candidates = vec![(0, input(1, 5_000_000)), (1, input(2, 3_000_000))];
+        match add_balancing_inputs(
+            &mut transaction,
+            candidates,
+            &address(2),
+            &LinearFee::default(),
+        ) {
+            Err(Error::CannotSealNotEnoughFunds(needed, available)) => {
+                assert_eq!(available, Coin::new(8_000_000).unwrap());
+                assert!(needed > Coin::new(100_000_000).unwrap());
+            }
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(added) => panic!("{} inputs added", added.len()),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! audit log of the staging transactions
+//!
+//! Every operation appended to a staging transaction (the inputs and the
+//! outputs added or removed, the fee pinned, the finalization, the
+//! signatures) is recorded in the audit log of the root directory, one JSON
+//! object per line, with the date and the originator of the operation (see
+//! `utils::logging::originator`). The log is only appended to, it is never
+//! rotated.
+//!
+
+use super::config;
+use super::{Operation, StagingId};
+use serde_json;
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::SystemTime,
+};
+use utils::logging;
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    time: String,
+    staging_id: &'a StagingId,
+    originator: String,
+    operation: &'a Operation,
+}
+
+/// record the operation appended to the staging transaction
+pub fn record(root_dir: PathBuf, id: &StagingId, operation: &Operation) -> io::Result<()> {
+    let entry = Entry {
+        time: format!("{}", ::humantime::format_rfc3339_seconds(SystemTime::now())),
+        staging_id: id,
+        originator: logging::originator(),
+        operation: operation,
+    };
+    let mut line =
+        serde_json::to_string(&entry).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    line.push('\n');
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config::audit_file(root_dir))?
+        .write_all(line.as_bytes())
+}
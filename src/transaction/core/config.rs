@@ -1,4 +1,5 @@
 use super::StagingId;
+use cardano::tx::TxId;
 use std::path::PathBuf;
 
 const TRANSACTION_DIR: &'static str = "transactions";
@@ -12,3 +13,22 @@ pub fn transaction_directory(root_dir: PathBuf) -> PathBuf {
 pub fn transaction_file(root_dir: PathBuf, id: StagingId) -> PathBuf {
     transaction_directory(root_dir).join(id.to_string())
 }
+
+const PENDING_DIR: &'static str = "pending";
+
+/// return the directory path where the journal of the sent transactions is
+pub fn pending_directory(root_dir: PathBuf) -> PathBuf {
+    root_dir.join(PENDING_DIR)
+}
+
+/// get the path of the journal entry of the given sent transaction
+pub fn pending_file(root_dir: PathBuf, txid: &TxId) -> PathBuf {
+    pending_directory(root_dir).join(txid.to_string())
+}
+
+const AUDIT_FILE: &'static str = "transactions.audit";
+
+/// get the path of the audit log of the staging transactions' operations
+pub fn audit_file(root_dir: PathBuf) -> PathBuf {
+    root_dir.join(AUDIT_FILE)
+}
@@ -1,10 +1,13 @@
+pub mod audit;
 pub mod config;
 pub mod operation;
+pub mod pending;
 pub mod staging_id;
 pub mod staging_transaction;
 pub mod transaction;
 
 pub use self::operation::{Change, Input, Operation, Output};
+pub use self::pending::PendingTransaction;
 pub use self::staging_id::StagingId;
 pub use self::staging_transaction::StagingTransaction;
 pub use self::transaction::Transaction;
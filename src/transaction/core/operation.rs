@@ -52,6 +52,11 @@ pub enum Operation {
     ///
     RemoveOutput(u32),
 
+    /// pin the fee of the transaction, in place of the one computed with
+    /// the fee policy: the change gets what the inputs leave once the
+    /// outputs and this fee are paid
+    SetFee(Coin),
+
     /// add a transaction signature
     Signature(TxInWitness),
 
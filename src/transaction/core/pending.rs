@@ -0,0 +1,176 @@
+//! journal of the transactions sent to the network
+//!
+//! Every time a transaction is sent with `transaction send` we record the
+//! signed transaction (the raw `TxAux` bytes) in the journal, so it is
+//! possible to list the transactions that have not been seen in the local
+//! blockchain yet and to resubmit them without rebuilding them.
+//!
+
+use super::config;
+use super::StagingId;
+use cardano::{block::HeaderHash, tx::TxAux, tx::TxId, util::hex};
+use cbor_event::{self, de::Deserializer, se::Serializer};
+use serde_yaml;
+use std::{error, fmt, fs, io, path::PathBuf, time::SystemTime};
+use utils::time::Time;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    InvalidJournalEntry(serde_yaml::Error),
+    InvalidRawTransaction(cbor_event::Error),
+    InvalidRawTransactionEncoding(hex::Error),
+    NotFound(TxId),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Error::InvalidJournalEntry(e)
+    }
+}
+impl From<cbor_event::Error> for Error {
+    fn from(e: cbor_event::Error) -> Self {
+        Error::InvalidRawTransaction(e)
+    }
+}
+impl From<hex::Error> for Error {
+    fn from(e: hex::Error) -> Self {
+        Error::InvalidRawTransactionEncoding(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O error"),
+            Error::InvalidJournalEntry(_) => write!(f, "Invalid entry in the pending journal"),
+            Error::InvalidRawTransaction(_) => write!(f, "Invalid raw transaction in journal"),
+            Error::InvalidRawTransactionEncoding(_) => {
+                write!(f, "Invalid raw transaction encoding in journal")
+            }
+            Error::NotFound(txid) => write!(f, "Transaction `{}' is not in the journal", txid),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::InvalidJournalEntry(ref err) => Some(err),
+            Error::InvalidRawTransaction(ref err) => Some(err),
+            Error::InvalidRawTransactionEncoding(ref err) => Some(err),
+            Error::NotFound(_) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// a signed transaction sent to the network, not yet seen in the
+/// local copy of the blockchain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub txid: TxId,
+
+    /// the staging transaction this transaction has been built from
+    pub staging_id: StagingId,
+
+    /// the name of the blockchain the transaction has been sent to
+    pub blockchain: String,
+
+    /// local tip of the blockchain at the time the transaction was sent,
+    /// the transaction cannot appear in any block before this one.
+    pub sent_at_tip: HeaderHash,
+
+    pub sent_at: SystemTime,
+
+    /// the CBOR encoded `TxAux`, in hexadecimal
+    raw: String,
+}
+impl PendingTransaction {
+    pub fn new(
+        staging_id: StagingId,
+        blockchain: String,
+        sent_at_tip: HeaderHash,
+        txaux: &TxAux,
+    ) -> Self {
+        let mut se = Serializer::new_vec();
+        se.serialize(txaux)
+            .expect("serialising a TxAux in memory cannot fail");
+        let bytes = se.finalize();
+
+        PendingTransaction {
+            txid: txaux.tx.id(),
+            staging_id: staging_id,
+            blockchain: blockchain,
+            sent_at_tip: sent_at_tip,
+            sent_at: SystemTime::now(),
+            raw: hex::encode(&bytes),
+        }
+    }
+
+    pub fn sent_at(&self) -> Time {
+        Time::from(self.sent_at)
+    }
+
+    /// decode the signed transaction recorded in the journal
+    pub fn txaux(&self) -> Result<TxAux> {
+        let bytes = hex::decode(&self.raw)?;
+        let txaux = Deserializer::from(io::Cursor::new(bytes)).deserialize_complete()?;
+        Ok(txaux)
+    }
+
+    /// record (or update) the pending transaction in the journal
+    pub fn save(&self, root_dir: PathBuf) -> Result<()> {
+        let dir = config::pending_directory(root_dir.clone());
+        fs::create_dir_all(dir)?;
+        let file = fs::File::create(config::pending_file(root_dir, &self.txid))?;
+        serde_yaml::to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(root_dir: PathBuf, txid: &TxId) -> Result<Self> {
+        let file = match fs::File::open(config::pending_file(root_dir, txid)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(txid.clone()))
+            }
+            Err(err) => return Err(Error::IoError(err)),
+            Ok(file) => file,
+        };
+        Ok(serde_yaml::from_reader(file)?)
+    }
+
+    /// remove the transaction from the journal (once it has been
+    /// seen in the blockchain)
+    pub fn remove(self, root_dir: PathBuf) -> Result<()> {
+        fs::remove_file(config::pending_file(root_dir, &self.txid))?;
+        Ok(())
+    }
+
+    /// list all the entries of the journal, ordered by the time they were sent
+    pub fn list(root_dir: PathBuf) -> Result<Vec<Self>> {
+        let dir = config::pending_directory(root_dir);
+        let mut pendings = Vec::new();
+
+        let entries = match fs::read_dir(dir) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(pendings),
+            Err(err) => return Err(Error::IoError(err)),
+            Ok(entries) => entries,
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            let file = fs::File::open(entry.path())?;
+            pendings.push(serde_yaml::from_reader(file)?);
+        }
+
+        pendings.sort_by_key(|p: &PendingTransaction| p.sent_at);
+        Ok(pendings)
+    }
+}
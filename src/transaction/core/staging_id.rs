@@ -1,8 +1,8 @@
 use cardano::util::base58;
-use rand;
 use serde::{de, ser};
 use std::{error, fmt, str};
 use storage_units::utils::serialize::{read_size, write_size, SIZE_SIZE};
+use utils::entropy::{self, EntropySource, Purpose};
 
 /// a Staging ID represents a transaction under construction
 ///
@@ -14,7 +14,12 @@ pub struct StagingId(u32);
 impl StagingId {
     /// Generate a new random `StagingId`
     pub fn generate() -> Self {
-        StagingId(rand::random())
+        Self::generate_with(&mut *entropy::default_source())
+    }
+
+    /// Generate a new `StagingId` from the given source of randomness
+    pub fn generate_with(source: &mut EntropySource) -> Self {
+        StagingId(source.next_u32(Purpose::StagingId))
     }
 
     fn to_base58(self) -> String {
@@ -153,6 +158,18 @@ mod test {
         }
     }
 
+    #[test]
+    fn seeded() {
+        let mut a = entropy::SeededEntropy::new(0);
+        let mut b = entropy::SeededEntropy::new(0);
+        for _ in 0..100 {
+            assert_eq!(
+                StagingId::generate_with(&mut a),
+                StagingId::generate_with(&mut b)
+            )
+        }
+    }
+
     #[test]
     fn goldens() {
         for (i, (id, encoded)) in GOLDEN_TESTS.iter().enumerate() {
@@ -1,10 +1,18 @@
 use cardano::{
     address::ExtendedAddr,
+    coin::Coin,
     config::ProtocolMagic,
+    fee::LinearFee,
     tx::{TxInWitness, TxoPointer},
     util::hex,
 };
-use std::{error, fmt, path::PathBuf};
+use std::{
+    error, fmt,
+    path::PathBuf,
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
+};
 use storage_units::{
     append,
     utils::{
@@ -12,7 +20,9 @@ use storage_units::{
         serialize,
     },
 };
+use utils::logging;
 
+use super::audit;
 use super::operation::ParsingOperationError;
 use super::transaction;
 use super::{config, Change, Input, Operation, Output, StagingId, Transaction};
@@ -34,25 +44,67 @@ pub struct StagingTransaction {
     /// keep a lock to the staging transaction file for as long as this object
     /// exist. This will prevent having code that opens the same staging
     /// transaction multiple time.
-    pub writer: append::Writer,
+    ///
+    /// `None` for a staging transaction kept in memory (see `in_memory`)
+    pub writer: Option<append::Writer>,
+
+    /// the root directory, where the audit log of the operations is
+    pub root_dir: PathBuf,
 }
 
 const MAGIC_TRANSACTION_V1: &'static [u8] = b"TRANSACTION_V1";
 
+lazy_static! {
+    static ref LOCK_WAIT: RwLock<Duration> = RwLock::new(Duration::from_secs(0));
+}
+
+/// how long to wait for the lock of a staging transaction's file another
+/// process holds, not at all by default
+pub fn lock_wait() -> Duration {
+    *LOCK_WAIT.read().unwrap()
+}
+
+/// set how long the following operations wait for the lock of a staging
+/// transaction's file, for the scripts running concurrent commands
+pub fn set_lock_wait(wait: Duration) {
+    *LOCK_WAIT.write().unwrap() = wait;
+}
+
+/// lock the file, retrying while another process holds it for at most
+/// `wait`
+fn lock_waiting(path: PathBuf, wait: Duration) -> Result<Lock, lock::Error> {
+    let start = Instant::now();
+    loop {
+        match Lock::lock(path.clone()) {
+            Err(lock::Error::AlreadyLocked(locked, pid)) => {
+                if start.elapsed() >= wait {
+                    return Err(lock::Error::AlreadyLocked(locked, pid));
+                }
+                debug!(
+                    "staging transaction file locked by process {}, waiting",
+                    pid
+                );
+                thread::sleep(Duration::from_millis(100));
+            }
+            result => return result,
+        }
+    }
+}
+
 impl StagingTransaction {
     fn new_with(
         root_dir: PathBuf,
         protocol_magic: ProtocolMagic,
         id: StagingId,
     ) -> append::Result<Self> {
-        let path = config::transaction_file(root_dir, id);
+        let path = config::transaction_file(root_dir.clone(), id);
 
         if path.is_file() {
             // the staging transaction already exist
             assert!(!path.is_file(), "Staging transaction already exists");
         }
 
-        let lock = Lock::lock(path)?;
+        let lock = lock_waiting(path, lock_wait())?;
         let mut w = append::Writer::open(lock)?;
         w.append_bytes(MAGIC_TRANSACTION_V1)?;
         {
@@ -65,10 +117,27 @@ impl StagingTransaction {
             protocol_magic: protocol_magic,
             operations: Vec::new(),
             transaction: Transaction::new(),
-            writer: w,
+            writer: Some(w),
+            root_dir: root_dir,
         })
     }
 
+    /// create a new staging transaction kept in memory: its operations are
+    /// written neither to a staging file nor to the audit log, for the dry
+    /// runs
+    pub fn in_memory(root_dir: PathBuf, protocol_magic: ProtocolMagic) -> Self {
+        let id = StagingId::generate();
+        debug!("new staging transaction {} in memory", id);
+        StagingTransaction {
+            id: id,
+            protocol_magic: protocol_magic,
+            operations: Vec::new(),
+            transaction: Transaction::new(),
+            writer: None,
+            root_dir: root_dir,
+        }
+    }
+
     /// create a new staging transaction.
     ///
     /// The `root_dir` is necessary as it will create the file (and the necessary
@@ -76,13 +145,23 @@ impl StagingTransaction {
     ///
     pub fn new(root_dir: PathBuf, protocol_magic: ProtocolMagic) -> append::Result<Self> {
         let id = StagingId::generate();
+        info!("new staging transaction {}", id);
         Self::new_with(root_dir, protocol_magic, id)
     }
 
     /// destroy the staging transaction from the file system
     pub fn destroy(self) -> append::Result<()> {
+        let writer = match self.writer {
+            None => return Ok(()),
+            Some(writer) => writer,
+        };
         // close the writer
-        let lock = self.writer.close();
+        info!(
+            "staging transaction {} destroyed by {}",
+            self.id,
+            logging::originator()
+        );
+        let lock = writer.close();
         ::std::fs::remove_file(&lock)?;
         Ok(())
     }
@@ -108,8 +187,16 @@ impl StagingTransaction {
         for output in export.transaction.outputs {
             st.add_output(output)?;
         }
+        for change in export.transaction.changes {
+            st.add_change(change)?;
+        }
+        if let Some(fee) = export.transaction.fee {
+            st.set_fee(fee)?;
+        }
+        // the fee of a finalized transaction is pinned, but for the exports
+        // of the staging transactions finalized before it was
         if export.transaction.finalized {
-            st.finalize()?;
+            st.append(Operation::Finalize)?;
         }
 
         Ok(st)
@@ -144,15 +231,31 @@ impl StagingTransaction {
     /// # Error
     ///
     /// 1. the staging file is already locked (opened for read/write) by another
-    ///    thread/process (or the same process);
+    ///    thread/process (or the same process) and was not released within
+    ///    `lock_wait()`;
     /// 2. the data is unsupported or corrupted;
     ///
     pub fn read_from_file(
         root_dir: PathBuf,
         id: StagingId,
     ) -> Result<Self, StagingTransactionParseError> {
-        let path = config::transaction_file(root_dir, id);
-        let lock = Lock::lock(path)?;
+        Self::read_from_file_waiting(root_dir, id, lock_wait())
+    }
+
+    /// same as `read_from_file`, waiting at most `wait` for the lock of the
+    /// staging file, instead of `lock_wait()`
+    pub fn read_from_file_waiting(
+        root_dir: PathBuf,
+        id: StagingId,
+        wait: Duration,
+    ) -> Result<Self, StagingTransactionParseError> {
+        let path = config::transaction_file(root_dir.clone(), id);
+        let lock = match lock_waiting(path, wait) {
+            Err(lock::Error::AlreadyLocked(_, pid)) => {
+                return Err(StagingTransactionParseError::AlreadyLocked(id, pid, wait))
+            }
+            result => result?,
+        };
         let mut reader = append::Reader::open(lock)?;
 
         // check the staging transaction magic
@@ -189,26 +292,57 @@ impl StagingTransaction {
             protocol_magic: protocol_magic,
             operations: operations,
             transaction: transaction,
-            writer: w,
+            writer: Some(w),
+            root_dir: root_dir,
         })
     }
 
     /// update the `StagingTransaction` with the given operation
     ///
-    /// This function updates (in the order):
+    /// The operation is checked against a copy of the transaction first,
+    /// then this function updates (in the order):
     ///
     /// 1. the staging file;
-    /// 2. the transaction;
-    /// 3. the in-memory list of operations
+    /// 2. the audit log, once the operation is in the staging file;
+    /// 3. the transaction and the in-memory list of operations.
+    ///
+    /// A staging transaction kept in memory only updates the last ones.
+    ///
+    /// The operation being in the staging file already, a failure to
+    /// record it in the audit log is logged, it does not fail the
+    /// operation: the callers appending several operations (`finalize`
+    /// pinning the fee first) would stop half way otherwise.
     ///
     fn append(&mut self, transaction_op: Operation) -> Result<(), StagingUpdateError> {
-        self.transaction.update_with(transaction_op.clone())?;
-        self.writer.append_bytes(&transaction_op.serialize())?;
+        let mut transaction = self.transaction.clone();
+        transaction.update_with(transaction_op.clone())?;
+        if let Some(ref mut writer) = self.writer {
+            writer.append_bytes(&transaction_op.serialize())?;
+            if let Err(err) = audit::record(self.root_dir.clone(), &self.id, &transaction_op) {
+                error!(
+                    "cannot record the operation of staging transaction {} in the audit log: {}",
+                    self.id, err
+                );
+            }
+        }
+        debug!("staging transaction {}: {:?}", self.id, transaction_op);
+        self.transaction = transaction;
         self.operations.push(transaction_op);
         Ok(())
     }
 
-    pub fn finalize(&mut self) -> Result<(), StagingUpdateError> {
+    /// finalize the transaction, pinning its fee (with `SetFee`) first if
+    /// it was not set: the change, hence the id of the transaction, are then
+    /// the same whatever the fee policy of the commands run afterwards. The
+    /// fee must not be below the fee the fee algorithm asks for.
+    pub fn finalize(&mut self, fee_algorithm: &LinearFee) -> Result<(), StagingUpdateError> {
+        let transaction = &self.transaction;
+        if !transaction.is_finalized() && !transaction.inputs().is_empty() {
+            let fee = transaction.checked_fee(fee_algorithm)?;
+            if transaction.fee().is_none() {
+                self.set_fee(fee)?;
+            }
+        }
         self.append(Operation::Finalize)
     }
 
@@ -257,6 +391,11 @@ impl StagingTransaction {
         self.append(Operation::RemoveChange(address))
     }
 
+    /// pin the fee of the transaction, replacing the fee previously set
+    pub fn set_fee(&mut self, fee: Coin) -> Result<(), StagingUpdateError> {
+        self.append(Operation::SetFee(fee))
+    }
+
     /// remove the output at the given index
     ///
     /// # panic
@@ -331,6 +470,10 @@ pub enum StagingTransactionParseError {
     /// It could mean there is already a lock on the `StagingTransaction`'s file
     AppendFile(append::Error),
 
+    /// the `StagingTransaction`'s file is locked by the process of the given
+    /// id, and still was after the given wait
+    AlreadyLocked(StagingId, u32, Duration),
+
     /// the `StagingTransaction`'s file has no magic, this is certainly an error,
     /// a corrupted of the file or an unsupported staging transaction file.
     NoMagic,
@@ -379,6 +522,13 @@ impl fmt::Display for StagingTransactionParseError {
             StagingTransactionParseError::AppendFile(_) => {
                 write!(f, "Staging transaction corrupted or unsupported format")
             }
+            StagingTransactionParseError::AlreadyLocked(id, pid, wait) => write!(
+                f,
+                "Staging transaction {} is locked by process {} (still after waiting {} seconds, see `--lock-wait')",
+                id,
+                pid,
+                wait.as_secs()
+            ),
             StagingTransactionParseError::NoMagic => {
                 write!(f, "Staging file is missing its MagicNumber")
             }
@@ -400,6 +550,7 @@ impl error::Error for StagingTransactionParseError {
     fn cause(&self) -> Option<&error::Error> {
         match self {
             StagingTransactionParseError::AppendFile(ref err) => Some(err),
+            StagingTransactionParseError::AlreadyLocked(_, _, _) => None,
             StagingTransactionParseError::NoMagic => None,
             StagingTransactionParseError::MissingProtocolMagic => None,
             StagingTransactionParseError::InvalidMagic(_) => None,
@@ -417,6 +568,15 @@ pub struct Export {
     protocol_magic: ProtocolMagic,
     transaction: Transaction,
 }
+impl Export {
+    pub fn staging_id(&self) -> &StagingId {
+        &self.staging_id
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+}
 impl From<StagingTransaction> for Export {
     fn from(st: StagingTransaction) -> Self {
         Export {
@@ -437,3 +597,132 @@ impl<'a> From<&'a StagingTransaction> for Export {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, process};
+    use utils::testing::{address, input};
+
+    #[test]
+    fn rejected_operation_is_not_recorded() {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-staging-{}", process::id()));
+        fs::create_dir_all(config::transaction_directory(root_dir.clone())).unwrap();
+
+        let mut staging =
+            StagingTransaction::new(root_dir.clone(), ProtocolMagic::from(764824073)).unwrap();
+        let id = *staging.id();
+        staging.add_input(input(1, 1_000_000)).unwrap();
+        match staging.remove_input(input(2, 1_000_000).extract_txin()) {
+            Err(StagingUpdateError::TransactionIsInvalidState(_)) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        assert_eq!(staging.operations().len(), 1);
+        assert_eq!(staging.transaction().inputs().len(), 1);
+        ::std::mem::drop(staging);
+
+        // neither the staging file nor the audit log have the rejected
+        // operation
+        let staging = StagingTransaction::read_from_file(root_dir.clone(), id).unwrap();
+        assert_eq!(staging.operations().len(), 1);
+        let audit = fs::read_to_string(config::audit_file(root_dir.clone())).unwrap();
+        assert_eq!(audit.lines().count(), 1);
+        ::std::mem::drop(staging);
+
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn audit_log_failure_does_not_stop_finalize() {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-staging-audit-{}", process::id()));
+        fs::create_dir_all(config::transaction_directory(root_dir.clone())).unwrap();
+        // the audit log cannot be opened
+        fs::create_dir_all(config::audit_file(root_dir.clone())).unwrap();
+
+        let protocol_magic = ProtocolMagic::from(764824073);
+        let mut staging = StagingTransaction::new(root_dir.clone(), protocol_magic).unwrap();
+        let id = *staging.id();
+        staging.add_input(input(1, 1_000_000)).unwrap();
+        staging
+            .add_output(Output {
+                address: address(1),
+                amount: Coin::new(800_000).unwrap(),
+            })
+            .unwrap();
+        staging.finalize(&LinearFee::default()).unwrap();
+        assert_eq!(
+            staging.transaction().fee(),
+            Some(Coin::new(200_000).unwrap())
+        );
+        assert!(staging.transaction().is_finalized());
+        ::std::mem::drop(staging);
+
+        // the fee and the finalization are both in the staging file
+        let staging = StagingTransaction::read_from_file(root_dir.clone(), id).unwrap();
+        assert_eq!(staging.operations().len(), 4);
+        assert!(staging.transaction().is_finalized());
+        ::std::mem::drop(staging);
+
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn held_lock_fails_after_the_wait() {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-staging-lock-{}", process::id()));
+        fs::create_dir_all(config::transaction_directory(root_dir.clone())).unwrap();
+
+        let staging =
+            StagingTransaction::new(root_dir.clone(), ProtocolMagic::from(764824073)).unwrap();
+        let id = *staging.id();
+
+        // the staging file stays locked while `staging` lives
+        let wait = Duration::from_millis(300);
+        let start = Instant::now();
+        match StagingTransaction::read_from_file_waiting(root_dir.clone(), id, wait) {
+            Err(StagingTransactionParseError::AlreadyLocked(locked, pid, waited)) => {
+                assert!(locked == id);
+                assert_eq!(pid, process::id());
+                assert_eq!(waited, wait);
+            }
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        assert!(start.elapsed() >= wait);
+        ::std::mem::drop(staging);
+
+        // and it is read once released
+        StagingTransaction::read_from_file_waiting(root_dir.clone(), id, wait).unwrap();
+
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn in_memory_staging_writes_nothing() {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-staging-memory-{}", process::id()));
+        fs::create_dir_all(config::transaction_directory(root_dir.clone())).unwrap();
+
+        let mut staging =
+            StagingTransaction::in_memory(root_dir.clone(), ProtocolMagic::from(764824073));
+        staging.add_input(input(1, 1_000_000)).unwrap();
+        staging
+            .add_output(Output {
+                address: address(1),
+                amount: Coin::new(800_000).unwrap(),
+            })
+            .unwrap();
+        staging.finalize(&LinearFee::default()).unwrap();
+        assert_eq!(staging.operations().len(), 4);
+        assert!(staging.transaction().is_finalized());
+        staging.destroy().unwrap();
+
+        // neither a staging file nor an audit log
+        let staged = fs::read_dir(config::transaction_directory(root_dir.clone())).unwrap();
+        assert_eq!(staged.count(), 0);
+        assert!(!config::audit_file(root_dir.clone()).exists());
+
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+}
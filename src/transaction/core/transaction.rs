@@ -1,14 +1,15 @@
 use super::{Change, Input, Operation, Output};
 use cardano::{
     address::ExtendedAddr,
+    coin::{self, sum_coins, Coin},
     tx::{TxInWitness, TxOut, TxWitness, TxoPointer},
 };
 use cardano::{
-    fee::LinearFee,
+    fee::{self, FeeAlgorithm, LinearFee},
     txbuild::{self, TxBuilder, TxFinalized},
     txutils::OutputPolicy,
 };
-use std::{error, fmt};
+use std::{error, fmt, iter};
 
 #[derive(Debug)]
 pub enum Error {
@@ -26,6 +27,8 @@ pub enum Error {
 
     CannotAddChangeToAFinalizedTransaction,
 
+    CannotSetFeeOfAFinalizedTransaction,
+
     TransactionNotFinalized,
 
     /// when input is duplicated in the transaction
@@ -44,6 +47,24 @@ pub enum Error {
 
     ErrorWhenApplyingOutputPolicy(txbuild::Error),
 
+    InvalidTotal(coin::Error),
+
+    /// the inputs do not cover the outputs (inputs, outputs)
+    InputsDoNotCoverOutputs(Coin, Coin),
+
+    /// the inputs do not cover the outputs and the fee set with
+    /// `SetFee` (inputs, outputs, fee)
+    InputsDoNotCoverOutputsAndFee(Coin, Coin, Coin),
+
+    /// the fee is below the fee the fee algorithm asks for (fee, minimum)
+    FeeBelowMinimum(Coin, Coin),
+
+    CannotComputeFee(fee::Error),
+
+    /// the inputs leave more than the fee set with `SetFee` and there is
+    /// no change address to receive the difference (inputs, outputs, fee)
+    InputsExceedOutputsAndFeeWithoutChange(Coin, Coin, Coin),
+
     CannotBuildTxFromBuilder(txbuild::Error),
 }
 impl fmt::Display for Error {
@@ -55,6 +76,7 @@ impl fmt::Display for Error {
             Error::CannotAddInputsToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more inputs"),
             Error::CannotAddOutputToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more outputs"),
             Error::CannotAddChangeToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more change addresses"),
+            Error::CannotSetFeeOfAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot set the fee"),
             Error::TransactionNotFinalized => write!(f, "Transaction is not finalized, finalize it first"),
             Error::DoubleSpend => write!(f, "Input already used in the transaction"),
             Error::CannotRemoveInputInputNotFound => write!(f, "Cannot remove input, input not found"),
@@ -63,6 +85,12 @@ impl fmt::Display for Error {
             Error::MoreThanOneChangeAddressIsNotSupportedYet => write!(f, "Cannot add more than one output address for now, this feature is not yet supported"),
             Error::ErrorWhenApplyingOutputPolicy(_) => write!(f, "Error when applying the output policy utilising the changes"),
             Error::CannotBuildTxFromBuilder(_) => write!(f, "Error when constructing the Tx, invalid data."),
+            Error::InvalidTotal(_) => write!(f, "Invalid total of the inputs or of the outputs"),
+            Error::InputsDoNotCoverOutputs(inputs, outputs) => write!(f, "The inputs ({}) do not cover the outputs ({})", inputs, outputs),
+            Error::InputsDoNotCoverOutputsAndFee(inputs, outputs, fee) => write!(f, "The inputs ({}) do not cover the outputs ({}) and the fee ({})", inputs, outputs, fee),
+            Error::FeeBelowMinimum(fee, minimum) => write!(f, "The fee ({}) is below the fee the fee policy asks for ({})", fee, minimum),
+            Error::CannotComputeFee(_) => write!(f, "Cannot compute the fee of the transaction"),
+            Error::InputsExceedOutputsAndFeeWithoutChange(inputs, outputs, fee) => write!(f, "The inputs ({}) exceed the outputs ({}) and the fee ({}), add a change address to receive the difference", inputs, outputs, fee),
         }
     }
 }
@@ -75,6 +103,7 @@ impl error::Error for Error {
             Error::CannotAddInputsToAFinalizedTransaction => None,
             Error::CannotAddOutputToAFinalizedTransaction => None,
             Error::CannotAddChangeToAFinalizedTransaction => None,
+            Error::CannotSetFeeOfAFinalizedTransaction => None,
             Error::TransactionNotFinalized => None,
             Error::DoubleSpend => None,
             Error::CannotRemoveInputInputNotFound => None,
@@ -83,6 +112,12 @@ impl error::Error for Error {
             Error::MoreThanOneChangeAddressIsNotSupportedYet => None,
             Error::ErrorWhenApplyingOutputPolicy(ref err) => Some(err),
             Error::CannotBuildTxFromBuilder(ref err) => Some(err),
+            Error::InvalidTotal(ref err) => Some(err),
+            Error::InputsDoNotCoverOutputs(_, _) => None,
+            Error::InputsDoNotCoverOutputsAndFee(_, _, _) => None,
+            Error::FeeBelowMinimum(_, _) => None,
+            Error::CannotComputeFee(ref err) => Some(err),
+            Error::InputsExceedOutputsAndFeeWithoutChange(_, _, _) => None,
         }
     }
 }
@@ -109,6 +144,9 @@ pub struct Transaction {
     pub changes: Vec<Change>,
     pub witnesses: TxWitness,
     pub finalized: bool,
+    /// the fee set with `SetFee`, if any
+    #[serde(default)]
+    pub fee: Option<Coin>,
 }
 impl Transaction {
     /// create an empty transaction
@@ -119,10 +157,14 @@ impl Transaction {
             changes: Vec::new(),
             witnesses: TxWitness::new(),
             finalized: false,
+            fee: None,
         }
     }
 
-    pub fn mk_txbuilder(&self) -> Result<(TxBuilder, Vec<TxOut>)> {
+    /// the builder of the transaction, the change (if any) is what the
+    /// inputs leave once the outputs and the fee (the one set with `SetFee`
+    /// or the one computed with the fee algorithm) are paid
+    pub fn mk_txbuilder(&self, fee_algorithm: &LinearFee) -> Result<(TxBuilder, Vec<TxOut>)> {
         let mut builder = TxBuilder::new();
         for input in self.inputs.iter() {
             let ptr = TxoPointer {
@@ -139,11 +181,24 @@ impl Transaction {
             };
             builder.add_output_value(&out);
         }
-        let changes_used = if self.changes.len() == 1 && (!self.inputs().is_empty()) {
-            let fee_algorithm = LinearFee::default();
+        let changes_used = if let Some(fee) = self.fee {
+            // the inputs not covering the outputs and the fee is reported
+            // when finalizing, there is just no change until then
+            match (self.changes.first(), self.leftover(fee)?) {
+                (Some(change), Some(value)) if u64::from(value) > 0 => {
+                    let out = TxOut {
+                        address: change.address.clone(),
+                        value,
+                    };
+                    builder.add_output_value(&out);
+                    vec![out]
+                }
+                _ => Vec::new(),
+            }
+        } else if self.changes.len() == 1 && (!self.inputs().is_empty()) {
             builder
                 .add_output_policy(
-                    &fee_algorithm,
+                    fee_algorithm,
                     &OutputPolicy::One(self.changes[0].address.clone()),
                 )
                 .map_err(Error::ErrorWhenApplyingOutputPolicy)?
@@ -154,8 +209,8 @@ impl Transaction {
         Ok((builder, changes_used))
     }
 
-    pub fn mk_finalized(&self) -> Result<(TxFinalized, Vec<TxOut>)> {
-        let (builder, changes_used) = self.mk_txbuilder()?;
+    pub fn mk_finalized(&self, fee_algorithm: &LinearFee) -> Result<(TxFinalized, Vec<TxOut>)> {
+        let (builder, changes_used) = self.mk_txbuilder(fee_algorithm)?;
 
         if !self.is_finalized() {
             return Err(Error::TransactionNotFinalized);
@@ -172,6 +227,86 @@ impl Transaction {
         Ok((finalized, changes_used))
     }
 
+    /// the fee the transaction pays: the one set with `SetFee`, or what
+    /// the inputs leave once the outputs and the change computed with the
+    /// fee algorithm are paid
+    pub fn fee_paid(&self, fee_algorithm: &LinearFee) -> Result<Coin> {
+        if let Some(fee) = self.fee {
+            return Ok(fee);
+        }
+        let (inputs, _) = self.totals()?;
+        let (builder, _) = self.mk_txbuilder(fee_algorithm)?;
+        let outputs = builder
+            .make_tx()
+            .map_err(Error::CannotBuildTxFromBuilder)?
+            .get_output_total()
+            .map_err(Error::InvalidTotal)?;
+        (inputs - outputs).map_err(|_| Error::InputsDoNotCoverOutputs(inputs, outputs))
+    }
+
+    /// the fee the fee algorithm asks for the transaction paying the given
+    /// fee (the change, hence the size of the transaction, depends on it)
+    fn minimum_fee(&self, fee: Coin, fee_algorithm: &LinearFee) -> Result<Coin> {
+        let mut paying = self.clone();
+        paying.fee = Some(fee);
+        let tx = paying
+            .mk_txbuilder(fee_algorithm)?
+            .0
+            .make_tx()
+            .map_err(Error::CannotBuildTxFromBuilder)?;
+        let witnesses: Vec<TxInWitness> = iter::repeat(TxInWitness::fake())
+            .take(self.inputs.len())
+            .collect();
+        let minimum = fee_algorithm
+            .calculate_for_txaux_component(&tx, &witnesses)
+            .map_err(Error::CannotComputeFee)?;
+        Ok(minimum.to_coin())
+    }
+
+    /// the fee the transaction pays (see `fee_paid`), not below the fee the
+    /// fee algorithm asks for
+    pub fn checked_fee(&self, fee_algorithm: &LinearFee) -> Result<Coin> {
+        let fee = self.fee_paid(fee_algorithm)?;
+        let minimum = self.minimum_fee(fee, fee_algorithm)?;
+        if fee < minimum {
+            return Err(Error::FeeBelowMinimum(fee, minimum));
+        }
+        Ok(fee)
+    }
+
+    fn totals(&self) -> Result<(Coin, Coin)> {
+        let inputs = sum_coins(self.inputs.iter().map(|input| input.expected_value))
+            .map_err(Error::InvalidTotal)?;
+        let outputs = sum_coins(self.outputs.iter().map(|output| output.amount))
+            .map_err(Error::InvalidTotal)?;
+        Ok((inputs, outputs))
+    }
+
+    /// what the inputs leave once the outputs and the given fee are paid,
+    /// none if they do not cover them
+    fn leftover(&self, fee: Coin) -> Result<Option<Coin>> {
+        let (inputs, outputs) = self.totals()?;
+        let spent = (outputs + fee).map_err(Error::InvalidTotal)?;
+        Ok((inputs - spent).ok())
+    }
+
+    /// check the inputs pay exactly for the outputs, the fee set with
+    /// `SetFee` and the change (if any)
+    fn check_fee(&self) -> Result<()> {
+        let fee = match self.fee {
+            None => return Ok(()),
+            Some(fee) => fee,
+        };
+        let (inputs, outputs) = self.totals()?;
+        match self.leftover(fee)? {
+            None => Err(Error::InputsDoNotCoverOutputsAndFee(inputs, outputs, fee)),
+            Some(value) if u64::from(value) > 0 && self.changes.is_empty() => Err(
+                Error::InputsExceedOutputsAndFeeWithoutChange(inputs, outputs, fee),
+            ),
+            Some(_) => Ok(()),
+        }
+    }
+
     /// update the transaction with the given operation
     pub fn update_with(&mut self, operation: Operation) -> Result<&mut Self> {
         match operation {
@@ -181,6 +316,7 @@ impl Transaction {
             Operation::RemoveInput(txin) => self.remove_input(txin),
             Operation::RemoveOutput(index) => self.remove_output(index),
             Operation::RemoveChange(addr) => self.remove_change(addr),
+            Operation::SetFee(fee) => self.set_fee(fee),
             Operation::Signature(witness) => self.add_witness(witness),
             Operation::Finalize => self.finalize(),
         }
@@ -214,6 +350,11 @@ impl Transaction {
         self.finalized
     }
 
+    /// the fee set with `SetFee`, if any
+    pub fn fee(&self) -> Option<Coin> {
+        self.fee
+    }
+
     /// lookup the inputs for the given `TxoPointer`
     pub fn lookup_input(&self, txin: TxoPointer) -> Option<usize> {
         self.inputs()
@@ -245,6 +386,13 @@ impl Transaction {
         self.changes.push(change);
         Ok(self)
     }
+    fn set_fee(&mut self, fee: Coin) -> Result<&mut Self> {
+        if self.is_finalized() {
+            return Err(Error::CannotSetFeeOfAFinalizedTransaction);
+        }
+        self.fee = Some(fee);
+        Ok(self)
+    }
     fn add_witness(&mut self, witness: TxInWitness) -> Result<&mut Self> {
         if !self.is_finalized() {
             return Err(Error::CannotAddWitnessesToAnOpenedTransaction);
@@ -325,6 +473,7 @@ impl Transaction {
         if self.finalized {
             return Err(Error::CannotFinalizeAFinalizedTransaction);
         }
+        self.check_fee()?;
         self.finalized = true;
         Ok(self)
     }
@@ -334,3 +483,128 @@ impl Default for Transaction {
         Transaction::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utils::testing::{address, input};
+
+    fn coin(value: u64) -> Coin {
+        Coin::new(value).unwrap()
+    }
+
+    /// a transaction spending inputs of the given values to an output of
+    /// the given value, with a change address if asked
+    fn transaction(inputs: &[u64], output: u64, change: bool) -> Transaction {
+        let mut transaction = Transaction::new();
+        for (index, value) in inputs.iter().enumerate() {
+            transaction
+                .update_with(Operation::AddInput(input(index as u8, *value)))
+                .unwrap();
+        }
+        transaction
+            .update_with(Operation::AddOutput(Output {
+                address: address(1),
+                amount: coin(output),
+            }))
+            .unwrap();
+        if change {
+            transaction
+                .update_with(Operation::AddChange(Change::from(address(2))))
+                .unwrap();
+        }
+        transaction
+    }
+
+    fn tx_id(transaction: &Transaction, fee_algorithm: &LinearFee) -> ::cardano::tx::TxId {
+        let (builder, _) = transaction.mk_txbuilder(fee_algorithm).unwrap();
+        builder.make_tx().unwrap().id()
+    }
+
+    #[test]
+    fn fee_set_is_the_fee_paid() {
+        let mut transaction = transaction(&[10_000_000], 5_000_000, true);
+        transaction
+            .update_with(Operation::SetFee(coin(200_000)))
+            .unwrap();
+        let fee = transaction.fee_paid(&LinearFee::default()).unwrap();
+        assert_eq!(fee, coin(200_000));
+    }
+
+    #[test]
+    fn fee_paid_without_change_is_what_the_inputs_leave() {
+        let transaction = transaction(&[10_000_000], 9_800_000, false);
+        let fee = transaction.fee_paid(&LinearFee::default()).unwrap();
+        assert_eq!(fee, coin(200_000));
+    }
+
+    #[test]
+    fn pinned_fee_keeps_the_transaction() {
+        let fee_algorithm = LinearFee::default();
+        let mut transaction = transaction(&[10_000_000, 3_000_000], 5_000_000, true);
+        let id = tx_id(&transaction, &fee_algorithm);
+        let fee = transaction.checked_fee(&fee_algorithm).unwrap();
+        transaction.update_with(Operation::SetFee(fee)).unwrap();
+        transaction.update_with(Operation::Finalize).unwrap();
+        assert_eq!(tx_id(&transaction, &fee_algorithm), id);
+
+        // another fee policy does not change the transaction anymore
+        let other = LinearFee::new(fee::Milli::new(1, 0), fee::Milli::new(1, 0));
+        assert_eq!(tx_id(&transaction, &other), id);
+    }
+
+    #[test]
+    fn fee_below_the_minimum_is_rejected() {
+        let mut transaction = transaction(&[10_000_000], 5_000_000, true);
+        transaction.update_with(Operation::SetFee(coin(1))).unwrap();
+        match transaction.checked_fee(&LinearFee::default()) {
+            Err(Error::FeeBelowMinimum(fee, minimum)) => {
+                assert_eq!(fee, coin(1));
+                assert!(minimum > coin(155_381));
+            }
+            result => panic!("fee below the minimum accepted: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn leftover_below_the_minimum_is_rejected() {
+        let transaction = transaction(&[10_000_000], 9_999_000, false);
+        match transaction.checked_fee(&LinearFee::default()) {
+            Err(Error::FeeBelowMinimum(fee, _)) => assert_eq!(fee, coin(1_000)),
+            result => panic!("fee below the minimum accepted: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn inputs_not_covering_the_outputs_are_rejected() {
+        let transaction = transaction(&[1_000_000], 5_000_000, false);
+        match transaction.fee_paid(&LinearFee::default()) {
+            Err(Error::InputsDoNotCoverOutputs(inputs, outputs)) => {
+                assert_eq!(inputs, coin(1_000_000));
+                assert_eq!(outputs, coin(5_000_000));
+            }
+            result => panic!("unbalanced transaction accepted: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fee_set_must_balance_the_inputs() {
+        let mut too_high = transaction(&[10_000_000], 9_900_000, true);
+        too_high
+            .update_with(Operation::SetFee(coin(200_000)))
+            .unwrap();
+        match too_high.update_with(Operation::Finalize) {
+            Err(Error::InputsDoNotCoverOutputsAndFee(_, _, _)) => {}
+            result => panic!("unbalanced transaction finalized: {:?}", result.map(|_| ())),
+        }
+
+        let mut without_change = transaction(&[10_000_000], 5_000_000, false);
+        without_change
+            .update_with(Operation::SetFee(coin(200_000)))
+            .unwrap();
+        match without_change.update_with(Operation::Finalize) {
+            Err(Error::InputsExceedOutputsAndFeeWithoutChange(_, _, _)) => {}
+            result => panic!("unbalanced transaction finalized: {:?}", result.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! differences between two staging transactions
+//!
+//! `transaction diff` compares the inputs, outputs, change addresses and
+//! signatures of two staging transactions (local or exported), e.g. for a
+//! co-signer to check the outputs were not altered before adding their own
+//! signature. The elements are compared by value: an output whose amount
+//! changed is reported as one output removed and another one added. The
+//! elements both transactions have are compared by order as well, the
+//! order of the inputs and outputs being part of the transaction id (hence
+//! of what the signatures sign).
+
+use super::core::{Change, Input, Output, Transaction};
+use cardano::{coin::Coin, config::ProtocolMagic, tx::TxInWitness};
+
+use std::collections::BTreeMap;
+
+/// the elements of the first transaction missing from the second one
+/// (`removed`) and the other way around (`added`), and the elements of both
+/// in the order of each transaction if it differs (`reordered`)
+#[derive(Debug, Serialize)]
+pub struct Changes<T> {
+    pub removed: Vec<T>,
+    pub added: Vec<T>,
+    pub reordered: Option<Modified<Vec<T>>>,
+}
+impl<T: Clone> Changes<T> {
+    fn new<K, F>(from: &[T], to: &[T], key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&T) -> K,
+    {
+        let removed = missing(from, to, &key);
+        let added = missing(to, from, &key);
+        let kept_from = missing(from, &removed, &key);
+        let kept_to = missing(to, &added, &key);
+        let reordered = if kept_from.iter().map(&key).eq(kept_to.iter().map(&key)) {
+            None
+        } else {
+            Some(Modified {
+                from: kept_from,
+                to: kept_to,
+            })
+        };
+        Changes {
+            removed,
+            added,
+            reordered,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.reordered.is_none()
+    }
+}
+
+/// the elements of `from` not in `to`, an element repeated in `from` being
+/// missing as many times as it is repeated more than in `to`
+fn missing<T, K, F>(from: &[T], to: &[T], key: &F) -> Vec<T>
+where
+    T: Clone,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut remaining: BTreeMap<K, usize> = BTreeMap::new();
+    for element in to {
+        *remaining.entry(key(element)).or_insert(0) += 1;
+    }
+    let mut missing = Vec::new();
+    for element in from {
+        let count = remaining.entry(key(element)).or_insert(0);
+        if *count > 0 {
+            *count -= 1;
+        } else {
+            missing.push(element.clone());
+        }
+    }
+    missing
+}
+
+#[derive(Debug, Serialize)]
+pub struct Modified<T> {
+    pub from: T,
+    pub to: T,
+}
+impl<T: PartialEq> Modified<T> {
+    fn new(from: T, to: T) -> Option<Self> {
+        if from == to {
+            None
+        } else {
+            Some(Modified { from, to })
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diff {
+    /// the protocol magic of the transactions, if it differs: they are
+    /// not for the same network
+    pub protocol_magic: Option<Modified<u32>>,
+    pub inputs: Changes<Input>,
+    pub outputs: Changes<Output>,
+    pub changes: Changes<Change>,
+    pub signatures: Changes<TxInWitness>,
+    /// the fee set with `SetFee`, if it differs
+    pub fee: Option<Modified<Option<Coin>>>,
+    pub finalized: Option<Modified<bool>>,
+}
+impl Diff {
+    /// what changed from the first transaction (for the network of the
+    /// given protocol magic) to the second one
+    pub fn new(
+        from_protocol_magic: ProtocolMagic,
+        from: &Transaction,
+        to_protocol_magic: ProtocolMagic,
+        to: &Transaction,
+    ) -> Self {
+        Diff {
+            protocol_magic: Modified::new(*from_protocol_magic, *to_protocol_magic),
+            inputs: Changes::new(from.inputs(), to.inputs(), |input| {
+                (input.extract_txin(), u64::from(input.expected_value))
+            }),
+            outputs: Changes::new(from.outputs(), to.outputs(), |output| {
+                (output.address.to_string(), u64::from(output.amount))
+            }),
+            changes: Changes::new(from.changes(), to.changes(), |change| {
+                change.address.to_string()
+            }),
+            signatures: Changes::new(from.signature(), to.signature(), |witness| {
+                format!("{:?}", witness)
+            }),
+            fee: Modified::new(from.fee(), to.fee()),
+            finalized: Modified::new(from.is_finalized(), to.is_finalized()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.protocol_magic.is_none()
+            && self.inputs.is_empty()
+            && self.outputs.is_empty()
+            && self.changes.is_empty()
+            && self.signatures.is_empty()
+            && self.fee.is_none()
+            && self.finalized.is_none()
+    }
+}
+
+/// what the signature signs with, for the terminal
+pub fn describe_signature(witness: &TxInWitness) -> String {
+    match witness {
+        TxInWitness::PkWitness(xpub, _) => format!("public key witness of {}", xpub),
+        TxInWitness::ScriptWitness(..) => "script witness".to_owned(),
+        TxInWitness::RedeemWitness(..) => "redeem witness".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::core::Operation;
+    use super::*;
+    use schema;
+    use serde_json;
+    use utils::testing::{address, input};
+
+    fn output(seed: u8, amount: u64) -> Output {
+        Output {
+            address: address(seed),
+            amount: Coin::new(amount).unwrap(),
+        }
+    }
+
+    fn transaction(inputs: &[Input], outputs: &[Output]) -> Transaction {
+        let mut transaction = Transaction::new();
+        for input in inputs {
+            transaction
+                .update_with(Operation::AddInput(input.clone()))
+                .unwrap();
+        }
+        for output in outputs {
+            transaction
+                .update_with(Operation::AddOutput(output.clone()))
+                .unwrap();
+        }
+        transaction
+    }
+
+    fn mainnet() -> ProtocolMagic {
+        ProtocolMagic::from(764824073)
+    }
+
+    #[test]
+    fn missing_elements() {
+        assert_eq!(missing(&[1, 2, 3], &[2], &|n: &u32| *n), vec![1, 3]);
+        assert_eq!(
+            missing(&[1, 2], &[1, 2, 3], &|n: &u32| *n),
+            Vec::<u32>::new()
+        );
+        // the repeated elements are missing as many times as they are
+        // repeated more
+        assert_eq!(missing(&[1, 1, 1, 2], &[1, 2], &|n: &u32| *n), vec![1, 1]);
+        // compared by key
+        assert_eq!(
+            missing(&[10, 21], &[11, 20], &|n: &u32| *n / 10),
+            Vec::<u32>::new()
+        );
+    }
+
+    #[test]
+    fn changes_of_the_elements() {
+        let changes = Changes::new(&[1, 2, 3], &[2, 3, 4], |n: &u32| *n);
+        assert_eq!(changes.removed, vec![1]);
+        assert_eq!(changes.added, vec![4]);
+        assert!(changes.reordered.is_none());
+        assert!(!changes.is_empty());
+
+        assert!(Changes::new(&[1, 2, 2], &[1, 2, 2], |n: &u32| *n).is_empty());
+    }
+
+    #[test]
+    fn changes_of_the_order() {
+        let changes = Changes::new(&[1, 2, 3], &[3, 2, 1], |n: &u32| *n);
+        assert!(changes.removed.is_empty());
+        assert!(changes.added.is_empty());
+        let reordered = changes.reordered.as_ref().expect("no reordering");
+        assert_eq!(reordered.from, vec![1, 2, 3]);
+        assert_eq!(reordered.to, vec![3, 2, 1]);
+        assert!(!changes.is_empty());
+
+        // the order of the elements both have
+        let changes = Changes::new(&[1, 2, 3], &[4, 3, 1], |n: &u32| *n);
+        assert_eq!(changes.removed, vec![2]);
+        assert_eq!(changes.added, vec![4]);
+        let reordered = changes.reordered.as_ref().expect("no reordering");
+        assert_eq!(reordered.from, vec![1, 3]);
+        assert_eq!(reordered.to, vec![3, 1]);
+
+        // the elements added or removed do not change the order of the
+        // others
+        let changes = Changes::new(&[1, 2, 3], &[1, 4, 3], |n: &u32| *n);
+        assert!(changes.reordered.is_none());
+    }
+
+    #[test]
+    fn identical_transactions() {
+        let from = transaction(
+            &[input(1, 1_000_000)],
+            &[output(1, 1_000), output(2, 2_000)],
+        );
+        let to = transaction(
+            &[input(1, 1_000_000)],
+            &[output(1, 1_000), output(2, 2_000)],
+        );
+        let diff = Diff::new(mainnet(), &from, mainnet(), &to);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn modified_output() {
+        let from = transaction(
+            &[input(1, 1_000_000)],
+            &[output(1, 1_000), output(2, 2_000)],
+        );
+        let to = transaction(
+            &[input(1, 1_000_000)],
+            &[output(1, 1_000), output(2, 3_000)],
+        );
+        let diff = Diff::new(mainnet(), &from, mainnet(), &to);
+        assert!(diff.inputs.is_empty());
+        assert_eq!(diff.outputs.removed.len(), 1);
+        assert_eq!(diff.outputs.removed[0].amount, Coin::new(2_000).unwrap());
+        assert_eq!(diff.outputs.added.len(), 1);
+        assert_eq!(diff.outputs.added[0].amount, Coin::new(3_000).unwrap());
+        assert!(diff.outputs.reordered.is_none());
+    }
+
+    #[test]
+    fn reordered_outputs() {
+        // the transaction id changes, the signatures do not verify anymore
+        let from = transaction(
+            &[input(1, 1_000_000)],
+            &[output(1, 1_000), output(2, 2_000)],
+        );
+        let to = transaction(
+            &[input(1, 1_000_000)],
+            &[output(2, 2_000), output(1, 1_000)],
+        );
+        let diff = Diff::new(mainnet(), &from, mainnet(), &to);
+        assert!(!diff.is_empty());
+        assert!(diff.outputs.removed.is_empty());
+        assert!(diff.outputs.added.is_empty());
+        let reordered = diff.outputs.reordered.as_ref().expect("no reordering");
+        assert_eq!(reordered.from[0].address, address(1));
+        assert_eq!(reordered.to[0].address, address(2));
+    }
+
+    #[test]
+    fn reordered_inputs() {
+        let from = transaction(
+            &[input(1, 1_000_000), input(2, 1_000_000)],
+            &[output(1, 1_000)],
+        );
+        let to = transaction(
+            &[input(2, 1_000_000), input(1, 1_000_000)],
+            &[output(1, 1_000)],
+        );
+        let diff = Diff::new(mainnet(), &from, mainnet(), &to);
+        assert!(diff.inputs.reordered.is_some());
+        assert!(diff.outputs.is_empty());
+    }
+
+    #[test]
+    fn other_network() {
+        let from = transaction(&[input(1, 1_000_000)], &[output(1, 1_000)]);
+        let to = transaction(&[input(1, 1_000_000)], &[output(1, 1_000)]);
+        let diff = Diff::new(mainnet(), &from, ProtocolMagic::from(1097911063), &to);
+        assert!(!diff.is_empty());
+        let protocol_magic = diff.protocol_magic.as_ref().unwrap();
+        assert_eq!(protocol_magic.from, 764824073);
+        assert_eq!(protocol_magic.to, 1097911063);
+    }
+
+    #[test]
+    fn diff_matches_its_schema() {
+        let from = transaction(
+            &[input(1, 1_000_000), input(2, 1_000_000)],
+            &[output(1, 1_000), output(2, 2_000)],
+        );
+        let to = transaction(
+            &[input(2, 1_000_000), input(1, 1_000_000)],
+            &[output(2, 2_000), output(3, 3_000)],
+        );
+        let diff = Diff::new(mainnet(), &from, ProtocolMagic::from(1097911063), &to);
+        let value = serde_json::to_value(&diff).unwrap();
+        let violations: Vec<String> = schema::TRANSACTION_DIFF
+            .validate(&value)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+}
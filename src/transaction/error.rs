@@ -1,7 +1,9 @@
 use super::super::{blockchain, wallet};
 use super::core;
+use super::signer;
 use cardano::{self, coin};
 use storage_units;
+use utils::{clock, qr};
 
 use std::{error, fmt, io};
 
@@ -18,9 +20,17 @@ pub enum Error {
     CannotSendTransactionNotFinalized(core::transaction::Error),
     CannotSendTransactionInvalidTxAux(cardano::txbuild::Error),
     CannotSendTransactionNotSent,
+    CannotSendTransactionClockSkewed(clock::Error),
+    CannotDryRunNoPeerReachable,
+    TransactionDryRunRejected(usize),
+    CannotRecordPendingTransaction(core::pending::Error),
+    CannotListPendingTransactions(core::pending::Error),
+    CannotResubmitTransaction(core::pending::Error),
+    CannotResubmitUnknownBlockchain(String),
     CannotSignTransactionNotFinalized(core::transaction::Error),
     CannotSignTransactionInvalidTxAux(cardano::txbuild::Error),
     CannotSignTransactionCannotAddSignature(core::staging_transaction::StagingUpdateError),
+    CannotSignTransactionWithSigner(signer::Error),
     CannotReportStatusInvalidInputTotal(coin::Error),
     CannotReportStatusInvalidOutputTotal(coin::Error),
     CannotReportStatusInvalidTxBuilder(core::transaction::Error),
@@ -29,22 +39,38 @@ pub enum Error {
     CannotAddInput(core::staging_transaction::StagingUpdateError),
     CannotFindInputsInAllLocalUtxos,
     CannotAddOutput(core::staging_transaction::StagingUpdateError),
+    CannotAddOutputDust(cardano::coin::Coin, cardano::coin::Coin), // (amount, dust threshold)
     CannotAddChange(core::staging_transaction::StagingUpdateError),
     CannotRemoveInput(core::staging_transaction::StagingUpdateError),
     CannotRemoveOutput(core::staging_transaction::StagingUpdateError),
     CannotRemoveChange(core::staging_transaction::StagingUpdateError),
+    CannotSetFee(core::staging_transaction::StagingUpdateError),
+    CannotCheckInvalidTxBuilder(core::transaction::Error),
+    TransactionCheckFailed(usize),
     CannotFinalize(core::staging_transaction::StagingUpdateError),
     CannotExportToFileCannotOpenOutFile(io::Error),
     CannotExportToFile(::serde_yaml::Error),
     CannotExportToStdout(::serde_yaml::Error),
+    CannotExportToQrCode(qr::Error),
     CannotImportFromFileCannotOpenInputFile(io::Error),
     CannotImportFromFile(::serde_yaml::Error),
     CannotImportFromStdin(::serde_yaml::Error),
+    CannotImportFromQrCode(qr::Error),
     CannotImportStaging(core::staging_transaction::StagingUpdateError),
+    CannotDiffCannotOpenExportFile(io::Error),
+    CannotDiffInvalidExportFile(::serde_yaml::Error),
+    CannotDiffInvalidQrCode(qr::Error),
 
     CannotInputSelectNoChangeOption,
     CannotInputSelectSelectionFailed(cardano::input_selection::Error),
     CannotInputSelectCannotAddInput(core::staging_transaction::StagingUpdateError),
+
+    CannotSealFinalizedTransaction,
+    CannotSealNoChangeAddress,
+    CannotSealNotEnoughFunds(coin::Coin, coin::Coin), // (needed, available)
+    CannotSealInvalidTotal(coin::Error),
+    CannotSealInvalidTransaction(core::transaction::Error),
+    CannotSeal(core::staging_transaction::StagingUpdateError),
 }
 impl From<::std::io::Error> for Error {
     fn from(e: ::std::io::Error) -> Self {
@@ -84,6 +110,25 @@ impl fmt::Display for Error {
             CannotSendTransactionNotSent => {
                 write!(f, "Cannot send transaction to any blockchain peers")
             }
+            CannotSendTransactionClockSkewed(_) => {
+                write!(f, "Cannot send transaction, the local clock is skewed")
+            }
+            CannotDryRunNoPeerReachable => write!(f, "Cannot reach any of the blockchain peers"),
+            TransactionDryRunRejected(rejections) => write!(
+                f,
+                "The transaction would be rejected for {} reason(s)",
+                rejections
+            ),
+            CannotRecordPendingTransaction(_) => {
+                write!(f, "Cannot record the transaction in the pending journal")
+            }
+            CannotListPendingTransactions(_) => write!(f, "Cannot read the pending journal"),
+            CannotResubmitTransaction(_) => write!(f, "Cannot resubmit the transaction"),
+            CannotResubmitUnknownBlockchain(name) => write!(
+                f,
+                "Cannot resubmit the transaction, unknown blockchain `{}'",
+                name
+            ),
             CannotSignTransactionNotFinalized(_) => {
                 write!(f, "Cannot sign transaction, finalize it first")
             }
@@ -91,6 +136,9 @@ impl fmt::Display for Error {
             CannotSignTransactionCannotAddSignature(_) => {
                 write!(f, "Cannot add signature to the transaction")
             }
+            CannotSignTransactionWithSigner(_) => {
+                write!(f, "Cannot sign transaction with the external signer")
+            }
             CannotReportStatusInvalidInputTotal(_) => {
                 write!(f, "Input total of the transaction is invalid")
             }
@@ -107,10 +155,18 @@ impl fmt::Display for Error {
                 write!(f, "Cannot find inputs within the local UTxOs")
             }
             CannotAddOutput(_) => write!(f, "Cannot add output to the staging transaction"),
+            CannotAddOutputDust(amount, threshold) => write!(
+                f,
+                "Cannot add an output of {}, below the dust threshold ({})",
+                amount, threshold
+            ),
             CannotAddChange(_) => write!(f, "Cannot add change to the staging transaction"),
             CannotRemoveInput(_) => write!(f, "Cannot remove input from the staging transaction"),
             CannotRemoveOutput(_) => write!(f, "Cannot remove output from the staging transaction"),
             CannotRemoveChange(_) => write!(f, "Cannot remove change from the staging transaction"),
+            CannotSetFee(_) => write!(f, "Cannot set the fee of the staging transaction"),
+            CannotCheckInvalidTxBuilder(_) => write!(f, "Cannot check the staging transaction"),
+            TransactionCheckFailed(issues) => write!(f, "The staging transaction has {} issue(s)", issues),
             CannotFinalize(_) => write!(f, "Cannot finalize the staging transaction"),
             CannotExportToFileCannotOpenOutFile(_) => write!(
                 f,
@@ -124,6 +180,10 @@ impl fmt::Display for Error {
                 f,
                 "Cannot export the staging transaction to the standard output"
             ),
+            CannotExportToQrCode(_) => write!(
+                f,
+                "Cannot export the staging transaction to QR codes"
+            ),
             CannotImportFromFileCannotOpenInputFile(_) => write!(
                 f,
                 "Cannot import the staging transaction: cannot open input file"
@@ -136,10 +196,20 @@ impl fmt::Display for Error {
                 f,
                 "Cannot import the staging transaction from the standard input"
             ),
+            CannotImportFromQrCode(_) => write!(
+                f,
+                "Cannot import the staging transaction from the QR code chunks"
+            ),
             CannotImportStaging(_) => write!(
                 f,
                 "Cannot import the staging transaction: invalid or corrupted"
             ),
+            CannotDiffCannotOpenExportFile(_) => write!(f, "Cannot open the exported staging transaction"),
+            CannotDiffInvalidExportFile(_) => write!(f, "Invalid exported staging transaction"),
+            CannotDiffInvalidQrCode(_) => write!(
+                f,
+                "Cannot read the staging transaction from the QR code chunks"
+            ),
             CannotInputSelectNoChangeOption => write!(
                 f,
                 "Add change before trying to run the input selection algorithm"
@@ -150,6 +220,24 @@ impl fmt::Display for Error {
             CannotInputSelectCannotAddInput(_) => {
                 write!(f, "Cannot add input to the staging transaction")
             }
+            CannotSealFinalizedTransaction => write!(f, "Cannot seal the transaction, it is already finalized"),
+            CannotSealNoChangeAddress => write!(
+                f,
+                "Cannot seal the transaction without change address, add one (`transaction add-change') or give one with `--change'"
+            ),
+            CannotSealNotEnoughFunds(needed, available) => write!(
+                f,
+                "Cannot seal the transaction, it needs {} and the inputs and the wallets only have {}",
+                needed, available
+            ),
+            CannotSealInvalidTotal(_) => {
+                write!(f, "Cannot seal the transaction, its total is not a valid amount")
+            }
+            CannotSealInvalidTransaction(_) => write!(f, "Cannot seal the transaction"),
+            CannotSeal(_) => write!(
+                f,
+                "Cannot seal the transaction, the operations already added were rolled back"
+            ),
         }
     }
 }
@@ -167,9 +255,17 @@ impl error::Error for Error {
             CannotSendTransactionNotFinalized(ref err) => Some(err),
             CannotSendTransactionInvalidTxAux(ref err) => Some(err),
             CannotSendTransactionNotSent => None,
+            CannotSendTransactionClockSkewed(ref err) => Some(err),
+            CannotDryRunNoPeerReachable => None,
+            TransactionDryRunRejected(_) => None,
+            CannotRecordPendingTransaction(ref err) => Some(err),
+            CannotListPendingTransactions(ref err) => Some(err),
+            CannotResubmitTransaction(ref err) => Some(err),
+            CannotResubmitUnknownBlockchain(_) => None,
             CannotSignTransactionNotFinalized(ref err) => Some(err),
             CannotSignTransactionInvalidTxAux(ref err) => Some(err),
             CannotSignTransactionCannotAddSignature(ref err) => Some(err),
+            CannotSignTransactionWithSigner(ref err) => Some(err),
             CannotReportStatusInvalidInputTotal(ref err) => Some(err),
             CannotReportStatusInvalidOutputTotal(ref err) => Some(err),
             CannotReportStatusInvalidTxBuilder(ref err) => Some(err),
@@ -178,21 +274,36 @@ impl error::Error for Error {
             CannotAddInput(ref err) => Some(err),
             CannotFindInputsInAllLocalUtxos => None,
             CannotAddOutput(ref err) => Some(err),
+            CannotAddOutputDust(_, _) => None,
             CannotAddChange(ref err) => Some(err),
             CannotRemoveInput(ref err) => Some(err),
             CannotRemoveOutput(ref err) => Some(err),
             CannotRemoveChange(ref err) => Some(err),
+            CannotSetFee(ref err) => Some(err),
+            CannotCheckInvalidTxBuilder(ref err) => Some(err),
+            TransactionCheckFailed(_) => None,
             CannotFinalize(ref err) => Some(err),
             CannotExportToFileCannotOpenOutFile(ref err) => Some(err),
             CannotExportToFile(ref err) => Some(err),
             CannotExportToStdout(ref err) => Some(err),
+            CannotExportToQrCode(ref err) => Some(err),
             CannotImportFromFileCannotOpenInputFile(ref err) => Some(err),
             CannotImportFromFile(ref err) => Some(err),
             CannotImportFromStdin(ref err) => Some(err),
+            CannotImportFromQrCode(ref err) => Some(err),
             CannotImportStaging(ref err) => Some(err),
+            CannotDiffCannotOpenExportFile(ref err) => Some(err),
+            CannotDiffInvalidExportFile(ref err) => Some(err),
+            CannotDiffInvalidQrCode(ref err) => Some(err),
             CannotInputSelectNoChangeOption => None,
             CannotInputSelectSelectionFailed(ref err) => Some(err),
             CannotInputSelectCannotAddInput(ref err) => Some(err),
+            CannotSealFinalizedTransaction => None,
+            CannotSealNoChangeAddress => None,
+            CannotSealNotEnoughFunds(_, _) => None,
+            CannotSealInvalidTotal(ref err) => Some(err),
+            CannotSealInvalidTransaction(ref err) => Some(err),
+            CannotSeal(ref err) => Some(err),
         }
     }
 }
@@ -1,3 +1,40 @@
+pub mod check;
+#[cfg(feature = "cli")]
 pub mod commands;
 pub mod core;
+pub mod diff;
 pub mod error;
+pub mod signer;
+pub mod signing;
+
+use std::path::Path;
+
+use cardano::fee::LinearFee;
+
+use blockchain::Blockchain;
+
+use self::core::StagingTransaction;
+pub use self::error::Error;
+
+/// the fee algorithm of the staging transaction: the given fee policy (the
+/// profile's) if any, the fee policy of the local blockchain of the same
+/// protocol magic otherwise, the mainnet's without such a blockchain
+pub fn staging_fee_algorithm(
+    root_dir: &Path,
+    staging: &StagingTransaction,
+    fee_policy: Option<LinearFee>,
+) -> LinearFee {
+    if let Some(fee_policy) = fee_policy {
+        return fee_policy;
+    }
+    match Blockchain::find_by_protocol_magic(root_dir, staging.protocol_magic) {
+        None => LinearFee::default(),
+        Some(blockchain) => blockchain.fee_policy().unwrap_or_else(|err| {
+            warn!(
+                "cannot read the fee policy of blockchain {}: {}",
+                blockchain.name, err
+            );
+            LinearFee::default()
+        }),
+    }
+}
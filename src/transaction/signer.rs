@@ -0,0 +1,281 @@
+//! external signers
+//!
+//! An external signer is a program, configured in the profile, signing
+//! the staging transactions with keys the command never sees (kept in a
+//! hardware wallet or an HSM). `transaction sign --signer NAME` runs it
+//! with:
+//!
+//! * on its standard input, the signing information of the staging
+//!   transaction in JSON (see `signing::SigningInfo`, the output of
+//!   `transaction signing-info`): the transaction, the address and the
+//!   derivation path of every input;
+//! * on its standard output, it writes `{"witnesses": [...]}`, the witness
+//!   of every input in the order of the inputs, each one the CBOR of a
+//!   `TxInWitness` in hexadecimal;
+//! * its standard error is the one of the command, for its prompts and
+//!   errors, and it exits with a non zero status when it cannot sign.
+//!
+//! The signer is killed if it has not exited after the `timeout` of its
+//! configuration (`DEFAULT_TIMEOUT` seconds if none), long enough for a
+//! user to confirm on the device but not to wait forever on a hung signer.
+//!
+//! The witnesses are checked before being added to the staging
+//! transaction: they must sign the transaction, with the key of the
+//! address of the input when one of the wallets owns it.
+
+use super::signing::SigningInfo;
+use cardano::{
+    address::ExtendedAddr,
+    config::ProtocolMagic,
+    tx::{Tx, TxInWitness},
+    util::hex,
+};
+use cbor_event::{self, de::Deserializer};
+use profile;
+use serde_json;
+
+use std::{
+    error, fmt,
+    io::{self, Cursor, Read, Write},
+    process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// the seconds the signer is given to sign, unless configured otherwise
+pub const DEFAULT_TIMEOUT: u64 = 300;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    CannotRunSigner(String, io::Error),
+    SignerFailed(String, ExitStatus),
+    SignerTimedOut(String, u64),
+    InvalidResponse(serde_json::Error),
+    InvalidWitness(usize, String),
+    WrongWitnessCount(usize, usize), // (inputs, witnesses)
+    WitnessDoesNotSignTransaction(usize),
+    WitnessDoesNotMatchAddress(usize, String),
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::CannotRunSigner(name, _) => write!(f, "Cannot run the signer `{}'", name),
+            Error::SignerFailed(name, status) => {
+                write!(f, "The signer `{}' failed ({})", name, status)
+            }
+            Error::SignerTimedOut(name, timeout) => write!(
+                f,
+                "The signer `{}' did not sign within {} seconds",
+                name, timeout
+            ),
+            Error::InvalidResponse(_) => write!(f, "Invalid response of the signer"),
+            Error::InvalidWitness(input, reason) => {
+                write!(f, "Invalid witness of input {}: {}", input, reason)
+            }
+            Error::WrongWitnessCount(inputs, witnesses) => write!(
+                f,
+                "The signer returned {} witnesses for {} inputs",
+                witnesses, inputs
+            ),
+            Error::WitnessDoesNotSignTransaction(input) => {
+                write!(
+                    f,
+                    "The witness of input {} does not sign the transaction",
+                    input
+                )
+            }
+            Error::WitnessDoesNotMatchAddress(input, address) => write!(
+                f,
+                "The witness of input {} is not from the key of its address {}",
+                input, address
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::CannotRunSigner(_, ref err) => Some(err),
+            Error::SignerFailed(_, _) => None,
+            Error::SignerTimedOut(_, _) => None,
+            Error::InvalidResponse(ref err) => Some(err),
+            Error::InvalidWitness(_, _) => None,
+            Error::WrongWitnessCount(_, _) => None,
+            Error::WitnessDoesNotSignTransaction(_) => None,
+            Error::WitnessDoesNotMatchAddress(_, _) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// what the signer writes on its standard output
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    /// the CBOR of the witnesses, in hexadecimal
+    pub witnesses: Vec<String>,
+}
+
+/// run the signer on the signing information, returns the checked
+/// witnesses of the inputs, in the order of the inputs
+pub fn sign(name: &str, signer: &profile::Signer, info: &SigningInfo) -> Result<Vec<TxInWitness>> {
+    let request = serde_json::to_vec(info).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    let mut child = Command::new(&signer.command)
+        .args(&signer.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| Error::CannotRunSigner(name.to_owned(), e))?;
+
+    // the request is written and the response read in their own threads,
+    // the signer may exit (or hang) before reading the whole request
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("the standard input of the signer is piped");
+    let writer = thread::spawn(move || {
+        // closing the standard input tells the signer the request is over
+        stdin.write_all(&request)
+    });
+    let mut stdout = child
+        .stdout
+        .take()
+        .expect("the standard output of the signer is piped");
+    let reader = thread::spawn(move || {
+        let mut response = Vec::new();
+        stdout.read_to_end(&mut response).map(|_| response)
+    });
+
+    let timeout = signer.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    // a timeout too large for a deadline is no timeout
+    let deadline = Instant::now().checked_add(Duration::from_secs(timeout));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            if let Err(err) = child.kill() {
+                warn!("cannot kill the signer `{}': {}", name, err);
+            }
+            child.wait()?;
+            return Err(Error::SignerTimedOut(name.to_owned(), timeout));
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    // a signer failing before reading the whole request breaks the pipe:
+    // its exit status tells why
+    let written = writer.join().expect("the signer's writer does not panic");
+    let response = reader.join().expect("the signer's reader does not panic");
+    if !status.success() {
+        return Err(Error::SignerFailed(name.to_owned(), status));
+    }
+    written?;
+    let response: Response = serde_json::from_slice(&response?).map_err(Error::InvalidResponse)?;
+
+    if response.witnesses.len() != info.inputs.len() {
+        return Err(Error::WrongWitnessCount(
+            info.inputs.len(),
+            response.witnesses.len(),
+        ));
+    }
+
+    let tx: Tx = decode(&info.tx).expect("the transaction of the signing information is valid");
+    let protocol_magic = ProtocolMagic::from(info.protocol_magic);
+    let mut witnesses = Vec::with_capacity(response.witnesses.len());
+    for (index, (witness, input)) in response
+        .witnesses
+        .iter()
+        .zip(info.inputs.iter())
+        .enumerate()
+    {
+        let witness: TxInWitness =
+            decode(witness).map_err(|reason| Error::InvalidWitness(index, reason))?;
+        if !witness.verify_tx(protocol_magic, &tx) {
+            return Err(Error::WitnessDoesNotSignTransaction(index));
+        }
+        if let Some(ref address) = input.address {
+            let extended =
+                ExtendedAddr::from_str(address).expect("the addresses of the wallets are valid");
+            if !witness.verify_address(&extended) {
+                return Err(Error::WitnessDoesNotMatchAddress(index, address.clone()));
+            }
+        }
+        witnesses.push(witness);
+    }
+    Ok(witnesses)
+}
+
+fn decode<T: cbor_event::de::Deserialize>(hex_str: &str) -> ::std::result::Result<T, String> {
+    let bytes = hex::decode(hex_str).map_err(|_| "invalid hexadecimal".to_owned())?;
+    Deserializer::from(Cursor::new(bytes))
+        .deserialize_complete()
+        .map_err(|e| format!("invalid CBOR: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn shell(script: &str, timeout: Option<u64>) -> profile::Signer {
+        profile::Signer {
+            command: PathBuf::from("/bin/sh"),
+            args: vec!["-c".to_owned(), script.to_owned()],
+            timeout,
+        }
+    }
+
+    /// a request larger than the pipe's buffer, so writing it fails if the
+    /// signer does not read it
+    fn large_info() -> SigningInfo {
+        SigningInfo {
+            staging_id: String::new(),
+            protocol_magic: 764824073,
+            txid: String::new(),
+            tx: "00".repeat(1 << 20),
+            inputs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn signer_exiting_early() {
+        match sign("early", &shell("exit 3", None), &large_info()) {
+            Err(Error::SignerFailed(ref name, status)) => {
+                assert_eq!(name, "early");
+                assert_eq!(status.code(), Some(3));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn signer_hanging() {
+        let start = Instant::now();
+        match sign("hung", &shell("sleep 30", Some(1)), &large_info()) {
+            Err(Error::SignerTimedOut(ref name, 1)) => assert_eq!(name, "hung"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn invalid_response() {
+        let signer = shell("cat > /dev/null; echo not json", None);
+        match sign("invalid", &signer, &large_info()) {
+            Err(Error::InvalidResponse(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! what an external signer needs to sign a staging transaction
+//!
+//! A hardware wallet or an HSM keeps the keys of the wallet but does not
+//! know which of them the inputs of a transaction spend: `transaction
+//! signing-info` gives, for every input, the address it spends and the
+//! derivation path of the address's key from the wallet's root key, along
+//! with the transaction to sign.
+
+use super::core::{transaction, StagingTransaction};
+use super::error::Error;
+use cardano::{fee::LinearFee, tx::TxoPointer, util::hex};
+use cbor_event::se::Serializer;
+use wallet::{state::lookup, utils::load_utxo_index, utxo_index::UtxoIndex, Wallet};
+
+use std::path::Path;
+
+/// the hardened derivation indices are offset by this value
+pub const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputInfo {
+    pub txid: String,
+    pub index: u32,
+    /// the value of the spent output, in lovelace
+    pub value: u64,
+    /// the wallet owning the input, `None` if none of the given wallets does
+    pub wallet: Option<String>,
+    pub address: Option<String>,
+    /// the derivation path, `m/44'/1815'/ACCOUNT'/CHANGE/INDEX` for the
+    /// BIP44 wallets
+    pub derivation_path: Option<String>,
+    /// the indices of the BIP44 derivation path, the hardened ones offset
+    /// by `HARDENED`
+    pub derivation_indices: Option<Vec<u32>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningInfo {
+    pub staging_id: String,
+    pub protocol_magic: u32,
+    /// the identifier of the transaction, what the witnesses sign
+    pub txid: String,
+    /// the CBOR of the transaction, in hexadecimal
+    pub tx: String,
+    pub inputs: Vec<InputInfo>,
+}
+impl SigningInfo {
+    /// the inputs no given wallet owns
+    pub fn unowned_inputs(&self) -> impl Iterator<Item = &InputInfo> {
+        self.inputs.iter().filter(|input| input.wallet.is_none())
+    }
+}
+
+/// the derivation indices of the address, for the BIP44 addresses only:
+/// the derivation of the random index addresses is encrypted in the
+/// address itself
+pub fn derivation_indices(address: &lookup::Address) -> Option<Vec<u32>> {
+    match address {
+        lookup::Address::Bip44(address) => Some(vec![
+            HARDENED | 44,
+            HARDENED | 1815,
+            HARDENED | address.account.get_account_number(),
+            address.change,
+            address.index.get_scheme_value(),
+        ]),
+        _ => None,
+    }
+}
+
+/// the UTxO indexes of the wallets, to look the inputs up
+pub fn load_indexes(
+    root_dir: &Path,
+    wallets: Vec<Wallet>,
+) -> Result<Vec<(Wallet, UtxoIndex)>, Error> {
+    let mut indexes = Vec::new();
+    for wallet in wallets {
+        let index = load_utxo_index(&wallet, root_dir)?;
+        indexes.push((wallet, index));
+    }
+    Ok(indexes)
+}
+
+/// the signing information of the finalized staging transaction, the
+/// inputs being looked up in the UTxO indexes of the given wallets
+pub fn signing_info(
+    staging: &StagingTransaction,
+    indexes: &[(Wallet, UtxoIndex)],
+    fee_algorithm: &LinearFee,
+) -> Result<SigningInfo, Error> {
+    // the fee, hence the change and the transaction id, are only pinned
+    // once the transaction is finalized
+    if !staging.is_finalized() {
+        return Err(Error::CannotSignTransactionNotFinalized(
+            transaction::Error::TransactionNotFinalized,
+        ));
+    }
+    let tx = staging
+        .transaction()
+        .mk_txbuilder(fee_algorithm)
+        .map_err(Error::CannotSignTransactionNotFinalized)?
+        .0
+        .make_tx()
+        .map_err(Error::CannotSignTransactionInvalidTxAux)?;
+    let bytes = {
+        let mut serializer = Serializer::new_vec();
+        serializer
+            .serialize(&tx)
+            .expect("serialize the transaction in memory");
+        serializer.finalize()
+    };
+
+    let inputs = staging
+        .transaction()
+        .inputs()
+        .iter()
+        .map(|input| {
+            let txin = input.extract_txin();
+            input_info(&txin, u64::from(input.expected_value), indexes)
+        })
+        .collect();
+
+    Ok(SigningInfo {
+        staging_id: staging.id().to_string(),
+        protocol_magic: *staging.protocol_magic,
+        txid: tx.id().to_string(),
+        tx: hex::encode(&bytes),
+        inputs,
+    })
+}
+
+fn input_info(txin: &TxoPointer, value: u64, indexes: &[(Wallet, UtxoIndex)]) -> InputInfo {
+    let owner = indexes
+        .iter()
+        .filter_map(|(wallet, index)| index.get(txin).map(|utxo| (wallet, utxo)))
+        .next();
+    let mut info = InputInfo {
+        txid: txin.id.to_string(),
+        index: txin.index,
+        value,
+        wallet: None,
+        address: None,
+        derivation_path: None,
+        derivation_indices: None,
+    };
+    if let Some((wallet, utxo)) = owner {
+        info.wallet = Some(wallet.name.to_string());
+        info.address = Some(utxo.credited_address.to_string());
+        info.derivation_path = Some(utxo.credited_addressing.derivation_path());
+        info.derivation_indices = derivation_indices(&utxo.credited_addressing);
+    }
+    info
+}
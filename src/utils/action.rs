@@ -9,7 +9,7 @@
 //! The terminal output will print a spinning ticker, with the alias
 //! of each tasks being updated upon completion.
 //!
-//! ```no_run
+//! ```ignore
 //! # use cardano_cli::utils::{term::{Term, Config}, action::{Executor, Async}};
 //! use std::time::Duration;
 //! use std::thread;
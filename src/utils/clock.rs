@@ -0,0 +1,341 @@
+//! clock sanity
+//!
+//! The nodes reject the blocks and the transactions of the slots ahead of
+//! their clock and the TLS handshakes fail on certificates not yet (or no
+//! longer) valid: with the local clock off by more than a few seconds,
+//! `blockchain pull` and `transaction send` fail in confusing ways. `check`
+//! compares the local clock with an NTP server (SNTP, RFC 4330) before
+//! them and warns, or aborts, beyond the maximum skew.
+//!
+//! The check is off by default: the query goes to a third party server
+//! (`pool.ntp.org` unless the profile gives another one) and may take up to
+//! `TIMEOUT` seconds when the server does not answer.
+//!
+//! NTP runs over UDP, which a SOCKS5 proxy does not carry: the clock is not
+//! checked when the peers are reached through a proxy, the query would
+//! bypass it.
+
+use std::{
+    error, fmt, io,
+    net::{ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use utils::interaction::Interaction;
+
+/// the NTP server queried when the profile gives none
+pub const DEFAULT_NTP_SERVER: &'static str = "pool.ntp.org:123";
+
+/// the skew of the local clock allowed when the profile gives none, in
+/// seconds: well below the 20 seconds of a slot
+pub const DEFAULT_MAX_SKEW: u64 = 10;
+
+/// how long to wait for the NTP server, in seconds
+const TIMEOUT: u64 = 3;
+
+/// seconds from the NTP epoch (1900) to the UNIX epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const NTP_PACKET_SIZE: usize = 48;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    ServerNotFound(String),
+    InvalidResponse(String),
+    ClockSkewed(String, f64, u64), // (server, offset, maximum)
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::ServerNotFound(server) => write!(f, "Cannot resolve the NTP server `{}'", server),
+            Error::InvalidResponse(reason) => write!(f, "Invalid response of the NTP server: {}", reason),
+            Error::ClockSkewed(server, offset, max) => write!(
+                f,
+                "The local clock is {:.1} seconds {} the NTP server `{}', more than the {} seconds allowed: fix the system clock",
+                offset.abs(),
+                if *offset > 0.0 { "behind" } else { "ahead of" },
+                server,
+                max
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::ServerNotFound(_) => None,
+            Error::InvalidResponse(_) => None,
+            Error::ClockSkewed(_, _, _) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// what to do when the local clock is skewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Off,
+    Warn,
+    Abort,
+}
+impl FromStr for Policy {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Policy::Off),
+            "warn" => Ok(Policy::Warn),
+            "abort" => Ok(Policy::Abort),
+            _ => Err(format!(
+                "invalid clock check `{}', expected `off', `warn' or `abort'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub policy: Policy,
+    /// `HOST:PORT` of the NTP server
+    pub server: String,
+    /// in seconds
+    pub max_skew: u64,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            policy: Policy::Off,
+            server: DEFAULT_NTP_SERVER.to_owned(),
+            max_skew: DEFAULT_MAX_SKEW,
+        }
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs() as f64 + f64::from(since_epoch.subsec_nanos()) * 1e-9
+}
+
+/// the NTP timestamp of the packet at the given offset, in seconds since
+/// the UNIX epoch
+fn read_timestamp(packet: &[u8], offset: usize) -> f64 {
+    let word = |at: usize| {
+        (u32::from(packet[at]) << 24)
+            | (u32::from(packet[at + 1]) << 16)
+            | (u32::from(packet[at + 2]) << 8)
+            | u32::from(packet[at + 3])
+    };
+    let mut seconds = u64::from(word(offset));
+    // the timestamps of the era starting in 2036 wrap around
+    if seconds < NTP_UNIX_OFFSET {
+        seconds += 1 << 32;
+    }
+    (seconds - NTP_UNIX_OFFSET) as f64 + f64::from(word(offset + 4)) / 4_294_967_296.0
+}
+
+/// the offset of the server's clock from the local clock, in seconds:
+/// positive when the local clock is behind
+pub fn offset(server: &str) -> Result<f64> {
+    let address = server
+        .to_socket_addrs()
+        .map_err(|_| Error::ServerNotFound(server.to_owned()))?
+        .next()
+        .ok_or_else(|| Error::ServerNotFound(server.to_owned()))?;
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })?;
+    socket.set_read_timeout(Some(Duration::from_secs(TIMEOUT)))?;
+    socket.connect(address)?;
+
+    let mut request = [0u8; NTP_PACKET_SIZE];
+    // leap indicator 0, version 4, mode 3 (client)
+    request[0] = 0x23;
+    let sent = unix_seconds(SystemTime::now());
+    socket.send(&request)?;
+
+    let mut response = [0u8; NTP_PACKET_SIZE];
+    let size = socket.recv(&mut response)?;
+    let received = unix_seconds(SystemTime::now());
+    if size < NTP_PACKET_SIZE {
+        return Err(Error::InvalidResponse(format!("{} bytes", size)));
+    }
+    if response[0] & 0x07 != 4 {
+        return Err(Error::InvalidResponse("not a server response".to_owned()));
+    }
+    if response[1] == 0 {
+        return Err(Error::InvalidResponse("kiss-o'-death".to_owned()));
+    }
+
+    let server_received = read_timestamp(&response, 32);
+    let server_sent = read_timestamp(&response, 40);
+    Ok(((server_received - sent) + (server_sent - received)) / 2.0)
+}
+
+/// check the local clock against the NTP server before a network
+/// operation, unless the peers are reached through a proxy. The server not
+/// answering is only logged: the operation is still attempted.
+pub fn check(term: &mut Interaction, config: &Config, proxy: Option<&str>) -> Result<()> {
+    if config.policy == Policy::Off {
+        return Ok(());
+    }
+    if proxy.is_some() {
+        info!("clock not checked, the NTP query would bypass the proxy");
+        return Ok(());
+    }
+
+    let offset = match offset(&config.server) {
+        Ok(offset) => offset,
+        Err(err) => {
+            warn!(
+                "cannot check the clock against the NTP server {}: {}",
+                config.server, err
+            );
+            return Ok(());
+        }
+    };
+    debug!(
+        "clock offset from the NTP server {}: {:.3} seconds",
+        config.server, offset
+    );
+    if offset.abs() <= config.max_skew as f64 {
+        return Ok(());
+    }
+
+    let skewed = Error::ClockSkewed(config.server.clone(), offset, config.max_skew);
+    match config.policy {
+        Policy::Abort => Err(skewed),
+        _ => {
+            term.warn(&format!("{}\n", skewed))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    /// write the UNIX time as the NTP timestamp of the packet at the given
+    /// offset
+    fn write_timestamp(packet: &mut [u8], offset: usize, unix: f64) {
+        let ntp = unix + NTP_UNIX_OFFSET as f64;
+        let seconds = (ntp as u64 % (1 << 32)) as u32;
+        let fraction = (ntp.fract() * 4_294_967_296.0) as u32;
+        for (i, word) in [seconds, fraction].iter().enumerate() {
+            for byte in 0..4 {
+                packet[offset + i * 4 + byte] = (word >> (24 - 8 * byte)) as u8;
+            }
+        }
+    }
+
+    fn assert_time(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// a local NTP server answering a single request with the response
+    /// built from it, and its address
+    fn serve_once<F>(respond: F) -> String
+    where
+        F: FnOnce(&[u8]) -> Vec<u8> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut request = [0u8; NTP_PACKET_SIZE];
+            let (size, client) = socket.recv_from(&mut request).unwrap();
+            socket.send_to(&respond(&request[..size]), client).unwrap();
+        });
+        address
+    }
+
+    /// a server response whose clock is `ahead` seconds ahead of the local
+    /// one
+    fn response(ahead: f64) -> Vec<u8> {
+        let mut response = vec![0u8; NTP_PACKET_SIZE];
+        // leap indicator 0, version 4, mode 4 (server), stratum 1
+        response[0] = 0x24;
+        response[1] = 1;
+        let now = unix_seconds(SystemTime::now()) + ahead;
+        write_timestamp(&mut response, 32, now);
+        write_timestamp(&mut response, 40, now);
+        response
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut packet = [0u8; NTP_PACKET_SIZE];
+        write_timestamp(&mut packet, 40, 0.5);
+        assert_eq!(&packet[40..48], &[0x83, 0xaa, 0x7e, 0x80, 0x80, 0, 0, 0]);
+        assert_time(read_timestamp(&packet, 40), 0.5);
+
+        // 2019-01-01T00:00:00Z
+        write_timestamp(&mut packet, 32, 1_546_300_800.0);
+        assert_time(read_timestamp(&packet, 32), 1_546_300_800.0);
+    }
+
+    #[test]
+    fn timestamps_wrap_around_in_2036() {
+        // the era ends at 2036-02-07T06:28:16Z
+        let era_end = (1u64 << 32) - NTP_UNIX_OFFSET;
+        let mut packet = [0u8; NTP_PACKET_SIZE];
+
+        write_timestamp(&mut packet, 32, era_end as f64 - 1.0);
+        assert_eq!(&packet[32..36], &[0xff, 0xff, 0xff, 0xff]);
+        assert_time(read_timestamp(&packet, 32), era_end as f64 - 1.0);
+
+        write_timestamp(&mut packet, 32, era_end as f64 + 1.0);
+        assert_eq!(&packet[32..36], &[0, 0, 0, 1]);
+        assert_time(read_timestamp(&packet, 32), era_end as f64 + 1.0);
+    }
+
+    #[test]
+    fn offset_of_the_server_clock() {
+        let server = serve_once(|request| {
+            // version 4, mode 3 (client)
+            assert_eq!(request[0], 0x23);
+            response(100.0)
+        });
+        let offset = offset(&server).unwrap();
+        assert!((offset - 100.0).abs() < 1.0, "offset {}", offset);
+
+        let server = serve_once(|_| response(-100.0));
+        let offset = offset(&server).unwrap();
+        assert!((offset + 100.0).abs() < 1.0, "offset {}", offset);
+    }
+
+    #[test]
+    fn invalid_responses() {
+        let invalid = |respond: fn(&[u8]) -> Vec<u8>| match offset(&serve_once(respond)) {
+            Err(Error::InvalidResponse(reason)) => reason,
+            other => panic!("unexpected result {:?}", other),
+        };
+        assert_eq!(invalid(|_| vec![0x24, 1]), "2 bytes");
+        assert_eq!(invalid(|request| request.to_vec()), "not a server response");
+        assert_eq!(
+            invalid(|_| {
+                let mut response = response(0.0);
+                response[1] = 0;
+                response
+            }),
+            "kiss-o'-death"
+        );
+    }
+}
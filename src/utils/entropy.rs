@@ -0,0 +1,210 @@
+//! sources of randomness
+//!
+//! All the randomness of the CLI (key material, salts and nonces of the
+//! encrypted keys, staging identifiers...) is drawn from an
+//! `EntropySource`, so a security review only has to look here to know
+//! where randomness enters the key material.
+//!
+//! The default source is the operating system's RNG. If the environment
+//! variable `CARDANO_CLI_ENTROPY_AUDIT` names a file, every draw is also
+//! recorded in this file (what the bytes are for, how many, from which
+//! source), never the drawn bytes themselves.
+
+use cardano::bip;
+use rand::{rngs::OsRng, RngCore};
+use std::{env, fmt, fs, io::Write, path::PathBuf, process, time::SystemTime};
+
+/// environment variable naming the file to record the draws to
+pub const ENTROPY_AUDIT_ENV: &'static str = "CARDANO_CLI_ENTROPY_AUDIT";
+
+/// what the random bytes are drawn for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// entropy of the mnemonic words of a new wallet
+    Mnemonic,
+    /// a private key generated directly from random bytes
+    PrivateKey,
+    /// salt of the password derivation of an encrypted key
+    Salt,
+    /// nonce of the encryption of a key
+    Nonce,
+    /// identifier of a staging transaction
+    StagingId,
+    /// anything not related to keys (temporary directory names...)
+    Other,
+}
+impl Purpose {
+    /// whether the bytes end up in, or protect, key material
+    pub fn is_key_material(self) -> bool {
+        match self {
+            Purpose::Mnemonic | Purpose::PrivateKey | Purpose::Salt | Purpose::Nonce => true,
+            Purpose::StagingId | Purpose::Other => false,
+        }
+    }
+}
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Purpose::Mnemonic => write!(f, "mnemonic"),
+            Purpose::PrivateKey => write!(f, "private-key"),
+            Purpose::Salt => write!(f, "salt"),
+            Purpose::Nonce => write!(f, "nonce"),
+            Purpose::StagingId => write!(f, "staging-id"),
+            Purpose::Other => write!(f, "other"),
+        }
+    }
+}
+
+pub trait EntropySource {
+    /// name of the source, as recorded by the audit
+    fn name(&self) -> &'static str;
+
+    /// fill `dest` with random bytes drawn for the given purpose
+    fn fill_bytes(&mut self, purpose: Purpose, dest: &mut [u8]);
+
+    fn next_u32(&mut self, purpose: Purpose) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(purpose, &mut bytes);
+        u32::from(bytes[0])
+            | u32::from(bytes[1]) << 8
+            | u32::from(bytes[2]) << 16
+            | u32::from(bytes[3]) << 24
+    }
+}
+
+/// the operating system's RNG
+pub struct OsEntropy(OsRng);
+impl OsEntropy {
+    pub fn new() -> Self {
+        // without a working OS RNG there is no safe way to generate keys
+        OsEntropy(OsRng::new().expect("the operating system's RNG is not available"))
+    }
+}
+impl EntropySource for OsEntropy {
+    fn name(&self) -> &'static str {
+        "os"
+    }
+    fn fill_bytes(&mut self, _purpose: Purpose, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+}
+
+/// wraps a source and records each draw to the audit file
+pub struct AuditedEntropy<S> {
+    inner: S,
+    audit_file: PathBuf,
+}
+impl<S: EntropySource> AuditedEntropy<S> {
+    pub fn new(inner: S, audit_file: PathBuf) -> Self {
+        AuditedEntropy { inner, audit_file }
+    }
+
+    fn record(&self, purpose: Purpose, len: usize) {
+        let line = format!(
+            "{} pid={} source={} purpose={} key-material={} bytes={}\n",
+            ::humantime::format_rfc3339(SystemTime::now()),
+            process::id(),
+            self.inner.name(),
+            purpose,
+            purpose.is_key_material(),
+            len
+        );
+        let result = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.audit_file)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(err) = result {
+            // an audit that silently loses entries is worse than none
+            panic!(
+                "cannot record the entropy draw to {:?}: {}",
+                self.audit_file, err
+            );
+        }
+    }
+}
+impl<S: EntropySource> EntropySource for AuditedEntropy<S> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    fn fill_bytes(&mut self, purpose: Purpose, dest: &mut [u8]) {
+        self.record(purpose, dest.len());
+        self.inner.fill_bytes(purpose, dest)
+    }
+}
+
+/// deterministic source, for the tests only
+#[cfg(test)]
+pub struct SeededEntropy(::rand::rngs::StdRng);
+#[cfg(test)]
+impl SeededEntropy {
+    pub fn new(seed: u64) -> Self {
+        use rand::SeedableRng;
+        SeededEntropy(::rand::rngs::StdRng::seed_from_u64(seed))
+    }
+}
+#[cfg(test)]
+impl EntropySource for SeededEntropy {
+    fn name(&self) -> &'static str {
+        "seeded"
+    }
+    fn fill_bytes(&mut self, _purpose: Purpose, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+}
+
+/// the source to use: the OS RNG, audited if `CARDANO_CLI_ENTROPY_AUDIT`
+/// is set
+pub fn default_source() -> Box<EntropySource> {
+    match env::var_os(ENTROPY_AUDIT_ENV) {
+        Some(ref file) if !file.is_empty() => {
+            Box::new(AuditedEntropy::new(OsEntropy::new(), PathBuf::from(file)))
+        }
+        _ => Box::new(OsEntropy::new()),
+    }
+}
+
+/// draw the entropy of new mnemonic words of the given size
+pub fn mnemonic_entropy(source: &mut EntropySource, size: bip::bip39::Type) -> bip::bip39::Entropy {
+    let mut bytes = [0; 32];
+    let bytes = &mut bytes[..size.to_key_size() / 8];
+    source.fill_bytes(Purpose::Mnemonic, bytes);
+    bip::bip39::Entropy::from_slice(bytes).expect("entropy of the size of the mnemonic type")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_is_deterministic() {
+        let mut a = [0; 32];
+        let mut b = [0; 32];
+        SeededEntropy::new(42).fill_bytes(Purpose::Other, &mut a);
+        SeededEntropy::new(42).fill_bytes(Purpose::Other, &mut b);
+        assert_eq!(a, b);
+
+        SeededEntropy::new(43).fill_bytes(Purpose::Other, &mut b);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn audit_records_draws_not_bytes() {
+        let audit_file =
+            env::temp_dir().join(format!("cardano-cli-entropy-audit-{}", process::id()));
+        let _ = fs::remove_file(&audit_file);
+
+        let mut source = AuditedEntropy::new(SeededEntropy::new(1), audit_file.clone());
+        let mut salt = [0; 16];
+        source.fill_bytes(Purpose::Salt, &mut salt);
+        source.next_u32(Purpose::StagingId);
+
+        let audit = fs::read_to_string(&audit_file).unwrap();
+        fs::remove_file(&audit_file).unwrap();
+
+        let lines: Vec<&str> = audit.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("source=seeded purpose=salt key-material=true bytes=16"));
+        assert!(lines[1].contains("purpose=staging-id key-material=false bytes=4"));
+    }
+}
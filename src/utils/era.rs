@@ -0,0 +1,517 @@
+//! eras of the blockchain
+//!
+//! Only the Byron era is supported, but the addresses (bech32, `addr1...`),
+//! the transactions and the blocks of the Shelley era and the later ones
+//! show up in what the users give and in what the peers send. Parsed as
+//! Byron structures, they fail with opaque base58 or CBOR errors: they are
+//! recognized first, to fail with an "unsupported era" error.
+//!
+//! The eras after Shelley share its address and transaction formats, they
+//! are told apart in the blocks only (the era tag of the hard fork
+//! combinator).
+
+use cardano::{address::ExtendedAddr, util::base58, util::try_from_slice::TryFromSlice};
+
+use std::{error, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Era {
+    Byron,
+    Shelley,
+    Allegra,
+    Mary,
+    Alonzo,
+    Babbage,
+    Conway,
+    /// an era tag unknown to this version
+    Unknown(u8),
+}
+impl Era {
+    /// the era of the tag of the blocks of the hard fork combinator, the
+    /// Byron boundary (0) and main (1) blocks keeping their own tags
+    pub fn from_block_tag(tag: u8) -> Self {
+        match tag {
+            0 | 1 => Era::Byron,
+            2 => Era::Shelley,
+            3 => Era::Allegra,
+            4 => Era::Mary,
+            5 => Era::Alonzo,
+            6 => Era::Babbage,
+            7 => Era::Conway,
+            tag => Era::Unknown(tag),
+        }
+    }
+
+    pub fn is_supported(self) -> bool {
+        self == Era::Byron
+    }
+}
+impl fmt::Display for Era {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Era::Byron => write!(f, "Byron"),
+            Era::Shelley => write!(f, "Shelley"),
+            Era::Allegra => write!(f, "Allegra"),
+            Era::Mary => write!(f, "Mary"),
+            Era::Alonzo => write!(f, "Alonzo"),
+            Era::Babbage => write!(f, "Babbage"),
+            Era::Conway => write!(f, "Conway"),
+            Era::Unknown(tag) => write!(f, "unknown (tag {})", tag),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidAddress(String),
+    UnsupportedAddress(String),
+    UnsupportedTransaction,
+    UnsupportedBlock(Era),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidAddress(address) => write!(f, "Invalid address `{}'", address),
+            Error::UnsupportedAddress(address) => write!(
+                f,
+                "`{}' is an address of the Shelley era (or a later one), only the Byron era addresses are supported",
+                address
+            ),
+            Error::UnsupportedTransaction => write!(
+                f,
+                "The transaction is of the Shelley era (or a later one), only the Byron era transactions are supported"
+            ),
+            Error::UnsupportedBlock(era) => write!(
+                f,
+                "The block is of the {} era, only the Byron era blocks are supported",
+                era
+            ),
+        }
+    }
+}
+impl error::Error for Error {}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// the human readable prefixes of the bech32 Shelley addresses
+const SHELLEY_ADDRESS_PREFIXES: &'static [&'static str] =
+    &["addr", "addr_test", "stake", "stake_test"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShelleyAddressKind {
+    Base,
+    Pointer,
+    Enterprise,
+    Reward,
+}
+impl fmt::Display for ShelleyAddressKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShelleyAddressKind::Base => write!(f, "base"),
+            ShelleyAddressKind::Pointer => write!(f, "pointer"),
+            ShelleyAddressKind::Enterprise => write!(f, "enterprise"),
+            ShelleyAddressKind::Reward => write!(f, "reward (stake)"),
+        }
+    }
+}
+
+/// a Shelley era address, only checked: it cannot be used by the commands
+#[derive(Debug, Clone)]
+pub struct ShelleyAddress {
+    pub text: String,
+    pub kind: ShelleyAddressKind,
+    /// the network id of the header: 1 for the mainnet, 0 for the testnets
+    pub network: u8,
+}
+
+/// an address of any era
+#[derive(Debug, Clone)]
+pub enum Address {
+    Byron(ExtendedAddr),
+    Shelley(ShelleyAddress),
+}
+
+/// the era of the address given as text, none if it is not a valid address
+pub fn address_era(address: &str) -> Option<Era> {
+    match validate_address(address) {
+        Ok(Address::Byron(_)) => Some(Era::Byron),
+        Ok(Address::Shelley(_)) => Some(Era::Shelley),
+        Err(_) => None,
+    }
+}
+
+/// the era of the address from its header, none if it has no valid header
+pub fn address_bytes_era(bytes: &[u8]) -> Option<Era> {
+    match bytes.first().map(|header| header >> 4) {
+        // the CBOR array of the Byron addresses
+        Some(8) => Some(Era::Byron),
+        Some(0..=7) | Some(14) | Some(15) => Some(Era::Shelley),
+        _ => None,
+    }
+}
+
+/// check the address of any era: the Byron addresses are parsed, the
+/// Shelley ones are passed through once their checksum and header are
+/// checked
+pub fn validate_address(address: &str) -> Result<Address> {
+    let invalid = || Error::InvalidAddress(address.to_owned());
+    // a bech32 string may happen to be valid base58 too
+    if let Ok(bytes) = base58::decode(address) {
+        if let Ok(address) = ExtendedAddr::try_from_slice(&bytes) {
+            return Ok(Address::Byron(address));
+        }
+    }
+
+    let (prefix, bytes) = bech32_decode(address).ok_or_else(invalid)?;
+    if !SHELLEY_ADDRESS_PREFIXES.contains(&prefix.as_str()) {
+        return Err(invalid());
+    }
+    let header = *bytes.first().ok_or_else(invalid)?;
+    let kind = match header >> 4 {
+        0..=3 => ShelleyAddressKind::Base,
+        4 | 5 => ShelleyAddressKind::Pointer,
+        6 | 7 => ShelleyAddressKind::Enterprise,
+        14 | 15 => ShelleyAddressKind::Reward,
+        _ => return Err(invalid()),
+    };
+    Ok(Address::Shelley(ShelleyAddress {
+        text: address.to_owned(),
+        kind,
+        network: header & 0x0f,
+    }))
+}
+
+/// parse the Byron address, failing with `UnsupportedAddress` for the
+/// addresses of the later eras
+pub fn parse_address(address: &str) -> Result<ExtendedAddr> {
+    match validate_address(address)? {
+        Address::Byron(address) => Ok(address),
+        Address::Shelley(shelley) => Err(Error::UnsupportedAddress(shelley.text)),
+    }
+}
+
+/// the era of the CBOR of a signed or unsigned transaction, none if it is
+/// neither a Byron nor a Shelley transaction
+pub fn transaction_era(bytes: &[u8]) -> Option<Era> {
+    match (bytes.get(0).cloned(), bytes.get(1).cloned()) {
+        // the transaction and its witnesses
+        (Some(0x82), Some(0x83)) => Some(Era::Byron),
+        // the inputs, the outputs and the attributes
+        (Some(0x83), Some(0x80..=0x9f)) => Some(Era::Byron),
+        // the body (a map), the witnesses, the metadata (and the validity
+        // since the Alonzo era)
+        (Some(0x83), Some(0xa0..=0xbf)) | (Some(0x84), Some(0xa0..=0xbf)) => Some(Era::Shelley),
+        // the body alone
+        (Some(0xa0..=0xbf), _) => Some(Era::Shelley),
+        _ => None,
+    }
+}
+
+/// fail with `UnsupportedTransaction` for the transactions of the later eras
+pub fn check_transaction(bytes: &[u8]) -> Result<()> {
+    match transaction_era(bytes) {
+        Some(era) if !era.is_supported() => Err(Error::UnsupportedTransaction),
+        _ => Ok(()),
+    }
+}
+
+/// the era of the CBOR of a block, `[era tag, block]`, none if it has no
+/// era tag
+pub fn block_era(bytes: &[u8]) -> Option<Era> {
+    match (bytes.get(0).cloned(), bytes.get(1).cloned()) {
+        (Some(0x82), Some(tag)) if tag <= 0x17 => Some(Era::from_block_tag(tag)),
+        _ => None,
+    }
+}
+
+/// fail with `UnsupportedBlock` for the blocks of the later eras
+pub fn check_block(bytes: &[u8]) -> Result<()> {
+    match block_era(bytes) {
+        Some(era) if !era.is_supported() => Err(Error::UnsupportedBlock(era)),
+        _ => Ok(()),
+    }
+}
+
+const BECH32_CHARSET: &'static [u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CHECKSUM_SIZE: usize = 6;
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATORS: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x01ff_ffff) << 5) ^ u32::from(*value);
+        for (index, generator) in GENERATORS.iter().enumerate() {
+            if (top >> index) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// the human readable prefix and the data of the bech32 string (BIP173,
+/// without the 90 characters limit the Shelley addresses exceed), none if
+/// it is not one or its checksum is wrong
+fn bech32_decode(s: &str) -> Option<(String, Vec<u8>)> {
+    if s.chars().any(|c| c.is_lowercase()) && s.chars().any(|c| c.is_uppercase()) {
+        return None;
+    }
+    let s = s.to_lowercase();
+    let separator = s.rfind('1')?;
+    if separator == 0 || separator + 1 + BECH32_CHECKSUM_SIZE > s.len() {
+        return None;
+    }
+    let (prefix, data) = (&s[..separator], &s[separator + 1..]);
+    if prefix.bytes().any(|b| b < 33 || b > 126) {
+        return None;
+    }
+
+    let mut values = Vec::with_capacity(data.len());
+    for c in data.bytes() {
+        values.push(BECH32_CHARSET.iter().position(|&d| d == c)? as u8);
+    }
+    let mut checked: Vec<u8> = prefix.bytes().map(|b| b >> 5).collect();
+    checked.push(0);
+    checked.extend(prefix.bytes().map(|b| b & 0x1f));
+    checked.extend(values.iter());
+    if bech32_polymod(&checked) != 1 {
+        return None;
+    }
+
+    // from the 5 bits groups to the bytes
+    let mut bytes = Vec::new();
+    let (mut accumulator, mut bits) = (0u32, 0u32);
+    for value in &values[..values.len() - BECH32_CHECKSUM_SIZE] {
+        accumulator = (accumulator << 5) | u32::from(*value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+            accumulator &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 5 || accumulator != 0 {
+        return None;
+    }
+    Some((prefix.to_owned(), bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{
+        coin::Coin,
+        config::ProtocolMagic,
+        hash::Blake2b256,
+        tx::{Tx, TxAux, TxInWitness, TxOut, TxWitness, TxoPointer},
+    };
+    use cbor_event::se::{self, Serializer};
+    use utils::testing::{address, xprv};
+
+    fn encode<T: se::Serialize>(value: &T) -> Vec<u8> {
+        let mut se = Serializer::new_vec();
+        se.serialize(value).unwrap();
+        se.finalize()
+    }
+
+    // the test vectors of BIP173
+    #[test]
+    fn bech32_valid() {
+        assert_eq!(bech32_decode("A12UEL5L"), Some(("a".to_owned(), vec![])));
+        assert_eq!(bech32_decode("a12uel5l"), Some(("a".to_owned(), vec![])));
+        let (prefix, data) = bech32_decode(
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+        )
+        .unwrap();
+        assert_eq!(
+            prefix,
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio"
+        );
+        assert!(data.is_empty());
+        let (prefix, data) =
+            bech32_decode("abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw").unwrap();
+        assert_eq!(prefix, "abcdef");
+        assert_eq!(data.len(), 20);
+        let (prefix, data) = bech32_decode(&format!("11{}c8247j", "q".repeat(82))).unwrap();
+        assert_eq!(prefix, "1");
+        assert_eq!(data, vec![0; 51]);
+    }
+
+    #[test]
+    fn bech32_invalid() {
+        for invalid in [
+            // a character of the prefix out of range
+            "\x201nwldj5",
+            // no separator
+            "pzry9x0s0muk",
+            // empty prefix
+            "1pzry9x0s0muk",
+            "10a06t8",
+            "1qzzfhee",
+            // invalid character of the data
+            "x1b4n0q5v",
+            // checksum too short
+            "li1dgmt3",
+            // checksum computed with the uppercase prefix
+            "A1G7SGD8",
+            // mixed case
+            "A12uEL5L",
+            // one character changed
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxx",
+        ]
+        .iter()
+        {
+            assert_eq!(bech32_decode(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    // the test vectors of CIP-19
+    #[test]
+    fn shelley_addresses() {
+        let vectors = [
+            (
+                "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x",
+                ShelleyAddressKind::Base,
+                1,
+            ),
+            (
+                "addr_test1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs68faae",
+                ShelleyAddressKind::Base,
+                0,
+            ),
+            (
+                "addr1gx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer5pnz75xxcrzqf96k",
+                ShelleyAddressKind::Pointer,
+                1,
+            ),
+            (
+                "addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8",
+                ShelleyAddressKind::Enterprise,
+                1,
+            ),
+            (
+                "addr_test1vz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzerspjrlsz",
+                ShelleyAddressKind::Enterprise,
+                0,
+            ),
+            (
+                "stake1uyehkck0lajq8gr28t9uxnuvgcqrc6070x3k9r8048z8y5gh6ffgw",
+                ShelleyAddressKind::Reward,
+                1,
+            ),
+            (
+                "stake_test1uqehkck0lajq8gr28t9uxnuvgcqrc6070x3k9r8048z8y5gssrtvn",
+                ShelleyAddressKind::Reward,
+                0,
+            ),
+        ];
+        for (text, kind, network) in vectors.iter() {
+            match validate_address(text) {
+                Ok(Address::Shelley(address)) => {
+                    assert_eq!(&address.kind, kind, "{}", text);
+                    assert_eq!(&address.network, network, "{}", text);
+                }
+                other => panic!("{}: {:?}", text, other),
+            }
+            assert_eq!(address_era(text), Some(Era::Shelley));
+            match parse_address(text) {
+                Err(Error::UnsupportedAddress(_)) => {}
+                other => panic!("{}: {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn other_addresses() {
+        let byron = address(1);
+        match parse_address(&byron.to_string()) {
+            Ok(address) => assert_eq!(address, byron),
+            Err(err) => panic!("{}", err),
+        }
+        assert_eq!(address_era(&byron.to_string()), Some(Era::Byron));
+
+        // a valid bech32 string of another prefix
+        assert_eq!(
+            address_era("abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw"),
+            None
+        );
+        // a Shelley address with a checksum error
+        assert_eq!(
+            address_era("addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl9"),
+            None
+        );
+        match validate_address("not an address") {
+            Err(Error::InvalidAddress(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn address_headers() {
+        assert_eq!(address_bytes_era(&encode(&address(1))), Some(Era::Byron));
+        assert_eq!(address_bytes_era(&[0x01]), Some(Era::Shelley));
+        assert_eq!(address_bytes_era(&[0x61]), Some(Era::Shelley));
+        assert_eq!(address_bytes_era(&[0xe1]), Some(Era::Shelley));
+        assert_eq!(address_bytes_era(&[0x91]), None);
+        assert_eq!(address_bytes_era(&[]), None);
+    }
+
+    #[test]
+    fn transactions() {
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(Blake2b256::new(&[1]), 0)],
+            vec![TxOut::new(address(1), Coin::new(1_000_000).unwrap())],
+        );
+        let witness = TxInWitness::new(ProtocolMagic::from(764824073), &xprv(1), &tx.id());
+        let txaux = TxAux::new(tx.clone(), TxWitness::from(vec![witness]));
+        assert_eq!(transaction_era(&encode(&tx)), Some(Era::Byron));
+        assert_eq!(transaction_era(&encode(&txaux)), Some(Era::Byron));
+        assert!(check_transaction(&encode(&txaux)).is_ok());
+
+        // the body, the witnesses and the metadata (null)
+        let shelley = [0x83, 0xa4, 0x00, 0x81];
+        // and the validity, since the Alonzo era
+        let alonzo = [0x84, 0xa5, 0x00, 0x81];
+        // the body alone
+        let body = [0xa3, 0x00, 0x81];
+        for bytes in [&shelley[..], &alonzo[..], &body[..]].iter() {
+            assert_eq!(transaction_era(bytes), Some(Era::Shelley));
+            match check_transaction(bytes) {
+                Err(Error::UnsupportedTransaction) => {}
+                other => panic!("{:?}: {:?}", bytes, other),
+            }
+        }
+
+        // left to the Byron decoder to reject
+        assert_eq!(transaction_era(&[0x01, 0x02]), None);
+        assert_eq!(transaction_era(&[]), None);
+        assert!(check_transaction(&[0x01]).is_ok());
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(block_era(&[0x82, 0x00, 0x83]), Some(Era::Byron));
+        assert_eq!(block_era(&[0x82, 0x01, 0x83]), Some(Era::Byron));
+        assert_eq!(block_era(&[0x82, 0x02, 0x84]), Some(Era::Shelley));
+        assert_eq!(block_era(&[0x82, 0x05, 0x85]), Some(Era::Alonzo));
+        assert_eq!(block_era(&[0x82, 0x07, 0x85]), Some(Era::Conway));
+        assert_eq!(block_era(&[0x82, 0x08, 0x85]), Some(Era::Unknown(8)));
+        // not a small integer tag
+        assert_eq!(block_era(&[0x82, 0x18, 0x20]), None);
+        assert_eq!(block_era(&[0x83, 0x00]), None);
+        assert_eq!(block_era(&[]), None);
+
+        assert!(check_block(&[0x82, 0x01, 0x83]).is_ok());
+        match check_block(&[0x82, 0x06, 0x85]) {
+            Err(Error::UnsupportedBlock(Era::Babbage)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
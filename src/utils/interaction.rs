@@ -0,0 +1,250 @@
+//! interaction of the long running operations with their user
+//!
+//! the sync of the blockchains and of the wallets, the rescans and the
+//! signing of the transactions report their progress and ask for the
+//! spending passwords through the `Interaction` trait instead of the
+//! terminal directly, so they can run without one: the terminal (see
+//! `utils::term`) is the implementation of the command line, `Silent` the
+//! one of the library (nothing is reported, the password is given ahead)
+//! and `JsonEvents` reports one JSON object per line, for the services and
+//! their logs.
+//!
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, Write},
+    rc::Rc,
+};
+
+use schema;
+use serde_json;
+
+/// progress of an operation of known length
+pub trait Progress {
+    fn set_message(&self, msg: &str);
+    fn inc(&self, delta: u64);
+    fn finish(&self);
+}
+
+pub trait Interaction {
+    /// report the operation completed
+    fn success(&mut self, msg: &str) -> io::Result<()>;
+    /// report what the operation is doing
+    fn info(&mut self, msg: &str) -> io::Result<()>;
+    /// report something the user should look at (a rollback, an alarm...)
+    fn warn(&mut self, msg: &str) -> io::Result<()>;
+    /// report the operation cannot complete, before it stops
+    fn error(&mut self, msg: &str) -> io::Result<()>;
+    /// the progress of the next `count` steps of the operation
+    fn progress(&mut self, count: u64) -> Box<Progress>;
+    /// the spending password of the wallet the operation needs
+    fn password(&mut self, prompt: &str) -> io::Result<String>;
+}
+
+/// nothing is reported, the spending password (if the operation needs one)
+/// is the one given ahead
+#[derive(Default)]
+pub struct Silent {
+    password: Option<String>,
+}
+impl Silent {
+    pub fn new() -> Self {
+        Silent { password: None }
+    }
+    pub fn with_password(password: String) -> Self {
+        Silent {
+            password: Some(password),
+        }
+    }
+}
+impl Interaction for Silent {
+    fn success(&mut self, _msg: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn info(&mut self, _msg: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn warn(&mut self, _msg: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn error(&mut self, _msg: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn progress(&mut self, _count: u64) -> Box<Progress> {
+        Box::new(NoProgress)
+    }
+    fn password(&mut self, prompt: &str) -> io::Result<String> {
+        match self.password {
+            Some(ref password) => Ok(password.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "Cannot prompt `{}', no password was given",
+                    prompt.trim_end_matches(|c| c == ':' || c == ' ')
+                ),
+            )),
+        }
+    }
+}
+
+struct NoProgress;
+impl Progress for NoProgress {
+    fn set_message(&self, _msg: &str) {}
+    fn inc(&self, _delta: u64) {}
+    fn finish(&self) {}
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Success {
+        message: &'a str,
+    },
+    Info {
+        message: &'a str,
+    },
+    Warning {
+        message: &'a str,
+    },
+    Error {
+        message: &'a str,
+    },
+    Progress {
+        message: &'a str,
+        position: u64,
+        total: u64,
+    },
+    Finished {
+        message: &'a str,
+        position: u64,
+        total: u64,
+    },
+}
+
+fn write_event<W: Write>(output: &RefCell<W>, event: &Event) -> io::Result<()> {
+    schema::PROGRESS_EVENT.debug_check(event);
+    let line = serde_json::to_string(event).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let mut output = output.borrow_mut();
+    writeln!(output, "{}", line)?;
+    output.flush()
+}
+
+/// one JSON object per line, `{"event":"info","message":"..."}`: the
+/// messages (without their line ending), and the progress every percent of
+/// the operation.
+///
+/// The spending passwords are not prompted, they are the one given ahead
+/// (if any).
+pub struct JsonEvents<W: Write> {
+    output: Rc<RefCell<W>>,
+    password: Option<String>,
+}
+impl<W: Write> JsonEvents<W> {
+    pub fn new(output: W) -> Self {
+        JsonEvents {
+            output: Rc::new(RefCell::new(output)),
+            password: None,
+        }
+    }
+    pub fn with_password(output: W, password: String) -> Self {
+        JsonEvents {
+            output: Rc::new(RefCell::new(output)),
+            password: Some(password),
+        }
+    }
+
+    fn message(&mut self, event: Event) -> io::Result<()> {
+        write_event(&self.output, &event)
+    }
+}
+impl<W: Write + 'static> Interaction for JsonEvents<W> {
+    fn success(&mut self, msg: &str) -> io::Result<()> {
+        self.message(Event::Success {
+            message: msg.trim_end(),
+        })
+    }
+    fn info(&mut self, msg: &str) -> io::Result<()> {
+        self.message(Event::Info {
+            message: msg.trim_end(),
+        })
+    }
+    fn warn(&mut self, msg: &str) -> io::Result<()> {
+        self.message(Event::Warning {
+            message: msg.trim_end(),
+        })
+    }
+    fn error(&mut self, msg: &str) -> io::Result<()> {
+        self.message(Event::Error {
+            message: msg.trim_end(),
+        })
+    }
+    fn progress(&mut self, count: u64) -> Box<Progress> {
+        Box::new(JsonProgress {
+            output: self.output.clone(),
+            total: count,
+            position: Cell::new(0),
+            reported: Cell::new(None),
+            message: RefCell::new(String::new()),
+        })
+    }
+    fn password(&mut self, prompt: &str) -> io::Result<String> {
+        Silent {
+            password: self.password.clone(),
+        }
+        .password(prompt)
+    }
+}
+
+struct JsonProgress<W: Write> {
+    output: Rc<RefCell<W>>,
+    total: u64,
+    position: Cell<u64>,
+    /// the percent of the last progress event
+    reported: Cell<Option<u64>>,
+    message: RefCell<String>,
+}
+impl<W: Write> JsonProgress<W> {
+    fn percent(&self) -> u64 {
+        if self.total == 0 {
+            100
+        } else {
+            self.position.get().min(self.total) * 100 / self.total
+        }
+    }
+}
+impl<W: Write> Progress for JsonProgress<W> {
+    fn set_message(&self, msg: &str) {
+        *self.message.borrow_mut() = msg
+            .trim_end_matches(|c| c == ' ' || c == '-' || c == '>')
+            .to_owned();
+    }
+    fn inc(&self, delta: u64) {
+        self.position.set(self.position.get() + delta);
+        let percent = self.percent();
+        if self.reported.get() == Some(percent) {
+            return;
+        }
+        self.reported.set(Some(percent));
+        let message = self.message.borrow();
+        // the progress is informative, it does not fail the operation
+        let _ = write_event(
+            &self.output,
+            &Event::Progress {
+                message: &message,
+                position: self.position.get(),
+                total: self.total,
+            },
+        );
+    }
+    fn finish(&self) {
+        let message = self.message.borrow();
+        let _ = write_event(
+            &self.output,
+            &Event::Finished {
+                message: &message,
+                position: self.position.get(),
+                total: self.total,
+            },
+        );
+    }
+}
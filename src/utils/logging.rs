@@ -0,0 +1,198 @@
+//! the logs of the command
+//!
+//! the records of the `log` macros are written to the standard error, or
+//! to a file rotated once it reaches `MAX_FILE_SIZE`, as text or as one
+//! JSON object per line. Their target is the module they come from
+//! (`cardano_cli::blockchain::peer` for the network operations,
+//! `cardano_cli::transaction` for the staging transactions...).
+//!
+
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use serde_json;
+
+/// size of the log file from which it is rotated
+pub const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// number of rotated log files kept (`FILE.1` the most recent one)
+pub const ROTATED_FILES: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Config {
+    pub level: LevelFilter,
+    pub format: Format,
+    /// the file to write the logs to, instead of the standard error
+    pub file: Option<PathBuf>,
+}
+
+lazy_static! {
+    static ref ORIGINATOR: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// who runs the operations recorded in the audit logs: the one given
+/// with `set_originator`, or the user running the process
+pub fn originator() -> String {
+    if let Some(ref originator) = *ORIGINATOR.read().unwrap() {
+        return originator.clone();
+    }
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned());
+    format!("{} (pid {})", user, process::id())
+}
+
+/// set the originator of the following operations, for the services
+/// running them on behalf of their clients (through the api)
+pub fn set_originator(originator: String) {
+    *ORIGINATOR.write().unwrap() = Some(originator);
+}
+
+enum Output {
+    Stderr,
+    File {
+        path: PathBuf,
+        file: fs::File,
+        size: u64,
+    },
+}
+impl Output {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Output::File { path, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let rotate = match self {
+            Output::Stderr => return writeln!(io::stderr(), "{}", line),
+            Output::File { size, .. } => *size > 0 && *size + line.len() as u64 + 1 > MAX_FILE_SIZE,
+        };
+        if rotate {
+            self.rotate()?;
+        }
+        if let Output::File { file, size, .. } = self {
+            writeln!(file, "{}", line)?;
+            *size += line.len() as u64 + 1;
+        }
+        Ok(())
+    }
+
+    /// `FILE.4` becomes `FILE.5`... `FILE` becomes `FILE.1` and a new
+    /// `FILE` is started, the oldest file is dropped
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = match self {
+            Output::Stderr => return Ok(()),
+            Output::File { path, .. } => path.clone(),
+        };
+        let rotated = |index: usize| {
+            let mut name = path.clone().into_os_string();
+            name.push(format!(".{}", index));
+            PathBuf::from(name)
+        };
+        for index in (1..ROTATED_FILES).rev() {
+            let from = rotated(index);
+            if from.exists() {
+                fs::rename(&from, rotated(index + 1))?;
+            }
+        }
+        fs::rename(&path, rotated(1))?;
+        *self = Output::open(path)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+struct Logger {
+    level: LevelFilter,
+    format: Format,
+    output: Mutex<Output>,
+}
+impl Logger {
+    fn format(&self, record: &Record) -> String {
+        let time = format!("{}", ::humantime::format_rfc3339_seconds(SystemTime::now()));
+        match self.format {
+            Format::Text => format!(
+                "{} {:5} {}: {}",
+                time,
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            Format::Json => {
+                let level = record.level().to_string().to_lowercase();
+                let json = JsonRecord {
+                    time: time,
+                    level: &level,
+                    target: record.target(),
+                    message: record.args().to_string(),
+                };
+                serde_json::to_string(&json).unwrap_or_else(|_| record.args().to_string())
+            }
+        }
+    }
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        let mut output = self.output.lock().unwrap();
+        if let Err(err) = output.write_line(&line) {
+            // the logs cannot be logged, the standard error is the last resort
+            eprintln!("cannot write the log record: {}", err);
+            if record.level() <= Level::Warn {
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Output::File { ref mut file, .. } = *self.output.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// install the logger of the process, for all the threads
+pub fn init(config: Config) -> io::Result<()> {
+    let output = match config.file {
+        None => Output::Stderr,
+        Some(path) => Output::open(path)?,
+    };
+    let logger = Logger {
+        level: config.level,
+        format: config.format,
+        output: Mutex::new(output),
+    };
+    log::set_boxed_logger(Box::new(logger))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    log::set_max_level(config.level);
+    Ok(())
+}
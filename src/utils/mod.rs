@@ -1,7 +1,19 @@
+#[cfg(feature = "cli")]
 #[macro_use]
 pub mod term;
 // pub mod action;
+pub mod clock;
+pub mod entropy;
+pub mod era;
+pub mod interaction;
+pub mod logging;
 pub mod password_encrypted;
+pub mod payment_uri;
+#[cfg(feature = "cli")]
 pub mod pretty;
+#[cfg(feature = "cli")]
 pub mod prompt;
+pub mod qr;
+#[cfg(test)]
+pub mod testing;
 pub mod time;
@@ -2,7 +2,7 @@
 //!
 //! These functions provide useful ready to use
 
-use rand;
+use super::entropy::{self, Purpose};
 use std::{
     io::{Read, Write},
     iter::repeat,
@@ -49,6 +49,9 @@ pub fn encrypt(password: &Password, data: &[u8]) -> Vec<u8> {
 }
 
 pub fn decrypt(password: &Password, data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < SALT_SIZE + NONCE_SIZE + TAG_SIZE {
+        return None;
+    }
     let mut reader = data;
     let mut salt = [0; SALT_SIZE];
     let mut nonce = [0; NONCE_SIZE];
@@ -75,11 +78,15 @@ fn password_to_key(password: &Password, salt: Salt, key: &mut Key) {
 }
 
 fn generate_salt() -> Salt {
-    rand::random()
+    let mut salt = [0; SALT_SIZE];
+    entropy::default_source().fill_bytes(Purpose::Salt, &mut salt);
+    salt
 }
 
 fn generate_nonce() -> Nonce {
-    rand::random()
+    let mut nonce = [0; NONCE_SIZE];
+    entropy::default_source().fill_bytes(Purpose::Nonce, &mut nonce);
+    nonce
 }
 
 #[cfg(test)]
@@ -0,0 +1,344 @@
+//! payment URIs
+//!
+//! a payment request in a single string, the address to pay followed by the
+//! amount (in ADA, down to the lovelace), the label of the payee and a
+//! message, all optional:
+//!
+//! ```text
+//! web+cardano:DdzFF...?amount=12.5&label=Coffee%20shop&message=Order%2042
+//! ```
+//!
+//! The `cardano:` scheme is accepted as well.
+
+use cardano::{address::ExtendedAddr, coin::Coin};
+
+use utils::era;
+
+use std::{error, fmt, str::FromStr};
+
+/// the scheme of the payment URIs
+pub const SCHEME: &'static str = "web+cardano:";
+
+const SCHEMES: &'static [&'static str] = &[SCHEME, "cardano:"];
+
+const LOVELACE_PER_ADA: u64 = 1_000_000;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidScheme,
+    InvalidAddress(String),
+    InvalidAmount(String),
+    InvalidEncoding(String),
+    UnknownParameter(String),
+    UnsupportedAddress(era::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidScheme => write!(f, "Not a payment URI, expected `{}'", SCHEME),
+            Error::InvalidAddress(address) => {
+                write!(f, "Invalid address `{}' in the payment URI", address)
+            }
+            Error::InvalidAmount(amount) => write!(
+                f,
+                "Invalid amount `{}' in the payment URI, expected ADA with at most 6 decimals",
+                amount
+            ),
+            Error::InvalidEncoding(value) => {
+                write!(f, "Invalid percent-encoding `{}' in the payment URI", value)
+            }
+            Error::UnknownParameter(parameter) => {
+                write!(f, "Unknown parameter `{}' in the payment URI", parameter)
+            }
+            Error::UnsupportedAddress(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl error::Error for Error {}
+
+/// is the argument a payment URI rather than an address
+pub fn is_payment_uri(argument: &str) -> bool {
+    SCHEMES.iter().any(|scheme| argument.starts_with(*scheme))
+}
+
+#[derive(Debug, Clone)]
+pub struct PaymentUri {
+    pub address: ExtendedAddr,
+    pub amount: Option<Coin>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+}
+
+fn format_amount(amount: Coin) -> String {
+    let lovelace = u64::from(amount);
+    let fraction = format!("{:06}", lovelace % LOVELACE_PER_ADA);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}", lovelace / LOVELACE_PER_ADA)
+    } else {
+        format!("{}.{}", lovelace / LOVELACE_PER_ADA, fraction)
+    }
+}
+
+fn parse_amount(amount: &str) -> Result<Coin, Error> {
+    let invalid = || Error::InvalidAmount(amount.to_owned());
+    let (integral, fraction) = match amount.find('.') {
+        None => (amount, ""),
+        Some(dot) => (&amount[..dot], &amount[dot + 1..]),
+    };
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if integral.is_empty() || !is_digits(integral) || !is_digits(fraction) || fraction.len() > 6 {
+        return Err(invalid());
+    }
+    let integral: u64 = integral.parse().map_err(|_| invalid())?;
+    let fraction: u64 = format!("{:0<6}", fraction).parse().map_err(|_| invalid())?;
+    let lovelace = integral
+        .checked_mul(LOVELACE_PER_ADA)
+        .and_then(|lovelace| lovelace.checked_add(fraction))
+        .ok_or_else(invalid)?;
+    Coin::new(lovelace).map_err(|_| invalid())
+}
+
+/// percent-encode everything but the unreserved characters
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// percent-decode the value, `+` standing for a space
+fn decode(value: &str) -> Result<String, Error> {
+    let invalid = || Error::InvalidEncoding(value.to_owned());
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = value.get(index + 1..index + 3).ok_or_else(invalid)?;
+                // `from_str_radix` accepts a sign
+                if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                    return Err(invalid());
+                }
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                index += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+impl fmt::Display for PaymentUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", SCHEME, self.address)?;
+        let mut parameters = Vec::new();
+        if let Some(amount) = self.amount {
+            parameters.push(format!("amount={}", format_amount(amount)));
+        }
+        if let Some(ref label) = self.label {
+            parameters.push(format!("label={}", encode(label)));
+        }
+        if let Some(ref message) = self.message {
+            parameters.push(format!("message={}", encode(message)));
+        }
+        if !parameters.is_empty() {
+            write!(f, "?{}", parameters.join("&"))?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for PaymentUri {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let rest = SCHEMES
+            .iter()
+            .find(|scheme| s.starts_with(**scheme))
+            .map(|scheme| &s[scheme.len()..])
+            .ok_or(Error::InvalidScheme)?;
+        // some encoders write `scheme://address`
+        let rest = rest.trim_start_matches("//");
+        let (address, query) = match rest.find('?') {
+            None => (rest, ""),
+            Some(mark) => (&rest[..mark], &rest[mark + 1..]),
+        };
+
+        let mut uri = PaymentUri {
+            address: era::parse_address(address).map_err(|err| match err {
+                era::Error::InvalidAddress(_) => Error::InvalidAddress(address.to_owned()),
+                err => Error::UnsupportedAddress(err),
+            })?,
+            amount: None,
+            label: None,
+            message: None,
+        };
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (key, value) = match parameter.find('=') {
+                None => (parameter, ""),
+                Some(equal) => (&parameter[..equal], &parameter[equal + 1..]),
+            };
+            match key {
+                "amount" => uri.amount = Some(parse_amount(value)?),
+                "label" => uri.label = Some(decode(value)?),
+                "message" => uri.message = Some(decode(value)?),
+                // the parameters prefixed with `req-` must be understood
+                key if key.starts_with("req-") => {
+                    return Err(Error::UnknownParameter(key.to_owned()))
+                }
+                key => debug!("ignoring the parameter `{}' of the payment URI", key),
+            }
+        }
+        Ok(uri)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utils::testing::address;
+
+    fn lovelace(amount: &str) -> u64 {
+        u64::from(parse_amount(amount).unwrap())
+    }
+
+    #[test]
+    fn amounts() {
+        assert_eq!(lovelace("1"), 1_000_000);
+        assert_eq!(lovelace("12.5"), 12_500_000);
+        assert_eq!(lovelace("0.000001"), 1);
+        assert_eq!(lovelace("007.100"), 7_100_000);
+        assert_eq!(lovelace("45000000000"), 45_000_000_000_000_000);
+    }
+
+    #[test]
+    fn invalid_amounts() {
+        for amount in &[
+            "",
+            ".5",
+            "1.1234567",
+            "1,5",
+            "-1",
+            "+1",
+            "1e6",
+            " 1",
+            "1 ",
+            "0x10",
+            "1.-5",
+        ] {
+            assert!(parse_amount(amount).is_err(), "`{}' accepted", amount);
+        }
+    }
+
+    #[test]
+    fn overflowing_amounts() {
+        // above the maximum value of a coin
+        assert!(parse_amount("45000000000.000001").is_err());
+        // the lovelace overflow a u64
+        assert!(parse_amount("18446744073710").is_err());
+        // the ADA overflow a u64
+        assert!(parse_amount("18446744073709551616").is_err());
+    }
+
+    #[test]
+    fn amounts_are_formatted_without_trailing_zeros() {
+        for amount in &["1", "12.5", "0.000001", "45000000000", "0"] {
+            assert_eq!(format_amount(parse_amount(amount).unwrap()), *amount);
+        }
+    }
+
+    #[test]
+    fn unreserved_characters_are_not_encoded() {
+        assert_eq!(encode("Az09-_.~"), "Az09-_.~");
+        assert_eq!(encode("Coffee shop"), "Coffee%20shop");
+        assert_eq!(encode("a&b=c?d%"), "a%26b%3Dc%3Fd%25");
+        assert_eq!(encode("caf\u{e9}"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(decode("Coffee%20shop").unwrap(), "Coffee shop");
+        assert_eq!(decode("Coffee+shop").unwrap(), "Coffee shop");
+        assert_eq!(decode("caf%c3%a9").unwrap(), "caf\u{e9}");
+        assert_eq!(decode("%2B%25").unwrap(), "+%");
+        assert_eq!(decode("").unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_percent_encodings() {
+        for value in &["%", "a%2", "%zz", "%+1", "%-1", "%FF", "%C3", "%\u{e9}0"] {
+            assert!(decode(value).is_err(), "`{}' accepted", value);
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let uri = PaymentUri {
+            address: address(0x42),
+            amount: Some(Coin::new(12_500_000).unwrap()),
+            label: Some("Coffee & co".to_owned()),
+            message: Some("Order #42, 100% paid?".to_owned()),
+        };
+        let text = uri.to_string();
+        assert!(text.starts_with(SCHEME));
+        let parsed = text.parse::<PaymentUri>().unwrap();
+        assert_eq!(parsed.address, uri.address);
+        assert_eq!(parsed.amount, uri.amount);
+        assert_eq!(parsed.label, uri.label);
+        assert_eq!(parsed.message, uri.message);
+    }
+
+    #[test]
+    fn address_alone() {
+        let text = format!("cardano://{}", address(0x42));
+        assert!(is_payment_uri(&text));
+        let uri = text.parse::<PaymentUri>().unwrap();
+        assert_eq!(uri.address, address(0x42));
+        assert!(uri.amount.is_none() && uri.label.is_none() && uri.message.is_none());
+        assert_eq!(uri.to_string(), format!("{}{}", SCHEME, address(0x42)));
+    }
+
+    #[test]
+    fn parameters() {
+        let unknown = format!("{}{}?amount=1&foo=bar", SCHEME, address(0x42));
+        assert_eq!(
+            unknown.parse::<PaymentUri>().unwrap().amount,
+            Some(Coin::new(1_000_000).unwrap())
+        );
+        let required = format!("{}{}?req-foo=bar", SCHEME, address(0x42));
+        match required.parse::<PaymentUri>() {
+            Err(Error::UnknownParameter(parameter)) => assert_eq!(parameter, "req-foo"),
+            result => panic!("required parameter ignored: {:?}", result),
+        }
+        let invalid = format!("{}{}?amount=1.5.0", SCHEME, address(0x42));
+        assert!(invalid.parse::<PaymentUri>().is_err());
+    }
+
+    #[test]
+    fn invalid_uris() {
+        let address = address(0x42).to_string();
+        assert!(!is_payment_uri(&address));
+        match address.parse::<PaymentUri>() {
+            Err(Error::InvalidScheme) => {}
+            result => panic!("address accepted as a payment URI: {:?}", result),
+        }
+        match format!("{}not-an-address", SCHEME).parse::<PaymentUri>() {
+            Err(Error::InvalidAddress(address)) => assert_eq!(address, "not-an-address"),
+            result => panic!("invalid address accepted: {:?}", result),
+        }
+    }
+}
@@ -1,24 +1,30 @@
 use super::super::term::Term;
 use cardano::bip::bip39::{self, dictionary::Language};
+use cardano::paperwallet;
 use console::style;
 use dialoguer::{Confirmation, Input};
+use std::io;
 
-fn interactive_input_word<D>(term: &mut Term, dic: &D, idx: usize, count: usize) -> String
+fn interactive_input_word<D>(
+    term: &mut Term,
+    dic: &D,
+    idx: usize,
+    count: usize,
+) -> io::Result<String>
 where
     D: Language,
 {
+    term.ensure_interactive("mnemonic")?;
+    let prompt = format!(
+        "mnemonic [{}/{}]",
+        style(idx).cyan(),
+        style(count).cyan().bold()
+    );
     loop {
-        let word: String = Input::new()
-            .with_prompt(&format!(
-                "mnemonic [{}/{}]",
-                style(idx).cyan(),
-                style(count).cyan().bold()
-            ))
-            .interact()
-            .unwrap();
+        let word: String = Input::new().with_prompt(&prompt).interact()?;
 
         match dic.lookup_mnemonic(&word) {
-            Ok(_) => return word,
+            Ok(_) => return Ok(word),
             Err(bip39::dictionary::Error::MnemonicWordNotFoundInDictionary(_)) => {
                 let prompt = format!(
                     "`{}' is not a valid mnemonic word in `{}'",
@@ -29,8 +35,7 @@ where
                     .with_text(&prompt)
                     .default(true)
                     .show_default(true)
-                    .interact()
-                    .unwrap()
+                    .interact()?
                 {}
             }
         }
@@ -91,7 +96,11 @@ where
     }
 }
 
-pub fn interactive_input_words<D>(term: &mut Term, dic: &D, size: bip39::Type) -> PromptedMnemonics
+pub fn interactive_input_words<D>(
+    term: &mut Term,
+    dic: &D,
+    size: bip39::Type,
+) -> io::Result<PromptedMnemonics>
 where
     D: Language,
 {
@@ -100,7 +109,7 @@ where
     loop {
         let mut string = String::new();
         for idx in 1..=count {
-            let result = interactive_input_word(term, dic, idx, count);
+            let result = interactive_input_word(term, dic, idx, count)?;
             if idx == 1 {
                 string = result;
             } else {
@@ -111,49 +120,150 @@ where
 
         match validate_mnemonics(dic, size, string) {
             Ok(res) => {
-                return res;
+                return Ok(res);
             }
             Err(prompt) => {
                 while !Confirmation::new()
                     .with_text(&prompt)
                     .default(true)
                     .show_default(true)
-                    .interact()
-                    .unwrap()
+                    .interact()?
                 {}
             }
         }
     }
 }
 
-pub fn input_mnemonic_phrase<D>(term: &mut Term, dic: &D, size: bip39::Type) -> PromptedMnemonics
+pub fn input_mnemonic_phrase<D>(
+    term: &mut Term,
+    dic: &D,
+    size: bip39::Type,
+) -> io::Result<PromptedMnemonics>
 where
     D: Language,
 {
     let count = size.mnemonic_count();
 
+    term.ensure_interactive("Please enter all your mnemonics")?;
+    let prompt = format!(
+        "Please enter all your {} mnemonics",
+        style(count).bold().red()
+    );
     loop {
-        let string = Input::new()
-            .with_prompt(&format!(
-                "Please enter all your {} mnemonics",
-                style(count).bold().red()
-            ))
-            .interact()
-            .unwrap();
+        let string = Input::new().with_prompt(&prompt).interact()?;
 
         match validate_mnemonics(dic, size, string) {
             Ok(res) => {
-                return res;
+                return Ok(res);
             }
             Err(prompt) => {
                 while !Confirmation::new()
                     .with_text(&prompt)
                     .default(true)
                     .show_default(true)
-                    .interact()
-                    .unwrap()
+                    .interact()?
                 {}
             }
         }
     }
 }
+
+/// prompt the 27 words of a Daedalus paper wallet certificate (the 18
+/// scrambled mnemonic words then the 9 words of the certificate's
+/// passphrase) and unscramble them.
+///
+/// Returns the 12 mnemonic words of the wallet. The passphrase has no
+/// checksum: a wrong one gives other (valid) mnemonic words.
+pub fn input_paper_wallet<D>(
+    term: &mut Term,
+    dic: &D,
+    interactive: bool,
+) -> io::Result<PromptedMnemonics>
+where
+    D: Language,
+{
+    let input = |term: &mut Term, size| {
+        if interactive {
+            interactive_input_words(term, dic, size)
+        } else {
+            input_mnemonic_phrase(term, dic, size)
+        }
+    };
+
+    term.info("enter the 18 scrambled words of the paper wallet certificate\n")?;
+    let (_, _, scrambled) = input(term, bip39::Type::Type18Words)?;
+    term.info("enter the 9 words of the certificate's passphrase\n")?;
+    let (passphrase, _, _) = input(term, bip39::Type::Type9Words)?;
+
+    unscramble_paper_wallet(dic, &scrambled, &passphrase)
+}
+
+/// the mnemonic words of the wallet a paper wallet certificate holds, from
+/// the entropy of its 18 scrambled words and its passphrase
+fn unscramble_paper_wallet<D>(
+    dic: &D,
+    scrambled: &bip39::Entropy,
+    passphrase: &bip39::MnemonicString,
+) -> io::Result<PromptedMnemonics>
+where
+    D: Language,
+{
+    let invalid = |err: bip39::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid paper wallet certificate: {}", err),
+        )
+    };
+
+    // the scrambled entropy is prefixed with the initialisation vector
+    let unscrambled = paperwallet::unscramble(passphrase.as_bytes(), &scrambled[..]);
+    let entropy = bip39::Entropy::from_slice(&unscrambled).map_err(invalid)?;
+    let string = entropy.to_mnemonics().to_string(dic);
+    process_mnemonics(dic, string).map_err(invalid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::bip::bip39::dictionary::ENGLISH;
+
+    fn mnemonics(words: &str) -> PromptedMnemonics {
+        process_mnemonics(&ENGLISH, words.to_owned()).unwrap()
+    }
+
+    // the certificate scrambles the entropy of the 12 words with the key
+    // PBKDF2-HMAC-SHA512 derives from the passphrase, salted with the
+    // initialisation vector (3e2cd8f3b4a56c21), in 10000 iterations
+    #[test]
+    fn paper_wallet_is_unscrambled() {
+        let (_, _, scrambled) = mnemonics(
+            "dilemma grocery diagram harsh fine awesome exclude toward frog \
+             clock drive clump palace drift bounce rice hint tennis",
+        );
+        let (passphrase, _, _) =
+            mnemonics("scheme spot photo card baby mountain device kick cousin");
+
+        let (string, _, entropy) =
+            unscramble_paper_wallet(&ENGLISH, &scrambled, &passphrase).unwrap();
+        assert_eq!(
+            &*string,
+            "legal winner thank year wave sausage worth useful legal winner thank yellow"
+        );
+        assert_eq!(&entropy[..], &[0x7f; 16][..]);
+    }
+
+    #[test]
+    fn paper_wallet_of_another_size_is_refused() {
+        // 12 scrambled words, the 8 bytes left once the initialisation
+        // vector removed are no entropy of mnemonic words
+        let (_, _, scrambled) = mnemonics(
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        );
+        let (passphrase, _, _) =
+            mnemonics("scheme spot photo card baby mountain device kick cousin");
+        match unscramble_paper_wallet(&ENGLISH, &scrambled, &passphrase) {
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
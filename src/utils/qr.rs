@@ -0,0 +1,450 @@
+//! QR codes
+//!
+//! renders data as QR codes in the terminal (two modules per character,
+//! with the half blocks) or as PNG files, to move addresses and staging
+//! transactions to and from air-gapped signers and mobile wallets.
+//!
+//! Data too large for a single QR code is split in chunks, each one a line
+//! of text `PREFIX:INDEX/TOTAL:BASE64`, the base64 encoding of the data
+//! being cut in pieces. Once scanned, the chunks (in any order, one per
+//! line) give the data back with `join_chunks`.
+
+use base64;
+use qrcode::{types::QrError, Color, QrCode};
+
+use std::{
+    error, fmt,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// the size of the base64 piece of each chunk, small enough for the QR code
+/// to be scanned from a terminal
+const CHUNK_SIZE: usize = 1000;
+
+/// the modules of light color around the QR code
+const QUIET_ZONE: usize = 4;
+
+/// the size of a module in the PNG files, in pixels
+const PNG_MODULE_SIZE: usize = 8;
+
+/// the most chunks `join_chunks` accepts: the total of a chunk comes from
+/// the scanned text, it must not decide how much memory is allocated
+const MAX_CHUNKS: usize = 256;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(io::Error),
+    EncodingFailed(QrError),
+    InvalidChunk(String),
+    MissingChunks(usize, usize), // (found, total)
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl From<QrError> for Error {
+    fn from(e: QrError) -> Self {
+        Error::EncodingFailed(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::EncodingFailed(_) => write!(f, "Cannot encode the data in a QR code"),
+            Error::InvalidChunk(chunk) => write!(f, "Invalid QR code chunk `{}'", chunk),
+            Error::MissingChunks(found, total) => write!(
+                f,
+                "Only {} of the {} QR code chunks were given",
+                found, total
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::IoError(ref err) => Some(err),
+            Error::EncodingFailed(ref err) => Some(err),
+            Error::InvalidChunk(_) => None,
+            Error::MissingChunks(_, _) => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// where to render the QR codes
+#[derive(Debug, Clone)]
+pub enum Output {
+    Terminal,
+    /// the PNG file, numbered (`FILE-1.png`, `FILE-2.png`...) when there
+    /// are several QR codes
+    Png(PathBuf),
+}
+
+fn is_dark(code: &QrCode, x: usize, y: usize) -> bool {
+    // the coordinates include the quiet zone
+    let width = code.width();
+    if x < QUIET_ZONE || y < QUIET_ZONE || x >= width + QUIET_ZONE || y >= width + QUIET_ZONE {
+        return false;
+    }
+    code[(x - QUIET_ZONE, y - QUIET_ZONE)] == Color::Dark
+}
+
+/// the QR code as text, the dark modules in the color of the text
+pub fn render_terminal(data: &[u8]) -> Result<String> {
+    let code = QrCode::new(data)?;
+    let size = code.width() + 2 * QUIET_ZONE;
+    let mut text = String::new();
+    for y in (0..size).step_by(2) {
+        for x in 0..size {
+            let top = is_dark(&code, x, y);
+            let bottom = y + 1 < size && is_dark(&code, x, y + 1);
+            text.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// write the QR code to the PNG file: 8 bits grayscale, not compressed
+pub fn write_png<P: AsRef<Path>>(data: &[u8], path: P) -> Result<()> {
+    let code = QrCode::new(data)?;
+    let size = (code.width() + 2 * QUIET_ZONE) * PNG_MODULE_SIZE;
+
+    let mut pixels = Vec::with_capacity((size + 1) * size);
+    for y in 0..size {
+        // no filter
+        pixels.push(0);
+        for x in 0..size {
+            let dark = is_dark(&code, x / PNG_MODULE_SIZE, y / PNG_MODULE_SIZE);
+            pixels.push(if dark { 0x00 } else { 0xff });
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&be_u32(size as u32));
+    header.extend_from_slice(&be_u32(size as u32));
+    // bit depth, grayscale, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut file = File::create(path)?;
+    file.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_png_chunk(&mut file, b"IHDR", &header)?;
+    write_png_chunk(&mut file, b"IDAT", &zlib_stored(&pixels))?;
+    write_png_chunk(&mut file, b"IEND", &[])?;
+    Ok(())
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&be_u32(data.len() as u32))?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_all(&be_u32(crc))
+}
+
+fn be_u32(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// the zlib stream of the data in stored (not compressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        stream.push(if last { 1 } else { 0 });
+        let len = block.len() as u16;
+        stream.extend_from_slice(&[len as u8, (len >> 8) as u8]);
+        stream.extend_from_slice(&[!len as u8, (!len >> 8) as u8]);
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&be_u32(adler32(data)));
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// the chunks of the data, each one small enough for a QR code
+pub fn split_chunks(prefix: &str, data: &[u8]) -> Vec<String> {
+    let encoded = base64::encode(data);
+    let pieces: Vec<&[u8]> = encoded.as_bytes().chunks(CHUNK_SIZE).collect();
+    let total = pieces.len();
+    pieces
+        .iter()
+        .enumerate()
+        .map(|(index, piece)| {
+            format!(
+                "{}:{}/{}:{}",
+                prefix,
+                index + 1,
+                total,
+                String::from_utf8_lossy(piece)
+            )
+        })
+        .collect()
+}
+
+/// are these the chunks made with the given prefix
+pub fn is_chunks(prefix: &str, text: &str) -> bool {
+    text.trim_start().starts_with(prefix)
+}
+
+/// the data of the chunks, one per line in any order. There are at most
+/// `MAX_CHUNKS` chunks.
+pub fn join_chunks(prefix: &str, text: &str) -> Result<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut total = None;
+    for line in text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+    {
+        let invalid = || Error::InvalidChunk(line.to_owned());
+        if !line.starts_with(prefix) {
+            return Err(invalid());
+        }
+        let mut fields = line[prefix.len()..].trim_start_matches(':').splitn(2, ':');
+        let position = fields.next().ok_or_else(invalid)?;
+        let piece = fields.next().ok_or_else(invalid)?;
+        let mut position = position.splitn(2, '/');
+        let index: usize = position
+            .next()
+            .and_then(|i| i.parse().ok())
+            .ok_or_else(invalid)?;
+        let line_total: usize = position
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(invalid)?;
+        if index == 0
+            || index > line_total
+            || line_total > MAX_CHUNKS
+            || total.map(|total| total != line_total).unwrap_or(false)
+        {
+            return Err(invalid());
+        }
+        total = Some(line_total);
+        chunks.push((index, piece));
+    }
+
+    let total = total.unwrap_or(0);
+    let mut pieces: Vec<Option<&str>> = vec![None; total];
+    for (index, piece) in chunks {
+        pieces[index - 1] = Some(piece);
+    }
+    let found = pieces.iter().filter(|piece| piece.is_some()).count();
+    if found == 0 || found != total {
+        return Err(Error::MissingChunks(found, total));
+    }
+    let encoded: String = pieces.into_iter().filter_map(|piece| piece).collect();
+    base64::decode(&encoded).map_err(|_| Error::InvalidChunk(encoded))
+}
+
+/// the path of the PNG file of the QR code `index` (from 1) of `total`
+fn png_path(path: &Path, index: usize, total: usize) -> PathBuf {
+    if total == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "png".to_owned());
+    path.with_file_name(format!("{}-{}.{}", stem, index, extension))
+}
+
+/// render each of the texts as a QR code, returns the rendering for the
+/// terminal (empty when written to PNG files)
+pub fn render_all(texts: &[String], output: &Output) -> Result<String> {
+    let mut rendered = String::new();
+    for (index, text) in texts.iter().enumerate() {
+        match output {
+            Output::Terminal => {
+                if texts.len() > 1 {
+                    rendered.push_str(&format!("{}/{}\n", index + 1, texts.len()));
+                }
+                rendered.push_str(&render_terminal(text.as_bytes())?);
+            }
+            Output::Png(path) => {
+                write_png(text.as_bytes(), png_path(path, index + 1, texts.len()))?
+            }
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, process};
+
+    #[test]
+    fn crc32_known_values() {
+        assert_eq!(crc32(b"".iter()), 0);
+        assert_eq!(crc32(b"123456789".iter()), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+    }
+
+    #[test]
+    fn adler32_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"abc"), 0x024d_0127);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // the sums wrap around the modulo
+        assert_eq!(adler32(&[0xff; 6000]), adler32_naive(&[0xff; 6000]));
+    }
+
+    fn adler32_naive(data: &[u8]) -> u32 {
+        let (mut a, mut b) = (1u64, 0u64);
+        for byte in data {
+            a += u64::from(*byte);
+            b += a;
+        }
+        (((b % 65521) << 16) | (a % 65521)) as u32
+    }
+
+    #[test]
+    fn zlib_stored_blocks() {
+        assert_eq!(
+            zlib_stored(b""),
+            vec![0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            zlib_stored(b"abc"),
+            vec![0x78, 0x01, 1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27]
+        );
+        // the header is a multiple of 31
+        assert_eq!((0x78 * 256 + 0x01) % 31, 0);
+
+        // the blocks are at most 65535 bytes, only the last one is final
+        let data = vec![7; 0xffff + 10];
+        let stream = zlib_stored(&data);
+        assert_eq!(&stream[2..7], &[0, 0xff, 0xff, 0, 0]);
+        let second = 7 + 0xffff;
+        assert_eq!(&stream[second..second + 5], &[1, 10, 0, 0xf5, 0xff]);
+        assert_eq!(stream.len(), 2 + 5 + 0xffff + 5 + 10 + 4);
+        assert_eq!(&stream[stream.len() - 4..], &be_u32(adler32(&data)));
+    }
+
+    #[test]
+    fn png_file() {
+        let path = ::std::env::temp_dir().join(format!("cardano-cli-qr-{}.png", process::id()));
+        write_png(b"web+cardano:test", &path).unwrap();
+        let png = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // the chunks, their CRC checked
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < png.len() {
+            let len = u32::from(png[offset]) << 24
+                | u32::from(png[offset + 1]) << 16
+                | u32::from(png[offset + 2]) << 8
+                | u32::from(png[offset + 3]);
+            let kind_and_data = &png[offset + 4..offset + 8 + len as usize];
+            let crc = &png[offset + 8 + len as usize..offset + 12 + len as usize];
+            assert_eq!(crc, &be_u32(crc32(kind_and_data.iter())));
+            chunks.push((kind_and_data[..4].to_vec(), kind_and_data[4..].to_vec()));
+            offset += 12 + len as usize;
+        }
+        assert_eq!(offset, png.len());
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+        assert_eq!(kinds, vec![&b"IHDR"[..], &b"IDAT"[..], &b"IEND"[..]]);
+
+        // a square of whole modules, 8 bits grayscale
+        let header = &chunks[0].1;
+        assert_eq!(&header[..4], &header[4..8]);
+        let size = u32::from(header[2]) << 8 | u32::from(header[3]);
+        assert_eq!(size as usize % PNG_MODULE_SIZE, 0);
+        assert_eq!(&header[8..], &[8, 0, 0, 0, 0]);
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn chunks_roundtrip_in_any_order() {
+        let data: Vec<u8> = (0..3000).map(|n| (n % 251) as u8).collect();
+        let mut chunks = split_chunks("test", &data);
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks[0].starts_with("test:1/4:"));
+        chunks.reverse();
+        let text = chunks.join("\n");
+        assert!(is_chunks("test", &text));
+        assert_eq!(join_chunks("test", &text).unwrap(), data);
+    }
+
+    #[test]
+    fn missing_chunks() {
+        let data = vec![0x42; 3000];
+        let chunks = split_chunks("test", &data);
+        let text = chunks[..3].join("\n");
+        match join_chunks("test", &text) {
+            Err(Error::MissingChunks(3, 4)) => {}
+            result => panic!("missing chunk not reported: {:?}", result),
+        }
+        match join_chunks("test", "") {
+            Err(Error::MissingChunks(0, 0)) => {}
+            result => panic!("no chunk not reported: {:?}", result),
+        }
+        // more chunks announced than given
+        match join_chunks("test", "test:1/200:AAAA") {
+            Err(Error::MissingChunks(1, 200)) => {}
+            result => panic!("missing chunks not reported: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_chunks() {
+        for text in &[
+            "test:1/4000000000:AAAA",
+            "test:1/257:AAAA",
+            "test:0/1:AAAA",
+            "test:2/1:AAAA",
+            "test:1/2:AAAA\ntest:2/3:AAAA",
+            "test:1-1:AAAA",
+            "test:1/1",
+            "other:1/1:AAAA",
+        ] {
+            match join_chunks("test", text) {
+                Err(Error::InvalidChunk(_)) => {}
+                result => panic!("`{}' accepted: {:?}", text, result),
+            }
+        }
+    }
+}
@@ -1,9 +1,27 @@
+use std::path::PathBuf;
+
+/// the environment variable holding the passphrase, when no
+/// `--passphrase-file` is given
+pub const PASSPHRASE_ENV: &'static str = "CARDANO_CLI_PASSPHRASE";
+
 pub enum ColorChoice {
     Auto,
     Always,
     Never,
 }
 
+/// how the long running operations (the sync, the rescan...) report their
+/// progress
+#[derive(PartialEq)]
+pub enum ProgressFormat {
+    /// progress bars and colored messages
+    Terminal,
+    /// one JSON event per line on the standard error
+    Json,
+    /// the messages without the progress bars
+    Hidden,
+}
+
 /// Configuration for the output options
 pub struct Config {
     /// when to display color or not
@@ -12,12 +30,26 @@ pub struct Config {
     ///
     /// Warning, this does not hide potential logging
     pub quiet: bool,
+    /// where to read the passphrases from instead of prompting them,
+    /// for the commands run without a user at the terminal
+    pub passphrase: Option<PassphraseSource>,
+    /// how to report the progress of the long running operations
+    pub progress: ProgressFormat,
 }
 impl Default for Config {
     fn default() -> Self {
         Config {
             color: ColorChoice::Auto,
             quiet: false,
+            passphrase: None,
+            progress: ProgressFormat::Terminal,
         }
     }
 }
+
+pub enum PassphraseSource {
+    /// the passphrase is the first line of the file
+    File(PathBuf),
+    /// the passphrase is the value of the environment variable
+    Environment(&'static str),
+}
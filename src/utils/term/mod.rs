@@ -6,14 +6,19 @@ pub mod emoji;
 #[macro_use]
 pub mod style;
 
+use atty;
 use console;
 use dialoguer;
 use indicatif;
 
-pub use self::config::{ColorChoice, Config};
+pub use self::config::{ColorChoice, Config, PassphraseSource, ProgressFormat, PASSPHRASE_ENV};
+
+use super::interaction::{Interaction, JsonEvents, Progress};
 
 use std::{
+    env,
     error::Error,
+    fs,
     io::{self, Write},
 };
 
@@ -50,6 +55,9 @@ pub struct Term {
     pub style: Style,
 
     pub term: console::Term,
+
+    /// the JSON events of the long running operations, with `--progress json`
+    pub events: Option<JsonEvents<io::Stderr>>,
 }
 impl Term {
     pub fn new(config: Config) -> Self {
@@ -60,32 +68,146 @@ impl Term {
 
         let term = console::Term::stdout();
         let style = Style::new(&config.color);
+        let events = if config.progress == ProgressFormat::Json {
+            Some(JsonEvents::new(io::stderr()))
+        } else {
+            None
+        };
 
         Term {
             config,
             term,
             style,
+            events,
         }
     }
 
     pub fn progress_bar(&self, count: u64) -> indicatif::ProgressBar {
+        if self.config.progress != ProgressFormat::Terminal {
+            return indicatif::ProgressBar::hidden();
+        }
         let pb = indicatif::ProgressBar::new(count);
         pb.enable_steady_tick(100);
         pb.set_style(
             indicatif::ProgressStyle::default_bar()
                 .template(
-                    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+                    "{spinner:.green} {msg}[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
                 )
                 .progress_chars("#>-"),
         );
         pb
     }
 
+    /// progress indicator for operations whose length is not known ahead
+    pub fn progress_spinner(&self) -> indicatif::ProgressBar {
+        if self.config.progress != ProgressFormat::Terminal {
+            return indicatif::ProgressBar::hidden();
+        }
+        let pb = indicatif::ProgressBar::new_spinner();
+        pb.enable_steady_tick(100);
+        pb.set_style(
+            indicatif::ProgressStyle::default_spinner()
+                .template("{spinner:.green} {msg}[{elapsed_precise}] {pos}"),
+        );
+        pb
+    }
+
     pub fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+        self.ensure_interactive(prompt)?;
         dialoguer::Input::new().with_prompt(prompt).interact()
     }
 
+    /// ask the user to confirm, the answer defaults to no
+    pub fn confirm(&mut self, text: &str) -> io::Result<bool> {
+        self.ensure_interactive(text)?;
+        dialoguer::Confirmation::new()
+            .with_text(text)
+            .default(false)
+            .interact()
+    }
+
+    /// fail, instead of waiting for an answer that will never come, when
+    /// the standard input is not a terminal (cron jobs, pipes...)
+    pub fn ensure_interactive(&self, prompt: &str) -> io::Result<()> {
+        if atty::is(atty::Stream::Stdin) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "Cannot prompt `{}', the standard input is not a terminal",
+                    prompt.trim_end_matches(|c| c == ':' || c == ' ')
+                ),
+            ))
+        }
+    }
+
+    fn ensure_password_prompt(&self, prompt: &str) -> io::Result<()> {
+        self.ensure_interactive(prompt).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "{}, supply the passphrase with `--passphrase-file' or `{}'",
+                    err, PASSPHRASE_ENV
+                ),
+            )
+        })
+    }
+
+    /// the passphrase supplied with `--passphrase-file` or the environment
+    /// variable, if any, warning about the unsafe ways to supply it
+    fn supplied_passphrase(&mut self) -> io::Result<Option<String>> {
+        let (passphrase, warning) = match self.config.passphrase {
+            None => return Ok(None),
+            Some(PassphraseSource::File(ref path)) => {
+                let content = fs::read_to_string(path).map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!(
+                            "Cannot read the passphrase file `{}': {}",
+                            path.display(),
+                            err
+                        ),
+                    )
+                })?;
+                let warning = if readable_by_others(&fs::metadata(path)?) {
+                    Some(format!(
+                        "The passphrase file `{}' can be read by other users, restrict its permissions\n",
+                        path.display()
+                    ))
+                } else {
+                    None
+                };
+                // the passphrase is the first line, without the line ending
+                (content.lines().next().unwrap_or("").to_owned(), warning)
+            }
+            Some(PassphraseSource::Environment(var)) => {
+                let passphrase = env::var(var).map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid passphrase in `{}': {}", var, err),
+                    )
+                })?;
+                let warning = format!(
+                    "The passphrase is read from the environment variable `{}', other processes may be able to read it\n",
+                    var
+                );
+                (passphrase, Some(warning))
+            }
+        };
+        if let Some(warning) = warning {
+            self.warn(&warning)?;
+        }
+        Ok(Some(passphrase))
+    }
+
+    /// prompt the spending (or encryption) password, unless a passphrase
+    /// was supplied with `--passphrase-file` or `CARDANO_CLI_PASSPHRASE`
     pub fn password(&mut self, prompt: &str) -> io::Result<String> {
+        if let Some(passphrase) = self.supplied_passphrase()? {
+            return Ok(passphrase);
+        }
+        self.ensure_password_prompt(prompt)?;
         #[cfg(windows)]
         {
             // TODO: there seems to be an issue with rust crate: console
@@ -108,11 +230,40 @@ impl Term {
         }
     }
 
+    /// prompt a new spending (or encryption) password, twice, unless a
+    /// passphrase was supplied with `--passphrase-file` or
+    /// `CARDANO_CLI_PASSPHRASE`
     pub fn new_password(
         &mut self,
         prompt: &str,
         confirmation: &str,
         mismatch_err: &str,
+    ) -> io::Result<String> {
+        if let Some(passphrase) = self.supplied_passphrase()? {
+            return Ok(passphrase);
+        }
+        self.ensure_password_prompt(prompt)?;
+        self.prompt_new_secret(prompt, confirmation, mismatch_err)
+    }
+
+    /// prompt a new secret twice, never answered by the supplied
+    /// passphrase (the mnemonic passphrase changes the keys of the wallet,
+    /// it cannot be the spending password)
+    pub fn new_secret(
+        &mut self,
+        prompt: &str,
+        confirmation: &str,
+        mismatch_err: &str,
+    ) -> io::Result<String> {
+        self.ensure_interactive(prompt)?;
+        self.prompt_new_secret(prompt, confirmation, mismatch_err)
+    }
+
+    fn prompt_new_secret(
+        &mut self,
+        prompt: &str,
+        confirmation: &str,
+        mismatch_err: &str,
     ) -> io::Result<String> {
         #[cfg(windows)]
         {
@@ -207,3 +358,62 @@ impl io::Read for Term {
         io::Read::read(&mut self.term, buf)
     }
 }
+
+/// the command line: colored messages and progress bars, unless the JSON
+/// events or no progress at all were asked for (`--progress`)
+impl Interaction for Term {
+    fn success(&mut self, msg: &str) -> io::Result<()> {
+        if let Some(ref mut events) = self.events {
+            return events.success(msg);
+        }
+        Term::success(self, msg)
+    }
+    fn info(&mut self, msg: &str) -> io::Result<()> {
+        if let Some(ref mut events) = self.events {
+            return events.info(msg);
+        }
+        Term::info(self, msg)
+    }
+    fn warn(&mut self, msg: &str) -> io::Result<()> {
+        if let Some(ref mut events) = self.events {
+            return events.warn(msg);
+        }
+        Term::warn(self, msg)
+    }
+    fn error(&mut self, msg: &str) -> io::Result<()> {
+        if let Some(ref mut events) = self.events {
+            return events.error(msg);
+        }
+        Term::error(self, msg)
+    }
+    fn progress(&mut self, count: u64) -> Box<Progress> {
+        if let Some(ref mut events) = self.events {
+            return events.progress(count);
+        }
+        Box::new(Term::progress_bar(self, count))
+    }
+    fn password(&mut self, prompt: &str) -> io::Result<String> {
+        Term::password(self, prompt)
+    }
+}
+impl Progress for indicatif::ProgressBar {
+    fn set_message(&self, msg: &str) {
+        indicatif::ProgressBar::set_message(self, msg)
+    }
+    fn inc(&self, delta: u64) {
+        indicatif::ProgressBar::inc(self, delta)
+    }
+    fn finish(&self) {
+        indicatif::ProgressBar::finish(self)
+    }
+}
+
+#[cfg(unix)]
+fn readable_by_others(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o077 != 0
+}
+#[cfg(not(unix))]
+fn readable_by_others(_metadata: &fs::Metadata) -> bool {
+    false
+}
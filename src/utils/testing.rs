@@ -0,0 +1,30 @@
+//! helpers shared by the unit tests
+
+use cardano::{
+    address::ExtendedAddr,
+    coin::Coin,
+    config::ProtocolMagic,
+    hash::Blake2b256,
+    hdwallet::{self, XPrv},
+};
+use transaction::core::Input;
+
+/// the private key made of the seed
+pub fn xprv(seed: u8) -> XPrv {
+    XPrv::normalize_bytes([seed; hdwallet::XPRV_SIZE])
+}
+
+/// a mainnet address of the public key of `xprv(seed)`
+pub fn address(seed: u8) -> ExtendedAddr {
+    ExtendedAddr::new_simple(xprv(seed).public(), ProtocolMagic::from(764824073).into())
+}
+
+/// an input of the given value, spending the first output of the
+/// transaction whose identifier is the hash of the seed
+pub fn input(seed: u8, value: u64) -> Input {
+    Input {
+        transaction_id: Blake2b256::new(&[seed]),
+        index_in_transaction: 0,
+        expected_value: Coin::new(value).unwrap(),
+    }
+}
@@ -0,0 +1,234 @@
+//! Wallet alarms
+//!
+//! alarms are conditions on the wallet's activity configured by the user
+//! (in the wallet's configuration file). They are evaluated every time the
+//! wallet is synchronised with its blockchain.
+//!
+
+use super::error::Result;
+use super::state::{log, lookup};
+use super::utils::lock_wallet_log;
+use super::Wallet;
+
+use blockchain::Blockchain;
+use cardano::{block::BlockDate, coin::Coin};
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Alarm {
+    /// the balance of the wallet dropped below the given value (in lovelace)
+    BalanceBelow(u64),
+
+    /// no funds were received for the given number of days
+    NoIncomingFunds(u64),
+
+    /// the wallet spent funds
+    OutgoingTransaction,
+}
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // the configuration file is edited by hand, the value may not
+            // be a valid coin
+            Alarm::BalanceBelow(v) => match Coin::new(*v) {
+                Ok(coin) => write!(f, "balance below {}", coin),
+                Err(_) => write!(f, "balance below {} lovelace", v),
+            },
+            Alarm::NoIncomingFunds(days) => write!(f, "no incoming funds for {} days", days),
+            Alarm::OutgoingTransaction => write!(f, "any outgoing transaction"),
+        }
+    }
+}
+
+/// an alarm whose condition has been met
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Triggered {
+    pub alarm: Alarm,
+    pub reason: String,
+}
+
+/// evaluate the alarms of the wallet against the wallet log.
+///
+/// `synced_from` is the block date the wallet was synced to before this
+/// synchronisation: only the outgoing transactions found after it trigger
+/// an alarm.
+pub fn evaluate(
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+    synced_from: &BlockDate,
+) -> Result<Vec<Triggered>> {
+    if wallet.config.alarms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut balance = Coin::zero();
+    let mut last_incoming = None;
+    let mut outgoing = Coin::zero();
+
+    let log_lock = lock_wallet_log(wallet)?;
+    let reader = log::LogReader::open(log_lock)?;
+    let reader: log::LogIterator<lookup::Address> = reader.into_iter();
+    for entry in reader {
+        match entry? {
+            log::Log::Checkpoint(_) => {}
+            log::Log::ReceivedFund(ptr, utxo) => {
+                balance = (balance + utxo.credited_value)?;
+                last_incoming = ptr.latest_addr;
+            }
+            log::Log::SpentFund(ptr, utxo) => {
+                balance = (balance - utxo.credited_value)?;
+                if ptr.latest_block_date() > *synced_from {
+                    outgoing = (outgoing + utxo.credited_value)?;
+                }
+            }
+        }
+    }
+
+    let genesis = blockchain.load_genesis_data()?;
+    let last_incoming = last_incoming.map(|date| blockchain.slot_start_time(&genesis, &date));
+    Ok(check(
+        &wallet.config.alarms,
+        balance,
+        last_incoming,
+        outgoing,
+        SystemTime::now(),
+    ))
+}
+
+/// the alarms triggered by the balance of the wallet, the time it last
+/// received funds (if ever) and what it spent since the last sync
+fn check(
+    alarms: &[Alarm],
+    balance: Coin,
+    last_incoming: Option<SystemTime>,
+    outgoing: Coin,
+    now: SystemTime,
+) -> Vec<Triggered> {
+    let mut triggered = Vec::new();
+    for alarm in alarms {
+        let reason = match alarm {
+            Alarm::BalanceBelow(threshold) => {
+                if u64::from(balance) < *threshold {
+                    Some(format!("balance is {}", balance))
+                } else {
+                    None
+                }
+            }
+            Alarm::NoIncomingFunds(days) => {
+                // the number of days is edited by hand, a huge one never
+                // triggers the alarm
+                let limit = Duration::from_secs(days.saturating_mul(24 * 3600));
+                match last_incoming {
+                    None => Some("no funds ever received".to_owned()),
+                    Some(received_at) => match now.duration_since(received_at) {
+                        Ok(elapsed) if elapsed > limit => Some(format!(
+                            "last funds received at {}",
+                            ::humantime::format_rfc3339_seconds(received_at)
+                        )),
+                        _ => None,
+                    },
+                }
+            }
+            Alarm::OutgoingTransaction => {
+                if outgoing > Coin::zero() {
+                    Some(format!("{} spent", outgoing))
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(reason) = reason {
+            triggered.push(Triggered {
+                alarm: alarm.clone(),
+                reason,
+            });
+        }
+    }
+    triggered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: u64 = 24 * 3600;
+
+    fn coin(value: u64) -> Coin {
+        Coin::new(value).unwrap()
+    }
+
+    fn triggered(alarm: Alarm, balance: u64, last_incoming: Option<u64>, outgoing: u64) -> bool {
+        let now = ::std::time::UNIX_EPOCH + Duration::from_secs(1000 * DAY);
+        let last_incoming = last_incoming.map(|days| now - Duration::from_secs(days * DAY));
+        let alarms = [alarm];
+        !check(&alarms, coin(balance), last_incoming, coin(outgoing), now).is_empty()
+    }
+
+    #[test]
+    fn balance_below() {
+        assert!(triggered(
+            Alarm::BalanceBelow(1_000_000),
+            999_999,
+            Some(1),
+            0
+        ));
+        assert!(!triggered(
+            Alarm::BalanceBelow(1_000_000),
+            1_000_000,
+            Some(1),
+            0
+        ));
+        assert!(!triggered(Alarm::BalanceBelow(0), 0, Some(1), 0));
+    }
+
+    #[test]
+    fn no_incoming_funds() {
+        assert!(triggered(Alarm::NoIncomingFunds(30), 0, Some(31), 0));
+        assert!(!triggered(Alarm::NoIncomingFunds(30), 0, Some(30), 0));
+        assert!(!triggered(Alarm::NoIncomingFunds(30), 0, Some(1), 0));
+        assert!(triggered(Alarm::NoIncomingFunds(30), 0, None, 0));
+    }
+
+    #[test]
+    fn no_incoming_funds_for_too_many_days() {
+        assert!(!triggered(
+            Alarm::NoIncomingFunds(u64::max_value()),
+            0,
+            Some(999),
+            0
+        ));
+    }
+
+    #[test]
+    fn outgoing_transaction() {
+        assert!(triggered(Alarm::OutgoingTransaction, 0, Some(1), 1));
+        assert!(!triggered(Alarm::OutgoingTransaction, 0, Some(1), 0));
+    }
+
+    #[test]
+    fn reasons() {
+        let now = SystemTime::now();
+        let alarms = [
+            Alarm::BalanceBelow(1_000_000),
+            Alarm::NoIncomingFunds(1),
+            Alarm::OutgoingTransaction,
+        ];
+        let triggered = check(&alarms, coin(500_000), None, coin(250_000), now);
+        let reasons: Vec<_> = triggered.iter().map(|t| t.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            [
+                format!("balance is {}", coin(500_000)),
+                "no funds ever received".to_owned(),
+                format!("{} spent", coin(250_000)),
+            ]
+        );
+        assert_eq!(triggered[0].alarm, alarms[0]);
+    }
+}
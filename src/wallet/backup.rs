@@ -0,0 +1,313 @@
+//! single file backup of a wallet
+//!
+//! The backup holds all a wallet needs to be restored on another machine:
+//! its configuration, its root private key (still encrypted with the
+//! spending password), its log and the snapshot of its state. The restored
+//! wallet has the same state as the original one, no rescan of the
+//! blockchain is needed.
+//!
+//! The content of the backup is encrypted with a password of its own, the
+//! backup file starts with `BACKUP_MAGIC` and the encrypted content follows.
+//!
+
+use super::config::{self, Config};
+use super::error::{Error, Result};
+use super::snapshot::{self, Snapshot};
+use super::state::log::WALLET_LOG_FILE;
+use super::utils::lock_wallet_log;
+use super::{Wallet, WalletName};
+
+use base64;
+use cardano::hdwallet::XPub;
+use serde_json;
+use storage_units::utils::tmpfile::TmpFile;
+use utils::password_encrypted::{self, Password};
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+const BACKUP_MAGIC: &'static [u8] = b"cardano-cli wallet backup 1\n";
+
+#[derive(Serialize, Deserialize)]
+struct Content {
+    name: String,
+    config: Config,
+    /// the root private key, encrypted with the spending password
+    encrypted_key: String,
+    public_key: Option<String>,
+    /// the wallet log, if the wallet was ever synced
+    log: Option<String>,
+    /// the state snapshot, kept as is: a snapshot of another version is
+    /// not restored (the state is rebuilt from the log), it does not make
+    /// the backup invalid
+    snapshot: Option<serde_json::Value>,
+}
+
+/// write the encrypted backup of the wallet to the given file
+pub fn write<P: AsRef<Path>>(wallet: &Wallet, password: &Password, output: P) -> Result<()> {
+    let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+
+    // the log and the snapshot are read while holding the wallet log's
+    // lock, so they are consistent with each other
+    let (log, snapshot) = {
+        let _lock = lock_wallet_log(wallet)?;
+        let log = match fs::read(dir.join(WALLET_LOG_FILE)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+            Ok(bytes) => Some(base64::encode(&bytes)),
+        };
+        let snapshot = match Snapshot::load(wallet, &snapshot::log_version(wallet)?) {
+            None => None,
+            Some(snapshot) => Some(
+                serde_json::to_value(snapshot)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
+            ),
+        };
+        (log, snapshot)
+    };
+
+    let content = Content {
+        name: wallet.name.as_dirname(),
+        config: wallet.config.clone(),
+        encrypted_key: base64::encode(&wallet.encrypted_key),
+        public_key: wallet
+            .public_key
+            .as_ref()
+            .map(|xpub| base64::encode(xpub.as_ref())),
+        log,
+        snapshot,
+    };
+    let bytes =
+        serde_json::to_vec(&content).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    let encrypted = password_encrypted::encrypt(password, &bytes);
+
+    // the backup is written next to the output, readable by the user only,
+    // and renamed over the output once complete: a failure leaves neither a
+    // partial backup nor a file other users can read
+    let output = output.as_ref();
+    let mut tmp_path = output.as_os_str().to_owned();
+    tmp_path.push(format!(".tmp-{}", process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = write_private(&tmp_path, &[BACKUP_MAGIC, &encrypted])
+        .and_then(|()| fs::rename(&tmp_path, output));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    Ok(result?)
+}
+
+/// write the new file with the permissions of the user only (the default
+/// ones of `TmpFile` let other users read it)
+fn write_private(path: &Path, chunks: &[&[u8]]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    for chunk in chunks {
+        file.write_all(chunk)?;
+    }
+    file.sync_all()
+}
+
+/// restore the wallet from the backup file, under its original name or
+/// the given one. The wallet must not exist already.
+///
+/// The whole backup is decoded before anything is written, and the wallet
+/// directory is removed if writing it fails: an invalid backup leaves no
+/// half-restored wallet.
+pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(
+    root_dir: P,
+    input: Q,
+    password: &Password,
+    name: Option<WalletName>,
+) -> Result<Wallet> {
+    let input = input.as_ref();
+    let invalid = || Error::BackupInvalid(input.to_path_buf());
+
+    let bytes = fs::read(input)?;
+    if !bytes.starts_with(BACKUP_MAGIC) {
+        return Err(invalid());
+    }
+    let bytes = password_encrypted::decrypt(password, &bytes[BACKUP_MAGIC.len()..])
+        .ok_or(Error::BackupInvalidPassword)?;
+    let content: Content = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+
+    let name = match name {
+        Some(name) => name,
+        None => WalletName::new(content.name).ok_or_else(invalid)?,
+    };
+    let dir = config::directory(root_dir.as_ref(), &name.as_dirname());
+    if dir.exists() {
+        return Err(Error::WalletAlreadyExists(name.to_string()));
+    }
+
+    let encrypted_key = base64::decode(&content.encrypted_key).map_err(|_| invalid())?;
+    let public_key = match content.public_key {
+        None => None,
+        Some(xpub) => {
+            let bytes = base64::decode(&xpub).map_err(|_| invalid())?;
+            Some(XPub::from_slice(&bytes).map_err(|_| invalid())?)
+        }
+    };
+    let log = match content.log {
+        None => None,
+        Some(log) => Some(base64::decode(&log).map_err(|_| invalid())?),
+    };
+    // the snapshot was taken from this very log
+    let snapshot = match (&log, content.snapshot) {
+        (Some(_), Some(snapshot)) => match serde_json::from_value::<Snapshot>(snapshot) {
+            Ok(snapshot) => Some(snapshot),
+            Err(err) => {
+                debug!("not restoring the state snapshot: {}", err);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let wallet = Wallet::new(root_dir, name, content.config, encrypted_key, public_key);
+    if let Err(err) = write_restored(&wallet, log, snapshot) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(err);
+    }
+    Ok(wallet)
+}
+
+/// write the wallet restored from a backup, with its log and its snapshot
+fn write_restored(wallet: &Wallet, log: Option<Vec<u8>>, snapshot: Option<Snapshot>) -> Result<()> {
+    wallet.save()?;
+
+    if let Some(log) = log {
+        let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+        let _lock = lock_wallet_log(wallet)?;
+        let mut tmpfile = TmpFile::create(dir.clone())?;
+        tmpfile.write_all(&log)?;
+        tmpfile.render_permanent(&dir.join(WALLET_LOG_FILE))?;
+        if let Some(snapshot) = snapshot {
+            snapshot.save_for_current_log(wallet)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PASSWORD: &'static [u8] = b"backup password";
+
+    fn temp_root_dir(name: &str) -> PathBuf {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-backup-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root_dir);
+        root_dir
+    }
+
+    fn wallet_name(name: &str) -> WalletName {
+        WalletName::new(name.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn private_file_is_written_once() {
+        let path = ::std::env::temp_dir().join(format!("cardano-cli-backup-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        write_private(&path, &[b"cardano", b"-cli"]).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"cardano-cli");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // an existing file is not written over
+        assert!(write_private(&path, &[b"other"]).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"cardano-cli");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn backup_is_restored() {
+        let root_dir = temp_root_dir("roundtrip");
+        let mut wallet_config = Config::default();
+        wallet_config.gap_limit = Some(30);
+        let wallet = Wallet::new(
+            root_dir.clone(),
+            wallet_name("wallet"),
+            wallet_config,
+            vec![1, 2, 3, 4],
+            None,
+        );
+        wallet.save().unwrap();
+        let dir = config::directory(&root_dir, &wallet.name.as_dirname());
+        fs::write(dir.join(WALLET_LOG_FILE), b"the wallet log").unwrap();
+
+        let path = root_dir.join("wallet.backup");
+        write(&wallet, PASSWORD, &path).unwrap();
+
+        // under another name, next to the original one
+        let restored = restore(&root_dir, &path, PASSWORD, Some(wallet_name("restored"))).unwrap();
+        assert_eq!(restored.name.as_dirname(), "restored");
+        assert_eq!(restored.encrypted_key, wallet.encrypted_key);
+        assert_eq!(restored.config.gap_limit, Some(30));
+        let restored_dir = config::directory(&root_dir, &restored.name.as_dirname());
+        assert_eq!(
+            fs::read(restored_dir.join(WALLET_LOG_FILE)).unwrap(),
+            b"the wallet log"
+        );
+        let loaded = Wallet::load(&root_dir, wallet_name("restored")).unwrap();
+        assert_eq!(loaded.encrypted_key, wallet.encrypted_key);
+
+        // not over an existing wallet, nor with another password
+        match restore(&root_dir, &path, PASSWORD, None) {
+            Err(Error::WalletAlreadyExists(_)) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        match restore(
+            &root_dir,
+            &path,
+            b"other password",
+            Some(wallet_name("other")),
+        ) {
+            Err(Error::BackupInvalidPassword) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        assert!(!config::directory(&root_dir, "other").exists());
+
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_backup_restores_nothing() {
+        let root_dir = temp_root_dir("invalid");
+        fs::create_dir_all(&root_dir).unwrap();
+        let content = Content {
+            name: "wallet".to_owned(),
+            config: Config::default(),
+            encrypted_key: base64::encode(&[1, 2, 3, 4]),
+            public_key: None,
+            log: Some("not base64!".to_owned()),
+            snapshot: None,
+        };
+        let encrypted =
+            password_encrypted::encrypt(PASSWORD, &serde_json::to_vec(&content).unwrap());
+        let path = root_dir.join("wallet.backup");
+        write_private(&path, &[BACKUP_MAGIC, &encrypted]).unwrap();
+
+        match restore(&root_dir, &path, PASSWORD, None) {
+            Err(Error::BackupInvalid(ref invalid)) if invalid == &path => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+        assert!(!config::directory(&root_dir, "wallet").exists());
+
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
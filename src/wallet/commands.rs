@@ -1,23 +1,48 @@
-use super::config::{encrypt_primary_key, Config, HDWalletModel};
+use super::config::{Config, HDWalletModel, Setting};
 use super::error::{Error, Result};
-use super::state::lookup;
+use super::state::{log, lookup, ptr::StatePtr, state, utxo::UTxO};
 use super::utils::*;
-use super::{Wallet, WalletName, Wallets};
+use super::{
+    alarm, backup, history, lease, message, migrate, portfolio, rescan, snapshot, Wallet,
+    WalletName, Wallets,
+};
+
+use schema;
 
 use cardano::{
+    address::ExtendedAddr,
     bip::bip39,
+    block::BlockDate,
+    coin::Coin,
+    config::ProtocolMagic,
+    fee::LinearFee,
     hdwallet::{self, DerivationScheme},
+    tx::TxoPointer,
     wallet,
 };
-use rand::random;
-use std::{io::Write, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use utils::{
-    prompt,
+    entropy,
+    interaction::Interaction,
+    logging,
+    payment_uri::PaymentUri,
+    prompt, qr,
     term::{style::Style, Term},
 };
 
 use blockchain::{Blockchain, BlockchainName};
+use serde_json;
+use transaction::{
+    check,
+    core::{Input, Operation, StagingTransaction},
+};
 
 pub fn list(term: &mut Term, root_dir: PathBuf, detailed: bool) -> Result<()> {
     let wallets = Wallets::load(root_dir.clone())?;
@@ -68,21 +93,23 @@ where
         attached_blockchain: None,
         derivation_scheme: derivation_scheme,
         hdwallet_model: wallet_scheme,
+        alarms: Vec::new(),
+        gap_limit: None,
     };
 
     // 1. generate the mnemonics
 
-    let entropy = bip39::Entropy::generate(mnemonic_size, random);
+    let entropy = entropy::mnemonic_entropy(&mut *entropy::default_source(), mnemonic_size);
     // 2. perform the seed generation from the entropy
 
-    term.info("You can add a recovery wallet password. You can set no password, however you won't benefit from plausible deniability\n").unwrap();
+    term.info("You can add a mnemonic passphrase (the \"25th word\" of other wallets), it will be needed along with the mnemonic words to recover the wallet. You can set no passphrase, however you won't benefit from plausible deniability\n").unwrap();
     let recovery_password = term
-        .new_password(
-            "recovery password",
-            "confirm password",
-            "password mismatch ",
+        .new_secret(
+            "mnemonic passphrase",
+            "confirm passphrase",
+            "passphrase mismatch ",
         )
-        .unwrap();
+        .unwrap_or_else(|e| term.fail_with(e));
     let mut seed = [0; hdwallet::XPRV_SIZE];
     wallet::keygen::generate_seed(&entropy, recovery_password.as_bytes(), &mut seed);
 
@@ -97,13 +124,7 @@ where
 
     let xprv = hdwallet::XPrv::normalize_bytes(seed);
 
-    // create the root public key
-    let public_key = match wallet_scheme {
-        HDWalletModel::BIP44 => None,
-        HDWalletModel::RandomIndex2Levels => Some(xprv.public()),
-    };
-
-    // 4. encrypt the private key
+    // 4. set the password encrypting the private key
     term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
     let password = term
         .new_password(
@@ -111,11 +132,10 @@ where
             "confirm spending password",
             "password mismatch",
         )
-        .unwrap();
-    let encrypted_xprv = encrypt_primary_key(password.as_bytes(), &xprv);
+        .unwrap_or_else(|e| term.fail_with(e));
 
     // 5. create the wallet
-    let wallet = Wallet::new(root_dir, name, config, encrypted_xprv, public_key);
+    let wallet = Wallet::from_root_key(root_dir, name, config, &xprv, password.as_bytes());
 
     // 6. save the wallet
     wallet.save()?;
@@ -138,6 +158,7 @@ pub fn recover<D>(
     mnemonic_size: bip39::Type,
     interactive: bool,
     daedalus_seed: bool,
+    paper_wallet: bool,
     language: D,
 ) -> Result<()>
 where
@@ -147,19 +168,23 @@ where
         attached_blockchain: None,
         derivation_scheme: derivation_scheme,
         hdwallet_model: wallet_scheme,
+        alarms: Vec::new(),
+        gap_limit: None,
     };
 
     // 1. generate the mnemonics
     term.info("enter your mnemonics\n").unwrap();
 
-    let (string, _, entropy) = if interactive {
-        prompt::mnemonics::interactive_input_words(term, &language, mnemonic_size)
+    let (string, _, entropy) = if paper_wallet {
+        prompt::mnemonics::input_paper_wallet(term, &language, interactive)?
+    } else if interactive {
+        prompt::mnemonics::interactive_input_words(term, &language, mnemonic_size)?
     } else {
-        prompt::mnemonics::input_mnemonic_phrase(term, &language, mnemonic_size)
+        prompt::mnemonics::input_mnemonic_phrase(term, &language, mnemonic_size)?
     };
 
     // 3. perform the seed generation from the entropy
-    let xprv = if daedalus_seed {
+    let xprv = if daedalus_seed || paper_wallet {
         match wallet::rindex::RootKey::from_daedalus_mnemonics(
             derivation_scheme,
             &language,
@@ -171,11 +196,19 @@ where
             }
         }
     } else {
+        // a wrong passphrase recovers another (empty) wallet without any
+        // error, hence the confirmation
         term.info(
-            "Enter the wallet recovery password (if the password is wrong, you won't know).\n",
+            "Enter the mnemonic passphrase (the \"25th word\"), leave it empty if the wallet has none. If the passphrase is wrong, you won't know.\n",
         )
         .unwrap();
-        let recovery_password = term.password("recovery password: ").unwrap();
+        let recovery_password = term
+            .new_secret(
+                "mnemonic passphrase",
+                "confirm passphrase",
+                "passphrase mismatch ",
+            )
+            .unwrap_or_else(|e| term.fail_with(e));
 
         let mut seed = [0; hdwallet::XPRV_SIZE];
         wallet::keygen::generate_seed(&entropy, recovery_password.as_bytes(), &mut seed);
@@ -184,13 +217,7 @@ where
         hdwallet::XPrv::normalize_bytes(seed)
     };
 
-    // create the root public key
-    let public_key = match wallet_scheme {
-        HDWalletModel::BIP44 => None,
-        HDWalletModel::RandomIndex2Levels => Some(xprv.public()),
-    };
-
-    // 4. encrypt the private key
+    // 4. set the password encrypting the private key
     term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
     let password = term
         .new_password(
@@ -198,11 +225,10 @@ where
             "confirm spending password",
             "password mismatch",
         )
-        .unwrap();
-    let encrypted_xprv = encrypt_primary_key(password.as_bytes(), &xprv);
+        .unwrap_or_else(|e| term.fail_with(e));
 
     // 5. create the wallet
-    let wallet = Wallet::new(root_dir, name, config, encrypted_xprv, public_key);
+    let wallet = Wallet::from_root_key(root_dir, name, config, &xprv, password.as_bytes());
 
     // 6. save the wallet
     wallet.save()?;
@@ -216,6 +242,78 @@ where
     Ok(())
 }
 
+/// encrypt the wallet's root private key with a new spending password
+pub fn change_password(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
+    let mut wallet = Wallet::load(&root_dir, name)?;
+
+    term.info("Enter the current wallet password.\n")?;
+    let old_password = term.password("current spending password: ")?;
+
+    // the supplied passphrase is the current one, the new password is
+    // always prompted
+    term.info("Set the new wallet password.\n")?;
+    let new_password = term.new_secret(
+        "new spending password",
+        "confirm new spending password",
+        "password mismatch",
+    )?;
+
+    wallet.change_password(old_password.as_bytes(), new_password.as_bytes())?;
+
+    term.success(&format!(
+        "spending password of wallet `{}' changed.\n",
+        &wallet.name
+    ))?;
+    Ok(())
+}
+
+/// write the encrypted backup of the wallet to a single file
+pub fn backup(term: &mut Term, root_dir: PathBuf, name: WalletName, output: PathBuf) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+
+    term.info(
+        "Set a password to encrypt the backup with. It will be needed to restore the wallet.\n",
+    )?;
+    let password = term.new_password(
+        "backup password",
+        "confirm backup password",
+        "password mismatch",
+    )?;
+
+    backup::write(&wallet, password.as_bytes(), &output)?;
+
+    term.success(&format!(
+        "wallet `{}' backed up to {}\n",
+        &wallet.name,
+        output.display()
+    ))?;
+    Ok(())
+}
+
+/// recreate the wallet from its backup, along with its state
+pub fn restore_backup(
+    term: &mut Term,
+    root_dir: PathBuf,
+    input: PathBuf,
+    name: Option<WalletName>,
+) -> Result<()> {
+    term.info("Enter the backup password.\n")?;
+    let password = term.password("backup password: ")?;
+
+    let wallet = backup::restore(&root_dir, &input, password.as_bytes(), name)?;
+
+    term.success(&format!("wallet `{}' restored.\n", &wallet.name))?;
+    if let Some(ref blk_name) = wallet.config.attached_blockchain {
+        if load_attached_blockchain(&root_dir, &wallet.config).is_err() {
+            term.warn(&format!(
+                "the wallet is attached to the blockchain `{}', which is not available here\n",
+                blk_name
+            ))?;
+        }
+    }
+    Ok(())
+}
+
 /// Destroy the wallet and remove all associated data.
 ///
 /// **Caveat:** the files in storage are only unlinked on the filesystem
@@ -235,17 +333,8 @@ new transactions will be by recovering the wallet with the mnemonic words.",
     )
     .unwrap();
 
-    // FIXME: create a confirmation API that is exposed on the command
-    // methods that need to put the user through a confirmation dialog.
-    // See issue #45
-
-    let confirmation = ::dialoguer::Confirmation::new()
-        .with_text("Are you sure?")
-        .default(false)
-        .interact()
-        .unwrap();
-    if !confirmation {
-        ::std::process::exit(0);
+    if !term.confirm("Are you sure?")? {
+        return Ok(());
     }
 
     wallet
@@ -329,6 +418,12 @@ pub fn status(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()
     term.warn(&format!("{:?}", &wallet.config.derivation_scheme))
         .unwrap();
     term.simply("\n").unwrap();
+    if wallet.config.hdwallet_model == HDWalletModel::BIP44 {
+        term.simply(" * gap limit ").unwrap();
+        term.warn(&format!("{}", wallet.config.gap_limit()))
+            .unwrap();
+        term.simply("\n").unwrap();
+    }
 
     let state = create_wallet_state_from_logs(&wallet, root_dir, lookup::accum::Accum::default())?;
 
@@ -352,6 +447,12 @@ pub fn status(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()
         }
     }
     term.simply("\n").unwrap();
+    if let Some(from_slot) = rescan::interrupted(&wallet)? {
+        term.simply(" * ").unwrap();
+        term.warn(&format!("rescan from slot {} interrupted", from_slot))
+            .unwrap();
+        term.simply("\n").unwrap();
+    }
 
     Ok(())
 }
@@ -363,51 +464,539 @@ pub fn log(term: &mut Term, root_dir: PathBuf, name: WalletName, pretty: bool) -
     let mut state =
         create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default())?;
 
-    display_wallet_state_logs(term, &wallet, &mut state, pretty);
+    display_wallet_state_logs(term, &wallet, &mut state, pretty)?;
 
     Ok(())
 }
 
-pub fn utxos(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
+/// print the unspent outputs of the wallet, only the ones sent to the given
+/// address if any
+pub fn utxos(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    address: Option<ExtendedAddr>,
+) -> Result<()> {
     // load the wallet
     let wallet = Wallet::load(root_dir.clone(), name)?;
 
-    let state = create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default())?;
+    let index = load_utxo_index(&wallet, &root_dir)?;
 
-    display_wallet_state_utxos(term, state);
+    match address {
+        None => display_wallet_utxos(term, index.utxos()),
+        Some(address) => display_wallet_utxos(term, index.by_address(&address)),
+    }
 
     Ok(())
 }
 
-pub fn sync(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
-    // 0. load the wallet
+/// rebuild the state snapshot and the UTxO index of the wallet from its log
+pub fn reindex(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
     let wallet = Wallet::load(root_dir.clone(), name)?;
 
-    // 1. get the wallet's blockchain
-    let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+    let index = reindex_wallet(&wallet, &root_dir)?;
+
+    term.success(&format!(
+        "wallet {} reindexed, {} unspent outputs\n",
+        wallet.name,
+        index.len()
+    ))?;
+    Ok(())
+}
 
-    match wallet.config.hdwallet_model {
+/// sync the wallets (all the attached ones if none is given), the wallets
+/// attached to the same blockchain together
+pub fn sync(term: &mut Term, root_dir: PathBuf, names: Vec<WalletName>) -> Result<()> {
+    // 0. load the wallets, by attached blockchain
+    let wallets = if names.is_empty() {
+        Wallets::load(root_dir.clone())?
+            .into_iter()
+            .map(|(_, wallet)| wallet)
+            .filter(|wallet| wallet.config.attached_blockchain.is_some())
+            .collect()
+    } else {
+        names
+            .into_iter()
+            .map(|name| Wallet::load(root_dir.clone(), name))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let mut by_blockchain: BTreeMap<String, Vec<Wallet>> = BTreeMap::new();
+    for wallet in wallets {
+        match wallet.config.attached_blockchain.clone() {
+            None => return Err(Error::NotAttachedToBlockchain),
+            Some(blockchain) => by_blockchain
+                .entry(blockchain)
+                .or_insert_with(Vec::new)
+                .push(wallet),
+        }
+    }
+
+    for (_, wallets) in by_blockchain {
+        // 1. get the wallets' blockchain
+        let blockchain = load_attached_blockchain(&root_dir, &wallets[0].config)?;
+
+        let synced = if wallets.len() == 1 {
+            vec![sync_with_blockchain(
+                term,
+                &root_dir,
+                &wallets[0],
+                &blockchain,
+            )?]
+        } else {
+            sync_wallets_with_blockchain(term, &root_dir, &wallets, &blockchain)?
+        };
+        for (wallet, (_, alarms)) in wallets.iter().zip(synced.iter()) {
+            for triggered in alarms {
+                term.warn(&format!(
+                    "alarm `{}' on wallet {}: {}\n",
+                    triggered.alarm, wallet.name, triggered.reason
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// sync the wallet with its (already loaded) attached blockchain.
+///
+/// Returns the block date the wallet was synced to before, the log entries
+/// after it are new, and the alarms of the wallet this sync triggered.
+pub fn sync_with_blockchain(
+    term: &mut Interaction,
+    root_dir: &Path,
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+) -> Result<(BlockDate, Vec<alarm::Triggered>)> {
+    // 2. unwind what the wallet learnt from blocks the blockchain rolled back
+    rollback_wallet_if_forked(term, root_dir, wallet, blockchain)?;
+
+    let synced_from = match wallet.config.hdwallet_model {
         HDWalletModel::BIP44 => {
             let mut lookup_struct =
-                load_bip44_lookup_structure(term, blockchain.config.protocol_magic.into(), &wallet);
+                load_bip44_lookup_structure(term, blockchain.config.protocol_magic.into(), wallet)?;
             lookup_struct.prepare_next_account()?;
 
-            let mut state = create_wallet_state_from_logs(&wallet, &root_dir, lookup_struct)?;
+            let mut state = create_wallet_state_from_logs(wallet, root_dir, lookup_struct)?;
+            let synced_from = state.ptr().latest_block_date();
 
-            update_wallet_state_with_utxos(term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(term, wallet, blockchain, &mut state)?;
+            synced_from
         }
         HDWalletModel::RandomIndex2Levels => {
             let lookup_struct = load_randomindex_lookup_structure(
                 term,
                 blockchain.config.protocol_magic.into(),
-                &wallet,
-            );
-            let mut state = create_wallet_state_from_logs(&wallet, &root_dir, lookup_struct)?;
+                wallet,
+            )?;
+            let mut state = create_wallet_state_from_logs(wallet, root_dir, lookup_struct)?;
+            let synced_from = state.ptr().latest_block_date();
 
-            update_wallet_state_with_utxos(term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(term, wallet, blockchain, &mut state)?;
+            synced_from
         }
     };
 
+    // 3. check the alarms of the wallet
+    let alarms = alarm::evaluate(wallet, blockchain, &synced_from)?;
+
+    Ok((synced_from, alarms))
+}
+
+/// sync the wallets attached to the (already loaded) blockchain together:
+/// its blocks are read once, the wallets looking for their addresses in
+/// them concurrently.
+///
+/// Returns the block date each wallet was synced to before and the alarms
+/// of the wallet the sync triggered.
+pub fn sync_wallets_with_blockchain(
+    term: &mut Interaction,
+    root_dir: &Path,
+    wallets: &[Wallet],
+    blockchain: &Blockchain,
+) -> Result<Vec<(BlockDate, Vec<alarm::Triggered>)>> {
+    let protocol_magic = blockchain.config.protocol_magic;
+    let mut update = WalletsUpdate::new();
+    let mut synced_from = Vec::with_capacity(wallets.len());
+    for wallet in wallets {
+        rollback_wallet_if_forked(term, root_dir, wallet, blockchain)?;
+
+        term.info(&format!("wallet {}\n", wallet.name))?;
+        let from = match wallet.config.hdwallet_model {
+            HDWalletModel::BIP44 => {
+                let mut lookup_struct =
+                    load_bip44_lookup_structure(term, protocol_magic.into(), wallet)?;
+                lookup_struct.prepare_next_account()?;
+
+                let state = create_wallet_state_from_logs(wallet, root_dir, lookup_struct)?;
+                let from = state.ptr().latest_block_date();
+                update.add(blockchain, wallet.clone(), state)?;
+                from
+            }
+            HDWalletModel::RandomIndex2Levels => {
+                let lookup_struct =
+                    load_randomindex_lookup_structure(term, protocol_magic.into(), wallet)?;
+                let state = create_wallet_state_from_logs(wallet, root_dir, lookup_struct)?;
+                let from = state.ptr().latest_block_date();
+                update.add(blockchain, wallet.clone(), state)?;
+                from
+            }
+        };
+        synced_from.push(from);
+    }
+
+    update.run(term, blockchain)?;
+
+    wallets
+        .iter()
+        .zip(synced_from.into_iter())
+        .map(|(wallet, synced_from)| {
+            let alarms = alarm::evaluate(wallet, blockchain, &synced_from)?;
+            Ok((synced_from, alarms))
+        })
+        .collect()
+}
+
+/// list the credits and debits of the wallet (matching the filter) with
+/// the running balance, or write them to a CSV file
+pub fn history(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    filter: history::Filter,
+    csv: Option<PathBuf>,
+) -> Result<()> {
+    let wallet = Wallet::load(root_dir.clone(), name)?;
+    let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+
+    let entries = history::history(term, &wallet, &blockchain, &filter)?;
+    let (credits, debits) = history::totals(&entries)?;
+
+    if let Some(path) = csv {
+        history::write_csv(fs::File::create(&path)?, &entries)?;
+        term.success(&format!(
+            "{} entries written to {}\n",
+            entries.len(),
+            path.display()
+        ))?;
+        return Ok(());
+    }
+
+    for entry in entries.iter() {
+        let amount = match entry.kind {
+            history::Kind::Credit => format!("{:>16}", format!("+{}", entry.amount)),
+            history::Kind::Debit => format!("{:>16}", format!("-{}", entry.amount)),
+        };
+        let amount = match entry.kind {
+            history::Kind::Credit => style!(amount.as_str()).green(),
+            history::Kind::Debit => style!(amount.as_str()).red(),
+        };
+        let balance = format!("{:>16}", entry.balance);
+        let txid = match entry.txid {
+            Some(ref txid) => format!("{}", style!(txid)),
+            None => format!("{}", style!("unknown transaction").red()),
+        };
+        let counterparties = if entry.counterparties.is_empty() {
+            String::new()
+        } else {
+            let counterparties: Vec<String> =
+                entry.counterparties.iter().map(|c| c.to_string()).collect();
+            format!(" {}", counterparties.join(" "))
+        };
+        writeln!(
+            term,
+            "{:9}|{}|{}|{}|{}",
+            ::console::pad_str(&entry.date.to_string(), 9, ::console::Alignment::Left, None),
+            txid,
+            amount,
+            style!(balance.as_str()),
+            counterparties
+        )?;
+    }
+    writeln!(
+        term,
+        "credited {}, debited {}, balance {}",
+        style!(credits).green(),
+        style!(debits).red(),
+        style!(entries
+            .last()
+            .map(|entry| entry.balance)
+            .unwrap_or_else(cardano::coin::Coin::zero))
+    )?;
+    Ok(())
+}
+
+/// rebuild the wallet's state from the blocks stored locally, from the
+/// given slot (or the genesis) onwards. An interrupted rescan is resumed,
+/// unless another slot is given.
+pub fn rescan(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    from_slot: Option<u64>,
+) -> Result<()> {
+    let wallet = Wallet::load(root_dir.clone(), name)?;
+    let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+
+    match rescan::interrupted(&wallet)? {
+        Some(interrupted) if from_slot.map(|slot| slot == interrupted).unwrap_or(true) => {
+            term.info(&format!(
+                "resuming the rescan of wallet {} from slot {}\n",
+                wallet.name, interrupted
+            ))?;
+            rescan::truncate_last_block(&wallet, &blockchain)?;
+        }
+        _ => {
+            let from_slot = from_slot.unwrap_or(0);
+            rescan::start(&wallet, from_slot)?;
+            let dropped = rescan::truncate_from_slot(&wallet, &blockchain, from_slot)?;
+            term.info(&format!(
+                "rescanning wallet {} from slot {}, {} log entries dropped\n",
+                wallet.name, from_slot, dropped
+            ))?;
+        }
+    }
+
+    sync_with_blockchain(term, &root_dir, &wallet, &blockchain)?;
+    rescan::finish(&wallet)?;
+
+    let state = create_wallet_state_from_logs(&wallet, &root_dir, lookup::accum::Accum::default())?;
+    term.success(&format!(
+        "wallet {} rescanned to {}, balance {}\n",
+        wallet.name,
+        state.ptr().latest_block_date(),
+        style!(state.total()?)
+    ))?;
+    Ok(())
+}
+
+/// print the balances of all the wallets, with the funds being spent and
+/// their last activity, as a table or in JSON
+pub fn portfolio(term: &mut Term, root_dir: PathBuf, json: bool) -> Result<()> {
+    let portfolio = portfolio::portfolio(&root_dir)?;
+
+    if json {
+        schema::WALLET_PORTFOLIO.debug_check(&portfolio);
+        let json = serde_json::to_string_pretty(&portfolio)
+            .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+        writeln!(term, "{}", json)?;
+        return Ok(());
+    }
+
+    let none = "-".to_owned();
+    for summary in portfolio.wallets.iter() {
+        writeln!(
+            term,
+            "{}\t{}\t{}\tstaged {}\tpending {}\tlast activity {}",
+            style!(summary.wallet.as_str()).cyan(),
+            summary.blockchain.as_ref().unwrap_or(&none),
+            style!(Coin::new(summary.balance)?).green().bold(),
+            style!(Coin::new(summary.staged)?),
+            style!(Coin::new(summary.pending)?),
+            summary
+                .last_activity_time
+                .as_ref()
+                .or(summary.last_activity.as_ref())
+                .unwrap_or(&none),
+        )?;
+    }
+    for (blockchain, total) in portfolio.totals.iter() {
+        writeln!(
+            term,
+            "total\t{}\t{}",
+            blockchain,
+            style!(Coin::new(*total)?).green().bold()
+        )?;
+    }
+    Ok(())
+}
+
+/// add an alarm to the wallet's configuration
+pub fn alarm_add(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    alarm: alarm::Alarm,
+) -> Result<()> {
+    let mut wallet = Wallet::load(&root_dir, name)?;
+
+    if !wallet.config.alarms.contains(&alarm) {
+        wallet.config.alarms.push(alarm);
+        wallet.save()?;
+    }
+
+    term.success("Alarm successfully added to the wallet.\n")?;
+    Ok(())
+}
+
+/// list the alarms of the wallet, with the index to use to remove them
+pub fn alarm_list(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+
+    for (index, alarm) in wallet.config.alarms.iter().enumerate() {
+        writeln!(term, "{} {}", style!(index), alarm)?;
+    }
+    Ok(())
+}
+
+/// remove the alarm at the given index (as displayed by `alarm_list`)
+pub fn alarm_remove(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    index: usize,
+) -> Result<()> {
+    let mut wallet = Wallet::load(&root_dir, name)?;
+
+    if index >= wallet.config.alarms.len() {
+        return Err(Error::AlarmNotFound(index));
+    }
+    let alarm = wallet.config.alarms.remove(index);
+    wallet.save()?;
+
+    term.success(&format!("Alarm `{}' removed from the wallet.\n", alarm))?;
+    Ok(())
+}
+
+fn setting_value(wallet: &Wallet, setting: Setting) -> String {
+    match setting {
+        Setting::GapLimit => match wallet.config.hdwallet_model {
+            HDWalletModel::BIP44 => wallet.config.gap_limit().to_string(),
+            HDWalletModel::RandomIndex2Levels => "-".to_owned(),
+        },
+    }
+}
+
+/// print the value of the setting, or of all the settings
+pub fn config_get(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    setting: Option<Setting>,
+) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+
+    match setting {
+        Some(setting) => writeln!(term, "{}", setting_value(&wallet, setting))?,
+        None => {
+            for setting in Setting::variants().iter() {
+                let setting: Setting = setting.parse().unwrap();
+                writeln!(
+                    term,
+                    "{} {}",
+                    setting,
+                    style!(setting_value(&wallet, setting).as_str())
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// change a setting of the wallet's configuration.
+///
+/// Raising the gap limit rescans the wallet (unless `rescan_wallet` is
+/// false): the addresses beyond the previous limit were not looked for.
+pub fn config_set(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    setting: Setting,
+    value: String,
+    rescan_wallet: bool,
+) -> Result<()> {
+    let mut wallet = Wallet::load(&root_dir, name)?;
+
+    match setting {
+        Setting::GapLimit => {
+            if wallet.config.hdwallet_model != HDWalletModel::BIP44 {
+                return Err(Error::GapLimitNotSupported);
+            }
+            let gap_limit = match value.parse::<u32>() {
+                Ok(gap_limit) if gap_limit > 0 => gap_limit,
+                _ => return Err(Error::InvalidSettingValue(setting, value)),
+            };
+            let previous = wallet.config.gap_limit();
+            wallet.config.gap_limit = Some(gap_limit);
+            wallet.save()?;
+            term.success(&format!(
+                "gap limit of wallet {} set to {}\n",
+                wallet.name, gap_limit
+            ))?;
+
+            let synced = wallet.config.attached_blockchain.is_some()
+                && snapshot::log_version(&wallet)?.is_some();
+            if gap_limit > previous && synced {
+                if rescan_wallet {
+                    return rescan(term, root_dir, wallet.name, Some(0));
+                }
+                term.warn(&format!(
+                    "the addresses beyond the previous gap limit ({}) were not looked for, run `wallet rescan' to find them\n",
+                    previous
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// lease the unspent output of the wallet to the owner for `ttl`, so the
+/// other instances of the CLI do not select it.
+pub fn utxo_lease(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    txin: TxoPointer,
+    ttl: Duration,
+    owner: String,
+) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+    let index = load_utxo_index(&wallet, &root_dir)?;
+    if index.get(&txin).is_none() {
+        return Err(Error::UtxoNotFound(txin.id, txin.index));
+    }
+
+    let mut leases = lease::Leases::open(&wallet)?;
+    let lease = leases.grant(txin, owner, ttl)?;
+
+    term.success(&format!(
+        "{} for {} seconds\n",
+        lease,
+        lease.remaining().as_secs()
+    ))?;
+    Ok(())
+}
+
+/// release the lease of the unspent output before it expires
+pub fn utxo_release(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    txin: TxoPointer,
+    owner: String,
+) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+    let mut leases = lease::Leases::open(&wallet)?;
+    let lease = leases.release(&txin, &owner)?;
+
+    term.success(&format!("{}.{} released\n", lease.txid, lease.index))?;
+    Ok(())
+}
+
+/// list the active leases of the wallet
+pub fn utxo_leases(term: &mut Term, root_dir: PathBuf, name: WalletName) -> Result<()> {
+    let wallet = Wallet::load(&root_dir, name)?;
+    let leases = lease::Leases::open(&wallet)?;
+
+    for lease in leases.iter() {
+        writeln!(
+            term,
+            "{}.{} {} (expires in {}s)",
+            style!(&lease.txid),
+            style!(lease.index).yellow(),
+            style!(&lease.owner).cyan(),
+            style!(lease.remaining().as_secs())
+        )?;
+    }
     Ok(())
 }
 
@@ -419,20 +1008,89 @@ pub fn address(
     account: u32,
     is_internal: bool,
     index: u32,
+    qr_output: Option<qr::Output>,
 ) -> Result<()> {
     // load the wallet
     let wallet = Wallet::load(root_dir.clone(), name)?;
 
+    let addr = derive_address(
+        term,
+        &root_dir,
+        &wallet,
+        protocol_magic,
+        account,
+        is_internal,
+        index,
+    )?;
+
+    writeln!(term, "{}", style!(addr));
+    if let Some(output) = qr_output {
+        write!(term, "{}", qr::render_all(&[addr.to_string()], &output)?)?;
+    }
+
+    Ok(())
+}
+
+/// print the payment URI of the wallet's address, to be given to the payer
+/// (see `transaction add-output`)
+pub fn payment_request(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    protocol_magic: Option<u32>,
+    account: u32,
+    index: u32,
+    amount: Option<Coin>,
+    label: Option<String>,
+    message: Option<String>,
+    qr_output: Option<qr::Output>,
+) -> Result<()> {
+    let wallet = Wallet::load(root_dir.clone(), name)?;
+
+    let address = derive_address(
+        term,
+        &root_dir,
+        &wallet,
+        protocol_magic,
+        account,
+        false,
+        index,
+    )?;
+    let uri = PaymentUri {
+        address,
+        amount,
+        label,
+        message,
+    };
+
+    writeln!(term, "{}", uri)?;
+    if let Some(output) = qr_output {
+        write!(term, "{}", qr::render_all(&[uri.to_string()], &output)?)?;
+    }
+
+    Ok(())
+}
+
+/// the address of the wallet at the given derivation indices
+fn derive_address(
+    term: &mut Term,
+    root_dir: &Path,
+    wallet: &Wallet,
+    protocol_magic: Option<u32>,
+    account: u32,
+    is_internal: bool,
+    index: u32,
+) -> Result<ExtendedAddr> {
     let protocol_magic = if let Some(protocol_magic) = protocol_magic {
         cardano::config::ProtocolMagic::from(protocol_magic).into()
     } else {
-        let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+        let blockchain = load_attached_blockchain(root_dir, &wallet.config)?;
         blockchain.config.protocol_magic.into()
     };
 
     let addr = match wallet.config.hdwallet_model {
         HDWalletModel::BIP44 => {
-            let mut lookup_struct = load_bip44_lookup_structure(term, protocol_magic, &wallet);
+            let mut lookup_struct = load_bip44_lookup_structure(term, protocol_magic, wallet)?;
             let account = ::cardano::bip::bip44::Account::new(account)?;
             let change = if is_internal {
                 account.internal()?
@@ -443,13 +1101,485 @@ pub fn address(
             lookup_struct.get_address(&addressing)
         }
         HDWalletModel::RandomIndex2Levels => {
-            let lookup_struct = load_randomindex_lookup_structure(term, protocol_magic, &wallet);
+            let lookup_struct = load_randomindex_lookup_structure(term, protocol_magic, wallet)?;
             let addressing = ::cardano::wallet::rindex::Addressing::new(account, index);
             lookup_struct.get_address(&addressing)
         }
     };
+    Ok(addr)
+}
 
-    writeln!(term, "{}", style!(addr));
+/// sign the message with the private key of the wallet's address
+pub fn sign_message(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    protocol_magic: Option<u32>,
+    address: ExtendedAddr,
+    message: String,
+) -> Result<()> {
+    let wallet = Wallet::load(root_dir.clone(), name)?;
+
+    let protocol_magic = if let Some(protocol_magic) = protocol_magic {
+        cardano::config::ProtocolMagic::from(protocol_magic).into()
+    } else {
+        let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+        blockchain.config.protocol_magic.into()
+    };
+
+    let xprv = match find_wallet_address(term, protocol_magic, &wallet, &address)? {
+        None => return Err(Error::AddressNotInWallet(address)),
+        Some((_, xprv)) => xprv,
+    };
 
+    let signature = message::MessageSignature::sign(&xprv, message.as_bytes());
+    writeln!(term, "{}", signature)?;
     Ok(())
 }
+
+/// check the message was signed with the private key of the address
+pub fn verify_message(
+    term: &mut Term,
+    address: ExtendedAddr,
+    message: String,
+    signature: String,
+) -> Result<()> {
+    let signature: message::MessageSignature = signature.parse()?;
+    signature.verify(&address, message.as_bytes())?;
+
+    term.success(&format!(
+        "the message was signed by the owner of {}\n",
+        address
+    ))?;
+    Ok(())
+}
+
+/// how `owns_address` reports whether the address is the wallet's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnershipReport {
+    Text,
+    Json,
+    /// only through the exit code
+    ExitCode,
+}
+
+/// the exit code of `wallet owns-address` when the address is not the
+/// wallet's, the failures exit with 1
+pub const NOT_OWNED_EXIT_CODE: i32 = 2;
+
+#[derive(Serialize)]
+struct Ownership {
+    address: String,
+    owned: bool,
+    /// the derivation path of the address, if owned
+    path: Option<String>,
+}
+
+/// tell whether the address is one of the wallet's, with its derivation
+/// path. Returns whether it is: the caller picks the exit code.
+pub fn owns_address(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: WalletName,
+    protocol_magic: Option<u32>,
+    address: ExtendedAddr,
+    report: OwnershipReport,
+) -> Result<bool> {
+    let wallet = Wallet::load(root_dir.clone(), name)?;
+
+    let protocol_magic = if let Some(protocol_magic) = protocol_magic {
+        cardano::config::ProtocolMagic::from(protocol_magic).into()
+    } else {
+        let blockchain = load_attached_blockchain(&root_dir, &wallet.config)?;
+        blockchain.config.protocol_magic.into()
+    };
+
+    let path = find_wallet_address(term, protocol_magic, &wallet, &address)?
+        .map(|(addressing, _)| addressing.derivation_path());
+
+    match report {
+        OwnershipReport::Text => match path {
+            Some(ref path) => term.success(&format!(
+                "{} is an address of wallet {}, derivation path {}\n",
+                address, wallet.name, path
+            ))?,
+            None => term.warn(&format!(
+                "{} is not an address of wallet {}\n",
+                address, wallet.name
+            ))?,
+        },
+        OwnershipReport::Json => {
+            let ownership = Ownership {
+                address: address.to_string(),
+                owned: path.is_some(),
+                path: path.clone(),
+            };
+            schema::WALLET_OWNS_ADDRESS.debug_check(&ownership);
+            let json = serde_json::to_string_pretty(&ownership)
+                .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::Other, e))?;
+            writeln!(term, "{}", json)?;
+        }
+        OwnershipReport::ExitCode => {}
+    }
+
+    Ok(path.is_some())
+}
+
+/// stage the transactions sweeping all the funds of the random index wallet
+/// `old` to the address of the BIP44 wallet `new` at the given account and
+/// index (see the `migrate` module), reporting the fees before anything is
+/// signed. The inputs of each staging transaction are leased to it. With
+/// `dry_run`, nothing is staged nor leased.
+pub fn migrate(
+    term: &mut Term,
+    root_dir: PathBuf,
+    old: WalletName,
+    new: WalletName,
+    account: u32,
+    index: u32,
+    fee_policy: Option<LinearFee>,
+    dry_run: bool,
+) -> Result<()> {
+    let old_wallet = Wallet::load(&root_dir, old.clone())?;
+    if old_wallet.config.hdwallet_model != HDWalletModel::RandomIndex2Levels {
+        return Err(Error::MigrationNotRandomIndex(old));
+    }
+    let new_wallet = Wallet::load(&root_dir, new.clone())?;
+    if new_wallet.config.hdwallet_model != HDWalletModel::BIP44 {
+        return Err(Error::MigrationNotBip44(new));
+    }
+
+    let blockchain = load_attached_blockchain(&root_dir, &old_wallet.config)?;
+    if let Some(attached) = new_wallet.config.attached_blockchain()? {
+        if attached != blockchain.name {
+            return Err(Error::MigrationOtherBlockchain(
+                blockchain.name.to_string(),
+                attached.to_string(),
+            ));
+        }
+    }
+    let fee_algorithm = fee_policy.unwrap_or_else(|| {
+        blockchain.fee_policy().unwrap_or_else(|err| {
+            warn!(
+                "cannot read the fee policy of blockchain {}: {}",
+                blockchain.name, err
+            );
+            LinearFee::default()
+        })
+    });
+    let max_tx_size = blockchain
+        .max_tx_size()
+        .unwrap_or_else(|err| {
+            warn!(
+                "cannot read the maximum transaction size of blockchain {}: {}",
+                blockchain.name, err
+            );
+            None
+        })
+        .unwrap_or(check::DEFAULT_MAX_TX_SIZE);
+
+    let protocol_magic = blockchain.config.protocol_magic;
+    let destination = derive_address(
+        term,
+        &root_dir,
+        &new_wallet,
+        Some(*protocol_magic),
+        account,
+        false,
+        index,
+    )?;
+
+    // the leases file stays locked until the inputs are leased to the
+    // staging transactions
+    let mut leases = lease::Leases::open(&old_wallet)?;
+    let utxos: Vec<Input> = load_utxo_index(&old_wallet, &root_dir)?
+        .utxos()
+        .filter(|utxo| !leases.is_leased_to_other(&utxo.extract_txin(), None))
+        .map(|utxo| {
+            let txin = utxo.extract_txin();
+            Input {
+                transaction_id: txin.id,
+                index_in_transaction: txin.index,
+                expected_value: utxo.credited_value,
+            }
+        })
+        .collect();
+    let plan = migrate::plan(utxos, &destination, &fee_algorithm, max_tx_size)?;
+    if plan.batches.is_empty() {
+        return Err(Error::MigrationNothingToSweep);
+    }
+
+    let stagings = if dry_run {
+        Vec::new()
+    } else {
+        stage_migration(
+            &root_dir,
+            protocol_magic,
+            &plan,
+            &destination,
+            &fee_algorithm,
+            &mut leases,
+        )?
+    };
+    drop(leases);
+
+    for (number, batch) in plan.batches.iter().enumerate() {
+        write!(term, "transaction {}: ", number + 1)?;
+        if let Some(staging) = stagings.get(number) {
+            write!(term, "{} ", style!(staging.id()))?;
+        }
+        writeln!(
+            term,
+            "{} inputs, {} for a fee of {} ({} bytes)",
+            batch.inputs.len(),
+            style!(batch.value),
+            style!(batch.fee),
+            batch.size
+        )?;
+    }
+    let total_value = plan.total_value()?;
+    let total_fee = plan.total_fee()?;
+    let received = (total_value - total_fee)?;
+    writeln!(term, "total fee: {}", style!(total_fee))?;
+    writeln!(
+        term,
+        "{} receives: {}",
+        style!(&destination),
+        style!(received)
+    )?;
+    if !plan.left_out.is_empty() {
+        term.warn(&format!(
+            "{} unspent outputs worth {} left out, each one is worth less than the fee to spend it\n",
+            plan.left_out.len(),
+            plan.left_out_value()?
+        ))?;
+    }
+
+    if dry_run {
+        term.info("dry run, nothing staged\n")?;
+    } else {
+        info!(
+            "wallet {} migrated to wallet {} in {} staging transactions by {}",
+            old,
+            new,
+            stagings.len(),
+            logging::originator()
+        );
+        term.success(&format!(
+            "{} staging transactions, sign them with `transaction sign' and send them with `transaction send'\n",
+            stagings.len()
+        ))?;
+    }
+    Ok(())
+}
+
+/// stage every batch of the plan, its inputs leased to its staging
+/// transaction, none of them if one fails
+fn stage_migration(
+    root_dir: &Path,
+    protocol_magic: ProtocolMagic,
+    plan: &migrate::Plan,
+    destination: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+    leases: &mut lease::Leases,
+) -> Result<Vec<StagingTransaction>> {
+    let mut stagings = Vec::new();
+    for batch in plan.batches.iter() {
+        match stage_batch(
+            root_dir,
+            protocol_magic,
+            batch,
+            destination,
+            fee_algorithm,
+            leases,
+        ) {
+            Ok(staging) => stagings.push(staging),
+            Err(err) => {
+                for (staging, batch) in stagings.into_iter().zip(plan.batches.iter()) {
+                    destroy_staging(staging, &batch.inputs, leases);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(stagings)
+}
+
+fn stage_batch(
+    root_dir: &Path,
+    protocol_magic: ProtocolMagic,
+    batch: &migrate::Batch,
+    destination: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+    leases: &mut lease::Leases,
+) -> Result<StagingTransaction> {
+    let mut staging = StagingTransaction::new(root_dir.to_path_buf(), protocol_magic)
+        .map_err(Error::MigrationCannotCreateTransaction)?;
+    let owner = lease::staging_owner(staging.id());
+    for (granted, input) in batch.inputs.iter().enumerate() {
+        if let Err(err) = leases.grant(input.extract_txin(), owner.clone(), lease::STAGING_TTL) {
+            destroy_staging(staging, &batch.inputs[..granted], leases);
+            return Err(err);
+        }
+    }
+    for operation in batch.operations(destination) {
+        let staged = match operation {
+            Operation::AddInput(input) => staging.add_input(input),
+            Operation::AddChange(change) => staging.add_change(change),
+            // the fee pinned is the fee of the batch
+            Operation::Finalize => staging.finalize(fee_algorithm),
+            operation => unreachable!("not an operation of a sweep: {:?}", operation),
+        };
+        if let Err(err) = staged {
+            destroy_staging(staging, &batch.inputs, leases);
+            return Err(Error::MigrationCannotStage(err));
+        }
+    }
+    Ok(staging)
+}
+
+/// destroy the staging transaction and release the leases of its inputs
+fn destroy_staging(staging: StagingTransaction, inputs: &[Input], leases: &mut lease::Leases) {
+    let owner = lease::staging_owner(staging.id());
+    for input in inputs {
+        if let Err(err) = leases.release(&input.extract_txin(), &owner) {
+            warn!(
+                "cannot release the lease of input {}.{}: {}",
+                input.transaction_id, input.index_in_transaction, err
+            );
+        }
+    }
+    let id = staging.id().to_string();
+    if let Err(err) = staging.destroy() {
+        error!("cannot destroy the staging transaction {}: {}", id, err);
+    }
+}
+
+pub fn display_wallet_utxos<'a, I>(term: &mut Term, utxos: I)
+where
+    I: IntoIterator<Item = &'a UTxO<lookup::Address>>,
+{
+    for utxo in utxos {
+        writeln!(
+            term,
+            "{}.{} {}",
+            style!(utxo.transaction_id),
+            style!(utxo.index_in_transaction).yellow(),
+            style!(utxo.credited_value).green()
+        )
+        .unwrap()
+    }
+}
+
+pub fn display_wallet_state_logs<LS>(
+    term: &mut Term,
+    wallet: &Wallet,
+    _state: &mut state::State<LS>,
+    pretty: bool,
+) -> Result<()>
+where
+    LS: lookup::AddressLookup,
+{
+    let log_lock = lock_wallet_log(&wallet)?;
+    let reader = log::LogReader::open(log_lock)?;
+    let reader: log::LogIterator<lookup::Address> = reader.into_iter();
+    let reader = reader.filter_map(|r| match r {
+        Err(err) => panic!("{:?}", err),
+        Ok(v) => Some(v),
+    });
+
+    for log in reader {
+        match log {
+            log::Log::Checkpoint(ptr) => {
+                if !pretty {
+                    writeln!(
+                        term,
+                        "{} {} ({})",
+                        style!("checkpoint").cyan(),
+                        style!(ptr.latest_block_date()),
+                        style!(ptr.latest_known_hash)
+                    )
+                    .unwrap();
+                    writeln!(term, "").unwrap();
+                }
+            }
+            log::Log::ReceivedFund(ptr, utxo) => {
+                if pretty {
+                    display_utxo(term, ptr, utxo, false);
+                } else {
+                    dump_utxo(term, ptr, utxo, false);
+                }
+            }
+            log::Log::SpentFund(ptr, utxo) => {
+                if pretty {
+                    display_utxo(term, ptr, utxo, true);
+                } else {
+                    dump_utxo(term, ptr, utxo, true);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn display_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
+    let ptr = format!("{}", style!(ptr.latest_block_date()));
+    let tid = format!("{}", style!(utxo.transaction_id));
+    let tii = format!("{:03}", utxo.index_in_transaction);
+    const WIDTH: usize = 14;
+    let credit = if debit {
+        format!("{:>width$}", " ", width = WIDTH)
+    } else {
+        format!(
+            "{:>width$}",
+            format!("{}", utxo.credited_value),
+            width = WIDTH
+        )
+    };
+    let debit = if debit {
+        format!(
+            "{:>width$}",
+            format!("{}", utxo.credited_value),
+            width = WIDTH
+        )
+    } else {
+        format!("{:>width$}", " ", width = WIDTH)
+    };
+
+    writeln!(
+        term,
+        "{:9}|{}.{}|{}|{}",
+        ::console::pad_str(&ptr, 9, ::console::Alignment::Left, None),
+        tid,
+        style!(tii).yellow(),
+        style!(credit).green(),
+        style!(debit).red()
+    )
+    .unwrap()
+}
+
+pub fn dump_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
+    let title = if debit {
+        style!("debit").red()
+    } else {
+        style!("credit").green()
+    };
+    let amount = if debit {
+        style!(format!("{}", utxo.credited_value)).red()
+    } else {
+        style!(format!("{}", utxo.credited_value)).green()
+    };
+
+    writeln!(
+        term,
+        "{} {}.{}",
+        title,
+        style!(utxo.transaction_id),
+        style!(utxo.index_in_transaction).yellow(),
+    )
+    .unwrap();
+    writeln!(term, "Date {}", style!(ptr.latest_block_date())).unwrap();
+    writeln!(term, "Block {}", style!(ptr.latest_known_hash)).unwrap();
+    writeln!(term, "Value {}", amount).unwrap();
+    writeln!(term, "").unwrap()
+}
@@ -6,6 +6,8 @@ use std::{
 use cardano::hdwallet::{self, DerivationScheme};
 
 use super::super::utils::password_encrypted::{self, Password};
+use super::alarm::Alarm;
+use super::state::lookup::sequentialindex::DEFAULT_GAP_LIMIT;
 use super::Error;
 use super::Result;
 use blockchain::{BlockchainName, BlockchainNameError};
@@ -38,7 +40,7 @@ pub enum HDWalletModel {
 
 /// this is the wallet configuration and will be saved to the local disk
 ///
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// optional name of the local blockchain the wallet is attached to
     ///
@@ -53,8 +55,21 @@ pub struct Config {
     /// This is needed so we know what kind of wallet HD we are dealing with
     ///
     pub hdwallet_model: HDWalletModel,
+
+    /// alarms to evaluate every time the wallet is synchronised
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alarms: Vec<Alarm>,
+
+    /// number of consecutive unused addresses after which the BIP44 wallets
+    /// stop looking for their addresses, the default gap limit if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gap_limit: Option<u32>,
 }
 impl Config {
+    pub fn gap_limit(&self) -> u32 {
+        self.gap_limit.unwrap_or(*DEFAULT_GAP_LIMIT)
+    }
+
     pub fn attached_blockchain(
         &self,
     ) -> ::std::result::Result<Option<BlockchainName>, BlockchainNameError> {
@@ -73,6 +88,36 @@ impl Default for Config {
             attached_blockchain: None,
             derivation_scheme: DerivationScheme::V2,
             hdwallet_model: HDWalletModel::BIP44,
+            alarms: Vec::new(),
+            gap_limit: None,
+        }
+    }
+}
+
+/// the settings of the wallet's configuration `wallet config` gives
+/// access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    GapLimit,
+}
+impl Setting {
+    pub fn variants() -> [&'static str; 1] {
+        ["gap-limit"]
+    }
+}
+impl ::std::fmt::Display for Setting {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            Setting::GapLimit => write!(f, "gap-limit"),
+        }
+    }
+}
+impl FromStr for Setting {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "gap-limit" => Ok(Setting::GapLimit),
+            _ => Err(format!("unknown wallet setting `{}'", s)),
         }
     }
 }
@@ -0,0 +1,168 @@
+//! Wallet activity digest
+//!
+//! summarize what happened to a wallet over a period of time (a day or a
+//! week): the funds received and sent, the number of transactions and the
+//! state of the transactions sent from this computer. The digests are
+//! generated by `watch --digest`, at the end of every period.
+//!
+
+use super::error::{Error, Result};
+use super::state::{log, lookup, ptr::StatePtr};
+use super::utils::lock_wallet_log;
+use super::Wallet;
+
+use blockchain::Blockchain;
+use cardano::{coin::Coin, tx::TxId};
+use serde_yaml;
+use storage_units::utils::tmpfile::TmpFile;
+use transaction::core::PendingTransaction;
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DigestPeriod {
+        Daily,
+        Weekly,
+    }
+}
+impl DigestPeriod {
+    pub fn duration(self) -> Duration {
+        match self {
+            DigestPeriod::Daily => Duration::from_secs(24 * 3600),
+            DigestPeriod::Weekly => Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+}
+
+/// summary of the activity of a wallet over a period of time
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub wallet: String,
+    pub period: String,
+    pub from: String,
+    pub to: String,
+    /// total of the funds received during the period, in lovelace
+    pub received: u64,
+    /// total of the funds spent during the period, in lovelace
+    pub sent: u64,
+    /// number of transactions crediting the wallet confirmed in the period
+    pub received_transactions: usize,
+    /// number of transactions debiting the wallet confirmed in the period
+    pub sent_transactions: usize,
+    /// balance of the wallet at the end of the period, in lovelace
+    pub balance: u64,
+    /// transactions sent to the wallet's blockchain, still not confirmed
+    pub pending: Vec<TxId>,
+    /// pending transactions sent before the beginning of the period, they
+    /// are unlikely to be confirmed and might need to be resubmitted
+    pub expired: Vec<TxId>,
+}
+
+/// compute the digest of the wallet for the period ending now
+pub fn compute(
+    root_dir: PathBuf,
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+    period: DigestPeriod,
+) -> Result<Digest> {
+    let genesis = blockchain.load_genesis_data()?;
+
+    let to = SystemTime::now();
+    let from = to - period.duration();
+    let in_period = |ptr: &StatePtr| -> bool {
+        match ptr.latest_addr {
+            None => false,
+            Some(ref date) => {
+                let time = blockchain.slot_start_time(&genesis, date);
+                time >= from && time < to
+            }
+        }
+    };
+
+    let mut received = Coin::zero();
+    let mut sent = Coin::zero();
+    let mut balance = Coin::zero();
+    let mut received_transactions = BTreeSet::new();
+    let mut sent_transactions = BTreeSet::new();
+
+    let log_lock = lock_wallet_log(wallet)?;
+    let reader = log::LogReader::open(log_lock)?;
+    let reader: log::LogIterator<lookup::Address> = reader.into_iter();
+    for entry in reader {
+        match entry? {
+            log::Log::Checkpoint(_) => {}
+            log::Log::ReceivedFund(ptr, utxo) => {
+                balance = (balance + utxo.credited_value)?;
+                if in_period(&ptr) {
+                    received = (received + utxo.credited_value)?;
+                    received_transactions.insert(utxo.transaction_id);
+                }
+            }
+            log::Log::SpentFund(ptr, utxo) => {
+                balance = (balance - utxo.credited_value)?;
+                if in_period(&ptr) {
+                    sent = (sent + utxo.credited_value)?;
+                    // the log does not keep the spending transaction's id,
+                    // the block is a good enough approximation
+                    sent_transactions.insert(ptr.latest_known_hash);
+                }
+            }
+        }
+    }
+
+    let mut pending = Vec::new();
+    let mut expired = Vec::new();
+    // a corrupted journal should not prevent from getting a digest
+    let pendings = PendingTransaction::list(root_dir).unwrap_or_else(|err| {
+        warn!("cannot read the pending transactions journal: {}", err);
+        Vec::new()
+    });
+    for tx in pendings {
+        if tx.blockchain != blockchain.name.as_ref() {
+            continue;
+        }
+        if tx.sent_at < from {
+            expired.push(tx.txid);
+        } else {
+            pending.push(tx.txid);
+        }
+    }
+
+    Ok(Digest {
+        wallet: wallet.name.to_string(),
+        period: format!("{}", period).to_lowercase(),
+        from: format!("{}", ::humantime::format_rfc3339_seconds(from)),
+        to: format!("{}", ::humantime::format_rfc3339_seconds(to)),
+        received: received.into(),
+        sent: sent.into(),
+        received_transactions: received_transactions.len(),
+        sent_transactions: sent_transactions.len(),
+        balance: balance.into(),
+        pending: pending,
+        expired: expired,
+    })
+}
+
+/// write the digest in the directory, as `WALLET-PERIOD-DAY.yml` (the day
+/// the period ended), returns the path of the file
+pub fn save(dir: &Path, digest: &Digest) -> Result<PathBuf> {
+    fs::DirBuilder::new().recursive(true).create(dir)?;
+    let path = dir.join(format!(
+        "{}-{}-{}.yml",
+        digest.wallet,
+        digest.period,
+        &digest.to[..10]
+    ));
+
+    let mut tmpfile = TmpFile::create(dir.to_path_buf())?;
+    serde_yaml::to_writer(&mut tmpfile, digest)
+        .map_err(|e| Error::DigestWriteFailed(path.clone(), e))?;
+    tmpfile.render_permanent(&path)?;
+    Ok(path)
+}
@@ -1,11 +1,21 @@
 use blockchain;
-use cardano::{bip::bip44, coin, hdwallet, wallet::rindex};
+use cardano::{
+    address::ExtendedAddr, bip::bip44, block::EpochId, coin, fee, hdwallet, tx::TxId,
+    wallet::rindex,
+};
 use serde_yaml;
-use storage_units::utils::lock;
+use storage_units::{append, utils::lock};
+use transaction::core;
 
-use std::{error, fmt, io, path::PathBuf};
+use std::{error, fmt, io, path::PathBuf, time::Duration};
 
+use utils::qr;
+
+use super::config::Setting;
+use super::lease;
+use super::message;
 use super::state::{log, lookup};
+use super::WalletName;
 
 /// wallet errors
 #[derive(Debug)]
@@ -30,6 +40,35 @@ pub enum Error {
     NotAttachedToBlockchain,
     AttachAlreadyAttached(String),
     WalletsLoadFailed(io::Error),
+    WalletAlreadyExists(String),
+    BackupInvalid(PathBuf),
+    BackupInvalidPassword,
+    AddressNotInWallet(ExtendedAddr),
+    MessageSignatureError(message::Error),
+    QrCodeFailed(qr::Error),
+    DigestWriteFailed(PathBuf, serde_yaml::Error),
+    AlarmNotFound(usize),
+    InvalidSettingValue(Setting, String),
+    GapLimitNotSupported,
+    SyncEpochsPruned(EpochId),
+    LeasesAlreadyLocked(u32),
+    LeasesLockFailed(lock::Error),
+    LeasesReadFailed(PathBuf, serde_yaml::Error),
+    LeasesWriteFailed(PathBuf, serde_yaml::Error),
+    UtxoNotFound(TxId, u32),
+    UtxoAlreadyLeased(lease::Lease),
+    UtxoNotLeased(TxId, u32),
+    LeaseTtlTooLong(Duration),
+    WalletSyncFailed(WalletName, Box<Error>),
+    WalletSyncPanicked(WalletName),
+    MigrationNotRandomIndex(WalletName),
+    MigrationNotBip44(WalletName),
+    MigrationOtherBlockchain(String, String), // (old wallet's, new wallet's)
+    MigrationNothingToSweep,
+    MigrationInvalidFee(fee::Error),
+    MigrationInvalidTransaction(core::transaction::Error),
+    MigrationCannotCreateTransaction(append::Error),
+    MigrationCannotStage(core::staging_transaction::StagingUpdateError),
 }
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
@@ -66,6 +105,16 @@ impl From<hdwallet::Error> for Error {
         Error::CannotRetrievePrivateKey(e)
     }
 }
+impl From<message::Error> for Error {
+    fn from(e: message::Error) -> Self {
+        Error::MessageSignatureError(e)
+    }
+}
+impl From<qr::Error> for Error {
+    fn from(e: qr::Error) -> Self {
+        Error::QrCodeFailed(e)
+    }
+}
 impl From<log::Error> for Error {
     fn from(e: log::Error) -> Self {
         match e {
@@ -85,9 +134,10 @@ impl fmt::Display for Error {
             Error::BlockchainNameError(_) => write!(f, "Invalid blockchain name"),
             Error::CoinError(_) => write!(f, "Error with coin calculations"),
             Error::AddressLookupError(_) => write!(f, "Error with account addressing"),
-            Error::CannotRetrievePrivateKey(_) => {
-                write!(f, "Unsupported private key serialisation")
-            }
+            Error::CannotRetrievePrivateKey(_) => write!(
+                f,
+                "Unsupported private key serialisation, the wallet might need to be recovered again"
+            ),
             Error::CannotRetrievePrivateKeyInvalidPassword => {
                 write!(f, "Invalid spending password")
             }
@@ -120,6 +170,91 @@ impl fmt::Display for Error {
                 write!(f, "Wallet already attached to blockchain `{}'", bn)
             }
             Error::WalletsLoadFailed(_) => write!(f, "Cannot load wallets"),
+            Error::WalletAlreadyExists(name) => write!(f, "Wallet `{}' already exists", name),
+            Error::BackupInvalid(ref path) => write!(
+                f,
+                "`{}` is not a valid wallet backup",
+                path.to_string_lossy()
+            ),
+            Error::BackupInvalidPassword => write!(f, "Invalid backup password"),
+            Error::AddressNotInWallet(address) => {
+                write!(f, "The address {} is not one of the wallet's", address)
+            }
+            Error::MessageSignatureError(_) => write!(f, "Invalid message signature"),
+            Error::QrCodeFailed(_) => write!(f, "Cannot render the QR code"),
+            Error::DigestWriteFailed(ref path, _) => write!(
+                f,
+                "Failed to write the wallet digest to `{}`",
+                path.to_string_lossy()
+            ),
+            Error::AlarmNotFound(index) => write!(f, "No alarm at index {}", index),
+            Error::InvalidSettingValue(setting, value) => {
+                write!(f, "Invalid value `{}' for the wallet setting `{}'", value, setting)
+            }
+            Error::GapLimitNotSupported => {
+                write!(f, "The gap limit only applies to the BIP44 wallets")
+            }
+            Error::SyncEpochsPruned(epoch) => write!(
+                f,
+                "The blocks the wallet needs to sync have been pruned, the local blockchain starts at epoch {}",
+                epoch
+            ),
+            Error::LeasesAlreadyLocked(pid) => write!(
+                f,
+                "The wallet's leases are being updated by another process (process id: {})",
+                pid
+            ),
+            Error::LeasesLockFailed(_) => write!(f, "Cannot lock the wallet's leases"),
+            Error::LeasesReadFailed(ref path, _) => write!(
+                f,
+                "Failed to read the wallet's leases from `{}`",
+                path.to_string_lossy()
+            ),
+            Error::LeasesWriteFailed(ref path, _) => write!(
+                f,
+                "Failed to write the wallet's leases to `{}`",
+                path.to_string_lossy()
+            ),
+            Error::UtxoNotFound(txid, index) => {
+                write!(f, "No unspent output {}.{} in the wallet", txid, index)
+            }
+            Error::UtxoAlreadyLeased(lease) => write!(
+                f,
+                "Unspent output {} for {} more seconds",
+                lease,
+                lease.remaining().as_secs()
+            ),
+            Error::UtxoNotLeased(txid, index) => {
+                write!(f, "Unspent output {}.{} is not leased", txid, index)
+            }
+            Error::LeaseTtlTooLong(ttl) => write!(
+                f,
+                "Lease duration of {} seconds is too long, its expiry time cannot be represented",
+                ttl.as_secs()
+            ),
+            Error::WalletSyncFailed(name, _) => write!(f, "Cannot sync the wallet `{}'", name),
+            Error::WalletSyncPanicked(name) => {
+                write!(f, "Cannot sync the wallet `{}', its update panicked", name)
+            }
+            Error::MigrationNotRandomIndex(name) => {
+                write!(f, "Wallet `{}' is not a random index (Daedalus) wallet", name)
+            }
+            Error::MigrationNotBip44(name) => write!(f, "Wallet `{}' is not a BIP44 (Icarus) wallet", name),
+            Error::MigrationOtherBlockchain(old, new) => write!(
+                f,
+                "The new wallet is attached to blockchain `{}', not to `{}'",
+                new, old
+            ),
+            Error::MigrationNothingToSweep => write!(
+                f,
+                "No unspent output worth sweeping, sync the wallet first"
+            ),
+            Error::MigrationInvalidFee(_) => write!(f, "Cannot compute the fee of the sweep transactions"),
+            Error::MigrationInvalidTransaction(_) => write!(f, "Invalid sweep transaction"),
+            Error::MigrationCannotCreateTransaction(_) => {
+                write!(f, "Cannot create the staging transaction of the sweep")
+            }
+            Error::MigrationCannotStage(_) => write!(f, "Cannot stage the sweep transaction"),
         }
     }
 }
@@ -146,6 +281,35 @@ impl error::Error for Error {
             Error::NotAttachedToBlockchain => None,
             Error::AttachAlreadyAttached(_) => None,
             Error::WalletsLoadFailed(ref err) => Some(err),
+            Error::WalletAlreadyExists(_) => None,
+            Error::BackupInvalid(_) => None,
+            Error::BackupInvalidPassword => None,
+            Error::AddressNotInWallet(_) => None,
+            Error::MessageSignatureError(ref err) => Some(err),
+            Error::QrCodeFailed(ref err) => Some(err),
+            Error::DigestWriteFailed(_, ref err) => Some(err),
+            Error::AlarmNotFound(_) => None,
+            Error::InvalidSettingValue(_, _) => None,
+            Error::GapLimitNotSupported => None,
+            Error::SyncEpochsPruned(_) => None,
+            Error::LeasesAlreadyLocked(_) => None,
+            Error::LeasesLockFailed(ref err) => Some(err),
+            Error::LeasesReadFailed(_, ref err) => Some(err),
+            Error::LeasesWriteFailed(_, ref err) => Some(err),
+            Error::UtxoNotFound(_, _) => None,
+            Error::UtxoAlreadyLeased(_) => None,
+            Error::UtxoNotLeased(_, _) => None,
+            Error::LeaseTtlTooLong(_) => None,
+            Error::WalletSyncFailed(_, ref err) => Some(err.as_ref()),
+            Error::WalletSyncPanicked(_) => None,
+            Error::MigrationNotRandomIndex(_) => None,
+            Error::MigrationNotBip44(_) => None,
+            Error::MigrationOtherBlockchain(_, _) => None,
+            Error::MigrationNothingToSweep => None,
+            Error::MigrationInvalidFee(ref err) => Some(err),
+            Error::MigrationInvalidTransaction(ref err) => Some(err),
+            Error::MigrationCannotCreateTransaction(ref err) => Some(err),
+            Error::MigrationCannotStage(ref err) => Some(err),
         }
     }
 }
@@ -0,0 +1,314 @@
+//! transaction history of a wallet
+//!
+//! every output credited to or spent by the wallet, in chain order, with
+//! the transaction it happened in, the counterparties and the running
+//! balance. The wallet log only records the outputs of the wallet, the
+//! transactions themselves are read from the local blockchain: the
+//! counterparties of the transactions in pruned epochs are not known.
+//!
+
+use super::error::Result;
+use super::state::{log, lookup};
+use super::utils::lock_wallet_log;
+use super::Wallet;
+
+use address_book::{AddressBook, LABEL_PREFIX};
+use blockchain::{
+    explorer::{self, BlockQuery},
+    Blockchain,
+};
+use cardano::{
+    address::ExtendedAddr,
+    block::{Block, BlockDate, HeaderHash},
+    coin::{self, Coin},
+    tx::{TxAux, TxId, TxoPointer},
+};
+use utils::term::Term;
+
+use std::{collections::BTreeSet, fmt, io::Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Credit,
+    Debit,
+}
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::Credit => write!(f, "credit"),
+            Kind::Debit => write!(f, "debit"),
+        }
+    }
+}
+
+/// which outputs of the wallet to list
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub address: Option<ExtendedAddr>,
+    /// the account of the BIP44 wallets
+    pub account: Option<u32>,
+}
+impl Filter {
+    fn matches(&self, address: &ExtendedAddr, addressing: &lookup::Address) -> bool {
+        if let Some(ref filter) = self.address {
+            if filter != address {
+                return false;
+            }
+        }
+        match (self.account, addressing) {
+            (None, _) => true,
+            (Some(account), lookup::Address::Bip44(addressing)) => {
+                addressing.account.get_account_number() == account
+            }
+            (Some(_), _) => false,
+        }
+    }
+}
+
+/// an address, not of the wallet, funds came from or went to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterparty {
+    pub address: String,
+    /// the label of the address in the address book
+    pub label: Option<String>,
+}
+impl fmt::Display for Counterparty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.label {
+            Some(ref label) => write!(f, "{} ({}{})", self.address, LABEL_PREFIX, label),
+            None => write!(f, "{}", self.address),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub date: BlockDate,
+    /// the slot of `date`, counted from the genesis
+    pub slot: u64,
+    pub kind: Kind,
+    /// the transaction crediting or spending the output, unknown for the
+    /// spending transactions in pruned epochs
+    pub txid: Option<TxId>,
+    pub output: TxoPointer,
+    pub address: ExtendedAddr,
+    pub amount: Coin,
+    /// the balance after this entry, of the outputs matching the filter
+    pub balance: Coin,
+    /// where the funds came from (credits) or went to (debits)
+    pub counterparties: Vec<Counterparty>,
+}
+
+/// the credits and debits of the wallet matching the filter
+pub fn history(
+    term: &mut Term,
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+    filter: &Filter,
+) -> Result<Vec<Entry>> {
+    let logs: Vec<log::Log<lookup::Address>> = {
+        let log_lock = lock_wallet_log(wallet)?;
+        let reader = log::LogReader::open(log_lock)?;
+        reader.into_iter().collect::<log::Result<Vec<_>>>()?
+    };
+
+    let address_book = AddressBook::load(&wallet.root_dir)
+        .map_err(|err| warn!("cannot load the address book: {}", err))
+        .ok();
+    let book = address_book.as_ref();
+
+    // the addresses of the wallet are not counterparties
+    let own: BTreeSet<String> = logs
+        .iter()
+        .filter_map(|entry| match entry {
+            log::Log::ReceivedFund(_, utxo) => Some(utxo.credited_address.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut entries = Vec::new();
+    // inputs of the crediting transactions, resolved all at once below
+    let mut credit_inputs: Vec<(usize, TxoPointer)> = Vec::new();
+    let mut block: Option<(HeaderHash, Block)> = None;
+    let mut balance = Coin::zero();
+    for entry in logs {
+        let (kind, ptr, utxo) = match entry {
+            log::Log::Checkpoint(_) => continue,
+            log::Log::ReceivedFund(ptr, utxo) => (Kind::Credit, ptr, utxo),
+            log::Log::SpentFund(ptr, utxo) => (Kind::Debit, ptr, utxo),
+        };
+        if !filter.matches(&utxo.credited_address, &utxo.credited_addressing) {
+            continue;
+        }
+
+        let reload = match block {
+            Some((ref hash, _)) => hash != &ptr.latest_known_hash,
+            None => true,
+        };
+        if reload {
+            let query = BlockQuery::Hash(ptr.latest_known_hash.clone());
+            block = match explorer::find_block(blockchain, &query) {
+                Ok(found) => Some(found),
+                Err(err) => {
+                    debug!(
+                        "block {} of the wallet log not available: {}",
+                        ptr.latest_known_hash, err
+                    );
+                    None
+                }
+            };
+        }
+        let txs = block
+            .as_ref()
+            .and_then(|(_, block)| block.get_transactions())
+            .map(|txs| txs.iter().cloned().collect::<Vec<TxAux>>())
+            .unwrap_or_default();
+
+        let output = utxo.extract_txin();
+        let mut counterparties = Vec::new();
+        let txid = match kind {
+            Kind::Credit => {
+                balance = (balance + utxo.credited_value)?;
+                if let Some(txaux) = txs
+                    .iter()
+                    .find(|txaux| txaux.tx.id() == utxo.transaction_id)
+                {
+                    for input in txaux.tx.inputs.iter() {
+                        credit_inputs.push((entries.len(), input.clone()));
+                    }
+                }
+                Some(utxo.transaction_id.clone())
+            }
+            Kind::Debit => {
+                balance = (balance - utxo.credited_value)?;
+                let spending = txs.iter().find(|txaux| {
+                    txaux
+                        .tx
+                        .inputs
+                        .iter()
+                        .any(|input| input.id == output.id && input.index == output.index)
+                });
+                spending.map(|txaux| {
+                    for txout in txaux.tx.outputs.iter() {
+                        push_counterparty(&mut counterparties, &own, book, &txout.address);
+                    }
+                    txaux.tx.id()
+                })
+            }
+        };
+
+        entries.push(Entry {
+            date: ptr.latest_block_date(),
+            slot: blockchain.absolute_slot(&ptr.latest_block_date()),
+            kind,
+            txid,
+            output,
+            address: utxo.credited_address,
+            amount: utxo.credited_value,
+            balance,
+            counterparties,
+        });
+    }
+
+    // the senders are the addresses of the outputs the crediting
+    // transactions spent
+    if !credit_inputs.is_empty() {
+        let tip = blockchain.load_tip().0.hash;
+        let spent = explorer::find_spent_outputs(
+            term,
+            blockchain,
+            tip,
+            credit_inputs.iter().map(|(_, input)| input),
+        )?;
+        for (index, input) in credit_inputs.iter() {
+            if let Some(txout) = spent.get(input) {
+                push_counterparty(
+                    &mut entries[*index].counterparties,
+                    &own,
+                    book,
+                    &txout.address,
+                );
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn push_counterparty(
+    counterparties: &mut Vec<Counterparty>,
+    own: &BTreeSet<String>,
+    book: Option<&AddressBook>,
+    address: &ExtendedAddr,
+) {
+    let counterparty = Counterparty {
+        address: address.to_string(),
+        label: book
+            .and_then(|book| book.label_of(address))
+            .map(|label| label.to_owned()),
+    };
+    if !own.contains(&counterparty.address) && !counterparties.contains(&counterparty) {
+        counterparties.push(counterparty);
+    }
+}
+
+/// the total credited and debited by the entries
+pub fn totals(entries: &[Entry]) -> coin::Result<(Coin, Coin)> {
+    let mut credits = Coin::zero();
+    let mut debits = Coin::zero();
+    for entry in entries {
+        match entry.kind {
+            Kind::Credit => credits = (credits + entry.amount)?,
+            Kind::Debit => debits = (debits + entry.amount)?,
+        }
+    }
+    Ok((credits, debits))
+}
+
+/// quote the CSV field if needed
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn join<'a, I: Iterator<Item = &'a str>>(items: I) -> String {
+    items.collect::<Vec<_>>().join(" ")
+}
+
+/// write the entries as CSV. The counterparties and their labels (`-` for
+/// the addresses without label) are separated by spaces, in the same order.
+pub fn write_csv<W: Write>(mut writer: W, entries: &[Entry]) -> ::std::io::Result<()> {
+    writeln!(
+        writer,
+        "date,slot,kind,txid,output,address,amount,balance,counterparties,counterparty-labels"
+    )?;
+    for entry in entries {
+        let fields = [
+            entry.date.to_string(),
+            entry.slot.to_string(),
+            entry.kind.to_string(),
+            entry
+                .txid
+                .as_ref()
+                .map(|txid| txid.to_string())
+                .unwrap_or_default(),
+            format!("{}.{}", entry.output.id, entry.output.index),
+            entry.address.to_string(),
+            u64::from(entry.amount).to_string(),
+            u64::from(entry.balance).to_string(),
+            join(entry.counterparties.iter().map(|c| c.address.as_str())),
+            join(
+                entry
+                    .counterparties
+                    .iter()
+                    .map(|c| c.label.as_ref().map(|l| l.as_str()).unwrap_or("-")),
+            ),
+        ];
+        let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(writer, "{}", line.join(","))?;
+    }
+    Ok(())
+}
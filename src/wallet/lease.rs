@@ -0,0 +1,308 @@
+//! UTxO leases
+//!
+//! a lease is a time-limited exclusive reservation of an unspent output of
+//! the wallet, granted to an owner (any identifier chosen by the caller).
+//! External coordinators running several instances of the CLI on the same
+//! wallet data lease the inputs they are about to spend, so the other
+//! instances do not select them concurrently.
+//!
+//! The leases are kept in the wallet directory; the file is locked while
+//! it is updated and the expired leases are dropped whenever it is read.
+//!
+//! The commands selecting the inputs of a staging transaction on their own
+//! (`transaction seal`, `wallet migrate`, the `input-select` step of `run`)
+//! lease them to the staging transaction, the owner being `staging_owner`
+//! of its identifier, for `STAGING_TTL`.
+//!
+
+use super::config;
+use super::error::{Error, Result};
+use super::Wallet;
+
+use cardano::tx::{TxId, TxoPointer};
+use serde_yaml;
+use storage_units::utils::{
+    lock::{self, Lock},
+    tmpfile::TmpFile,
+};
+use transaction::core::StagingId;
+
+use std::{
+    fmt, fs, io,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+static WALLET_LEASES_FILE: &'static str = "leases.yml";
+static WALLET_LEASES_LOCK: &'static str = "leases";
+
+/// how long to wait for another process to release the leases file
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how long the inputs selected for a staging transaction stay leased to it
+pub const STAGING_TTL: Duration = Duration::from_secs(3600);
+
+/// the owner of the leases of the inputs selected for the staging
+/// transaction
+pub fn staging_owner(id: &StagingId) -> String {
+    format!("staging-{}", id)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub txid: TxId,
+    pub index: u32,
+    pub owner: String,
+    pub expires_at: SystemTime,
+}
+impl Lease {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+
+    pub fn is_for(&self, txin: &TxoPointer) -> bool {
+        self.txid == txin.id && self.index == txin.index
+    }
+
+    /// time left before the lease expires
+    pub fn remaining(&self) -> Duration {
+        self.expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+}
+impl fmt::Display for Lease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{} leased by `{}'", self.txid, self.index, self.owner)
+    }
+}
+
+/// the active leases of a wallet, the file stays locked as long as this
+/// object lives.
+pub struct Leases {
+    _lock: Lock,
+    dir: PathBuf,
+    leases: Vec<Lease>,
+}
+impl Leases {
+    /// lock the leases file of the wallet and read the active leases,
+    /// waiting up to `LOCK_TIMEOUT` if another process is updating them.
+    pub fn open(wallet: &Wallet) -> Result<Self> {
+        let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+        let path = dir.join(WALLET_LEASES_FILE);
+
+        let start = Instant::now();
+        let lock = loop {
+            match Lock::lock(dir.join(WALLET_LEASES_LOCK)) {
+                Ok(lock) => break lock,
+                Err(lock::Error::AlreadyLocked(_, pid)) => {
+                    if start.elapsed() >= LOCK_TIMEOUT {
+                        return Err(Error::LeasesAlreadyLocked(pid));
+                    }
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(err) => return Err(Error::LeasesLockFailed(err)),
+            }
+        };
+
+        let leases: Vec<Lease> = match fs::File::open(&path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+            Ok(file) => serde_yaml::from_reader(file)
+                .map_err(|e| Error::LeasesReadFailed(path.clone(), e))?,
+        };
+
+        Ok(Leases {
+            _lock: lock,
+            dir,
+            leases: leases
+                .into_iter()
+                .filter(|lease| !lease.is_expired())
+                .collect(),
+        })
+    }
+
+    /// write the leases to a temporary file renamed over the leases file:
+    /// an interrupted write does not lose the other leases
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join(WALLET_LEASES_FILE);
+        let mut tmpfile = TmpFile::create(self.dir.clone())?;
+        serde_yaml::to_writer(&mut tmpfile, &self.leases)
+            .map_err(|e| Error::LeasesWriteFailed(path.clone(), e))?;
+        tmpfile.render_permanent(&path)?;
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Lease> {
+        self.leases.iter()
+    }
+
+    pub fn get(&self, txin: &TxoPointer) -> Option<&Lease> {
+        self.leases.iter().find(|lease| lease.is_for(txin))
+    }
+
+    /// whether the output is leased to another owner than the given one
+    pub fn is_leased_to_other(&self, txin: &TxoPointer, owner: Option<&str>) -> bool {
+        match self.get(txin) {
+            None => false,
+            Some(lease) => Some(lease.owner.as_str()) != owner,
+        }
+    }
+
+    /// lease the output to the owner for the given time. The owner of an
+    /// active lease can renew it, it cannot be granted to another owner
+    /// before it expires or is released.
+    pub fn grant(&mut self, txin: TxoPointer, owner: String, ttl: Duration) -> Result<Lease> {
+        let expires_at = SystemTime::now()
+            .checked_add(ttl)
+            .ok_or(Error::LeaseTtlTooLong(ttl))?;
+        if let Some(lease) = self.get(&txin) {
+            if lease.owner != owner {
+                return Err(Error::UtxoAlreadyLeased(lease.clone()));
+            }
+        }
+        self.leases.retain(|lease| !lease.is_for(&txin));
+
+        let lease = Lease {
+            txid: txin.id,
+            index: txin.index,
+            owner,
+            expires_at,
+        };
+        self.leases.push(lease.clone());
+        self.save()?;
+        Ok(lease)
+    }
+
+    /// release the lease before it expires, only its owner can release it
+    pub fn release(&mut self, txin: &TxoPointer, owner: &str) -> Result<Lease> {
+        let lease = match self.get(txin) {
+            None => return Err(Error::UtxoNotLeased(txin.id.clone(), txin.index)),
+            Some(lease) => lease.clone(),
+        };
+        if lease.owner != owner {
+            return Err(Error::UtxoAlreadyLeased(lease));
+        }
+        self.leases.retain(|lease| !lease.is_for(txin));
+        self.save()?;
+        Ok(lease)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::hash::Blake2b256;
+    use std::process;
+    use wallet::{config::Config, WalletName};
+
+    fn temp_wallet(name: &str) -> Wallet {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-lease-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root_dir);
+        let name = WalletName::new("wallet".to_owned()).unwrap();
+        let wallet = Wallet::new(root_dir, name, Config::default(), Vec::new(), None);
+        wallet.save().unwrap();
+        wallet
+    }
+
+    fn txin(seed: u8) -> TxoPointer {
+        TxoPointer {
+            id: Blake2b256::new(&[seed]),
+            index: 0,
+        }
+    }
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn grant() {
+        let wallet = temp_wallet("grant");
+        {
+            let mut leases = Leases::open(&wallet).unwrap();
+            let lease = leases.grant(txin(1), "a".to_owned(), TTL).unwrap();
+            assert_eq!(lease.owner, "a");
+            assert!(lease.remaining() > Duration::from_secs(59));
+        }
+
+        // the lease is kept, and refused to another owner
+        let mut leases = Leases::open(&wallet).unwrap();
+        assert_eq!(leases.iter().count(), 1);
+        assert!(leases.is_leased_to_other(&txin(1), Some("b")));
+        assert!(leases.is_leased_to_other(&txin(1), None));
+        assert!(!leases.is_leased_to_other(&txin(1), Some("a")));
+        assert!(!leases.is_leased_to_other(&txin(2), Some("b")));
+        match leases.grant(txin(1), "b".to_owned(), TTL) {
+            Err(Error::UtxoAlreadyLeased(ref lease)) if lease.owner == "a" => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        ::std::mem::drop(leases);
+
+        fs::remove_dir_all(&wallet.root_dir).unwrap();
+    }
+
+    #[test]
+    fn expiry() {
+        let wallet = temp_wallet("expiry");
+        {
+            let mut leases = Leases::open(&wallet).unwrap();
+            let lease = leases
+                .grant(txin(1), "a".to_owned(), Duration::from_secs(0))
+                .unwrap();
+            assert!(lease.is_expired());
+        }
+
+        // the expired lease is dropped when the leases are read
+        let mut leases = Leases::open(&wallet).unwrap();
+        assert!(leases.get(&txin(1)).is_none());
+        leases.grant(txin(1), "b".to_owned(), TTL).unwrap();
+        ::std::mem::drop(leases);
+
+        fs::remove_dir_all(&wallet.root_dir).unwrap();
+    }
+
+    #[test]
+    fn renew() {
+        let wallet = temp_wallet("renew");
+        let mut leases = Leases::open(&wallet).unwrap();
+        let lease = leases.grant(txin(1), "a".to_owned(), TTL).unwrap();
+        let renewed = leases
+            .grant(txin(1), "a".to_owned(), Duration::from_secs(3600))
+            .unwrap();
+        assert!(renewed.expires_at > lease.expires_at);
+        assert_eq!(leases.iter().count(), 1);
+        assert_eq!(leases.get(&txin(1)).unwrap().expires_at, renewed.expires_at);
+        ::std::mem::drop(leases);
+
+        fs::remove_dir_all(&wallet.root_dir).unwrap();
+    }
+
+    #[test]
+    fn release() {
+        let wallet = temp_wallet("release");
+        let mut leases = Leases::open(&wallet).unwrap();
+        leases.grant(txin(1), "a".to_owned(), TTL).unwrap();
+
+        // only the owner releases the lease
+        match leases.release(&txin(1), "b") {
+            Err(Error::UtxoAlreadyLeased(ref lease)) if lease.owner == "a" => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(leases.get(&txin(1)).is_some());
+
+        leases.release(&txin(1), "a").unwrap();
+        assert!(leases.get(&txin(1)).is_none());
+        match leases.release(&txin(1), "a") {
+            Err(Error::UtxoNotLeased(_, 0)) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        ::std::mem::drop(leases);
+
+        let leases = Leases::open(&wallet).unwrap();
+        assert_eq!(leases.iter().count(), 0);
+        ::std::mem::drop(leases);
+
+        fs::remove_dir_all(&wallet.root_dir).unwrap();
+    }
+}
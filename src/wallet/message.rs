@@ -0,0 +1,211 @@
+//! message signing
+//!
+//! proves the control of an address of the wallet by signing a message
+//! with the private key of the address. The signature carries the public
+//! key of the address, anyone knowing the address and the message can
+//! verify it.
+//!
+//! The message is prefixed with `MESSAGE_PREFIX` before being signed: the
+//! signature of a message cannot be used as the witness of a transaction.
+//!
+
+use cardano::{
+    address::{ExtendedAddr, SpendingData},
+    hdwallet::{self, Signature, XPrv, XPub, SIGNATURE_SIZE, XPUB_SIZE},
+    util::hex,
+};
+
+use std::{error, fmt, str::FromStr};
+
+const MESSAGE_PREFIX: &'static [u8] = b"Cardano Signed Message:\n";
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidEncoding,
+    PublicKeyNotOfAddress,
+    InvalidSignature,
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidEncoding => write!(f, "Invalid message signature encoding"),
+            Error::PublicKeyNotOfAddress => {
+                write!(f, "The message was not signed with the key of the address")
+            }
+            Error::InvalidSignature => write!(f, "The signature does not match the message"),
+        }
+    }
+}
+impl error::Error for Error {}
+
+/// marker type of the signatures of messages
+#[derive(Debug)]
+pub enum SignedMessage {}
+
+fn prefixed(message: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MESSAGE_PREFIX.len() + message.len());
+    bytes.extend_from_slice(MESSAGE_PREFIX);
+    bytes.extend_from_slice(message);
+    bytes
+}
+
+/// the signature of a message along with the public key it was signed
+/// with, written as the hexadecimal public key followed by the hexadecimal
+/// signature
+#[derive(Debug)]
+pub struct MessageSignature {
+    pub public_key: XPub,
+    pub signature: Signature<SignedMessage>,
+}
+impl MessageSignature {
+    pub fn sign(xprv: &XPrv, message: &[u8]) -> Self {
+        MessageSignature {
+            public_key: xprv.public(),
+            signature: xprv.sign(&prefixed(message)),
+        }
+    }
+
+    /// check the message was signed with the private key of the address
+    pub fn verify(&self, address: &ExtendedAddr, message: &[u8]) -> Result<(), Error> {
+        // the address is the hash of its type, public key and attributes
+        let expected = ExtendedAddr::new(
+            address.addr_type,
+            SpendingData::PubKeyASD(self.public_key.clone()),
+            address.attributes.clone(),
+        );
+        if &expected != address {
+            return Err(Error::PublicKeyNotOfAddress);
+        }
+        if !self.public_key.verify(&prefixed(message), &self.signature) {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            hex::encode(self.public_key.as_ref()),
+            hex::encode(self.signature.as_ref())
+        )
+    }
+}
+impl FromStr for MessageSignature {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s.trim()).map_err(|_| Error::InvalidEncoding)?;
+        if bytes.len() != XPUB_SIZE + SIGNATURE_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let public_key =
+            XPub::from_slice(&bytes[..XPUB_SIZE]).map_err(|_| Error::InvalidEncoding)?;
+        let signature = Signature::from_slice(&bytes[XPUB_SIZE..])
+            .map_err(|_: hdwallet::Error| Error::InvalidEncoding)?;
+        Ok(MessageSignature {
+            public_key,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{config::ProtocolMagic, hdwallet::DerivationScheme, wallet::bip44};
+    use wallet::state::lookup::sequentialindex::SequentialBip44Lookup;
+
+    const MESSAGE: &'static [u8] = b"I own this address";
+
+    /// the private key and the address of the given index
+    fn key_and_address(index: u32) -> (XPrv, ExtendedAddr) {
+        let wallet = bip44::Wallet::from_root_key(
+            XPrv::normalize_bytes([0x42; hdwallet::XPRV_SIZE]),
+            DerivationScheme::V2,
+        );
+        let lookup = SequentialBip44Lookup::new(wallet, ProtocolMagic::from(764824073).into());
+        let addressing = bip44::Addressing {
+            account: bip44::bip44::Account::new(0).unwrap(),
+            change: 0,
+            index: bip44::Index::new(index).unwrap(),
+        };
+        let xprv: XPrv = (*lookup.get_private_key(&addressing)).clone();
+        (xprv, lookup.get_address(&addressing))
+    }
+
+    #[test]
+    fn signed_message_is_verified() {
+        let (xprv, address) = key_and_address(0);
+        let signature = MessageSignature::sign(&xprv, MESSAGE);
+        signature.verify(&address, MESSAGE).unwrap();
+
+        // as printed and read back
+        let signature: MessageSignature = signature.to_string().parse().unwrap();
+        signature.verify(&address, MESSAGE).unwrap();
+    }
+
+    #[test]
+    fn tampered_message_is_rejected() {
+        let (xprv, address) = key_and_address(0);
+        let signature = MessageSignature::sign(&xprv, MESSAGE);
+        match signature.verify(&address, b"I own this address!") {
+            Err(Error::InvalidSignature) => {}
+            result => panic!("tampered message verified: {:?}", result),
+        }
+        // a transaction witness is not the signature of its bytes
+        let unprefixed = MessageSignature {
+            public_key: xprv.public(),
+            signature: xprv.sign(MESSAGE),
+        };
+        match unprefixed.verify(&address, MESSAGE) {
+            Err(Error::InvalidSignature) => {}
+            result => panic!("unprefixed signature verified: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let (xprv, address) = key_and_address(0);
+        let mut encoded = MessageSignature::sign(&xprv, MESSAGE).to_string();
+        // flip the last hexadecimal digit of the signature
+        let last = if encoded.ends_with('0') { "1" } else { "0" };
+        encoded.pop();
+        encoded.push_str(last);
+        let signature: MessageSignature = encoded.parse().unwrap();
+        match signature.verify(&address, MESSAGE) {
+            Err(Error::InvalidSignature) => {}
+            result => panic!("tampered signature verified: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn signature_of_another_address_is_rejected() {
+        let (xprv, _) = key_and_address(0);
+        let (_, other) = key_and_address(1);
+        let signature = MessageSignature::sign(&xprv, MESSAGE);
+        match signature.verify(&other, MESSAGE) {
+            Err(Error::PublicKeyNotOfAddress) => {}
+            result => panic!("signature of another address verified: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_encodings_are_rejected() {
+        let (xprv, _) = key_and_address(0);
+        let encoded = MessageSignature::sign(&xprv, MESSAGE).to_string();
+        let longer = format!("{}00", encoded);
+        let invalids: [&str; 4] = [
+            "",
+            "not hexadecimal",
+            &encoded[..encoded.len() - 2],
+            &longer,
+        ];
+        for invalid in invalids.iter() {
+            match invalid.parse::<MessageSignature>() {
+                Err(Error::InvalidEncoding) => {}
+                result => panic!("`{}' parsed: {:?}", invalid, result),
+            }
+        }
+    }
+}
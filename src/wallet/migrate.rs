@@ -0,0 +1,283 @@
+//! sweeping a random index wallet into a sequential one
+//!
+//! The random index (Daedalus) wallets are only recognized with their root
+//! key, the derivation path being encrypted in each address. `wallet
+//! migrate` moves all the funds of such a wallet to an address of a BIP44
+//! (Icarus) wallet: the unspent outputs are split in batches, each one
+//! fitting in a transaction of the maximum size, and every batch is staged
+//! as a finalized transaction paying its inputs, less the fee, to the new
+//! wallet. The staging transactions are then signed and sent as any other.
+//!
+//! An unspent output worth less than what it adds to the fee is left out:
+//! spending it would lose money.
+
+use cardano::{
+    address::ExtendedAddr,
+    coin::Coin,
+    fee::{FeeAlgorithm, LinearFee},
+    tx::{self, Tx, TxInWitness, TxOut},
+};
+use transaction::core::{Change, Input, Operation, Transaction};
+
+use std::iter;
+
+use super::error::{Error, Result};
+
+/// one sweep transaction
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub inputs: Vec<Input>,
+    /// the value of the inputs
+    pub value: Coin,
+    /// the fee of the fee policy
+    pub fee: Coin,
+    /// the size of the signed transaction, in bytes
+    pub size: u64,
+}
+impl Batch {
+    /// what the new wallet receives
+    pub fn received(&self) -> Coin {
+        (self.value - self.fee).expect("the inputs of a batch cover its fee")
+    }
+
+    /// the operations of the staging transaction of the batch
+    pub fn operations(&self, destination: &ExtendedAddr) -> Vec<Operation> {
+        self.inputs
+            .iter()
+            .cloned()
+            .map(Operation::AddInput)
+            .chain(iter::once(Operation::AddChange(Change::from(
+                destination.clone(),
+            ))))
+            .chain(iter::once(Operation::Finalize))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub batches: Vec<Batch>,
+    /// the unspent outputs worth less than the fee to spend them
+    pub left_out: Vec<Input>,
+}
+impl Plan {
+    pub fn total_value(&self) -> Result<Coin> {
+        Ok(::cardano::coin::sum_coins(
+            self.batches.iter().map(|batch| batch.value),
+        )?)
+    }
+
+    pub fn total_fee(&self) -> Result<Coin> {
+        Ok(::cardano::coin::sum_coins(
+            self.batches.iter().map(|batch| batch.fee),
+        )?)
+    }
+
+    pub fn left_out_value(&self) -> Result<Coin> {
+        Ok(::cardano::coin::sum_coins(
+            self.left_out.iter().map(|input| input.expected_value),
+        )?)
+    }
+}
+
+/// split the unspent outputs in batches of transactions of at most
+/// `max_tx_size` bytes paying the `destination`, the largest outputs first
+pub fn plan(
+    mut utxos: Vec<Input>,
+    destination: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+    max_tx_size: u64,
+) -> Result<Plan> {
+    utxos.sort_by_key(|input| ::std::cmp::Reverse(u64::from(input.expected_value)));
+
+    let (base_fee, _) = estimate(&[], destination, fee_algorithm)?;
+    let mut batches = Vec::new();
+    let mut left_out = Vec::new();
+    let mut current: Vec<Input> = Vec::new();
+    for input in utxos {
+        let (alone_fee, _) = estimate(&[input.clone()], destination, fee_algorithm)?;
+        if u64::from(input.expected_value) <= alone_fee - base_fee {
+            left_out.push(input);
+            continue;
+        }
+        current.push(input);
+        let (_, size) = estimate(&current, destination, fee_algorithm)?;
+        if size > max_tx_size && current.len() > 1 {
+            let input = current.pop().expect("the batch has more than one input");
+            close(
+                current,
+                destination,
+                fee_algorithm,
+                &mut batches,
+                &mut left_out,
+            )?;
+            current = vec![input];
+        }
+    }
+    if !current.is_empty() {
+        close(
+            current,
+            destination,
+            fee_algorithm,
+            &mut batches,
+            &mut left_out,
+        )?;
+    }
+
+    Ok(Plan { batches, left_out })
+}
+
+/// the batch of the inputs, built as the staging transaction will be, or
+/// the inputs left out if they do not cover the fee
+fn close(
+    inputs: Vec<Input>,
+    destination: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+    batches: &mut Vec<Batch>,
+    left_out: &mut Vec<Input>,
+) -> Result<()> {
+    let value: u64 = inputs
+        .iter()
+        .map(|input| u64::from(input.expected_value))
+        .sum();
+    let (fee, _) = estimate(&inputs, destination, fee_algorithm)?;
+    if value <= fee {
+        left_out.extend(inputs);
+        return Ok(());
+    }
+
+    let mut batch = Batch {
+        inputs,
+        value: Coin::new(value)?,
+        fee: Coin::zero(),
+        size: 0,
+    };
+    let mut transaction = Transaction::new();
+    for operation in batch.operations(destination) {
+        transaction
+            .update_with(operation)
+            .map_err(Error::MigrationInvalidTransaction)?;
+    }
+    let (_, changes) = transaction
+        .mk_txbuilder(fee_algorithm)
+        .map_err(Error::MigrationInvalidTransaction)?;
+    let received = changes
+        .first()
+        .map(|change| change.value)
+        .unwrap_or_else(Coin::zero);
+    let (_, size) = estimate_with(&batch.inputs, destination, received, fee_algorithm)?;
+    batch.fee = (batch.value - received)?;
+    batch.size = size;
+    batches.push(batch);
+    Ok(())
+}
+
+/// the fee and the size of the signed transaction spending the inputs to
+/// the destination, their whole value overestimating the output
+fn estimate(
+    inputs: &[Input],
+    destination: &ExtendedAddr,
+    fee_algorithm: &LinearFee,
+) -> Result<(u64, u64)> {
+    let value: u64 = inputs
+        .iter()
+        .map(|input| u64::from(input.expected_value))
+        .sum();
+    estimate_with(inputs, destination, Coin::new(value)?, fee_algorithm)
+}
+
+fn estimate_with(
+    inputs: &[Input],
+    destination: &ExtendedAddr,
+    value: Coin,
+    fee_algorithm: &LinearFee,
+) -> Result<(u64, u64)> {
+    let mut tx = Tx::new();
+    tx.inputs = inputs.iter().map(|input| input.extract_txin()).collect();
+    tx.outputs = vec![TxOut {
+        address: destination.clone(),
+        value,
+    }];
+    let fake_witnesses: Vec<TxInWitness> = iter::repeat(TxInWitness::fake())
+        .take(inputs.len())
+        .collect();
+    let fee = fee_algorithm
+        .calculate_for_txaux_component(&tx, &fake_witnesses)
+        .map_err(Error::MigrationInvalidFee)?;
+    let size = tx::txaux_serialize_size(&tx, &fake_witnesses) as u64;
+    Ok((u64::from(fee.to_coin()), size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use utils::testing::{address, input};
+
+    #[test]
+    fn one_batch() {
+        let utxos = vec![input(1, 1_000_000), input(2, 3_000_000)];
+        let plan = plan(utxos, &address(1), &LinearFee::default(), 4096).unwrap();
+        assert!(plan.left_out.is_empty());
+        assert_eq!(plan.batches.len(), 1);
+
+        let batch = &plan.batches[0];
+        assert_eq!(batch.value, Coin::new(4_000_000).unwrap());
+        // the largest first
+        assert_eq!(
+            batch.inputs[0].expected_value,
+            Coin::new(3_000_000).unwrap()
+        );
+        assert!(batch.fee > Coin::new(155_381).unwrap());
+        assert_eq!((batch.received() + batch.fee).unwrap(), batch.value);
+        assert!(batch.size <= 4096);
+        assert_eq!(plan.total_value().unwrap(), batch.value);
+        assert_eq!(plan.total_fee().unwrap(), batch.fee);
+    }
+
+    #[test]
+    fn batches_of_the_maximum_size() {
+        let utxos: Vec<_> = (1..=20).map(|seed| input(seed, 2_000_000)).collect();
+        let plan = plan(utxos, &address(1), &LinearFee::default(), 1000).unwrap();
+        assert!(plan.left_out.is_empty());
+        assert!(plan.batches.len() > 1, "{} batches", plan.batches.len());
+        for batch in plan.batches.iter() {
+            assert!(batch.size <= 1000, "batch of {} bytes", batch.size);
+        }
+        let inputs: usize = plan.batches.iter().map(|batch| batch.inputs.len()).sum();
+        assert_eq!(inputs, 20);
+        assert_eq!(plan.total_value().unwrap(), Coin::new(40_000_000).unwrap());
+    }
+
+    #[test]
+    fn outputs_worth_less_than_their_fee() {
+        let utxos = vec![input(1, 5_000_000), input(2, 100), input(3, 1)];
+        let plan = plan(utxos, &address(1), &LinearFee::default(), 4096).unwrap();
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(plan.batches[0].inputs.len(), 1);
+        assert_eq!(plan.left_out.len(), 2);
+        assert_eq!(plan.left_out_value().unwrap(), Coin::new(101).unwrap());
+    }
+
+    #[test]
+    fn nothing_covers_the_fee() {
+        // each output covers what it adds to the fee, not the base fee
+        let utxos = vec![input(1, 100_000)];
+        let plan = plan(utxos, &address(1), &LinearFee::default(), 4096).unwrap();
+        assert!(plan.batches.is_empty());
+        assert_eq!(plan.left_out.len(), 1);
+    }
+
+    #[test]
+    fn operations_of_a_batch() {
+        let utxos = vec![input(1, 1_000_000), input(2, 3_000_000)];
+        let plan = plan(utxos, &address(1), &LinearFee::default(), 4096).unwrap();
+        let operations = plan.batches[0].operations(&address(1));
+        assert_eq!(operations.len(), 4);
+        match (&operations[2], &operations[3]) {
+            (Operation::AddChange(change), Operation::Finalize) => {
+                assert_eq!(change.address, address(1))
+            }
+            other => panic!("unexpected operations: {:?}", other),
+        }
+    }
+}
@@ -1,15 +1,28 @@
+pub mod alarm;
+pub mod backup;
+#[cfg(feature = "cli")]
 pub mod commands;
 mod config;
+pub mod digest;
 mod error;
+#[cfg(feature = "cli")]
+pub mod history;
+pub mod lease;
+pub mod message;
+pub mod migrate;
+pub mod portfolio;
+pub mod rescan;
 mod result;
+pub mod snapshot;
 pub mod state;
 pub mod utils;
+pub mod utxo_index;
 
-pub use self::config::{Config, HDWalletModel};
+pub use self::config::{Config, HDWalletModel, Setting};
 pub use self::error::Error;
 pub use self::result::Result;
 
-use self::config::decrypt_primary_key;
+use self::config::{decrypt_primary_key, encrypt_primary_key};
 
 use self::state::log::{LogLock, LogWriter};
 
@@ -21,7 +34,7 @@ use std::{
 };
 
 use cardano::{
-    hdwallet::{XPub, XPUB_SIZE},
+    hdwallet::{self, XPub, XPUB_SIZE},
     wallet,
 };
 use serde_yaml;
@@ -37,7 +50,7 @@ static WALLET_PUBLIC_KEY: &'static str = "wallet.pub";
 ///
 /// A valid wallet name need to be unicode compliant, and
 /// not contains dot, nor slash. TODO: validate the rules
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct WalletName(String);
 
 impl WalletName {
@@ -71,6 +84,7 @@ impl fmt::Display for WalletName {
 ///
 /// simple object to provide small atomic actions that can be composed
 /// in the commands.
+#[derive(Clone)]
 pub struct Wallet {
     /// by design we do not want to decryp the wallet everytime
     ///
@@ -113,6 +127,24 @@ impl Wallet {
         }
     }
 
+    /// create a new wallet of the root private key, encrypted with the
+    /// spending password (the random index wallets keep the root public key
+    /// too, to recognize their addresses without the password)
+    pub fn from_root_key<P: AsRef<Path>>(
+        root_dir: P,
+        name: WalletName,
+        config: Config,
+        xprv: &hdwallet::XPrv,
+        password: &Password,
+    ) -> Self {
+        let public_key = match config.hdwallet_model {
+            HDWalletModel::BIP44 => None,
+            HDWalletModel::RandomIndex2Levels => Some(xprv.public()),
+        };
+        let encrypted_key = encrypt_primary_key(password, xprv);
+        Self::new(root_dir, name, config, encrypted_key, public_key)
+    }
+
     pub fn destroy(self) -> ::std::io::Result<()> {
         let dir = config::directory(self.root_dir.clone(), &self.name.0);
         ::std::fs::remove_dir_all(dir)
@@ -213,6 +245,30 @@ impl Wallet {
         Ok(lock.delete_wallet_log()?)
     }
 
+    /// encrypt the root private key with the new spending password instead
+    /// of the old one. Only the key file is replaced (atomically), the rest
+    /// of the wallet is left untouched.
+    ///
+    /// # Error
+    ///
+    /// This function may fail if the old password is invalid.
+    ///
+    pub fn change_password(&mut self, old: &Password, new: &Password) -> Result<()> {
+        let xprv = decrypt_primary_key(old, &self.encrypted_key)?;
+        let encrypted_key = encrypt_primary_key(new, &xprv);
+
+        let dir = config::directory(&self.root_dir, &self.name.as_dirname());
+        let save = || -> ::std::io::Result<()> {
+            let mut tmpfile = TmpFile::create(dir.clone())?;
+            tmpfile.write_all(&encrypted_key)?;
+            tmpfile.render_permanent(&dir.join(WALLET_PRIMARY_KEY))
+        };
+        save().map_err(Error::WalletSaveFailed)?;
+
+        self.encrypted_key = encrypted_key;
+        Ok(())
+    }
+
     /// convenient function to reconstruct a BIP44 wallet from the encrypted key and password
     ///
     /// # Error
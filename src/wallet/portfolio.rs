@@ -0,0 +1,219 @@
+//! portfolio of all the wallets
+//!
+//! the balance of every wallet of the root directory, read from its cached
+//! state (the snapshot of its state, rebuilt from its log if stale), with
+//! the funds the staging and pending transactions are spending and the last
+//! time the wallet received or spent funds (kept in the snapshot as well,
+//! so the log is only read when the snapshot is stale). Nothing is downloaded: `wallet
+//! sync` brings the wallets up to date.
+//!
+
+use super::error::Result;
+use super::state::lookup;
+use super::utils::{create_wallet_state_and_last_activity, load_attached_blockchain};
+use super::{Wallet, Wallets};
+
+use blockchain::Blockchain;
+use cardano::{coin::Coin, tx::TxoPointer};
+use transaction::core::{
+    config as transaction_config, PendingTransaction, StagingId, StagingTransaction,
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+#[derive(Debug, Serialize)]
+pub struct WalletSummary {
+    pub wallet: String,
+    pub blockchain: Option<String>,
+    /// in lovelace
+    pub balance: u64,
+    pub utxos: usize,
+    /// the funds of the wallet the staging transactions spend, in lovelace
+    pub staged: u64,
+    /// the funds of the wallet the transactions sent but not yet seen in
+    /// the local blockchain spend, in lovelace
+    pub pending: u64,
+    /// the block the wallet is synced to
+    pub synced_to: Option<String>,
+    /// the block of the last credit or debit of the wallet
+    pub last_activity: Option<String>,
+    /// the time of the block of the last activity
+    pub last_activity_time: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Portfolio {
+    pub wallets: Vec<WalletSummary>,
+    /// the total balance of the wallets of each blockchain, in lovelace
+    pub totals: BTreeMap<String, u64>,
+}
+
+/// the outputs spent by the staging transactions
+fn staged_inputs(root_dir: &Path) -> BTreeSet<TxoPointer> {
+    let mut inputs = BTreeSet::new();
+    let dir = transaction_config::transaction_directory(root_dir.to_path_buf());
+    let entries = match fs::read_dir(dir) {
+        Err(_) => return inputs,
+        Ok(entries) => entries,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let id = match name.parse::<StagingId>() {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        match StagingTransaction::read_from_file(root_dir.to_path_buf(), id) {
+            Ok(staging) => {
+                inputs.extend(
+                    staging
+                        .transaction()
+                        .inputs()
+                        .iter()
+                        .map(|input| input.extract_txin()),
+                );
+            }
+            Err(err) => warn!("cannot read the staging transaction {}: {:?}", name, err),
+        }
+    }
+    inputs
+}
+
+/// the outputs spent by the pending transactions of each blockchain
+fn pending_inputs(root_dir: &Path) -> BTreeMap<String, BTreeSet<TxoPointer>> {
+    let mut inputs: BTreeMap<String, BTreeSet<TxoPointer>> = BTreeMap::new();
+    let pendings = PendingTransaction::list(root_dir.to_path_buf()).unwrap_or_else(|err| {
+        warn!("cannot read the pending transactions journal: {}", err);
+        Vec::new()
+    });
+    for pending in pendings {
+        match pending.txaux() {
+            Ok(txaux) => inputs
+                .entry(pending.blockchain.clone())
+                .or_default()
+                .extend(txaux.tx.inputs.iter().cloned()),
+            Err(err) => warn!(
+                "cannot decode the pending transaction {}: {}",
+                pending.txid, err
+            ),
+        }
+    }
+    inputs
+}
+
+fn summarize(
+    wallet: &Wallet,
+    root_dir: &Path,
+    blockchain: Option<&Blockchain>,
+    staged: &BTreeSet<TxoPointer>,
+    pending: Option<&BTreeSet<TxoPointer>>,
+) -> Result<WalletSummary> {
+    let mut summary = WalletSummary {
+        wallet: wallet.name.to_string(),
+        blockchain: wallet.config.attached_blockchain.clone(),
+        balance: 0,
+        utxos: 0,
+        staged: 0,
+        pending: 0,
+        synced_to: None,
+        last_activity: None,
+        last_activity_time: None,
+    };
+    if wallet.config.attached_blockchain.is_none() {
+        return Ok(summary);
+    }
+
+    let (state, last_activity) =
+        create_wallet_state_and_last_activity(wallet, root_dir, lookup::accum::Accum::default())?;
+    let mut staged_total = Coin::zero();
+    let mut pending_total = Coin::zero();
+    for (txin, utxo) in state.utxos.iter() {
+        if staged.contains(txin) {
+            staged_total = (staged_total + utxo.credited_value)?;
+        }
+        if pending
+            .map(|pending| pending.contains(txin))
+            .unwrap_or(false)
+        {
+            pending_total = (pending_total + utxo.credited_value)?;
+        }
+    }
+    summary.balance = state.total()?.into();
+    summary.utxos = state.utxos.len();
+    summary.staged = staged_total.into();
+    summary.pending = pending_total.into();
+    summary.synced_to = state
+        .ptr()
+        .latest_addr
+        .as_ref()
+        .map(|date| date.to_string());
+
+    if let Some(date) = last_activity {
+        summary.last_activity = Some(date.to_string());
+        if let Some(blockchain) = blockchain {
+            match blockchain.load_genesis_data() {
+                Ok(genesis) => {
+                    let time = blockchain.slot_start_time(&genesis, &date);
+                    summary.last_activity_time =
+                        Some(format!("{}", ::humantime::format_rfc3339_seconds(time)));
+                }
+                Err(err) => debug!(
+                    "cannot load the genesis data of wallet {}'s blockchain: {}",
+                    wallet.name, err
+                ),
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// the summary of every wallet of the root directory. The wallets whose
+/// state cannot be read are left out (and logged).
+pub fn portfolio(root_dir: &Path) -> Result<Portfolio> {
+    let wallets = Wallets::load(root_dir)?;
+    let staged = staged_inputs(root_dir);
+    let pending = pending_inputs(root_dir);
+
+    let mut blockchains: BTreeMap<String, Option<Blockchain>> = BTreeMap::new();
+    let mut summaries = Vec::new();
+    let mut totals: BTreeMap<String, Coin> = BTreeMap::new();
+    for (name, wallet) in wallets {
+        let blockchain = match wallet.config.attached_blockchain {
+            None => None,
+            Some(ref blockchain_name) => blockchains
+                .entry(blockchain_name.clone())
+                .or_insert_with(|| load_attached_blockchain(root_dir, &wallet.config).ok())
+                .as_ref(),
+        };
+        let pending = wallet
+            .config
+            .attached_blockchain
+            .as_ref()
+            .and_then(|blockchain_name| pending.get(blockchain_name));
+
+        match summarize(&wallet, root_dir, blockchain, &staged, pending) {
+            Ok(summary) => {
+                if let Some(ref blockchain_name) = summary.blockchain {
+                    let total = totals
+                        .entry(blockchain_name.clone())
+                        .or_insert_with(Coin::zero);
+                    *total = (*total + Coin::new(summary.balance)?)?;
+                }
+                summaries.push(summary);
+            }
+            Err(err) => warn!("cannot read the state of wallet {}: {}", name, err),
+        }
+    }
+
+    let totals = totals
+        .into_iter()
+        .map(|(name, total)| (name, u64::from(total)))
+        .collect();
+    Ok(Portfolio {
+        wallets: summaries,
+        totals,
+    })
+}
@@ -0,0 +1,262 @@
+//! wallet rescan
+//!
+//! a rescan drops the entries of the wallet log from a given slot onwards
+//! and syncs the wallet again from the blocks stored locally. The slot the
+//! rescan started from is kept in the wallet directory until it completes:
+//! an interrupted rescan is resumed from the last block recorded in the
+//! wallet log (the sync adds a checkpoint to the log at every new epoch)
+//! instead of being started over.
+//!
+
+use super::config;
+use super::error::{Error, Result};
+use super::state::{log, lookup};
+use super::utils::lock_wallet_log;
+use super::Wallet;
+
+use blockchain::Blockchain;
+use cardano::block::{BlockDate, EpochId};
+
+use std::{fs, io, path::PathBuf};
+
+static WALLET_RESCAN_FILE: &'static str = "rescan";
+
+fn rescan_file(wallet: &Wallet) -> PathBuf {
+    config::directory(&wallet.root_dir, &wallet.name.as_dirname()).join(WALLET_RESCAN_FILE)
+}
+
+/// the slot the interrupted rescan of the wallet started from, if any
+pub fn interrupted(wallet: &Wallet) -> Result<Option<u64>> {
+    match fs::read_to_string(rescan_file(wallet)) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+        Ok(content) => match content.trim().parse() {
+            Ok(slot) => Ok(Some(slot)),
+            Err(_) => {
+                warn!("ignoring the invalid rescan file of wallet {}", wallet.name);
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// record that a rescan from the given slot started
+pub fn start(wallet: &Wallet, from_slot: u64) -> Result<()> {
+    fs::write(rescan_file(wallet), format!("{}\n", from_slot))?;
+    Ok(())
+}
+
+/// record that the rescan completed
+pub fn finish(wallet: &Wallet) -> Result<()> {
+    match fs::remove_file(rescan_file(wallet)) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// keep the first entries of the wallet log, as many as `kept` returns
+/// given all the entries, and drop the others. The log stays locked from
+/// the read to the rewrite, which replaces the log atomically.
+///
+/// Returns the number of entries dropped.
+fn truncate_log<F>(wallet: &Wallet, blockchain: &Blockchain, kept: F) -> Result<usize>
+where
+    F: FnOnce(&[log::Log<lookup::Address>]) -> usize,
+{
+    let pruned_until = blockchain.load_pruned_until()?;
+    truncate_locked(lock_wallet_log(wallet)?, pruned_until, kept)
+}
+
+fn truncate_locked<F>(
+    log_lock: log::LogLock,
+    pruned_until: Option<EpochId>,
+    kept: F,
+) -> Result<usize>
+where
+    F: FnOnce(&[log::Log<lookup::Address>]) -> usize,
+{
+    let mut reader = log::LogReader::open(log_lock)?;
+    let mut logs: Vec<log::Log<lookup::Address>> = Vec::new();
+    while let Some(entry) = reader.next()? {
+        logs.push(entry);
+    }
+    let log_lock = reader.release_lock();
+
+    let kept = kept(&logs);
+    if kept >= logs.len() {
+        return Ok(0);
+    }
+    let dropped = logs.split_off(kept).len();
+
+    // the blocks after the entries kept must still be stored locally
+    if let Some(pruned_until) = pruned_until {
+        let synced = logs
+            .last()
+            .and_then(|entry| entry.ptr().latest_addr.as_ref())
+            .map(|date| date.get_epochid());
+        if synced.map(|epoch| epoch < pruned_until).unwrap_or(true) {
+            return Err(Error::SyncEpochsPruned(pruned_until));
+        }
+    }
+
+    log_lock.rewrite(&logs)?;
+    Ok(dropped)
+}
+
+/// the number of entries before the given slot
+fn kept_before_slot<A, F>(logs: &[log::Log<A>], from_slot: u64, absolute_slot: F) -> usize
+where
+    F: Fn(&BlockDate) -> u64,
+{
+    logs.iter()
+        .take_while(|entry| match entry.ptr().latest_addr {
+            None => from_slot > 0,
+            Some(ref date) => absolute_slot(date) < from_slot,
+        })
+        .count()
+}
+
+/// the number of entries before the ones of the last block
+fn kept_before_last_block<A>(logs: &[log::Log<A>]) -> usize {
+    match logs.last() {
+        None => 0,
+        Some(last) => {
+            let last_block = &last.ptr().latest_known_hash;
+            logs.iter()
+                .take_while(|entry| &entry.ptr().latest_known_hash != last_block)
+                .count()
+        }
+    }
+}
+
+/// drop the entries of the wallet log from the given slot onwards
+pub fn truncate_from_slot(
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+    from_slot: u64,
+) -> Result<usize> {
+    truncate_log(wallet, blockchain, |logs| {
+        kept_before_slot(logs, from_slot, |date| blockchain.absolute_slot(date))
+    })
+}
+
+/// drop the entries of the last block of the wallet log: the rescan may
+/// have been interrupted before all the transactions of this block were
+/// recorded.
+pub fn truncate_last_block(wallet: &Wallet, blockchain: &Blockchain) -> Result<usize> {
+    truncate_log(wallet, blockchain, kept_before_last_block)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::block::{types::EpochSlotId, HeaderHash};
+    use std::process;
+    use wallet::state::ptr::StatePtr;
+
+    const SLOTS_PER_EPOCH: u64 = 21600;
+
+    fn absolute_slot(date: &BlockDate) -> u64 {
+        match date {
+            BlockDate::Boundary(epoch) => epoch * SLOTS_PER_EPOCH,
+            BlockDate::Normal(date) => date.epoch * SLOTS_PER_EPOCH + u64::from(date.slotid),
+        }
+    }
+
+    /// a checkpoint in the block `block` at the given date
+    fn checkpoint(block: u8, epoch: u64, slotid: u16) -> log::Log<lookup::Address> {
+        let date = BlockDate::Normal(EpochSlotId { epoch, slotid });
+        log::Log::Checkpoint(StatePtr::new(date, HeaderHash::from([block; 32])))
+    }
+
+    fn entries() -> Vec<log::Log<lookup::Address>> {
+        vec![
+            checkpoint(1, 0, 10),
+            checkpoint(2, 1, 0),
+            checkpoint(2, 1, 0),
+            checkpoint(3, 1, 5),
+            checkpoint(3, 1, 5),
+        ]
+    }
+
+    fn temp_log(name: &str, entries: &[log::Log<lookup::Address>]) -> PathBuf {
+        let dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-rescan-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut writer = log::LogWriter::open(log::LogLock::acquire(&dir).unwrap()).unwrap();
+        for entry in entries {
+            writer.append(entry).unwrap();
+        }
+        dir
+    }
+
+    fn read_log(dir: &PathBuf) -> Vec<log::Log<lookup::Address>> {
+        let reader = log::LogReader::open(log::LogLock::acquire(dir).unwrap()).unwrap();
+        reader.into_iter().collect::<log::Result<Vec<_>>>().unwrap()
+    }
+
+    fn hashes(logs: &[log::Log<lookup::Address>]) -> Vec<HeaderHash> {
+        logs.iter()
+            .map(|entry| entry.ptr().latest_known_hash.clone())
+            .collect()
+    }
+
+    #[test]
+    fn entries_before_slot() {
+        let logs = entries();
+        assert_eq!(kept_before_slot(&logs, 0, absolute_slot), 0);
+        assert_eq!(kept_before_slot(&logs, 10, absolute_slot), 0);
+        assert_eq!(kept_before_slot(&logs, 11, absolute_slot), 1);
+        assert_eq!(
+            kept_before_slot(&logs, SLOTS_PER_EPOCH + 5, absolute_slot),
+            3
+        );
+        assert_eq!(
+            kept_before_slot(&logs, SLOTS_PER_EPOCH + 6, absolute_slot),
+            5
+        );
+    }
+
+    #[test]
+    fn entries_before_last_block() {
+        let logs = entries();
+        assert_eq!(kept_before_last_block(&logs), 3);
+        assert_eq!(kept_before_last_block(&logs[..3]), 1);
+        assert_eq!(kept_before_last_block(&logs[..1]), 0);
+        assert_eq!(kept_before_last_block::<lookup::Address>(&[]), 0);
+    }
+
+    #[test]
+    fn log_is_truncated() {
+        let dir = temp_log("truncated", &entries());
+        let lock = log::LogLock::acquire(&dir).unwrap();
+        let dropped = truncate_locked(lock, None, kept_before_last_block).unwrap();
+        assert_eq!(dropped, 2);
+        assert_eq!(hashes(&read_log(&dir)), hashes(&entries()[..3]));
+
+        // nothing to drop, the log is left as is
+        let lock = log::LogLock::acquire(&dir).unwrap();
+        assert_eq!(truncate_locked(lock, None, |logs| logs.len()).unwrap(), 0);
+        assert_eq!(read_log(&dir).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn log_is_not_truncated_before_the_pruned_epochs() {
+        let dir = temp_log("pruned", &entries());
+        let lock = log::LogLock::acquire(&dir).unwrap();
+        match truncate_locked(lock, Some(1), |_| 1) {
+            Err(Error::SyncEpochsPruned(1)) => {}
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(dropped) => panic!("{} entries dropped from a pruned blockchain", dropped),
+        }
+        assert_eq!(read_log(&dir).len(), 5);
+
+        // the blocks after epoch 1 are still stored
+        let lock = log::LogLock::acquire(&dir).unwrap();
+        assert_eq!(truncate_locked(lock, Some(1), |_| 3).unwrap(), 2);
+        assert_eq!(read_log(&dir).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,403 @@
+//! wallet state snapshot
+//!
+//! Knowing the balance of a wallet means replaying its whole log, which
+//! gets slower as the log grows. The state the log leads to (the unspent
+//! outputs, the latest block, the addresses the wallet used and the date of
+//! its last credit or debit) is kept in the wallet directory along with the
+//! version of the log it was built from (its size and a hash of its end):
+//! as long as the log has not changed, the snapshot is read instead of the
+//! log. The version is read with the log locked, so it is the version of
+//! what was read or written.
+//!
+//! The unspent outputs are kept grouped by the address they were sent to,
+//! which makes the snapshot the on-disk UTxO index of the wallet too (see
+//! `utxo_index`).
+//!
+//! The sync updates the snapshot with what it adds to the log. Any other
+//! change of the log (rollback, rescan...) makes the snapshot stale, it is
+//! rebuilt the next time the state is read from the log.
+//!
+
+use super::config;
+use super::error::Result;
+use super::state::{log, lookup, ptr::StatePtr, state::State, utxo::UTxO};
+use super::utxo_index::{self, UtxoIndex};
+use super::Wallet;
+
+use cardano::{block::BlockDate, hash::Blake2b256};
+use serde_yaml;
+use storage_units::utils::tmpfile::TmpFile;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+static WALLET_SNAPSHOT_FILE: &'static str = "state.yml";
+
+/// version of the snapshot's content, the snapshots of another version
+/// are ignored (and rebuilt)
+const SNAPSHOT_VERSION: u32 = 4;
+
+/// size of the end of the wallet log hashed into its version
+const LOG_TAIL_SIZE: u64 = 64 * 1024;
+
+/// what identifies the content of the wallet log: it is only appended to,
+/// which changes its size, or rewritten from a given entry onwards
+/// (rollback, rescan), which changes its last entries. Unlike the
+/// modification time, the hash of the end of the log tells a rewrite of
+/// the same size apart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogVersion {
+    size: u64,
+    tail: Blake2b256,
+}
+
+/// the version of the wallet log, `None` if the wallet has no log.
+///
+/// The log must be locked while its version is read and until what was
+/// read from it, or written to it, is saved along with the version.
+pub fn log_version(wallet: &Wallet) -> io::Result<Option<LogVersion>> {
+    let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+    let mut file = match fs::File::open(dir.join(log::WALLET_LOG_FILE)) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+        Ok(file) => file,
+    };
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(LOG_TAIL_SIZE)))?;
+    let mut tail = Vec::new();
+    file.take(LOG_TAIL_SIZE).read_to_end(&mut tail)?;
+    Ok(Some(LogVersion {
+        size,
+        tail: Blake2b256::new(&tail),
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    log: Option<LogVersion>,
+    ptr: StatePtr,
+    /// the addresses of all the outputs the wallet received, for the
+    /// address lookup to know which addresses have been used
+    addresses: Vec<lookup::Address>,
+    /// the date of the last credit or debit of the wallet
+    last_activity: Option<BlockDate>,
+    /// the unspent outputs, by address (in base58)
+    utxos: BTreeMap<String, Vec<UTxO<lookup::Address>>>,
+}
+impl Snapshot {
+    /// the snapshot of the wallet's state, if there is one up to date
+    /// with the wallet log of the given version
+    pub fn load(wallet: &Wallet, log: &Option<LogVersion>) -> Option<Self> {
+        let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+        let path = dir.join(WALLET_SNAPSHOT_FILE);
+        let snapshot: Snapshot = match fs::File::open(&path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!("cannot open the state snapshot {:?}: {}", path, err);
+                return None;
+            }
+            Ok(file) => match serde_yaml::from_reader(file) {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    debug!("ignoring the state snapshot {:?}: {}", path, err);
+                    return None;
+                }
+            },
+        };
+        if snapshot.version != SNAPSHOT_VERSION {
+            debug!(
+                "ignoring the state snapshot {:?} of version {}",
+                path, snapshot.version
+            );
+            return None;
+        }
+        if log == &snapshot.log {
+            Some(snapshot)
+        } else {
+            None
+        }
+    }
+
+    /// save the state built from the wallet log of the given version, the
+    /// addresses it received outputs to (once each) and the date of its
+    /// last credit or debit
+    pub fn save<LS: lookup::AddressLookup>(
+        wallet: &Wallet,
+        log: Option<LogVersion>,
+        state: &State<LS>,
+        addresses: Vec<lookup::Address>,
+        last_activity: Option<BlockDate>,
+    ) -> Result<()> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            log,
+            ptr: state.ptr().clone(),
+            addresses: unique(addresses),
+            last_activity,
+            utxos: utxo_index::group_by_address(state.utxos.values()),
+        };
+        snapshot.write(wallet)
+    }
+
+    /// save the snapshot taken from another copy of the wallet log (a
+    /// backup of the wallet), as up to date with the wallet log as it is
+    /// now. The log must be locked.
+    pub fn save_for_current_log(mut self, wallet: &Wallet) -> Result<()> {
+        self.log = log_version(wallet)?;
+        self.write(wallet)
+    }
+
+    fn write(&self, wallet: &Wallet) -> Result<()> {
+        let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+        let mut tmpfile = TmpFile::create(dir.clone())?;
+        serde_yaml::to_writer(&mut tmpfile, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        tmpfile.render_permanent(&dir.join(WALLET_SNAPSHOT_FILE))?;
+        Ok(())
+    }
+
+    /// remove the snapshot of the wallet, if any: the state is rebuilt
+    /// from the wallet log the next time it is read
+    pub fn remove(wallet: &Wallet) -> Result<()> {
+        let dir = config::directory(&wallet.root_dir, &wallet.name.as_dirname());
+        match fs::remove_file(dir.join(WALLET_SNAPSHOT_FILE)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    pub fn addresses(&self) -> &[lookup::Address] {
+        &self.addresses
+    }
+
+    pub fn last_activity(&self) -> Option<&BlockDate> {
+        self.last_activity.as_ref()
+    }
+
+    /// the wallet's state, the address lookup acknowledges the addresses
+    /// the wallet used
+    pub fn into_state<LS: lookup::AddressLookup>(self, mut lookup_struct: LS) -> Result<State<LS>> {
+        for address in self.addresses {
+            lookup_struct.acknowledge(address)?;
+        }
+        let mut state = State::new(self.ptr, lookup_struct);
+        state.utxos = self
+            .utxos
+            .into_iter()
+            .flat_map(|(_, utxos)| utxos)
+            .map(|utxo| (utxo.extract_txin(), utxo))
+            .collect();
+        Ok(state)
+    }
+
+    /// the UTxO index of the wallet, as kept in the snapshot
+    pub fn into_utxo_index(self) -> UtxoIndex {
+        UtxoIndex::from_addresses(self.utxos)
+    }
+}
+
+/// the addresses without their repetitions, in their order
+fn unique(addresses: Vec<lookup::Address>) -> Vec<lookup::Address> {
+    // the addresses are not ordered, their debug representations are
+    let mut seen = BTreeSet::new();
+    addresses
+        .into_iter()
+        .filter(|address| seen.insert(format!("{:?}", address)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{
+        block::{EpochSlotId, HeaderHash},
+        coin::Coin,
+        config::ProtocolMagic,
+        hdwallet::{self, DerivationScheme, XPrv},
+        wallet::bip44,
+    };
+    use std::process;
+    use wallet::{
+        config::Config,
+        state::lookup::{accum::Accum, sequentialindex::SequentialBip44Lookup},
+        WalletName,
+    };
+
+    fn temp_wallet(name: &str) -> Wallet {
+        let root_dir =
+            ::std::env::temp_dir().join(format!("cardano-cli-snapshot-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root_dir);
+        let name = WalletName::new("wallet".to_owned()).unwrap();
+        let wallet = Wallet::new(root_dir, name, Config::default(), Vec::new(), None);
+        wallet.save().unwrap();
+        wallet
+    }
+
+    fn ptr(block: u8, slotid: u16) -> StatePtr {
+        let date = BlockDate::Normal(EpochSlotId { epoch: 0, slotid });
+        StatePtr::new(date, HeaderHash::from([block; 32]))
+    }
+
+    fn utxo(index: u32) -> UTxO<lookup::Address> {
+        let addressing = bip44::Addressing {
+            account: bip44::bip44::Account::new(0).unwrap(),
+            change: 0,
+            index: bip44::Index::new(index).unwrap(),
+        };
+        let wallet = bip44::Wallet::from_root_key(
+            XPrv::normalize_bytes([0x42; hdwallet::XPRV_SIZE]),
+            DerivationScheme::V2,
+        );
+        let address = SequentialBip44Lookup::new(wallet, ProtocolMagic::from(764824073).into())
+            .get_address(&addressing);
+        UTxO {
+            transaction_id: Blake2b256::new(&[index as u8]),
+            index_in_transaction: index,
+            credited_address: address,
+            credited_addressing: lookup::Address::Bip44(addressing),
+            credited_value: Coin::new(1_000_000).unwrap(),
+        }
+    }
+
+    fn append(wallet: &Wallet, entries: &[log::Log<lookup::Address>]) {
+        let mut writer = log::LogWriter::open(wallet.log().unwrap()).unwrap();
+        for entry in entries {
+            writer.append(entry).unwrap();
+        }
+    }
+
+    fn remove(wallet: Wallet) {
+        fs::remove_dir_all(&wallet.root_dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_is_loaded_back() {
+        let wallet = temp_wallet("roundtrip");
+        let utxos = vec![utxo(0), utxo(1)];
+        append(
+            &wallet,
+            &[
+                log::Log::ReceivedFund(ptr(1, 1), utxos[0].clone()),
+                log::Log::ReceivedFund(ptr(2, 2), utxos[1].clone()),
+            ],
+        );
+        let log = log_version(&wallet).unwrap();
+        assert!(log.is_some());
+
+        let mut state = State::new(ptr(2, 2), Accum::default());
+        for utxo in utxos.iter() {
+            state.utxos.insert(utxo.extract_txin(), utxo.clone());
+        }
+        // the first address received two outputs
+        let addresses = vec![
+            utxos[0].credited_addressing.clone(),
+            utxos[1].credited_addressing.clone(),
+            utxos[0].credited_addressing.clone(),
+        ];
+        let last_activity = ptr(2, 2).latest_addr;
+        Snapshot::save(
+            &wallet,
+            log.clone(),
+            &state,
+            addresses,
+            last_activity.clone(),
+        )
+        .unwrap();
+
+        let snapshot = Snapshot::load(&wallet, &log).expect("no snapshot up to date");
+        let addresses: Vec<String> = snapshot.addresses().iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            addresses,
+            vec![
+                utxos[0].credited_addressing.to_string(),
+                utxos[1].credited_addressing.to_string()
+            ]
+        );
+        assert_eq!(snapshot.last_activity(), last_activity.as_ref());
+
+        let loaded = snapshot.into_state(Accum::default()).unwrap();
+        assert_eq!(loaded.ptr.latest_addr, state.ptr.latest_addr);
+        assert_eq!(loaded.ptr.latest_known_hash, state.ptr.latest_known_hash);
+        assert_eq!(
+            loaded.utxos.keys().collect::<Vec<_>>(),
+            state.utxos.keys().collect::<Vec<_>>()
+        );
+        for (loaded, utxo) in loaded.utxos.values().zip(utxos.iter()) {
+            assert_eq!(loaded.credited_address, utxo.credited_address);
+            assert_eq!(loaded.credited_value, utxo.credited_value);
+        }
+        remove(wallet);
+    }
+
+    #[test]
+    fn utxo_index_is_loaded_back() {
+        let wallet = temp_wallet("index");
+        append(&wallet, &[log::Log::Checkpoint(ptr(1, 1))]);
+        let log = log_version(&wallet).unwrap();
+        let utxos = vec![utxo(0), utxo(1)];
+        let mut state = State::new(ptr(1, 1), Accum::default());
+        for utxo in utxos.iter() {
+            state.utxos.insert(utxo.extract_txin(), utxo.clone());
+        }
+        Snapshot::save(&wallet, log.clone(), &state, Vec::new(), None).unwrap();
+
+        let index = Snapshot::load(&wallet, &log)
+            .expect("no snapshot up to date")
+            .into_utxo_index();
+        assert_eq!(index.len(), 2);
+        for utxo in utxos.iter() {
+            let by_address = index.by_address(&utxo.credited_address);
+            assert_eq!(by_address.len(), 1);
+            assert_eq!(by_address[0].extract_txin(), utxo.extract_txin());
+            assert!(index.get(&utxo.extract_txin()).is_some());
+        }
+        remove(wallet);
+    }
+
+    #[test]
+    fn snapshot_is_stale_once_the_log_changed() {
+        let wallet = temp_wallet("stale");
+        append(&wallet, &[log::Log::Checkpoint(ptr(1, 1))]);
+        let log = log_version(&wallet).unwrap();
+        let state = State::new(ptr(1, 1), Accum::default());
+        Snapshot::save(&wallet, log.clone(), &state, Vec::new(), None).unwrap();
+        assert!(Snapshot::load(&wallet, &log).is_some());
+
+        // appended to
+        append(&wallet, &[log::Log::Checkpoint(ptr(2, 2))]);
+        let appended = log_version(&wallet).unwrap();
+        assert!(Snapshot::load(&wallet, &appended).is_none());
+
+        // rewritten with entries of the same size (rolled back and synced
+        // to another branch)
+        let entries: Vec<log::Log<lookup::Address>> = vec![
+            log::Log::Checkpoint(ptr(1, 1)),
+            log::Log::Checkpoint(ptr(3, 2)),
+        ];
+        wallet.log().unwrap().rewrite(&entries).unwrap();
+        let rewritten = log_version(&wallet).unwrap();
+        assert_eq!(
+            appended.as_ref().map(|v| v.size),
+            rewritten.as_ref().map(|v| v.size)
+        );
+        assert_ne!(appended, rewritten);
+        remove(wallet);
+    }
+
+    #[test]
+    fn addresses_are_kept_once() {
+        let addresses = vec![
+            lookup::Address::RIndexInvalid(vec![1, 2]),
+            lookup::Address::RIndexInvalid(vec![3]),
+            lookup::Address::RIndexInvalid(vec![1, 2]),
+            lookup::Address::RIndexInvalid(vec![3]),
+        ];
+        let unique: Vec<String> = unique(addresses).iter().map(|a| a.to_string()).collect();
+        assert_eq!(unique, vec!["[1, 2]", "[3]"]);
+    }
+}
@@ -2,11 +2,11 @@ use cardano::{block::Block, tx::TxAux};
 use cardano_storage as storage;
 
 use super::ptr::StatePtr;
-use indicatif::ProgressBar;
+use utils::interaction::Progress;
 
 pub struct TransactionIterator<'a> {
     block_iterator: storage::iter::Iter<'a>,
-    progress: ProgressBar,
+    progress: Box<Progress>,
 
     current_tx: Option<(Block, usize)>,
 }
@@ -46,7 +46,7 @@ impl<'a> TransactionIterator<'a> {
         }
         Ok(())
     }
-    pub fn new(progress: ProgressBar, block_iterator: storage::iter::Iter<'a>) -> Self {
+    pub fn new(progress: Box<Progress>, block_iterator: storage::iter::Iter<'a>) -> Self {
         TransactionIterator {
             block_iterator: block_iterator,
             progress: progress,
@@ -9,7 +9,7 @@ use storage_units::{
 
 use std::{
     error, fmt,
-    fs::remove_file,
+    fs::{remove_file, rename},
     io::{self, Read, Write},
     path::{Path, PathBuf},
     result,
@@ -50,11 +50,11 @@ impl From<append::Error> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::LogNotFound         => write!(f, "Log file not found"),
-            Error::IoError(_)          => write!(f, "I/O Error"),
+            Error::LogNotFound => write!(f, "Log file not found"),
+            Error::IoError(_) => write!(f, "I/O Error"),
             Error::LogFormatError(err) => write!(f, "Log format error: `{}`", err),
-            Error::LockError(_)        => write!(f, "Log's Lock file error"),
-            Error::AppendError(_)      => write!(f, "Error when appending data to the log file"),
+            Error::LockError(_) => write!(f, "Log's Lock file error"),
+            Error::AppendError(_) => write!(f, "Error when appending data to the log file"),
             Error::UnsupportedLogFormat(_) => {
                 write!(f, "Unsupported Log format (tried to deserialize log of unknown encoding or log is corrupted)")
             }
@@ -212,7 +212,12 @@ impl<A: fmt::Display> fmt::Display for Log<A> {
     }
 }
 
-const WALLET_LOG_FILE: &'static str = "LOG";
+/// name of the log file, in the wallet directory
+pub const WALLET_LOG_FILE: &'static str = "LOG";
+
+/// name of the log being written by `LogLock::rewrite`, renamed over the
+/// log once complete
+const WALLET_LOG_REWRITE_FILE: &'static str = "LOG.rewrite";
 
 pub struct LogLock {
     lock: lock::Lock,
@@ -233,6 +238,31 @@ impl LogLock {
     pub fn delete_wallet_log(self) -> Result<()> {
         Ok(remove_file(self.log_path)?)
     }
+
+    /// Replaces the content of the wallet log with the given entries.
+    ///
+    /// The entries are written to a new log next to the wallet log, renamed
+    /// over it once complete: the wallet log is left untouched if this
+    /// fails half way.
+    pub fn rewrite<A>(self, entries: &[Log<A>]) -> Result<Self>
+    where
+        A: serde::Serialize + fmt::Debug,
+    {
+        let rewrite_path = self.log_path.with_file_name(WALLET_LOG_REWRITE_FILE);
+        let rewrite_lock = Lock::lock(rewrite_path.clone())?;
+        // the leftover of an interrupted rewrite
+        if rewrite_path.is_file() {
+            remove_file(&rewrite_path)?;
+        }
+
+        let mut writer = append::Writer::open(rewrite_lock)?;
+        for entry in entries {
+            writer.append_bytes(&entry.serialise()?)?;
+        }
+        let _rewrite_lock = writer.close();
+        rename(&rewrite_path, &self.log_path)?;
+        Ok(self)
+    }
 }
 
 /// Structure to read the Wallet Log one by one
@@ -20,6 +20,21 @@ impl ::std::fmt::Display for Address {
         }
     }
 }
+impl Address {
+    /// the derivation path of the address from the wallet's root key, as
+    /// far as it is known
+    pub fn derivation_path(&self) -> String {
+        match self {
+            Address::Bip44(address) => format!(
+                "m/44'/1815'/{}'/{}/{}",
+                address.account.get_account_number(),
+                address.change,
+                address.index.get_scheme_value()
+            ),
+            address => address.to_string(),
+        }
+    }
+}
 impl From<bip44::Addressing> for Address {
     fn from(address: bip44::Addressing) -> Self {
         Address::Bip44(address)
@@ -34,6 +34,47 @@ impl RandomIndexLookup {
     pub fn get_address(&self, addr: &rindex::Addressing) -> ExtendedAddr {
         self.generator.address(addr, self.network_magic)
     }
+
+    /// the addressing of the address, if it is one of the wallet's
+    ///
+    /// 1. we check if the address contains a derivation_path (see cardano::address's ExtendedAddress);
+    /// 2. we reconstruct the address with the derivation path and check it is actually one of ours;
+    ///
+    pub fn find(
+        &self,
+        address: &ExtendedAddr,
+    ) -> Result<Option<rindex::Addressing>, AddressLookupError> {
+        match self.decrypt_addressing(address)? {
+            None => Ok(None),
+            Some(addressing) => {
+                let reconstructed = self.get_address(&addressing);
+
+                if &reconstructed != address {
+                    debug!("credited address:    {}", address);
+                    debug!("constructed address: {}", reconstructed);
+                    Err(rindex::Error::CannotReconstructAddress(reconstructed).into())
+                } else {
+                    Ok(Some(addressing))
+                }
+            }
+        }
+    }
+
+    /// the addressing of the derivation path of the address, if the wallet's
+    /// key decrypts it. Unlike `find`, the address is not reconstructed: it
+    /// may not be the address the wallet derives with this path.
+    pub fn decrypt_addressing(
+        &self,
+        address: &ExtendedAddr,
+    ) -> Result<Option<rindex::Addressing>, AddressLookupError> {
+        use cardano::hdpayload;
+        match self.generator.try_get_addressing(address) {
+            Ok(addressing) => Ok(addressing),
+            Err(rindex::Error::PayloadError(hdpayload::Error::PayloadIsTooLarge(_))) => Ok(None),
+            Err(rindex::Error::PayloadError(hdpayload::Error::NotEnoughEncryptedData)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
 }
 
 impl From<rindex::Error> for AddressLookupError {
@@ -43,37 +84,15 @@ impl From<rindex::Error> for AddressLookupError {
 }
 
 impl AddressLookup for RandomIndexLookup {
-    /// Random index lookup is more a random index decryption and reconstruction method
-    ///
-    /// 1. we check if the input address contains a derivation_path (see cardano::address's ExtendedAddress);
-    /// 2. we reconstruct the address with the derivation path and check it is actually one of ours;
-    ///
+    /// Random index lookup is more a random index decryption and reconstruction method,
+    /// see `RandomIndexLookup::find`
     fn lookup(
         &mut self,
         utxo: UTxO<ExtendedAddr>,
     ) -> Result<Option<UTxO<Address>>, AddressLookupError> {
-        use cardano::hdpayload;
-        use cardano::wallet::rindex;
-        let opt_addressing = match self.generator.try_get_addressing(&utxo.credited_address) {
-            Ok(addressing) => addressing,
-            Err(rindex::Error::PayloadError(hdpayload::Error::PayloadIsTooLarge(_))) => None,
-            Err(rindex::Error::PayloadError(hdpayload::Error::NotEnoughEncryptedData)) => None,
-            Err(err) => return Err(err.into()),
-        };
-
-        match opt_addressing {
+        match self.find(&utxo.credited_address)? {
             None => Ok(None),
-            Some(addressing) => {
-                let address = self.get_address(&addressing);
-
-                if address != utxo.credited_address {
-                    debug!("credited address:    {}", utxo.credited_address);
-                    debug!("constructed address: {}", address);
-                    Err(rindex::Error::CannotReconstructAddress(address).into())
-                } else {
-                    Ok(Some(utxo.map(|_| addressing.into())))
-                }
-            }
+            Some(addressing) => Ok(Some(utxo.map(|_| addressing.into()))),
         }
     }
 
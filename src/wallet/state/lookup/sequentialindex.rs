@@ -47,15 +47,27 @@ pub struct SequentialBip44Lookup {
 
 impl SequentialBip44Lookup {
     pub fn new(wallet: bip44::Wallet, network_magic: NetworkMagic) -> Self {
+        Self::with_gap_limit(wallet, network_magic, *DEFAULT_GAP_LIMIT)
+    }
+
+    pub fn with_gap_limit(
+        wallet: bip44::Wallet,
+        network_magic: NetworkMagic,
+        gap_limit: u32,
+    ) -> Self {
         SequentialBip44Lookup {
             wallet: wallet,
             expected: BTreeMap::new(),
             accounts: Vec::new(),
-            gap_limit: *DEFAULT_GAP_LIMIT,
+            gap_limit: gap_limit,
             network_magic: network_magic,
         }
     }
 
+    pub fn gap_limit(&self) -> u32 {
+        self.gap_limit
+    }
+
     pub fn get_private_key(&self, addr: &bip44::Addressing) -> bip44::IndexLevel<XPrv> {
         self.wallet
             .account(
@@ -69,6 +81,12 @@ impl SequentialBip44Lookup {
             )
     }
 
+    /// the addressing of the address, if it is one of the addresses
+    /// expected so far
+    pub fn find(&self, address: &ExtendedAddr) -> Option<bip44::Addressing> {
+        self.expected.get(&address.to_address()).cloned()
+    }
+
     pub fn get_address(&self, addr: &bip44::Addressing) -> ExtendedAddr {
         let xprv = self.get_private_key(addr);
         let xpub = xprv.public();
@@ -117,19 +135,19 @@ impl SequentialBip44Lookup {
         if addressing.account.get_account_number() as usize >= self.accounts.len() {
             return Ok(());
         }
-        let mut limits = self.accounts[addressing.account.get_account_number() as usize];
+        let account = addressing.account.get_account_number() as usize;
         if addressing.change != 0 && addressing.change != 1 {
             return Ok(());
         }
         let lidx = addressing.change as usize;
-        let current_threshold = limits[lidx];
+        let current_threshold = self.accounts[account][lidx];
         if addressing.index <= current_threshold {
             return Ok(());
         }
         let new_threshold = current_threshold.incr(self.gap_limit)?;
         let gap = self.gap_limit;
         self.mut_generate_from(&addressing.account, addressing.change, &new_threshold, gap)?;
-        limits[lidx] = new_threshold;
+        self.accounts[account][lidx] = new_threshold;
         Ok(())
     }
 }
@@ -142,10 +160,7 @@ impl From<bip44::bip44::Error> for AddressLookupError {
 
 impl AddressLookup for SequentialBip44Lookup {
     fn lookup(&mut self, utxo: UTxO<ExtendedAddr>) -> Result<Option<UTxO<Address>>> {
-        let addressing = self
-            .expected
-            .get(&utxo.credited_address.to_address())
-            .cloned();
+        let addressing = self.find(&utxo.credited_address);
         if let Some(addressing) = addressing {
             self.threshold_generate(addressing)?;
 
@@ -6,237 +6,537 @@
 
 use super::config::{Config, HDWalletModel};
 use super::error::{Error, Result};
+use super::snapshot::{self, LogVersion, Snapshot};
 use super::state::{iter::TransactionIterator, log, lookup, ptr, ptr::StatePtr, state, utxo::UTxO};
-use super::Wallet;
+use super::utxo_index::UtxoIndex;
+use super::{Wallet, WalletName};
 
 use cardano::{
     address::ExtendedAddr,
     block::BlockDate,
+    coin::Coin,
     config::{NetworkMagic, ProtocolMagic},
-    tx::{TxId, TxInWitness},
+    hdwallet::XPrv,
+    tx::{TxAux, TxId, TxInWitness},
 };
 
-use utils::term::{style::Style, Term};
+use utils::interaction::Interaction;
 
-use blockchain::Blockchain;
+use blockchain::{self, Blockchain};
 
-use std::{io::Write, path::Path};
+use std::{
+    path::Path,
+    sync::{mpsc, Arc},
+    thread,
+};
+
+/// the update of a wallet's state with the transactions of the blocks
+/// after its latest known block, the new entries being appended to its log
+struct StateUpdate {
+    from_date: BlockDate,
+    last_block_date: BlockDate,
+    /// the snapshot is updated with the entries added to the log, as long
+    /// as it was up to date with the log before
+    addresses: Option<Vec<lookup::Address>>,
+    /// the date of the last credit or debit, from the snapshot and the
+    /// entries added to the log
+    last_activity: Option<BlockDate>,
+    last_logged: Option<StatePtr>,
+    /// the version of the log after the update's last append
+    log: Option<LogVersion>,
+    /// whether another process changed the log since the update started:
+    /// the state does not match the log anymore, and is not saved
+    log_changed: bool,
+}
+impl StateUpdate {
+    fn start<LS>(wallet: &Wallet, blockchain: &Blockchain, state: &state::State<LS>) -> Result<Self>
+    where
+        LS: lookup::AddressLookup,
+    {
+        let from_ptr = state.ptr();
+        let from_date = from_ptr.latest_addr.unwrap_or(BlockDate::Boundary(0));
+
+        // the blocks between the wallet's state and the first epoch still
+        // stored locally are not available anymore
+        if let Some(pruned_until) = blockchain.load_pruned_until()? {
+            let synced = from_ptr.latest_addr.map(|date| date.get_epochid());
+            if synced.map(|epoch| epoch < pruned_until).unwrap_or(true) {
+                return Err(Error::SyncEpochsPruned(pruned_until));
+            }
+        }
+
+        let log = {
+            let _log_lock = lock_wallet_log(wallet)?;
+            snapshot::log_version(wallet)?
+        };
+        let snapshot = Snapshot::load(wallet, &log);
+        Ok(StateUpdate {
+            from_date: from_date.clone(),
+            last_block_date: from_date,
+            addresses: snapshot
+                .as_ref()
+                .map(|snapshot| snapshot.addresses().to_vec()),
+            last_activity: snapshot
+                .as_ref()
+                .and_then(|snapshot| snapshot.last_activity().cloned()),
+            last_logged: None,
+            log,
+            log_changed: false,
+        })
+    }
+
+    /// append the entries to the wallet log, and read the version of the
+    /// log before and after with the log locked
+    fn append<A>(&mut self, wallet: &Wallet, logs: &[log::Log<A>]) -> Result<()>
+    where
+        A: ::serde::Serialize + ::std::fmt::Debug,
+    {
+        if logs.is_empty() {
+            return Ok(());
+        }
+        let log_lock = lock_wallet_log(wallet)?;
+        if snapshot::log_version(wallet)? != self.log {
+            self.log_changed = true;
+        }
+        let mut writer = log::LogWriter::open(log_lock)?;
+        for log in logs {
+            writer.append(log)?;
+            match log {
+                log::Log::Checkpoint(_) => {}
+                log::Log::ReceivedFund(ptr, _) | log::Log::SpentFund(ptr, _) => {
+                    self.last_activity = ptr.latest_addr.clone();
+                }
+            }
+            self.last_logged = Some(log.ptr().clone());
+        }
+        self.log = snapshot::log_version(wallet)?;
+        Ok(())
+    }
+
+    fn forward<LS>(
+        &mut self,
+        wallet: &Wallet,
+        state: &mut state::State<LS>,
+        ptr: &StatePtr,
+        txaux: &TxAux,
+    ) -> Result<()>
+    where
+        LS: lookup::AddressLookup,
+    {
+        if let Some(ref addr) = ptr.latest_addr {
+            // Ignore the blocks up to the latest known block so next call
+            // to sync won't fail (the other wallets synced along may be
+            // behind this one).
+            if *addr <= self.from_date {
+                return Ok(());
+            }
+            if self.last_block_date.get_epochid() != addr.get_epochid() {
+                let log: log::Log<ExtendedAddr> = log::Log::Checkpoint(ptr.clone());
+                self.append(wallet, &[log])?;
+            }
+
+            self.last_block_date = addr.clone();
+        }
+
+        {
+            let logs =
+                state.forward_with_txins(txaux.tx.inputs.iter().map(|txin| (ptr.clone(), txin)))?;
+            self.append(wallet, &logs)?;
+        }
+
+        {
+            let txid = txaux.tx.id();
+            let logs = state.forward_with_utxos(txaux.tx.outputs.iter().enumerate().map(
+                |(idx, txout)| {
+                    (
+                        ptr.clone(),
+                        UTxO {
+                            transaction_id: txid.clone(),
+                            index_in_transaction: idx as u32,
+                            credited_address: txout.address.clone(),
+                            credited_addressing: txout.address.clone(),
+                            credited_value: txout.value,
+                        },
+                    )
+                },
+            ))?;
+
+            self.append(wallet, &logs)?;
+            if let Some(ref mut addresses) = self.addresses {
+                for log in logs.iter() {
+                    if let log::Log::ReceivedFund(_, ref utxo) = log {
+                        addresses.push(utxo.credited_addressing.clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish<LS>(self, wallet: &Wallet, state: &mut state::State<LS>)
+    where
+        LS: lookup::AddressLookup,
+    {
+        // the state now points to the last entry of the log, as if it was
+        // read from it
+        if let Some(ptr) = self.last_logged {
+            state.ptr = ptr;
+        }
+        if self.log_changed {
+            warn!(
+                "the log of wallet {} changed during the sync, its state snapshot is rebuilt next time",
+                wallet.name
+            );
+            return;
+        }
+        if let Some(addresses) = self.addresses {
+            save_snapshot(wallet, self.log, state, addresses, self.last_activity);
+        }
+    }
+}
 
 pub fn update_wallet_state_with_utxos<LS>(
-    term: &mut Term,
+    term: &mut Interaction,
     wallet: &Wallet,
     blockchain: &Blockchain,
     state: &mut state::State<LS>,
-) where
+) -> Result<()>
+where
     LS: lookup::AddressLookup,
 {
     let blockchain_tip = blockchain.load_tip().0;
-
-    let from_ptr = state.ptr().clone();
-    let from = from_ptr.latest_known_hash;
-    let from_date = from_ptr.latest_addr.unwrap_or(BlockDate::Boundary(0));
-    let num_blocks = blockchain_tip.date - from_date;
+    let from = state.ptr().latest_known_hash.clone();
+    let mut update = StateUpdate::start(wallet, blockchain, state)?;
+    let num_blocks = blockchain_tip.date - update.from_date;
 
     term.info(&format!(
         "syncing wallet from {} to {}\n",
-        from_date, blockchain_tip.date
-    ))
-    .unwrap();
+        update.from_date, blockchain_tip.date
+    ))?;
 
-    let progress = term.progress_bar(num_blocks as u64);
+    let progress = term.progress(num_blocks as u64);
     progress.set_message("loading transactions... ");
 
-    let mut last_block_date = from_date;
     for res in TransactionIterator::new(
         progress,
         blockchain
             .iter_to_tip(from)
-            .unwrap_or_else(|e| term.fail_with(e)),
+            .map_err(blockchain::Error::from)?,
     ) {
-        let (ptr, txaux) = res.unwrap_or_else(|e| term.fail_with(e));
+        let (ptr, txaux) = res.map_err(blockchain::Error::from)?;
         debug!("transactions in: {}", ptr);
+        update.forward(wallet, state, &ptr, &txaux)?;
+    }
 
-        if let Some(addr) = ptr.latest_addr {
-            // Ignore the latest known block hash so next call to sync won't fail.
-            if addr == from_date {
-                continue;
-            }
-            if last_block_date.get_epochid() != addr.get_epochid() {
-                let log_lock = lock_wallet_log(&wallet);
-                let mut writer =
-                    log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
-                let log: log::Log<ExtendedAddr> = log::Log::Checkpoint(ptr.clone());
-                writer.append(&log).unwrap_or_else(|e| term.fail_with(e));
-            }
+    update.finish(wallet, state);
+    Ok(())
+}
+
+/// the transactions of the blocks, shared by the wallets' updates
+type SharedTransaction = Arc<(StatePtr, TxAux)>;
+
+/// number of transactions read ahead of the slowest wallet update
+const WALLETS_UPDATE_BUFFER: usize = 1024;
+
+struct WalletUpdateWorker {
+    name: WalletName,
+    from: StatePtr,
+    sender: Option<mpsc::SyncSender<SharedTransaction>>,
+    handle: thread::JoinHandle<Result<()>>,
+}
 
-            last_block_date = addr.clone();
+/// the update of the states of several wallets attached to the same
+/// blockchain: the blocks are read once, each wallet looking for its
+/// addresses in them in its own thread.
+#[derive(Default)]
+pub struct WalletsUpdate {
+    workers: Vec<WalletUpdateWorker>,
+}
+impl WalletsUpdate {
+    pub fn new() -> Self {
+        WalletsUpdate {
+            workers: Vec::new(),
         }
+    }
 
-        {
-            let logs = state
-                .forward_with_txins(txaux.tx.inputs.iter().map(|txin| (ptr.clone(), txin)))
-                .unwrap_or_else(|e| term.fail_with(e));
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
-            for log in logs {
-                writer.append(&log).unwrap_or_else(|e| term.fail_with(e));
+    /// add the wallet to the update, its state (loaded with the lookup
+    /// structure of its addresses) is updated and dropped in the end
+    pub fn add<LS>(
+        &mut self,
+        blockchain: &Blockchain,
+        wallet: Wallet,
+        mut state: state::State<LS>,
+    ) -> Result<()>
+    where
+        LS: lookup::AddressLookup + Send + 'static,
+    {
+        let mut update = StateUpdate::start(&wallet, blockchain, &state)?;
+        let (sender, receiver) = mpsc::sync_channel::<SharedTransaction>(WALLETS_UPDATE_BUFFER);
+        let name = wallet.name.clone();
+        let from = state.ptr().clone();
+
+        let handle = thread::spawn(move || {
+            for shared in receiver {
+                let (ref ptr, ref txaux) = *shared;
+                update.forward(&wallet, &mut state, ptr, txaux)?;
+            }
+            update.finish(&wallet, &mut state);
+            Ok(())
+        });
+
+        self.workers.push(WalletUpdateWorker {
+            name: name,
+            from: from,
+            sender: Some(sender),
+            handle: handle,
+        });
+        Ok(())
+    }
+
+    /// read the blocks from the earliest latest known block of the wallets
+    /// to the local tip, and wait for the wallets to be updated
+    pub fn run(mut self, term: &mut Interaction, blockchain: &Blockchain) -> Result<()> {
+        let read = self.read_blocks(term, blockchain);
+        // the errors of the wallets are reported first, all of them
+        // stopped before the error reading the blocks
+        self.join().and(read)
+    }
+
+    /// send the blocks from the earliest latest known block of the wallets
+    /// to the local tip to the wallets' updates
+    fn read_blocks(&mut self, term: &mut Interaction, blockchain: &Blockchain) -> Result<()> {
+        // the blocks are read from the wallet the most behind
+        let mut from: Option<StatePtr> = None;
+        for worker in self.workers.iter() {
+            let behind = match from {
+                None => true,
+                Some(ref from) => worker.from.latest_block_date() < from.latest_block_date(),
+            };
+            if behind {
+                from = Some(worker.from.clone());
+            }
+        }
+        let from = match from {
+            None => return Ok(()),
+            Some(from) => from,
+        };
+        let from_date = from.latest_addr.unwrap_or(BlockDate::Boundary(0));
+        let blockchain_tip = blockchain.load_tip().0;
+
+        term.info(&format!(
+            "syncing {} wallets from {} to {}\n",
+            self.workers.len(),
+            from_date,
+            blockchain_tip.date
+        ))?;
+
+        let progress = term.progress((blockchain_tip.date - from_date) as u64);
+        progress.set_message("loading transactions... ");
+
+        for res in TransactionIterator::new(
+            progress,
+            blockchain
+                .iter_to_tip(from.latest_known_hash)
+                .map_err(blockchain::Error::from)?,
+        ) {
+            let (ptr, txaux) = res.map_err(blockchain::Error::from)?;
+            debug!("transactions in: {}", ptr);
+
+            let shared = Arc::new((ptr, txaux));
+            for worker in self.workers.iter_mut() {
+                let failed = match worker.sender {
+                    Some(ref sender) => sender.send(shared.clone()).is_err(),
+                    None => false,
+                };
+                // the update of the wallet stopped, its error is reported
+                // once the others are done
+                if failed {
+                    worker.sender = None;
+                }
             }
         }
 
-        {
-            let txid = txaux.tx.id();
-            let logs =
-                state
-                    .forward_with_utxos(txaux.tx.outputs.into_iter().enumerate().map(
-                        |(idx, txout)| {
-                            (
-                                ptr.clone(),
-                                UTxO {
-                                    transaction_id: txid.clone(),
-                                    index_in_transaction: idx as u32,
-                                    credited_address: txout.address.clone(),
-                                    credited_addressing: txout.address,
-                                    credited_value: txout.value,
-                                },
-                            )
-                        },
-                    ))
-                    .unwrap_or_else(|e| term.fail_with(e));
+        Ok(())
+    }
 
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
-            for log in logs {
-                writer.append(&log).unwrap_or_else(|e| term.fail_with(e));
+    /// wait for all the wallets to be updated, the error is the one of the
+    /// first wallet which failed (the others are logged)
+    fn join(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for mut worker in self.workers.drain(..) {
+            // no more transactions, the update finishes
+            worker.sender = None;
+            let error = match worker.handle.join() {
+                Ok(Ok(())) => continue,
+                Ok(Err(err)) => {
+                    warn!("cannot sync wallet {}: {}", worker.name, err);
+                    Error::WalletSyncFailed(worker.name, Box::new(err))
+                }
+                Err(_) => {
+                    warn!("cannot sync wallet {}: the update panicked", worker.name);
+                    Error::WalletSyncPanicked(worker.name)
+                }
+            };
+            if result.is_ok() {
+                result = Err(error);
             }
         }
+        result
     }
 }
-
-pub fn display_wallet_state_utxos<LS>(term: &mut Term, state: state::State<LS>)
-where
-    LS: lookup::AddressLookup,
-{
-    for (_, utxo) in state.utxos {
-        writeln!(
-            term,
-            "{}.{} {}",
-            style!(utxo.transaction_id),
-            style!(utxo.index_in_transaction).yellow(),
-            style!(utxo.credited_value).green()
-        )
-        .unwrap()
+impl Drop for WalletsUpdate {
+    fn drop(&mut self) {
+        // the wallets added to an update which does not run (adding the
+        // next ones failed) are not left updating in the background
+        if let Err(err) = self.join() {
+            debug!("the updates of the wallets stopped: {}", err);
+        }
     }
 }
 
-pub fn display_wallet_state_logs<LS>(
-    term: &mut Term,
+/// save the snapshot of the state built from the log of the given version,
+/// the state read from the log next time is rebuilt if this fails
+fn save_snapshot<LS>(
     wallet: &Wallet,
-    _state: &mut state::State<LS>,
-    pretty: bool,
+    log: Option<LogVersion>,
+    state: &state::State<LS>,
+    addresses: Vec<lookup::Address>,
+    last_activity: Option<BlockDate>,
 ) where
     LS: lookup::AddressLookup,
 {
-    let log_lock = lock_wallet_log(&wallet);
-    let reader = log::LogReader::open(log_lock).unwrap();
-    let reader: log::LogIterator<lookup::Address> = reader.into_iter();
-    let reader = reader.filter_map(|r| match r {
-        Err(err) => panic!("{:?}", err),
-        Ok(v) => Some(v),
-    });
-
-    for log in reader {
-        match log {
-            log::Log::Checkpoint(ptr) => {
-                if !pretty {
-                    writeln!(
-                        term,
-                        "{} {} ({})",
-                        style!("checkpoint").cyan(),
-                        style!(ptr.latest_block_date()),
-                        style!(ptr.latest_known_hash)
-                    )
-                    .unwrap();
-                    writeln!(term, "").unwrap();
-                }
-            }
-            log::Log::ReceivedFund(ptr, utxo) => {
-                if pretty {
-                    display_utxo(term, ptr, utxo, false);
-                } else {
-                    dump_utxo(term, ptr, utxo, false);
-                }
-            }
-            log::Log::SpentFund(ptr, utxo) => {
-                if pretty {
-                    display_utxo(term, ptr, utxo, true);
-                } else {
-                    dump_utxo(term, ptr, utxo, true);
-                }
-            }
+    if let Err(err) = Snapshot::save(wallet, log, state, addresses, last_activity) {
+        warn!(
+            "cannot save the state snapshot of wallet {}: {}",
+            wallet.name, err
+        );
+    }
+}
+
+/// the UTxO index of the wallet, read from the state snapshot if it is up
+/// to date with the wallet log, otherwise built from the wallet state (and
+/// saved with the rebuilt snapshot)
+pub fn load_utxo_index<P>(wallet: &Wallet, root_dir: P) -> Result<UtxoIndex>
+where
+    P: AsRef<Path>,
+{
+    {
+        let _log_lock = lock_wallet_log(wallet)?;
+        if let Some(snapshot) = Snapshot::load(wallet, &snapshot::log_version(wallet)?) {
+            return Ok(snapshot.into_utxo_index());
         }
     }
+
+    let loaded = load_wallet_state(wallet, root_dir, lookup::accum::Accum::default())?;
+    Ok(UtxoIndex::new(loaded.state.utxos.values()))
 }
 
-pub fn display_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
-    let ptr = format!("{}", style!(ptr.latest_block_date()));
-    let tid = format!("{}", style!(utxo.transaction_id));
-    let tii = format!("{:03}", utxo.index_in_transaction);
-    const WIDTH: usize = 14;
-    let credit = if debit {
-        format!("{:>width$}", " ", width = WIDTH)
-    } else {
-        format!(
-            "{:>width$}",
-            format!("{}", utxo.credited_value),
-            width = WIDTH
-        )
-    };
-    let debit = if debit {
-        format!(
-            "{:>width$}",
-            format!("{}", utxo.credited_value),
-            width = WIDTH
-        )
-    } else {
-        format!("{:>width$}", " ", width = WIDTH)
+/// rebuild the state snapshot of the wallet, and the UTxO index it holds,
+/// from its log
+pub fn reindex_wallet<P>(wallet: &Wallet, root_dir: P) -> Result<UtxoIndex>
+where
+    P: AsRef<Path>,
+{
+    Snapshot::remove(wallet)?;
+    load_utxo_index(wallet, root_dir)
+}
+
+/// roll the wallet log back to its latest entry still part of the local
+/// blockchain. This is needed when the blockchain switched to another branch
+/// since the last time the wallet was synced.
+///
+/// Returns the pointer the wallet has been rolled back to and the entries
+/// removed from the log, or `None` if the wallet did not need rolling back.
+pub fn rollback_wallet_log(
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+) -> Result<Option<(StatePtr, Vec<log::Log<lookup::Address>>)>> {
+    // the log stays locked from the read to the rewrite
+    let mut reader = log::LogReader::open(lock_wallet_log(wallet)?)?;
+    let mut logs: Vec<log::Log<lookup::Address>> = Vec::new();
+    while let Some(entry) = reader.next()? {
+        logs.push(entry);
+    }
+    let log_lock = reader.release_lock();
+
+    let on_chain = |entry: &log::Log<lookup::Address>| -> Result<bool> {
+        let ptr = entry.ptr();
+        match ptr.latest_addr {
+            None => Ok(true),
+            Some(ref date) => Ok(blockchain.is_on_chain(&ptr.latest_known_hash, date)?),
+        }
     };
 
-    writeln!(
-        term,
-        "{:9}|{}.{}|{}|{}",
-        ::console::pad_str(&ptr, 9, ::console::Alignment::Left, None),
-        tid,
-        style!(tii).yellow(),
-        style!(credit).green(),
-        style!(debit).red()
-    )
-    .unwrap()
-}
+    match logs.last() {
+        None => return Ok(None),
+        Some(last) => {
+            if on_chain(last)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // the log is ordered by block date, so only a suffix of it can be on
+    // the abandoned branch. Look for the first entry of this suffix.
+    let (mut lo, mut hi) = (0, logs.len() - 1);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if on_chain(&logs[mid])? {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    let removed = logs.split_off(lo);
+    log_lock.rewrite(&logs)?;
 
-pub fn dump_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
-    let title = if debit {
-        style!("debit").red()
-    } else {
-        style!("credit").green()
+    let ptr = match logs.last() {
+        Some(entry) => entry.ptr().clone(),
+        None => StatePtr::new_before_genesis(blockchain.config.genesis.clone()),
     };
-    let amount = if debit {
-        style!(format!("{}", utxo.credited_value)).red()
-    } else {
-        style!(format!("{}", utxo.credited_value)).green()
+    Ok(Some((ptr, removed)))
+}
+
+/// roll the wallet back if the blockchain forked away from the wallet's
+/// latest known block, and report how the balance of the wallet changed.
+pub fn rollback_wallet_if_forked<P: AsRef<Path>>(
+    term: &mut Interaction,
+    root_dir: P,
+    wallet: &Wallet,
+    blockchain: &Blockchain,
+) -> Result<()> {
+    let (ptr, removed) = match rollback_wallet_log(wallet, blockchain)? {
+        None => return Ok(()),
+        Some(rollback) => rollback,
     };
 
-    writeln!(
-        term,
-        "{} {}.{}",
-        title,
-        style!(utxo.transaction_id),
-        style!(utxo.index_in_transaction).yellow(),
-    )
-    .unwrap();
-    writeln!(term, "Date {}", style!(ptr.latest_block_date())).unwrap();
-    writeln!(term, "Block {}", style!(ptr.latest_known_hash)).unwrap();
-    writeln!(term, "Value {}", amount).unwrap();
-    writeln!(term, "").unwrap()
+    let mut unwound_credits = Coin::zero();
+    let mut unwound_debits = Coin::zero();
+    for entry in removed.iter() {
+        match entry {
+            log::Log::ReceivedFund(_, utxo) => {
+                unwound_credits = (unwound_credits + utxo.credited_value)?;
+            }
+            log::Log::SpentFund(_, utxo) => {
+                unwound_debits = (unwound_debits + utxo.credited_value)?;
+            }
+            log::Log::Checkpoint(_) => {}
+        }
+    }
+
+    let state = create_wallet_state_from_logs(wallet, root_dir, lookup::accum::Accum::default())?;
+
+    term.warn(&format!(
+        "the blockchain forked, wallet rolled back to {}\n",
+        ptr
+    ))?;
+    term.info(&format!(
+        "unwound credits: {}, unwound debits: {}, balance is now {}\n",
+        unwound_credits,
+        unwound_debits,
+        state.total()?
+    ))?;
+    Ok(())
 }
 
 pub fn create_wallet_state_from_logs<P, LS>(
@@ -244,18 +544,87 @@ pub fn create_wallet_state_from_logs<P, LS>(
     root_dir: P,
     lookup_structure: LS,
 ) -> Result<state::State<LS>>
+where
+    P: AsRef<Path>,
+    LS: lookup::AddressLookup,
+{
+    load_wallet_state(wallet, root_dir, lookup_structure).map(|loaded| loaded.state)
+}
+
+/// the state of the wallet and the date of its last credit or debit (if
+/// any), read from the state snapshot when it is up to date with the log
+pub fn create_wallet_state_and_last_activity<P, LS>(
+    wallet: &Wallet,
+    root_dir: P,
+    lookup_structure: LS,
+) -> Result<(state::State<LS>, Option<BlockDate>)>
+where
+    P: AsRef<Path>,
+    LS: lookup::AddressLookup,
+{
+    load_wallet_state(wallet, root_dir, lookup_structure)
+        .map(|loaded| (loaded.state, loaded.last_activity))
+}
+
+/// the state of a wallet, as read from its snapshot or its log
+struct LoadedState<LS> {
+    state: state::State<LS>,
+    /// the date of the last credit or debit of the wallet
+    last_activity: Option<BlockDate>,
+}
+
+/// the state of the wallet, from its snapshot or its log
+fn load_wallet_state<P, LS>(
+    wallet: &Wallet,
+    root_dir: P,
+    lookup_structure: LS,
+) -> Result<LoadedState<LS>>
 where
     P: AsRef<Path>,
     LS: lookup::AddressLookup,
 {
     pub use super::state::state::FromLogsError::*;
 
-    let log_lock = lock_wallet_log(wallet);
+    // the version of the log is read with the log locked, and the log
+    // stays locked until it is read
+    let log_lock = lock_wallet_log(wallet)?;
+    let log_version = snapshot::log_version(wallet)?;
+    if let Some(snapshot) = Snapshot::load(wallet, &log_version) {
+        let last_activity = snapshot.last_activity().cloned();
+        return Ok(LoadedState {
+            state: snapshot.into_state(lookup_structure)?,
+            last_activity,
+        });
+    }
+    let mut addresses = Vec::new();
+    let mut last_activity = None;
+
     let log_reader = log::LogReader::open(log_lock)?;
-    let log_iter = log_reader.into_iter();
-    let state_res = state::State::from_logs(lookup_structure, log_iter);
+    let state_res = state::State::from_logs(
+        lookup_structure,
+        log_reader.into_iter().inspect(|entry| match entry {
+            Ok(log::Log::ReceivedFund(ptr, utxo)) => {
+                addresses.push(utxo.credited_addressing.clone());
+                last_activity = ptr.latest_addr.clone();
+            }
+            Ok(log::Log::SpentFund(ptr, _)) => last_activity = ptr.latest_addr.clone(),
+            _ => {}
+        }),
+    );
     match state_res {
-        Ok(state) => Ok(state),
+        Ok(state) => {
+            save_snapshot(
+                wallet,
+                log_version,
+                &state,
+                addresses,
+                last_activity.clone(),
+            );
+            Ok(LoadedState {
+                state,
+                last_activity,
+            })
+        }
         Err(NoEntries(lookup_structure)) => {
             // create empty state
             // 1. get the wallet's blockchain
@@ -263,96 +632,62 @@ where
 
             // 2. prepare the wallet state
             let initial_ptr = ptr::StatePtr::new_before_genesis(blockchain.config.genesis.clone());
-            Ok(state::State::new(initial_ptr, lookup_structure))
+            Ok(LoadedState {
+                state: state::State::new(initial_ptr, lookup_structure),
+                last_activity: None,
+            })
         }
         Err(LogReadFailed(e)) => Err(e.into()),
         Err(AddressLookupFailed(e)) => Err(e.into()),
     }
 }
 
+/// the lookup structure of the BIP44 wallet, its keys recovered with the
+/// spending password prompted to the user
 pub fn load_bip44_lookup_structure(
-    term: &mut Term,
+    term: &mut Interaction,
     network_magic: NetworkMagic,
     wallet: &Wallet,
-) -> lookup::sequentialindex::SequentialBip44Lookup {
+) -> Result<lookup::sequentialindex::SequentialBip44Lookup> {
     // TODO: to prevent from the need of the password, we can ask the user to create accounts ahead.
     //       if we store the wallet's account public keys in the config file we may not need for the
     //       password (and for the private key).
-    term.info("Enter the wallet password.\n").unwrap();
-    let password = term.password("wallet password: ").unwrap();
-
-    let wallet = match wallet.get_wallet_bip44(password.as_bytes()) {
-        Err(Error::CannotRetrievePrivateKeyInvalidPassword) => {
-            term.error("Invalid wallet spending password").unwrap();
-            ::std::process::exit(1);
-        }
-        Err(Error::CannotRetrievePrivateKey(err)) => {
-            term.error(&format!(
-                "Cannot retrieve the private key of the wallet: {}",
-                err
-            ))
-            .unwrap();
-            term.info("The encrypted wallet password is in an invalid format. You might need to delete this wallet and recover it.").unwrap();
-            ::std::process::exit(1);
-        }
-        Err(err) => {
-            term.error(IMPOSSIBLE_HAPPENED).unwrap();
-            panic!("failing with an unexpected error {:#?}", err);
-        }
-        Ok(wallet) => wallet,
-    };
-    lookup::sequentialindex::SequentialBip44Lookup::new(wallet, network_magic)
+    term.info("Enter the wallet password.\n")?;
+    let password = term.password("wallet password: ")?;
+    let gap_limit = wallet.config.gap_limit();
+
+    let wallet = wallet.get_wallet_bip44(password.as_bytes())?;
+    Ok(
+        lookup::sequentialindex::SequentialBip44Lookup::with_gap_limit(
+            wallet,
+            network_magic,
+            gap_limit,
+        ),
+    )
 }
+
+/// the lookup structure of the random index wallet, its keys recovered
+/// with the spending password prompted to the user
 pub fn load_randomindex_lookup_structure(
-    term: &mut Term,
+    term: &mut Interaction,
     network_magic: NetworkMagic,
     wallet: &Wallet,
-) -> lookup::randomindex::RandomIndexLookup {
+) -> Result<lookup::randomindex::RandomIndexLookup> {
     // in the case of the random index, we may not need the password if we have the public key
-    term.info("Enter the wallet password.\n").unwrap();
-    let password = term.password("wallet password: ").unwrap();
+    term.info("Enter the wallet password.\n")?;
+    let password = term.password("wallet password: ")?;
 
-    let wallet = match wallet.get_wallet_rindex(password.as_bytes()) {
-        Err(Error::CannotRetrievePrivateKeyInvalidPassword) => {
-            term.error("Invalid wallet spending password").unwrap();
-            ::std::process::exit(1);
-        }
-        Err(Error::CannotRetrievePrivateKey(err)) => {
-            term.error(&format!(
-                "Cannot retrieve the private key of the wallet: {}",
-                err
-            ))
-            .unwrap();
-            term.info("The encrypted wallet password is in an invalid format. You might need to delete this wallet and recover it.").unwrap();
-            ::std::process::exit(1);
-        }
-        Err(err) => {
-            term.error(IMPOSSIBLE_HAPPENED).unwrap();
-            panic!("failing with an unexpected error {:#?}", err);
-        }
-        Ok(wallet) => wallet,
-    };
-    lookup::randomindex::RandomIndexLookup::from_wallet(wallet, network_magic)
+    let wallet = wallet.get_wallet_rindex(password.as_bytes())?;
+    Ok(lookup::randomindex::RandomIndexLookup::from_wallet(
+        wallet,
+        network_magic,
+    ))
 }
 
-pub fn lock_wallet_log(wallet: &Wallet) -> log::LogLock {
-    match wallet.log() {
-        Err(Error::WalletLogAlreadyLocked(pid)) => {
-            error!(
-                "Wallet's LOG already locked by another process or thread ({})\n",
-                pid
-            );
-            ::std::process::exit(1);
-        }
-        Err(err) => {
-            error!("{}", IMPOSSIBLE_HAPPENED);
-            panic!(
-                "`lock_wallet_log' has failed with an unexpected error {:#?}",
-                err
-            );
-        }
-        Ok(lock) => lock,
-    }
+/// lock the log of the wallet, failing with `Error::WalletLogAlreadyLocked`
+/// if another process (or thread) holds the lock
+pub fn lock_wallet_log(wallet: &Wallet) -> Result<log::LogLock> {
+    wallet.log()
 }
 
 pub fn load_attached_blockchain<P: AsRef<Path>>(
@@ -373,15 +708,15 @@ fn load_attached_blockchain_internal(
 }
 
 pub fn wallet_sign_tx(
-    term: &mut Term,
+    term: &mut Interaction,
     wallet: &Wallet,
     protocol_magic: ProtocolMagic,
     txid: &TxId,
     address: &lookup::Address,
-) -> TxInWitness {
-    match wallet.config.hdwallet_model {
+) -> Result<TxInWitness> {
+    let witness = match wallet.config.hdwallet_model {
         HDWalletModel::BIP44 => {
-            let wallet = load_bip44_lookup_structure(term, protocol_magic.into(), wallet);
+            let wallet = load_bip44_lookup_structure(term, protocol_magic.into(), wallet)?;
             if let lookup::Address::Bip44(addressing) = address {
                 let xprv = wallet.get_private_key(addressing);
                 TxInWitness::new(protocol_magic, &*xprv, txid)
@@ -390,7 +725,7 @@ pub fn wallet_sign_tx(
             }
         }
         HDWalletModel::RandomIndex2Levels => {
-            let wallet = load_randomindex_lookup_structure(term, protocol_magic.into(), wallet);
+            let wallet = load_randomindex_lookup_structure(term, protocol_magic.into(), wallet)?;
             if let lookup::Address::RIndex(addressing) = address {
                 let xprv = wallet.get_private_key(addressing);
                 TxInWitness::new(protocol_magic, &xprv, txid)
@@ -398,11 +733,61 @@ pub fn wallet_sign_tx(
                 panic!()
             }
         }
+    };
+    Ok(witness)
+}
+
+/// acknowledge the addresses the wallet used, as recorded in its state
+/// snapshot or its log, so the lookup expects the addresses after them
+fn acknowledge_used_addresses<LS>(wallet: &Wallet, lookup_struct: &mut LS) -> Result<()>
+where
+    LS: lookup::AddressLookup,
+{
+    let log_lock = lock_wallet_log(wallet)?;
+    if let Some(snapshot) = Snapshot::load(wallet, &snapshot::log_version(wallet)?) {
+        for address in snapshot.addresses() {
+            lookup_struct.acknowledge(address.clone())?;
+        }
+        return Ok(());
     }
+
+    let reader: log::LogIterator<lookup::Address> = log::LogReader::open(log_lock)?.into_iter();
+    for entry in reader {
+        if let log::Log::ReceivedFund(_, utxo) = entry? {
+            lookup_struct.acknowledge(utxo.credited_addressing)?;
+        }
+    }
+    Ok(())
 }
 
-const IMPOSSIBLE_HAPPENED: &'static str = "The impossible happened
-The process will panic with an error message, this is because something
-unexpected happened. Please report the error message with the panic
-error message to: https://github.com/input-output-hk/rust-cardano/issues
-";
+/// look for the address in the wallet's address space, as the sync does:
+/// the BIP44 addresses up to the gap limit after the addresses the wallet
+/// used, the random index addresses from their derivation path.
+///
+/// Returns the addressing of the address and its private key, if the
+/// address is one of the wallet's.
+pub fn find_wallet_address(
+    term: &mut Interaction,
+    network_magic: NetworkMagic,
+    wallet: &Wallet,
+    address: &ExtendedAddr,
+) -> Result<Option<(lookup::Address, XPrv)>> {
+    match wallet.config.hdwallet_model {
+        HDWalletModel::BIP44 => {
+            let mut lookup_struct = load_bip44_lookup_structure(term, network_magic, wallet)?;
+            lookup_struct.prepare_next_account()?;
+            acknowledge_used_addresses(wallet, &mut lookup_struct)?;
+            Ok(lookup_struct.find(address).map(|addressing| {
+                let xprv = (*lookup_struct.get_private_key(&addressing)).clone();
+                (addressing.into(), xprv)
+            }))
+        }
+        HDWalletModel::RandomIndex2Levels => {
+            let lookup_struct = load_randomindex_lookup_structure(term, network_magic, wallet)?;
+            Ok(lookup_struct.find(address)?.map(|addressing| {
+                let xprv = lookup_struct.get_private_key(&addressing);
+                (addressing.into(), xprv)
+            }))
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! wallet UTxO index
+//!
+//! The unspent outputs of the wallet grouped by the address they were sent
+//! to. Listing the unspent outputs, selecting the inputs of a transaction
+//! or finding which wallet owns an input reads the index instead of going
+//! through the wallet state.
+//!
+//! The index is kept on disk as part of the state snapshot, which stores the
+//! unspent outputs grouped by address: while the snapshot is up to date with
+//! the wallet log the index is read from it as is, otherwise it is rebuilt
+//! from the wallet state along with the snapshot (see
+//! `utils::load_utxo_index`). There is no other copy of the unspent outputs
+//! on disk to keep in sync with the snapshot.
+//!
+
+use super::state::{lookup, utxo::UTxO};
+
+use cardano::{address::ExtendedAddr, tx::TxoPointer};
+
+use std::collections::BTreeMap;
+
+#[derive(Debug)]
+pub struct UtxoIndex {
+    /// the unspent outputs, by address (in base58)
+    addresses: BTreeMap<String, Vec<UTxO<lookup::Address>>>,
+    /// the address of each unspent output
+    pointers: BTreeMap<TxoPointer, String>,
+}
+impl UtxoIndex {
+    /// index the unspent outputs of the wallet state
+    pub fn new<'a, I>(utxos: I) -> Self
+    where
+        I: IntoIterator<Item = &'a UTxO<lookup::Address>>,
+    {
+        Self::from_addresses(group_by_address(utxos))
+    }
+
+    /// the index of the unspent outputs already grouped by address (in
+    /// base58), as kept in the state snapshot
+    pub fn from_addresses(addresses: BTreeMap<String, Vec<UTxO<lookup::Address>>>) -> Self {
+        let pointers = addresses
+            .iter()
+            .flat_map(|(address, utxos)| {
+                utxos
+                    .iter()
+                    .map(move |utxo| (utxo.extract_txin(), address.clone()))
+            })
+            .collect();
+        UtxoIndex {
+            addresses,
+            pointers,
+        }
+    }
+
+    /// the unspent output, if the wallet owns it
+    pub fn get(&self, txin: &TxoPointer) -> Option<&UTxO<lookup::Address>> {
+        let address = self.pointers.get(txin)?;
+        self.addresses[address]
+            .iter()
+            .find(|utxo| &utxo.extract_txin() == txin)
+    }
+
+    /// the unspent outputs sent to the address
+    pub fn by_address(&self, address: &ExtendedAddr) -> &[UTxO<lookup::Address>] {
+        self.addresses
+            .get(&address.to_string())
+            .map(|utxos| utxos.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// all the unspent outputs of the wallet, address by address
+    pub fn utxos(&self) -> impl Iterator<Item = &UTxO<lookup::Address>> {
+        self.addresses.values().flat_map(|utxos| utxos.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+}
+
+/// the unspent outputs by the address (in base58) they were sent to
+pub fn group_by_address<'a, I>(utxos: I) -> BTreeMap<String, Vec<UTxO<lookup::Address>>>
+where
+    I: IntoIterator<Item = &'a UTxO<lookup::Address>>,
+{
+    let mut addresses: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for utxo in utxos {
+        addresses
+            .entry(utxo.credited_address.to_string())
+            .or_insert_with(Vec::new)
+            .push(utxo.clone());
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{coin::Coin, hash::Blake2b256};
+    use utils::testing::address;
+
+    fn utxo(id: u8, index: u32, address: ExtendedAddr) -> UTxO<lookup::Address> {
+        UTxO {
+            transaction_id: Blake2b256::new(&[id]),
+            index_in_transaction: index,
+            credited_address: address,
+            credited_addressing: lookup::Address::RIndexInvalid(vec![id]),
+            credited_value: Coin::new(1_000_000).unwrap(),
+        }
+    }
+
+    #[test]
+    fn utxos_are_grouped_by_address() {
+        let utxos = vec![
+            utxo(1, 0, address(1)),
+            utxo(2, 0, address(2)),
+            utxo(1, 1, address(1)),
+        ];
+        let index = UtxoIndex::new(utxos.iter());
+        assert_eq!(index.len(), 3);
+
+        let txins: Vec<_> = index
+            .by_address(&address(1))
+            .iter()
+            .map(|utxo| utxo.extract_txin())
+            .collect();
+        assert_eq!(
+            txins,
+            vec![utxos[0].extract_txin(), utxos[2].extract_txin()]
+        );
+        assert_eq!(index.by_address(&address(2)).len(), 1);
+        assert!(index.by_address(&address(3)).is_empty());
+
+        let found = index.get(&utxos[2].extract_txin()).unwrap();
+        assert_eq!(found.credited_address, address(1));
+        assert_eq!(found.index_in_transaction, 1);
+        assert!(index.get(&utxo(3, 0, address(1)).extract_txin()).is_none());
+    }
+}
@@ -0,0 +1,500 @@
+//! Prometheus metrics of `watch`
+//!
+//! The metrics are refreshed after each round of `watch` and served in the
+//! Prometheus text format on `/metrics`. Collecting them never stops the
+//! watch: what cannot be read is left out and logged.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as FmtWrite,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    mem,
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use blockchain::{health, Blockchain};
+use transaction::core::{config as transaction_config, PendingTransaction};
+use wallet::{state::lookup, utils::create_wallet_state_from_logs, Wallets};
+
+use super::Event;
+
+/// the size of the request (line and headers) read from a client, the
+/// rest is ignored
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+struct PeerMetrics {
+    latency: Option<u64>,
+    up: bool,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    blockchain: String,
+    tip_epoch: u64,
+    tip_slot: u64,
+    /// slots between the local tip and the current time
+    sync_lag: Option<u64>,
+    peers: BTreeMap<String, PeerMetrics>,
+    /// balance of the attached wallets, in lovelace
+    wallets: BTreeMap<String, u64>,
+    staged: usize,
+    pending: usize,
+    events: BTreeMap<&'static str, u64>,
+    last_round: Option<SystemTime>,
+}
+impl Metrics {
+    /// collect the gauges from the blockchain, its peers' health, the
+    /// attached wallets and the transactions. This replays the wallets'
+    /// logs: it is done without holding the lock of the served metrics,
+    /// which are then replaced with `update`.
+    pub fn collect(root_dir: &Path, blockchain: &Blockchain) -> Self {
+        let mut metrics = Metrics::default();
+        metrics.refresh(root_dir, blockchain);
+        metrics
+    }
+
+    /// replace the gauges with the collected ones, keeping the events'
+    /// counters
+    pub fn update(&mut self, gauges: Metrics) {
+        let events = mem::replace(&mut self.events, BTreeMap::new());
+        *self = Metrics { events, ..gauges };
+    }
+
+    fn refresh(&mut self, root_dir: &Path, blockchain: &Blockchain) {
+        self.blockchain = blockchain.name.to_string();
+
+        let tip = blockchain.load_tip().0;
+        self.tip_epoch = tip.date.get_epochid();
+        self.tip_slot = blockchain.absolute_slot(&tip.date);
+        self.sync_lag = match blockchain.load_genesis_data() {
+            Err(err) => {
+                warn!("metrics: cannot load the genesis data: {}", err);
+                None
+            }
+            Ok(genesis) => SystemTime::now()
+                .duration_since(genesis.start_time)
+                .ok()
+                .map(|elapsed| elapsed.as_secs() / genesis.slot_duration.as_secs().max(1))
+                .map(|current_slot| current_slot.saturating_sub(self.tip_slot)),
+        };
+
+        let peers_health = health::Health::load(blockchain);
+        self.peers = blockchain
+            .peers()
+            .map(|np| {
+                let health = peers_health.get(np.name());
+                let metrics = PeerMetrics {
+                    latency: health.and_then(|h| h.latency),
+                    up: health.map(|h| h.is_available()).unwrap_or(false),
+                };
+                (np.name().to_owned(), metrics)
+            })
+            .collect();
+
+        self.wallets.clear();
+        match Wallets::load(root_dir) {
+            Err(err) => warn!("metrics: cannot load the wallets: {}", err),
+            Ok(wallets) => {
+                for (name, wallet) in wallets {
+                    if wallet
+                        .config
+                        .attached_blockchain
+                        .as_ref()
+                        .map(|name| name.as_str())
+                        != Some(blockchain.name.as_ref())
+                    {
+                        continue;
+                    }
+                    let total = create_wallet_state_from_logs(
+                        &wallet,
+                        root_dir,
+                        lookup::accum::Accum::default(),
+                    )
+                    .and_then(|state| state.total().map_err(Into::into));
+                    match total {
+                        Ok(total) => {
+                            self.wallets.insert(name.to_string(), u64::from(total));
+                        }
+                        Err(err) => warn!(
+                            "metrics: cannot compute the balance of wallet {}: {}",
+                            name, err
+                        ),
+                    }
+                }
+            }
+        }
+
+        let transactions_dir = transaction_config::transaction_directory(root_dir.to_path_buf());
+        self.staged = fs::read_dir(transactions_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+                    .count()
+            })
+            .unwrap_or(0);
+        self.pending = match PendingTransaction::list(root_dir.to_path_buf()) {
+            Err(err) => {
+                warn!("metrics: cannot list the pending transactions: {}", err);
+                0
+            }
+            Ok(pendings) => pendings
+                .iter()
+                .filter(|p| p.blockchain == self.blockchain)
+                .count(),
+        };
+
+        self.last_round = Some(SystemTime::now());
+    }
+
+    pub fn count(&mut self, event: &Event) {
+        let name = match event {
+            Event::Block { .. } => "block",
+            Event::Rollback { .. } => "rollback",
+            Event::Resync { .. } => "resync",
+            Event::Credited { .. } => "credited",
+            Event::Debited { .. } => "debited",
+            Event::Digest { .. } => "digest",
+            Event::Alarm { .. } => "alarm",
+        };
+        *self.events.entry(name).or_insert(0) += 1;
+    }
+
+    /// the metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let blockchain = format!("blockchain=\"{}\"", escape(&self.blockchain));
+
+        metric(
+            &mut out,
+            "cardano_cli_tip_epoch",
+            "gauge",
+            "epoch of the local tip",
+        );
+        let _ = writeln!(
+            out,
+            "cardano_cli_tip_epoch{{{}}} {}",
+            blockchain, self.tip_epoch
+        );
+        metric(
+            &mut out,
+            "cardano_cli_tip_slot",
+            "gauge",
+            "slot of the local tip, counted from the genesis",
+        );
+        let _ = writeln!(
+            out,
+            "cardano_cli_tip_slot{{{}}} {}",
+            blockchain, self.tip_slot
+        );
+        if let Some(sync_lag) = self.sync_lag {
+            metric(
+                &mut out,
+                "cardano_cli_sync_lag_slots",
+                "gauge",
+                "slots between the local tip and the current time",
+            );
+            let _ = writeln!(
+                out,
+                "cardano_cli_sync_lag_slots{{{}}} {}",
+                blockchain, sync_lag
+            );
+        }
+
+        metric(
+            &mut out,
+            "cardano_cli_peer_up",
+            "gauge",
+            "whether the last connection to the peer succeeded",
+        );
+        for (name, peer) in self.peers.iter() {
+            let _ = writeln!(
+                out,
+                "cardano_cli_peer_up{{{},peer=\"{}\"}} {}",
+                blockchain,
+                escape(name),
+                if peer.up { 1 } else { 0 }
+            );
+        }
+        metric(
+            &mut out,
+            "cardano_cli_peer_latency_seconds",
+            "gauge",
+            "duration of the last successful connection to the peer",
+        );
+        for (name, peer) in self.peers.iter() {
+            if let Some(latency) = peer.latency {
+                let _ = writeln!(
+                    out,
+                    "cardano_cli_peer_latency_seconds{{{},peer=\"{}\"}} {}",
+                    blockchain,
+                    escape(name),
+                    latency as f64 / 1000.0
+                );
+            }
+        }
+
+        metric(
+            &mut out,
+            "cardano_cli_wallet_balance_lovelace",
+            "gauge",
+            "balance of the wallet attached to the blockchain",
+        );
+        for (name, balance) in self.wallets.iter() {
+            let _ = writeln!(
+                out,
+                "cardano_cli_wallet_balance_lovelace{{{},wallet=\"{}\"}} {}",
+                blockchain,
+                escape(name),
+                balance
+            );
+        }
+
+        metric(
+            &mut out,
+            "cardano_cli_staged_transactions",
+            "gauge",
+            "staging transactions, of all the blockchains",
+        );
+        let _ = writeln!(out, "cardano_cli_staged_transactions {}", self.staged);
+        metric(
+            &mut out,
+            "cardano_cli_pending_transactions",
+            "gauge",
+            "transactions sent but not confirmed yet",
+        );
+        let _ = writeln!(
+            out,
+            "cardano_cli_pending_transactions{{{}}} {}",
+            blockchain, self.pending
+        );
+
+        metric(
+            &mut out,
+            "cardano_cli_events_total",
+            "counter",
+            "events emitted since watch started",
+        );
+        for (event, count) in self.events.iter() {
+            let _ = writeln!(
+                out,
+                "cardano_cli_events_total{{{},event=\"{}\"}} {}",
+                blockchain, event, count
+            );
+        }
+
+        if let Some(last_round) = self.last_round {
+            let seconds = last_round
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            metric(
+                &mut out,
+                "cardano_cli_last_round_timestamp_seconds",
+                "gauge",
+                "time the last round of watch finished",
+            );
+            let _ = writeln!(
+                out,
+                "cardano_cli_last_round_timestamp_seconds{{{}}} {}",
+                blockchain, seconds
+            );
+        }
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// serve the metrics on `http://<address>/metrics`
+pub fn serve(address: &str, metrics: Arc<Mutex<Metrics>>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("metrics: {}", err);
+                    continue;
+                }
+            };
+            // a slow client does not hold the others back
+            let metrics = metrics.clone();
+            thread::spawn(move || {
+                if let Err(err) = respond(stream, &metrics) {
+                    debug!("metrics: {}", err);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, metrics: &Mutex<Metrics>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let request = read_request(&mut stream)?;
+
+    let mut words = request.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.lock().unwrap().render();
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => write!(
+            stream,
+            "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        ),
+    }
+}
+
+/// read the request line and skip the headers, reading at most
+/// `MAX_REQUEST_SIZE` bytes: what follows is ignored, a longer line is cut
+fn read_request<R: Read>(reader: R) -> io::Result<String> {
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_SIZE));
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn metrics() -> Metrics {
+        let mut metrics = Metrics::default();
+        metrics.blockchain = "mainnet".to_owned();
+        metrics.tip_epoch = 130;
+        metrics.tip_slot = 2810104;
+        metrics.peers.insert(
+            "iohk-hosts".to_owned(),
+            PeerMetrics {
+                latency: Some(250),
+                up: true,
+            },
+        );
+        metrics.peers.insert(
+            "relay".to_owned(),
+            PeerMetrics {
+                latency: None,
+                up: false,
+            },
+        );
+        metrics.wallets.insert("savings".to_owned(), 1_000_000);
+        metrics.staged = 2;
+        metrics.pending = 1;
+        metrics
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("mainnet"), "mainnet");
+        assert_eq!(escape(r#"a"b"#), r#"a\"b"#);
+        assert_eq!(escape(r"a\b"), r"a\\b");
+        assert_eq!(escape("a\nb"), r"a\nb");
+        assert_eq!(escape("\\\"\n"), r#"\\\"\n"#);
+    }
+
+    #[test]
+    fn metrics_are_rendered_in_the_text_format() {
+        let rendered = metrics().render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        for line in &[
+            "# HELP cardano_cli_tip_epoch epoch of the local tip",
+            "# TYPE cardano_cli_tip_epoch gauge",
+            "cardano_cli_tip_epoch{blockchain=\"mainnet\"} 130",
+            "cardano_cli_tip_slot{blockchain=\"mainnet\"} 2810104",
+            "cardano_cli_peer_up{blockchain=\"mainnet\",peer=\"iohk-hosts\"} 1",
+            "cardano_cli_peer_up{blockchain=\"mainnet\",peer=\"relay\"} 0",
+            "cardano_cli_peer_latency_seconds{blockchain=\"mainnet\",peer=\"iohk-hosts\"} 0.25",
+            "cardano_cli_wallet_balance_lovelace{blockchain=\"mainnet\",wallet=\"savings\"} 1000000",
+            "cardano_cli_staged_transactions 2",
+            "cardano_cli_pending_transactions{blockchain=\"mainnet\"} 1",
+        ] {
+            assert!(lines.contains(line), "missing `{}' in:\n{}", line, rendered);
+        }
+        // no latency is known for a peer down, no lag without genesis data
+        assert!(!rendered
+            .contains("cardano_cli_peer_latency_seconds{blockchain=\"mainnet\",peer=\"relay\"}"));
+        assert!(!rendered.contains("cardano_cli_sync_lag_slots"));
+        assert!(!rendered.contains("cardano_cli_last_round_timestamp_seconds"));
+        // every sample line is `NAME{LABELS} VALUE' or `NAME VALUE'
+        for line in lines.iter().filter(|line| !line.starts_with('#')) {
+            let value = line.rsplit(' ').next().unwrap();
+            assert!(value.parse::<f64>().is_ok(), "invalid sample `{}'", line);
+        }
+    }
+
+    #[test]
+    fn label_values_are_escaped_when_rendered() {
+        let mut metrics = metrics();
+        metrics.wallets.insert("my \"hot\" wallet".to_owned(), 5);
+        let rendered = metrics.render();
+        assert!(rendered.lines().any(|line| line
+            == "cardano_cli_wallet_balance_lovelace{blockchain=\"mainnet\",wallet=\"my \\\"hot\\\" wallet\"} 5"));
+    }
+
+    #[test]
+    fn update_keeps_the_events() {
+        let mut metrics = Metrics::default();
+        metrics.count(&Event::Rollback {
+            blockchain: "mainnet".to_owned(),
+            from: "a".to_owned(),
+            to: "b".to_owned(),
+        });
+        metrics.update(self::metrics());
+        assert_eq!(metrics.tip_epoch, 130);
+        assert!(metrics
+            .render()
+            .lines()
+            .any(|line| line
+                == "cardano_cli_events_total{blockchain=\"mainnet\",event=\"rollback\"} 1"));
+    }
+
+    #[test]
+    fn request_is_read() {
+        let request = "GET /metrics HTTP/1.0\r\nHost: localhost\r\n\r\nignored";
+        assert_eq!(
+            read_request(Cursor::new(request)).unwrap(),
+            "GET /metrics HTTP/1.0\r\n"
+        );
+    }
+
+    #[test]
+    fn request_size_is_capped() {
+        // an endless request line is cut
+        let request = read_request(io::repeat(b'a')).unwrap();
+        assert_eq!(request.len() as u64, MAX_REQUEST_SIZE);
+
+        // so are endless headers
+        let headers = Cursor::new("GET /metrics HTTP/1.0\r\nX-Padding: ").chain(io::repeat(b'a'));
+        assert_eq!(read_request(headers).unwrap(), "GET /metrics HTTP/1.0\r\n");
+    }
+}
@@ -0,0 +1,632 @@
+//! stream of the blockchain's and wallets' events
+//!
+//! `cardano-cli watch` pulls the blockchain from its peers at regular
+//! intervals and emits one JSON object per line for every new block, every
+//! rollback of the local tip, and every output credited to or spent by the
+//! wallets attached to the blockchain. Downstream services read the events
+//! from the standard output, or from a Unix socket, instead of polling.
+//!
+//! The human readable messages (sync progress, warnings) go to the standard
+//! error so the standard output only carries the events.
+//!
+//! With `--metrics`, the state of the blockchain, its peers and the wallets
+//! is also served to Prometheus (see the `metrics` module). With `--digest`,
+//! a digest of the activity of every wallet is emitted at the end of every
+//! day or week (see `wallet::digest`). The alarms of the wallets (see
+//! `wallet::alarm`) triggered by the sync are events too, and run the
+//! alarm hook if any.
+
+pub mod metrics;
+
+use std::{
+    collections::BTreeSet,
+    error, fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use cardano::block::HeaderHash;
+use cardano_storage as storage;
+use console;
+use exe_common::network::api::BlockRef;
+use serde_json;
+
+use blockchain::{self, Blockchain, BlockchainName};
+use schema;
+use utils::{clock, term::Term};
+use wallet::{
+    self,
+    digest::{self, DigestPeriod},
+    state::{log, lookup},
+    utils::lock_wallet_log,
+    Wallet, Wallets,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    BlockchainError(blockchain::Error),
+    WalletError(wallet::Error),
+    IoError(io::Error),
+    SocketNotSupported,
+}
+impl From<blockchain::Error> for Error {
+    fn from(e: blockchain::Error) -> Self {
+        Error::BlockchainError(e)
+    }
+}
+impl From<storage::Error> for Error {
+    fn from(e: storage::Error) -> Self {
+        Error::BlockchainError(e.into())
+    }
+}
+impl From<wallet::Error> for Error {
+    fn from(e: wallet::Error) -> Self {
+        Error::WalletError(e)
+    }
+}
+impl From<log::Error> for Error {
+    fn from(e: log::Error) -> Self {
+        Error::WalletError(e.into())
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BlockchainError(_) => write!(f, "Blockchain error"),
+            Error::WalletError(_) => write!(f, "Wallet error"),
+            Error::IoError(_) => write!(f, "I/O Error"),
+            Error::SocketNotSupported => {
+                write!(f, "Unix sockets are not supported on this platform")
+            }
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match self {
+            Error::BlockchainError(ref err) => Some(err),
+            Error::WalletError(ref err) => Some(err),
+            Error::IoError(ref err) => Some(err),
+            Error::SocketNotSupported => None,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// an event, printed as a JSON object on one line. See the `watch` schema.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// a new block became part of the local blockchain
+    Block {
+        blockchain: String,
+        hash: String,
+        date: String,
+        previous: String,
+        transactions: usize,
+    },
+    /// the local tip switched to another branch, the blocks after `to` are
+    /// not part of the local blockchain anymore
+    Rollback {
+        blockchain: String,
+        from: String,
+        to: String,
+    },
+    /// the local tip moved too far to emit the blocks in between: `to` is
+    /// not within the stability depth of `from`, either because too many
+    /// blocks were added or because of a deeper rollback. The consumers
+    /// start over from the new tip.
+    Resync {
+        blockchain: String,
+        from: String,
+        to: String,
+        date: String,
+    },
+    /// an output was credited to an address of the wallet
+    Credited {
+        blockchain: String,
+        wallet: String,
+        txid: String,
+        index: u32,
+        address: String,
+        amount: u64,
+        date: String,
+    },
+    /// an output of the wallet was spent
+    Debited {
+        blockchain: String,
+        wallet: String,
+        txid: String,
+        index: u32,
+        address: String,
+        amount: u64,
+        date: String,
+    },
+    /// the activity of the wallet over the period which just ended
+    Digest {
+        blockchain: String,
+        wallet: String,
+        period: String,
+        from: String,
+        to: String,
+        received: u64,
+        sent: u64,
+        received_transactions: usize,
+        sent_transactions: usize,
+        balance: u64,
+        pending: Vec<String>,
+        expired: Vec<String>,
+    },
+    /// an alarm of the wallet was triggered by the sync
+    Alarm {
+        blockchain: String,
+        wallet: String,
+        alarm: String,
+        reason: String,
+    },
+}
+
+/// the digests of the wallets' activity to emit
+pub struct Digests {
+    pub period: DigestPeriod,
+    /// the directory to write them to as well
+    pub dir: Option<PathBuf>,
+}
+
+/// where the events are written to
+enum Sink {
+    Stdout,
+    #[cfg(unix)]
+    Socket(::std::sync::Arc<::std::sync::Mutex<Vec<::std::os::unix::net::UnixStream>>>),
+}
+impl Sink {
+    fn open(socket: Option<PathBuf>) -> Result<Self> {
+        match socket {
+            None => Ok(Sink::Stdout),
+            Some(path) => Sink::listen(&path),
+        }
+    }
+
+    /// listen on the Unix socket, every client connected receives the events
+    /// emitted after it connected
+    #[cfg(unix)]
+    fn listen(path: &Path) -> Result<Self> {
+        use std::{
+            fs,
+            os::unix::{fs::FileTypeExt, net::UnixListener},
+            sync::{Arc, Mutex},
+        };
+
+        // remove the socket left by a previous run, nothing else
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(path)?;
+            }
+        }
+        let listener = UnixListener::bind(path)?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for client in listener.incoming() {
+                match client {
+                    Ok(client) => accepted.lock().unwrap().push(client),
+                    Err(err) => warn!(
+                        "cannot accept the connection to the events' socket: {}",
+                        err
+                    ),
+                }
+            }
+        });
+        Ok(Sink::Socket(clients))
+    }
+
+    #[cfg(not(unix))]
+    fn listen(_path: &Path) -> Result<Self> {
+        Err(Error::SocketNotSupported)
+    }
+
+    fn emit(&mut self, event: &Event) -> Result<()> {
+        schema::WATCH_EVENT.debug_check(event);
+        let mut line = serde_json::to_string(event).map_err(io::Error::from)?;
+        line.push('\n');
+        match self {
+            Sink::Stdout => {
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()?;
+            }
+            #[cfg(unix)]
+            Sink::Socket(clients) => {
+                // the clients which went away are dropped
+                clients.lock().unwrap().retain(|client| {
+                    let mut client = client;
+                    client.write_all(line.as_bytes()).is_ok()
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// pull the blockchain every `interval` and emit the events, until killed
+pub fn run(
+    term: &mut Term,
+    root_dir: PathBuf,
+    name: BlockchainName,
+    interval: Duration,
+    socket: Option<PathBuf>,
+    metrics_address: Option<String>,
+    proxy: Option<String>,
+    clock_check: &clock::Config,
+    digests: Option<Digests>,
+    alarm_hook: Option<PathBuf>,
+) -> Result<()> {
+    term.term = console::Term::stderr();
+    let mut sink = Sink::open(socket)?;
+    let metrics = Arc::new(Mutex::new(metrics::Metrics::default()));
+    if let Some(address) = metrics_address {
+        metrics::serve(&address, metrics.clone())?;
+        term.info(&format!(
+            "serving the metrics on http://{}/metrics\n",
+            address
+        ))?;
+    }
+
+    // the clock is checked once, not before every pull, and not at all
+    // when the peers are reached through the proxy given or the one of the
+    // blockchain (as `pull' does)
+    let blockchain = Blockchain::load(&root_dir, name.clone())?;
+    let clock_proxy = proxy.clone().or_else(|| blockchain.proxy.clone());
+    clock::check(term, clock_check, clock_proxy.as_ref().map(|p| p.as_str()))
+        .map_err(blockchain::Error::ClockSkewed)?;
+    let no_clock_check = clock::Config {
+        policy: clock::Policy::Off,
+        ..clock_check.clone()
+    };
+
+    let mut tip = blockchain.load_tip().0;
+    let mut next_digest = digests
+        .as_ref()
+        .map(|digests| SystemTime::now() + digests.period.duration());
+    loop {
+        if let Err(err) = blockchain::commands::pull(
+            term,
+            root_dir.clone(),
+            name.clone(),
+            1,
+            proxy.clone(),
+            &no_clock_check,
+        ) {
+            term.warn(&format!("cannot pull blockchain `{}': {}\n", name, err))?;
+        }
+
+        let blockchain = Blockchain::load(&root_dir, name.clone())?;
+        let new_tip = blockchain.load_tip().0;
+        if new_tip.hash != tip.hash {
+            for event in block_events(&blockchain, &tip, &new_tip)? {
+                sink.emit(&event)?;
+                metrics.lock().unwrap().count(&event);
+            }
+            match wallet_events(term, &root_dir, &blockchain) {
+                Ok(events) => {
+                    for event in events {
+                        sink.emit(&event)?;
+                        metrics.lock().unwrap().count(&event);
+                        if let (Event::Alarm { .. }, Some(hook)) = (&event, alarm_hook.as_ref()) {
+                            run_alarm_hook(hook, &event)?;
+                        }
+                    }
+                }
+                Err(err) => term.warn(&format!(
+                    "cannot sync the wallets of blockchain `{}': {}\n",
+                    name, err
+                ))?,
+            }
+            tip = new_tip;
+        }
+        let gauges = metrics::Metrics::collect(&root_dir, &blockchain);
+        metrics.lock().unwrap().update(gauges);
+
+        if let (Some(digests), Some(at)) = (digests.as_ref(), next_digest) {
+            if at <= SystemTime::now() {
+                for event in digest_events(&root_dir, &blockchain, digests)? {
+                    sink.emit(&event)?;
+                    metrics.lock().unwrap().count(&event);
+                }
+                next_digest = Some(at + digests.period.duration());
+            }
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+/// the events of the local tip moving from `tip` to `new_tip`: the rollback
+/// to the common ancestor of both, if `new_tip` does not follow `tip`, then
+/// the new blocks in chain order.
+///
+/// At most the stability depth of blocks are scanned back from `new_tip`:
+/// when the common ancestor is not among them (first pull of a blockchain,
+/// long downtime, deeper rollback) a single resync event is emitted instead
+/// of an event for every block back to the genesis.
+fn block_events(blockchain: &Blockchain, tip: &BlockRef, new_tip: &BlockRef) -> Result<Vec<Event>> {
+    let name = blockchain.name.to_string();
+
+    // the blocks the previous tip could have been rolled back to
+    let mut previous_chain = BTreeSet::new();
+    previous_chain.insert(tip.hash.clone());
+    let depth = blockchain.config.epoch_stability_depth;
+    // the genesis block of a blockchain never synced is not stored
+    if let Ok(blocks) = storage::iter::ReverseIter::from(&blockchain.storage, tip.hash.clone()) {
+        for block in blocks.take(depth) {
+            previous_chain.insert(block.get_header().get_previous_header());
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut ancestor: Option<HeaderHash> = None;
+    for block in
+        storage::iter::ReverseIter::from(&blockchain.storage, new_tip.hash.clone())?.take(depth)
+    {
+        let header = block.get_header();
+        let hash = header.compute_hash();
+        if previous_chain.contains(&hash) {
+            ancestor = Some(hash);
+            break;
+        }
+        events.push(Event::Block {
+            blockchain: name.clone(),
+            hash: hash.to_string(),
+            date: header.get_blockdate().to_string(),
+            previous: header.get_previous_header().to_string(),
+            transactions: block.get_transactions().map(|txs| txs.len()).unwrap_or(0),
+        });
+    }
+
+    match ancestor {
+        Some(ref ancestor) if ancestor == &tip.hash => {}
+        Some(ancestor) => events.push(Event::Rollback {
+            blockchain: name.clone(),
+            from: tip.hash.to_string(),
+            to: ancestor.to_string(),
+        }),
+        None => {
+            warn!(
+                "the local tip {} is not within {} blocks of the new tip {}",
+                tip.hash, depth, new_tip.hash
+            );
+            return Ok(vec![Event::Resync {
+                blockchain: name,
+                from: tip.hash.to_string(),
+                to: new_tip.hash.to_string(),
+                date: new_tip.date.to_string(),
+            }]);
+        }
+    }
+    events.reverse();
+    Ok(events)
+}
+
+/// sync the wallets attached to the blockchain, the entries added to their
+/// logs are the events, followed by the alarms the sync triggered
+fn wallet_events(term: &mut Term, root_dir: &Path, blockchain: &Blockchain) -> Result<Vec<Event>> {
+    let wallets = attached_wallets(root_dir, blockchain)?;
+    let synced =
+        wallet::commands::sync_wallets_with_blockchain(term, root_dir, &wallets, blockchain)?;
+
+    let mut events = Vec::new();
+    for (wallet, (synced_from, alarms)) in wallets.iter().zip(synced.into_iter()) {
+        let log_lock = lock_wallet_log(wallet)?;
+        let reader: log::LogIterator<lookup::Address> = log::LogReader::open(log_lock)?.into_iter();
+        for entry in reader {
+            let (credited, ptr, utxo) = match entry? {
+                log::Log::Checkpoint(_) => continue,
+                log::Log::ReceivedFund(ptr, utxo) => (true, ptr, utxo),
+                log::Log::SpentFund(ptr, utxo) => (false, ptr, utxo),
+            };
+            let date = ptr.latest_block_date();
+            if date <= synced_from {
+                continue;
+            }
+
+            let blockchain = blockchain.name.to_string();
+            let wallet = wallet.name.to_string();
+            let txid = utxo.transaction_id.to_string();
+            let index = utxo.index_in_transaction;
+            let address = utxo.credited_address.to_string();
+            let amount = u64::from(utxo.credited_value);
+            let date = date.to_string();
+            events.push(if credited {
+                Event::Credited {
+                    blockchain,
+                    wallet,
+                    txid,
+                    index,
+                    address,
+                    amount,
+                    date,
+                }
+            } else {
+                Event::Debited {
+                    blockchain,
+                    wallet,
+                    txid,
+                    index,
+                    address,
+                    amount,
+                    date,
+                }
+            });
+        }
+        for triggered in alarms {
+            events.push(Event::Alarm {
+                blockchain: blockchain.name.to_string(),
+                wallet: wallet.name.to_string(),
+                alarm: triggered.alarm.to_string(),
+                reason: triggered.reason,
+            });
+        }
+    }
+    Ok(events)
+}
+
+/// run the alarm hook with the alarm event (the JSON object, on one line)
+/// on its standard input. The hook runs in the background: its failure is
+/// logged, it does not stop `watch`.
+fn run_alarm_hook(hook: &Path, event: &Event) -> Result<()> {
+    let line = serde_json::to_string(event).map_err(io::Error::from)?;
+    let hook = hook.to_path_buf();
+    thread::spawn(move || {
+        let status = Command::new(&hook)
+            .stdin(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                if let Some(mut stdin) = child.stdin.take() {
+                    writeln!(stdin, "{}", line)?;
+                }
+                child.wait()
+            });
+        match status {
+            Ok(ref status) if status.success() => {}
+            Ok(status) => warn!("alarm hook {:?} failed: {}", hook, status),
+            Err(err) => warn!("cannot run the alarm hook {:?}: {}", hook, err),
+        }
+    });
+    Ok(())
+}
+
+/// the wallets attached to the blockchain
+fn attached_wallets(root_dir: &Path, blockchain: &Blockchain) -> Result<Vec<Wallet>> {
+    Ok(Wallets::load(root_dir)?
+        .into_iter()
+        .map(|(_, wallet)| wallet)
+        .filter(|wallet| {
+            wallet
+                .config
+                .attached_blockchain
+                .as_ref()
+                .map(|name| name.as_str())
+                == Some(blockchain.name.as_ref())
+        })
+        .collect())
+}
+
+/// the digests of the wallets attached to the blockchain for the period
+/// which just ended, written to the digests' directory too. A wallet whose
+/// digest fails is skipped, not the others.
+fn digest_events(
+    root_dir: &Path,
+    blockchain: &Blockchain,
+    digests: &Digests,
+) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for wallet in attached_wallets(root_dir, blockchain)? {
+        let digest =
+            match digest::compute(root_dir.to_path_buf(), &wallet, blockchain, digests.period) {
+                Ok(digest) => digest,
+                Err(err) => {
+                    warn!(
+                        "cannot compute the digest of the wallet {}: {}",
+                        wallet.name, err
+                    );
+                    continue;
+                }
+            };
+        if let Some(ref dir) = digests.dir {
+            match digest::save(dir, &digest) {
+                Ok(path) => info!("digest of the wallet {} written to {:?}", wallet.name, path),
+                Err(err) => warn!(
+                    "cannot write the digest of the wallet {}: {}",
+                    wallet.name, err
+                ),
+            }
+        }
+        events.push(Event::Digest {
+            blockchain: blockchain.name.to_string(),
+            wallet: digest.wallet,
+            period: digest.period,
+            from: digest.from,
+            to: digest.to,
+            received: digest.received,
+            sent: digest.sent,
+            received_transactions: digest.received_transactions,
+            sent_transactions: digest.sent_transactions,
+            balance: digest.balance,
+            pending: digest.pending.iter().map(|txid| txid.to_string()).collect(),
+            expired: digest.expired.iter().map(|txid| txid.to_string()).collect(),
+        });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn alarm_event() -> Event {
+        Event::Alarm {
+            blockchain: "mainnet".to_owned(),
+            wallet: "savings".to_owned(),
+            alarm: "balance below 1.000000".to_owned(),
+            reason: "balance is 0.500000".to_owned(),
+        }
+    }
+
+    #[test]
+    fn alarm_event_matches_its_schema() {
+        let value = serde_json::to_value(&alarm_event()).unwrap();
+        assert_eq!(value["event"], "alarm");
+        let violations: Vec<String> = schema::WATCH_EVENT
+            .validate(&value)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn alarm_hook_reads_the_event() {
+        use std::{fs, os::unix::fs::PermissionsExt, process};
+
+        let dir = ::std::env::temp_dir().join(format!("cardano-cli-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hook = dir.join("hook.sh");
+        let output = dir.join("event.json");
+        fs::write(
+            &hook,
+            format!(
+                "#!/bin/sh\ncat > {:?}.tmp\nmv {:?}.tmp {:?}\n",
+                output, output, output
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+        run_alarm_hook(&hook, &alarm_event()).unwrap();
+        let mut written = None;
+        for _ in 0..50 {
+            if let Ok(content) = fs::read_to_string(&output) {
+                written = Some(content);
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let event: serde_json::Value = serde_json::from_str(&written.unwrap()).unwrap();
+        assert_eq!(event["wallet"], "savings");
+        assert_eq!(event["reason"], "balance is 0.500000");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}